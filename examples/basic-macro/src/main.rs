@@ -0,0 +1,29 @@
+// This example requires the following input to succeed:
+// { "command": "do something" }
+
+use lambda_runtime::{Error, LambdaEvent};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+struct Request {
+    command: String,
+}
+
+#[derive(Serialize)]
+struct Response {
+    req_id: String,
+    msg: String,
+}
+
+/// The `main` attribute generates the `main` function of the binary: it starts
+/// the Tokio runtime, initializes the CloudWatch Logs friendly tracing output,
+/// and runs this function as the handler of every invocation.
+#[lambda_runtime::main]
+async fn my_handler(event: LambdaEvent<Request>) -> Result<Response, Error> {
+    let command = event.payload.command;
+
+    Ok(Response {
+        req_id: event.context.request_id,
+        msg: format!("Command {command} executed."),
+    })
+}
@@ -0,0 +1,13 @@
+use lambda_http::{Error, IntoResponse, Request, RequestExt};
+
+/// The `handler` attribute generates the `main` function of the binary, and
+/// runs this function for every HTTP request.
+#[lambda_http::handler]
+async fn function_handler(event: Request) -> Result<impl IntoResponse, Error> {
+    let name = event
+        .query_string_parameters_ref()
+        .and_then(|params| params.first("name"))
+        .unwrap_or("world")
+        .to_string();
+    Ok(format!("Hello {name}, this is an AWS Lambda HTTP request"))
+}
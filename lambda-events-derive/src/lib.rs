@@ -0,0 +1,252 @@
+#![deny(rust_2018_idioms)]
+//! Derive macros for `aws_lambda_events`.
+//!
+//! Don't use this crate directly, enable the `derive` feature of `aws_lambda_events`.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields, GenericArgument, PathArguments, Type,
+};
+
+/// Implement `Serialize` and `Deserialize` with the conventions of the AWS event payloads.
+///
+/// - Fields are renamed to camelCase, unless the struct sets its own `#[serde(rename_all)]`.
+/// - Every field is optional, and takes its default value when it's missing.
+/// - `Vec<u8>` fields are encoded in base64.
+/// - `DateTime<Utc>` fields are timestamps in milliseconds since the Unix epoch.
+/// - Maps and lists accept `null` as empty.
+///
+/// Fields with their own `#[serde(with)]`, `#[serde(serialize_with)]`, or
+/// `#[serde(deserialize_with)]` attributes keep them instead.
+#[proc_macro_derive(LambdaEventType, attributes(serde))]
+pub fn derive_lambda_event_type(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    derive(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn derive(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new(input.ident.span(), "`LambdaEventType` needs named fields")),
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "`LambdaEventType` can only be derived for structs",
+            ))
+        }
+    };
+
+    let serde = quote!(::aws_lambda_events::__private::serde);
+    let conventions = "::aws_lambda_events::conventions";
+
+    let mut container = serde_attrs(&input.attrs);
+    if !serde_keys(&container)?.iter().any(|key| key == "rename_all") {
+        container.push(parse_quote!(#[serde(rename_all = "camelCase")]));
+    }
+
+    let mut mirror = Vec::with_capacity(fields.len());
+    for field in fields {
+        let mut attrs = serde_attrs(&field.attrs);
+        let keys = serde_keys(&attrs)?;
+        if !keys
+            .iter()
+            .any(|key| key == "default" || key == "skip" || key == "skip_deserializing")
+        {
+            attrs.push(parse_quote!(#[serde(default)]));
+        }
+        let custom = keys
+            .iter()
+            .any(|key| key == "with" || key == "serialize_with" || key == "deserialize_with");
+        if !custom {
+            if let Some(module) = convention(&field.ty) {
+                let with = format!("{conventions}::{module}");
+                attrs.push(parse_quote!(#[serde(with = #with)]));
+            }
+        }
+        let name = &field.ident;
+        let ty = &field.ty;
+        mirror.push(quote!(#(#attrs)* #name: #ty));
+    }
+
+    // The remote functions generated by serde need the same bounds that it
+    // infers for the fields, which are generic on the type parameters only.
+    let mut ser_generics = input.generics.clone();
+    let mut de_generics = input.generics.clone();
+    de_generics.params.insert(0, parse_quote!('de));
+    for param in input.generics.type_params() {
+        let ident = &param.ident;
+        ser_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(#ident: #serde::Serialize));
+        de_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(#ident: #serde::Deserialize<'de> + ::core::default::Default));
+    }
+    let (ser_impl_generics, _, ser_where_clause) = ser_generics.split_for_impl();
+    let (de_impl_generics, _, de_where_clause) = de_generics.split_for_impl();
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let remote = name.to_string();
+    let krate = serde.to_string().replace(' ', "");
+    let turbofish = ty_generics.as_turbofish();
+
+    Ok(quote! {
+        const _: () = {
+            #[derive(#serde::Serialize, #serde::Deserialize)]
+            #[serde(crate = #krate, remote = #remote)]
+            #(#container)*
+            struct LambdaEventType #impl_generics #where_clause {
+                #(#mirror,)*
+            }
+
+            impl #ser_impl_generics #serde::Serialize for #name #ty_generics #ser_where_clause {
+                fn serialize<S>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error>
+                where
+                    S: #serde::Serializer,
+                {
+                    LambdaEventType #turbofish::serialize(self, serializer)
+                }
+            }
+
+            impl #de_impl_generics #serde::Deserialize<'de> for #name #ty_generics #de_where_clause {
+                fn deserialize<D>(deserializer: D) -> ::core::result::Result<Self, D::Error>
+                where
+                    D: #serde::Deserializer<'de>,
+                {
+                    LambdaEventType #turbofish::deserialize(deserializer)
+                }
+            }
+        };
+    })
+}
+
+fn serde_attrs(attrs: &[Attribute]) -> Vec<Attribute> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("serde"))
+        .cloned()
+        .collect()
+}
+
+// Return the names of the arguments of `#[serde(...)]` attributes.
+fn serde_keys(attrs: &[Attribute]) -> syn::Result<Vec<String>> {
+    let mut keys = Vec::new();
+    for attr in attrs {
+        attr.parse_nested_meta(|meta| {
+            keys.push(meta.path.to_token_stream().to_string());
+            if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<syn::Expr>()?;
+            } else if meta.input.peek(syn::token::Paren) {
+                meta.input.parse::<proc_macro2::Group>()?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(keys)
+}
+
+// Return the conventions module for the type of a field.
+fn convention(ty: &Type) -> Option<&'static str> {
+    let (name, args) = last_segment(ty)?;
+    match (name.as_str(), args.as_slice()) {
+        ("Vec", [inner]) if last_segment(inner).map(|(name, _)| name == "u8").unwrap_or(false) => Some("base64"),
+        ("DateTime", [inner]) if is_utc(inner) => Some("milliseconds"),
+        ("Option", [inner]) => match last_segment(inner) {
+            Some((name, args)) if name == "DateTime" && args.len() == 1 && is_utc(args[0]) => {
+                Some("optional_milliseconds")
+            }
+            _ => None,
+        },
+        ("Vec" | "HashMap" | "BTreeMap" | "HashSet" | "BTreeSet", _) => Some("nullable"),
+        _ => None,
+    }
+}
+
+fn is_utc(ty: &Type) -> bool {
+    last_segment(ty).map(|(name, _)| name == "Utc").unwrap_or(false)
+}
+
+fn last_segment(ty: &Type) -> Option<(String, Vec<&Type>)> {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?;
+    let args = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    Some((segment.ident.to_string(), args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(input: TokenStream2) -> syn::Result<String> {
+        derive(&syn::parse2(input).unwrap()).map(|tokens| tokens.to_string())
+    }
+
+    #[test]
+    fn selects_conventions_from_field_types() {
+        let cases: [(Type, Option<&str>); 7] = [
+            (parse_quote!(Vec<u8>), Some("base64")),
+            (parse_quote!(chrono::DateTime<chrono::Utc>), Some("milliseconds")),
+            (parse_quote!(Option<DateTime<Utc>>), Some("optional_milliseconds")),
+            (parse_quote!(HashMap<String, String>), Some("nullable")),
+            (parse_quote!(Vec<String>), Some("nullable")),
+            (parse_quote!(Option<String>), None),
+            (parse_quote!(String), None),
+        ];
+        for (ty, expected) in cases {
+            assert_eq!(expected, convention(&ty), "{}", ty.to_token_stream());
+        }
+    }
+
+    #[test]
+    fn keeps_custom_serde_attributes() {
+        let expanded = expand(quote! {
+            #[serde(rename_all = "PascalCase")]
+            struct Event {
+                #[serde(default = "default_id")]
+                id: String,
+                #[serde(with = "custom")]
+                payload: Vec<u8>,
+            }
+        })
+        .unwrap();
+        assert!(!expanded.contains("camelCase"), "{expanded}");
+        assert!(!expanded.contains("base64"), "{expanded}");
+        assert!(
+            expanded.contains("# [serde (default = \"default_id\")] id"),
+            "{expanded}"
+        );
+    }
+
+    #[test]
+    fn rejects_enums_and_tuple_structs() {
+        assert!(expand(quote!(
+            enum Event {
+                A,
+            }
+        ))
+        .is_err());
+        assert!(expand(quote!(
+            struct Event(String);
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Serde helpers that implement the conventions of the AWS event payloads.
+//!
+//! Use them with `#[serde(with = "...")]` on custom event types, or derive
+//! [`LambdaEventType`](crate::LambdaEventType) with the `derive` feature to
+//! apply them based on the types of the fields.
+//!
+//! ```
+//! use aws_lambda_events::conventions;
+//! use serde::{Deserialize, Serialize};
+//! use std::collections::HashMap;
+//!
+//! #[derive(Deserialize, Serialize)]
+//! #[serde(rename_all = "camelCase")]
+//! struct PartnerEvent {
+//!     #[serde(default, with = "conventions::base64")]
+//!     payload: Vec<u8>,
+//!     #[serde(default, with = "conventions::nullable")]
+//!     attributes: HashMap<String, String>,
+//! }
+//!
+//! let event: PartnerEvent = serde_json::from_str(r#"{"payload":"aGVsbG8=","attributes":null}"#).unwrap();
+//! assert_eq!(b"hello", event.payload.as_slice());
+//! assert!(event.attributes.is_empty());
+//! ```
+
+/// Binary data encoded in base64.
+pub mod base64 {
+    use serde::{Deserializer, Serializer};
+
+    /// Serialize bytes as a base64 string.
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        crate::custom_serde::serialize_base64(value, serializer)
+    }
+
+    /// Deserialize bytes from a base64 string.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        crate::custom_serde::deserialize_base64(deserializer)
+    }
+}
+
+/// Values that AWS sends as `null` instead of empty, like maps and lists.
+pub mod nullable {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialize the value as is.
+    pub fn serialize<T: Serialize, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+
+    /// Deserialize the value, mapping `null` to its default.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Deserialize<'de> + Default,
+        D: Deserializer<'de>,
+    {
+        // https://github.com/serde-rs/serde/issues/1098
+        Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+    }
+}
+
+/// Timestamps in milliseconds since the Unix epoch.
+#[cfg(feature = "chrono")]
+pub mod milliseconds {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserializer, Serializer};
+
+    /// Serialize a timestamp in milliseconds since the Unix epoch.
+    pub fn serialize<S: Serializer>(value: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::encodings::serialize_milliseconds(value, serializer)
+    }
+
+    /// Deserialize a timestamp in milliseconds since the Unix epoch.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        crate::encodings::deserialize_milliseconds(deserializer)
+    }
+}
+
+/// Optional timestamps in milliseconds since the Unix epoch.
+#[cfg(feature = "chrono")]
+pub mod optional_milliseconds {
+    use crate::encodings::MillisecondTimestamp;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Serialize an optional timestamp in milliseconds since the Unix epoch.
+    pub fn serialize<S: Serializer>(value: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.map(MillisecondTimestamp).serialize(serializer)
+    }
+
+    /// Deserialize an optional timestamp in milliseconds since the Unix epoch.
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<MillisecondTimestamp>::deserialize(deserializer)?.map(|timestamp| timestamp.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[test]
+    fn nullable_maps_null_to_the_default() {
+        #[derive(Deserialize, Serialize)]
+        struct Test {
+            #[serde(with = "nullable")]
+            values: HashMap<String, u64>,
+        }
+        let test: Test = serde_json::from_str(r#"{"values":null}"#).unwrap();
+        assert!(test.values.is_empty());
+        assert_eq!(r#"{"values":{}}"#, serde_json::to_string(&test).unwrap());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn optional_milliseconds_round_trip() {
+        use chrono::{DateTime, TimeZone, Utc};
+
+        #[derive(Deserialize, Serialize)]
+        struct Test {
+            #[serde(with = "optional_milliseconds")]
+            at: Option<DateTime<Utc>>,
+        }
+        let test: Test = serde_json::from_str(r#"{"at":1700000000500}"#).unwrap();
+        assert_eq!(Some(Utc.timestamp_millis_opt(1_700_000_000_500).unwrap()), test.at);
+        assert_eq!(r#"{"at":"1700000000500"}"#, serde_json::to_string(&test).unwrap());
+
+        let test: Test = serde_json::from_str(r#"{"at":null}"#).unwrap();
+        assert_eq!(None, test.at);
+    }
+}
@@ -96,7 +96,6 @@ where
 mod test {
     use super::*;
     use serde::{Deserialize, Serialize};
-    use serde_json;
 
     #[test]
     fn test_deserialize_base64() {
@@ -177,18 +176,18 @@ mod test {
 
         let test = r#"{"v": null}"#;
         let decoded: Test = serde_json::from_str(test).unwrap();
-        assert_eq!(false, decoded.v);
+        assert!(!decoded.v);
 
         let test = r#"{}"#;
         let decoded: Test = serde_json::from_str(test).unwrap();
-        assert_eq!(false, decoded.v);
+        assert!(!decoded.v);
 
         let test = r#"{"v": true}"#;
         let decoded: Test = serde_json::from_str(test).unwrap();
-        assert_eq!(true, decoded.v);
+        assert!(decoded.v);
 
         let test = r#"{"v": false}"#;
         let decoded: Test = serde_json::from_str(test).unwrap();
-        assert_eq!(false, decoded.v);
+        assert!(!decoded.v);
     }
 }
@@ -94,7 +94,7 @@ impl DerefMut for MinuteDuration {
     }
 }
 
-fn serialize_milliseconds<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+pub(crate) fn serialize_milliseconds<S>(date: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -102,7 +102,7 @@ where
     serializer.serialize_str(&ts_with_millis.to_string())
 }
 
-fn deserialize_milliseconds<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+pub(crate) fn deserialize_milliseconds<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -281,7 +281,7 @@ mod test {
 
         // Make sure milliseconds are included.
         let instance = Test {
-            v: Utc.ymd(1983, 7, 22).and_hms_nano(1, 0, 0, 1_234_000_000),
+            v: Utc.ymd(1983, 7, 22).and_hms_nano(1, 0, 1, 234_000_000),
         };
         let encoded = serde_json::to_string(&instance).unwrap();
         assert_eq!(encoded, String::from(r#"{"v":"427683601.234"}"#));
@@ -747,12 +747,104 @@ pub struct IamPolicyStatement {
     pub resource: Vec<String>,
 }
 
+/// `ApiGatewayPassthroughRequest` contains the event that API Gateway sends to a function with a
+/// Lambda custom (non-proxy) integration and the "Method Request passthrough" mapping template.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ApiGatewayPassthroughRequest<T1 = Value>
+where
+    T1: DeserializeOwned,
+    T1: Serialize,
+{
+    #[serde(bound = "", default, rename = "body-json")]
+    pub body_json: T1,
+    #[serde(default)]
+    pub params: ApiGatewayPassthroughRequestParams,
+    #[serde(deserialize_with = "deserialize_lambda_map")]
+    #[serde(default, rename = "stage-variables")]
+    pub stage_variables: HashMap<String, String>,
+    #[serde(default)]
+    pub context: ApiGatewayPassthroughRequestContext,
+}
+
+/// `ApiGatewayPassthroughRequestParams` contains the path, query string and header parameters of
+/// the method request of a non-proxy integration.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ApiGatewayPassthroughRequestParams {
+    #[serde(deserialize_with = "deserialize_lambda_map")]
+    #[serde(default)]
+    pub path: HashMap<String, String>,
+    #[serde(deserialize_with = "deserialize_lambda_map")]
+    #[serde(default)]
+    pub querystring: HashMap<String, String>,
+    #[serde(deserialize_with = "deserialize_lambda_map")]
+    #[serde(default)]
+    pub header: HashMap<String, String>,
+}
+
+/// `ApiGatewayPassthroughRequestContext` contains the `$context` variables of the method request
+/// of a non-proxy integration. API Gateway sends empty strings for the variables that aren't set.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ApiGatewayPassthroughRequestContext {
+    #[serde(default)]
+    pub account_id: Option<String>,
+    #[serde(default)]
+    pub api_id: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub authorizer_principal_id: Option<String>,
+    #[serde(default)]
+    pub caller: Option<String>,
+    #[serde(default)]
+    pub cognito_authentication_provider: Option<String>,
+    #[serde(default)]
+    pub cognito_authentication_type: Option<String>,
+    #[serde(default)]
+    pub cognito_identity_id: Option<String>,
+    #[serde(default)]
+    pub cognito_identity_pool_id: Option<String>,
+    #[serde(deserialize_with = "http_method::deserialize_optional")]
+    #[serde(serialize_with = "http_method::serialize_optional")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub http_method: Option<Method>,
+    #[serde(default)]
+    pub stage: Option<String>,
+    #[serde(default)]
+    pub source_ip: Option<String>,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub user_arn: Option<String>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub resource_id: Option<String>,
+    #[serde(default)]
+    pub resource_path: Option<String>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     use serde_json;
 
+    #[test]
+    #[cfg(feature = "apigw")]
+    fn example_apigw_passthrough_request() {
+        let data = include_bytes!("../../fixtures/example-apigw-passthrough-request.json");
+        let parsed: ApiGatewayPassthroughRequest = serde_json::from_slice(data).unwrap();
+        assert_eq!(Some(Method::POST), parsed.context.http_method);
+        assert_eq!("42", parsed.params.path["petId"]);
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: ApiGatewayPassthroughRequest = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
     #[test]
     #[cfg(feature = "apigw")]
     fn example_apigw_custom_auth_request_type_request() {
@@ -0,0 +1,395 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Namespace of the metrics of Route 53. The status of a health check is published in the
+/// `HealthCheckStatus` metric, so its changes are delivered as alarm state changes.
+pub const ROUTE53_NAMESPACE: &str = "AWS/Route53";
+
+/// Dimension of the Route 53 metrics with the id of the health check.
+pub const ROUTE53_HEALTH_CHECK_ID_DIMENSION: &str = "HealthCheckId";
+
+/// `CloudWatchAlarmStateChange` is the `detail` of the `CloudWatch Alarm State Change` events
+/// delivered by EventBridge, with the configuration of a metric alarm by default. Use it as
+/// the `detail` of a `CloudWatchEvent`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudWatchAlarmStateChange<C = CloudWatchMetricAlarmConfiguration>
+where
+    C: DeserializeOwned,
+    C: Serialize,
+{
+    pub alarm_name: String,
+    pub state: CloudWatchAlarmState,
+    pub previous_state: CloudWatchAlarmState,
+    #[serde(bound = "")]
+    pub configuration: C,
+}
+
+/// `CloudWatchMetricAlarmStateChange` is the state change of an alarm on metrics.
+pub type CloudWatchMetricAlarmStateChange = CloudWatchAlarmStateChange<CloudWatchMetricAlarmConfiguration>;
+
+/// `CloudWatchCompositeAlarmStateChange` is the state change of a composite alarm.
+pub type CloudWatchCompositeAlarmStateChange = CloudWatchAlarmStateChange<CloudWatchCompositeAlarmConfiguration>;
+
+/// `CloudWatchAlarmState` is the state of an alarm, before or after a state change.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudWatchAlarmState {
+    pub value: CloudWatchAlarmStateValue,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// JSON document with the data points, or the triggering alarms of composite alarms,
+    /// that caused the state change.
+    #[serde(default)]
+    pub reason_data: Option<String>,
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actions_suppressed_by: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actions_suppressed_reason: Option<String>,
+}
+
+impl CloudWatchAlarmState {
+    /// Parse the JSON document of `reason_data`.
+    pub fn reason_data_json(&self) -> Option<Value> {
+        serde_json::from_str(self.reason_data.as_deref()?).ok()
+    }
+}
+
+/// `CloudWatchAlarmStateValue` is the value of the state of an alarm.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CloudWatchAlarmStateValue {
+    #[default]
+    Ok,
+    Alarm,
+    InsufficientData,
+}
+
+impl fmt::Display for CloudWatchAlarmStateValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match self {
+            CloudWatchAlarmStateValue::Ok => "OK",
+            CloudWatchAlarmStateValue::Alarm => "ALARM",
+            CloudWatchAlarmStateValue::InsufficientData => "INSUFFICIENT_DATA",
+        };
+        write!(f, "{}", val)
+    }
+}
+
+/// `CloudWatchMetricAlarmConfiguration` is the configuration of an alarm on metrics.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudWatchMetricAlarmConfiguration {
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub metrics: Vec<CloudWatchMetricDataQuery>,
+}
+
+impl CloudWatchMetricAlarmConfiguration {
+    /// Return the dimensions of the metrics of the alarm. When several metrics have a
+    /// dimension with the same name, the value of the first metric is kept.
+    pub fn dimensions(&self) -> HashMap<String, String> {
+        let mut dimensions = HashMap::new();
+        for stat in self.metrics.iter().filter_map(|query| query.metric_stat.as_ref()) {
+            for (name, value) in &stat.metric.dimensions {
+                dimensions.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        dimensions
+    }
+
+    /// Return the id of the Route 53 health check monitored by the alarm, if any.
+    pub fn route53_health_check_id(&self) -> Option<&str> {
+        self.metrics
+            .iter()
+            .filter_map(|query| query.metric_stat.as_ref())
+            .filter(|stat| stat.metric.namespace == ROUTE53_NAMESPACE)
+            .find_map(|stat| stat.metric.dimensions.get(ROUTE53_HEALTH_CHECK_ID_DIMENSION))
+            .map(String::as_str)
+    }
+}
+
+/// `CloudWatchMetricDataQuery` is a metric, or a math expression on metrics, evaluated by an alarm.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudWatchMetricDataQuery {
+    pub id: String,
+    #[serde(default)]
+    pub metric_stat: Option<CloudWatchMetricStat>,
+    #[serde(default)]
+    pub expression: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub return_data: Option<bool>,
+}
+
+/// `CloudWatchMetricStat` is a metric with the statistic and the period of its evaluation.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudWatchMetricStat {
+    pub metric: CloudWatchMetric,
+    pub period: i64,
+    pub stat: String,
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// `CloudWatchMetric` identifies a metric by its namespace, name and dimensions.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudWatchMetric {
+    pub namespace: String,
+    pub name: String,
+    #[serde(default)]
+    pub dimensions: HashMap<String, String>,
+}
+
+/// `CloudWatchCompositeAlarmConfiguration` is the configuration of a composite alarm.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudWatchCompositeAlarmConfiguration {
+    pub alarm_rule: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub actions_suppressor: Option<String>,
+    #[serde(default)]
+    pub actions_suppressor_wait_period: Option<i64>,
+    #[serde(default)]
+    pub actions_suppressor_extension_period: Option<i64>,
+}
+
+/// `CloudWatchAlarmSnsMessage` is the message that CloudWatch publishes to the SNS topics
+/// of the actions of an alarm, in the `Message` of the SNS record.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CloudWatchAlarmSnsMessage {
+    pub alarm_name: String,
+    #[serde(default)]
+    pub alarm_description: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "AWSAccountId")]
+    pub aws_account_id: Option<String>,
+    #[serde(default)]
+    pub alarm_configuration_updated_timestamp: Option<String>,
+    pub new_state_value: CloudWatchAlarmStateValue,
+    #[serde(default)]
+    pub new_state_reason: Option<String>,
+    #[serde(default)]
+    pub state_change_time: Option<String>,
+    /// Name of the region, like `US East (N. Virginia)`.
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub alarm_arn: Option<String>,
+    #[serde(default)]
+    pub old_state_value: Option<CloudWatchAlarmStateValue>,
+    #[serde(default)]
+    #[serde(rename = "OKActions")]
+    pub ok_actions: Vec<String>,
+    #[serde(default)]
+    pub alarm_actions: Vec<String>,
+    #[serde(default)]
+    pub insufficient_data_actions: Vec<String>,
+    /// Set for alarms on metrics.
+    #[serde(default)]
+    pub trigger: Option<CloudWatchAlarmTrigger>,
+    /// Set for composite alarms.
+    #[serde(default)]
+    pub alarm_rule: Option<String>,
+}
+
+/// `CloudWatchAlarmTrigger` is the condition of an alarm on metrics, in SNS messages.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CloudWatchAlarmTrigger {
+    #[serde(default)]
+    pub metric_name: Option<String>,
+    #[serde(default)]
+    pub namespace: Option<String>,
+    #[serde(default)]
+    pub statistic_type: Option<String>,
+    #[serde(default)]
+    pub statistic: Option<String>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub dimensions: Vec<CloudWatchAlarmDimension>,
+    #[serde(default)]
+    pub period: Option<i64>,
+    #[serde(default)]
+    pub evaluation_periods: Option<i64>,
+    #[serde(default)]
+    pub datapoints_to_alarm: Option<i64>,
+    #[serde(default)]
+    pub comparison_operator: Option<String>,
+    #[serde(default)]
+    pub threshold: Option<f64>,
+    #[serde(default)]
+    pub treat_missing_data: Option<String>,
+    #[serde(default)]
+    pub evaluate_low_sample_count_percentile: Option<String>,
+    /// Set for alarms on metric math expressions, instead of the metric fields.
+    #[serde(default)]
+    pub metrics: Vec<CloudWatchAlarmTriggerMetric>,
+}
+
+impl CloudWatchAlarmTrigger {
+    /// Return the dimensions of the metrics of the alarm. When several metrics have a
+    /// dimension with the same name, the value of the first metric is kept.
+    pub fn dimensions(&self) -> HashMap<String, String> {
+        let nested = self
+            .metrics
+            .iter()
+            .filter_map(|query| query.metric_stat.as_ref())
+            .flat_map(|stat| &stat.metric.dimensions);
+        let mut dimensions = HashMap::new();
+        for dimension in self.dimensions.iter().chain(nested) {
+            dimensions
+                .entry(dimension.name.clone())
+                .or_insert_with(|| dimension.value.clone());
+        }
+        dimensions
+    }
+
+    /// Return the id of the Route 53 health check monitored by the alarm, if any.
+    pub fn route53_health_check_id(&self) -> Option<&str> {
+        if self.namespace.as_deref() != Some(ROUTE53_NAMESPACE) {
+            return None;
+        }
+        self.dimensions
+            .iter()
+            .find(|dimension| dimension.name == ROUTE53_HEALTH_CHECK_ID_DIMENSION)
+            .map(|dimension| dimension.value.as_str())
+    }
+}
+
+/// `CloudWatchAlarmDimension` is a dimension of a metric, in SNS messages.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CloudWatchAlarmDimension {
+    pub name: String,
+    pub value: String,
+}
+
+/// `CloudWatchAlarmTriggerMetric` is a metric, or a math expression on metrics, in SNS messages.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CloudWatchAlarmTriggerMetric {
+    pub id: String,
+    #[serde(default)]
+    pub metric_stat: Option<CloudWatchAlarmTriggerMetricStat>,
+    #[serde(default)]
+    pub expression: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub return_data: Option<bool>,
+}
+
+/// `CloudWatchAlarmTriggerMetricStat` is a metric with the statistic and the period of its
+/// evaluation, in SNS messages.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CloudWatchAlarmTriggerMetricStat {
+    pub metric: CloudWatchAlarmTriggerMetricId,
+    pub period: i64,
+    pub stat: String,
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// `CloudWatchAlarmTriggerMetricId` identifies a metric, in SNS messages.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CloudWatchAlarmTriggerMetricId {
+    pub namespace: String,
+    pub metric_name: String,
+    #[serde(default)]
+    pub dimensions: Vec<CloudWatchAlarmDimension>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json;
+
+    #[test]
+    #[cfg(feature = "cloudwatch_alarms")]
+    fn example_cloudwatch_alarm_state_change() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-alarm-state-change.json");
+        let parsed: CloudWatchMetricAlarmStateChange = serde_json::from_slice(data).unwrap();
+        assert_eq!(CloudWatchAlarmStateValue::Alarm, parsed.state.value);
+        assert_eq!(
+            Some("i-12345678901234567"),
+            parsed.configuration.dimensions().get("InstanceId").map(String::as_str)
+        );
+        assert!(parsed.state.reason_data_json().unwrap()["recentDatapoints"].is_array());
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: CloudWatchMetricAlarmStateChange = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "cloudwatch_alarms")]
+    fn example_cloudwatch_composite_alarm_state_change() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-composite-alarm-state-change.json");
+        let parsed: CloudWatchCompositeAlarmStateChange = serde_json::from_slice(data).unwrap();
+        assert_eq!(CloudWatchAlarmStateValue::Ok, parsed.previous_state.value);
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: CloudWatchCompositeAlarmStateChange = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "cloudwatch_alarms")]
+    fn example_cloudwatch_route53_health_check_alarm_state_change() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-route53-health-check-alarm-state-change.json");
+        let parsed: CloudWatchMetricAlarmStateChange = serde_json::from_slice(data).unwrap();
+        assert_eq!(
+            Some("abcdef11-2222-3333-4444-555555fedcba"),
+            parsed.configuration.route53_health_check_id()
+        );
+        assert_eq!(CloudWatchAlarmStateValue::Alarm, parsed.state.value);
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: CloudWatchMetricAlarmStateChange = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "cloudwatch_alarms")]
+    fn example_cloudwatch_alarm_sns_message() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-alarm-sns-message.json");
+        let parsed: CloudWatchAlarmSnsMessage = serde_json::from_slice(data).unwrap();
+        let trigger = parsed.trigger.as_ref().unwrap();
+        assert_eq!(Some(80.0), trigger.threshold);
+        let dimensions = trigger.dimensions();
+        assert_eq!(Some("my-function"), dimensions.get("FunctionName").map(String::as_str));
+        assert_eq!(None, trigger.route53_health_check_id());
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: CloudWatchAlarmSnsMessage = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(all(feature = "cloudwatch_alarms", feature = "sns"))]
+    fn example_cloudwatch_alarm_sns_event_multiple_metrics() {
+        use crate::event::sns::SnsEventObj;
+
+        let data = include_bytes!("../../fixtures/example-cloudwatch-alarm-sns-payload-multiple-metrics.json");
+        let parsed: SnsEventObj<CloudWatchAlarmSnsMessage> = serde_json::from_slice(data).unwrap();
+        let message = &parsed.records[0].sns.message;
+        assert_eq!(CloudWatchAlarmStateValue::Alarm, message.new_state_value);
+        let dimensions = message.trigger.as_ref().unwrap().dimensions();
+        assert_eq!(Some("TestInstance"), dimensions.get("InstanceId").map(String::as_str));
+    }
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+/// `detail-type` of the events sent daily for certificates that expire in 45 days or less.
+pub const APPROACHING_EXPIRATION_DETAIL_TYPE: &str = "ACM Certificate Approaching Expiration";
+
+/// `detail-type` of the events sent when a certificate expires.
+pub const EXPIRED_DETAIL_TYPE: &str = "ACM Certificate Expired";
+
+/// `detail-type` of the events sent when a certificate is issued, renewed or imported.
+pub const AVAILABLE_DETAIL_TYPE: &str = "ACM Certificate Available";
+
+/// `detail-type` of the events sent when the renewal of a certificate needs an action.
+pub const RENEWAL_ACTION_REQUIRED_DETAIL_TYPE: &str = "ACM Certificate Renewal Action Required";
+
+/// `CertificateEvent` is the detail of the certificate lifecycle events of ACM.
+/// The fields that don't apply to a detail type are unset.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CertificateEvent {
+    /// `ISSUANCE`, `RENEWAL`, `IMPORT` or `REIMPORT`, for available certificates.
+    #[serde(default)]
+    pub action: Option<String>,
+    #[serde(default)]
+    pub certificate_type: Option<String>,
+    pub common_name: String,
+    /// `DNS` or `EMAIL`.
+    #[serde(default)]
+    pub domain_validation_method: Option<String>,
+    #[serde(default)]
+    pub certificate_created_date: Option<String>,
+    #[serde(default)]
+    pub certificate_expiration_date: Option<String>,
+    #[serde(default)]
+    pub days_to_expiry: Option<i64>,
+    #[serde(default)]
+    pub in_use: Option<bool>,
+    #[serde(default)]
+    pub exported: Option<bool>,
+    /// Status of the renewal, for the certificates that need an action.
+    #[serde(default)]
+    pub renewal_status: Option<String>,
+    #[serde(default)]
+    pub failure_reason: Option<String>,
+}
+
+impl CertificateEvent {
+    /// Return the domain of the certificate, without the `*.` of wildcard certificates.
+    pub fn domain_name(&self) -> &str {
+        self.common_name.strip_prefix("*.").unwrap_or(&self.common_name)
+    }
+
+    /// Whether the certificate covers the subdomains of its domain.
+    pub fn is_wildcard(&self) -> bool {
+        self.common_name.starts_with("*.")
+    }
+
+    /// Whether the domain of the certificate is validated with DNS records.
+    pub fn is_dns_validated(&self) -> bool {
+        self.domain_validation_method.as_deref() == Some("DNS")
+    }
+
+    /// Whether the certificate expires in `days` days or less.
+    pub fn expires_within(&self, days: i64) -> bool {
+        self.days_to_expiry.map_or(false, |expiry| expiry <= days)
+    }
+
+    /// Return the fully qualified name of the TXT record of an ACME DNS-01 challenge
+    /// for the domain of the certificate, like `_acme-challenge.example.com.`.
+    pub fn dns_challenge_record_name(&self) -> String {
+        format!("_acme-challenge.{}.", self.domain_name().trim_end_matches('.'))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::event::cloudwatch_events::CloudWatchEvent;
+
+    #[test]
+    #[cfg(feature = "cloudwatch_events")]
+    fn example_acm_certificate_approaching_expiration() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-acm-certificate-approaching-expiration.json");
+        let parsed: CloudWatchEvent<CertificateEvent> = serde_json::from_slice(data).unwrap();
+        assert_eq!(Some(APPROACHING_EXPIRATION_DETAIL_TYPE), parsed.detail_type.as_deref());
+        let detail = parsed.detail.as_ref().unwrap();
+        assert!(detail.expires_within(30));
+        assert!(detail.is_wildcard());
+        assert_eq!("example.com", detail.domain_name());
+        assert_eq!("_acme-challenge.example.com.", detail.dns_challenge_record_name());
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: CloudWatchEvent<CertificateEvent> = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "cloudwatch_events")]
+    fn example_acm_certificate_available() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-acm-certificate-available.json");
+        let parsed: CloudWatchEvent<CertificateEvent> = serde_json::from_slice(data).unwrap();
+        let detail = parsed.detail.as_ref().unwrap();
+        assert_eq!(Some("RENEWAL"), detail.action.as_deref());
+        assert!(detail.is_dns_validated());
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: CloudWatchEvent<CertificateEvent> = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::CloudWatchEvent;
+
+/// `detail-type` of the events sent when findings are created or updated.
+pub const FINDING_DETAIL_TYPE: &str = "GuardDuty Finding";
+
+/// `GuardDutyFindingEvent` is the EventBridge event of a finding of GuardDuty.
+pub type GuardDutyFindingEvent = CloudWatchEvent<Finding>;
+
+/// `Finding` is a finding of GuardDuty. The details of the resource, and of the action
+/// that caused the finding, depend on its type and are left as JSON values.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Finding {
+    pub schema_version: String,
+    pub account_id: String,
+    pub region: String,
+    #[serde(default)]
+    pub partition: Option<String>,
+    pub id: String,
+    pub arn: String,
+    /// Type of the finding, like `UnauthorizedAccess:EC2/SSHBruteForce`.
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub resource: FindingResource,
+    pub service: FindingService,
+    pub severity: f64,
+    pub created_at: String,
+    pub updated_at: String,
+    pub title: String,
+    pub description: String,
+}
+
+impl Finding {
+    /// Return the label of the severity of the finding: `Low`, `Medium`, `High` or `Critical`.
+    pub fn severity_label(&self) -> &'static str {
+        match self.severity {
+            s if s >= 9.0 => "Critical",
+            s if s >= 7.0 => "High",
+            s if s >= 4.0 => "Medium",
+            _ => "Low",
+        }
+    }
+
+    /// Return the kind of resource of the finding, the part of the type between `:` and `/`,
+    /// like `EC2` for `UnauthorizedAccess:EC2/SSHBruteForce`.
+    pub fn resource_type_name(&self) -> Option<&str> {
+        let (_, rest) = self.type_.split_once(':')?;
+        rest.split('/').next()
+    }
+}
+
+/// `FindingResource` is the resource affected by a finding.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindingResource {
+    /// `Instance`, `AccessKey`, `S3Bucket`, `EKSCluster`, and so on.
+    pub resource_type: String,
+    /// Details of the resource, keyed by their kind, like `instanceDetails`.
+    #[serde(flatten)]
+    pub details: HashMap<String, Value>,
+}
+
+/// `FindingService` describes how GuardDuty detected a finding.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindingService {
+    pub service_name: String,
+    pub detector_id: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<Value>,
+    #[serde(default)]
+    pub resource_role: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub additional_info: Option<Value>,
+    #[serde(default)]
+    pub event_first_seen: Option<String>,
+    #[serde(default)]
+    pub event_last_seen: Option<String>,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub count: i64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "cloudwatch_events")]
+    fn example_guardduty_finding() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-guardduty-finding.json");
+        let parsed: GuardDutyFindingEvent = serde_json::from_slice(data).unwrap();
+        assert_eq!(Some(FINDING_DETAIL_TYPE), parsed.detail_type.as_deref());
+        let finding = parsed.detail.as_ref().unwrap();
+        assert_eq!("Medium", finding.severity_label());
+        assert_eq!(Some("EC2"), finding.resource_type_name());
+        assert_eq!("Instance", finding.resource.resource_type);
+        assert!(finding.resource.details.contains_key("instanceDetails"));
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: GuardDutyFindingEvent = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+}
@@ -1,6 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use super::CloudWatchEvent;
+
+/// `detail-type` of the events sent by AWS Health.
+pub const HEALTH_EVENT_DETAIL_TYPE: &str = "AWS Health Event";
+
+/// `HealthEvent` is the EventBridge event of a notification of AWS Health.
+pub type HealthEvent = CloudWatchEvent<Event>;
+
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Event {
@@ -8,12 +16,71 @@ pub struct Event {
     pub service: String,
     pub event_type_code: String,
     pub event_type_category: String,
+    #[serde(default)]
+    pub event_scope_code: Option<EventScopeCode>,
+    #[serde(default)]
+    pub status_code: Option<EventStatusCode>,
+    #[serde(default)]
+    pub communication_id: Option<String>,
+    #[serde(default)]
+    pub affected_account: Option<String>,
+    #[serde(default)]
+    pub event_region: Option<String>,
     pub start_time: String,
+    /// Empty for the events that aren't closed yet.
+    #[serde(default)]
     pub end_time: String,
+    #[serde(default)]
+    pub last_updated_time: Option<String>,
     pub event_description: Vec<EventDescription>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_metadata: Option<HashMap<String, String>>,
     pub affected_entities: Option<Vec<Entity>>,
 }
 
+impl Event {
+    /// Return the description of the event in English.
+    pub fn description(&self) -> Option<&str> {
+        self.event_description
+            .iter()
+            .find(|description| description.language == "en_US")
+            .or_else(|| self.event_description.first())
+            .map(|description| description.latest_description.as_str())
+    }
+
+    /// Return the values of the entities affected by the event, like the ids of instances.
+    pub fn affected_entity_values(&self) -> impl Iterator<Item = &str> {
+        self.affected_entities
+            .iter()
+            .flatten()
+            .map(|entity| entity.entity_value.as_str())
+    }
+}
+
+/// `EventScopeCode` tells whether an event is about a public service, or about resources
+/// of specific accounts.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EventScopeCode {
+    Public,
+    AccountSpecific,
+    None,
+    #[serde(other)]
+    Unknown,
+}
+
+/// `EventStatusCode` is the status of an event.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventStatusCode {
+    Open,
+    Closed,
+    Upcoming,
+    #[serde(other)]
+    Unknown,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EventDescription {
@@ -25,5 +92,37 @@ pub struct EventDescription {
 #[serde(rename_all = "camelCase")]
 pub struct Entity {
     pub entity_value: String,
+    #[serde(default)]
+    pub entity_arn: Option<String>,
+    /// `IMPAIRED`, `UNIMPAIRED`, `UNKNOWN`, `PENDING` or `RESOLVED`.
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub last_updated_time: Option<String>,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "cloudwatch_events")]
+    fn example_health_event() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-health-event.json");
+        let parsed: HealthEvent = serde_json::from_slice(data).unwrap();
+        assert_eq!(Some(HEALTH_EVENT_DETAIL_TYPE), parsed.detail_type.as_deref());
+        let event = parsed.detail.as_ref().unwrap();
+        assert_eq!(Some(EventScopeCode::AccountSpecific), event.event_scope_code);
+        assert_eq!(Some(EventStatusCode::Upcoming), event.status_code);
+        assert_eq!(
+            vec!["i-abcd1111", "i-abcd2222"],
+            event.affected_entity_values().collect::<Vec<_>>()
+        );
+        assert!(event.description().unwrap().starts_with("A scheduled maintenance"));
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: HealthEvent = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+}
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::CloudWatchEvent;
+
+/// `detail-type` of the events sent when findings of Amazon Inspector are created or updated.
+pub const FINDING_DETAIL_TYPE: &str = "Inspector2 Finding";
+
+/// `InspectorFindingEvent` is the EventBridge event of a finding of Amazon Inspector.
+pub type InspectorFindingEvent = CloudWatchEvent<Finding>;
+
+/// `Finding` is a finding of Amazon Inspector.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Finding {
+    pub aws_account_id: String,
+    pub finding_arn: String,
+    /// `PACKAGE_VULNERABILITY`, `CODE_VULNERABILITY` or `NETWORK_REACHABILITY`.
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub title: String,
+    pub description: String,
+    /// `INFORMATIONAL`, `LOW`, `MEDIUM`, `HIGH`, `CRITICAL` or `UNTRIAGED`.
+    pub severity: String,
+    /// `ACTIVE`, `SUPPRESSED` or `CLOSED`.
+    pub status: String,
+    #[serde(default)]
+    pub inspector_score: Option<f64>,
+    #[serde(default)]
+    pub fix_available: Option<String>,
+    #[serde(default)]
+    pub exploit_available: Option<String>,
+    #[serde(default)]
+    pub first_observed_at: Option<String>,
+    #[serde(default)]
+    pub last_observed_at: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    #[serde(default)]
+    pub package_vulnerability_details: Option<PackageVulnerabilityDetails>,
+    #[serde(default)]
+    pub remediation: Option<Remediation>,
+    pub resources: Vec<Resource>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_reachability_details: Option<Value>,
+}
+
+impl Finding {
+    /// Return the id of the vulnerability of the finding, like `CVE-2023-1234`.
+    pub fn vulnerability_id(&self) -> Option<&str> {
+        Some(self.package_vulnerability_details.as_ref()?.vulnerability_id.as_str())
+    }
+}
+
+/// `PackageVulnerabilityDetails` describes a vulnerability of the packages of a resource.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageVulnerabilityDetails {
+    pub vulnerability_id: String,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub source_url: Option<String>,
+    #[serde(default)]
+    pub vendor_severity: Option<String>,
+    #[serde(default)]
+    pub reference_urls: Vec<String>,
+    #[serde(default)]
+    pub related_vulnerabilities: Vec<String>,
+    #[serde(default)]
+    pub cvss: Vec<Cvss>,
+    #[serde(default)]
+    pub vulnerable_packages: Vec<VulnerablePackage>,
+}
+
+/// `Cvss` is a CVSS score of a vulnerability.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cvss {
+    pub base_score: f64,
+    pub scoring_vector: String,
+    pub source: String,
+    pub version: String,
+}
+
+/// `VulnerablePackage` is a package affected by a vulnerability.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VulnerablePackage {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub epoch: Option<i64>,
+    #[serde(default)]
+    pub release: Option<String>,
+    #[serde(default)]
+    pub arch: Option<String>,
+    #[serde(default)]
+    pub package_manager: Option<String>,
+    #[serde(default)]
+    pub file_path: Option<String>,
+    #[serde(default)]
+    pub fixed_in_version: Option<String>,
+    #[serde(default)]
+    pub remediation: Option<String>,
+}
+
+/// `Remediation` tells how to remediate a finding.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Remediation {
+    #[serde(default)]
+    pub recommendation: Option<Recommendation>,
+}
+
+/// `Recommendation` describes, or links to, the remediation of a finding.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Recommendation {
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "Url")]
+    pub url: Option<String>,
+}
+
+/// `Resource` is a resource a finding applies to.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    /// `AWS_EC2_INSTANCE`, `AWS_ECR_CONTAINER_IMAGE`, `AWS_LAMBDA_FUNCTION`, and so on.
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub id: String,
+    #[serde(default)]
+    pub partition: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    /// Details of the resource, keyed by its kind, like `awsEcrContainerImage`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "cloudwatch_events")]
+    fn example_inspector_finding() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-inspector-finding.json");
+        let parsed: InspectorFindingEvent = serde_json::from_slice(data).unwrap();
+        assert_eq!(Some(FINDING_DETAIL_TYPE), parsed.detail_type.as_deref());
+        let finding = parsed.detail.as_ref().unwrap();
+        assert_eq!(Some("CVE-2023-38545"), finding.vulnerability_id());
+        assert_eq!("AWS_ECR_CONTAINER_IMAGE", finding.resources[0].type_);
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: InspectorFindingEvent = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+}
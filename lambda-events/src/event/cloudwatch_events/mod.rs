@@ -3,6 +3,7 @@ use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+pub mod acm;
 pub mod cloudtrail;
 pub mod codedeploy;
 pub mod codepipeline;
@@ -10,10 +11,13 @@ pub mod ec2;
 pub mod emr;
 pub mod gamelift;
 pub mod glue;
+pub mod guardduty;
 pub mod health;
+pub mod inspector;
 pub mod kms;
 pub mod macie;
 pub mod opsworks;
+pub mod securityhub;
 pub mod signin;
 pub mod sms;
 pub mod ssm;
@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::CloudWatchEvent;
+
+/// `detail-type` of the events sent when findings are created or updated.
+pub const FINDINGS_IMPORTED_DETAIL_TYPE: &str = "Security Hub Findings - Imported";
+
+/// `detail-type` of the events sent when findings are sent to a custom action.
+pub const FINDINGS_CUSTOM_ACTION_DETAIL_TYPE: &str = "Security Hub Findings - Custom Action";
+
+/// `SecurityHubFindingsEvent` is the EventBridge event of the findings of Security Hub.
+pub type SecurityHubFindingsEvent = CloudWatchEvent<FindingsEvent>;
+
+/// `FindingsEvent` is the detail of the findings events of Security Hub.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindingsEvent {
+    /// Set for the findings sent to a custom action.
+    #[serde(default)]
+    pub action_name: Option<String>,
+    #[serde(default)]
+    pub action_description: Option<String>,
+    #[serde(default)]
+    pub findings: Vec<AwsSecurityFinding>,
+}
+
+/// `AwsSecurityFinding` is a finding in the AWS Security Finding Format (ASFF).
+/// The details of the resources, and of the less common sections, are left as JSON values.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AwsSecurityFinding {
+    pub schema_version: String,
+    pub id: String,
+    pub product_arn: String,
+    #[serde(default)]
+    pub product_name: Option<String>,
+    #[serde(default)]
+    pub company_name: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+    pub generator_id: String,
+    pub aws_account_id: String,
+    #[serde(default)]
+    pub types: Vec<String>,
+    #[serde(default)]
+    pub first_observed_at: Option<String>,
+    #[serde(default)]
+    pub last_observed_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub severity: AwsSecurityFindingSeverity,
+    #[serde(default)]
+    pub confidence: Option<i64>,
+    #[serde(default)]
+    pub criticality: Option<i64>,
+    pub title: String,
+    pub description: String,
+    #[serde(default)]
+    pub remediation: Option<AwsSecurityFindingRemediation>,
+    #[serde(default)]
+    pub source_url: Option<String>,
+    #[serde(default)]
+    pub product_fields: HashMap<String, String>,
+    #[serde(default)]
+    pub user_defined_fields: HashMap<String, String>,
+    pub resources: Vec<AwsSecurityFindingResource>,
+    #[serde(default)]
+    pub compliance: Option<AwsSecurityFindingCompliance>,
+    #[serde(default)]
+    pub workflow_state: Option<String>,
+    #[serde(default)]
+    pub workflow: Option<AwsSecurityFindingWorkflow>,
+    #[serde(default)]
+    pub record_state: Option<String>,
+    #[serde(default)]
+    pub note: Option<AwsSecurityFindingNote>,
+    #[serde(default)]
+    pub finding_provider_fields: Option<AwsSecurityFindingProviderFields>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<Value>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process: Option<Value>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threat_intel_indicators: Option<Vec<Value>>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vulnerabilities: Option<Vec<Value>>,
+}
+
+impl AwsSecurityFinding {
+    /// Return the severity label of the finding, like `HIGH`.
+    pub fn severity_label(&self) -> Option<&str> {
+        self.severity.label.as_deref()
+    }
+
+    /// Whether the finding is active and its workflow isn't resolved or suppressed.
+    pub fn is_open(&self) -> bool {
+        let workflow_status = self.workflow.as_ref().and_then(|workflow| workflow.status.as_deref());
+        self.record_state.as_deref() != Some("ARCHIVED") && !matches!(workflow_status, Some("RESOLVED" | "SUPPRESSED"))
+    }
+}
+
+/// `AwsSecurityFindingSeverity` is the severity of a finding.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AwsSecurityFindingSeverity {
+    /// `INFORMATIONAL`, `LOW`, `MEDIUM`, `HIGH` or `CRITICAL`.
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub normalized: Option<i64>,
+    #[serde(default)]
+    pub original: Option<String>,
+    #[serde(default)]
+    pub product: Option<f64>,
+}
+
+/// `AwsSecurityFindingRemediation` tells how to remediate a finding.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AwsSecurityFindingRemediation {
+    #[serde(default)]
+    pub recommendation: Option<AwsSecurityFindingRecommendation>,
+}
+
+/// `AwsSecurityFindingRecommendation` describes, or links to, the remediation of a finding.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AwsSecurityFindingRecommendation {
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// `AwsSecurityFindingResource` is a resource a finding applies to.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AwsSecurityFindingResource {
+    #[serde(rename = "Type")]
+    pub type_: String,
+    pub id: String,
+    #[serde(default)]
+    pub partition: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    /// Details of the resource, keyed by its type, like `AwsS3Bucket`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+/// `AwsSecurityFindingCompliance` is the result of the compliance check of a finding.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AwsSecurityFindingCompliance {
+    /// `PASSED`, `WARNING`, `FAILED` or `NOT_AVAILABLE`.
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub related_requirements: Vec<String>,
+    #[serde(default)]
+    pub status_reasons: Vec<Value>,
+    #[serde(default)]
+    pub security_control_id: Option<String>,
+}
+
+/// `AwsSecurityFindingWorkflow` is the status of the investigation of a finding.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AwsSecurityFindingWorkflow {
+    /// `NEW`, `NOTIFIED`, `RESOLVED` or `SUPPRESSED`.
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+/// `AwsSecurityFindingNote` is a note added to a finding.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AwsSecurityFindingNote {
+    pub text: String,
+    pub updated_by: String,
+    pub updated_at: String,
+}
+
+/// `AwsSecurityFindingProviderFields` are the values set by the provider of a finding.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AwsSecurityFindingProviderFields {
+    #[serde(default)]
+    pub severity: Option<AwsSecurityFindingSeverity>,
+    #[serde(default)]
+    pub types: Vec<String>,
+    #[serde(default)]
+    pub confidence: Option<i64>,
+    #[serde(default)]
+    pub criticality: Option<i64>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "cloudwatch_events")]
+    fn example_securityhub_findings_imported() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-securityhub-findings-imported.json");
+        let parsed: SecurityHubFindingsEvent = serde_json::from_slice(data).unwrap();
+        assert_eq!(Some(FINDINGS_IMPORTED_DETAIL_TYPE), parsed.detail_type.as_deref());
+        let finding = &parsed.detail.as_ref().unwrap().findings[0];
+        assert_eq!(Some("HIGH"), finding.severity_label());
+        assert_eq!("AwsS3Bucket", finding.resources[0].type_);
+        assert!(finding.is_open());
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: SecurityHubFindingsEvent = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+}
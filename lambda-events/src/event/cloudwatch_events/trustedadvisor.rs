@@ -1,6 +1,14 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use super::CloudWatchEvent;
+
+/// `detail-type` of the events sent when the status of a checked resource changes.
+pub const CHECK_ITEM_REFRESH_DETAIL_TYPE: &str = "Trusted Advisor Check Item Refresh Notification";
+
+/// `TrustedAdvisorEvent` is the EventBridge event of a change of the status of a check.
+pub type TrustedAdvisorEvent = CloudWatchEvent<CheckItemRefreshNotification>;
+
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CheckItemRefreshNotification {
@@ -8,8 +16,52 @@ pub struct CheckItemRefreshNotification {
     pub check_name: String,
     #[serde(rename = "check-item-detail")]
     pub check_item_detail: HashMap<String, String>,
+    /// `OK`, `WARN` or `ERROR`.
     pub status: String,
+    /// Empty for the checks of resources without ARN.
+    #[serde(default)]
     #[serde(rename = "resource_id")]
     pub resource_id: String,
     pub uuid: String,
 }
+
+impl CheckItemRefreshNotification {
+    /// Return the value of a column of the checked item, like `Region` or `Estimated Monthly Savings`.
+    pub fn check_item(&self, name: &str) -> Option<&str> {
+        self.check_item_detail.get(name).map(String::as_str)
+    }
+
+    /// Whether the check passed for the item.
+    pub fn is_ok(&self) -> bool {
+        self.status == "OK"
+    }
+
+    /// Whether the check recommends an investigation of the item.
+    pub fn is_warning(&self) -> bool {
+        self.status == "WARN"
+    }
+
+    /// Whether the check recommends an action on the item.
+    pub fn is_error(&self) -> bool {
+        self.status == "ERROR"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "cloudwatch_events")]
+    fn example_trusted_advisor_check_item_refresh() {
+        let data = include_bytes!("../../fixtures/example-cloudwatch-trustedadvisor-check-item-refresh.json");
+        let parsed: TrustedAdvisorEvent = serde_json::from_slice(data).unwrap();
+        assert_eq!(Some(CHECK_ITEM_REFRESH_DETAIL_TYPE), parsed.detail_type.as_deref());
+        let notification = parsed.detail.as_ref().unwrap();
+        assert!(notification.is_warning());
+        assert_eq!(Some("us-east-1"), notification.check_item("Region"));
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: TrustedAdvisorEvent = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+}
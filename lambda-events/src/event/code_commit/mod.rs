@@ -65,6 +65,70 @@ pub struct CodeCommitReference {
     pub created: bool,
 }
 
+/// `CodeCommitRepositoryStateChangeEvent` is sent by EventBridge when a reference of a
+/// repository is created, updated or deleted.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeCommitRepositoryStateChangeEvent {
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "detail-type")]
+    pub detail_type: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "account")]
+    pub account_id: Option<String>,
+    pub time: DateTime<Utc>,
+    #[serde(default)]
+    pub region: Option<String>,
+    pub resources: Vec<String>,
+    pub detail: CodeCommitRepositoryStateChange,
+}
+
+/// `CodeCommitRepositoryStateChange` describes the change of a reference of a repository.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeCommitRepositoryStateChange {
+    /// `referenceCreated`, `referenceUpdated` or `referenceDeleted`.
+    #[serde(default)]
+    pub event: Option<String>,
+    #[serde(default)]
+    pub repository_name: Option<String>,
+    #[serde(default)]
+    pub repository_id: Option<String>,
+    /// `branch` or `tag`.
+    #[serde(default)]
+    pub reference_type: Option<String>,
+    #[serde(default)]
+    pub reference_name: Option<String>,
+    #[serde(default)]
+    pub reference_full_name: Option<String>,
+    /// Commit of the reference after the change, unset when it's deleted.
+    #[serde(default)]
+    pub commit_id: Option<String>,
+    /// Commit of the reference before the change, unset when it's created.
+    #[serde(default)]
+    pub old_commit_id: Option<String>,
+    #[serde(default)]
+    pub base_commit_id: Option<String>,
+    #[serde(default)]
+    pub source_commit_id: Option<String>,
+    #[serde(default)]
+    pub destination_commit_id: Option<String>,
+    #[serde(default)]
+    pub merge_option: Option<String>,
+    #[serde(default)]
+    pub conflict_details_level: Option<String>,
+    #[serde(default)]
+    pub conflict_resolution_strategy: Option<String>,
+    #[serde(default)]
+    pub caller_user_arn: Option<String>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -80,4 +144,15 @@ mod test {
         let reparsed: CodeCommitEvent = serde_json::from_slice(output.as_bytes()).unwrap();
         assert_eq!(parsed, reparsed);
     }
+
+    #[test]
+    #[cfg(feature = "code_commit")]
+    fn example_code_commit_repository_state_change_event() {
+        let data = include_bytes!("../../fixtures/example-code_commit-repository-state-change.json");
+        let parsed: CodeCommitRepositoryStateChangeEvent = serde_json::from_slice(data).unwrap();
+        assert_eq!(Some("refs/heads/main"), parsed.detail.reference_full_name.as_deref());
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: CodeCommitRepositoryStateChangeEvent = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
 }
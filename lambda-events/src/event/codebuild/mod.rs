@@ -9,6 +9,15 @@ pub type CodeBuildPhaseStatus = String;
 
 pub type CodeBuildPhaseType = String;
 
+/// `source` of the events sent by CodeBuild.
+pub const CODEBUILD_EVENT_SOURCE: &str = "aws.codebuild";
+
+/// `detail-type` of the build state-change events.
+pub const CODEBUILD_STATE_CHANGE_DETAIL_TYPE: &str = "CodeBuild Build State Change";
+
+/// `detail-type` of the build phase-change events.
+pub const CODEBUILD_PHASE_CHANGE_DETAIL_TYPE: &str = "CodeBuild Build Phase Change";
+
 /// `CodeBuildEvent` is documented at:
 /// https://docs.aws.amazon.com/codebuild/latest/userguide/sample-build-notifications.html#sample-build-notifications-ref
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
@@ -22,12 +31,12 @@ pub struct CodeBuildEvent {
     #[serde(default)]
     pub region: Option<String>,
     /// DetailType informs the schema of the Detail field. For build state-change
-    /// events, the value will be CodeBuildStateChangeDetailType. For phase-change
-    /// events, it will be CodeBuildPhaseChangeDetailType.
+    /// events, the value will be `CODEBUILD_STATE_CHANGE_DETAIL_TYPE`. For phase-change
+    /// events, it will be `CODEBUILD_PHASE_CHANGE_DETAIL_TYPE`.
     #[serde(default)]
     #[serde(rename = "detail-type")]
     pub detail_type: Option<String>,
-    /// Source should be equal to CodeBuildEventSource.
+    /// Source should be equal to `CODEBUILD_EVENT_SOURCE`.
     #[serde(default)]
     pub source: Option<String>,
     /// Version is the version of the event's schema.
@@ -45,6 +54,18 @@ pub struct CodeBuildEvent {
     pub detail: CodeBuildEventDetail,
 }
 
+impl CodeBuildEvent {
+    /// Whether this event reports a change of the state of the build.
+    pub fn is_state_change(&self) -> bool {
+        self.detail_type.as_deref() == Some(CODEBUILD_STATE_CHANGE_DETAIL_TYPE)
+    }
+
+    /// Whether this event reports the completion of a phase of the build.
+    pub fn is_phase_change(&self) -> bool {
+        self.detail_type.as_deref() == Some(CODEBUILD_PHASE_CHANGE_DETAIL_TYPE)
+    }
+}
+
 /// `CodeBuildEventDetail` represents the all details related to the code build event
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -93,6 +114,9 @@ pub struct CodeBuildEventAdditionalInformation {
     pub environment: CodeBuildEnvironment,
     #[serde(rename = "timeout-in-minutes")]
     pub timeout: MinuteDuration,
+    #[serde(default)]
+    #[serde(rename = "queued-timeout-in-minutes")]
+    pub queued_timeout: Option<MinuteDuration>,
     #[serde(rename = "build-complete")]
     pub build_complete: bool,
     #[serde(rename = "build-number")]
@@ -108,6 +132,10 @@ pub struct CodeBuildEventAdditionalInformation {
     pub source_version: Option<String>,
     pub logs: CodeBuildLogs,
     pub phases: Vec<CodeBuildPhase>,
+    /// NetworkInterface is set for the builds that run in a VPC.
+    #[serde(default)]
+    #[serde(rename = "network-interface")]
+    pub network_interface: Option<CodeBuildNetworkInterface>,
 }
 
 /// `CodeBuildArtifact` represents the artifact provided to build
@@ -181,6 +209,18 @@ pub struct CodeBuildLogs {
     pub deep_link: Option<String>,
 }
 
+/// `CodeBuildNetworkInterface` represents the network interface of a build that runs in a VPC
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeBuildNetworkInterface {
+    #[serde(default)]
+    #[serde(rename = "subnet-id")]
+    pub subnet_id: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "network-interface-id")]
+    pub network_interface_id: Option<String>,
+}
+
 /// `CodeBuildPhase` represents the phase of a build and its details
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -220,6 +260,7 @@ mod test {
     fn example_codebuild_phase_change() {
         let data = include_bytes!("../../fixtures/example-codebuild-phase-change.json");
         let parsed: CodeBuildEvent = serde_json::from_slice(data).unwrap();
+        assert!(parsed.is_phase_change());
         let output: String = serde_json::to_string(&parsed).unwrap();
         let reparsed: CodeBuildEvent = serde_json::from_slice(output.as_bytes()).unwrap();
         assert_eq!(parsed, reparsed);
@@ -234,4 +275,22 @@ mod test {
         let reparsed: CodeBuildEvent = serde_json::from_slice(output.as_bytes()).unwrap();
         assert_eq!(parsed, reparsed);
     }
+
+    #[test]
+    #[cfg(feature = "codebuild")]
+    fn example_codebuild_state_change_in_vpc() {
+        let data = include_bytes!("../../fixtures/example-codebuild-state-change-vpc.json");
+        let parsed: CodeBuildEvent = serde_json::from_slice(data).unwrap();
+        assert!(parsed.is_state_change());
+        let info = &parsed.detail.additional_information;
+        assert_eq!(
+            Some(MinuteDuration(chrono::Duration::minutes(480))),
+            info.queued_timeout
+        );
+        let network_interface = info.network_interface.as_ref().unwrap();
+        assert_eq!(Some("subnet-0123456789abcdef0"), network_interface.subnet_id.as_deref());
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: CodeBuildEvent = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
 }
@@ -40,23 +40,83 @@ pub struct EcrScanEventDetailType {
     pub image_tags: Vec<String>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+/// `EcrScanEventFindingSeverityCounts` counts the findings of a scan by severity.
+/// ECR leaves out the severities without findings, they count as zero.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EcrScanEventFindingSeverityCounts {
+    #[serde(default)]
     #[serde(rename = "CRITICAL")]
     pub critical: i64,
+    #[serde(default)]
     #[serde(rename = "HIGH")]
     pub high: i64,
+    #[serde(default)]
     #[serde(rename = "MEDIUM")]
     pub medium: i64,
+    #[serde(default)]
     #[serde(rename = "LOW")]
     pub low: i64,
+    #[serde(default)]
     #[serde(rename = "INFORMATIONAL")]
     pub informational: i64,
+    #[serde(default)]
     #[serde(rename = "UNDEFINED")]
     pub undefined: i64,
 }
 
+impl EcrScanEventFindingSeverityCounts {
+    /// Return the number of findings of all severities.
+    pub fn total(&self) -> i64 {
+        self.critical + self.high + self.medium + self.low + self.informational + self.undefined
+    }
+}
+
+/// `EcrImageActionEvent` is sent when an image is pushed to, or deleted from, a repository.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EcrImageActionEvent {
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "detail-type")]
+    pub detail_type: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub time: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+    pub resources: Vec<String>,
+    #[serde(default)]
+    pub account: Option<String>,
+    pub detail: EcrImageActionEventDetailType,
+}
+
+/// `EcrImageActionEventDetailType` describes the image and the outcome of the action.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EcrImageActionEventDetailType {
+    /// `PUSH` or `DELETE`.
+    #[serde(default)]
+    pub action_type: Option<String>,
+    /// `SUCCESS` or `FAILURE`.
+    #[serde(default)]
+    pub result: Option<String>,
+    #[serde(default)]
+    pub repository_name: Option<String>,
+    #[serde(default)]
+    pub image_digest: Option<String>,
+    #[serde(default)]
+    pub image_tag: Option<String>,
+    #[serde(default)]
+    pub manifest_media_type: Option<String>,
+    #[serde(default)]
+    pub artifact_media_type: Option<String>,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -72,4 +132,28 @@ mod test {
         let reparsed: EcrScanEvent = serde_json::from_slice(output.as_bytes()).unwrap();
         assert_eq!(parsed, reparsed);
     }
+
+    #[test]
+    #[cfg(feature = "ecr_scan")]
+    fn example_ecr_image_scan_event_with_missing_severities() {
+        let data = include_bytes!("../../fixtures/example-ecr-image-scan-event-missing-severities.json");
+        let parsed: EcrScanEvent = serde_json::from_slice(data).unwrap();
+        let counts = &parsed.detail.finding_severity_counts;
+        assert_eq!(0, counts.critical);
+        assert_eq!(3, counts.total());
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: EcrScanEvent = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "ecr_scan")]
+    fn example_ecr_image_action_event() {
+        let data = include_bytes!("../../fixtures/example-ecr-image-action-event.json");
+        let parsed: EcrImageActionEvent = serde_json::from_slice(data).unwrap();
+        assert_eq!(Some("PUSH"), parsed.detail.action_type.as_deref());
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: EcrImageActionEvent = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
 }
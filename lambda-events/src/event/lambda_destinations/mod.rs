@@ -0,0 +1,179 @@
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// `DestinationRecord` is the envelope that Lambda sends to the success and failure
+/// destinations of asynchronous invocations, and to the on-failure destinations of
+/// event source mappings.
+///
+/// `Req` is the payload of the invocation, and `Res` is the response of the function,
+/// a [`DestinationError`] for failed invocations.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(bound(deserialize = "Req: DeserializeOwned, Res: DeserializeOwned"))]
+pub struct DestinationRecord<Req: Serialize = Value, Res: Serialize = Value> {
+    #[serde(default)]
+    pub version: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub request_context: DestinationRequestContext,
+    pub request_payload: Req,
+    #[serde(default)]
+    pub response_context: Option<DestinationResponseContext>,
+    #[serde(default = "Option::default")]
+    pub response_payload: Option<Res>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationRequestContext {
+    pub request_id: String,
+    pub function_arn: String,
+    pub condition: DestinationCondition,
+    pub approximate_invoke_count: i64,
+}
+
+/// `DestinationCondition` is the reason why the record was sent to the destination.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum DestinationCondition {
+    Success,
+    RetriesExhausted,
+    EventAgeExceeded,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationResponseContext {
+    pub status_code: i64,
+    #[serde(default)]
+    pub executed_version: Option<String>,
+    /// `Handled` or `Unhandled` when the function failed.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function_error: Option<String>,
+}
+
+/// `DestinationError` is the response payload of failed invocations.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DestinationError {
+    #[serde(default)]
+    pub error_message: Option<String>,
+    #[serde(default)]
+    pub error_type: Option<String>,
+    #[serde(default)]
+    pub stack_trace: Vec<String>,
+}
+
+/// `WithMetadata` is a function response that carries metadata for the consumers of
+/// its destination records, like the version of the function that produced it.
+///
+/// Functions return it from their handler, and destination consumers receive it in
+/// [`DestinationRecord::response_payload`].
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(bound(deserialize = "T: DeserializeOwned"))]
+pub struct WithMetadata<T: Serialize> {
+    pub metadata: DestinationMetadata,
+    pub result: T,
+}
+
+impl<T: Serialize> WithMetadata<T> {
+    /// Wrap `result` without any metadata.
+    pub fn new(result: T) -> Self {
+        WithMetadata {
+            metadata: DestinationMetadata::default(),
+            result,
+        }
+    }
+
+    /// Set the version of the function that produced the result.
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.metadata.version = Some(version.into());
+        self
+    }
+
+    /// Add a custom attribute to the metadata.
+    pub fn with_attribute(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.metadata.attributes.insert(name.into(), value.into());
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DestinationMetadata {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(flatten)]
+    pub attributes: HashMap<String, Value>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json;
+
+    #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Order {
+        order_id: String,
+    }
+
+    #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+    struct Shipment {
+        shipped: bool,
+    }
+
+    #[test]
+    #[cfg(feature = "lambda_destinations")]
+    fn example_lambda_destination_success() {
+        let data = include_bytes!("../../fixtures/example-lambda-destination-success.json");
+        let parsed: DestinationRecord<Order, WithMetadata<Shipment>> = serde_json::from_slice(data).unwrap();
+        assert_eq!(DestinationCondition::Success, parsed.request_context.condition);
+        assert_eq!("42", parsed.request_payload.order_id);
+        let response = parsed.response_payload.as_ref().unwrap();
+        assert_eq!(Some("7"), response.metadata.version.as_deref());
+        assert_eq!(Value::from("acme"), response.metadata.attributes["tenant"]);
+        assert!(response.result.shipped);
+
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: DestinationRecord<Order, WithMetadata<Shipment>> =
+            serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "lambda_destinations")]
+    fn example_lambda_destination_failure() {
+        let data = include_bytes!("../../fixtures/example-lambda-destination-failure.json");
+        let parsed: DestinationRecord<Order, DestinationError> = serde_json::from_slice(data).unwrap();
+        assert_eq!(DestinationCondition::RetriesExhausted, parsed.request_context.condition);
+        assert_eq!(3, parsed.request_context.approximate_invoke_count);
+        let response_context = parsed.response_context.as_ref().unwrap();
+        assert_eq!(Some("Unhandled"), response_context.function_error.as_deref());
+        let error = parsed.response_payload.as_ref().unwrap();
+        assert_eq!(Some("order 42 is corrupted"), error.error_message.as_deref());
+
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: DestinationRecord<Order, DestinationError> = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "lambda_destinations")]
+    fn serialize_with_metadata() {
+        let response = WithMetadata::new(Shipment { shipped: true })
+            .with_version("7")
+            .with_attribute("tenant", "acme");
+        assert_eq!(
+            serde_json::json!({
+                "metadata": { "version": "7", "tenant": "acme" },
+                "result": { "shipped": true },
+            }),
+            serde_json::to_value(response).unwrap()
+        );
+    }
+}
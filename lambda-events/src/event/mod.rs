@@ -25,6 +25,10 @@ pub mod chime_bot;
 #[cfg(feature = "clientvpn")]
 pub mod clientvpn;
 
+/// CloudWatch alarm state change payloads
+#[cfg(feature = "cloudwatch_alarms")]
+pub mod cloudwatch_alarms;
+
 /// CloudWatch Events payload
 #[cfg(feature = "cloudwatch_events")]
 pub mod cloudwatch_events;
@@ -109,6 +113,10 @@ pub mod kinesis;
 #[cfg(feature = "lambda_function_urls")]
 pub mod lambda_function_urls;
 
+/// AWS Lambda event definitions for the records sent to asynchronous invocation destinations.
+#[cfg(feature = "lambda_destinations")]
+pub mod lambda_destinations;
+
 /// AWS Lambda event definitions for lex.
 #[cfg(feature = "lex")]
 pub mod lex;
@@ -136,3 +144,7 @@ pub mod sqs;
 /// AWS Lambda event definitions for streams.
 #[cfg(feature = "streams")]
 pub mod streams;
+
+/// AWS Lambda event definitions for VPC Lattice.
+#[cfg(feature = "vpc_lattice")]
+pub mod vpc_lattice;
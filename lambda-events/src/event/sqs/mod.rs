@@ -42,6 +42,18 @@ pub struct SqsMessage {
     pub aws_region: Option<String>,
 }
 
+impl SqsMessage {
+    /// The message group of a message from a FIFO queue.
+    pub fn message_group_id(&self) -> Option<&str> {
+        self.attributes.get("MessageGroupId").map(String::as_str)
+    }
+
+    /// The sequence number of a message from a FIFO queue.
+    pub fn sequence_number(&self) -> Option<&str> {
+        self.attributes.get("SequenceNumber").map(String::as_str)
+    }
+}
+
 /// Alternative to `SqsEvent` to be used alongside `SqsMessageObj<T>` when you need to deserialize a nested object into a struct of type `T` within the SQS Message rather than just using the raw SQS Message string
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -0,0 +1,159 @@
+use crate::custom_serde::{
+    deserialize_headers, deserialize_nullish_boolean, http_method, serialize_headers, serialize_multi_value_headers,
+};
+use crate::encodings::Body;
+use http::{HeaderMap, Method};
+use query_map::QueryMap;
+use serde::{Deserialize, Serialize};
+
+/// `VpcLatticeRequestV1` contains data originating from a VPC Lattice target group
+/// with the event structure version 1.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct VpcLatticeRequestV1 {
+    #[serde(with = "http_method")]
+    pub method: Method,
+    #[serde(default)]
+    pub raw_path: Option<String>,
+    #[serde(deserialize_with = "deserialize_headers", default)]
+    #[serde(serialize_with = "serialize_headers")]
+    pub headers: HeaderMap,
+    #[serde(default)]
+    pub query_string_parameters: QueryMap,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullish_boolean")]
+    pub is_base64_encoded: bool,
+}
+
+/// `VpcLatticeRequestV2` contains data originating from a VPC Lattice target group
+/// with the event structure version 2. The values of the headers and of the query
+/// string parameters are lists.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VpcLatticeRequestV2 {
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(with = "http_method")]
+    pub method: Method,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(deserialize_with = "deserialize_headers", default)]
+    #[serde(serialize_with = "serialize_multi_value_headers")]
+    pub headers: HeaderMap,
+    #[serde(default)]
+    pub query_string_parameters: QueryMap,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullish_boolean")]
+    pub is_base64_encoded: bool,
+    pub request_context: VpcLatticeRequestV2Context,
+}
+
+/// `VpcLatticeRequestV2Context` contains the information to identify the service and the caller
+/// of a VPC Lattice request.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VpcLatticeRequestV2Context {
+    #[serde(default)]
+    pub service_network_arn: Option<String>,
+    #[serde(default)]
+    pub service_arn: Option<String>,
+    #[serde(default)]
+    pub target_group_arn: Option<String>,
+    #[serde(default)]
+    pub identity: VpcLatticeRequestV2Identity,
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Time of the request in microseconds since the epoch.
+    #[serde(default)]
+    pub time_epoch: Option<String>,
+}
+
+/// `VpcLatticeRequestV2Identity` contains the identity of the caller of a VPC Lattice request.
+/// The principal fields are only set for requests authenticated with `AWS_IAM`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VpcLatticeRequestV2Identity {
+    #[serde(default)]
+    pub source_vpc_arn: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "type")]
+    pub type_: Option<String>,
+    #[serde(default)]
+    pub principal: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "principalOrgID")]
+    pub principal_org_id: Option<String>,
+    #[serde(default)]
+    pub session_name: Option<String>,
+    #[serde(default)]
+    pub x509_san_dns: Option<String>,
+    #[serde(default)]
+    pub x509_san_name_cn: Option<String>,
+    #[serde(default)]
+    pub x509_san_uri: Option<String>,
+    #[serde(default)]
+    pub x509_issuer_ou: Option<String>,
+    #[serde(default)]
+    pub x509_subject_cn: Option<String>,
+}
+
+/// `VpcLatticeResponse` configures the response to be returned by VPC Lattice for the request
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VpcLatticeResponse {
+    pub status_code: i64,
+    #[serde(default)]
+    pub status_description: Option<String>,
+    #[serde(deserialize_with = "deserialize_headers", default)]
+    #[serde(serialize_with = "serialize_headers")]
+    pub headers: HeaderMap,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Body>,
+    #[serde(default, deserialize_with = "deserialize_nullish_boolean")]
+    pub is_base64_encoded: bool,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json;
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn example_vpc_lattice_v1_request() {
+        let data = include_bytes!("../../fixtures/example-vpc-lattice-v1-request.json");
+        let parsed: VpcLatticeRequestV1 = serde_json::from_slice(data).unwrap();
+        assert_eq!(Method::GET, parsed.method);
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: VpcLatticeRequestV1 = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn example_vpc_lattice_v2_request() {
+        let data = include_bytes!("../../fixtures/example-vpc-lattice-v2-request.json");
+        let parsed: VpcLatticeRequestV2 = serde_json::from_slice(data).unwrap();
+        let values: Vec<_> = parsed.headers.get_all("x-custom").iter().collect();
+        assert_eq!(vec!["a", "b"], values);
+        assert_eq!(
+            Some("o-abcdefghij"),
+            parsed.request_context.identity.principal_org_id.as_deref()
+        );
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: VpcLatticeRequestV2 = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn example_vpc_lattice_response() {
+        let data = include_bytes!("../../fixtures/example-vpc-lattice-response.json");
+        let parsed: VpcLatticeResponse = serde_json::from_slice(data).unwrap();
+        let output: String = serde_json::to_string(&parsed).unwrap();
+        let reparsed: VpcLatticeResponse = serde_json::from_slice(output.as_bytes()).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+}
@@ -0,0 +1,502 @@
+//! Matching of events against EventBridge event patterns.
+//!
+//! An [`EventPattern`] is compiled once from the JSON of an EventBridge rule,
+//! and matched against any `serde_json::Value`. It can test the patterns of
+//! rules before they are deployed, and route heterogeneous events inside a
+//! function with a [`PatternRouter`].
+//!
+//! Patterns support exact values, `prefix`, `suffix`, `equals-ignore-case`,
+//! `wildcard`, `numeric`, `exists`, `anything-but`, and `$or`, with the
+//! semantics of EventBridge: all the fields of a pattern must match, any of
+//! the values listed for a field can match, and a field of the event that is
+//! an array matches when any of its elements matches.
+//!
+//! # Example
+//! ```
+//! use aws_lambda_events::event_pattern::EventPattern;
+//! use serde_json::json;
+//!
+//! let pattern = EventPattern::parse(
+//!     r#"{"source": ["aws.s3"], "detail": {"object": {"key": [{"wildcard": "uploads/*.png"}]}}}"#,
+//! )
+//! .unwrap();
+//!
+//! let event = json!({ "source": "aws.s3", "detail": { "object": { "key": "uploads/cat.png" } } });
+//! assert!(pattern.matches(&event));
+//! ```
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Error returned when a pattern isn't valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError(String);
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid event pattern: {}", self.0)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl PatternError {
+    pub(crate) fn new(message: impl Into<String>) -> Self {
+        PatternError(message.into())
+    }
+}
+
+fn invalid<T>(message: impl Into<String>) -> Result<T, PatternError> {
+    Err(PatternError::new(message))
+}
+
+/// Operators that are only supported by EventBridge, and not by the filters of event source mappings.
+const EVENTBRIDGE_ONLY: [&str; 2] = ["wildcard", "$or"];
+
+/// A compiled EventBridge event pattern.
+#[derive(Clone, Debug)]
+pub struct EventPattern {
+    root: Node,
+}
+
+impl EventPattern {
+    /// Compile a pattern written as a JSON string.
+    pub fn parse(pattern: &str) -> Result<Self, PatternError> {
+        let pattern: Value =
+            serde_json::from_str(pattern).or_else(|err| invalid(format!("the pattern is not JSON: {err}")))?;
+        Self::from_value(&pattern)
+    }
+
+    /// Compile a pattern.
+    pub fn from_value(pattern: &Value) -> Result<Self, PatternError> {
+        Self::from_value_without(pattern, &[])
+    }
+
+    /// Compile a pattern, rejecting the operators listed in `unsupported`.
+    fn from_value_without(pattern: &Value, unsupported: &[&str]) -> Result<Self, PatternError> {
+        match pattern {
+            Value::Object(fields) => Ok(EventPattern {
+                root: Node::parse(fields, unsupported)?,
+            }),
+            _ => invalid("the pattern must be a JSON object"),
+        }
+    }
+
+    /// Compile a pattern with the operators supported by the filters of event source mappings.
+    pub(crate) fn filter(pattern: &Value) -> Result<Self, PatternError> {
+        Self::from_value_without(pattern, &EVENTBRIDGE_ONLY)
+    }
+
+    /// Return whether `event` matches the pattern.
+    pub fn matches(&self, event: &Value) -> bool {
+        self.root.matches(Some(event))
+    }
+}
+
+/// Routes events to the first of a list of patterns that they match.
+///
+/// # Example
+/// ```
+/// use aws_lambda_events::event_pattern::{EventPattern, PatternRouter};
+/// use serde_json::json;
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Kind {
+///     Upload,
+///     Deletion,
+/// }
+///
+/// let router = PatternRouter::new()
+///     .route(EventPattern::parse(r#"{"detail-type": ["Object Created"]}"#).unwrap(), Kind::Upload)
+///     .route(EventPattern::parse(r#"{"detail-type": ["Object Deleted"]}"#).unwrap(), Kind::Deletion);
+///
+/// assert_eq!(Some(&Kind::Deletion), router.find(&json!({ "detail-type": "Object Deleted" })));
+/// assert_eq!(None, router.find(&json!({ "detail-type": "Object Restore Completed" })));
+/// ```
+#[derive(Clone, Debug)]
+pub struct PatternRouter<T> {
+    routes: Vec<(EventPattern, T)>,
+}
+
+impl<T> PatternRouter<T> {
+    /// Create a router without routes.
+    pub fn new() -> Self {
+        PatternRouter { routes: Vec::new() }
+    }
+
+    /// Route the events that match `pattern` to `target`, unless they match a previous route.
+    pub fn route(mut self, pattern: EventPattern, target: T) -> Self {
+        self.routes.push((pattern, target));
+        self
+    }
+
+    /// Return the target of the first route that `event` matches.
+    pub fn find(&self, event: &Value) -> Option<&T> {
+        self.routes
+            .iter()
+            .find(|(pattern, _)| pattern.matches(event))
+            .map(|(_, target)| target)
+    }
+}
+
+impl<T> Default for PatternRouter<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    /// Conditions on the fields of an object, all of them must match.
+    Fields(Vec<Condition>),
+    /// Matchers for a value, any of them must match.
+    Matchers(Vec<Matcher>),
+}
+
+#[derive(Clone, Debug)]
+enum Condition {
+    Field(String, Node),
+    /// Any of the patterns must match the same object.
+    Or(Vec<Node>),
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Exact(Value),
+    Prefix(String),
+    Suffix(String),
+    EqualsIgnoreCase(String),
+    Wildcard(String),
+    Exists(bool),
+    Numeric(Vec<(Comparison, f64)>),
+    AnythingBut(Vec<Matcher>),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Node {
+    fn parse(fields: &Map<String, Value>, unsupported: &[&str]) -> Result<Self, PatternError> {
+        let conditions = fields
+            .iter()
+            .map(|(name, value)| {
+                if name == "$or" {
+                    check_supported(name, unsupported)?;
+                    let patterns = match value {
+                        Value::Array(patterns) if patterns.len() >= 2 => patterns,
+                        _ => return invalid("`$or` takes an array of at least two patterns"),
+                    };
+                    let patterns = patterns
+                        .iter()
+                        .map(|pattern| match pattern {
+                            Value::Object(fields) => Self::parse(fields, unsupported),
+                            _ => invalid("the patterns of `$or` must be objects"),
+                        })
+                        .collect::<Result<_, _>>()?;
+                    return Ok(Condition::Or(patterns));
+                }
+                let node = match value {
+                    Value::Object(fields) => Self::parse(fields, unsupported)?,
+                    Value::Array(matchers) => Node::Matchers(
+                        matchers
+                            .iter()
+                            .map(|matcher| Matcher::parse(matcher, unsupported))
+                            .collect::<Result<_, _>>()?,
+                    ),
+                    _ => return invalid(format!("`{name}` must be an object or an array of matchers")),
+                };
+                Ok(Condition::Field(name.clone(), node))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Node::Fields(conditions))
+    }
+
+    fn matches(&self, value: Option<&Value>) -> bool {
+        match self {
+            Node::Fields(conditions) => conditions.iter().all(|condition| match condition {
+                Condition::Field(name, node) => node.matches(value.and_then(|value| value.get(name))),
+                Condition::Or(nodes) => nodes.iter().any(|node| node.matches(value)),
+            }),
+            Node::Matchers(matchers) => match value {
+                None => matchers.iter().any(|matcher| matches!(matcher, Matcher::Exists(false))),
+                Some(Value::Array(values)) => matchers
+                    .iter()
+                    .any(|matcher| values.iter().any(|value| matcher.matches(value))),
+                Some(value) => matchers.iter().any(|matcher| matcher.matches(value)),
+            },
+        }
+    }
+}
+
+fn check_supported(operator: &str, unsupported: &[&str]) -> Result<(), PatternError> {
+    match unsupported.contains(&operator) {
+        true => invalid(format!("`{operator}` isn't supported here")),
+        false => Ok(()),
+    }
+}
+
+impl Matcher {
+    fn parse(matcher: &Value, unsupported: &[&str]) -> Result<Self, PatternError> {
+        let operators = match matcher {
+            Value::Object(operators) => operators,
+            Value::Array(_) => return invalid("matchers can't be arrays"),
+            value => return Ok(Matcher::Exact(value.clone())),
+        };
+        let (operator, operand) = match operators.iter().next() {
+            Some(operator) if operators.len() == 1 => operator,
+            _ => return invalid("matcher objects must have exactly one operator"),
+        };
+        check_supported(operator, unsupported)?;
+        match (operator.as_str(), operand) {
+            ("prefix", Value::String(prefix)) => Ok(Matcher::Prefix(prefix.clone())),
+            ("suffix", Value::String(suffix)) => Ok(Matcher::Suffix(suffix.clone())),
+            ("equals-ignore-case", Value::String(value)) => Ok(Matcher::EqualsIgnoreCase(value.clone())),
+            ("wildcard", Value::String(wildcard)) => Ok(Matcher::Wildcard(wildcard.clone())),
+            ("exists", Value::Bool(exists)) => Ok(Matcher::Exists(*exists)),
+            ("numeric", Value::Array(comparisons)) => parse_numeric(comparisons),
+            ("anything-but", operand) => parse_anything_but(operand, unsupported),
+            (operator, operand) => invalid(format!("unsupported operator `{operator}` with operand {operand}")),
+        }
+    }
+
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            Matcher::Exact(expected) => equals(expected, value),
+            Matcher::Prefix(prefix) => value.as_str().map_or(false, |value| value.starts_with(prefix)),
+            Matcher::Suffix(suffix) => value.as_str().map_or(false, |value| value.ends_with(suffix)),
+            Matcher::EqualsIgnoreCase(expected) => value
+                .as_str()
+                .map_or(false, |value| value.to_lowercase() == expected.to_lowercase()),
+            Matcher::Wildcard(wildcard) => value.as_str().map_or(false, |value| wildcard_matches(wildcard, value)),
+            Matcher::Exists(exists) => *exists,
+            Matcher::Numeric(comparisons) => value.as_f64().map_or(false, |value| {
+                comparisons.iter().all(|(comparison, operand)| match comparison {
+                    Comparison::Eq => value == *operand,
+                    Comparison::Lt => value < *operand,
+                    Comparison::Le => value <= *operand,
+                    Comparison::Gt => value > *operand,
+                    Comparison::Ge => value >= *operand,
+                })
+            }),
+            Matcher::AnythingBut(excluded) => !excluded.iter().any(|excluded| excluded.matches(value)),
+        }
+    }
+}
+
+fn equals(expected: &Value, value: &Value) -> bool {
+    match (expected.as_f64(), value.as_f64()) {
+        (Some(expected), Some(value)) => expected == value,
+        _ => expected == value,
+    }
+}
+
+fn parse_anything_but(operand: &Value, unsupported: &[&str]) -> Result<Matcher, PatternError> {
+    let excluded = match operand {
+        Value::Array(values) if values.iter().all(|value| value.is_string() || value.is_number()) => {
+            values.iter().cloned().map(Matcher::Exact).collect()
+        }
+        Value::String(_) | Value::Number(_) => vec![Matcher::Exact(operand.clone())],
+        Value::Object(operators) if operators.len() == 1 => match Matcher::parse(operand, unsupported)? {
+            matcher @ (Matcher::Prefix(_) | Matcher::Suffix(_) | Matcher::Wildcard(_)) => vec![matcher],
+            Matcher::EqualsIgnoreCase(value) => vec![Matcher::EqualsIgnoreCase(value)],
+            _ => {
+                return invalid("`anything-but` only supports `prefix`, `suffix`, `equals-ignore-case`, and `wildcard`")
+            }
+        },
+        _ => return invalid(format!("unsupported `anything-but` operand {operand}")),
+    };
+    Ok(Matcher::AnythingBut(excluded))
+}
+
+fn parse_numeric(comparisons: &[Value]) -> Result<Matcher, PatternError> {
+    if comparisons.is_empty() || comparisons.len() > 4 || comparisons.len() % 2 != 0 {
+        return invalid("`numeric` takes one or two comparisons");
+    }
+    let comparisons = comparisons
+        .chunks(2)
+        .map(|comparison| {
+            let operator = match comparison[0].as_str() {
+                Some("=") => Comparison::Eq,
+                Some("<") => Comparison::Lt,
+                Some("<=") => Comparison::Le,
+                Some(">") => Comparison::Gt,
+                Some(">=") => Comparison::Ge,
+                _ => return invalid(format!("unsupported numeric operator {}", comparison[0])),
+            };
+            match comparison[1].as_f64() {
+                Some(operand) => Ok((operator, operand)),
+                None => invalid(format!("numeric operands must be numbers, got {}", comparison[1])),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Matcher::Numeric(comparisons))
+}
+
+/// Match `value` against a wildcard, where `*` matches any sequence of characters and `\*` a literal `*`.
+fn wildcard_matches(wildcard: &str, value: &str) -> bool {
+    let mut tokens = Vec::new();
+    let mut chars = wildcard.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => tokens.push(Some(chars.next().unwrap_or('\\'))),
+            '*' => tokens.push(None),
+            c => tokens.push(Some(c)),
+        }
+    }
+    let value: Vec<char> = value.chars().collect();
+
+    // Greedy matching with backtracking to the last `*`.
+    let (mut t, mut v) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while v < value.len() {
+        match tokens.get(t) {
+            Some(Some(c)) if *c == value[v] => {
+                t += 1;
+                v += 1;
+            }
+            Some(None) => {
+                star = Some((t, v));
+                t += 1;
+            }
+            _ => match star {
+                Some((star_t, star_v)) => {
+                    t = star_t + 1;
+                    v = star_v + 1;
+                    star = Some((star_t, star_v + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[t..].iter().all(Option::is_none)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn matches(pattern: Value, event: Value) -> bool {
+        EventPattern::from_value(&pattern).unwrap().matches(&event)
+    }
+
+    #[test]
+    fn exact_values() {
+        let pattern = json!({ "source": ["aws.ec2"], "detail": { "state": ["running", "stopped"] } });
+        assert!(matches(
+            pattern.clone(),
+            json!({ "source": "aws.ec2", "detail": { "state": "running" } })
+        ));
+        assert!(!matches(
+            pattern.clone(),
+            json!({ "source": "aws.ec2", "detail": { "state": "pending" } })
+        ));
+        assert!(!matches(pattern, json!({ "source": "aws.ec2" })));
+    }
+
+    #[test]
+    fn array_values_match_any_element() {
+        let pattern = json!({ "resources": ["arn:aws:ec2:us-east-1:123456789012:instance/i-1"] });
+        let event = json!({ "resources": ["arn:aws:ec2:us-east-1:123456789012:instance/i-0", "arn:aws:ec2:us-east-1:123456789012:instance/i-1"] });
+        assert!(matches(pattern, event));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_matches("uploads/*.png", "uploads/cats/tom.png"));
+        assert!(wildcard_matches("*", ""));
+        assert!(wildcard_matches("a*b*c", "aXXbYYbc"));
+        assert!(!wildcard_matches("a*b*c", "aXXbYY"));
+        assert!(wildcard_matches(r"price\*", "price*"));
+        assert!(!wildcard_matches(r"price\*", "prices"));
+        assert!(!wildcard_matches("uploads/*.png", "uploads/tom.jpg"));
+    }
+
+    #[test]
+    fn anything_but() {
+        let event = json!({ "detail": { "state": "running", "type": "t3.micro" } });
+        assert!(matches(
+            json!({ "detail": { "state": [{ "anything-but": "stopped" }] } }),
+            event.clone()
+        ));
+        assert!(!matches(
+            json!({ "detail": { "state": [{ "anything-but": ["running", "pending"] }] } }),
+            event.clone()
+        ));
+        assert!(!matches(
+            json!({ "detail": { "type": [{ "anything-but": { "prefix": "t3." } }] } }),
+            event.clone()
+        ));
+        assert!(matches(
+            json!({ "detail": { "type": [{ "anything-but": { "suffix": ".large" } }] } }),
+            event.clone()
+        ));
+        assert!(!matches(
+            json!({ "detail": { "type": [{ "anything-but": { "wildcard": "t*.micro" } }] } }),
+            event
+        ));
+    }
+
+    #[test]
+    fn numeric_and_exists() {
+        let event = json!({ "detail": { "price": 12.5, "currency": "EUR" } });
+        assert!(matches(
+            json!({ "detail": { "price": [{ "numeric": [">=", 10, "<", 20] }] } }),
+            event.clone()
+        ));
+        assert!(!matches(
+            json!({ "detail": { "price": [{ "numeric": ["<", 10] }] } }),
+            event.clone()
+        ));
+        assert!(matches(
+            json!({ "detail": { "discount": [{ "exists": false }] } }),
+            event.clone()
+        ));
+        assert!(!matches(
+            json!({ "detail": { "currency": [{ "exists": false }] } }),
+            event
+        ));
+    }
+
+    #[test]
+    fn or_conditions() {
+        let pattern = json!({
+            "source": ["aws.s3"],
+            "$or": [
+                { "detail": { "size": [{ "numeric": [">", 1000] }] } },
+                { "detail": { "key": [{ "suffix": ".zip" }] } },
+            ],
+        });
+        assert!(matches(
+            pattern.clone(),
+            json!({ "source": "aws.s3", "detail": { "size": 5000, "key": "a.txt" } })
+        ));
+        assert!(matches(
+            pattern.clone(),
+            json!({ "source": "aws.s3", "detail": { "size": 10, "key": "a.zip" } })
+        ));
+        assert!(!matches(
+            pattern,
+            json!({ "source": "aws.s3", "detail": { "size": 10, "key": "a.txt" } })
+        ));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        for pattern in [
+            json!(["source"]),
+            json!({ "source": "aws.s3" }),
+            json!({ "source": [{ "prefix": 1 }] }),
+            json!({ "source": [{ "prefix": "a", "suffix": "b" }] }),
+            json!({ "source": [{ "anything-but": { "exists": true } }] }),
+            json!({ "$or": [{ "source": ["aws.s3"] }] }),
+        ] {
+            assert!(EventPattern::from_value(&pattern).is_err(), "{pattern}");
+        }
+    }
+}
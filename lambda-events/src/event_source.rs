@@ -0,0 +1,882 @@
+//! A common interface for the services that invoke functions with batches of records.
+//!
+//! An [`EventSource`] knows how to split an event into the records to process,
+//! how to decode the payload of every record, and how to build the response
+//! that reports the records that failed, so the service only retries those.
+//! [`process`] runs a handler over every record of any event source, and
+//! third-party sources can implement the trait to get the same behavior.
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+};
+
+/// A service that invokes functions with a batch of records.
+pub trait EventSource: DeserializeOwned {
+    /// A unit of work in the event.
+    type Record: Clone + Serialize;
+    /// The response that reports the failed records to the service.
+    type Response: Serialize;
+
+    /// Whether the service requires records to be processed in order. Ordered
+    /// sources stop processing the batch at the first failure, and the
+    /// service retries from that record.
+    const ORDERED: bool = false;
+
+    /// Split the event into its records.
+    fn into_records(self) -> Vec<Self::Record>;
+
+    /// The key of the records that must be processed in order, one at a time.
+    fn partition_key(_record: &Self::Record) -> Option<String> {
+        None
+    }
+
+    /// The identifier that reports the record as failed, or `None` when the
+    /// service can't retry individual records.
+    fn item_identifier(record: &Self::Record) -> Option<String>;
+
+    /// The raw payload of the record, if it has one.
+    fn body(record: &Self::Record) -> Option<&[u8]>;
+
+    /// Decode the JSON payload of the record.
+    fn decode<T: DeserializeOwned>(record: &Self::Record) -> Result<T, serde_json::Error> {
+        match Self::body(record) {
+            Some(body) => serde_json::from_slice(body),
+            None => Err(serde::de::Error::custom("the record doesn't have a payload")),
+        }
+    }
+
+    /// Build the response that reports the identifiers of the failed records.
+    fn response(failed: Vec<String>) -> Self::Response;
+}
+
+/// Error returned when a batch has failures that can't be reported to the
+/// service individually, so the whole batch must be retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchFailed {
+    failures: usize,
+}
+
+impl BatchFailed {
+    /// The number of records that failed.
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+}
+
+impl fmt::Display for BatchFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} records of the batch failed", self.failures)
+    }
+}
+
+impl Error for BatchFailed {}
+
+/// Build the response for a batch, from the identifiers of the failed records.
+///
+/// Returns an error if any failed record doesn't have an identifier, because
+/// the service must then retry the whole batch.
+pub fn response<E: EventSource>(failed: impl IntoIterator<Item = Option<String>>) -> Result<E::Response, BatchFailed> {
+    let failed = failed.into_iter().collect::<Vec<_>>();
+    if failed.iter().any(Option::is_none) {
+        return Err(BatchFailed { failures: failed.len() });
+    }
+    Ok(E::response(failed.into_iter().flatten().collect()))
+}
+
+/// Call `handler` with every record of the event, one at a time, and build the
+/// response that reports the records that failed.
+///
+/// For [ordered](EventSource::ORDERED) sources, the records after the first
+/// failure are not processed. Use a [`BatchProcessor`] to process records
+/// concurrently.
+///
+/// # Example
+/// ```
+/// use aws_lambda_events::{
+///     event::sqs::{SqsBatchResponse, SqsEvent, SqsMessage},
+///     event_source::{self, EventSource},
+/// };
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Order {
+///     id: String,
+/// }
+///
+/// async fn handle(event: SqsEvent) -> Result<SqsBatchResponse, event_source::BatchFailed> {
+///     event_source::process(event, |message: SqsMessage| async move {
+///         let order: Order = SqsEvent::decode(&message)?;
+///         println!("processing order {}", order.id);
+///         Ok::<_, serde_json::Error>(())
+///     })
+///     .await
+/// }
+/// ```
+pub async fn process<E, F, Fut, Err>(event: E, handler: F) -> Result<E::Response, BatchFailed>
+where
+    E: EventSource,
+    F: FnMut(E::Record) -> Fut,
+    Fut: Future<Output = Result<(), Err>>,
+    Err: fmt::Display,
+{
+    BatchProcessor::new().process(event, handler).await
+}
+
+/// Processes the records of a batch with bounded concurrency.
+///
+/// Records with the same [partition key](EventSource::partition_key) are
+/// processed one at a time, in the order of the batch. When a record fails,
+/// the records after it in the same partition are not processed, and are
+/// reported as failed too. For [ordered](EventSource::ORDERED) sources, no
+/// new records start after a failure, and the response reports the first
+/// record of the batch that failed or wasn't processed, so the service
+/// retries the batch from there.
+///
+/// Failed records can be [retried](Self::retries) in the same invocation, and
+/// sent to a [dead-letter sink](Self::dead_letters) when they keep failing.
+///
+/// # Example
+/// ```
+/// use aws_lambda_events::{
+///     event::kinesis::KinesisEvent,
+///     event::streams::KinesisEventResponse,
+///     event_source::{BatchFailed, BatchProcessor, EventSource},
+/// };
+///
+/// async fn handle(event: KinesisEvent) -> Result<KinesisEventResponse, BatchFailed> {
+///     BatchProcessor::new()
+///         .concurrency(10)
+///         .process(event, |record| async move {
+///             let value: serde_json::Value = KinesisEvent::decode(&record)?;
+///             println!("{value}");
+///             Ok::<_, serde_json::Error>(())
+///         })
+///         .await
+/// }
+/// ```
+#[derive(Clone)]
+pub struct BatchProcessor {
+    concurrency: usize,
+    retries: usize,
+    dead_letters: Option<Arc<dyn DeadLetterSink>>,
+}
+
+impl fmt::Debug for BatchProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchProcessor")
+            .field("concurrency", &self.concurrency)
+            .field("retries", &self.retries)
+            .field("dead_letters", &self.dead_letters.is_some())
+            .finish()
+    }
+}
+
+impl BatchProcessor {
+    /// Create a processor that handles one record at a time.
+    pub fn new() -> Self {
+        BatchProcessor {
+            concurrency: 1,
+            retries: 0,
+            dead_letters: None,
+        }
+    }
+
+    /// Set the maximum number of records processed at the same time.
+    pub fn concurrency(self, concurrency: usize) -> Self {
+        BatchProcessor {
+            concurrency: concurrency.max(1),
+            ..self
+        }
+    }
+
+    /// Call the handler again, up to `retries` times, when a record fails.
+    pub fn retries(self, retries: usize) -> Self {
+        BatchProcessor { retries, ..self }
+    }
+
+    /// Send the records that still fail after all the retries to `sink`, and
+    /// treat them as processed, so a poison message doesn't block the queue
+    /// or the stream. Records that can't be sent to the sink are reported as
+    /// failed.
+    pub fn dead_letters(self, sink: impl DeadLetterSink + 'static) -> Self {
+        BatchProcessor {
+            dead_letters: Some(Arc::new(sink)),
+            ..self
+        }
+    }
+
+    /// Call `handler` with every record of the event, and build the response
+    /// that reports the records that failed.
+    pub async fn process<E, F, Fut, Err>(&self, event: E, mut handler: F) -> Result<E::Response, BatchFailed>
+    where
+        E: EventSource,
+        F: FnMut(E::Record) -> Fut,
+        Fut: Future<Output = Result<(), Err>>,
+        Err: fmt::Display,
+    {
+        let records = event.into_records();
+        let identifiers = records.iter().map(E::item_identifier).collect::<Vec<_>>();
+        let mut done = vec![false; records.len()];
+        let mut copies = vec![None; records.len()];
+        let mut payloads = vec![serde_json::Value::Null; records.len()];
+
+        // Records in the same lane run one at a time. Records of ordered
+        // sources without a partition key all share the same lane.
+        let mut lanes: Vec<VecDeque<(usize, E::Record)>> = Vec::new();
+        let mut partitions = HashMap::new();
+        for (index, record) in records.into_iter().enumerate() {
+            let key = E::partition_key(&record).or_else(|| E::ORDERED.then(String::new));
+            let lane = match key {
+                Some(key) => *partitions.entry(key).or_insert_with(|| {
+                    lanes.push(VecDeque::new());
+                    lanes.len() - 1
+                }),
+                None => {
+                    lanes.push(VecDeque::new());
+                    lanes.len() - 1
+                }
+            };
+            lanes[lane].push_back((index, record));
+        }
+
+        let mut busy = vec![false; lanes.len()];
+        let mut stopped = false;
+        let mut running = FuturesUnordered::new();
+        loop {
+            while !stopped && running.len() < self.concurrency {
+                // Start the waiting record that comes first in the batch.
+                let next = lanes
+                    .iter()
+                    .enumerate()
+                    .filter(|(lane, _)| !busy[*lane])
+                    .filter_map(|(lane, records)| records.front().map(|(index, _)| (*index, lane)))
+                    .min();
+                let lane = match next {
+                    Some((_, lane)) => lane,
+                    None => break,
+                };
+                if let Some((index, record)) = lanes[lane].pop_front() {
+                    busy[lane] = true;
+                    if self.retries > 0 {
+                        copies[index] = Some(record.clone());
+                    }
+                    if self.dead_letters.is_some() {
+                        payloads[index] = serde_json::to_value(&record).unwrap_or_default();
+                    }
+                    running.push(attempt(&mut handler, lane, index, 1, record));
+                }
+            }
+
+            let (lane, index, outcome) = match running.next().await {
+                Some(step) => step,
+                None => break,
+            };
+            let failed = match outcome {
+                Outcome::Handled(Ok(())) | Outcome::DeadLettered(true) => false,
+                Outcome::DeadLettered(false) => true,
+                Outcome::Handled(Err((attempts, error))) => {
+                    if attempts <= self.retries {
+                        if let Some(record) = copies[index].take() {
+                            if attempts < self.retries {
+                                copies[index] = Some(record.clone());
+                            }
+                            running.push(attempt(&mut handler, lane, index, attempts + 1, record));
+                            continue;
+                        }
+                    }
+                    match &self.dead_letters {
+                        Some(sink) => {
+                            let letter = DeadLetter {
+                                item_identifier: identifiers[index].clone(),
+                                record: std::mem::take(&mut payloads[index]),
+                                error,
+                                attempts,
+                            };
+                            running.push(Box::pin(async move {
+                                let sent = sink.send(letter).await.is_ok();
+                                (lane, index, Outcome::DeadLettered(sent))
+                            }));
+                            continue;
+                        }
+                        None => true,
+                    }
+                }
+            };
+
+            busy[lane] = false;
+            if failed {
+                lanes[lane].clear();
+                stopped = E::ORDERED;
+            } else {
+                done[index] = true;
+            }
+        }
+
+        let failed = identifiers
+            .into_iter()
+            .zip(done)
+            .filter(|(_, done)| !done)
+            .map(|(identifier, _)| identifier);
+        if E::ORDERED {
+            response::<E>(failed.take(1))
+        } else {
+            response::<E>(failed)
+        }
+    }
+}
+
+impl Default for BatchProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Outcome {
+    /// The result of the handler, with the number of attempts.
+    Handled(Result<(), (usize, String)>),
+    /// Whether the record was sent to the dead letter sink.
+    DeadLettered(bool),
+}
+
+type Step<'a> = Pin<Box<dyn Future<Output = (usize, usize, Outcome)> + 'a>>;
+
+fn attempt<'a, R, F, Fut, Err>(handler: &mut F, lane: usize, index: usize, attempts: usize, record: R) -> Step<'a>
+where
+    F: FnMut(R) -> Fut,
+    Fut: Future<Output = Result<(), Err>> + 'a,
+    Err: fmt::Display,
+{
+    let future = handler(record);
+    Box::pin(async move {
+        let result = future.await.map_err(|e| (attempts, e.to_string()));
+        (lane, index, Outcome::Handled(result))
+    })
+}
+
+/// Error returned by a [`DeadLetterSink`].
+pub type DeadLetterError = Box<dyn Error + Send + Sync>;
+
+/// A record that failed all its attempts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetter {
+    /// The identifier of the record in the batch, if it has one.
+    pub item_identifier: Option<String>,
+    /// The record, as received from the service.
+    pub record: serde_json::Value,
+    /// The error of the last attempt.
+    pub error: String,
+    /// The number of times that the handler was called with the record.
+    pub attempts: usize,
+}
+
+/// Destination of the records that a [`BatchProcessor`] gives up on, like an
+/// SQS dead-letter queue, an S3 bucket, or an EventBridge bus.
+pub trait DeadLetterSink: Send + Sync {
+    /// Send the record to the destination.
+    fn send(&self, letter: DeadLetter) -> Pin<Box<dyn Future<Output = Result<(), DeadLetterError>> + Send + '_>>;
+}
+
+/// An event sent by a direct invocation, processed as a batch of one record.
+///
+/// Failures can't be reported individually, so the whole invocation fails.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Direct<T = serde_json::Value>(pub T);
+
+impl<T: DeserializeOwned + Serialize + Clone> EventSource for Direct<T> {
+    type Record = T;
+    type Response = ();
+
+    fn into_records(self) -> Vec<T> {
+        vec![self.0]
+    }
+
+    fn item_identifier(_record: &T) -> Option<String> {
+        None
+    }
+
+    fn body(_record: &T) -> Option<&[u8]> {
+        None
+    }
+
+    fn decode<U: DeserializeOwned>(record: &T) -> Result<U, serde_json::Error> {
+        serde_json::to_value(record).and_then(serde_json::from_value)
+    }
+
+    fn response(_failed: Vec<String>) {}
+}
+
+#[cfg(feature = "sqs")]
+impl EventSource for crate::event::sqs::SqsEvent {
+    type Record = crate::event::sqs::SqsMessage;
+    type Response = crate::event::sqs::SqsBatchResponse;
+
+    fn into_records(self) -> Vec<Self::Record> {
+        self.records
+    }
+
+    fn item_identifier(record: &Self::Record) -> Option<String> {
+        record.message_id.clone()
+    }
+
+    /// The message group of FIFO queues. Groups are processed concurrently,
+    /// and the messages of a group in order, one at a time. When a message
+    /// fails, the rest of its group is reported as failed without being
+    /// processed, so SQS delivers the group again in the same order.
+    fn partition_key(record: &Self::Record) -> Option<String> {
+        record.message_group_id().map(String::from)
+    }
+
+    fn body(record: &Self::Record) -> Option<&[u8]> {
+        record.body.as_deref().map(str::as_bytes)
+    }
+
+    fn response(failed: Vec<String>) -> Self::Response {
+        crate::event::sqs::SqsBatchResponse {
+            batch_item_failures: failed
+                .into_iter()
+                .map(|item_identifier| crate::event::sqs::BatchItemFailure { item_identifier })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(all(feature = "kinesis", feature = "streams"))]
+impl EventSource for crate::event::kinesis::KinesisEvent {
+    type Record = crate::event::kinesis::KinesisEventRecord;
+    type Response = crate::event::streams::KinesisEventResponse;
+
+    const ORDERED: bool = true;
+
+    fn into_records(self) -> Vec<Self::Record> {
+        self.records
+    }
+
+    fn item_identifier(record: &Self::Record) -> Option<String> {
+        record.kinesis.sequence_number.clone()
+    }
+
+    fn partition_key(record: &Self::Record) -> Option<String> {
+        record.kinesis.partition_key.clone()
+    }
+
+    fn body(record: &Self::Record) -> Option<&[u8]> {
+        Some(&record.kinesis.data.0)
+    }
+
+    fn response(failed: Vec<String>) -> Self::Response {
+        crate::event::streams::KinesisEventResponse {
+            batch_item_failures: failed
+                .into_iter()
+                .map(|id| crate::event::streams::KinesisBatchItemFailure {
+                    item_identifier: Some(id),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "dynamodb")]
+impl EventSource for crate::event::dynamodb::Event {
+    type Record = crate::event::dynamodb::EventRecord;
+    type Response = crate::event::streams::DynamoDbEventResponse;
+
+    const ORDERED: bool = true;
+
+    fn into_records(self) -> Vec<Self::Record> {
+        self.records
+    }
+
+    fn item_identifier(record: &Self::Record) -> Option<String> {
+        record.change.sequence_number.clone()
+    }
+
+    /// The primary key of the item that changed.
+    fn partition_key(record: &Self::Record) -> Option<String> {
+        let keys = record.change.keys.iter().collect::<std::collections::BTreeMap<_, _>>();
+        Some(format!("{keys:?}"))
+    }
+
+    fn body(_record: &Self::Record) -> Option<&[u8]> {
+        None
+    }
+
+    /// Decode the new image of the record.
+    fn decode<T: DeserializeOwned>(record: &Self::Record) -> Result<T, serde_json::Error> {
+        serde_dynamo::from_item(record.change.new_image.clone()).map_err(serde::de::Error::custom)
+    }
+
+    fn response(failed: Vec<String>) -> Self::Response {
+        crate::event::streams::DynamoDbEventResponse {
+            batch_item_failures: failed
+                .into_iter()
+                .map(|id| crate::event::streams::DynamoDbBatchItemFailure {
+                    item_identifier: Some(id),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "sns")]
+impl EventSource for crate::event::sns::SnsEvent {
+    type Record = crate::event::sns::SnsRecord;
+    type Response = ();
+
+    fn into_records(self) -> Vec<Self::Record> {
+        self.records
+    }
+
+    fn item_identifier(_record: &Self::Record) -> Option<String> {
+        None
+    }
+
+    fn body(record: &Self::Record) -> Option<&[u8]> {
+        Some(record.sns.message.as_bytes())
+    }
+
+    fn response(_failed: Vec<String>) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[tokio::test]
+    #[cfg(feature = "sqs")]
+    async fn sqs_reports_failed_messages() {
+        use crate::event::sqs::SqsEvent;
+
+        let event: SqsEvent = serde_json::from_value(serde_json::json!({
+            "Records": [
+                { "messageId": "1", "body": "{\"ok\":true}", "attributes": {}, "messageAttributes": {} },
+                { "messageId": "2", "body": "{\"ok\":false}", "attributes": {}, "messageAttributes": {} },
+                { "messageId": "3", "body": "not json", "attributes": {}, "messageAttributes": {} },
+            ]
+        }))
+        .unwrap();
+
+        #[derive(Deserialize)]
+        struct Body {
+            ok: bool,
+        }
+
+        let response = process(event, |message| async move {
+            let body: Body = SqsEvent::decode(&message).map_err(|e| e.to_string())?;
+            if body.ok {
+                Ok(())
+            } else {
+                Err("not ok".to_string())
+            }
+        })
+        .await
+        .unwrap();
+        let failed = response
+            .batch_item_failures
+            .into_iter()
+            .map(|f| f.item_identifier)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["2", "3"], failed);
+    }
+
+    #[tokio::test]
+    #[cfg(all(feature = "kinesis", feature = "streams"))]
+    async fn ordered_sources_stop_at_the_first_failure() {
+        use crate::event::kinesis::KinesisEvent;
+
+        let data = include_bytes!("fixtures/example-kinesis-event.json");
+        let mut event: KinesisEvent = serde_json::from_slice(data).unwrap();
+        let record = event.records[0].clone();
+        event.records = vec![record.clone(), record.clone(), record];
+        for (i, record) in event.records.iter_mut().enumerate() {
+            record.kinesis.sequence_number = Some(i.to_string());
+        }
+
+        let mut calls = 0;
+        let response = process(event, |record| {
+            calls += 1;
+            let failed = record.kinesis.sequence_number.as_deref() == Some("1");
+            async move {
+                if failed {
+                    Err("failed")
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(2, calls);
+        assert_eq!(1, response.batch_item_failures.len());
+        assert_eq!(Some("1".into()), response.batch_item_failures[0].item_identifier);
+    }
+
+    #[tokio::test]
+    async fn direct_invocations_fail_as_a_whole() {
+        let event: Direct = serde_json::from_str(r#"{"id":"1"}"#).unwrap();
+        let err = process(event, |record| async move {
+            let id: String = Direct::decode::<serde_json::Value>(&record).unwrap()["id"]
+                .as_str()
+                .unwrap()
+                .to_string();
+            Err(id)
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(1, err.failures());
+
+        let event: Direct = serde_json::from_str(r#"{"id":"1"}"#).unwrap();
+        assert!(process(event, |_| async { Ok::<_, String>(()) }).await.is_ok());
+    }
+
+    #[cfg(feature = "sqs")]
+    fn sqs_event(messages: &[(&str, Option<&str>)]) -> crate::event::sqs::SqsEvent {
+        let records = messages
+            .iter()
+            .map(|(id, group)| {
+                let mut attributes = serde_json::Map::new();
+                if let Some(group) = group {
+                    attributes.insert("MessageGroupId".into(), (*group).into());
+                }
+                serde_json::json!({
+                    "messageId": id,
+                    "body": "{}",
+                    "attributes": attributes,
+                    "messageAttributes": {},
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::from_value(serde_json::json!({ "Records": records })).unwrap()
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqs")]
+    async fn records_are_processed_concurrently() {
+        use std::cell::Cell;
+
+        let event = sqs_event(&[("1", None), ("2", None), ("3", None), ("4", None), ("5", None)]);
+        let running = Cell::new(0);
+        let peak = Cell::new(0);
+        let response = BatchProcessor::new()
+            .concurrency(3)
+            .process(event, |message| {
+                let (running, peak) = (&running, &peak);
+                async move {
+                    running.set(running.get() + 1);
+                    peak.set(peak.get().max(running.get()));
+                    tokio::task::yield_now().await;
+                    running.set(running.get() - 1);
+                    if message.message_id.as_deref() == Some("4") {
+                        Err("failed")
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(3, peak.get());
+        assert_eq!(1, response.batch_item_failures.len());
+        assert_eq!("4", response.batch_item_failures[0].item_identifier);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqs")]
+    async fn fifo_groups_skip_the_messages_after_a_failure() {
+        let event = sqs_event(&[("1", Some("a")), ("2", Some("b")), ("3", Some("a")), ("4", Some("b"))]);
+        let mut processed = Vec::new();
+        let response = BatchProcessor::new()
+            .concurrency(4)
+            .process(event, |message| {
+                let id = message.message_id.unwrap();
+                processed.push(id.clone());
+                async move {
+                    if id == "1" {
+                        Err("failed")
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        let failed = response
+            .batch_item_failures
+            .into_iter()
+            .map(|f| f.item_identifier)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["1", "3"], failed);
+        assert!(!processed.contains(&"3".to_string()));
+        assert!(processed.contains(&"4".to_string()));
+    }
+
+    #[tokio::test]
+    #[cfg(all(feature = "kinesis", feature = "streams"))]
+    async fn ordered_sources_report_the_first_unprocessed_record() {
+        use crate::event::kinesis::KinesisEvent;
+
+        let data = include_bytes!("fixtures/example-kinesis-event.json");
+        let mut event: KinesisEvent = serde_json::from_slice(data).unwrap();
+        let record = event.records[0].clone();
+        event.records = vec![record; 4];
+        for (i, record) in event.records.iter_mut().enumerate() {
+            record.kinesis.sequence_number = Some(i.to_string());
+            record.kinesis.partition_key = Some(format!("p{}", i % 2));
+        }
+
+        let mut processed = Vec::new();
+        let response = BatchProcessor::new()
+            .concurrency(2)
+            .process(event, |record| {
+                let sequence_number = record.kinesis.sequence_number.unwrap();
+                processed.push(sequence_number.clone());
+                async move {
+                    if sequence_number == "1" {
+                        Err("failed")
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        assert!(!processed.contains(&"3".to_string()));
+        assert_eq!(1, response.batch_item_failures.len());
+        assert_eq!(Some("1".into()), response.batch_item_failures[0].item_identifier);
+    }
+
+    #[derive(Clone, Default)]
+    struct MemorySink {
+        letters: Arc<std::sync::Mutex<Vec<DeadLetter>>>,
+        unavailable: bool,
+    }
+
+    impl DeadLetterSink for MemorySink {
+        fn send(&self, letter: DeadLetter) -> Pin<Box<dyn Future<Output = Result<(), DeadLetterError>> + Send + '_>> {
+            Box::pin(async move {
+                if self.unavailable {
+                    return Err("sink unavailable".into());
+                }
+                self.letters.lock().unwrap().push(letter);
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqs")]
+    async fn failed_records_are_retried() {
+        let event = sqs_event(&[("1", None), ("2", None)]);
+        let mut calls = HashMap::<String, usize>::new();
+        let response = BatchProcessor::new()
+            .retries(2)
+            .process(event, |message| {
+                let calls = calls.entry(message.message_id.unwrap()).or_default();
+                *calls += 1;
+                let attempt = *calls;
+                async move {
+                    if attempt < 3 {
+                        Err("failed")
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        assert!(response.batch_item_failures.is_empty());
+        assert_eq!(Some(&3), calls.get("1"));
+        assert_eq!(Some(&3), calls.get("2"));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqs")]
+    async fn poison_messages_are_sent_to_the_dead_letter_sink() {
+        let sink = MemorySink::default();
+        let event = sqs_event(&[("1", None), ("2", None)]);
+        let response = BatchProcessor::new()
+            .concurrency(2)
+            .retries(1)
+            .dead_letters(sink.clone())
+            .process(event, |message| async move {
+                if message.message_id.as_deref() == Some("2") {
+                    Err("poison")
+                } else {
+                    Ok(())
+                }
+            })
+            .await
+            .unwrap();
+        assert!(response.batch_item_failures.is_empty());
+
+        let letters = sink.letters.lock().unwrap();
+        assert_eq!(1, letters.len());
+        assert_eq!(Some("2".into()), letters[0].item_identifier);
+        assert_eq!("poison", letters[0].error);
+        assert_eq!(2, letters[0].attempts);
+        assert_eq!("2", letters[0].record["messageId"]);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqs")]
+    async fn records_are_reported_when_the_sink_fails() {
+        let sink = MemorySink {
+            unavailable: true,
+            ..Default::default()
+        };
+        let event = sqs_event(&[("1", None)]);
+        let response = BatchProcessor::new()
+            .dead_letters(sink)
+            .process(event, |_| async { Err("poison") })
+            .await
+            .unwrap();
+        assert_eq!("1", response.batch_item_failures[0].item_identifier);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqs")]
+    async fn fifo_groups_are_processed_in_order() {
+        use std::cell::RefCell;
+
+        let event = sqs_event(&[
+            ("1", Some("a")),
+            ("2", Some("b")),
+            ("3", Some("a")),
+            ("4", Some("b")),
+            ("5", Some("a")),
+        ]);
+        let in_flight = RefCell::new(HashMap::<String, usize>::new());
+        let started = RefCell::new(Vec::new());
+        let response = BatchProcessor::new()
+            .concurrency(5)
+            .process(event, |message| {
+                let (in_flight, started) = (&in_flight, &started);
+                async move {
+                    let group = message.message_group_id().unwrap().to_string();
+                    started.borrow_mut().push(message.message_id.clone().unwrap());
+                    *in_flight.borrow_mut().entry(group.clone()).or_default() += 1;
+                    assert_eq!(1, in_flight.borrow()[&group], "group {group} ran concurrently");
+                    tokio::task::yield_now().await;
+                    *in_flight.borrow_mut().get_mut(&group).unwrap() -= 1;
+                    Ok::<_, String>(())
+                }
+            })
+            .await
+            .unwrap();
+        assert!(response.batch_item_failures.is_empty());
+
+        let started = started.into_inner();
+        let position = |id: &str| started.iter().position(|s| s == id).unwrap();
+        assert!(position("1") < position("3") && position("3") < position("5"));
+        assert!(position("2") < position("4"));
+        assert_eq!(vec!["1", "2"], started[..2]);
+    }
+}
@@ -0,0 +1,241 @@
+//! Local evaluation of the filter criteria of event source mappings.
+//!
+//! Event source mappings can drop the records that a function isn't interested
+//! in before invoking it, with filter patterns written in a subset of the
+//! [EventBridge event patterns](crate::event_pattern), without `wildcard` and
+//! `$or`. [`FilterCriteria`] evaluates those patterns
+//! against records the same way Lambda does, so filters can be unit tested
+//! next to the handler instead of after a deployment.
+//!
+//! Like Lambda, the `body` of SQS messages and the `data` of Kinesis records are
+//! decoded before matching, when they contain a JSON object, so patterns can
+//! match their fields.
+//!
+//! # Example
+//! ```
+//! use aws_lambda_events::filter_criteria::FilterCriteria;
+//! use serde_json::json;
+//!
+//! let criteria = FilterCriteria::from_patterns([
+//!     r#"{"body": {"temperature": [{"numeric": [">", 30]}], "city": ["Seattle"]}}"#,
+//! ])
+//! .unwrap();
+//!
+//! let hot = json!({ "messageId": "1", "body": r#"{"temperature": 34, "city": "Seattle"}"# });
+//! let cold = json!({ "messageId": "2", "body": r#"{"temperature": 12, "city": "Seattle"}"# });
+//! assert!(criteria.matches(&hot));
+//! assert!(!criteria.matches(&cold));
+//! ```
+use crate::event_pattern::EventPattern;
+pub use crate::event_pattern::PatternError;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The filter criteria of an event source mapping, in the format of the Lambda API.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FilterCriteriaDefinition {
+    #[serde(default)]
+    pub filters: Vec<FilterDefinition>,
+}
+
+/// A filter of an event source mapping, with its pattern as a JSON string.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FilterDefinition {
+    pub pattern: String,
+}
+
+/// Compiled filter criteria. A record matches when it matches any of the patterns.
+#[derive(Clone, Debug)]
+pub struct FilterCriteria {
+    patterns: Vec<EventPattern>,
+}
+
+impl FilterCriteria {
+    /// Compile filter patterns written as JSON strings.
+    pub fn from_patterns<I, S>(patterns: I) -> Result<Self, PatternError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns = patterns
+            .into_iter()
+            .map(|pattern| {
+                let pattern: Value = serde_json::from_str(pattern.as_ref())
+                    .map_err(|err| PatternError::new(format!("the pattern is not JSON: {err}")))?;
+                EventPattern::filter(&pattern)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(FilterCriteria { patterns })
+    }
+
+    /// Compile the filter criteria of an event source mapping, in the format of the Lambda API,
+    /// like `{"Filters": [{"Pattern": "{\"body\": ...}"}]}`.
+    pub fn from_definition(definition: &FilterCriteriaDefinition) -> Result<Self, PatternError> {
+        Self::from_patterns(definition.filters.iter().map(|filter| &filter.pattern))
+    }
+
+    /// Return whether Lambda would invoke the function with `record`.
+    ///
+    /// `record` is a single record of the event, like an SQS message, in the
+    /// format it has in the event sent to the function.
+    pub fn matches(&self, record: &Value) -> bool {
+        let record = decode_record(record);
+        self.patterns.iter().any(|pattern| pattern.matches(&record))
+    }
+
+    /// Return whether Lambda would invoke the function with `message`.
+    #[cfg(feature = "sqs")]
+    pub fn matches_sqs(&self, message: &crate::event::sqs::SqsMessage) -> bool {
+        let record = serde_json::to_value(message).expect("SQS messages are always serializable");
+        self.matches(&record)
+    }
+
+    /// Keep the messages of `event` that Lambda would invoke the function with.
+    #[cfg(feature = "sqs")]
+    pub fn filter_sqs(&self, mut event: crate::event::sqs::SqsEvent) -> crate::event::sqs::SqsEvent {
+        event.records.retain(|message| self.matches_sqs(message));
+        event
+    }
+}
+
+/// Decode the payloads of SQS messages and Kinesis records that contain JSON objects.
+fn decode_record(record: &Value) -> Value {
+    let mut record = record.clone();
+    if let Some(body) = record.get_mut("body") {
+        if let Some(decoded) = body.as_str().and_then(json_object) {
+            *body = decoded;
+        }
+    }
+    if let Some(data) = record.get_mut("kinesis").and_then(|kinesis| kinesis.get_mut("data")) {
+        let decoded = data
+            .as_str()
+            .and_then(|data| base64::engine::general_purpose::STANDARD.decode(data).ok())
+            .and_then(|data| String::from_utf8(data).ok())
+            .and_then(|data| json_object(&data));
+        if let Some(decoded) = decoded {
+            *data = decoded;
+        }
+    }
+    record
+}
+
+fn json_object(s: &str) -> Option<Value> {
+    serde_json::from_str::<Value>(s).ok().filter(Value::is_object)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn matches(pattern: &str, record: Value) -> bool {
+        FilterCriteria::from_patterns([pattern]).unwrap().matches(&record)
+    }
+
+    #[test]
+    fn exact_values_and_nested_fields() {
+        let pattern = r#"{"body": {"city": ["Seattle", "Portland"], "active": [true], "owner": [null]}}"#;
+        let record = |city: &str| json!({ "body": json!({ "city": city, "active": true, "owner": null }).to_string() });
+        assert!(matches(pattern, record("Seattle")));
+        assert!(matches(pattern, record("Portland")));
+        assert!(!matches(pattern, record("Boston")));
+    }
+
+    #[test]
+    fn string_operators() {
+        let record = json!({ "body": r#"{"region": "us-west-2"}"# });
+        assert!(matches(r#"{"body": {"region": [{"prefix": "us-"}]}}"#, record.clone()));
+        assert!(matches(r#"{"body": {"region": [{"suffix": "-2"}]}}"#, record.clone()));
+        assert!(matches(
+            r#"{"body": {"region": [{"equals-ignore-case": "US-WEST-2"}]}}"#,
+            record.clone()
+        ));
+        assert!(!matches(
+            r#"{"body": {"region": [{"anything-but": ["us-west-2"]}]}}"#,
+            record
+        ));
+    }
+
+    #[test]
+    fn numeric_ranges() {
+        let pattern = r#"{"body": {"temperature": [{"numeric": [">", 0, "<=", 30]}]}}"#;
+        let record = |temperature: f64| json!({ "body": json!({ "temperature": temperature }).to_string() });
+        assert!(matches(pattern, record(30.0)));
+        assert!(!matches(pattern, record(0.0)));
+        assert!(!matches(pattern, record(31.5)));
+        assert!(matches(r#"{"body": {"temperature": [30]}}"#, record(30.0)));
+    }
+
+    #[test]
+    fn exists() {
+        let record = json!({ "body": r#"{"order": {"id": 42}}"# });
+        assert!(matches(
+            r#"{"body": {"order": {"id": [{"exists": true}]}}}"#,
+            record.clone()
+        ));
+        assert!(matches(
+            r#"{"body": {"order": {"coupon": [{"exists": false}]}}}"#,
+            record.clone()
+        ));
+        assert!(!matches(
+            r#"{"body": {"order": {"coupon": [{"exists": true}]}}}"#,
+            record
+        ));
+    }
+
+    #[test]
+    fn plain_text_bodies_are_matched_as_strings() {
+        let record = json!({ "body": "ERROR: disk full" });
+        assert!(matches(r#"{"body": [{"prefix": "ERROR"}]}"#, record.clone()));
+        assert!(!matches(r#"{"body": {"level": ["ERROR"]}}"#, record));
+    }
+
+    #[test]
+    fn kinesis_data_is_decoded() {
+        let data = base64::engine::general_purpose::STANDARD.encode(r#"{"type": "order"}"#);
+        let record = json!({ "kinesis": { "partitionKey": "1", "data": data } });
+        assert!(matches(r#"{"kinesis": {"data": {"type": ["order"]}}}"#, record));
+    }
+
+    #[test]
+    fn any_filter_matches() {
+        let definition: FilterCriteriaDefinition = serde_json::from_value(json!({
+            "Filters": [
+                { "Pattern": r#"{"body": {"type": ["order"]}}"# },
+                { "Pattern": r#"{"body": {"type": ["refund"]}}"# },
+            ]
+        }))
+        .unwrap();
+        let criteria = FilterCriteria::from_definition(&definition).unwrap();
+        assert!(criteria.matches(&json!({ "body": r#"{"type": "refund"}"# })));
+        assert!(!criteria.matches(&json!({ "body": r#"{"type": "invoice"}"# })));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        for pattern in [
+            r#"["body"]"#,
+            r#"{"body": "order"}"#,
+            r#"{"body": [{"prefix": 1}]}"#,
+            r#"{"body": [{"numeric": [">"]}]}"#,
+            r#"{"body": [{"wildcard": "*"}]}"#,
+        ] {
+            assert!(FilterCriteria::from_patterns([pattern]).is_err(), "{pattern}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "sqs")]
+    fn filters_sqs_events() {
+        let data = include_bytes!("fixtures/example-sqs-event.json");
+        let event: crate::event::sqs::SqsEvent = serde_json::from_slice(data).unwrap();
+        let criteria = FilterCriteria::from_patterns([r#"{"messageId": [{"prefix": "MessageID_"}]}"#]).unwrap();
+        assert_eq!(event.records.len(), criteria.filter_sqs(event.clone()).records.len());
+
+        let criteria = FilterCriteria::from_patterns([r#"{"eventSource": [{"anything-but": "aws:sqs"}]}"#]).unwrap();
+        assert!(criteria.filter_sqs(event).records.is_empty());
+    }
+}
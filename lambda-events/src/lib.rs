@@ -4,6 +4,7 @@ pub use http;
 #[cfg(feature = "query_map")]
 pub use query_map;
 
+pub mod conventions;
 mod custom_serde;
 /// Encodings used in AWS Lambda json event values.
 pub mod encodings;
@@ -13,6 +14,22 @@ pub mod time_window;
 /// AWS Lambda event definitions.
 pub mod event;
 
+pub mod event_source;
+
+pub mod event_pattern;
+
+pub mod filter_criteria;
+
+/// Derive `Serialize` and `Deserialize` with the conventions of the AWS event payloads.
+#[cfg(feature = "derive")]
+pub use aws_lambda_events_derive::LambdaEventType;
+
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __private {
+    pub use serde;
+}
+
 /// AWS Lambda event definitions for activemq.
 #[cfg(feature = "activemq")]
 pub use event::activemq;
@@ -40,6 +57,10 @@ pub use event::chime_bot;
 #[cfg(feature = "clientvpn")]
 pub use event::clientvpn;
 
+/// CloudWatch alarm state change payloads
+#[cfg(feature = "cloudwatch_alarms")]
+pub use event::cloudwatch_alarms;
+
 /// CloudWatch Events payload
 #[cfg(feature = "cloudwatch_events")]
 pub use event::cloudwatch_events;
@@ -128,6 +149,10 @@ pub use event::kinesis::analytics as kinesis_analytics;
 #[cfg(feature = "lambda_function_urls")]
 pub use event::lambda_function_urls;
 
+/// AWS Lambda event definitions for the records sent to asynchronous invocation destinations.
+#[cfg(feature = "lambda_destinations")]
+pub use event::lambda_destinations;
+
 /// AWS Lambda event definitions for lex.
 #[cfg(feature = "lex")]
 pub use event::lex;
@@ -159,3 +184,7 @@ pub use event::sqs;
 /// AWS Lambda event definitions for streams.
 #[cfg(feature = "streams")]
 pub use event::streams;
+
+/// AWS Lambda event definitions for VPC Lattice.
+#[cfg(feature = "vpc_lattice")]
+pub use event::vpc_lattice;
@@ -0,0 +1,73 @@
+#![cfg(all(feature = "derive", feature = "chrono"))]
+
+use aws_lambda_events::LambdaEventType;
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::json;
+use std::collections::HashMap;
+
+#[derive(Debug, Default, PartialEq, LambdaEventType)]
+struct PartnerEvent {
+    event_id: String,
+    detail_type: Option<String>,
+    payload: Vec<u8>,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+    tags: HashMap<String, String>,
+    #[serde(rename = "X-Source")]
+    source: String,
+}
+
+#[derive(Debug, PartialEq, LambdaEventType)]
+#[serde(rename_all = "PascalCase")]
+struct Envelope<T> {
+    message_id: String,
+    message: T,
+}
+
+#[test]
+fn applies_the_event_conventions() {
+    let event: PartnerEvent = serde_json::from_value(json!({
+        "eventId": "1",
+        "payload": "aGVsbG8=",
+        "createdAt": 1700000000500u64,
+        "tags": null,
+        "X-Source": "partner",
+    }))
+    .unwrap();
+    assert_eq!(
+        PartnerEvent {
+            event_id: "1".to_string(),
+            detail_type: None,
+            payload: b"hello".to_vec(),
+            created_at: Utc.timestamp_millis_opt(1_700_000_000_500).unwrap(),
+            updated_at: None,
+            tags: HashMap::new(),
+            source: "partner".to_string(),
+        },
+        event
+    );
+
+    assert_eq!(
+        json!({
+            "eventId": "1",
+            "detailType": null,
+            "payload": "aGVsbG8=",
+            "createdAt": "1700000000500",
+            "updatedAt": null,
+            "tags": {},
+            "X-Source": "partner",
+        }),
+        serde_json::to_value(&event).unwrap()
+    );
+}
+
+#[test]
+fn keeps_the_container_attributes_and_generics() {
+    let envelope: Envelope<PartnerEvent> = serde_json::from_value(json!({
+        "MessageId": "m-1",
+        "Message": { "eventId": "2" },
+    }))
+    .unwrap();
+    assert_eq!("m-1", envelope.message_id);
+    assert_eq!("2", envelope.message.event_id);
+}
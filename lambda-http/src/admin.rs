@@ -0,0 +1,354 @@
+//! Operational endpoints for debugging functions without redeploying them.
+//!
+//! [`AdminLayer`] answers two endpoints before the handler:
+//! - [`/__lambda/health`](HEALTH_PATH) returns `200 OK` while the execution
+//!   environment can serve requests.
+//! - [`/__lambda/info`](INFO_PATH) returns a JSON document with the version of
+//!   this crate and its enabled features, the configuration of the function,
+//!   the cold start and the number of invocations of the execution environment,
+//!   and the [values](AdminLayer::info) set by the function.
+//!
+//! Nothing is exposed unless the layer is added to the handler, and every
+//! request to the endpoints must send the token of the layer in an
+//! `Authorization: Bearer` header. Requests without the token get a
+//! `404 Not Found` response, as if the endpoints didn't exist. The responses to
+//! authorized requests are not logged by the
+//! [`RequestLogLayer`](crate::RequestLogLayer), so polling them doesn't flood
+//! the logs.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{admin::AdminLayer, service_fn, tower::Layer, Error, Request};
+//!
+//! async fn hello(_req: Request) -> Result<&'static str, Error> {
+//!     Ok("hello")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let admin = AdminLayer::new(std::env::var("ADMIN_TOKEN")?).info("table", "orders");
+//!     lambda_http::run(admin.layer(service_fn(hello))).await
+//! }
+//! ```
+use crate::{ext::RequestExt, Body, IntoResponse, Request, Response};
+use futures::future::BoxFuture;
+use http::{
+    header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE},
+    StatusCode,
+};
+use lambda_runtime::{tower::Layer, Service};
+use serde_json::{json, Map, Value};
+use std::{
+    env, fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant},
+};
+use subtle::ConstantTimeEq;
+
+/// Prefix of the paths of the admin endpoints.
+pub const PATH_PREFIX: &str = "/__lambda/";
+/// Path of the health endpoint.
+pub const HEALTH_PATH: &str = "/__lambda/health";
+/// Path of the info endpoint.
+pub const INFO_PATH: &str = "/__lambda/info";
+
+/// The features of this crate, and whether they're enabled.
+///
+/// Every feature declared in `Cargo.toml`, except `default`, must be listed.
+const FEATURES: &[(&str, bool)] = &[
+    ("apigw_rest", cfg!(feature = "apigw_rest")),
+    ("apigw_http", cfg!(feature = "apigw_http")),
+    ("apigw_websockets", cfg!(feature = "apigw_websockets")),
+    ("alb", cfg!(feature = "alb")),
+    ("vpc_lattice", cfg!(feature = "vpc_lattice")),
+    ("protobuf", cfg!(feature = "protobuf")),
+    ("macros", cfg!(feature = "macros")),
+    ("askama", cfg!(feature = "askama")),
+    ("minijinja", cfg!(feature = "minijinja")),
+    ("sniff", cfg!(feature = "sniff")),
+    ("presign", cfg!(feature = "presign")),
+    ("alb_oidc_verify", cfg!(feature = "alb_oidc_verify")),
+    ("websocket_dynamodb", cfg!(feature = "websocket_dynamodb")),
+    ("session_dynamodb", cfg!(feature = "session_dynamodb")),
+    ("oidc", cfg!(feature = "oidc")),
+];
+
+/// Marks the responses of the admin endpoints, which are not logged.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AdminResponse;
+
+/// What the execution environment has done since the layer was created.
+#[derive(Debug)]
+struct Stats {
+    started: Instant,
+    invocations: AtomicU64,
+    init_duration: Mutex<Option<Duration>>,
+}
+
+/// A [`Layer`] that answers the admin endpoints.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone)]
+pub struct AdminLayer {
+    token: Arc<str>,
+    info: Map<String, Value>,
+    stats: Arc<Stats>,
+}
+
+impl AdminLayer {
+    /// Create a layer whose endpoints require `token`.
+    ///
+    /// Use a long random token, kept in a secret. An empty token rejects every request.
+    pub fn new(token: impl Into<String>) -> Self {
+        AdminLayer {
+            token: token.into().into(),
+            info: Map::new(),
+            stats: Arc::new(Stats {
+                started: Instant::now(),
+                invocations: AtomicU64::new(0),
+                init_duration: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Add `key` to the `info` object of the info endpoint, like the
+    /// configuration of the handler.
+    pub fn info(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.info.insert(key.into(), value.into());
+        self
+    }
+
+    fn is_authorized(&self, req: &Request) -> bool {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) if !self.token.is_empty() => token.as_bytes().ct_eq(self.token.as_bytes()).into(),
+            _ => false,
+        }
+    }
+
+    fn info_document(&self, req: &Request) -> Value {
+        let ctx = req.lambda_context_ref();
+        let var = |name: &str| env::var(name).ok();
+        let features: Vec<&str> = FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+        let init_duration = *self.stats.init_duration.lock().unwrap_or_else(|err| err.into_inner());
+        json!({
+            "runtime": {
+                "lambda_http": env!("CARGO_PKG_VERSION"),
+                "features": features,
+            },
+            "function": {
+                "name": ctx.map(|ctx| ctx.env_config.function_name.clone()),
+                "version": ctx.map(|ctx| ctx.env_config.version.clone()),
+                "memory_mb": ctx.map(|ctx| ctx.env_config.memory),
+                "log_group": ctx.map(|ctx| ctx.env_config.log_group.clone()),
+                "log_stream": ctx.map(|ctx| ctx.env_config.log_stream.clone()),
+                "handler": var("_HANDLER"),
+                "region": var("AWS_REGION"),
+            },
+            "environment": {
+                "uptime_ms": self.stats.started.elapsed().as_millis() as u64,
+                "invocations": self.stats.invocations.load(Ordering::Relaxed),
+                "init_duration_ms": init_duration.map(|duration| duration.as_millis() as u64),
+            },
+            "info": self.info,
+        })
+    }
+}
+
+impl fmt::Debug for AdminLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminLayer")
+            .field("info", &self.info)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+fn respond(status: StatusCode, content_type: &str, body: Body) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .header(CACHE_CONTROL, "no-store")
+        .body(body)
+        .expect("unable to build http::Response")
+}
+
+impl<S> Layer<S> for AdminLayer {
+    type Service = AdminService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AdminService {
+            inner,
+            config: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that answers the admin endpoints.
+///
+/// See [`AdminLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct AdminService<S> {
+    inner: S,
+    config: AdminLayer,
+}
+
+impl<S> Service<Request> for AdminService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        if let Some(ctx) = req.lambda_context_ref().filter(|ctx| ctx.cold_start) {
+            *self
+                .config
+                .stats
+                .init_duration
+                .lock()
+                .unwrap_or_else(|err| err.into_inner()) = ctx.init_duration;
+        }
+
+        if req.uri().path().starts_with(PATH_PREFIX) {
+            let path = req.uri().path();
+            let mut response = match path {
+                HEALTH_PATH | INFO_PATH if self.config.is_authorized(&req) => {
+                    let mut response = match path {
+                        HEALTH_PATH => respond(StatusCode::OK, "application/json", Body::from(r#"{"status":"ok"}"#)),
+                        _ => respond(
+                            StatusCode::OK,
+                            "application/json",
+                            Body::from(self.config.info_document(&req).to_string()),
+                        ),
+                    };
+                    response.extensions_mut().insert(AdminResponse);
+                    response
+                }
+                _ => respond(StatusCode::NOT_FOUND, "text/plain", Body::from("Not Found")),
+            };
+            if req.method() == http::Method::HEAD {
+                *response.body_mut() = Body::Empty;
+            }
+            return Box::pin(async move { Ok(response) });
+        }
+
+        self.config.stats.invocations.fetch_add(1, Ordering::Relaxed);
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            Ok(response.await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RequestLogLayer;
+    use lambda_runtime::{service_fn, Context, Error};
+
+    fn request(path: &str, token: Option<&str>) -> Request {
+        let mut ctx = Context::default();
+        ctx.env_config.function_name = "orders".into();
+        ctx.cold_start = true;
+        ctx.init_duration = Some(Duration::from_millis(120));
+        let mut builder = http::Request::builder().uri(path);
+        if let Some(token) = token {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        builder.body(Body::Empty).unwrap().with_lambda_context(ctx)
+    }
+
+    #[tokio::test]
+    async fn requires_the_token() {
+        let mut service = AdminLayer::new("secret").layer(service_fn(|_: Request| async { Ok::<_, Error>("hello") }));
+
+        for token in [None, Some("guess"), Some("")] {
+            let res = service.call(request(HEALTH_PATH, token)).await.unwrap();
+            assert_eq!(StatusCode::NOT_FOUND, res.status());
+        }
+        let res = service.call(request(HEALTH_PATH, Some("secret"))).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let res = service.call(request("/__lambda/other", Some("secret"))).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let mut empty = AdminLayer::new("").layer(service_fn(|_: Request| async { Ok::<_, Error>("hello") }));
+        let res = empty.call(request(HEALTH_PATH, Some(""))).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+    }
+
+    #[tokio::test]
+    async fn describes_the_function() {
+        let layer = AdminLayer::new("secret").info("table", "orders-table");
+        let mut service = layer.layer(service_fn(|_: Request| async { Ok::<_, Error>("hello") }));
+
+        service.call(request("/orders", None)).await.unwrap();
+        service.call(request("/orders", None)).await.unwrap();
+        let res = service.call(request(INFO_PATH, Some("secret"))).await.unwrap();
+        let info: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(env!("CARGO_PKG_VERSION"), info["runtime"]["lambda_http"]);
+        assert!(info["runtime"]["features"]
+            .as_array()
+            .unwrap()
+            .contains(&"apigw_http".into()));
+        assert_eq!("orders", info["function"]["name"]);
+        assert_eq!(2, info["environment"]["invocations"]);
+        assert_eq!(120, info["environment"]["init_duration_ms"]);
+        assert_eq!("orders-table", info["info"]["table"]);
+    }
+
+    #[test]
+    fn lists_every_feature() {
+        let manifest = include_str!("../Cargo.toml");
+        let mut declared: Vec<&str> = manifest
+            .lines()
+            .skip_while(|line| line.trim() != "[features]")
+            .skip(1)
+            .take_while(|line| !line.starts_with('['))
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, _)| name.trim())
+            .filter(|name| !name.starts_with('#') && *name != "default")
+            .collect();
+        declared.sort_unstable();
+
+        let mut listed: Vec<&str> = FEATURES.iter().map(|(name, _)| *name).collect();
+        listed.sort_unstable();
+        assert_eq!(declared, listed);
+    }
+
+    #[tokio::test]
+    async fn is_not_logged() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let l = lines.clone();
+        let log = RequestLogLayer::new().sink(move |line| l.lock().unwrap().push(line.to_string()));
+        let admin = AdminLayer::new("secret");
+        let mut service = log.layer(admin.layer(service_fn(|_: Request| async { Ok::<_, Error>("hello") })));
+
+        service.call(request(HEALTH_PATH, Some("secret"))).await.unwrap();
+        assert!(lines.lock().unwrap().is_empty());
+        service.call(request(HEALTH_PATH, Some("guess"))).await.unwrap();
+        service.call(request("/orders", None)).await.unwrap();
+        assert_eq!(2, lines.lock().unwrap().len());
+    }
+}
@@ -0,0 +1,470 @@
+//! Identity of the users authenticated by an Application Load Balancer.
+//!
+//! When a listener rule authenticates users with an OIDC identity provider or
+//! Amazon Cognito, the load balancer forwards three headers to the target:
+//!
+//! * `x-amzn-oidc-accesstoken`, the access token of the identity provider.
+//! * `x-amzn-oidc-identity`, the subject of the user.
+//! * `x-amzn-oidc-data`, a JWT with the claims of the user, signed by the load balancer.
+//!
+//! [`AlbOidc::from_headers`] parses them, without checking the signature of
+//! the data token. Functions that can be reached without going through the
+//! load balancer, like with a Function URL, must check it: with the
+//! `alb_oidc_verify` feature, [`OidcVerifier`] checks the ES256 signature of
+//! the token against the public keys of the load balancers of the region,
+//! and caches the keys in the execution environment.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{alb_oidc::AlbOidc, service_fn, Error, Request};
+//!
+//! async fn handler(req: Request) -> Result<String, Error> {
+//!     match AlbOidc::from_headers(req.headers())? {
+//!         Some(oidc) => Ok(format!("hello {}", oidc.data.claims.sub)),
+//!         None => Ok("hello stranger".into()),
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run(service_fn(handler)).await
+//! }
+//! ```
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use http::HeaderMap;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+use std::{collections::HashMap, fmt};
+
+/// Header with the access token of the identity provider.
+pub const X_AMZN_OIDC_ACCESSTOKEN: &str = "x-amzn-oidc-accesstoken";
+/// Header with the subject of the authenticated user.
+pub const X_AMZN_OIDC_IDENTITY: &str = "x-amzn-oidc-identity";
+/// Header with the claims of the authenticated user, as a JWT signed by the load balancer.
+pub const X_AMZN_OIDC_DATA: &str = "x-amzn-oidc-data";
+
+// The load balancer pads the segments of the data token, unlike most JWT issuers.
+pub(crate) const JWT_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Error returned when the OIDC headers of a request can't be parsed or verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OidcError {
+    /// Some of the OIDC headers are missing, or aren't valid UTF-8.
+    MissingHeader(&'static str),
+    /// The data token isn't a well formed JWT.
+    MalformedToken(String),
+    /// The data token is signed with another algorithm than ES256.
+    UnsupportedAlgorithm(String),
+    /// The data token was signed by another load balancer than the expected one.
+    UnexpectedSigner(String),
+    /// The data token has expired.
+    Expired,
+    /// The public key of the load balancer couldn't be fetched or parsed.
+    Key(String),
+    /// The signature of the data token doesn't match its content.
+    InvalidSignature,
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::MissingHeader(name) => write!(f, "the `{name}` header is missing or invalid"),
+            OidcError::MalformedToken(reason) => write!(f, "the OIDC data token is malformed: {reason}"),
+            OidcError::UnsupportedAlgorithm(alg) => write!(f, "the OIDC data token is signed with `{alg}`"),
+            OidcError::UnexpectedSigner(signer) => write!(f, "the OIDC data token was signed by `{signer}`"),
+            OidcError::Expired => write!(f, "the OIDC data token has expired"),
+            OidcError::Key(reason) => write!(f, "the public key of the load balancer is unavailable: {reason}"),
+            OidcError::InvalidSignature => write!(f, "the signature of the OIDC data token is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for OidcError {}
+
+/// Identity of a user authenticated by the load balancer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlbOidc {
+    /// The access token of the identity provider, from `x-amzn-oidc-accesstoken`.
+    pub access_token: String,
+    /// The subject of the user, from `x-amzn-oidc-identity`.
+    pub identity: String,
+    /// The claims of the user, from `x-amzn-oidc-data`.
+    pub data: OidcData,
+}
+
+impl AlbOidc {
+    /// Parse the OIDC headers, without verifying the signature of the data token.
+    ///
+    /// Returns `None` when the request wasn't authenticated by the load balancer.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Option<Self>, OidcError> {
+        if !headers.contains_key(X_AMZN_OIDC_DATA) {
+            return Ok(None);
+        }
+        let header = |name: &'static str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+                .ok_or(OidcError::MissingHeader(name))
+        };
+        Ok(Some(AlbOidc {
+            access_token: header(X_AMZN_OIDC_ACCESSTOKEN)?,
+            identity: header(X_AMZN_OIDC_IDENTITY)?,
+            data: OidcData::parse(&header(X_AMZN_OIDC_DATA)?)?,
+        }))
+    }
+}
+
+/// The data token signed by the load balancer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcData {
+    /// The header of the token.
+    pub header: OidcDataHeader,
+    /// The claims of the user.
+    pub claims: OidcClaims,
+    token: String,
+}
+
+impl OidcData {
+    /// Parse a data token, without verifying its signature.
+    pub fn parse(token: &str) -> Result<Self, OidcError> {
+        let mut segments = token.split('.');
+        let (header, claims) = match (segments.next(), segments.next(), segments.next(), segments.next()) {
+            (Some(header), Some(claims), Some(_signature), None) => (header, claims),
+            _ => return Err(OidcError::MalformedToken("expected three segments".into())),
+        };
+        Ok(OidcData {
+            header: decode_segment(header)?,
+            claims: decode_segment(claims)?,
+            token: token.to_string(),
+        })
+    }
+
+    /// Return the token, as sent by the load balancer.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+fn decode_segment<T: DeserializeOwned>(segment: &str) -> Result<T, OidcError> {
+    let bytes = JWT_BASE64
+        .decode(segment)
+        .map_err(|err| OidcError::MalformedToken(err.to_string()))?;
+    serde_json::from_slice(&bytes).map_err(|err| OidcError::MalformedToken(err.to_string()))
+}
+
+/// Header of the data token.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct OidcDataHeader {
+    /// The signing algorithm, `ES256`.
+    pub alg: String,
+    /// The id of the key that signed the token.
+    pub kid: String,
+    /// The ARN of the load balancer that signed the token.
+    pub signer: String,
+    /// The issuer of the identity provider.
+    #[serde(default)]
+    pub iss: Option<String>,
+    /// The client id of the load balancer at the identity provider.
+    #[serde(default)]
+    pub client: Option<String>,
+    /// The expiration time of the token, in seconds since the epoch.
+    #[serde(default)]
+    pub exp: Option<u64>,
+}
+
+/// Claims of the user, returned by the user info endpoint of the identity provider.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OidcClaims {
+    /// The subject of the user.
+    pub sub: String,
+    /// The expiration time of the token, in seconds since the epoch.
+    #[serde(default)]
+    pub exp: Option<u64>,
+    /// The issuer of the identity provider.
+    #[serde(default)]
+    pub iss: Option<String>,
+    /// The email address of the user.
+    #[serde(default)]
+    pub email: Option<String>,
+    /// The user name.
+    #[serde(default)]
+    pub username: Option<String>,
+    /// The other claims returned by the identity provider.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+#[cfg(feature = "alb_oidc_verify")]
+pub use verify::{KeyFetcher, OidcVerifier};
+
+#[cfg(feature = "alb_oidc_verify")]
+mod verify {
+    use super::{AlbOidc, OidcData, OidcError, JWT_BASE64};
+    use base64::Engine;
+    use futures::future::BoxFuture;
+    use http::HeaderMap;
+    use lambda_runtime::Error;
+    use p256::{
+        ecdsa::{signature::Verifier, Signature, VerifyingKey},
+        pkcs8::DecodePublicKey,
+    };
+    use std::{
+        collections::HashMap,
+        fmt,
+        sync::{Arc, Mutex},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    /// Source of the public keys of the load balancers.
+    ///
+    /// The keys are PEM documents served over HTTPS, at the URLs returned by
+    /// [`OidcVerifier::key_url`]. This crate doesn't ship an HTTPS client, so
+    /// fetching them is delegated to the function.
+    pub trait KeyFetcher: Send + Sync {
+        /// Fetch the PEM document at `url`.
+        fn fetch(&self, url: &str) -> BoxFuture<'_, Result<String, Error>>;
+    }
+
+    /// Verifier of the signature of the data tokens signed by the load balancers of a region.
+    ///
+    /// Keys are fetched the first time a token signed with them is verified, and
+    /// cached for the life of the execution environment. Clones share the cache.
+    #[derive(Clone)]
+    pub struct OidcVerifier {
+        region: String,
+        signer: Option<String>,
+        fetcher: Arc<dyn KeyFetcher>,
+        keys: Arc<Mutex<HashMap<String, VerifyingKey>>>,
+    }
+
+    impl fmt::Debug for OidcVerifier {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("OidcVerifier")
+                .field("region", &self.region)
+                .field("signer", &self.signer)
+                .field("keys", &self.keys.lock().map(|keys| keys.len()).unwrap_or_default())
+                .finish()
+        }
+    }
+
+    impl OidcVerifier {
+        /// Create a verifier of the tokens signed in `region`, fetching the keys with `fetcher`.
+        pub fn new(region: impl Into<String>, fetcher: impl KeyFetcher + 'static) -> Self {
+            OidcVerifier {
+                region: region.into(),
+                signer: None,
+                fetcher: Arc::new(fetcher),
+                keys: Arc::default(),
+            }
+        }
+
+        /// Only accept tokens signed by the load balancer with the ARN `signer`.
+        pub fn signer(self, signer: impl Into<String>) -> Self {
+            OidcVerifier {
+                signer: Some(signer.into()),
+                ..self
+            }
+        }
+
+        /// Return the URL of the public key with the id `kid`.
+        pub fn key_url(&self, kid: &str) -> String {
+            format!("https://public-keys.auth.elb.{}.amazonaws.com/{}", self.region, kid)
+        }
+
+        /// Parse the OIDC headers, and verify the signature of the data token.
+        ///
+        /// Returns `None` when the request wasn't authenticated by the load balancer.
+        pub async fn verify_headers(&self, headers: &HeaderMap) -> Result<Option<AlbOidc>, OidcError> {
+            match AlbOidc::from_headers(headers)? {
+                Some(oidc) => {
+                    self.verify(&oidc.data).await?;
+                    Ok(Some(oidc))
+                }
+                None => Ok(None),
+            }
+        }
+
+        /// Verify the signature, the signer and the expiration time of a data token.
+        pub async fn verify(&self, data: &OidcData) -> Result<(), OidcError> {
+            if data.header.alg != "ES256" {
+                return Err(OidcError::UnsupportedAlgorithm(data.header.alg.clone()));
+            }
+            if let Some(signer) = &self.signer {
+                if *signer != data.header.signer {
+                    return Err(OidcError::UnexpectedSigner(data.header.signer.clone()));
+                }
+            }
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            if data.header.exp.or(data.claims.exp).map_or(false, |exp| exp <= now) {
+                return Err(OidcError::Expired);
+            }
+
+            let (message, signature) = data
+                .token
+                .rsplit_once('.')
+                .ok_or_else(|| OidcError::MalformedToken("expected three segments".into()))?;
+            let signature = JWT_BASE64
+                .decode(signature)
+                .map_err(|err| OidcError::MalformedToken(err.to_string()))?;
+            let signature = Signature::from_slice(&signature).map_err(|_| OidcError::InvalidSignature)?;
+            let key = self.key(&data.header.kid).await?;
+            key.verify(message.as_bytes(), &signature)
+                .map_err(|_| OidcError::InvalidSignature)
+        }
+
+        async fn key(&self, kid: &str) -> Result<VerifyingKey, OidcError> {
+            if let Some(key) = self.keys.lock().expect("poisoned key cache").get(kid) {
+                return Ok(*key);
+            }
+            // Key ids end up in the URL, don't let them point anywhere else.
+            if kid.is_empty() || !kid.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-') {
+                return Err(OidcError::Key(format!("invalid key id `{kid}`")));
+            }
+            let pem = self
+                .fetcher
+                .fetch(&self.key_url(kid))
+                .await
+                .map_err(|err| OidcError::Key(err.to_string()))?;
+            let key = VerifyingKey::from_public_key_pem(pem.trim()).map_err(|err| OidcError::Key(err.to_string()))?;
+            self.keys
+                .lock()
+                .expect("poisoned key cache")
+                .insert(kid.to_string(), key);
+            Ok(key)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(header: &Value, claims: &Value, signature: &[u8]) -> String {
+        let encode = |bytes: &[u8]| JWT_BASE64.encode(bytes);
+        format!(
+            "{}.{}.{}",
+            encode(header.to_string().as_bytes()),
+            encode(claims.to_string().as_bytes()),
+            encode(signature)
+        )
+    }
+
+    fn headers(data: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_AMZN_OIDC_ACCESSTOKEN, "access".parse().unwrap());
+        headers.insert(X_AMZN_OIDC_IDENTITY, "user-1".parse().unwrap());
+        headers.insert(X_AMZN_OIDC_DATA, data.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn parses_the_oidc_headers() {
+        let header = serde_json::json!({
+            "alg": "ES256",
+            "kid": "12345678-1234-1234-1234-123456789012",
+            "signer": "arn:aws:elasticloadbalancing:us-east-1:123456789012:loadbalancer/app/my-alb/1234",
+            "iss": "https://idp.example.com",
+            "client": "client-id",
+            "exp": 1700000000
+        });
+        let claims = serde_json::json!({"sub": "user-1", "email": "user@example.com", "groups": ["admin"]});
+        let oidc = AlbOidc::from_headers(&headers(&token(&header, &claims, b"sig")))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!("access", oidc.access_token);
+        assert_eq!("user-1", oidc.identity);
+        assert_eq!("ES256", oidc.data.header.alg);
+        assert_eq!(Some(1700000000), oidc.data.header.exp);
+        assert_eq!("user-1", oidc.data.claims.sub);
+        assert_eq!(Some("user@example.com"), oidc.data.claims.email.as_deref());
+        assert_eq!(serde_json::json!(["admin"]), oidc.data.claims.other["groups"]);
+
+        assert_eq!(None, AlbOidc::from_headers(&HeaderMap::new()).unwrap());
+        let mut partial = headers(&token(&header, &claims, b"sig"));
+        partial.remove(X_AMZN_OIDC_IDENTITY);
+        assert_eq!(
+            Err(OidcError::MissingHeader(X_AMZN_OIDC_IDENTITY)),
+            AlbOidc::from_headers(&partial)
+        );
+        assert!(matches!(
+            AlbOidc::from_headers(&headers("not-a-token")),
+            Err(OidcError::MalformedToken(_))
+        ));
+    }
+
+    #[cfg(feature = "alb_oidc_verify")]
+    #[tokio::test]
+    async fn verifies_the_signature_with_cached_keys() {
+        use futures::future::BoxFuture;
+        use lambda_runtime::Error;
+        use p256::{
+            ecdsa::{signature::Signer, Signature, SigningKey},
+            pkcs8::{EncodePublicKey, LineEnding},
+        };
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        struct Fetcher(String, Arc<AtomicUsize>);
+
+        impl KeyFetcher for Fetcher {
+            fn fetch(&self, url: &str) -> BoxFuture<'_, Result<String, Error>> {
+                assert_eq!("https://public-keys.auth.elb.us-east-1.amazonaws.com/key-1", url);
+                self.1.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async move { Ok(self.0.clone()) })
+            }
+        }
+
+        let signing_key = SigningKey::from_slice(&[7; 32]).unwrap();
+        let pem = signing_key.verifying_key().to_public_key_pem(LineEnding::LF).unwrap();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let verifier = OidcVerifier::new("us-east-1", Fetcher(pem, fetches.clone())).signer("arn:alb");
+
+        let sign = |header: Value, claims: Value| {
+            let unsigned = token(&header, &claims, b"");
+            let message = unsigned.trim_end_matches('.');
+            let signature: Signature = signing_key.sign(message.as_bytes());
+            format!("{message}.{}", JWT_BASE64.encode(signature.to_bytes()))
+        };
+        let header = serde_json::json!({"alg": "ES256", "kid": "key-1", "signer": "arn:alb", "exp": 4102444800u64});
+        let claims = serde_json::json!({"sub": "user-1"});
+
+        let data = sign(header.clone(), claims.clone());
+        let oidc = verifier.verify_headers(&headers(&data)).await.unwrap().unwrap();
+        assert_eq!("user-1", oidc.data.claims.sub);
+        verifier.verify(&oidc.data).await.unwrap();
+        assert_eq!(1, fetches.load(Ordering::SeqCst));
+
+        let mut segments: Vec<&str> = data.split('.').collect();
+        let admin = JWT_BASE64.encode(br#"{"sub":"admin"}"#);
+        segments[1] = &admin;
+        let forged = OidcData::parse(&segments.join(".")).unwrap();
+        assert_eq!(Err(OidcError::InvalidSignature), verifier.verify(&forged).await);
+
+        let mut other_signer = header.clone();
+        other_signer["signer"] = "arn:other".into();
+        let data = OidcData::parse(&sign(other_signer, claims.clone())).unwrap();
+        assert_eq!(
+            Err(OidcError::UnexpectedSigner("arn:other".into())),
+            verifier.verify(&data).await
+        );
+
+        let mut expired = header;
+        expired["exp"] = 1.into();
+        let data = OidcData::parse(&sign(expired, claims)).unwrap();
+        assert_eq!(Err(OidcError::Expired), verifier.verify(&data).await);
+    }
+}
@@ -0,0 +1,492 @@
+//! Response caching in the `/tmp` directory of the execution environment.
+//!
+//! [`CacheLayer`] stores the successful responses to `GET` and `HEAD` requests
+//! in files, keyed by the path and the query string of the request, and answers
+//! the same requests from the files while they're fresh. The `/tmp` directory
+//! survives between the invocations of a warm execution environment, so
+//! read-heavy endpoints skip the handler, and its calls to databases and other
+//! services, without any external infrastructure. Every execution environment
+//! has its own cache.
+//!
+//! Responses are kept for the time to live of the layer, or for the `max-age`
+//! or `s-maxage` directive of their `Cache-Control` header when it's shorter.
+//! Responses with a `no-store`, `no-cache` or `private` directive, with cookies,
+//! or with a `Vary` header aren't cached. Requests with an `Authorization`
+//! header, or a `no-cache` or `no-store` directive, always go to the handler.
+//!
+//! Cached responses have a strong `ETag` and an `Age` header, and conditional
+//! requests with a matching `If-None-Match` header are answered with a
+//! `304 Not Modified` response. When the files grow larger than the size limit
+//! of the layer, expired responses are removed first, then the oldest ones.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{cache::CacheLayer, service_fn, tower::Layer, Error, Request};
+//! use std::time::Duration;
+//!
+//! async fn catalog(_req: Request) -> Result<&'static str, Error> {
+//!     Ok("an expensive catalog")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let cache = CacheLayer::new(Duration::from_secs(60));
+//!     lambda_http::run(cache.layer(service_fn(catalog))).await
+//! }
+//! ```
+use crate::{
+    conditional::{etag_matches, strong_etag},
+    Body, IntoResponse, Request, Response,
+};
+use base64::Engine;
+use futures::future::BoxFuture;
+use http::{
+    header::{AGE, AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH, SET_COOKIE, VARY},
+    HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+};
+use lambda_runtime::{tower::Layer, Service};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Directory of the cached responses by default.
+pub const DEFAULT_DIRECTORY: &str = "/tmp/lambda-http-cache";
+
+/// Size limit of the cached responses by default, 64MiB.
+pub const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+const EXTENSION: &str = "json";
+
+/// A [`Layer`] that caches responses in files of the `/tmp` directory.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct CacheLayer {
+    config: Arc<CacheConfig>,
+}
+
+#[derive(Debug)]
+struct CacheConfig {
+    ttl: Duration,
+    directory: PathBuf,
+    max_size: u64,
+}
+
+impl CacheLayer {
+    /// Create a new layer that keeps responses for `ttl` at most.
+    pub fn new(ttl: Duration) -> Self {
+        CacheLayer {
+            config: Arc::new(CacheConfig {
+                ttl,
+                directory: PathBuf::from(DEFAULT_DIRECTORY),
+                max_size: DEFAULT_MAX_SIZE,
+            }),
+        }
+    }
+
+    /// Store the responses in `directory` instead of [`DEFAULT_DIRECTORY`].
+    pub fn directory(self, directory: impl Into<PathBuf>) -> Self {
+        self.with(|config| config.directory = directory.into())
+    }
+
+    /// Keep the size of the cached responses under `max_size` bytes instead of [`DEFAULT_MAX_SIZE`].
+    pub fn max_size(self, max_size: u64) -> Self {
+        self.with(|config| config.max_size = max_size)
+    }
+
+    fn with(self, f: impl FnOnce(&mut CacheConfig)) -> Self {
+        let mut config = Arc::try_unwrap(self.config).unwrap_or_else(|config| CacheConfig {
+            ttl: config.ttl,
+            directory: config.directory.clone(),
+            max_size: config.max_size,
+        });
+        f(&mut config);
+        CacheLayer {
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl<S> Layer<S> for CacheLayer {
+    type Service = CacheService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that answers requests with cached responses while they're fresh.
+///
+/// See [`CacheLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct CacheService<S> {
+    inner: S,
+    config: Arc<CacheConfig>,
+}
+
+impl<S> Service<Request> for CacheService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let cacheable =
+            (req.method() == Method::GET || req.method() == Method::HEAD) && !req.headers().contains_key(AUTHORIZATION);
+        let path = match cacheable {
+            true => Some(self.config.entry_path(&req)),
+            false => None,
+        };
+        let bypass = has_directive(req.headers(), &["no-cache", "no-store"]);
+        let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+
+        if let (Some(path), false) = (&path, bypass) {
+            if let Some(response) = Entry::read(path).and_then(|entry| entry.into_response(if_none_match.as_ref())) {
+                return Box::pin(async move { Ok(response) });
+            }
+        }
+
+        let fut = self.inner.call(req);
+        let config = self.config.clone();
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            let mut response = response.await;
+            let (path, ttl) = match (path, config.ttl_of(&response)) {
+                (Some(path), Some(ttl)) => (path, ttl),
+                _ => return Ok(response),
+            };
+
+            if !response.headers().contains_key(ETAG) {
+                let etag = strong_etag(response.body().as_ref());
+                response.headers_mut().insert(ETAG, etag);
+            }
+            if let Some(entry) = Entry::new(&response, ttl) {
+                if let Err(err) = config.write(&path, &entry) {
+                    tracing::debug!(error = %err, "failed to cache the response");
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+impl CacheConfig {
+    fn entry_path(&self, req: &Request) -> PathBuf {
+        let uri = req.uri();
+        let key = match uri.query() {
+            Some(query) => format!("{}?{query}", uri.path()),
+            None => uri.path().to_string(),
+        };
+        let digest = Sha256::digest(key.as_bytes());
+        let name = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(digest);
+        self.directory.join(name).with_extension(EXTENSION)
+    }
+
+    // How long `response` can be cached, if at all.
+    fn ttl_of(&self, response: &Response<Body>) -> Option<Duration> {
+        let headers = response.headers();
+        if response.status() != StatusCode::OK
+            || headers.contains_key(SET_COOKIE)
+            || headers.contains_key(VARY)
+            || has_directive(headers, &["no-store", "no-cache", "private"])
+        {
+            return None;
+        }
+        let max_age = ["s-maxage", "max-age"]
+            .iter()
+            .find_map(|name| directive_value(headers, name))
+            .map(Duration::from_secs);
+        let ttl = max_age.map_or(self.ttl, |max_age| max_age.min(self.ttl));
+        (!ttl.is_zero()).then_some(ttl)
+    }
+
+    fn write(&self, path: &Path, entry: &Entry) -> std::io::Result<()> {
+        fs::create_dir_all(&self.directory)?;
+        let data = serde_json::to_vec(entry)?;
+        self.evict(data.len() as u64)?;
+        // Readers never see a partial file: the entry is renamed once written.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, path)
+    }
+
+    // Remove expired entries, then the oldest ones, until `incoming` bytes fit in the size limit.
+    fn evict(&self, incoming: u64) -> std::io::Result<()> {
+        let now = now();
+        let mut entries = Vec::new();
+        let mut size = 0;
+        for file in fs::read_dir(&self.directory)? {
+            let file = file?;
+            let path = file.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            let metadata = file.metadata()?;
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            let expired = !matches!(Entry::read_expires(&path), Some(expires) if expires > now);
+            size += metadata.len();
+            entries.push((!expired, modified, metadata.len(), path));
+        }
+        if size + incoming <= self.max_size {
+            return Ok(());
+        }
+
+        // Expired entries sort first, then the least recently written.
+        entries.sort();
+        for (_, _, len, path) in entries {
+            if size + incoming <= self.max_size {
+                break;
+            }
+            fs::remove_file(path)?;
+            size -= len;
+        }
+        Ok(())
+    }
+}
+
+/// A cached response.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    expires: u64,
+    stored: u64,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: CachedBody,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+enum CachedBody {
+    Empty,
+    Text(String),
+    Binary(String),
+}
+
+#[derive(Deserialize)]
+struct Expires {
+    expires: u64,
+}
+
+impl Entry {
+    fn new(response: &Response<Body>, ttl: Duration) -> Option<Self> {
+        let mut headers = Vec::with_capacity(response.headers().len());
+        for (name, value) in response.headers() {
+            headers.push((name.to_string(), value.to_str().ok()?.to_string()));
+        }
+        let body = match response.body() {
+            Body::Empty => CachedBody::Empty,
+            Body::Text(text) => CachedBody::Text(text.clone()),
+            Body::Binary(bytes) => CachedBody::Binary(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        };
+        let stored = now();
+        Some(Entry {
+            expires: stored + ttl.as_secs(),
+            stored,
+            status: response.status().as_u16(),
+            headers,
+            body,
+        })
+    }
+
+    fn read(path: &Path) -> Option<Self> {
+        let entry: Entry = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+        (entry.expires > now()).then_some(entry)
+    }
+
+    fn read_expires(path: &Path) -> Option<u64> {
+        let expires: Expires = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+        Some(expires.expires)
+    }
+
+    fn into_response(self, if_none_match: Option<&HeaderValue>) -> Option<Response<Body>> {
+        let mut headers = HeaderMap::with_capacity(self.headers.len() + 1);
+        for (name, value) in self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).ok()?;
+            headers.append(name, HeaderValue::from_str(&value).ok()?);
+        }
+        headers.insert(AGE, HeaderValue::from(now().saturating_sub(self.stored)));
+
+        let not_modified = match (if_none_match, headers.get(ETAG)) {
+            (Some(tags), Some(etag)) => etag_matches(tags, etag),
+            _ => false,
+        };
+        let (status, body) = if not_modified {
+            headers.remove(CONTENT_TYPE);
+            headers.remove(CONTENT_LENGTH);
+            (StatusCode::NOT_MODIFIED, Body::Empty)
+        } else {
+            let body = match self.body {
+                CachedBody::Empty => Body::Empty,
+                CachedBody::Text(text) => Body::Text(text),
+                CachedBody::Binary(data) => Body::Binary(base64::engine::general_purpose::STANDARD.decode(data).ok()?),
+            };
+            (StatusCode::from_u16(self.status).ok()?, body)
+        };
+
+        let mut response = Response::new(body);
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+        Some(response)
+    }
+}
+
+fn has_directive(headers: &HeaderMap, names: &[&str]) -> bool {
+    directives(headers).any(|(name, _)| names.iter().any(|n| name.eq_ignore_ascii_case(n)))
+}
+
+fn directive_value(headers: &HeaderMap, name: &str) -> Option<u64> {
+    directives(headers)
+        .find(|(directive, _)| directive.eq_ignore_ascii_case(name))
+        .and_then(|(_, value)| value?.trim_matches('"').parse().ok())
+}
+
+fn directives(headers: &HeaderMap) -> impl Iterator<Item = (&str, Option<&str>)> {
+    headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|directive| match directive.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (directive.trim(), None),
+        })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_fn;
+    use lambda_runtime::{tower::ServiceExt, Error};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("lambda-http-cache-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn service(
+        layer: CacheLayer,
+        calls: Arc<AtomicUsize>,
+    ) -> impl Service<Request, Response = Response<Body>, Error = Error> + Clone {
+        layer.layer(service_fn(move |req: Request| {
+            let calls = calls.clone();
+            async move {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                let builder = Response::builder().header(CONTENT_TYPE, "application/json");
+                let builder = match req.uri().path() {
+                    "/private" => builder.header(CACHE_CONTROL, "private, max-age=60"),
+                    "/short" => builder.header(CACHE_CONTROL, "max-age=0"),
+                    _ => builder,
+                };
+                Ok::<_, Error>(builder.body(Body::from(format!("{{\"call\":{call}}}"))).unwrap())
+            }
+        }))
+    }
+
+    fn request(path: &str, headers: &[(&str, &str)]) -> Request {
+        let mut builder = http::Request::builder().uri(path);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::Empty).unwrap()
+    }
+
+    async fn body(service: impl Service<Request, Response = Response<Body>, Error = Error>, req: Request) -> String {
+        let response = service.oneshot(req).await.unwrap();
+        String::from_utf8(response.body().to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn caches_responses_by_path_and_query() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let layer = CacheLayer::new(Duration::from_secs(60)).directory(directory("hits"));
+        let service = service(layer, calls.clone());
+
+        assert_eq!(
+            r#"{"call":0}"#,
+            body(service.clone(), request("/items?page=1", &[])).await
+        );
+        let response = service.clone().oneshot(request("/items?page=1", &[])).await.unwrap();
+        assert_eq!(r#"{"call":0}"#, std::str::from_utf8(response.body()).unwrap());
+        assert_eq!("application/json", response.headers()[CONTENT_TYPE]);
+        assert!(response.headers().contains_key(AGE));
+
+        let etag = response.headers()[ETAG].to_str().unwrap();
+        let response = service
+            .clone()
+            .oneshot(request("/items?page=1", &[("if-none-match", etag)]))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+
+        assert_eq!(
+            r#"{"call":1}"#,
+            body(service.clone(), request("/items?page=2", &[])).await
+        );
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn respects_cache_control() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let layer = CacheLayer::new(Duration::from_secs(60)).directory(directory("control"));
+        let service = service(layer, calls.clone());
+
+        for path in ["/private", "/private", "/short", "/short"] {
+            body(service.clone(), request(path, &[])).await;
+        }
+        assert_eq!(4, calls.load(Ordering::SeqCst));
+
+        body(service.clone(), request("/", &[])).await;
+        body(service.clone(), request("/", &[("cache-control", "no-cache")])).await;
+        body(service.clone(), request("/", &[("authorization", "Bearer token")])).await;
+        assert_eq!(7, calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn evicts_the_oldest_responses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let directory = directory("evict");
+        let layer = CacheLayer::new(Duration::from_secs(60))
+            .directory(&directory)
+            .max_size(700);
+        let service = service(layer, calls.clone());
+
+        for page in 0..10 {
+            body(service.clone(), request(&format!("/items?page={page}"), &[])).await;
+        }
+        let size: u64 = fs::read_dir(&directory)
+            .unwrap()
+            .map(|file| file.unwrap().metadata().unwrap().len())
+            .sum();
+        assert!(size <= 700, "{size}");
+        body(service.clone(), request("/items?page=9", &[])).await;
+        assert_eq!(10, calls.load(Ordering::SeqCst));
+    }
+}
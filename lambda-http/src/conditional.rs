@@ -0,0 +1,221 @@
+//! Conditional requests for cacheable responses.
+//!
+//! [`ConditionalLayer`] sets a strong `ETag` header on successful responses to
+//! `GET` and `HEAD` requests, computed from the response body. When the client
+//! already has the current version of the response, because its `If-None-Match`
+//! header matches the tag, or because its `If-Modified-Since` header isn't older
+//! than the `Last-Modified` header set by the handler, the body is dropped and
+//! a `304 Not Modified` response is returned instead.
+//!
+//! The handler still runs for every request, but clients and caches don't
+//! download the body again, which reduces the data transferred through API
+//! Gateway, and the costs that come with it.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{conditional::ConditionalLayer, service_fn, tower::Layer, Error, Request};
+//!
+//! async fn catalog(_req: Request) -> Result<&'static str, Error> {
+//!     Ok("the same catalog for everyone")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run(ConditionalLayer::new().layer(service_fn(catalog))).await
+//! }
+//! ```
+use crate::{Body, IntoResponse, Request, Response};
+use base64::Engine;
+use futures::future::BoxFuture;
+use http::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    HeaderMap, HeaderValue, Method, StatusCode,
+};
+use lambda_runtime::{tower::Layer, Service};
+use sha2::{Digest, Sha256};
+use std::task::{Context as TaskContext, Poll};
+
+/// A [`Layer`] that answers conditional `GET` and `HEAD` requests with `304 Not Modified` responses.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConditionalLayer {
+    _priv: (),
+}
+
+impl ConditionalLayer {
+    /// Create a new layer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for ConditionalLayer {
+    type Service = ConditionalService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConditionalService { inner }
+    }
+}
+
+/// A [`Service`] that answers conditional requests without sending the response body again.
+///
+/// See [`ConditionalLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct ConditionalService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for ConditionalService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let cacheable = req.method() == Method::GET || req.method() == Method::HEAD;
+        let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+        let if_modified_since = req.headers().get(IF_MODIFIED_SINCE).cloned();
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            let mut response = response.await;
+            if !cacheable || response.status() != StatusCode::OK {
+                return Ok(response);
+            }
+
+            if !response.headers().contains_key(ETAG) {
+                let etag = strong_etag(response.body().as_ref());
+                response.headers_mut().insert(ETAG, etag);
+            }
+
+            let not_modified = match if_none_match {
+                Some(tags) => etag_matches(&tags, &response.headers()[ETAG]),
+                None => if_modified_since
+                    .map(|since| not_modified_since(&since, response.headers()))
+                    .unwrap_or_default(),
+            };
+            if !not_modified {
+                return Ok(response);
+            }
+
+            let (mut parts, _) = response.into_parts();
+            parts.status = StatusCode::NOT_MODIFIED;
+            parts.headers.remove(CONTENT_TYPE);
+            parts.headers.remove(CONTENT_LENGTH);
+            Ok(Response::from_parts(parts, Body::Empty))
+        })
+    }
+}
+
+// Quoted, base64 encoded SHA-256 digest of the body.
+pub(crate) fn strong_etag(body: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(body);
+    let tag = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(digest);
+    HeaderValue::from_str(&format!("\"{tag}\"")).expect("base64 is a valid header value")
+}
+
+// `If-None-Match` uses the weak comparison: tags match regardless of their `W/` prefix.
+pub(crate) fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let (tags, etag) = match (if_none_match.to_str(), etag.to_str()) {
+        (Ok(tags), Ok(etag)) => (tags, etag),
+        _ => return false,
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    tags.split(',').any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+fn not_modified_since(if_modified_since: &HeaderValue, headers: &HeaderMap) -> bool {
+    let parse = |value: &HeaderValue| value.to_str().ok().and_then(|v| httpdate::parse_http_date(v).ok());
+    match (parse(if_modified_since), headers.get(LAST_MODIFIED).and_then(parse)) {
+        (Some(since), Some(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_fn;
+    use lambda_runtime::{tower::ServiceExt, Error};
+
+    fn service() -> impl Service<Request, Response = Response<Body>, Error = Error> {
+        ConditionalLayer::new().layer(service_fn(|req: Request| async move {
+            if req.uri().path() == "/missing" {
+                return Ok::<_, Error>(Response::builder().status(404).body(Body::from("missing")).unwrap());
+            }
+            Ok(Response::builder()
+                .header(CONTENT_TYPE, "text/plain")
+                .header(LAST_MODIFIED, "Wed, 21 Oct 2015 07:28:00 GMT")
+                .body(Body::from("catalog"))
+                .unwrap())
+        }))
+    }
+
+    fn request(method: Method, path: &str, headers: &[(&str, &str)]) -> Request {
+        let mut builder = http::Request::builder().method(method).uri(path);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::Empty).unwrap()
+    }
+
+    async fn send(req: Request) -> Response<Body> {
+        service().oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn matching_etags_return_not_modified() {
+        let response = send(request(Method::GET, "/", &[])).await;
+        assert_eq!(StatusCode::OK, response.status());
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+        assert!(etag.starts_with('"') && etag.ends_with('"'), "{etag}");
+
+        let weak = format!("\"other\", W/{etag}");
+        let response = send(request(Method::GET, "/", &[("if-none-match", &weak)])).await;
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+        assert_eq!(etag, response.headers()[ETAG]);
+        assert!(!response.headers().contains_key(CONTENT_TYPE));
+        assert!(matches!(response.body(), Body::Empty));
+
+        let response = send(request(Method::GET, "/", &[("if-none-match", "\"other\"")])).await;
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[tokio::test]
+    async fn if_modified_since_is_ignored_with_etags() {
+        let since = "Thu, 22 Oct 2015 07:28:00 GMT";
+        let response = send(request(Method::GET, "/", &[("if-modified-since", since)])).await;
+        assert_eq!(StatusCode::NOT_MODIFIED, response.status());
+
+        let older = "Tue, 20 Oct 2015 07:28:00 GMT";
+        let response = send(request(Method::GET, "/", &[("if-modified-since", older)])).await;
+        assert_eq!(StatusCode::OK, response.status());
+
+        let headers = [("if-modified-since", since), ("if-none-match", "\"other\"")];
+        let response = send(request(Method::GET, "/", &headers)).await;
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[tokio::test]
+    async fn only_successful_reads_are_tagged() {
+        let response = send(request(Method::POST, "/", &[("if-none-match", "*")])).await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert!(!response.headers().contains_key(ETAG));
+
+        let response = send(request(Method::GET, "/missing", &[("if-none-match", "*")])).await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert!(!response.headers().contains_key(ETAG));
+    }
+}
@@ -0,0 +1,472 @@
+//! Configuration of the built-in HTTP behaviors from environment variables.
+//!
+//! [`HttpConfig::from_env`] reads the `LAMBDA_RT_*` environment variables below
+//! when the function starts, so the same code can be tuned per environment
+//! without changes:
+//!
+//! | Variable | Value | Default |
+//! |----------|-------|---------|
+//! | [`LAMBDA_RT_COMPRESSION`](COMPRESSION_VAR) | `on` or `off`, gzip responses for clients that accept it | `off` |
+//! | [`LAMBDA_RT_BINARY_CONTENT_TYPES`](BINARY_CONTENT_TYPES_VAR) | comma separated media types, like `image/*,application/pdf`, sent base64 encoded | none |
+//! | [`LAMBDA_RT_LOG_LEVEL`](LOG_LEVEL_VAR) | `trace`, `debug`, `info`, `warn`, `error` or `off` | none |
+//! | [`LAMBDA_RT_HEALTH_PATHS`](HEALTH_PATHS_VAR) | comma separated paths answered with `200 OK` without calling the handler | none |
+//! | [`LAMBDA_RT_MAX_REQUEST_BYTES`](MAX_REQUEST_BYTES_VAR) | requests with larger bodies are rejected with `413 Payload Too Large` | no limit |
+//!
+//! Every variable is validated, and so is the name of every variable that starts
+//! with `LAMBDA_RT_`, to catch typos. The returned [`ConfigError`] lists all the
+//! invalid variables at once, so the function fails its initialization with a
+//! message that says what to fix.
+//!
+//! [`HttpConfig::layer`] applies the configuration to a handler.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{config::HttpConfig, service_fn, tower::Layer, Error, Request};
+//!
+//! async fn hello(_req: Request) -> Result<&'static str, Error> {
+//!     Ok("hello")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let config = HttpConfig::from_env()?;
+//!     // Configure the `tracing` subscriber with `config.log_level` here.
+//!     lambda_http::run(config.layer().layer(service_fn(hello))).await
+//! }
+//! ```
+use crate::{Body, IntoResponse, Request, Response};
+use futures::future::BoxFuture;
+use http::{
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+    HeaderValue, Method, StatusCode,
+};
+use lambda_runtime::{tower::Layer, Service};
+use std::{
+    env, fmt,
+    io::Write,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+};
+use tracing::level_filters::LevelFilter;
+
+/// Prefix of the environment variables read by [`HttpConfig::from_env`].
+pub const VAR_PREFIX: &str = "LAMBDA_RT_";
+/// Environment variable that enables the gzip compression of responses.
+pub const COMPRESSION_VAR: &str = "LAMBDA_RT_COMPRESSION";
+/// Environment variable with the media types of the responses sent as binary.
+pub const BINARY_CONTENT_TYPES_VAR: &str = "LAMBDA_RT_BINARY_CONTENT_TYPES";
+/// Environment variable with the log level of the function.
+pub const LOG_LEVEL_VAR: &str = "LAMBDA_RT_LOG_LEVEL";
+/// Environment variable with the paths of the health checks.
+pub const HEALTH_PATHS_VAR: &str = "LAMBDA_RT_HEALTH_PATHS";
+/// Environment variable with the maximum size of the request bodies, in bytes.
+pub const MAX_REQUEST_BYTES_VAR: &str = "LAMBDA_RT_MAX_REQUEST_BYTES";
+
+const KNOWN_VARS: [&str; 5] = [
+    COMPRESSION_VAR,
+    BINARY_CONTENT_TYPES_VAR,
+    LOG_LEVEL_VAR,
+    HEALTH_PATHS_VAR,
+    MAX_REQUEST_BYTES_VAR,
+];
+
+/// Responses smaller than this are not worth compressing.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Configuration of the built-in HTTP behaviors.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HttpConfig {
+    /// Whether responses are compressed with gzip for clients that accept it.
+    pub compression: bool,
+    /// Media types of the responses that are always sent as binary, like `image/png` or `image/*`.
+    pub binary_content_types: Vec<String>,
+    /// Log level of the function, to configure a `tracing` subscriber with.
+    pub log_level: Option<LevelFilter>,
+    /// Paths answered with `200 OK` without calling the handler.
+    pub health_paths: Vec<String>,
+    /// Maximum size of the request bodies, in bytes.
+    pub max_request_bytes: Option<usize>,
+}
+
+impl HttpConfig {
+    /// Read the configuration from the `LAMBDA_RT_*` environment variables.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(env::vars())
+    }
+
+    /// Read the configuration from a list of variables, the same way as [`HttpConfig::from_env`].
+    pub fn from_vars<I, K, V>(vars: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut config = HttpConfig::default();
+        let mut problems = Vec::new();
+
+        for (name, value) in vars {
+            let (name, value) = (name.as_ref(), value.as_ref().trim());
+            if !name.starts_with(VAR_PREFIX) {
+                continue;
+            }
+            let parsed = match name {
+                COMPRESSION_VAR => parse_switch(value).map(|on| config.compression = on),
+                BINARY_CONTENT_TYPES_VAR => {
+                    parse_list(value, parse_media_type).map(|types| config.binary_content_types = types)
+                }
+                LOG_LEVEL_VAR => parse_level(value).map(|level| config.log_level = Some(level)),
+                HEALTH_PATHS_VAR => parse_list(value, parse_path).map(|paths| config.health_paths = paths),
+                MAX_REQUEST_BYTES_VAR => parse_size(value).map(|size| config.max_request_bytes = Some(size)),
+                _ => Err(format!("unknown variable, expected one of {}", KNOWN_VARS.join(", "))),
+            };
+            if let Err(reason) = parsed {
+                problems.push(Problem {
+                    var: name.to_string(),
+                    value: value.to_string(),
+                    reason,
+                });
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            problems.sort_by(|a, b| a.var.cmp(&b.var));
+            Err(ConfigError { problems })
+        }
+    }
+
+    /// Return a [`Layer`] that applies this configuration to a handler.
+    pub fn layer(&self) -> ConfigLayer {
+        ConfigLayer {
+            config: Arc::new(self.clone()),
+        }
+    }
+
+    fn is_binary(&self, content_type: &str) -> bool {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        self.binary_content_types
+            .iter()
+            .any(|pattern| match pattern.strip_suffix("/*") {
+                Some(kind) => essence
+                    .split_once('/')
+                    .map_or(false, |(k, _)| k.eq_ignore_ascii_case(kind)),
+                None => essence.eq_ignore_ascii_case(pattern),
+            })
+    }
+}
+
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err("expected `on` or `off`".to_string()),
+    }
+}
+
+fn parse_level(value: &str) -> Result<LevelFilter, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "trace" => Ok(LevelFilter::TRACE),
+        "debug" => Ok(LevelFilter::DEBUG),
+        "info" => Ok(LevelFilter::INFO),
+        "warn" => Ok(LevelFilter::WARN),
+        "error" => Ok(LevelFilter::ERROR),
+        "off" => Ok(LevelFilter::OFF),
+        _ => Err("expected one of `trace`, `debug`, `info`, `warn`, `error` or `off`".to_string()),
+    }
+}
+
+fn parse_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err("expected a positive number of bytes".to_string()),
+    }
+}
+
+fn parse_list(value: &str, parse: fn(&str) -> Result<String, String>) -> Result<Vec<String>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(parse)
+        .collect()
+}
+
+fn parse_media_type(value: &str) -> Result<String, String> {
+    match value.split_once('/') {
+        Some((kind, subtype)) if !kind.is_empty() && kind != "*" && !subtype.is_empty() && !subtype.contains('/') => {
+            Ok(value.to_ascii_lowercase())
+        }
+        _ => Err(format!("`{value}` is not a media type like `image/png` or `image/*`")),
+    }
+}
+
+fn parse_path(value: &str) -> Result<String, String> {
+    if value.starts_with('/') {
+        Ok(value.to_string())
+    } else {
+        Err(format!("`{value}` is not a path starting with `/`"))
+    }
+}
+
+/// Error returned when some `LAMBDA_RT_*` environment variables are invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    problems: Vec<Problem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Problem {
+    var: String,
+    value: String,
+    reason: String,
+}
+
+impl ConfigError {
+    /// Names of the invalid variables.
+    pub fn vars(&self) -> impl Iterator<Item = &str> {
+        self.problems.iter().map(|problem| problem.var.as_str())
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid runtime configuration")?;
+        for (i, problem) in self.problems.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { "; " };
+            write!(f, "{separator}{}=`{}`: {}", problem.var, problem.value, problem.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A [`Layer`] that applies an [`HttpConfig`] to a handler.
+#[derive(Clone, Debug)]
+pub struct ConfigLayer {
+    config: Arc<HttpConfig>,
+}
+
+impl<S> Layer<S> for ConfigLayer {
+    type Service = ConfigService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConfigService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that applies an [`HttpConfig`] to a handler.
+///
+/// See [`ConfigLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct ConfigService<S> {
+    inner: S,
+    config: Arc<HttpConfig>,
+}
+
+impl<S> Service<Request> for ConfigService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let config = self.config.clone();
+
+        let is_read = req.method() == Method::GET || req.method() == Method::HEAD;
+        if is_read && config.health_paths.iter().any(|path| path == req.uri().path()) {
+            return Box::pin(async { Ok(status(StatusCode::OK)) });
+        }
+        if config.max_request_bytes.map_or(false, |max| req.body().len() > max) {
+            return Box::pin(async { Ok(status(StatusCode::PAYLOAD_TOO_LARGE)) });
+        }
+
+        let accepts_gzip = config.compression && accepts_gzip(&req);
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            let mut response = response.await;
+
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            if config.is_binary(content_type) {
+                if let Body::Text(text) = response.body_mut() {
+                    *response.body_mut() = Body::Binary(std::mem::take(text).into_bytes());
+                }
+            }
+
+            if accepts_gzip {
+                compress(&mut response);
+            }
+            Ok(response)
+        })
+    }
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::Empty);
+    *response.status_mut() = status;
+    response
+}
+
+fn accepts_gzip(req: &Request) -> bool {
+    req.headers()
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let rejected = parts.any(|param| param.replace(' ', "").eq_ignore_ascii_case("q=0"));
+            (name.eq_ignore_ascii_case("gzip") || name == "*") && !rejected
+        })
+}
+
+fn compress(response: &mut Response<Body>) {
+    if response.headers().contains_key(CONTENT_ENCODING) {
+        return;
+    }
+    let body: &[u8] = match response.body() {
+        Body::Text(text) => text.as_bytes(),
+        Body::Binary(bytes) => bytes,
+        Body::Empty => return,
+    };
+    if body.len() < COMPRESSION_THRESHOLD {
+        return;
+    }
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let compressed = match encoder.write_all(body).and_then(|_| encoder.finish()) {
+        Ok(compressed) => compressed,
+        Err(_) => return,
+    };
+    *response.body_mut() = Body::Binary(compressed);
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    headers.remove(CONTENT_LENGTH);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_runtime::service_fn;
+    use std::io::Read;
+
+    fn config(vars: &[(&str, &str)]) -> Result<HttpConfig, ConfigError> {
+        HttpConfig::from_vars(vars.iter().copied())
+    }
+
+    #[test]
+    fn parses_variables() {
+        let config = config(&[
+            ("PATH", "/usr/bin"),
+            ("LAMBDA_RT_COMPRESSION", "on"),
+            ("LAMBDA_RT_BINARY_CONTENT_TYPES", "image/*, application/PDF"),
+            ("LAMBDA_RT_LOG_LEVEL", "Debug"),
+            ("LAMBDA_RT_HEALTH_PATHS", "/health,/ready"),
+            ("LAMBDA_RT_MAX_REQUEST_BYTES", "1024"),
+        ])
+        .unwrap();
+        assert_eq!(
+            HttpConfig {
+                compression: true,
+                binary_content_types: vec!["image/*".to_string(), "application/pdf".to_string()],
+                log_level: Some(LevelFilter::DEBUG),
+                health_paths: vec!["/health".to_string(), "/ready".to_string()],
+                max_request_bytes: Some(1024),
+            },
+            config
+        );
+        assert!(config.is_binary("image/png"));
+        assert!(config.is_binary("application/pdf; charset=binary"));
+        assert!(!config.is_binary("text/html"));
+    }
+
+    #[test]
+    fn reports_every_invalid_variable() {
+        let err = config(&[
+            ("LAMBDA_RT_COMPRESSION", "yes please"),
+            ("LAMBDA_RT_MAX_REQUEST_BYTES", "-1"),
+            ("LAMBDA_RT_COMPRES", "on"),
+            ("LAMBDA_RT_HEALTH_PATHS", "health"),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            vec![
+                "LAMBDA_RT_COMPRES",
+                "LAMBDA_RT_COMPRESSION",
+                "LAMBDA_RT_HEALTH_PATHS",
+                "LAMBDA_RT_MAX_REQUEST_BYTES"
+            ],
+            err.vars().collect::<Vec<_>>()
+        );
+        let message = err.to_string();
+        assert!(
+            message.contains("LAMBDA_RT_COMPRESSION=`yes please`: expected `on` or `off`"),
+            "{message}"
+        );
+        assert!(message.contains("`health` is not a path"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn applies_the_configuration() {
+        let config = config(&[
+            ("LAMBDA_RT_COMPRESSION", "on"),
+            ("LAMBDA_RT_BINARY_CONTENT_TYPES", "application/pdf"),
+            ("LAMBDA_RT_HEALTH_PATHS", "/health"),
+            ("LAMBDA_RT_MAX_REQUEST_BYTES", "16"),
+        ])
+        .unwrap();
+        let mut service = config.layer().layer(service_fn(|req: Request| async move {
+            let response = match req.uri().path() {
+                "/pdf" => Response::builder()
+                    .header(CONTENT_TYPE, "application/pdf")
+                    .body("%PDF".to_string()),
+                _ => Response::builder().body("a".repeat(COMPRESSION_THRESHOLD)),
+            };
+            Ok::<_, lambda_runtime::Error>(response.unwrap())
+        }));
+
+        let mut req = Request::new(Body::Empty);
+        *req.uri_mut() = "/health".parse().unwrap();
+        let health = service.call(req).await.unwrap();
+        assert_eq!((StatusCode::OK, &Body::Empty), (health.status(), health.body()));
+
+        let too_large = service.call(Request::new(Body::from("x".repeat(17)))).await.unwrap();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, too_large.status());
+
+        let mut req = Request::new(Body::Empty);
+        *req.uri_mut() = "/pdf".parse().unwrap();
+        let pdf = service.call(req).await.unwrap();
+        assert_eq!(&Body::Binary(b"%PDF".to_vec()), pdf.body());
+
+        let mut req = Request::new(Body::Empty);
+        req.headers_mut()
+            .insert(ACCEPT_ENCODING, HeaderValue::from_static("br, gzip"));
+        let compressed = service.call(req).await.unwrap();
+        assert_eq!("gzip", compressed.headers()[CONTENT_ENCODING]);
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(compressed.body().as_ref())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!("a".repeat(COMPRESSION_THRESHOLD), decoded);
+    }
+}
@@ -0,0 +1,517 @@
+//! Protection against cross-site request forgery.
+//!
+//! Browsers send the cookies of a site with the requests that other sites make
+//! to it, so a form on another site can act on behalf of the users that are
+//! logged in. [`CsrfLayer`] gives every user a token that other sites can't read,
+//! and rejects the requests with unsafe methods, like `POST` or `DELETE`, that
+//! don't send it back, with a `403 Forbidden` response. The token is kept either:
+//! - In a [signed cookie](CsrfLayer::double_submit), that the client sends back
+//!   in the `x-csrf-token` header or the `csrf_token` form field. Scripts can
+//!   read the cookie to set the header. The signature covers the token and the
+//!   [identifier of the session or user](CsrfLayer::session_id) of the request,
+//!   so other sites can't plant their own token, and a token issued for one
+//!   session isn't valid in another. A token stops being valid, and is
+//!   replaced, when the identifier changes, like when a user logs in.
+//! - In the [`Session`] of the user, set by a [`SessionLayer`] that wraps the
+//!   CSRF layer, with [`CsrfLayer::session`].
+//!
+//! Handlers get the token of the request with the [`CsrfToken`] extension, to
+//! add it to their forms. Requests that can't be forged by browsers, like the
+//! requests of API clients that authenticate with a [bearer token](bearer_token),
+//! can be [exempted](CsrfLayer::exempt).
+//!
+//! [`SessionLayer`]: crate::session::SessionLayer
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{
+//!     csrf::{self, CsrfLayer, CsrfToken},
+//!     service_fn,
+//!     session::SessionKey,
+//!     tower::Layer,
+//!     Error, Request,
+//! };
+//!
+//! async fn form(req: Request) -> Result<String, Error> {
+//!     let token = req.extensions().get::<CsrfToken>().expect("csrf layer");
+//!     Ok(format!(
+//!         r#"<form method="post"><input type="hidden" name="csrf_token" value="{}"></form>"#,
+//!         token.as_str()
+//!     ))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let key = SessionKey::new(std::env::var("CSRF_SECRET")?.as_bytes())?;
+//!     let layer = CsrfLayer::double_submit(key).exempt(csrf::bearer_token);
+//!     lambda_http::run(layer.layer(service_fn(form))).await
+//! }
+//! ```
+use crate::{
+    session::{self, Session, SessionKey},
+    Body, IntoResponse, Request, Response,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures::future::BoxFuture;
+use hmac::Mac;
+use http::{
+    header::{AUTHORIZATION, CONTENT_TYPE, SET_COOKIE},
+    HeaderName, HeaderValue, Method, StatusCode,
+};
+use lambda_runtime::{tower::Layer, Service};
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+};
+use subtle::ConstantTimeEq;
+
+/// Header in which clients send the token.
+pub const X_CSRF_TOKEN: HeaderName = HeaderName::from_static("x-csrf-token");
+/// Form field in which clients send the token.
+pub const DEFAULT_FIELD_NAME: &str = "csrf_token";
+/// Name of the cookie with the token of [`CsrfLayer::double_submit`].
+pub const DEFAULT_COOKIE_NAME: &str = "csrf";
+/// Key of the token in the [`Session`] of [`CsrfLayer::session`].
+pub const SESSION_KEY: &str = "csrf_token";
+
+type ExemptFn = Arc<dyn Fn(&Request) -> bool + Send + Sync>;
+type SessionIdFn = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// The CSRF token of a request, in the extensions of requests that went
+/// through a [`CsrfLayer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    /// The token, to add to forms and to the headers of scripts.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Exempt requests with an `Authorization: Bearer` header, which browsers
+/// don't add to the requests of other sites.
+pub fn bearer_token(req: &Request) -> bool {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.get(..7))
+        .map_or(false, |scheme| scheme.eq_ignore_ascii_case("bearer "))
+}
+
+#[derive(Clone)]
+enum Storage {
+    Cookie(Arc<SessionKey>),
+    Session,
+}
+
+/// A [`Layer`] that rejects the requests with unsafe methods without a valid CSRF token.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone)]
+pub struct CsrfLayer {
+    storage: Storage,
+    exempt: Vec<ExemptFn>,
+    session_id: Option<SessionIdFn>,
+    cookie_name: String,
+    field_name: String,
+    secure: bool,
+}
+
+impl CsrfLayer {
+    fn new(storage: Storage) -> Self {
+        CsrfLayer {
+            storage,
+            exempt: Vec::new(),
+            session_id: None,
+            cookie_name: DEFAULT_COOKIE_NAME.to_string(),
+            field_name: DEFAULT_FIELD_NAME.to_string(),
+            secure: true,
+        }
+    }
+
+    /// Keep the tokens in cookies signed with `key`.
+    pub fn double_submit(key: SessionKey) -> Self {
+        Self::new(Storage::Cookie(Arc::new(key)))
+    }
+
+    /// Keep the tokens in the [`Session`] of the request.
+    ///
+    /// The [`SessionLayer`](crate::session::SessionLayer) must wrap this
+    /// layer, requests without a session are rejected.
+    pub fn session() -> Self {
+        Self::new(Storage::Session)
+    }
+
+    /// Don't check the requests for which `exempt` returns `true`.
+    pub fn exempt<F>(mut self, exempt: F) -> Self
+    where
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        self.exempt.push(Arc::new(exempt));
+        self
+    }
+
+    /// Don't check the requests whose path starts with `prefix`, like `/api/`.
+    pub fn exempt_prefix(self, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        self.exempt(move |req| req.uri().path().starts_with(&prefix))
+    }
+
+    /// Bind the tokens of [`CsrfLayer::double_submit`] to the identifier of the
+    /// session or user of the request, that `session_id` returns.
+    ///
+    /// Requests for which `session_id` returns `None`, and all requests when it
+    /// isn't set, share the same anonymous identifier.
+    pub fn session_id<F>(self, session_id: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        CsrfLayer {
+            session_id: Some(Arc::new(session_id)),
+            ..self
+        }
+    }
+
+    /// Set the name of the cookie with the token, [`DEFAULT_COOKIE_NAME`] by default.
+    pub fn cookie_name(self, cookie_name: impl Into<String>) -> Self {
+        CsrfLayer {
+            cookie_name: cookie_name.into(),
+            ..self
+        }
+    }
+
+    /// Set the name of the form field with the token, [`DEFAULT_FIELD_NAME`] by default.
+    pub fn field_name(self, field_name: impl Into<String>) -> Self {
+        CsrfLayer {
+            field_name: field_name.into(),
+            ..self
+        }
+    }
+
+    /// Set whether the cookie with the token is only sent over HTTPS, `true` by default.
+    pub fn secure(self, secure: bool) -> Self {
+        CsrfLayer { secure, ..self }
+    }
+
+    /// The token expected from the request, and whether it must be set in a new cookie.
+    fn expected(&self, req: &Request) -> Option<(String, bool)> {
+        match &self.storage {
+            Storage::Cookie(key) => {
+                let session_id = self.session_id.as_ref().and_then(|session_id| session_id(req));
+                let session_id = session_id.as_deref().unwrap_or_default();
+                let cookie = session::request_cookie(req.headers(), &self.cookie_name);
+                match cookie.filter(|token| verify(key, session_id, token)) {
+                    Some(token) => Some((token.to_string(), false)),
+                    None => Some((sign(key, session_id), true)),
+                }
+            }
+            Storage::Session => {
+                let session = req.extensions().get::<Session>()?;
+                let token = session.get::<String>(SESSION_KEY).unwrap_or_else(|| {
+                    let token = session::random_token();
+                    session.insert(SESSION_KEY, &token).expect("strings are JSON values");
+                    token
+                });
+                Some((token, false))
+            }
+        }
+    }
+
+    /// The token sent with the request, in the header or in the form.
+    fn submitted<'a>(&self, req: &'a Request) -> Option<std::borrow::Cow<'a, str>> {
+        if let Some(token) = req.headers().get(X_CSRF_TOKEN).and_then(|value| value.to_str().ok()) {
+            return Some(token.into());
+        }
+        let is_form = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| value.starts_with("application/x-www-form-urlencoded"));
+        if !is_form {
+            return None;
+        }
+        url::form_urlencoded::parse(req.body().as_ref())
+            .find(|(name, _)| *name == self.field_name)
+            .map(|(_, value)| value)
+    }
+
+    fn set_cookie(&self, token: &str) -> HeaderValue {
+        let mut cookie = format!("{}={token}; Path=/; SameSite=Strict", self.cookie_name);
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        HeaderValue::try_from(cookie).expect("CSRF cookies are valid header values")
+    }
+}
+
+/// A random token followed by its signature, bound to `session_id`.
+fn sign(key: &SessionKey, session_id: &str) -> String {
+    let token = session::random_token();
+    let tag = key
+        .tag(&[b"csrf", session_id.as_bytes(), token.as_bytes()])
+        .finalize()
+        .into_bytes();
+    format!("{token}.{}", URL_SAFE_NO_PAD.encode(tag))
+}
+
+fn verify(key: &SessionKey, session_id: &str, signed: &str) -> bool {
+    let (token, tag) = match signed.split_once('.') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    match URL_SAFE_NO_PAD.decode(tag) {
+        Ok(tag) => key
+            .tag(&[b"csrf", session_id.as_bytes(), token.as_bytes()])
+            .verify_slice(&tag)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE)
+}
+
+fn forbidden() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from("Forbidden: invalid CSRF token"))
+        .expect("unable to build http::Response")
+}
+
+impl fmt::Debug for CsrfLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let storage = match self.storage {
+            Storage::Cookie(_) => "double_submit",
+            Storage::Session => "session",
+        };
+        f.debug_struct("CsrfLayer")
+            .field("storage", &storage)
+            .field("exempt", &self.exempt.len())
+            .field("session_id", &self.session_id.is_some())
+            .field("cookie_name", &self.cookie_name)
+            .field("field_name", &self.field_name)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for CsrfLayer {
+    type Service = CsrfService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CsrfService {
+            inner,
+            config: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that rejects the requests with unsafe methods without a valid CSRF token.
+///
+/// See [`CsrfLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct CsrfService<S> {
+    inner: S,
+    config: CsrfLayer,
+}
+
+impl<S> Service<Request> for CsrfService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let exempt = self.config.exempt.iter().any(|exempt| exempt(&req));
+        let expected = self.config.expected(&req);
+        if !exempt && !is_safe(req.method()) {
+            let valid = match (&expected, self.config.submitted(&req)) {
+                (Some((expected, false)), Some(submitted)) => expected.as_bytes().ct_eq(submitted.as_bytes()).into(),
+                _ => false,
+            };
+            if !valid {
+                return Box::pin(async { Ok(forbidden()) });
+            }
+        }
+
+        let mut set_cookie = None;
+        if let Some((token, new)) = expected {
+            if new {
+                set_cookie = Some(self.config.set_cookie(&token));
+            }
+            req.extensions_mut().insert(CsrfToken(token));
+        }
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            let mut response = response.await;
+            if let Some(cookie) = set_cookie {
+                response.headers_mut().append(SET_COOKIE, cookie);
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::{SessionLayer, MIN_SECRET_LENGTH};
+    use lambda_runtime::{service_fn, Error};
+
+    fn key() -> SessionKey {
+        SessionKey::new(&[3; MIN_SECRET_LENGTH]).unwrap()
+    }
+
+    async fn token(req: Request) -> Result<String, Error> {
+        Ok(req.extensions().get::<CsrfToken>().unwrap().as_str().to_string())
+    }
+
+    fn request(method: &str, headers: &[(&str, &str)], body: &str) -> Request {
+        let mut builder = http::Request::builder().method(method).uri("/orders");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::from(body)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn checks_double_submitted_tokens() {
+        let mut service = CsrfLayer::double_submit(key()).layer(service_fn(token));
+
+        let res = service.call(request("GET", &[], "")).await.unwrap();
+        let token = std::str::from_utf8(res.body()).unwrap().to_string();
+        let set_cookie = res.headers()[SET_COOKIE].to_str().unwrap();
+        assert_eq!(format!("csrf={token}; Path=/; SameSite=Strict; Secure"), set_cookie);
+        let cookie = format!("csrf={token}");
+
+        let res = service
+            .call(request("POST", &[("cookie", &cookie), ("x-csrf-token", &token)], ""))
+            .await
+            .unwrap();
+        assert_eq!(200, res.status().as_u16());
+        assert!(res.headers().get(SET_COOKIE).is_none());
+
+        let form = format!("item=1&csrf_token={token}");
+        let headers = [
+            ("cookie", cookie.as_str()),
+            ("content-type", "application/x-www-form-urlencoded"),
+        ];
+        let res = service.call(request("POST", &headers, &form)).await.unwrap();
+        assert_eq!(200, res.status().as_u16());
+
+        let res = service.call(request("POST", &[("cookie", &cookie)], "")).await.unwrap();
+        assert_eq!(403, res.status().as_u16());
+
+        // Tokens that the layer didn't sign are rejected, even when they match.
+        let forged = [("cookie", "csrf=abc.def"), ("x-csrf-token", "abc.def")];
+        let res = service.call(request("DELETE", &forged, "")).await.unwrap();
+        assert_eq!(403, res.status().as_u16());
+    }
+
+    #[tokio::test]
+    async fn binds_double_submitted_tokens_to_sessions() {
+        let session_id = |req: &Request| {
+            req.headers()
+                .get("x-user")
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        let mut service = CsrfLayer::double_submit(key())
+            .session_id(session_id)
+            .layer(service_fn(token));
+
+        let res = service.call(request("GET", &[("x-user", "alice")], "")).await.unwrap();
+        let token = std::str::from_utf8(res.body()).unwrap().to_string();
+        let cookie = format!("csrf={token}");
+        let headers = [("cookie", cookie.as_str()), ("x-csrf-token", token.as_str())];
+
+        let res = service
+            .call(request("POST", &[headers[0], headers[1], ("x-user", "alice")], ""))
+            .await
+            .unwrap();
+        assert_eq!(200, res.status().as_u16());
+
+        // The token of a session isn't valid in another one, nor without one.
+        let res = service
+            .call(request("POST", &[headers[0], headers[1], ("x-user", "mallory")], ""))
+            .await
+            .unwrap();
+        assert_eq!(403, res.status().as_u16());
+        let res = service.call(request("POST", &headers, "")).await.unwrap();
+        assert_eq!(403, res.status().as_u16());
+
+        // A new token is issued when the session changes.
+        let res = service
+            .call(request("GET", &[headers[0], ("x-user", "bob")], ""))
+            .await
+            .unwrap();
+        assert!(res.headers().get(SET_COOKIE).is_some());
+        assert_ne!(token, std::str::from_utf8(res.body()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn exempts_token_authenticated_requests() {
+        let mut service = CsrfLayer::double_submit(key())
+            .exempt(bearer_token)
+            .exempt_prefix("/webhooks/")
+            .layer(service_fn(token));
+
+        let res = service
+            .call(request("POST", &[("authorization", "Bearer abc")], ""))
+            .await
+            .unwrap();
+        assert_eq!(200, res.status().as_u16());
+
+        let mut req = request("POST", &[], "");
+        *req.uri_mut() = "/webhooks/stripe".parse().unwrap();
+        assert_eq!(200, service.call(req).await.unwrap().status().as_u16());
+
+        let res = service
+            .call(request("POST", &[("authorization", "Basic abc")], ""))
+            .await
+            .unwrap();
+        assert_eq!(403, res.status().as_u16());
+    }
+
+    #[tokio::test]
+    async fn keeps_tokens_in_sessions() {
+        let mut service = SessionLayer::signed(key()).layer(CsrfLayer::session().layer(service_fn(token)));
+
+        let res = service.call(request("GET", &[], "")).await.unwrap();
+        let token = std::str::from_utf8(res.body()).unwrap().to_string();
+        let session = res.headers()[SET_COOKIE].to_str().unwrap().split(';').next().unwrap();
+
+        let res = service
+            .call(request("POST", &[("cookie", session), ("x-csrf-token", &token)], ""))
+            .await
+            .unwrap();
+        assert_eq!(200, res.status().as_u16());
+
+        let res = service
+            .call(request("POST", &[("x-csrf-token", &token)], ""))
+            .await
+            .unwrap();
+        assert_eq!(403, res.status().as_u16());
+
+        let mut without_session = CsrfLayer::session().layer(service_fn(|_: Request| async { Ok::<_, Error>("") }));
+        let res = without_session
+            .call(request("POST", &[("x-csrf-token", &token)], ""))
+            .await
+            .unwrap();
+        assert_eq!(403, res.status().as_u16());
+    }
+}
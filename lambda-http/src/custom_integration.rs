@@ -0,0 +1,225 @@
+//! Support for the Lambda custom (non-proxy) integrations of API Gateway REST APIs.
+//!
+//! With a custom integration, API Gateway sends the event produced by the
+//! mapping template of the integration request, and maps the result of the
+//! function with the templates of the integration responses. This module
+//! supports the "Method Request passthrough" template offered by the console,
+//! which sends an [`ApiGatewayPassthroughRequest`]: handlers written for proxy
+//! integrations keep working, and receive a [`Request`] with the method, path,
+//! query string, headers, path parameters and stage variables of the method
+//! request.
+//!
+//! The body of the response is returned as the JSON result of the function.
+//! API Gateway can't see the status of a successful result, so responses with an
+//! error status are reported as invocation errors with an `errorMessage` that
+//! starts with the status, like `[404] not found`. Configure an integration
+//! response for each status with the [`selection_pattern`] of the status.
+//! Errors of the handler are reported as they are, and use the default
+//! integration response.
+//!
+//! Start the runtime with [`run_custom_integration`](crate::run_custom_integration).
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{custom_integration::selection_pattern, http::StatusCode, service_fn, Error, Request, RequestExt};
+//! use lambda_http::{Body, Response};
+//!
+//! async fn get_pet(req: Request) -> Result<Response<Body>, Error> {
+//!     let res = match req.path_parameters().first("petId") {
+//!         Some("42") => Response::new(r#"{"name":"Rex"}"#.into()),
+//!         _ => Response::builder().status(404).body("no such pet".into())?,
+//!     };
+//!     Ok(res)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     // The integration response of 404 uses this pattern.
+//!     assert_eq!(r"\[404\].*", selection_pattern(StatusCode::NOT_FOUND));
+//!     lambda_http::run_custom_integration(service_fn(get_pet)).await
+//! }
+//! ```
+use crate::{
+    ext::extensions::{PathParameters, QueryStringParameters, RawHttpPath, StageVariables},
+    ext::RequestExt,
+    request::apigw_path_with_stage,
+    Body, Error, IntoResponse, Request,
+};
+pub use aws_lambda_events::apigw::ApiGatewayPassthroughRequest;
+use aws_lambda_events::query_map::QueryMap;
+use base64::Engine;
+use futures::future::BoxFuture;
+use http::{header::HOST, HeaderName, HeaderValue, Method, StatusCode};
+use lambda_runtime::{Diagnostic, LambdaEvent, Service};
+use serde_json::Value;
+use std::task::{Context as TaskContext, Poll};
+
+/// `errorType` of the errors reported for the responses with an error status.
+pub const HTTP_ERROR_TYPE: &str = "HttpError";
+
+/// Return the selection pattern of the integration response of `status`.
+///
+/// The pattern matches the `errorMessage` of the errors reported for the
+/// responses with this status.
+pub fn selection_pattern(status: StatusCode) -> String {
+    format!(r"\[{}\].*", status.as_u16())
+}
+
+/// Convert the event of the passthrough template into a [`Request`].
+///
+/// The path of the request is the resource path of the method, with the values
+/// of its path parameters.
+pub fn into_request(event: ApiGatewayPassthroughRequest) -> Request {
+    let context = event.context;
+    let params = event.params;
+
+    let mut raw_path = context.resource_path.unwrap_or_else(|| "/".into());
+    for (name, value) in &params.path {
+        raw_path = raw_path
+            .replace(&format!("{{{name}+}}"), value)
+            .replace(&format!("{{{name}}}"), value);
+    }
+    let stage = context.stage.filter(|stage| !stage.is_empty());
+    let path = apigw_path_with_stage(&stage, &raw_path);
+    let query = QueryMap::from(params.querystring);
+    let host = params
+        .header
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(HOST.as_str()))
+        .map(|(_, host)| host.clone());
+    let mut uri = match host {
+        Some(host) => format!("https://{host}{path}"),
+        None => path,
+    };
+    if !query.is_empty() {
+        uri.push('?');
+        uri.push_str(&query.to_query_string());
+    }
+
+    let body = match event.body_json {
+        Value::Null => Body::Empty,
+        Value::String(text) => Body::Text(text),
+        json => Body::Text(json.to_string()),
+    };
+    let mut req = http::Request::builder()
+        .method(context.http_method.unwrap_or(Method::GET))
+        .uri(uri)
+        .extension(RawHttpPath(raw_path))
+        .extension(QueryStringParameters(query))
+        .extension(PathParameters(QueryMap::from(params.path)))
+        .extension(StageVariables(QueryMap::from(event.stage_variables)))
+        .body(body)
+        .expect("failed to build request");
+    let headers = req.headers_mut();
+    for (name, value) in params.header {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value)) {
+            headers.append(name, value);
+        }
+    }
+    req
+}
+
+/// Wraps a `Service<Request>` in a service that answers the events of custom integrations.
+///
+/// This is completely internal to the `lambda_http::run_custom_integration` function.
+#[doc(hidden)]
+pub struct CustomIntegrationAdapter<S> {
+    service: S,
+}
+
+impl<S> CustomIntegrationAdapter<S> {
+    pub(crate) fn new(service: S) -> Self {
+        CustomIntegrationAdapter { service }
+    }
+}
+
+impl<S> Service<LambdaEvent<ApiGatewayPassthroughRequest>> for CustomIntegrationAdapter<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Into<Error>,
+{
+    type Response = Value;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Value, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, event: LambdaEvent<ApiGatewayPassthroughRequest>) -> Self::Future {
+        let (payload, context) = event.into_parts();
+        let fut = self.service.call(into_request(payload).with_lambda_context(context));
+
+        Box::pin(async move {
+            let response = fut.await.map_err(Into::into)?.into_response();
+            let response = response.await;
+            let status = response.status();
+            let body = match response.into_body() {
+                Body::Empty => Value::Null,
+                Body::Text(text) => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+                Body::Binary(bytes) => Value::String(base64::engine::general_purpose::STANDARD.encode(bytes)),
+            };
+            if status.is_client_error() || status.is_server_error() {
+                let message = match body {
+                    Value::String(text) => text,
+                    Value::Null => status.canonical_reason().unwrap_or_default().to_string(),
+                    json => json.to_string(),
+                };
+                let message = format!("[{}] {message}", status.as_u16());
+                return Err(Diagnostic::new(HTTP_ERROR_TYPE, message).into());
+            }
+            Ok(body)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_runtime::{service_fn, Context};
+    use serde_json::json;
+
+    fn event() -> LambdaEvent<ApiGatewayPassthroughRequest> {
+        let data = include_bytes!("../tests/data/apigw_passthrough_request.json");
+        LambdaEvent::new(serde_json::from_slice(data).unwrap(), Context::default())
+    }
+
+    #[test]
+    fn converts_passthrough_events() {
+        let req = into_request(event().payload);
+        assert_eq!(Method::POST, req.method());
+        assert_eq!(
+            "https://abcdef1234.execute-api.us-east-1.amazonaws.com/prod/pets/42?notify=true",
+            req.uri()
+        );
+        assert_eq!("/pets/42", req.raw_http_path());
+        assert_eq!(Some("42"), req.path_parameters().first("petId"));
+        assert_eq!(Some("pets-prod"), req.stage_variables().first("table"));
+        assert_eq!("curl/8.4.0", req.headers()["user-agent"]);
+        assert_eq!(&Body::Text(r#"{"name":"Rex","type":"dog"}"#.into()), req.body());
+    }
+
+    #[tokio::test]
+    async fn reports_error_statuses_as_errors() {
+        let mut adapter = CustomIntegrationAdapter::new(service_fn(|req: Request| async move {
+            let res = match req.path_parameters().first("petId") {
+                Some("42") => http::Response::new(Body::from(r#"{"name":"Rex"}"#)),
+                _ => http::Response::builder().status(404).body(Body::from("no such pet"))?,
+            };
+            Ok::<_, Error>(res)
+        }));
+
+        let res = adapter.call(event()).await.unwrap();
+        assert_eq!(json!({ "name": "Rex" }), res);
+
+        let mut event = event();
+        event.payload.params.path.insert("petId".into(), "7".into());
+        let err = adapter.call(event).await.unwrap_err();
+        let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+        assert_eq!(HTTP_ERROR_TYPE, diagnostic.error_type());
+        assert_eq!("[404] no such pet", diagnostic.error_message());
+        assert_eq!(r"\[404\].*", selection_pattern(StatusCode::NOT_FOUND));
+    }
+}
@@ -2,9 +2,11 @@
 
 use aws_lambda_events::query_map::QueryMap;
 use http::request::Parts;
-use lambda_runtime::Context;
+use lambda_runtime::{ClientContext, CognitoIdentity, Context};
+use std::net::IpAddr;
 
-use crate::request::RequestContext;
+use super::links;
+use crate::{forwarded, request::RequestContext};
 
 /// ALB/API gateway pre-parsed http query string parameters
 pub(crate) struct QueryStringParameters(pub(crate) QueryMap);
@@ -23,6 +25,12 @@ pub(crate) struct StageVariables(pub(crate) QueryMap);
 /// ALB/API gateway raw http path without any stage information
 pub(crate) struct RawHttpPath(pub(crate) String);
 
+/// Correlation id of the request, set by [`crate::request_id::RequestIdLayer`]
+pub(crate) struct CorrelationId(pub(crate) String);
+
+/// Path of the request before [`crate::path::PathLayer`] normalized it
+pub(crate) struct OriginalPath(pub(crate) String);
+
 /// Extensions for [`lambda_http::Request`], `http::request::Parts`, and `http::Extensions` structs
 /// that provide access to
 /// [API gateway](https://docs.aws.amazon.com/apigateway/latest/developerguide/set-up-lambda-proxy-integrations.html#api-gateway-simple-proxy-for-lambda-input-format)
@@ -139,6 +147,53 @@ pub trait RequestExt {
 
     /// Configures instance with lambda context
     fn with_lambda_context(self, context: Context) -> Self;
+
+    /// Return a reference to the client context sent by the AWS Mobile SDK,
+    /// if the function was invoked by a mobile application.
+    fn client_context_ref(&self) -> Option<&ClientContext>;
+
+    /// Return the Cognito identity that made the request.
+    ///
+    /// The identity is taken from the Lambda function context when it's present,
+    /// or from the API Gateway request context when the request was signed with
+    /// credentials issued by an Amazon Cognito Identity Pool.
+    fn cognito_identity(&self) -> Option<CognitoIdentity>;
+
+    /// Return the id that correlates the request with the logs of its caller.
+    ///
+    /// This is the `x-request-id` header sent by the client when the request went
+    /// through a [`RequestIdLayer`](crate::request_id::RequestIdLayer), and the
+    /// Lambda request id otherwise.
+    fn correlation_id(&self) -> Option<&str>;
+
+    /// Return the path of the URI of the request before a
+    /// [`PathLayer`](crate::path::PathLayer) normalized it.
+    fn original_path(&self) -> Option<&str>;
+
+    /// Return the URL under which clients reach the API, with the scheme, the
+    /// host, and the prefix of the path that API Gateway removed before it
+    /// matched a route, like the stage or the base path of a custom domain.
+    ///
+    /// The `X-Forwarded-Proto` and `X-Forwarded-Port` headers set by the
+    /// outermost trusted proxy take precedence, and so do `X-Forwarded-Host`
+    /// and `X-Forwarded-Prefix` when the
+    /// [`ForwardedTrust`](crate::forwarded::ForwardedTrust) of the request
+    /// trusts proxies in front of the trigger. `http::Extensions` don't have the headers of the request,
+    /// so their base URL only uses the domain name of the request context.
+    fn base_url(&self) -> Option<String>;
+
+    /// Return the absolute URL of `path`, relative to the [`base_url`](RequestExt::base_url),
+    /// for redirects and links.
+    fn url_for(&self, path: &str) -> Option<String> {
+        self.base_url().map(|base| links::join(base, path))
+    }
+
+    /// Return the IP address of the client, as seen by the proxies trusted
+    /// by the [`ForwardedTrust`](crate::forwarded::ForwardedTrust) of the request.
+    ///
+    /// `http::Extensions` don't have the headers of the request, so they only
+    /// return the source IP reported by API Gateway.
+    fn client_ip(&self) -> Option<IpAddr>;
 }
 
 impl RequestExt for http::Extensions {
@@ -257,6 +312,62 @@ impl RequestExt for http::Extensions {
         s.insert(context);
         s
     }
+
+    fn client_context_ref(&self) -> Option<&ClientContext> {
+        self.lambda_context_ref().and_then(|ctx| ctx.client_context.as_ref())
+    }
+
+    fn cognito_identity(&self) -> Option<CognitoIdentity> {
+        if let Some(identity) = self.lambda_context_ref().and_then(|ctx| ctx.identity.as_ref()) {
+            return Some(identity.clone());
+        }
+
+        let (identity_id, identity_pool_id) = match self.request_context_ref()? {
+            #[cfg(feature = "apigw_rest")]
+            RequestContext::ApiGatewayV1(ctx) => (
+                ctx.identity.cognito_identity_id.as_ref(),
+                ctx.identity.cognito_identity_pool_id.as_ref(),
+            ),
+            #[cfg(feature = "apigw_http")]
+            RequestContext::ApiGatewayV2(ctx) => {
+                let identity = ctx.authorizer.as_ref()?.iam.as_ref()?.cognito_identity.as_ref()?;
+                (identity.identity_id.as_ref(), identity.identity_pool_id.as_ref())
+            }
+            #[cfg(feature = "alb")]
+            RequestContext::Alb(_) => return None,
+            #[cfg(feature = "apigw_websockets")]
+            RequestContext::WebSocket(ctx) => (
+                ctx.identity.cognito_identity_id.as_ref(),
+                ctx.identity.cognito_identity_pool_id.as_ref(),
+            ),
+            #[cfg(feature = "vpc_lattice")]
+            RequestContext::VpcLattice(_) => return None,
+        };
+
+        Some(CognitoIdentity {
+            identity_id: identity_id?.clone(),
+            identity_pool_id: identity_pool_id?.clone(),
+        })
+    }
+
+    fn correlation_id(&self) -> Option<&str> {
+        match self.get::<CorrelationId>() {
+            Some(CorrelationId(id)) => Some(id.as_str()),
+            None => self.lambda_context_ref().map(|ctx| ctx.request_id.as_str()),
+        }
+    }
+
+    fn original_path(&self) -> Option<&str> {
+        self.get::<OriginalPath>().map(|OriginalPath(path)| path.as_str())
+    }
+
+    fn base_url(&self) -> Option<String> {
+        links::base_url(None, None, self)
+    }
+
+    fn client_ip(&self) -> Option<IpAddr> {
+        forwarded::trust(self).client_ip_of(None, self)
+    }
 }
 
 impl RequestExt for Parts {
@@ -358,6 +469,30 @@ impl RequestExt for Parts {
 
         s
     }
+
+    fn client_context_ref(&self) -> Option<&ClientContext> {
+        self.extensions.client_context_ref()
+    }
+
+    fn cognito_identity(&self) -> Option<CognitoIdentity> {
+        self.extensions.cognito_identity()
+    }
+
+    fn correlation_id(&self) -> Option<&str> {
+        self.extensions.correlation_id()
+    }
+
+    fn original_path(&self) -> Option<&str> {
+        self.extensions.original_path()
+    }
+
+    fn base_url(&self) -> Option<String> {
+        links::base_url(Some(&self.headers), Some(&self.uri), &self.extensions)
+    }
+
+    fn client_ip(&self) -> Option<IpAddr> {
+        forwarded::trust(&self.extensions).client_ip_of(Some(&self.headers), &self.extensions)
+    }
 }
 
 fn map_req_ext<B, F>(req: http::Request<B>, f: F) -> http::Request<B>
@@ -451,12 +586,37 @@ impl<B> RequestExt for http::Request<B> {
     fn with_lambda_context(self, context: Context) -> Self {
         map_req_ext(self, |ext| ext.with_lambda_context(context))
     }
+
+    fn client_context_ref(&self) -> Option<&ClientContext> {
+        self.extensions().client_context_ref()
+    }
+
+    fn cognito_identity(&self) -> Option<CognitoIdentity> {
+        self.extensions().cognito_identity()
+    }
+
+    fn correlation_id(&self) -> Option<&str> {
+        self.extensions().correlation_id()
+    }
+
+    fn original_path(&self) -> Option<&str> {
+        self.extensions().original_path()
+    }
+
+    fn base_url(&self) -> Option<String> {
+        links::base_url(Some(self.headers()), Some(self.uri()), self.extensions())
+    }
+
+    fn client_ip(&self) -> Option<IpAddr> {
+        forwarded::trust(self.extensions()).client_ip_of(Some(self.headers()), self.extensions())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use aws_lambda_events::query_map::QueryMap;
     use http::Extensions;
+    use lambda_runtime::{ClientContext, Context};
 
     use crate::Request;
 
@@ -624,4 +784,39 @@ mod tests {
         let request = Request::default().with_raw_http_path("/raw-path");
         assert_eq!("/raw-path", request.raw_http_path());
     }
+
+    #[test]
+    fn requests_have_client_context_from_lambda_context() {
+        let client_context: ClientContext =
+            serde_json::from_str(r#"{"client":{"app_title":"Demo"},"env":{"platform":"Android"}}"#).unwrap();
+        let mut ctx = Context::default();
+        ctx.client_context = Some(client_context);
+
+        let request = Request::default();
+        assert_eq!(None, request.client_context_ref());
+
+        let request = request.with_lambda_context(ctx);
+        let client_context = request.client_context_ref().unwrap();
+        assert_eq!("Demo", client_context.client.app_title);
+        assert_eq!(Some("Android"), client_context.platform());
+    }
+
+    #[test]
+    #[cfg(feature = "apigw_rest")]
+    fn requests_have_cognito_identity_from_request_context() {
+        use crate::request::RequestContext;
+        use aws_lambda_events::apigw::ApiGatewayProxyRequestContext;
+
+        let mut apigw_ctx = ApiGatewayProxyRequestContext::default();
+        apigw_ctx.identity.cognito_identity_id = Some("us-east-1:1234".into());
+        apigw_ctx.identity.cognito_identity_pool_id = Some("us-east-1:pool".into());
+
+        let request = Request::default();
+        assert_eq!(None, request.cognito_identity());
+
+        let request = request.with_request_context(RequestContext::ApiGatewayV1(apigw_ctx));
+        let identity = request.cognito_identity().unwrap();
+        assert_eq!("us-east-1:1234", identity.identity_id);
+        assert_eq!("us-east-1:pool", identity.identity_pool_id);
+    }
 }
@@ -0,0 +1,185 @@
+//! Reconstruction of the URL under which clients reach the function.
+
+use http::{header::HOST, HeaderMap, Uri};
+
+use super::RequestExt;
+use crate::{forwarded, request::RequestContext};
+
+/// The first value of a forwarded header, set by the outermost proxy.
+fn forwarded<'a>(headers: Option<&'a HeaderMap>, name: &str) -> Option<&'a str> {
+    headers?
+        .get(name)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .map(str::trim)
+        .find(|value| !value.is_empty())
+}
+
+fn is_execute_api(host: &str) -> bool {
+    host.split(':')
+        .next()
+        .map_or(false, |host| host.ends_with(".amazonaws.com"))
+}
+
+/// The prefix that API Gateway removed from the path before it matched a route:
+/// the stage on the default endpoint, or the base path of a custom domain.
+fn path_prefix(extensions: &http::Extensions, host: &str) -> String {
+    let prefix = match extensions.request_context_ref() {
+        #[cfg(feature = "apigw_rest")]
+        Some(RequestContext::ApiGatewayV1(ctx)) => {
+            // The path of the context is the path sent by the client.
+            let raw = extensions.raw_http_path();
+            match ctx.path.as_deref() {
+                Some(path) if !raw.is_empty() && path.ends_with(raw) => path[..path.len() - raw.len()].to_string(),
+                _ if is_execute_api(host) => ctx
+                    .stage
+                    .as_deref()
+                    .map(|stage| format!("/{stage}"))
+                    .unwrap_or_default(),
+                _ => String::new(),
+            }
+        }
+        #[cfg(feature = "apigw_http")]
+        Some(RequestContext::ApiGatewayV2(ctx)) if is_execute_api(host) => match ctx.stage.as_deref() {
+            Some("$default") | None => String::new(),
+            Some(stage) => format!("/{stage}"),
+        },
+        _ => String::new(),
+    };
+    prefix.trim_end_matches('/').to_string()
+}
+
+fn domain_name(extensions: &http::Extensions) -> Option<&str> {
+    match extensions.request_context_ref() {
+        #[cfg(feature = "apigw_rest")]
+        Some(RequestContext::ApiGatewayV1(ctx)) => ctx.domain_name.as_deref(),
+        #[cfg(feature = "apigw_http")]
+        Some(RequestContext::ApiGatewayV2(ctx)) => ctx.domain_name.as_deref(),
+        #[cfg(feature = "apigw_websockets")]
+        Some(RequestContext::WebSocket(ctx)) => ctx.domain_name.as_deref(),
+        _ => None,
+    }
+}
+
+pub(crate) fn base_url(
+    headers: Option<&HeaderMap>,
+    uri: Option<&Uri>,
+    extensions: &http::Extensions,
+) -> Option<String> {
+    let trust = forwarded::trust(extensions);
+    // Only the proxies in front of the trigger set the host and the prefix.
+    let forwarded = |name| forwarded(headers, name).filter(|_| trust.trusts_proxies());
+    let scheme = trust.proto_of(headers).or_else(|| uri?.scheme_str()).unwrap_or("https");
+    let host = forwarded("x-forwarded-host")
+        .or_else(|| headers?.get(HOST)?.to_str().ok())
+        .or_else(|| domain_name(extensions))
+        .or_else(|| Some(uri?.authority()?.as_str()))?;
+
+    let mut url = format!("{scheme}://{host}");
+    let default_port = if scheme == "http" { "80" } else { "443" };
+    if let Some(port) = trust.port_of(headers) {
+        if !host.contains(':') && port != default_port {
+            url.push(':');
+            url.push_str(port);
+        }
+    }
+    match forwarded("x-forwarded-prefix") {
+        Some(prefix) => url.push_str(prefix.trim_end_matches('/')),
+        None => url.push_str(&path_prefix(extensions, host)),
+    }
+    Some(url)
+}
+
+pub(crate) fn join(base: String, path: &str) -> String {
+    let mut url = base;
+    url.push('/');
+    url.push_str(path.trim_start_matches('/'));
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{forwarded::ForwardedTrust, Body};
+    use std::sync::Arc;
+
+    fn request(uri: &str, headers: &[(&str, &str)]) -> http::Request<Body> {
+        let mut builder = http::Request::builder().uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::Empty).unwrap()
+    }
+
+    #[test]
+    fn uses_the_forwarded_headers_of_trusted_proxies() {
+        let mut req = request(
+            "https://internal.example.com/orders",
+            &[
+                ("host", "internal.example.com"),
+                ("x-forwarded-proto", "http, https"),
+                ("x-forwarded-host", "api.example.com"),
+                ("x-forwarded-port", "8080"),
+                ("x-forwarded-prefix", "/shop/"),
+            ],
+        );
+        assert_eq!(Some("https://internal.example.com:8080".to_string()), req.base_url());
+
+        let trust = ForwardedTrust::new().hops(1);
+        req.extensions_mut().insert(forwarded::Trust(Arc::new(trust)));
+        assert_eq!(Some("http://api.example.com:8080/shop".to_string()), req.base_url());
+        assert_eq!(
+            Some("http://api.example.com:8080/shop/orders/42".to_string()),
+            req.url_for("/orders/42")
+        );
+    }
+
+    #[cfg(feature = "apigw_rest")]
+    #[test]
+    fn adds_the_stage_or_the_base_path_of_rest_apis() {
+        let input = include_str!("../../tests/data/apigw_proxy_request.json");
+        let mut req = crate::request::from_str(input).unwrap();
+        let ctx = match req.request_context_ref() {
+            Some(RequestContext::ApiGatewayV1(ctx)) => ctx,
+            _ => panic!("not a REST API request"),
+        };
+        let stage = ctx.stage.clone().unwrap();
+        let mut ctx = ctx.clone();
+        let host = req.uri().host().unwrap().to_string();
+
+        ctx.path = Some(format!("/{stage}{}", req.raw_http_path()));
+        req.extensions_mut().insert(RequestContext::ApiGatewayV1(ctx.clone()));
+        assert_eq!(Some(format!("https://{host}/{stage}")), req.base_url());
+
+        ctx.path = Some(format!("/v1{}", req.raw_http_path()));
+        req.extensions_mut().insert(RequestContext::ApiGatewayV1(ctx));
+        assert_eq!(Some(format!("https://{host}/v1/next")), req.url_for("next"));
+    }
+
+    #[cfg(feature = "apigw_http")]
+    #[test]
+    fn adds_the_stage_of_http_apis_on_the_default_endpoint() {
+        use aws_lambda_events::apigw::ApiGatewayV2httpRequestContext;
+
+        let context = |stage: &str| {
+            RequestContext::ApiGatewayV2(ApiGatewayV2httpRequestContext {
+                stage: Some(stage.into()),
+                domain_name: Some("id.execute-api.us-east-1.amazonaws.com".into()),
+                ..Default::default()
+            })
+        };
+        let req = request("/orders", &[]).with_request_context(context("beta"));
+        assert_eq!(
+            Some("https://id.execute-api.us-east-1.amazonaws.com/beta".to_string()),
+            req.base_url()
+        );
+        let req = request("/orders", &[]).with_request_context(context("$default"));
+        assert_eq!(
+            Some("https://id.execute-api.us-east-1.amazonaws.com".to_string()),
+            req.base_url()
+        );
+        let req = request("/orders", &[("host", "api.example.com")]).with_request_context(context("beta"));
+        assert_eq!(Some("https://api.example.com".to_string()), req.base_url());
+    }
+}
@@ -1,6 +1,7 @@
 //! Extension methods for `Request` types
 
 pub mod extensions;
+mod links;
 pub mod request;
 
 pub use extensions::RequestExt;
@@ -0,0 +1,394 @@
+//! Which `X-Forwarded-*` headers to trust.
+//!
+//! Clients can send any `X-Forwarded-For`, `X-Forwarded-Proto` or
+//! `X-Forwarded-Port` header, and the proxies on the way append their own
+//! values to them. Only the values added by proxies that the function trusts
+//! are reliable, and taking the first IP address of `X-Forwarded-For` lets any
+//! client choose the address that is logged, rate limited, or allowed.
+//!
+//! The trigger is always trusted, and [`ForwardedTrust`] tells how many proxies
+//! in front of it, like a CloudFront distribution, are trusted too, and which
+//! addresses belong to trusted proxies. The chain of addresses of a request is
+//! made of the `X-Forwarded-For` entries followed by the address of the peer
+//! of the trigger:
+//! - API Gateway and function URLs report the peer in the request context, as
+//!   the source IP.
+//! - Application Load Balancers and VPC Lattice append the peer to
+//!   `X-Forwarded-For`, so it's the last entry.
+//!
+//! The client IP is the first address of the chain, from the right, that is not
+//! a trusted proxy. By default, no proxy is trusted and the client IP is the
+//! peer of the trigger. The scheme and the port are the values of
+//! `X-Forwarded-Proto` and `X-Forwarded-Port` that were set by the outermost
+//! trusted proxy, and `X-Forwarded-Host` and `X-Forwarded-Prefix` are only
+//! honored when there are trusted [hops](ForwardedTrust::hops) in front of the
+//! trigger.
+//!
+//! [`ForwardedLayer`] sets the trust of the requests, which
+//! [`RequestExt::client_ip`], [`RequestExt::base_url`], the
+//! [`RequestLogLayer`](crate::RequestLogLayer) and the
+//! [`RateLimitLayer`](crate::rate_limit::RateLimitLayer) use.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{
+//!     forwarded::{ForwardedLayer, ForwardedTrust},
+//!     service_fn, tower::Layer, Error, Request, RequestExt,
+//! };
+//!
+//! async fn whoami(req: Request) -> Result<String, Error> {
+//!     Ok(format!("{:?}", req.client_ip()))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     // The API is behind a CloudFront distribution.
+//!     let trust = ForwardedTrust::new().hops(1);
+//!     lambda_http::run(ForwardedLayer::new(trust).layer(service_fn(whoami))).await
+//! }
+//! ```
+use crate::{ext::RequestExt, request::RequestContext, Request};
+use http::{HeaderMap, HeaderName};
+use lambda_runtime::{tower::Layer, Service};
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+};
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const X_FORWARDED_PORT: HeaderName = HeaderName::from_static("x-forwarded-port");
+
+/// A range of IP addresses, like `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    /// Whether `ip` is in the network.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, canonical(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = InvalidNetwork;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidNetwork(s.to_string());
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        let address = canonical(IpAddr::from_str(address).map_err(|_| invalid())?);
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(invalid)?,
+            None => max,
+        };
+        Ok(Network { address, prefix })
+    }
+}
+
+/// Error returned for networks that are not an IP address or a CIDR block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidNetwork(String);
+
+impl fmt::Display for InvalidNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid network `{}`, expected an IP address or a CIDR block",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidNetwork {}
+
+/// The proxies trusted to set the `X-Forwarded-*` headers.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardedTrust {
+    hops: usize,
+    proxies: Vec<Network>,
+}
+
+impl ForwardedTrust {
+    /// Trust the trigger only.
+    pub fn new() -> Self {
+        ForwardedTrust::default()
+    }
+
+    /// Trust the `hops` proxies closest to the trigger, like a CloudFront
+    /// distribution in front of API Gateway.
+    pub fn hops(self, hops: usize) -> Self {
+        ForwardedTrust { hops, ..self }
+    }
+
+    /// Trust the proxies with an address in `network`, an IP address or a CIDR block.
+    pub fn proxy(mut self, network: &str) -> Result<Self, InvalidNetwork> {
+        self.proxies.push(network.parse()?);
+        Ok(self)
+    }
+
+    fn is_trusted(&self, distance: usize, ip: IpAddr) -> bool {
+        distance < self.hops || self.proxies.iter().any(|network| network.contains(ip))
+    }
+
+    /// The IP address of the client that sent `req`.
+    pub fn client_ip(&self, req: &Request) -> Option<IpAddr> {
+        self.client_ip_of(Some(req.headers()), req.extensions())
+    }
+
+    pub(crate) fn client_ip_of(&self, headers: Option<&HeaderMap>, extensions: &http::Extensions) -> Option<IpAddr> {
+        let mut chain = values(headers, &X_FORWARDED_FOR);
+        if let Some(peer) = peer(extensions) {
+            if chain.last() != Some(&peer) {
+                chain.push(peer);
+            }
+        }
+        let mut client = None;
+        for (distance, ip) in chain.into_iter().rev().map(parse_ip).enumerate() {
+            // An entry that isn't an address can't be trusted.
+            let ip = ip?;
+            client = Some(ip);
+            if !self.is_trusted(distance, ip) {
+                break;
+            }
+        }
+        client
+    }
+
+    /// The value of a forwarded header set by the outermost trusted hop.
+    fn forwarded<'a>(&self, headers: Option<&'a HeaderMap>, name: &HeaderName) -> Option<&'a str> {
+        let values = values(headers, name);
+        let index = values.len().checked_sub(1)?.saturating_sub(self.hops);
+        Some(values[index])
+    }
+
+    /// The scheme used by the client, from `X-Forwarded-Proto`.
+    pub fn proto<'a>(&self, req: &'a Request) -> Option<&'a str> {
+        self.forwarded(Some(req.headers()), &X_FORWARDED_PROTO)
+    }
+
+    pub(crate) fn proto_of<'a>(&self, headers: Option<&'a HeaderMap>) -> Option<&'a str> {
+        self.forwarded(headers, &X_FORWARDED_PROTO)
+    }
+
+    pub(crate) fn port_of<'a>(&self, headers: Option<&'a HeaderMap>) -> Option<&'a str> {
+        self.forwarded(headers, &X_FORWARDED_PORT)
+    }
+
+    /// Whether headers set by proxies in front of the trigger, like
+    /// `X-Forwarded-Host`, are trusted.
+    pub(crate) fn trusts_proxies(&self) -> bool {
+        self.hops > 0
+    }
+}
+
+/// The values of a header, with the values joined by commas split apart.
+fn values<'a>(headers: Option<&'a HeaderMap>, name: &HeaderName) -> Vec<&'a str> {
+    let headers = match headers {
+        Some(headers) => headers,
+        None => return Vec::new(),
+    };
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// `ip`, with IPv4-mapped IPv6 addresses converted to IPv4.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, high, low] => IpAddr::V4(Ipv4Addr::from(u32::from(high) << 16 | u32::from(low))),
+            _ => ip,
+        },
+        IpAddr::V4(_) => ip,
+    }
+}
+
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    IpAddr::from_str(value)
+        .or_else(|_| SocketAddr::from_str(value).map(|addr| addr.ip()))
+        .ok()
+        .map(canonical)
+}
+
+/// The address of the peer of the trigger, for the triggers that report it.
+fn peer(extensions: &http::Extensions) -> Option<&str> {
+    match extensions.request_context_ref() {
+        #[cfg(feature = "apigw_rest")]
+        Some(RequestContext::ApiGatewayV1(ctx)) => ctx.identity.source_ip.as_deref(),
+        #[cfg(feature = "apigw_http")]
+        Some(RequestContext::ApiGatewayV2(ctx)) => ctx.http.source_ip.as_deref(),
+        #[cfg(feature = "apigw_websockets")]
+        Some(RequestContext::WebSocket(ctx)) => ctx.identity.source_ip.as_deref(),
+        _ => None,
+    }
+}
+
+/// The trust of a request, set by a [`ForwardedLayer`].
+#[derive(Clone)]
+pub(crate) struct Trust(pub(crate) Arc<ForwardedTrust>);
+
+/// The trust of the request, or the default one.
+pub(crate) fn trust(extensions: &http::Extensions) -> &ForwardedTrust {
+    static DEFAULT: ForwardedTrust = ForwardedTrust {
+        hops: 0,
+        proxies: Vec::new(),
+    };
+    extensions
+        .get::<Trust>()
+        .map(|Trust(trust)| trust.as_ref())
+        .unwrap_or(&DEFAULT)
+}
+
+/// A [`Layer`] that sets the [`ForwardedTrust`] of requests.
+#[derive(Debug, Clone)]
+pub struct ForwardedLayer {
+    trust: Arc<ForwardedTrust>,
+}
+
+impl ForwardedLayer {
+    /// Create a layer that applies `trust` to requests.
+    pub fn new(trust: ForwardedTrust) -> Self {
+        ForwardedLayer { trust: Arc::new(trust) }
+    }
+}
+
+impl<S> Layer<S> for ForwardedLayer {
+    type Service = ForwardedService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ForwardedService {
+            inner,
+            trust: self.trust.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that sets the [`ForwardedTrust`] of requests.
+///
+/// See [`ForwardedLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct ForwardedService<S> {
+    inner: S,
+    trust: Arc<ForwardedTrust>,
+}
+
+impl<S> Service<Request> for ForwardedService<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        req.extensions_mut().insert(Trust(self.trust.clone()));
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+
+    fn request(forwarded_for: &str) -> Request {
+        http::Request::builder()
+            .header("x-forwarded-for", forwarded_for)
+            .header("x-forwarded-proto", "http, https")
+            .body(Body::Empty)
+            .unwrap()
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn matches_networks() {
+        let network: Network = "10.0.0.0/8".parse().unwrap();
+        assert!(network.contains("10.1.2.3".parse().unwrap()));
+        assert!(network.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!network.contains("11.0.0.1".parse().unwrap()));
+        let network: Network = "2001:db8::/32".parse().unwrap();
+        assert!(network.contains("2001:db8::1".parse().unwrap()));
+        assert!("0.0.0.0/0"
+            .parse::<Network>()
+            .unwrap()
+            .contains("1.2.3.4".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<Network>().is_err());
+        assert!("example.com".parse::<Network>().is_err());
+    }
+
+    #[test]
+    fn trusts_the_trigger_only_by_default() {
+        // Load balancers append the peer, the client sent the first entry.
+        let req = request("6.6.6.6, 203.0.113.7");
+        assert_eq!(ip("203.0.113.7"), req.client_ip());
+        assert_eq!(Some("https"), ForwardedTrust::new().proto(&req));
+    }
+
+    #[test]
+    fn skips_trusted_proxies() {
+        let req = request("6.6.6.6, 203.0.113.7, 10.0.0.2, 198.51.100.1:443");
+        let trust = ForwardedTrust::new().hops(1).proxy("10.0.0.0/8").unwrap();
+        assert_eq!(ip("203.0.113.7"), trust.client_ip(&req));
+        assert_eq!(Some("http"), trust.proto(&req));
+
+        let everything = ForwardedTrust::new().hops(10);
+        assert_eq!(ip("6.6.6.6"), everything.client_ip(&req));
+
+        let req = request("unknown, 10.0.0.2");
+        assert_eq!(None, ForwardedTrust::new().hops(1).client_ip(&req));
+    }
+
+    #[cfg(feature = "apigw_http")]
+    #[test]
+    fn uses_the_source_ip_of_api_gateway() {
+        use aws_lambda_events::apigw::ApiGatewayV2httpRequestContext;
+
+        let mut context = ApiGatewayV2httpRequestContext::default();
+        context.http.source_ip = Some("198.51.100.1".into());
+        let req = request("6.6.6.6").with_request_context(RequestContext::ApiGatewayV2(context));
+        assert_eq!(ip("198.51.100.1"), req.client_ip());
+
+        let mut req = req;
+        req.extensions_mut()
+            .insert(Trust(Arc::new(ForwardedTrust::new().proxy("198.51.100.0/24").unwrap())));
+        assert_eq!(ip("6.6.6.6"), req.client_ip());
+    }
+}
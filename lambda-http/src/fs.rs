@@ -0,0 +1,535 @@
+//! Serve static files and assets.
+//!
+//! [`ServeFiles`] answers `GET` and `HEAD` requests with files from a [`FileSource`],
+//! either a directory in the filesystem, like `/tmp` or an EFS mount, or assets
+//! embedded in the binary. Responses include the content type of the file, a strong
+//! `ETag`, and support conditional and range requests. Binary files are sent base64
+//! encoded, as expected by API Gateway and ALB.
+use crate::{
+    conditional::{etag_matches, strong_etag},
+    precompressed::{self, Encoding},
+    Body, Request, Response,
+};
+use http::{
+    header::{
+        ACCEPT_ENCODING, ACCEPT_RANGES, ALLOW, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+        CONTENT_TYPE, ETAG, IF_NONE_MATCH, RANGE, VARY,
+    },
+    Method, StatusCode,
+};
+use lambda_runtime::Service;
+use std::{
+    borrow::Cow,
+    convert::Infallible,
+    future::{ready, Ready},
+    path::{Component, Path, PathBuf},
+    task::{Context, Poll},
+};
+
+/// Default `Cache-Control` header, which makes clients revalidate files with their `ETag`.
+const DEFAULT_CACHE_CONTROL: &str = "no-cache";
+
+/// A source of files to serve.
+///
+/// Implement this trait to serve assets embedded with crates like `include_dir`:
+///
+/// ```ignore
+/// use include_dir::{include_dir, Dir};
+/// use lambda_http::fs::FileSource;
+/// use std::borrow::Cow;
+///
+/// static ASSETS: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/assets");
+///
+/// struct Assets;
+///
+/// impl FileSource for Assets {
+///     fn read(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+///         ASSETS.get_file(path).map(|f| Cow::Borrowed(f.contents()))
+///     }
+/// }
+/// ```
+pub trait FileSource {
+    /// Return the content of the file at `path`, relative to the root of the source.
+    ///
+    /// The path never starts with `/`, and never contains `.` or `..` segments.
+    fn read(&self, path: &str) -> Option<Cow<'static, [u8]>>;
+}
+
+/// Files in a directory of the filesystem.
+#[derive(Debug, Clone)]
+pub struct Dir {
+    root: PathBuf,
+}
+
+impl Dir {
+    /// Serve the files under `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Dir { root: root.into() }
+    }
+}
+
+impl FileSource for Dir {
+    fn read(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        std::fs::read(self.root.join(path)).ok().map(Cow::Owned)
+    }
+}
+
+/// Files embedded in the binary, as pairs of path and content.
+///
+/// # Example
+/// ```
+/// use lambda_http::fs::{Embedded, ServeFiles};
+///
+/// static ASSETS: Embedded = Embedded(&[
+///     ("index.html", b"<h1>hello</h1>"),
+///     ("app.js", b"console.log('hello')"),
+/// ]);
+///
+/// let files = ServeFiles::new(&ASSETS);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Embedded(pub &'static [(&'static str, &'static [u8])]);
+
+impl FileSource for Embedded {
+    fn read(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        self.0
+            .iter()
+            .find(|(name, _)| name.trim_start_matches('/') == path)
+            .map(|(_, content)| Cow::Borrowed(*content))
+    }
+}
+
+impl<S: FileSource + ?Sized> FileSource for &S {
+    fn read(&self, path: &str) -> Option<Cow<'static, [u8]>> {
+        (**self).read(path)
+    }
+}
+
+/// Serves files from a [`FileSource`].
+///
+/// `ServeFiles` can be used as a handler with `lambda_http::run`,
+/// or called from a handler with [`ServeFiles::serve`].
+///
+/// # Example
+/// ```no_run
+/// use lambda_http::{fs::{Dir, ServeFiles}, Error};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let files = ServeFiles::new(Dir::new("/mnt/efs/public")).prefix("/static");
+///     lambda_http::run(files).await
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServeFiles<S> {
+    source: S,
+    prefix: String,
+    index_file: Option<String>,
+    cache_control: String,
+    precompressed: bool,
+}
+
+impl<S: FileSource> ServeFiles<S> {
+    /// Serve the files in `source`.
+    pub fn new(source: S) -> Self {
+        ServeFiles {
+            source,
+            prefix: String::new(),
+            index_file: Some("index.html".into()),
+            cache_control: DEFAULT_CACHE_CONTROL.into(),
+            precompressed: false,
+        }
+    }
+
+    /// Strip `prefix` from request paths before looking up files.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Serve `index_file` for requests to directories, `index.html` by default.
+    pub fn index_file(mut self, index_file: Option<String>) -> Self {
+        self.index_file = index_file;
+        self
+    }
+
+    /// Set the `Cache-Control` header of the responses, `no-cache` by default.
+    pub fn cache_control(mut self, cache_control: impl Into<String>) -> Self {
+        self.cache_control = cache_control.into();
+        self
+    }
+
+    /// Serve the pre-compressed variants of files, like `app.js.br` and `app.js.gz` for `app.js`,
+    /// to the clients that accept them. Disabled by default.
+    ///
+    /// See the [`precompressed`](crate::precompressed) module for details.
+    pub fn precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// Build the response for the file requested by `req`.
+    pub fn serve(&self, req: &Request) -> Response<Body> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(ALLOW, "GET, HEAD")
+                .body(Body::Empty)
+                .expect("unable to build http::Response");
+        }
+
+        let content = match self.file_path(req.uri().path()) {
+            Some(path) => self.source.read(&path).map(|content| (path, content)),
+            None => None,
+        };
+        let (path, content) = match content {
+            Some(found) => found,
+            None => return status(StatusCode::NOT_FOUND),
+        };
+
+        let mut builder = Response::builder();
+        let (encoding, content) = self.encoded(req, &path, content);
+        if self.precompressed {
+            builder = builder.header(VARY, ACCEPT_ENCODING.as_str());
+        }
+        if encoding != Encoding::Identity {
+            builder = builder.header(CONTENT_ENCODING, encoding.as_str());
+        }
+
+        let etag = strong_etag(&content);
+        let builder = builder
+            .header(ETAG, &etag)
+            .header(CACHE_CONTROL, &self.cache_control)
+            .header(ACCEPT_RANGES, "bytes");
+
+        if let Some(if_none_match) = req.headers().get(IF_NONE_MATCH) {
+            if etag_matches(if_none_match, &etag) {
+                return builder
+                    .status(StatusCode::NOT_MODIFIED)
+                    .body(Body::Empty)
+                    .expect("unable to build http::Response");
+            }
+        }
+
+        let content_type = content_type(&path);
+        let builder = builder.header(CONTENT_TYPE, content_type);
+        let total = content.len();
+
+        let (builder, content) = match req.headers().get(RANGE).and_then(|r| r.to_str().ok()) {
+            Some(range) => match parse_range(range, total) {
+                Ok(Some((start, end))) => (
+                    builder
+                        .status(StatusCode::PARTIAL_CONTENT)
+                        .header(CONTENT_RANGE, format!("bytes {start}-{end}/{total}")),
+                    slice(content, start, end + 1),
+                ),
+                Ok(None) => (builder.status(StatusCode::OK), content),
+                Err(()) => {
+                    return builder
+                        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                        .header(CONTENT_RANGE, format!("bytes */{total}"))
+                        .body(Body::Empty)
+                        .expect("unable to build http::Response");
+                }
+            },
+            None => (builder.status(StatusCode::OK), content),
+        };
+
+        let builder = builder.header(CONTENT_LENGTH, content.len());
+        let body = if req.method() == Method::HEAD {
+            Body::Empty
+        } else if encoding == Encoding::Identity && is_text(content_type) {
+            match String::from_utf8(content.into_owned()) {
+                Ok(text) => Body::Text(text),
+                Err(err) => Body::Binary(err.into_bytes()),
+            }
+        } else {
+            Body::Binary(content.into_owned())
+        };
+
+        builder.body(body).expect("unable to build http::Response")
+    }
+
+    // Replace the content with the variant preferred by the client, when
+    // pre-compressed variants are enabled and available in the source.
+    fn encoded(&self, req: &Request, path: &str, content: Cow<'static, [u8]>) -> (Encoding, Cow<'static, [u8]>) {
+        if !self.precompressed {
+            return (Encoding::Identity, content);
+        }
+        let mut variants = Vec::new();
+        for encoding in [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip] {
+            let extension = encoding.extension().expect("compressed encodings have an extension");
+            if let Some(variant) = self.source.read(&format!("{path}.{extension}")) {
+                variants.push((encoding, variant));
+            }
+        }
+        let mut available = vec![Encoding::Identity];
+        available.extend(variants.iter().map(|(encoding, _)| *encoding));
+        let accept_encoding = req.headers().get(ACCEPT_ENCODING).and_then(|value| value.to_str().ok());
+        let selected = precompressed::select(accept_encoding, &available);
+        match variants.into_iter().find(|(encoding, _)| *encoding == selected) {
+            Some(variant) => variant,
+            None => (Encoding::Identity, content),
+        }
+    }
+
+    // Map the request path to a path in the source, rejecting paths
+    // outside of the prefix and paths that try to escape the root.
+    fn file_path(&self, request_path: &str) -> Option<String> {
+        let decoded = percent_encoding::percent_decode_str(request_path).decode_utf8().ok()?;
+        let relative = decoded.strip_prefix(self.prefix.as_str())?;
+        if !self.prefix.is_empty() && !relative.is_empty() && !relative.starts_with('/') {
+            return None;
+        }
+
+        let mut segments = Vec::new();
+        for component in Path::new(relative).components() {
+            match component {
+                Component::Normal(segment) => segments.push(segment.to_str()?),
+                Component::RootDir | Component::CurDir => {}
+                Component::ParentDir | Component::Prefix(_) => return None,
+            }
+        }
+
+        let mut path = segments.join("/");
+        if path.is_empty() || relative.ends_with('/') {
+            let index_file = self.index_file.as_ref()?;
+            if !path.is_empty() {
+                path.push('/');
+            }
+            path.push_str(index_file);
+        }
+        Some(path)
+    }
+}
+
+impl<S: FileSource> Service<Request> for ServeFiles<S> {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        ready(Ok(self.serve(&req)))
+    }
+}
+
+/// Return the content type of a file based on its extension.
+pub fn content_type(path: &str) -> &'static str {
+    let extension = path.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("json") | Some("map") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mp3") => "audio/mpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+fn is_text(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || content_type.starts_with("application/json")
+        || content_type.starts_with("application/xml")
+        || content_type.starts_with("image/svg+xml")
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::Empty)
+        .expect("unable to build http::Response")
+}
+
+// Parse a single byte range, returning the inclusive bounds of the range.
+// Multiple ranges are not supported, so the whole file is returned instead.
+fn parse_range(range: &str, total: usize) -> Result<Option<(usize, usize)>, ()> {
+    let spec = match range.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec,
+        _ => return Ok(None),
+    };
+    let (start, end) = spec.split_once('-').ok_or(())?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: usize = suffix.parse().map_err(|_| ())?;
+            if suffix == 0 {
+                return Err(());
+            }
+            (total.saturating_sub(suffix), total.saturating_sub(1))
+        }
+        (start, "") => (start.parse().map_err(|_| ())?, total.saturating_sub(1)),
+        (start, end) => {
+            let end: usize = end.parse().map_err(|_| ())?;
+            (start.parse().map_err(|_| ())?, end.min(total.saturating_sub(1)))
+        }
+    };
+
+    if total == 0 || start > end || start >= total {
+        return Err(());
+    }
+    Ok(Some((start, end)))
+}
+
+fn slice(content: Cow<'static, [u8]>, start: usize, end: usize) -> Cow<'static, [u8]> {
+    match content {
+        Cow::Borrowed(content) => Cow::Borrowed(&content[start..end]),
+        Cow::Owned(mut content) => {
+            content.truncate(end);
+            content.drain(..start);
+            Cow::Owned(content)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static ASSETS: Embedded = Embedded(&[
+        ("index.html", b"<h1>hello</h1>"),
+        ("img/logo.png", &[0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a]),
+    ]);
+
+    fn get(uri: &str) -> http::request::Builder {
+        http::Request::builder().uri(uri)
+    }
+
+    #[test]
+    fn serves_embedded_files() {
+        let files = ServeFiles::new(&ASSETS).prefix("/static/");
+
+        let res = files.serve(&get("/static/").body(Body::Empty).unwrap());
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!("text/html; charset=utf-8", res.headers()[CONTENT_TYPE]);
+        assert_eq!(&Body::Text("<h1>hello</h1>".into()), res.body());
+
+        let res = files.serve(&get("/static/img/logo.png").body(Body::Empty).unwrap());
+        assert_eq!("image/png", res.headers()[CONTENT_TYPE]);
+        assert!(matches!(res.body(), Body::Binary(data) if data.len() == 8));
+
+        for path in [
+            "/static/missing.css",
+            "/static/../index.html",
+            "/other/index.html",
+            "/staticindex.html",
+        ] {
+            let res = files.serve(&get(path).body(Body::Empty).unwrap());
+            assert_eq!(StatusCode::NOT_FOUND, res.status(), "{path}");
+        }
+
+        let res = files.serve(
+            &get("/static/index.html")
+                .method(Method::POST)
+                .body(Body::Empty)
+                .unwrap(),
+        );
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, res.status());
+    }
+
+    #[test]
+    fn serves_precompressed_variants() {
+        static COMPRESSED: Embedded = Embedded(&[
+            ("app.js", b"console.log('hello')"),
+            ("app.js.gz", b"gzipped"),
+            ("app.js.br", b"brotli"),
+        ]);
+        let files = ServeFiles::new(&COMPRESSED).precompressed(true);
+
+        let res = files.serve(
+            &get("/app.js")
+                .header(ACCEPT_ENCODING, "gzip")
+                .body(Body::Empty)
+                .unwrap(),
+        );
+        assert_eq!("gzip", res.headers()[CONTENT_ENCODING]);
+        assert_eq!("text/javascript; charset=utf-8", res.headers()[CONTENT_TYPE]);
+        assert_eq!("accept-encoding", res.headers()[VARY]);
+        assert_eq!(&Body::Binary(b"gzipped".to_vec()), res.body());
+        let gzip_etag = res.headers()[ETAG].clone();
+
+        let res = files.serve(
+            &get("/app.js")
+                .header(ACCEPT_ENCODING, "gzip, br")
+                .body(Body::Empty)
+                .unwrap(),
+        );
+        assert_eq!("br", res.headers()[CONTENT_ENCODING]);
+        assert_ne!(gzip_etag, res.headers()[ETAG]);
+
+        let res = files.serve(&get("/app.js").body(Body::Empty).unwrap());
+        assert!(!res.headers().contains_key(CONTENT_ENCODING));
+        assert_eq!(&Body::Text("console.log('hello')".into()), res.body());
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let files = ServeFiles::new(&ASSETS);
+        let res = files.serve(&get("/index.html").body(Body::Empty).unwrap());
+        let etag = res.headers()[ETAG].clone();
+        assert_eq!("\"TbfvYwAFxGJFDqWHcisafP9T39zTXX3UC8-Ol-UIJu4\"", etag);
+
+        let res = files.serve(
+            &get("/index.html")
+                .header(IF_NONE_MATCH, &etag)
+                .body(Body::Empty)
+                .unwrap(),
+        );
+        assert_eq!(StatusCode::NOT_MODIFIED, res.status());
+        assert_eq!(&Body::Empty, res.body());
+
+        let res = files.serve(
+            &get("/index.html")
+                .header(IF_NONE_MATCH, "\"other\"")
+                .body(Body::Empty)
+                .unwrap(),
+        );
+        assert_eq!(StatusCode::OK, res.status());
+    }
+
+    #[test]
+    fn range_requests() {
+        let files = ServeFiles::new(&ASSETS);
+
+        let res = files.serve(&get("/index.html").header(RANGE, "bytes=4-8").body(Body::Empty).unwrap());
+        assert_eq!(StatusCode::PARTIAL_CONTENT, res.status());
+        assert_eq!("bytes 4-8/14", res.headers()[CONTENT_RANGE]);
+        assert_eq!(&Body::Text("hello".into()), res.body());
+
+        let res = files.serve(&get("/index.html").header(RANGE, "bytes=-5").body(Body::Empty).unwrap());
+        assert_eq!(&Body::Text("</h1>".into()), res.body());
+
+        let res = files.serve(&get("/index.html").header(RANGE, "bytes=20-").body(Body::Empty).unwrap());
+        assert_eq!(StatusCode::RANGE_NOT_SATISFIABLE, res.status());
+        assert_eq!("bytes */14", res.headers()[CONTENT_RANGE]);
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_root() {
+        let files = ServeFiles::new(Dir::new("/tmp"));
+        assert_eq!(None, files.file_path("/../etc/passwd"));
+        assert_eq!(None, files.file_path("/%2e%2e/etc/passwd"));
+        assert_eq!(Some("a/b.txt".to_string()), files.file_path("/a/./b.txt"));
+    }
+}
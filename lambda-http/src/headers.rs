@@ -0,0 +1,367 @@
+//! Normalization of the request headers sent by the different triggers.
+//!
+//! Every trigger encodes headers its own way: API Gateway REST APIs and ALB
+//! send single and multi-value maps, HTTP APIs and function URLs join the
+//! values of a header with commas, and all of them keep the case of the names
+//! sent by the client. The conversion to a [`Request`] lowercases the names, so
+//! `Content-Length` and `content-length` become two values of the same header,
+//! and the resulting header set may be inconsistent in ways that a server
+//! reading from a socket would have rejected, like two different lengths for
+//! the body. Code that reads the first value, and a proxy or an authorizer that
+//! read the last one, then disagree about the request, which is the basis of
+//! request smuggling.
+//!
+//! [`HeaderNormalizationLayer`] fixes the headers before they reach the handler:
+//! - `Content-Length` values that differ are rejected with `400 Bad Request`,
+//!   as [RFC 9112](https://www.rfc-editor.org/rfc/rfc9112#section-6.3) requires,
+//!   and identical values are collapsed into one, which is set to the length of
+//!   the body.
+//! - `Transfer-Encoding` is removed, because the triggers always send the
+//!   decoded body.
+//! - Headers that only accept a single value, like `Host`, `Content-Type` or
+//!   `Authorization`, keep one value when all their values are identical, and
+//!   their first value otherwise.
+//!
+//! In [strict](HeaderNormalizationLayer::strict) mode, requests with conflicting
+//! values of single value headers, with a `Transfer-Encoding` other than
+//! `chunked`, or with both `Transfer-Encoding` and `Content-Length`, are
+//! rejected with `400 Bad Request` instead.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{headers::HeaderNormalizationLayer, service_fn, tower::Layer, Error, Request};
+//!
+//! async fn hello(_req: Request) -> Result<&'static str, Error> {
+//!     Ok("hello")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run(HeaderNormalizationLayer::new().strict().layer(service_fn(hello))).await
+//! }
+//! ```
+use crate::{Body, IntoResponse, Request, Response};
+use futures::future::BoxFuture;
+use http::{
+    header::{
+        HeaderName, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, HOST, IF_MODIFIED_SINCE, IF_UNMODIFIED_SINCE,
+        PROXY_AUTHORIZATION, TRANSFER_ENCODING,
+    },
+    HeaderMap, HeaderValue, StatusCode,
+};
+use lambda_runtime::{tower::Layer, Service};
+use std::{
+    fmt,
+    task::{Context as TaskContext, Poll},
+};
+use tracing::{debug, warn};
+
+/// Headers that only accept a single value.
+const SINGLE_VALUE: [HeaderName; 6] = [
+    HOST,
+    CONTENT_TYPE,
+    AUTHORIZATION,
+    PROXY_AUTHORIZATION,
+    IF_MODIFIED_SINCE,
+    IF_UNMODIFIED_SINCE,
+];
+
+/// A reason to reject the headers of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Violation {
+    InvalidContentLength,
+    ConflictingContentLength,
+    TransferEncodingWithContentLength,
+    UnsupportedTransferEncoding,
+    ConflictingValues(HeaderName),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::InvalidContentLength => write!(f, "invalid Content-Length"),
+            Violation::ConflictingContentLength => write!(f, "conflicting Content-Length values"),
+            Violation::TransferEncodingWithContentLength => {
+                write!(f, "both Transfer-Encoding and Content-Length are set")
+            }
+            Violation::UnsupportedTransferEncoding => write!(f, "unsupported Transfer-Encoding"),
+            Violation::ConflictingValues(name) => write!(f, "conflicting values of {name}"),
+        }
+    }
+}
+
+/// The values of a header, with the values joined by commas split apart.
+fn split_values<'a>(headers: &'a HeaderMap, name: &HeaderName) -> impl Iterator<Item = &'a [u8]> {
+    headers
+        .get_all(name)
+        .iter()
+        .flat_map(|value| value.as_bytes().split(|b| *b == b','))
+        .map(trim)
+}
+
+/// `value` without its leading and trailing ASCII whitespace.
+fn trim(value: &[u8]) -> &[u8] {
+    let start = value
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(value.len());
+    let end = value
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |end| end + 1);
+    &value[start..end]
+}
+
+fn normalize(headers: &mut HeaderMap, body_len: usize, strict: bool) -> Result<(), Violation> {
+    let mut lengths = split_values(headers, &CONTENT_LENGTH).peekable();
+    let has_length = lengths.peek().is_some();
+    let mut length = None;
+    for value in lengths {
+        if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+            return Err(Violation::InvalidContentLength);
+        }
+        match length {
+            Some(length) if length != value => return Err(Violation::ConflictingContentLength),
+            _ => length = Some(value),
+        }
+    }
+
+    if headers.contains_key(TRANSFER_ENCODING) {
+        if strict {
+            if has_length {
+                return Err(Violation::TransferEncodingWithContentLength);
+            }
+            let codings: Vec<&[u8]> = split_values(headers, &TRANSFER_ENCODING)
+                .filter(|coding| !coding.is_empty())
+                .collect();
+            if !matches!(codings.as_slice(), [coding] if coding.eq_ignore_ascii_case(b"chunked")) {
+                return Err(Violation::UnsupportedTransferEncoding);
+            }
+        }
+        debug!("removing the Transfer-Encoding of the request, the body is already decoded");
+        headers.remove(TRANSFER_ENCODING);
+    }
+
+    if has_length {
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(body_len));
+    }
+
+    for name in SINGLE_VALUE {
+        let mut values = headers.get_all(&name).iter();
+        let first = match values.next() {
+            Some(first) => first,
+            None => continue,
+        };
+        let mut conflicting = false;
+        let mut duplicated = false;
+        for value in values {
+            duplicated = true;
+            conflicting |= value != first;
+        }
+        if conflicting && strict {
+            return Err(Violation::ConflictingValues(name));
+        }
+        if duplicated {
+            debug!(header = %name, conflicting, "keeping the first value of a single value header");
+            let first = first.clone();
+            headers.insert(name, first);
+        }
+    }
+    Ok(())
+}
+
+/// A [`Layer`] that normalizes duplicate and conflicting request headers.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeaderNormalizationLayer {
+    strict: bool,
+}
+
+impl HeaderNormalizationLayer {
+    /// Create a layer that normalizes headers, and only rejects requests with
+    /// conflicting `Content-Length` values.
+    pub fn new() -> Self {
+        HeaderNormalizationLayer { strict: false }
+    }
+
+    /// Reject requests with headers that could be interpreted differently by
+    /// the handler and by the proxies in front of it.
+    pub fn strict(self) -> Self {
+        HeaderNormalizationLayer { strict: true }
+    }
+}
+
+impl<S> Layer<S> for HeaderNormalizationLayer {
+    type Service = HeaderNormalizationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HeaderNormalizationService {
+            inner,
+            strict: self.strict,
+        }
+    }
+}
+
+/// A [`Service`] that normalizes the headers of requests.
+///
+/// See [`HeaderNormalizationLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct HeaderNormalizationService<S> {
+    inner: S,
+    strict: bool,
+}
+
+impl<S> Service<Request> for HeaderNormalizationService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let body_len = req.body().len();
+        if let Err(violation) = normalize(req.headers_mut(), body_len, self.strict) {
+            warn!(%violation, "rejecting a request with inconsistent headers");
+            return Box::pin(async move { Ok(bad_request(&violation)) });
+        }
+
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            Ok(response.await)
+        })
+    }
+}
+
+fn bad_request(violation: &Violation) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(format!("Bad Request: {violation}")))
+        .expect("unable to build http::Response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, Error};
+
+    fn headers(values: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.append(HeaderName::from_bytes(name.as_bytes()).unwrap(), value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn values<'a>(headers: &'a HeaderMap, name: &str) -> Vec<&'a str> {
+        headers
+            .get_all(name)
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn collapses_duplicate_content_lengths() {
+        for lengths in [&["5", "5"][..], &["5, 5"], &["4"]] {
+            let mut headers = headers(
+                &lengths
+                    .iter()
+                    .map(|value| ("content-length", *value))
+                    .collect::<Vec<_>>(),
+            );
+            normalize(&mut headers, 5, true).unwrap();
+            assert_eq!(vec!["5"], values(&headers, "content-length"));
+        }
+    }
+
+    #[test]
+    fn rejects_conflicting_content_lengths() {
+        for strict in [false, true] {
+            let mut conflicting = headers(&[("content-length", "5"), ("content-length", "50")]);
+            assert_eq!(
+                Err(Violation::ConflictingContentLength),
+                normalize(&mut conflicting, 5, strict)
+            );
+            let mut invalid = headers(&[("content-length", "+5")]);
+            assert_eq!(Err(Violation::InvalidContentLength), normalize(&mut invalid, 5, strict));
+        }
+    }
+
+    #[test]
+    fn removes_transfer_encoding() {
+        let mut lenient = headers(&[("transfer-encoding", "chunked"), ("transfer-encoding", "identity")]);
+        normalize(&mut lenient, 5, false).unwrap();
+        assert!(lenient.is_empty());
+
+        let mut chunked = headers(&[("transfer-encoding", "Chunked")]);
+        normalize(&mut chunked, 5, true).unwrap();
+        assert!(chunked.is_empty());
+
+        let mut smuggling = headers(&[("transfer-encoding", "chunked"), ("content-length", "5")]);
+        normalize(&mut smuggling.clone(), 5, false).unwrap();
+        assert_eq!(
+            Err(Violation::TransferEncodingWithContentLength),
+            normalize(&mut smuggling, 5, true)
+        );
+
+        let mut obfuscated = headers(&[("transfer-encoding", "chunked, chunked")]);
+        assert_eq!(
+            Err(Violation::UnsupportedTransferEncoding),
+            normalize(&mut obfuscated, 5, true)
+        );
+    }
+
+    #[test]
+    fn keeps_one_value_of_single_value_headers() {
+        let mut duplicated = headers(&[
+            ("host", "example.com"),
+            ("host", "example.com"),
+            ("accept", "a"),
+            ("accept", "b"),
+        ]);
+        normalize(&mut duplicated, 0, true).unwrap();
+        assert_eq!(vec!["example.com"], values(&duplicated, "host"));
+        assert_eq!(vec!["a", "b"], values(&duplicated, "accept"));
+
+        let mut conflicting = headers(&[("content-type", "text/plain"), ("content-type", "application/json")]);
+        assert_eq!(
+            Err(Violation::ConflictingValues(CONTENT_TYPE)),
+            normalize(&mut conflicting.clone(), 0, true)
+        );
+        normalize(&mut conflicting, 0, false).unwrap();
+        assert_eq!(vec!["text/plain"], values(&conflicting, "content-type"));
+    }
+
+    #[tokio::test]
+    async fn answers_bad_request() {
+        let mut service = HeaderNormalizationLayer::new()
+            .strict()
+            .layer(service_fn(|_req: Request| async { Ok::<_, Error>("ok") }));
+        let req = http::Request::builder()
+            .header("host", "a.example.com")
+            .header("host", "b.example.com")
+            .body(Body::Empty)
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(&Body::from("Bad Request: conflicting values of host"), response.body());
+
+        let req = http::Request::builder()
+            .header("content-length", "2")
+            .body(Body::from("hi"))
+            .unwrap();
+        let response = service.call(req).await.unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+    }
+}
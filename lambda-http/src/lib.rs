@@ -66,16 +66,72 @@ extern crate maplit;
 
 pub use http::{self, Response};
 use lambda_runtime::LambdaEvent;
-pub use lambda_runtime::{self, service_fn, tower, Context, Error, Service};
+pub use lambda_runtime::{self, service_fn, tower, Context, Error, HandlerExt, Service};
 use request::RequestFuture;
 use response::ResponseFuture;
 
+pub mod admin;
+#[cfg(feature = "alb")]
+pub mod alb_oidc;
+pub mod cache;
+pub mod conditional;
+pub mod config;
+pub mod csrf;
+#[cfg(feature = "apigw_rest")]
+pub mod custom_integration;
 pub mod ext;
+pub mod forwarded;
+pub mod fs;
+pub mod headers;
+pub mod ndjson;
+pub mod negotiate;
+#[cfg(feature = "oidc")]
+pub mod oidc;
+pub mod origin;
+pub mod pagination;
+pub mod parse;
+pub mod path;
+#[cfg(any(
+    feature = "apigw_rest",
+    feature = "apigw_http",
+    feature = "alb",
+    feature = "apigw_websockets",
+    feature = "vpc_lattice"
+))]
+pub mod payload;
+pub mod precompressed;
+#[cfg(feature = "presign")]
+pub mod presign;
+pub mod problem;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod rate_limit;
 pub mod request;
+pub mod request_id;
+pub mod request_log;
 mod response;
+pub mod security_headers;
+pub mod session;
+#[cfg(feature = "sniff")]
+pub mod sniff;
+pub mod sse;
+pub mod template;
+#[cfg(any(
+    feature = "apigw_rest",
+    feature = "apigw_http",
+    feature = "alb",
+    feature = "apigw_websockets",
+    feature = "vpc_lattice"
+))]
+pub mod testing;
+#[cfg(feature = "apigw_websockets")]
+pub mod websocket;
 pub use crate::{
+    conditional::ConditionalLayer,
     ext::{RequestExt, RequestPayloadExt},
-    response::IntoResponse,
+    request_log::RequestLogLayer,
+    response::{Attachment, IntoResponse, NoContent, Redirect},
+    security_headers::SecurityHeadersLayer,
 };
 use crate::{
     request::{LambdaRequest, RequestOrigin},
@@ -98,6 +154,9 @@ use std::{
 mod streaming;
 pub use streaming::run_with_streaming_response;
 
+#[cfg(feature = "macros")]
+pub use lambda_runtime_macros::{handler, IntoResponse};
+
 /// Type alias for `http::Request`s with a fixed [`Body`](enum.Body.html) type
 pub type Request = http::Request<Body>;
 
@@ -180,6 +239,55 @@ where
     }
 }
 
+/// Wraps a `Service<Request>` whose futures are not `Send` in a `Service<LambdaEvent<Request>>`
+///
+/// This is completely internal to the `lambda_http::run_local` function.
+#[doc(hidden)]
+pub struct LocalAdapter<'a, R, S> {
+    service: S,
+    _phantom_data: PhantomData<&'a R>,
+}
+
+impl<'a, R, S, E> From<S> for LocalAdapter<'a, R, S>
+where
+    S: Service<Request, Response = R, Error = E>,
+    S::Future: 'a,
+    R: IntoResponse,
+{
+    fn from(service: S) -> Self {
+        LocalAdapter {
+            service,
+            _phantom_data: PhantomData,
+        }
+    }
+}
+
+impl<'a, R, S, E> Service<LambdaEvent<LambdaRequest>> for LocalAdapter<'a, R, S>
+where
+    S: Service<Request, Response = R, Error = E>,
+    S::Future: 'a,
+    R: IntoResponse,
+{
+    type Response = LambdaResponse;
+    type Error = E;
+    type Future = Pin<Box<dyn Future<Output = Result<LambdaResponse, E>> + 'a>>;
+
+    fn poll_ready(&mut self, cx: &mut core::task::Context<'_>) -> core::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaEvent<LambdaRequest>) -> Self::Future {
+        let request_origin = req.payload.request_origin();
+        let event: Request = req.payload.into();
+        let fut = self.service.call(event.with_lambda_context(req.context));
+
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            Ok(LambdaResponse::from_response(&request_origin, response.await))
+        })
+    }
+}
+
 /// Starts the Lambda Rust runtime and begins polling for events on the [Lambda
 /// Runtime APIs](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html).
 ///
@@ -190,11 +298,87 @@ where
     S: Service<Request, Response = R, Error = E>,
     S::Future: Send + 'a,
     R: IntoResponse,
-    E: std::fmt::Debug + std::fmt::Display,
+    E: std::fmt::Debug + std::fmt::Display + 'static,
 {
     lambda_runtime::run(Adapter::from(handler)).await
 }
 
+/// Starts the Lambda Rust runtime like [`run`], and parses the events in `mode`.
+///
+/// See the [`parse`] module for details.
+pub async fn run_with_parse_mode<'a, R, S, E>(handler: S, mode: parse::ParseMode) -> Result<(), Error>
+where
+    S: Service<Request, Response = R, Error = E>,
+    S::Future: Send + 'a,
+    R: IntoResponse,
+    E: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    lambda_runtime::RuntimeBuilder::new()
+        .with_codec(parse::RequestCodec::new(mode))
+        .run(Adapter::from(handler))
+        .await
+}
+
+/// Starts the Lambda Rust runtime like [`run`], and converts the events with `origins`.
+///
+/// See the [`origin`] module for details.
+pub async fn run_with_origins<R, S, E>(handler: S, origins: origin::Origins) -> Result<(), Error>
+where
+    S: Service<Request, Response = R, Error = E>,
+    S::Future: Send + 'static,
+    R: IntoResponse,
+    E: Into<Error>,
+{
+    lambda_runtime::run(origin::OriginAdapter::new(handler, origins)).await
+}
+
+/// Starts the Lambda Rust runtime like [`run`], for the Lambda custom (non-proxy)
+/// integrations of API Gateway REST APIs.
+///
+/// See the [`custom_integration`] module for details.
+#[cfg(feature = "apigw_rest")]
+pub async fn run_custom_integration<R, S, E>(handler: S) -> Result<(), Error>
+where
+    S: Service<Request, Response = R, Error = E>,
+    S::Future: Send + 'static,
+    R: IntoResponse,
+    E: Into<Error>,
+{
+    lambda_runtime::run(custom_integration::CustomIntegrationAdapter::new(handler)).await
+}
+
+/// Starts the Lambda Rust runtime on a single-threaded tokio runtime, and blocks
+/// the current thread until it finishes.
+///
+/// Unlike [`run`], the handler's future doesn't need to be `Send`.
+/// See [`lambda_runtime::run_local`] for details.
+///
+/// # Example
+/// ```no_run
+/// use lambda_http::{service_fn, Error, Request};
+/// use std::{cell::Cell, rc::Rc};
+///
+/// fn main() -> Result<(), Error> {
+///     let hits = Rc::new(Cell::new(0));
+///     lambda_http::run_local(service_fn(move |_: Request| {
+///         let hits = hits.clone();
+///         async move {
+///             hits.set(hits.get() + 1);
+///             Ok::<_, Error>(format!("{} hits", hits.get()))
+///         }
+///     }))
+/// }
+/// ```
+pub fn run_local<'a, R, S, E>(handler: S) -> Result<(), Error>
+where
+    S: Service<Request, Response = R, Error = E>,
+    S::Future: 'a,
+    R: IntoResponse,
+    E: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    lambda_runtime::run_local(LocalAdapter::from(handler))
+}
+
 #[cfg(test)]
 mod test_adapter {
     use std::task::{Context, Poll};
@@ -205,8 +389,9 @@ mod test_adapter {
         request::LambdaRequest,
         response::LambdaResponse,
         tower::{util::BoxService, Service, ServiceBuilder, ServiceExt},
-        Adapter, Body, Request,
+        Adapter, Body, LocalAdapter, Request,
     };
+    use std::{cell::Cell, rc::Rc};
 
     // A middleware that logs requests before forwarding them to another service
     struct LogService<S> {
@@ -249,4 +434,29 @@ mod test_adapter {
             .service_fn(|_event: Request| async move { Response::builder().status(StatusCode::OK).body(Body::Empty) })
             .boxed();
     }
+
+    /// This tests that `LocalAdapter` accepts handlers whose futures are not `Send`.
+    #[tokio::test]
+    async fn local_adapter_accepts_non_send_futures() {
+        let hits = Rc::new(Cell::new(0));
+        let h = hits.clone();
+        let mut adapter = LocalAdapter::from(crate::service_fn(move |_event: Request| {
+            let hits = h.clone();
+            async move {
+                hits.set(hits.get() + 1);
+                Ok::<_, http::Error>("hello")
+            }
+        }));
+
+        let input = include_str!("../tests/data/apigw_v2_proxy_request_minimal.json");
+        let req: LambdaRequest = serde_json::from_str(input).unwrap();
+        let event = LambdaEvent::new(req, Default::default());
+        let res = adapter.ready().await.unwrap().call(event).await.unwrap();
+
+        assert_eq!(1, hits.get());
+        match res {
+            LambdaResponse::ApiGatewayV2(res) => assert_eq!(200, res.status_code),
+            _ => panic!("unexpected response"),
+        }
+    }
 }
@@ -0,0 +1,174 @@
+//! Newline delimited JSON responses.
+//!
+//! [`NdjsonStream`] serializes the items of a [`Stream`] as JSON Lines, one item
+//! at a time. With [`run_with_streaming_response`](crate::run_with_streaming_response),
+//! items are sent to the client as they are produced, so responses can go over the
+//! 6 MB limit of buffered responses. With [`run`](crate::run), the items are buffered
+//! up to a maximum size, and the response is truncated at the last complete line.
+//!
+//! # Example
+//! ```no_run
+//! use futures::{stream, Stream};
+//! use lambda_http::{ndjson::NdjsonStream, service_fn, Error, Request, Response};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run_with_streaming_response(service_fn(export)).await
+//! }
+//!
+//! async fn export(_req: Request) -> Result<Response<NdjsonStream<impl Stream<Item = u32> + Send>>, Error> {
+//!     let rows = stream::iter(0..1_000_000);
+//!     Ok(NdjsonStream::new(rows).into_streaming_response())
+//! }
+//! ```
+use crate::{response::ResponseFuture, Body, IntoResponse};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use http::{header::CONTENT_TYPE, Response};
+use serde::Serialize;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Content type of newline delimited JSON responses.
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Header set on buffered responses that were truncated because they reached the maximum size.
+pub const TRUNCATED_HEADER: &str = "x-ndjson-truncated";
+
+/// Default maximum size of buffered responses, which leaves
+/// room for headers under the buffered payload limit.
+pub const DEFAULT_MAX_BUFFERED_SIZE: usize = 5 * 1024 * 1024;
+
+/// A response body that serializes every item of a stream as a line of JSON.
+pub struct NdjsonStream<S> {
+    stream: Pin<Box<S>>,
+    max_buffered_size: usize,
+}
+
+impl<S, T> NdjsonStream<S>
+where
+    S: Stream<Item = T>,
+    T: Serialize,
+{
+    /// Create a new body from a stream of serializable items.
+    pub fn new(stream: S) -> Self {
+        NdjsonStream {
+            stream: Box::pin(stream),
+            max_buffered_size: DEFAULT_MAX_BUFFERED_SIZE,
+        }
+    }
+
+    /// Set the maximum size of the response when it's buffered.
+    pub fn max_buffered_size(mut self, max_buffered_size: usize) -> Self {
+        self.max_buffered_size = max_buffered_size;
+        self
+    }
+
+    /// Wrap the stream in a response for streaming invocations.
+    pub fn into_streaming_response(self) -> Response<Self> {
+        Response::builder()
+            .header(CONTENT_TYPE, NDJSON_CONTENT_TYPE)
+            .body(self)
+            .expect("unable to build http::Response")
+    }
+}
+
+impl<S> std::fmt::Debug for NdjsonStream<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NdjsonStream")
+            .field("max_buffered_size", &self.max_buffered_size)
+            .finish()
+    }
+}
+
+fn encode_line<T: Serialize>(item: &T) -> Result<Vec<u8>, serde_json::Error> {
+    let mut line = serde_json::to_vec(item)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+impl<S, T> http_body::Body for NdjsonStream<S>
+where
+    S: Stream<Item = T>,
+    T: Serialize,
+{
+    type Data = Bytes;
+    type Error = serde_json::Error;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.stream.as_mut().poll_next(cx) {
+            Poll::Ready(Some(item)) => Poll::Ready(Some(encode_line(&item).map(Bytes::from))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+impl<S, T> IntoResponse for NdjsonStream<S>
+where
+    S: Stream<Item = T> + Send + 'static,
+    T: Serialize,
+{
+    fn into_response(mut self) -> ResponseFuture {
+        Box::pin(async move {
+            let mut body = Vec::new();
+            let mut truncated = false;
+
+            while let Some(item) = self.stream.next().await {
+                let line = encode_line(&item).expect("unable to serialize the stream item");
+                if body.len() + line.len() > self.max_buffered_size {
+                    truncated = true;
+                    break;
+                }
+                body.extend_from_slice(&line);
+            }
+
+            let mut builder = Response::builder().header(CONTENT_TYPE, NDJSON_CONTENT_TYPE);
+            if truncated {
+                builder = builder.header(TRUNCATED_HEADER, "true");
+            }
+            let body = String::from_utf8(body).expect("serde_json always produces valid UTF-8");
+            builder.body(Body::Text(body)).expect("unable to build http::Response")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn stream_lines() {
+        let items = stream::iter(vec![json!({"id": 1}), json!({"id": 2})]);
+        let response = NdjsonStream::new(items).into_streaming_response();
+        assert_eq!(NDJSON_CONTENT_TYPE, response.headers()[CONTENT_TYPE]);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!("{\"id\":1}\n{\"id\":2}\n", body);
+    }
+
+    #[tokio::test]
+    async fn buffer_lines_up_to_the_maximum_size() {
+        let response = NdjsonStream::new(stream::iter(0..5))
+            .max_buffered_size(6)
+            .into_response()
+            .await;
+        assert_eq!("true", response.headers()[TRUNCATED_HEADER]);
+        assert_eq!(&Body::Text("0\n1\n2\n".into()), response.body());
+
+        let response = NdjsonStream::new(stream::iter(0..3)).into_response().await;
+        assert!(response.headers().get(TRUNCATED_HEADER).is_none());
+        assert_eq!(&Body::Text("0\n1\n2\n".into()), response.body());
+    }
+}
@@ -0,0 +1,334 @@
+//! Content negotiation of responses with the `Accept` header.
+//!
+//! [`Negotiate`] renders the same value as JSON, HTML, or plain text, depending
+//! on the representations that the client accepts. JSON is always available, HTML
+//! and plain text are available once their renderer is set. Clients that accept
+//! none of the available representations get a `406 Not Acceptable` response,
+//! unless the response is [lenient](Negotiate::lenient).
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{negotiate::Negotiate, service_fn, Error, IntoResponse, Request};
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! struct Order {
+//!     id: u64,
+//!     total: f64,
+//! }
+//!
+//! async fn order(req: Request) -> Result<impl IntoResponse, Error> {
+//!     let order = Order { id: 42, total: 9.99 };
+//!     Ok(Negotiate::new(&req, order)
+//!         .html(|order| format!("<h1>Order {}</h1><p>Total: {}</p>", order.id, order.total))
+//!         .text(|order| format!("order {}: {}", order.id, order.total)))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run(service_fn(order)).await
+//! }
+//! ```
+use crate::{response::ResponseFuture, Body, IntoResponse, Request};
+use http::{
+    header::{ACCEPT, CONTENT_TYPE, VARY},
+    Response, StatusCode,
+};
+use serde::Serialize;
+use std::{fmt, future::ready};
+
+type Renderer<T> = Box<dyn FnOnce(&T) -> String + Send>;
+
+/// Representations of a [`Negotiate`] response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    /// `application/json`
+    Json,
+    /// `text/html`
+    Html,
+    /// `text/plain`
+    Text,
+}
+
+impl Representation {
+    /// Return the content type of this representation.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Representation::Json => "application/json",
+            Representation::Html => "text/html; charset=utf-8",
+            Representation::Text => "text/plain; charset=utf-8",
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Representation> {
+        let media_type = media_type.to_ascii_lowercase();
+        match media_type.as_str() {
+            "application/json" => Some(Representation::Json),
+            "text/html" | "application/xhtml+xml" => Some(Representation::Html),
+            "text/plain" => Some(Representation::Text),
+            other if other.starts_with("application/") && other.ends_with("+json") => Some(Representation::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A response that renders `T` in the representation preferred by the client.
+pub struct Negotiate<T> {
+    value: T,
+    accept: Option<String>,
+    default: Representation,
+    lenient: bool,
+    html: Option<Renderer<T>>,
+    text: Option<Renderer<T>>,
+}
+
+impl<T> Negotiate<T> {
+    /// Create a response for `value`, negotiated with the `Accept` header of `request`.
+    pub fn new(request: &Request, value: T) -> Self {
+        let accept = request
+            .headers()
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|accept| accept.to_str().ok())
+            .collect::<Vec<_>>();
+        Negotiate {
+            value,
+            accept: (!accept.is_empty()).then(|| accept.join(",")),
+            default: Representation::Json,
+            lenient: false,
+            html: None,
+            text: None,
+        }
+    }
+
+    /// Render the value as HTML with `renderer`.
+    pub fn html(self, renderer: impl FnOnce(&T) -> String + Send + 'static) -> Self {
+        Negotiate {
+            html: Some(Box::new(renderer)),
+            ..self
+        }
+    }
+
+    /// Render the value as plain text with `renderer`.
+    pub fn text(self, renderer: impl FnOnce(&T) -> String + Send + 'static) -> Self {
+        Negotiate {
+            text: Some(Box::new(renderer)),
+            ..self
+        }
+    }
+
+    /// Set the representation for requests without an `Accept` header, or that
+    /// accept any media type, `Json` by default.
+    ///
+    /// Representations without a renderer fall back to JSON.
+    pub fn default_representation(self, default: Representation) -> Self {
+        Negotiate { default, ..self }
+    }
+
+    /// Respond with the default representation, instead of `406 Not Acceptable`,
+    /// when the client accepts none of the available representations.
+    pub fn lenient(self) -> Self {
+        Negotiate { lenient: true, ..self }
+    }
+
+    /// Return the representations that can be rendered, in order of preference.
+    pub fn available(&self) -> Vec<Representation> {
+        let mut available = vec![self.default()];
+        for (representation, renderer) in [
+            (Representation::Json, true),
+            (Representation::Html, self.html.is_some()),
+            (Representation::Text, self.text.is_some()),
+        ] {
+            if renderer && !available.contains(&representation) {
+                available.push(representation);
+            }
+        }
+        available
+    }
+
+    /// Return the representation selected for the request, or `None` if the
+    /// client accepts none of the available representations.
+    pub fn select(&self) -> Option<Representation> {
+        let accept = match &self.accept {
+            Some(accept) => accept,
+            None => return Some(self.default()),
+        };
+        let available = self.available();
+
+        let mut preferred: Option<(Representation, f32)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let media_type = params.next().unwrap_or_default().trim();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let representation = match media_type {
+                "*/*" => Some(self.default()),
+                "text/*" => available
+                    .iter()
+                    .copied()
+                    .find(|representation| matches!(representation, Representation::Html | Representation::Text)),
+                "application/*" => Some(Representation::Json),
+                other => Representation::from_media_type(other).filter(|other| available.contains(other)),
+            };
+            let representation = match representation {
+                Some(representation) => representation,
+                None => continue,
+            };
+            let better = match preferred {
+                Some((_, q)) => quality > q,
+                None => quality > 0.0,
+            };
+            if better {
+                preferred = Some((representation, quality));
+            }
+        }
+        preferred
+            .map(|(representation, _)| representation)
+            .or_else(|| self.lenient.then(|| self.default()))
+    }
+
+    fn default(&self) -> Representation {
+        match self.default {
+            Representation::Html if self.html.is_none() => Representation::Json,
+            Representation::Text if self.text.is_none() => Representation::Json,
+            default => default,
+        }
+    }
+}
+
+impl<T: Serialize> Negotiate<T> {
+    fn render(self) -> Response<Body> {
+        let representation = match self.select() {
+            Some(representation) => representation,
+            None => {
+                let available = self
+                    .available()
+                    .into_iter()
+                    .map(|representation| representation.content_type())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Response::builder()
+                    .status(StatusCode::NOT_ACCEPTABLE)
+                    .header(VARY, ACCEPT.as_str())
+                    .header(CONTENT_TYPE, Representation::Text.content_type())
+                    .body(format!("expected one of: {available}").into())
+                    .expect("unable to build http::Response");
+            }
+        };
+
+        let body = match (representation, self.html, self.text) {
+            (Representation::Html, Some(html), _) => html(&self.value),
+            (Representation::Text, _, Some(text)) => text(&self.value),
+            _ => match serde_json::to_string(&self.value) {
+                Ok(body) => body,
+                Err(err) => {
+                    return Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(format!("unable to serialize response: {err}").into())
+                        .expect("unable to build http::Response")
+                }
+            },
+        };
+        Response::builder()
+            .header(VARY, ACCEPT.as_str())
+            .header(CONTENT_TYPE, representation.content_type())
+            .body(body.into())
+            .expect("unable to build http::Response")
+    }
+}
+
+impl<T: Serialize> IntoResponse for Negotiate<T> {
+    fn into_response(self) -> ResponseFuture {
+        Box::pin(ready(self.render()))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Negotiate<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Negotiate")
+            .field("value", &self.value)
+            .field("accept", &self.accept)
+            .field("available", &self.available())
+            .field("lenient", &self.lenient)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn negotiate(accept: Option<&str>) -> Negotiate<serde_json::Value> {
+        let mut request = http::Request::builder();
+        if let Some(accept) = accept {
+            request = request.header(ACCEPT, accept);
+        }
+        Negotiate::new(&request.body(Body::Empty).unwrap(), json!({"id": 42}))
+    }
+
+    fn rendered(negotiate: Negotiate<serde_json::Value>) -> Negotiate<serde_json::Value> {
+        negotiate
+            .html(|value| format!("<p>{}</p>", value["id"]))
+            .text(|value| format!("id: {}", value["id"]))
+    }
+
+    #[test]
+    fn selects_the_preferred_representation() {
+        assert_eq!(Some(Representation::Json), rendered(negotiate(None)).select());
+        assert_eq!(Some(Representation::Json), rendered(negotiate(Some("*/*"))).select());
+        assert_eq!(
+            Some(Representation::Html),
+            rendered(negotiate(Some("text/html,application/xhtml+xml,*/*;q=0.8"))).select()
+        );
+        assert_eq!(
+            Some(Representation::Text),
+            rendered(negotiate(Some("text/html;q=0.5, text/plain"))).select()
+        );
+        assert_eq!(Some(Representation::Html), rendered(negotiate(Some("text/*"))).select());
+        assert_eq!(
+            Some(Representation::Html),
+            rendered(negotiate(None))
+                .default_representation(Representation::Html)
+                .select()
+        );
+    }
+
+    #[test]
+    fn skips_representations_without_renderer() {
+        assert_eq!(None, negotiate(Some("text/html")).select());
+        assert_eq!(
+            Some(Representation::Json),
+            negotiate(Some("text/html, application/json;q=0.1")).select()
+        );
+        assert_eq!(
+            Some(Representation::Json),
+            negotiate(None).default_representation(Representation::Text).select()
+        );
+        assert_eq!(
+            Some(Representation::Json),
+            negotiate(Some("text/html")).lenient().select()
+        );
+    }
+
+    #[tokio::test]
+    async fn renders_the_selected_representation() {
+        let response = rendered(negotiate(Some("text/html"))).into_response().await;
+        assert_eq!("text/html; charset=utf-8", response.headers()[CONTENT_TYPE]);
+        assert_eq!("accept", response.headers()[VARY]);
+        assert_eq!(&Body::Text("<p>42</p>".into()), response.body());
+
+        let response = negotiate(Some("application/json")).into_response().await;
+        assert_eq!("application/json", response.headers()[CONTENT_TYPE]);
+        assert_eq!(&Body::Text(r#"{"id":42}"#.into()), response.body());
+    }
+
+    #[tokio::test]
+    async fn rejects_unacceptable_requests() {
+        let response = negotiate(Some("image/png")).into_response().await;
+        assert_eq!(StatusCode::NOT_ACCEPTABLE, response.status());
+        assert_eq!(&Body::Text("expected one of: application/json".into()), response.body());
+    }
+}
@@ -0,0 +1,959 @@
+//! Login with an OpenID Connect identity provider.
+//!
+//! Function URLs don't have the authorizers of API Gateway, nor the
+//! authentication of Application Load Balancers, so functions that serve
+//! browsers must log their users in themselves. [`OidcLayer`] implements the
+//! authorization code flow of OpenID Connect, with PKCE, on three paths:
+//! - `/login` redirects the user to the identity provider, and remembers the
+//!   page to come back to from the `return_to` query parameter.
+//! - `/callback`, where the identity provider sends the user back, exchanges
+//!   the code for tokens, verifies the ID token, and saves the user in the
+//!   session.
+//! - `/logout` destroys the session.
+//!
+//! Other requests get the [`OidcUser`] logged in with the session, as an
+//! extension, and can be [rejected](OidcLayer::require_login) when there's
+//! none. The state of the flow is kept in the [`Session`] of the user between
+//! the redirects, so nothing is stored in the execution environment, and the
+//! layer must be wrapped by a [`SessionLayer`], with encrypted cookies or a
+//! store, like any session that holds personal data.
+//!
+//! The discovery document and the keys of the identity provider are fetched
+//! the first time they're needed, and cached for the life of the execution
+//! environment. Keys are fetched again when a token is signed by a new one,
+//! at most once a minute. ID tokens signed with RS256 and ES256 are supported.
+//! This crate doesn't ship an HTTPS client, so the calls to the identity
+//! provider are delegated to an [`OidcHttpClient`].
+//!
+//! This module is available with the `oidc` feature.
+//!
+//! [`SessionLayer`]: crate::session::SessionLayer
+//!
+//! # Example
+//! ```no_run
+//! use futures::future::BoxFuture;
+//! use lambda_http::{
+//!     oidc::{OidcHttpClient, OidcLayer, OidcProvider, OidcUser},
+//!     service_fn,
+//!     session::{SessionKey, SessionLayer},
+//!     tower::Layer,
+//!     Error, Request,
+//! };
+//!
+//! struct Client;
+//!
+//! impl OidcHttpClient for Client {
+//!     fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<String, Error>> {
+//!         # unimplemented!()
+//!     }
+//!
+//!     fn post_form<'a>(&'a self, url: &'a str, form: &'a [(&'a str, &'a str)]) -> BoxFuture<'a, Result<String, Error>> {
+//!         # unimplemented!()
+//!     }
+//! }
+//!
+//! async fn hello(req: Request) -> Result<String, Error> {
+//!     let user = req.extensions().get::<OidcUser>().expect("logged in");
+//!     Ok(format!("hello {}", user.sub))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let provider = OidcProvider::new(
+//!         "https://accounts.example.com",
+//!         "client-id",
+//!         "https://app.example.com/callback",
+//!         Client,
+//!     )
+//!     .client_secret(std::env::var("OIDC_CLIENT_SECRET")?);
+//!     let key = SessionKey::new(std::env::var("SESSION_SECRET")?.as_bytes())?;
+//!
+//!     let oidc = OidcLayer::new(provider).require_login(true);
+//!     let sessions = SessionLayer::encrypted(key);
+//!     lambda_http::run(sessions.layer(oidc.layer(service_fn(hello)))).await
+//! }
+//! ```
+use crate::{
+    alb_oidc::JWT_BASE64,
+    session::{self, Session},
+    Body, IntoResponse, Redirect, Request, Response,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures::future::BoxFuture;
+use http::{header::CONTENT_TYPE, Method, StatusCode};
+use lambda_runtime::{tower::Layer, Error, Service};
+use p256::{
+    ecdsa::{signature::Verifier, Signature, VerifyingKey},
+    EncodedPoint, FieldBytes,
+};
+use rsa::{pkcs1v15, traits::PublicKeyParts, BigUint, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Key of the state of the flow in the [`Session`], between the login and the callback.
+const PENDING_KEY: &str = "oidc_pending";
+/// Key of the [`OidcUser`] in the [`Session`].
+pub const USER_KEY: &str = "oidc_user";
+/// Minimum time between two fetches of the keys of the identity provider.
+const KEYS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// Minimum size of the RSA keys of RS256 tokens, in bytes.
+const MIN_RSA_KEY_SIZE: usize = 256;
+/// Claims of the ID token that only matter to the login.
+const PROTOCOL_CLAIMS: [&str; 5] = ["nonce", "at_hash", "c_hash", "auth_time", "azp"];
+
+/// Error returned when a user can't be logged in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OidcError {
+    /// The request went through no [`SessionLayer`](crate::session::SessionLayer).
+    NoSession,
+    /// The discovery document or the keys of the identity provider couldn't be fetched or parsed.
+    Discovery(String),
+    /// The callback was called without a login in progress in the session.
+    NoLoginInProgress,
+    /// The state of the callback doesn't match the state of the login.
+    StateMismatch,
+    /// The identity provider returned an error to the callback.
+    Provider {
+        /// The error code.
+        error: String,
+        /// The description of the error.
+        description: Option<String>,
+    },
+    /// The code couldn't be exchanged for tokens.
+    TokenExchange(String),
+    /// The ID token is malformed, or its claims are invalid.
+    InvalidToken(String),
+    /// The ID token is signed with another algorithm than RS256 or ES256.
+    UnsupportedAlgorithm(String),
+    /// The ID token is signed with a key that the identity provider doesn't publish.
+    UnknownKey(String),
+    /// The signature of the ID token doesn't match its content.
+    InvalidSignature,
+}
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OidcError::NoSession => write!(f, "the request has no session"),
+            OidcError::Discovery(reason) => write!(f, "the identity provider is unavailable: {reason}"),
+            OidcError::NoLoginInProgress => write!(f, "no login is in progress"),
+            OidcError::StateMismatch => write!(f, "the state of the callback doesn't match the login"),
+            OidcError::Provider { error, description } => match description {
+                Some(description) => write!(f, "the identity provider returned `{error}`: {description}"),
+                None => write!(f, "the identity provider returned `{error}`"),
+            },
+            OidcError::TokenExchange(reason) => write!(f, "the code couldn't be exchanged: {reason}"),
+            OidcError::InvalidToken(reason) => write!(f, "the ID token is invalid: {reason}"),
+            OidcError::UnsupportedAlgorithm(alg) => write!(f, "the ID token is signed with `{alg}`"),
+            OidcError::UnknownKey(kid) => write!(f, "the ID token is signed with the unknown key `{kid}`"),
+            OidcError::InvalidSignature => write!(f, "the signature of the ID token is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for OidcError {}
+
+/// Client for the HTTPS calls to the identity provider.
+pub trait OidcHttpClient: Send + Sync {
+    /// Fetch the document at `url`.
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<String, Error>>;
+
+    /// Post `form` to `url`, encoded as `application/x-www-form-urlencoded`,
+    /// and return the body of the response, whatever its status.
+    fn post_form<'a>(&'a self, url: &'a str, form: &'a [(&'a str, &'a str)]) -> BoxFuture<'a, Result<String, Error>>;
+}
+
+/// A user logged in with the identity provider, in the extensions of requests
+/// that went through an [`OidcLayer`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OidcUser {
+    /// The subject of the user at the identity provider.
+    pub sub: String,
+    /// The issuer of the ID token.
+    pub iss: String,
+    /// The claims of the ID token, like `email` or `name`.
+    pub claims: Map<String, Value>,
+}
+
+impl OidcUser {
+    /// Return the claim `name` when it's a string.
+    pub fn claim(&self, name: &str) -> Option<&str> {
+        self.claims.get(name).and_then(Value::as_str)
+    }
+}
+
+/// Return the user logged in with `session`.
+pub fn user(session: &Session) -> Option<OidcUser> {
+    session.get(USER_KEY)
+}
+
+/// The result of a successful callback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcLogin {
+    /// The user, also saved in the session.
+    pub user: OidcUser,
+    /// The access token returned by the identity provider.
+    pub access_token: String,
+    /// The refresh token returned by the identity provider.
+    pub refresh_token: Option<String>,
+    /// The page to return to, given to the login.
+    pub return_to: Option<String>,
+}
+
+/// The state of a login, between the redirect to the identity provider and the callback.
+#[derive(Serialize, Deserialize)]
+struct Pending {
+    state: String,
+    nonce: String,
+    verifier: String,
+    #[serde(default)]
+    return_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    id_token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default, rename = "use")]
+    usage: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+#[derive(Clone)]
+enum PublicKey {
+    Rsa(pkcs1v15::VerifyingKey<Sha256>),
+    P256(VerifyingKey),
+}
+
+impl PublicKey {
+    fn from_jwk(jwk: &Jwk) -> Option<Self> {
+        let decode = |value: &Option<String>| URL_SAFE_NO_PAD.decode(value.as_deref()?).ok();
+        match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("RSA", _) => {
+                let n = BigUint::from_bytes_be(&decode(&jwk.n)?);
+                let e = BigUint::from_bytes_be(&decode(&jwk.e)?);
+                let key = RsaPublicKey::new(n, e).ok()?;
+                if key.size() < MIN_RSA_KEY_SIZE {
+                    return None;
+                }
+                Some(PublicKey::Rsa(pkcs1v15::VerifyingKey::new(key)))
+            }
+            ("EC", Some("P-256")) => {
+                let (x, y) = (decode(&jwk.x)?, decode(&jwk.y)?);
+                if x.len() != 32 || y.len() != 32 {
+                    return None;
+                }
+                let point = EncodedPoint::from_affine_coordinates(
+                    FieldBytes::from_slice(&x),
+                    FieldBytes::from_slice(&y),
+                    false,
+                );
+                VerifyingKey::from_encoded_point(&point).ok().map(PublicKey::P256)
+            }
+            _ => None,
+        }
+    }
+
+    fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> Result<(), OidcError> {
+        let valid = match (self, alg) {
+            (PublicKey::Rsa(key), "RS256") => pkcs1v15::Signature::try_from(signature)
+                .map_or(false, |signature| key.verify(message, &signature).is_ok()),
+            (PublicKey::P256(key), "ES256") => {
+                Signature::from_slice(signature).map_or(false, |signature| key.verify(message, &signature).is_ok())
+            }
+            _ => return Err(OidcError::UnsupportedAlgorithm(alg.to_string())),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(OidcError::InvalidSignature)
+        }
+    }
+}
+
+/// The PKCE challenge of `verifier`, with the `S256` method.
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+#[derive(Default)]
+struct Cache {
+    metadata: Option<Arc<Metadata>>,
+    keys: HashMap<String, PublicKey>,
+    keys_fetched: Option<Instant>,
+}
+
+/// An OpenID Connect identity provider, and the client registered with it.
+///
+/// Clones share the cache of the discovery document and of the keys.
+#[derive(Clone)]
+pub struct OidcProvider {
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_uri: String,
+    scopes: String,
+    leeway: Duration,
+    client: Arc<dyn OidcHttpClient>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl fmt::Debug for OidcProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcProvider")
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .field("redirect_uri", &self.redirect_uri)
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+impl OidcProvider {
+    /// Create a provider for `issuer`, with which the client `client_id` is
+    /// registered with the callback URL `redirect_uri`.
+    pub fn new(
+        issuer: impl Into<String>,
+        client_id: impl Into<String>,
+        redirect_uri: impl Into<String>,
+        client: impl OidcHttpClient + 'static,
+    ) -> Self {
+        OidcProvider {
+            issuer: issuer.into().trim_end_matches('/').to_string(),
+            client_id: client_id.into(),
+            client_secret: None,
+            redirect_uri: redirect_uri.into(),
+            scopes: "openid email profile".into(),
+            leeway: Duration::from_secs(60),
+            client: Arc::new(client),
+            cache: Arc::default(),
+        }
+    }
+
+    /// Set the secret of confidential clients, sent to the token endpoint.
+    pub fn client_secret(self, client_secret: impl Into<String>) -> Self {
+        OidcProvider {
+            client_secret: Some(client_secret.into()),
+            ..self
+        }
+    }
+
+    /// Set the scopes requested from the identity provider, `openid email profile` by default.
+    pub fn scopes(self, scopes: &[&str]) -> Self {
+        OidcProvider {
+            scopes: scopes.join(" "),
+            ..self
+        }
+    }
+
+    /// Set the tolerated difference between the clocks of the function and of
+    /// the identity provider, one minute by default.
+    pub fn leeway(self, leeway: Duration) -> Self {
+        OidcProvider { leeway, ..self }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, OidcError> {
+        let document = self
+            .client
+            .get(url)
+            .await
+            .map_err(|err| OidcError::Discovery(format!("{url}: {err}")))?;
+        serde_json::from_str(&document).map_err(|err| OidcError::Discovery(format!("{url}: {err}")))
+    }
+
+    async fn metadata(&self) -> Result<Arc<Metadata>, OidcError> {
+        if let Some(metadata) = self.cache().metadata.clone() {
+            return Ok(metadata);
+        }
+        let url = format!("{}/.well-known/openid-configuration", self.issuer);
+        let metadata: Metadata = self.fetch_json(&url).await?;
+        if metadata.issuer.trim_end_matches('/') != self.issuer {
+            return Err(OidcError::Discovery(format!("unexpected issuer `{}`", metadata.issuer)));
+        }
+        let metadata = Arc::new(metadata);
+        self.cache().metadata = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    async fn key(&self, kid: &str) -> Result<PublicKey, OidcError> {
+        let stale = {
+            let cache = self.cache();
+            if let Some(key) = cache.keys.get(kid) {
+                return Ok(key.clone());
+            }
+            cache
+                .keys_fetched
+                .map_or(true, |fetched| fetched.elapsed() >= KEYS_REFRESH_INTERVAL)
+        };
+        if !stale {
+            return Err(OidcError::UnknownKey(kid.to_string()));
+        }
+
+        let metadata = self.metadata().await?;
+        let jwks: JwkSet = self.fetch_json(&metadata.jwks_uri).await?;
+        let keys: HashMap<String, PublicKey> = jwks
+            .keys
+            .iter()
+            .filter(|jwk| jwk.usage.as_deref().map_or(true, |usage| usage == "sig"))
+            .filter_map(|jwk| Some((jwk.kid.clone().unwrap_or_default(), PublicKey::from_jwk(jwk)?)))
+            .collect();
+        let key = keys.get(kid).cloned();
+        let mut cache = self.cache();
+        cache.keys = keys;
+        cache.keys_fetched = Some(Instant::now());
+        key.ok_or_else(|| OidcError::UnknownKey(kid.to_string()))
+    }
+
+    /// Start a login: save its state in `session`, and return the URL of the
+    /// identity provider to redirect the user to.
+    pub async fn authorization_url(&self, session: &Session, return_to: Option<&str>) -> Result<String, OidcError> {
+        let metadata = self.metadata().await?;
+        let pending = Pending {
+            state: session::random_token(),
+            nonce: session::random_token(),
+            verifier: session::random_token(),
+            return_to: return_to.map(String::from),
+        };
+        let mut url = url::Url::parse(&metadata.authorization_endpoint)
+            .map_err(|err| OidcError::Discovery(format!("invalid authorization endpoint: {err}")))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri)
+            .append_pair("scope", &self.scopes)
+            .append_pair("state", &pending.state)
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("code_challenge", &pkce_challenge(&pending.verifier))
+            .append_pair("code_challenge_method", "S256");
+        session
+            .insert(PENDING_KEY, &pending)
+            .expect("the state of logins is a JSON value");
+        Ok(url.into())
+    }
+
+    /// Complete the login started by [`authorization_url`](Self::authorization_url),
+    /// with the callback request sent by the identity provider.
+    ///
+    /// The user is saved in the session, whose id is renewed.
+    pub async fn callback(&self, req: &Request) -> Result<OidcLogin, OidcError> {
+        let session = req.extensions().get::<Session>().ok_or(OidcError::NoSession)?;
+        let query: HashMap<String, String> =
+            url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                .into_owned()
+                .collect();
+        let pending: Pending = session.get(PENDING_KEY).ok_or(OidcError::NoLoginInProgress)?;
+        // A state is only good for one callback.
+        session.remove(PENDING_KEY);
+
+        if let Some(error) = query.get("error") {
+            return Err(OidcError::Provider {
+                error: error.clone(),
+                description: query.get("error_description").cloned(),
+            });
+        }
+        if query.get("state") != Some(&pending.state) {
+            return Err(OidcError::StateMismatch);
+        }
+        let code = query
+            .get("code")
+            .ok_or_else(|| OidcError::TokenExchange("the callback has no code".into()))?;
+
+        let metadata = self.metadata().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("client_id", self.client_id.as_str()),
+            ("code_verifier", pending.verifier.as_str()),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let response = self
+            .client
+            .post_form(&metadata.token_endpoint, &form)
+            .await
+            .map_err(|err| OidcError::TokenExchange(err.to_string()))?;
+        let tokens: TokenResponse =
+            serde_json::from_str(&response).map_err(|err| OidcError::TokenExchange(err.to_string()))?;
+        if let Some(error) = tokens.error {
+            return Err(OidcError::Provider {
+                error,
+                description: tokens.error_description,
+            });
+        }
+        let (id_token, access_token) = match (tokens.id_token, tokens.access_token) {
+            (Some(id_token), Some(access_token)) => (id_token, access_token),
+            _ => {
+                return Err(OidcError::TokenExchange(
+                    "the response has no ID or access token".into(),
+                ))
+            }
+        };
+
+        let user = self.verify_id_token(&id_token, Some(&pending.nonce)).await?;
+        session.renew();
+        session.insert(USER_KEY, &user).expect("users are JSON values");
+        Ok(OidcLogin {
+            user,
+            access_token,
+            refresh_token: tokens.refresh_token,
+            return_to: pending.return_to,
+        })
+    }
+
+    /// Verify the signature and the claims of an ID token issued to this client.
+    ///
+    /// The `nonce` claim must match `nonce` when it's set.
+    pub async fn verify_id_token(&self, token: &str, nonce: Option<&str>) -> Result<OidcUser, OidcError> {
+        let malformed = |reason: &str| OidcError::InvalidToken(reason.to_string());
+        let (message, signature) = token
+            .rsplit_once('.')
+            .ok_or_else(|| malformed("expected three segments"))?;
+        let (header, claims) = message
+            .split_once('.')
+            .ok_or_else(|| malformed("expected three segments"))?;
+        let decode = |segment: &str| -> Result<Map<String, Value>, OidcError> {
+            let bytes = JWT_BASE64.decode(segment).map_err(|err| malformed(&err.to_string()))?;
+            serde_json::from_slice(&bytes).map_err(|err| malformed(&err.to_string()))
+        };
+        let header = decode(header)?;
+        let mut claims = decode(claims)?;
+        let signature = JWT_BASE64
+            .decode(signature)
+            .map_err(|err| malformed(&err.to_string()))?;
+
+        let alg = header.get("alg").and_then(Value::as_str).unwrap_or_default();
+        let kid = header.get("kid").and_then(Value::as_str).unwrap_or_default();
+        self.key(kid).await?.verify(alg, message.as_bytes(), &signature)?;
+
+        let claim = |name: &str| claims.get(name).and_then(Value::as_str);
+        if claim("iss").map(|iss| iss.trim_end_matches('/')) != Some(self.issuer.as_str()) {
+            return Err(malformed("unexpected issuer"));
+        }
+        let audiences: Vec<&str> = match claims.get("aud") {
+            Some(Value::String(aud)) => vec![aud.as_str()],
+            Some(Value::Array(aud)) => aud.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !audiences.contains(&self.client_id.as_str()) {
+            return Err(malformed("the token was issued to another client"));
+        }
+        if audiences.len() > 1 && claim("azp") != Some(self.client_id.as_str()) {
+            return Err(malformed("the token was issued to another party"));
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let exp = claims.get("exp").and_then(Value::as_u64).unwrap_or_default();
+        if Duration::from_secs(exp) + self.leeway <= now {
+            return Err(malformed("the token has expired"));
+        }
+        if let Some(nonce) = nonce {
+            if claim("nonce") != Some(nonce) {
+                return Err(malformed("unexpected nonce"));
+            }
+        }
+
+        let sub = claim("sub")
+            .ok_or_else(|| malformed("the token has no subject"))?
+            .to_string();
+        let iss = self.issuer.clone();
+        for name in ["sub", "iss", "aud", "exp", "iat"].into_iter().chain(PROTOCOL_CLAIMS) {
+            claims.remove(name);
+        }
+        Ok(OidcUser { sub, iss, claims })
+    }
+}
+
+/// Whether `return_to` is a path of this site, so the login can't be used to
+/// redirect users to another one.
+fn is_local_path(return_to: &str) -> bool {
+    return_to.starts_with('/') && !return_to.starts_with("//") && !return_to.contains('\\')
+}
+
+/// A [`Layer`] that logs users in with an [`OidcProvider`].
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Debug)]
+pub struct OidcLayer {
+    provider: OidcProvider,
+    login_path: String,
+    callback_path: String,
+    logout_path: String,
+    require_login: bool,
+}
+
+impl OidcLayer {
+    /// Create a layer that logs users in with `provider`.
+    pub fn new(provider: OidcProvider) -> Self {
+        OidcLayer {
+            provider,
+            login_path: "/login".into(),
+            callback_path: "/callback".into(),
+            logout_path: "/logout".into(),
+            require_login: false,
+        }
+    }
+
+    /// Set the path that starts logins, `/login` by default.
+    pub fn login_path(self, login_path: impl Into<String>) -> Self {
+        OidcLayer {
+            login_path: login_path.into(),
+            ..self
+        }
+    }
+
+    /// Set the path of the redirect URI of the client, `/callback` by default.
+    pub fn callback_path(self, callback_path: impl Into<String>) -> Self {
+        OidcLayer {
+            callback_path: callback_path.into(),
+            ..self
+        }
+    }
+
+    /// Set the path that logs users out, `/logout` by default.
+    pub fn logout_path(self, logout_path: impl Into<String>) -> Self {
+        OidcLayer {
+            logout_path: logout_path.into(),
+            ..self
+        }
+    }
+
+    /// Redirect the `GET` requests of users that aren't logged in to the login,
+    /// and reject their other requests with `401 Unauthorized`. Disabled by default.
+    pub fn require_login(self, require_login: bool) -> Self {
+        OidcLayer { require_login, ..self }
+    }
+}
+
+impl<S> Layer<S> for OidcLayer {
+    type Service = OidcService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OidcService {
+            inner,
+            config: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that logs users in with an [`OidcProvider`].
+///
+/// See [`OidcLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct OidcService<S> {
+    inner: S,
+    config: OidcLayer,
+}
+
+fn unauthorized(message: &str) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(format!("Unauthorized: {message}")))
+        .expect("unable to build http::Response")
+}
+
+impl<S> Service<Request> for OidcService<S>
+where
+    S: Service<Request> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Into<Error>,
+{
+    type Response = Response<Body>;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        // The inner service was driven to readiness, so it's the one that must handle the request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            let session = match req.extensions().get::<Session>().cloned() {
+                Some(session) => session,
+                None => return Err(OidcError::NoSession.into()),
+            };
+            let path = req.uri().path();
+
+            if path == config.login_path {
+                let return_to = url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                    .find(|(name, _)| name == "return_to")
+                    .map(|(_, value)| value.into_owned())
+                    .filter(|return_to| is_local_path(return_to));
+                let url = config
+                    .provider
+                    .authorization_url(&session, return_to.as_deref())
+                    .await?;
+                return Ok(Redirect::to(url).into_response().await);
+            }
+            if path == config.callback_path {
+                return match config.provider.callback(&req).await {
+                    Ok(login) => {
+                        let return_to = login.return_to.unwrap_or_else(|| "/".into());
+                        Ok(Redirect::to(return_to).into_response().await)
+                    }
+                    Err(err @ OidcError::Discovery(_)) => Err(err.into()),
+                    Err(err) => {
+                        tracing::warn!(error = %err, "OIDC login failed");
+                        Ok(unauthorized("the login failed"))
+                    }
+                };
+            }
+            if path == config.logout_path {
+                session.destroy();
+                return Ok(Redirect::to("/").into_response().await);
+            }
+
+            match user(&session) {
+                Some(user) => {
+                    req.extensions_mut().insert(user);
+                }
+                None if config.require_login && req.method() == Method::GET => {
+                    let return_to = req.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/");
+                    let query = url::form_urlencoded::Serializer::new(String::new())
+                        .append_pair("return_to", return_to)
+                        .finish();
+                    let login = format!("{}?{query}", config.login_path);
+                    return Ok(Redirect::to(login).into_response().await);
+                }
+                None if config.require_login => return Ok(unauthorized("login required")),
+                None => {}
+            }
+            let response = inner.call(req).await.map_err(Into::into)?.into_response();
+            Ok(response.await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::{SessionKey, SessionLayer, MIN_SECRET_LENGTH};
+    use http::header::{COOKIE, LOCATION, SET_COOKIE};
+    use lambda_runtime::service_fn;
+    use p256::ecdsa::{signature::Signer, SigningKey};
+
+    const ISSUER: &str = "https://idp.example.com";
+
+    // A 2048 bits RSA key, in base64url.
+    const RSA_N: &str = "nQ0az0iBUydjlI8ZxiHbjvQIlc6XkJtndXAit-z7WhKgdvU9dMA09HySzqeBMjHbLJdpTe-5DKlbqWBXlkYCSCSjhys3hBYEs-rsdqaaANl-gqn4tXHBJt8uTr_uDxeNiFTD7W6OBjA2CQm3EuDjIbjqgJTAFi4ZZCrRPkVVO9s-BW8c_1IWMiWRbO_zKye1Af4dP3X23DWmF-6F698fCx7CRuuM2xLbZ6Ao3jYtc2nqh6DH4ZNC8C115v3aReJRKGqg82rUZ7bv1TxYAhZdPizJctPvrYrd1FPtjogE_RGhCj3PznqTFeZtDDdcfhHR6_20kujTBMaGcvL6PZ9sDw";
+    const RSA_D: &str = "S2mnBYDV5MWFuijK4gqRAMBqvPCyc9RDpi895J6thW9rUSPqqjy_UOTeo8_oKF-rVGqyPKPPcmaCZr8l37lnUBgT1AyrAEiIYpJ8kG7ohV7qsjiG_i2M56sEc_kSiXmwUxYM7N6I4SzWTZm2KKEHP8KpDCwhXqUtOR2cUy_8wRwBwZgxRVmjw_9DGoc8-nWJwp0QYFO-nFew1xGDQZj0BrCZQqPJCSIhWnr5o2EXqE9IlTxWajtepTFD6Xihwh7B8HJON2ocsdx-6kkRccDEAvanS6HWSlmHSme-f9iiTDxV1yZMtS46NDTZySxbWyV63tgzTgRDChkg2pk9gxXoBQ";
+
+    #[test]
+    fn computes_pkce_challenges() {
+        // From RFC 7636, appendix B.
+        assert_eq!(
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk")
+        );
+    }
+
+    #[test]
+    fn verifies_rs256_signatures() {
+        use rsa::{pkcs1v15::SigningKey, signature::SignatureEncoding, RsaPrivateKey};
+
+        let bigint = |value: &str| BigUint::from_bytes_be(&URL_SAFE_NO_PAD.decode(value).unwrap());
+        let private_key =
+            RsaPrivateKey::from_components(bigint(RSA_N), BigUint::from(65537u32), bigint(RSA_D), vec![]).unwrap();
+        let jwk: Jwk = serde_json::from_value(serde_json::json!({"kty": "RSA", "n": RSA_N, "e": "AQAB"})).unwrap();
+        let key = PublicKey::from_jwk(&jwk).unwrap();
+
+        let signature = SigningKey::<Sha256>::new(private_key).sign(b"header.claims").to_vec();
+        assert_eq!(Ok(()), key.verify("RS256", b"header.claims", &signature));
+        assert_eq!(
+            Err(OidcError::InvalidSignature),
+            key.verify("RS256", b"header.forged", &signature)
+        );
+        assert_eq!(
+            Err(OidcError::InvalidSignature),
+            key.verify("RS256", b"header.claims", &signature[1..])
+        );
+    }
+
+    struct Provider {
+        key: SigningKey,
+        nonce: Mutex<String>,
+    }
+
+    impl Provider {
+        fn id_token(&self, claims: Value) -> String {
+            let header = URL_SAFE_NO_PAD.encode(br#"{"alg":"ES256","kid":"key-1"}"#);
+            let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+            let message = format!("{header}.{claims}");
+            let signature: Signature = self.key.sign(message.as_bytes());
+            format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature.to_bytes()))
+        }
+    }
+
+    impl OidcHttpClient for Arc<Provider> {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<String, Error>> {
+            let document = match url {
+                "https://idp.example.com/.well-known/openid-configuration" => serde_json::json!({
+                    "issuer": ISSUER,
+                    "authorization_endpoint": "https://idp.example.com/authorize",
+                    "token_endpoint": "https://idp.example.com/token",
+                    "jwks_uri": "https://idp.example.com/jwks",
+                }),
+                "https://idp.example.com/jwks" => {
+                    let point = self.key.verifying_key().to_encoded_point(false);
+                    serde_json::json!({"keys": [{
+                        "kty": "EC",
+                        "crv": "P-256",
+                        "kid": "key-1",
+                        "x": URL_SAFE_NO_PAD.encode(point.x().unwrap()),
+                        "y": URL_SAFE_NO_PAD.encode(point.y().unwrap()),
+                    }]})
+                }
+                _ => return Box::pin(async move { Err(format!("unexpected url {url}").into()) }),
+            };
+            Box::pin(async move { Ok(document.to_string()) })
+        }
+
+        fn post_form<'a>(
+            &'a self,
+            url: &'a str,
+            form: &'a [(&'a str, &'a str)],
+        ) -> BoxFuture<'a, Result<String, Error>> {
+            assert_eq!("https://idp.example.com/token", url);
+            assert!(form.contains(&("code", "code-1")));
+            assert!(form.contains(&("client_secret", "secret")));
+            assert!(form.iter().any(|(name, _)| *name == "code_verifier"));
+            let id_token = self.id_token(serde_json::json!({
+                "iss": ISSUER,
+                "aud": "client-1",
+                "sub": "user-1",
+                "email": "user@example.com",
+                "exp": 4102444800u64,
+                "nonce": *self.nonce.lock().unwrap(),
+            }));
+            let response = serde_json::json!({"id_token": id_token, "access_token": "access-1"});
+            Box::pin(async move { Ok(response.to_string()) })
+        }
+    }
+
+    fn get(path: &str, cookie: Option<&str>) -> Request {
+        let mut builder = http::Request::builder().uri(path);
+        if let Some(cookie) = cookie {
+            builder = builder.header(COOKIE, cookie);
+        }
+        builder.body(Body::Empty).unwrap()
+    }
+
+    fn session_cookie(res: &Response<Body>) -> String {
+        let set_cookie = res.headers()[SET_COOKIE].to_str().unwrap();
+        set_cookie.split(';').next().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn logs_users_in() {
+        let idp = Arc::new(Provider {
+            key: SigningKey::from_slice(&[9; 32]).unwrap(),
+            nonce: Mutex::default(),
+        });
+        let provider = OidcProvider::new(ISSUER, "client-1", "https://app.example.com/callback", idp.clone())
+            .client_secret("secret");
+        let handler = service_fn(|req: Request| async move {
+            let user = req.extensions().get::<OidcUser>().unwrap();
+            Ok::<_, Error>(format!("{} {}", user.sub, user.claim("email").unwrap_or_default()))
+        });
+        let key = SessionKey::new(&[1; MIN_SECRET_LENGTH]).unwrap();
+        let mut service =
+            SessionLayer::encrypted(key).layer(OidcLayer::new(provider).require_login(true).layer(handler));
+
+        let res = service.call(get("/orders?page=2", None)).await.unwrap();
+        assert_eq!(StatusCode::SEE_OTHER, res.status());
+        assert_eq!("/login?return_to=%2Forders%3Fpage%3D2", res.headers()[LOCATION]);
+
+        let res = service.call(get("/login?return_to=%2Forders", None)).await.unwrap();
+        let location = url::Url::parse(res.headers()[LOCATION].to_str().unwrap()).unwrap();
+        assert!(location
+            .as_str()
+            .starts_with("https://idp.example.com/authorize?response_type=code"));
+        let query: HashMap<String, String> = location.query_pairs().into_owned().collect();
+        assert_eq!("S256", query["code_challenge_method"]);
+        *idp.nonce.lock().unwrap() = query["nonce"].clone();
+        let cookie = session_cookie(&res);
+
+        let forged = service
+            .call(get("/callback?code=code-1&state=forged", Some(&cookie)))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, forged.status());
+
+        let callback = format!("/callback?code=code-1&state={}", query["state"]);
+        let res = service.call(get(&callback, Some(&cookie))).await.unwrap();
+        assert_eq!(StatusCode::SEE_OTHER, res.status());
+        assert_eq!("/orders", res.headers()[LOCATION]);
+        let cookie = session_cookie(&res);
+
+        let res = service.call(get("/orders", Some(&cookie))).await.unwrap();
+        assert_eq!("user-1 user@example.com", std::str::from_utf8(res.body()).unwrap());
+
+        // The state was used, the callback can't be replayed.
+        let res = service.call(get(&callback, Some(&cookie))).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+    }
+
+    #[test]
+    fn only_returns_to_local_paths() {
+        assert!(is_local_path("/orders?page=2"));
+        assert!(!is_local_path("//evil.example.com"));
+        assert!(!is_local_path("/\\evil.example.com"));
+        assert!(!is_local_path("https://evil.example.com"));
+    }
+}
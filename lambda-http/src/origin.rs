@@ -0,0 +1,233 @@
+//! Support for triggers that send HTTP requests in their own format.
+//!
+//! `lambda_http` understands the events of API Gateway, Function URLs and
+//! Application Load Balancers. Gateways that send their own format, for
+//! example an in-house gateway that wraps API Gateway events with its own
+//! metadata, can be supported with an [`Origin`]: it recognizes the payloads of
+//! the gateway, converts them into requests, and picks the format of the
+//! responses.
+//!
+//! [`Origins`] tries the registered origins in order, and falls back to the
+//! built-in triggers when none of them recognizes a payload. Start the runtime
+//! with [`run_with_origins`](crate::run_with_origins).
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{
+//!     origin::{Origin, Origins},
+//!     request::{self, RequestOrigin},
+//!     service_fn, Error, Request,
+//! };
+//! use serde_json::Value;
+//!
+//! /// A gateway that sends `{"gateway": {...}, "event": <API Gateway event>}`.
+//! struct CorporateGateway;
+//!
+//! impl Origin for CorporateGateway {
+//!     fn matches(&self, payload: &Value) -> bool {
+//!         payload.get("gateway").is_some()
+//!     }
+//!
+//!     fn to_request(&self, mut payload: Value) -> Result<Request, Error> {
+//!         let mut req = request::from_value(payload["event"].take())?;
+//!         if let Some(team) = payload["gateway"]["team"].as_str() {
+//!             req.headers_mut().insert("x-team", team.parse()?);
+//!         }
+//!         Ok(req)
+//!     }
+//!
+//!     fn response_format(&self) -> RequestOrigin {
+//!         RequestOrigin::ApiGatewayV1
+//!     }
+//! }
+//!
+//! async fn hello(_req: Request) -> Result<&'static str, Error> {
+//!     Ok("hello")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let origins = Origins::new().register(CorporateGateway);
+//!     lambda_http::run_with_origins(service_fn(hello), origins).await
+//! }
+//! ```
+use crate::{
+    ext::RequestExt,
+    parse::{diagnose, ParseError},
+    request::{LambdaRequest, RequestOrigin},
+    response::LambdaResponse,
+    Error, IntoResponse, Request,
+};
+use futures::future::BoxFuture;
+use lambda_runtime::{LambdaEvent, Service};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+};
+
+/// A trigger that sends HTTP requests in its own format.
+pub trait Origin: Send + Sync {
+    /// Return whether `payload` was sent by this trigger.
+    fn matches(&self, payload: &Value) -> bool;
+
+    /// Convert a payload sent by this trigger into a request.
+    fn to_request(&self, payload: Value) -> Result<Request, Error>;
+
+    /// Return the built-in trigger whose response format this trigger expects.
+    fn response_format(&self) -> RequestOrigin;
+}
+
+/// The origins that payloads are matched against, before the built-in triggers.
+#[derive(Clone, Default)]
+pub struct Origins {
+    custom: Vec<Arc<dyn Origin>>,
+}
+
+impl Origins {
+    /// Create a set with only the built-in triggers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match payloads against `origin`, after the origins already registered.
+    pub fn register(mut self, origin: impl Origin + 'static) -> Self {
+        self.custom.push(Arc::new(origin));
+        self
+    }
+
+    /// Convert `payload` into a request, and return the format of its response.
+    pub fn parse(&self, payload: Value) -> Result<(RequestOrigin, Request), Error> {
+        if let Some(origin) = self.custom.iter().find(|origin| origin.matches(&payload)) {
+            return Ok((origin.response_format(), origin.to_request(payload)?));
+        }
+        match LambdaRequest::deserialize(&payload) {
+            Ok(req) => Ok((req.request_origin(), req.into())),
+            Err(err) => Err(ParseError {
+                diagnosis: diagnose(&payload),
+                source: Some(err),
+            }
+            .into()),
+        }
+    }
+}
+
+impl fmt::Debug for Origins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Origins").field("custom", &self.custom.len()).finish()
+    }
+}
+
+/// Wraps a `Service<Request>` in a `Service<LambdaEvent<Value>>` that converts events with [`Origins`].
+///
+/// This is completely internal to the `lambda_http::run_with_origins` function.
+#[doc(hidden)]
+pub struct OriginAdapter<S> {
+    service: S,
+    origins: Origins,
+}
+
+impl<S> OriginAdapter<S> {
+    pub(crate) fn new(service: S, origins: Origins) -> Self {
+        OriginAdapter { service, origins }
+    }
+}
+
+impl<S> Service<LambdaEvent<Value>> for OriginAdapter<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Into<Error>,
+{
+    type Response = LambdaResponse;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<LambdaResponse, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, event: LambdaEvent<Value>) -> Self::Future {
+        let (payload, context) = event.into_parts();
+        let (origin, req) = match self.origins.parse(payload) {
+            Ok(parsed) => parsed,
+            Err(err) => return Box::pin(async move { Err(err) }),
+        };
+        let fut = self.service.call(req.with_lambda_context(context));
+
+        Box::pin(async move {
+            let response = fut.await.map_err(Into::into)?.into_response();
+            Ok(LambdaResponse::from_response(&origin, response.await))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request;
+    use lambda_runtime::{service_fn, Context};
+    use serde_json::json;
+
+    struct Wrapped;
+
+    impl Origin for Wrapped {
+        fn matches(&self, payload: &Value) -> bool {
+            payload.get("gateway").is_some()
+        }
+
+        fn to_request(&self, mut payload: Value) -> Result<Request, Error> {
+            let mut req = request::from_value(payload["event"].take())?;
+            let team = payload["gateway"]["team"].as_str().unwrap_or_default();
+            req.headers_mut().insert("x-team", team.parse()?);
+            Ok(req)
+        }
+
+        fn response_format(&self) -> RequestOrigin {
+            RequestOrigin::ApiGatewayV1
+        }
+    }
+
+    fn alb_request() -> Value {
+        serde_json::from_str(include_str!("../tests/data/alb_request.json")).unwrap()
+    }
+
+    #[test]
+    fn custom_origins_are_tried_first() {
+        let origins = Origins::new().register(Wrapped);
+        let payload = json!({ "gateway": { "team": "payments" }, "event": alb_request() });
+        let (origin, req) = origins.parse(payload).unwrap();
+        assert_eq!(RequestOrigin::ApiGatewayV1, origin);
+        assert_eq!("payments", req.headers()["x-team"]);
+        assert_eq!("/", req.uri().path());
+    }
+
+    #[test]
+    fn falls_back_to_builtin_origins() {
+        let (origin, _) = Origins::new().register(Wrapped).parse(alb_request()).unwrap();
+        assert_eq!(RequestOrigin::Alb, origin);
+
+        let err = Origins::new().parse(json!({ "httpMethod": 42 })).unwrap_err();
+        assert!(err.downcast_ref::<ParseError>().is_some(), "{err}");
+    }
+
+    #[tokio::test]
+    async fn responds_in_the_format_of_the_origin() {
+        let service =
+            service_fn(
+                |req: Request| async move { Ok::<_, Error>(req.headers()["x-team"].to_str().unwrap().to_string()) },
+            );
+        let mut adapter = OriginAdapter::new(service, Origins::new().register(Wrapped));
+        let payload = json!({ "gateway": { "team": "payments" }, "event": alb_request() });
+        let response = adapter
+            .call(LambdaEvent::new(payload, Context::default()))
+            .await
+            .unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        assert_eq!(json!("payments"), response["body"]);
+        assert!(response.get("statusDescription").is_none());
+    }
+}
@@ -0,0 +1,353 @@
+//! Signed continuation tokens for list endpoints.
+//!
+//! List endpoints often hand out the position of the next page as a base64
+//! offset, which clients can decode and tamper with to skip access checks or
+//! scan the whole table. A [`Paginator`] signs the position with HMAC-SHA256
+//! instead: tokens are tamper-proof, not secret. Clients can still decode the
+//! position, so don't put anything in it that they must not see, but tokens
+//! that weren't issued by the function, or that have expired, are rejected
+//! with a [`CursorError`].
+//!
+//! The position can be any serializable type, like the last key returned by a
+//! DynamoDB query. [`Paginator::cursor`] reads it back from the `cursor` query
+//! parameter of the next request, and [`Paginator::page`] builds a [`Page`]
+//! response with the items of the page and the token of the next one.
+//!
+//! The signing key must be the same in every execution environment. Load it
+//! from an environment variable with [`Paginator::from_env`], or fetch it from
+//! SSM Parameter Store or Secrets Manager during the initialization of the
+//! function, and pass it to [`Paginator::new`].
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{pagination::{Page, Paginator}, service_fn, Error, Request};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct After {
+//!     id: u64,
+//! }
+//!
+//! async fn list(paginator: &Paginator, req: Request) -> Result<Page<u64>, Error> {
+//!     let start = paginator.cursor::<After>(&req)?.map(|after| after.id + 1).unwrap_or(0);
+//!     let items: Vec<u64> = (start..start + paginator.limit(&req) as u64).collect();
+//!     let next = items.last().map(|id| After { id: *id });
+//!     Ok(paginator.page(items, next.as_ref()))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let paginator = Paginator::from_env("PAGINATION_KEY")?;
+//!     lambda_http::run(service_fn(|req| list(&paginator, req))).await
+//! }
+//! ```
+use crate::{
+    ext::RequestExt, problem::ProblemDetails, response::ResponseFuture, Error, IntoResponse, Request, Response,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use http::{header::CONTENT_TYPE, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    env, fmt,
+    future::ready,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Error returned when a continuation token can't be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorError {
+    /// The token isn't a token issued by a [`Paginator`].
+    Malformed,
+    /// The signature of the token doesn't match, it was forged or signed with another key.
+    InvalidSignature,
+    /// The token is older than the time to live of the paginator, or was
+    /// issued without an expiry before the time to live was set.
+    Expired,
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CursorError::Malformed => write!(f, "the pagination cursor is malformed"),
+            CursorError::InvalidSignature => write!(f, "the pagination cursor has an invalid signature"),
+            CursorError::Expired => write!(f, "the pagination cursor has expired"),
+        }
+    }
+}
+
+impl std::error::Error for CursorError {}
+
+impl From<CursorError> for ProblemDetails {
+    fn from(err: CursorError) -> Self {
+        ProblemDetails::new(StatusCode::BAD_REQUEST).with_detail(err.to_string())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<C> {
+    #[serde(rename = "c")]
+    cursor: C,
+    #[serde(rename = "e", default, skip_serializing_if = "Option::is_none")]
+    expires: Option<u64>,
+}
+
+/// Signs and verifies the continuation tokens of list endpoints.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone)]
+pub struct Paginator {
+    key: Vec<u8>,
+    ttl: Option<Duration>,
+    cursor_param: String,
+    limit_param: String,
+    default_limit: usize,
+    max_limit: usize,
+}
+
+impl fmt::Debug for Paginator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Paginator")
+            .field("ttl", &self.ttl)
+            .field("cursor_param", &self.cursor_param)
+            .field("limit_param", &self.limit_param)
+            .field("default_limit", &self.default_limit)
+            .field("max_limit", &self.max_limit)
+            .finish()
+    }
+}
+
+impl Paginator {
+    /// Create a paginator that signs tokens with `key`.
+    ///
+    /// Pages have 20 items by default, and at most 100.
+    pub fn new(key: impl AsRef<[u8]>) -> Self {
+        Paginator {
+            key: key.as_ref().to_vec(),
+            ttl: None,
+            cursor_param: "cursor".into(),
+            limit_param: "limit".into(),
+            default_limit: 20,
+            max_limit: 100,
+        }
+    }
+
+    /// Create a paginator that signs tokens with the key in the environment variable `var`.
+    pub fn from_env(var: &str) -> Result<Self, Error> {
+        match env::var(var) {
+            Ok(key) if !key.is_empty() => Ok(Paginator::new(key)),
+            _ => Err(format!("the pagination key variable `{var}` is missing or empty").into()),
+        }
+    }
+
+    /// Reject tokens issued more than `ttl` ago, and tokens without an expiry.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        Paginator { ttl: Some(ttl), ..self }
+    }
+
+    /// Read the tokens from the query parameter `name`, `cursor` by default.
+    pub fn cursor_param(self, name: impl Into<String>) -> Self {
+        Paginator {
+            cursor_param: name.into(),
+            ..self
+        }
+    }
+
+    /// Read the page size from the query parameter `name`, `limit` by default.
+    pub fn limit_param(self, name: impl Into<String>) -> Self {
+        Paginator {
+            limit_param: name.into(),
+            ..self
+        }
+    }
+
+    /// Use pages of `default_limit` items when the request doesn't set a size, and of
+    /// at most `max_limit` items.
+    pub fn limits(self, default_limit: usize, max_limit: usize) -> Self {
+        let max_limit = max_limit.max(1);
+        Paginator {
+            default_limit: default_limit.clamp(1, max_limit),
+            max_limit,
+            ..self
+        }
+    }
+
+    /// Sign `cursor` into a token, that clients can read but not change.
+    pub fn encode<C: Serialize>(&self, cursor: &C) -> String {
+        let expires = self.ttl.map(|ttl| {
+            (SystemTime::now() + ttl)
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        });
+        let payload = serde_json::to_vec(&Envelope { cursor, expires }).expect("unable to serialize the cursor");
+        let signature = self.mac(&payload).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// Verify a token, and return the cursor signed in it.
+    pub fn decode<C: DeserializeOwned>(&self, token: &str) -> Result<C, CursorError> {
+        let (payload, signature) = token.split_once('.').ok_or(CursorError::Malformed)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| CursorError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| CursorError::Malformed)?;
+        self.mac(&payload)
+            .verify_slice(&signature)
+            .map_err(|_| CursorError::InvalidSignature)?;
+
+        let envelope: Envelope<C> = serde_json::from_slice(&payload).map_err(|_| CursorError::Malformed)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        match (envelope.expires, self.ttl) {
+            (Some(expires), _) if expires <= now => Err(CursorError::Expired),
+            // Tokens issued before the ttl was set would never expire.
+            (None, Some(_)) => Err(CursorError::Expired),
+            _ => Ok(envelope.cursor),
+        }
+    }
+
+    /// Return the cursor of the token sent in the query string of `req`, `None` for the first page.
+    pub fn cursor<C: DeserializeOwned>(&self, req: &Request) -> Result<Option<C>, CursorError> {
+        req.query_string_parameters_ref()
+            .and_then(|params| params.first(&self.cursor_param))
+            .filter(|token| !token.is_empty())
+            .map(|token| self.decode(token))
+            .transpose()
+    }
+
+    /// Return the page size requested in the query string of `req`, bounded by the limits of the paginator.
+    pub fn limit(&self, req: &Request) -> usize {
+        req.query_string_parameters_ref()
+            .and_then(|params| params.first(&self.limit_param))
+            .and_then(|limit| limit.parse::<usize>().ok())
+            .map(|limit| limit.clamp(1, self.max_limit))
+            .unwrap_or(self.default_limit)
+    }
+
+    /// Build a page with `items`, and the token of `next`, the cursor of the next page, if any.
+    pub fn page<T, C: Serialize>(&self, items: Vec<T>, next: Option<&C>) -> Page<T> {
+        Page {
+            items,
+            next_cursor: next.map(|cursor| self.encode(cursor)),
+        }
+    }
+
+    fn mac(&self, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any size");
+        mac.update(payload);
+        mac
+    }
+}
+
+/// A page of items, and the token of the next page.
+///
+/// It's sent as a JSON object: `{"items": [...], "next_cursor": "..."}`, without
+/// `next_cursor` on the last page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Page<T> {
+    /// The items of the page.
+    pub items: Vec<T>,
+    /// The token of the next page, `None` on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+impl<T: Serialize> IntoResponse for Page<T> {
+    fn into_response(self) -> ResponseFuture {
+        let response = Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&self).expect("unable to serialize Page").into())
+            .expect("unable to build http::Response");
+        Box::pin(ready(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use aws_lambda_events::query_map::QueryMap;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct After {
+        id: u64,
+    }
+
+    fn request(params: &[(&str, &str)]) -> Request {
+        let params: HashMap<String, Vec<String>> = params
+            .iter()
+            .map(|(name, value)| (name.to_string(), vec![value.to_string()]))
+            .collect();
+        Request::default().with_query_string_parameters(QueryMap::from(params))
+    }
+
+    #[test]
+    fn round_trips_signed_cursors() {
+        let paginator = Paginator::new("secret");
+        let token = paginator.encode(&After { id: 42 });
+        assert_eq!(After { id: 42 }, paginator.decode(&token).unwrap());
+        assert_eq!(
+            Some(After { id: 42 }),
+            paginator.cursor(&request(&[("cursor", &token)])).unwrap()
+        );
+        assert_eq!(None, paginator.cursor::<After>(&request(&[])).unwrap());
+
+        let forged = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(br#"{"c":{"id":0}}"#),
+            token.split_once('.').unwrap().1
+        );
+        assert_eq!(Err(CursorError::InvalidSignature), paginator.decode::<After>(&forged));
+        assert_eq!(
+            Err(CursorError::InvalidSignature),
+            Paginator::new("other").decode::<After>(&token)
+        );
+        assert_eq!(Err(CursorError::Malformed), paginator.decode::<After>("42"));
+
+        let expired = Paginator::new("secret").ttl(Duration::ZERO).encode(&After { id: 1 });
+        assert_eq!(Err(CursorError::Expired), paginator.decode::<After>(&expired));
+
+        let with_ttl = Paginator::new("secret").ttl(Duration::from_secs(60));
+        assert_eq!(
+            After { id: 1 },
+            with_ttl.decode(&with_ttl.encode(&After { id: 1 })).unwrap()
+        );
+        assert_eq!(Err(CursorError::Expired), with_ttl.decode::<After>(&token));
+    }
+
+    #[test]
+    fn bounds_the_page_size() {
+        let paginator = Paginator::new("secret").limits(10, 50);
+        assert_eq!(10, paginator.limit(&request(&[])));
+        assert_eq!(25, paginator.limit(&request(&[("limit", "25")])));
+        assert_eq!(50, paginator.limit(&request(&[("limit", "1000")])));
+        assert_eq!(1, paginator.limit(&request(&[("limit", "0")])));
+        assert_eq!(10, paginator.limit(&request(&[("limit", "ten")])));
+    }
+
+    #[tokio::test]
+    async fn pages_are_json_responses() {
+        let paginator = Paginator::new("secret");
+        let res = paginator.page(vec![1, 2], Some(&After { id: 2 })).into_response().await;
+        assert_eq!("application/json", res.headers()[CONTENT_TYPE]);
+        let body: serde_json::Value = match res.body() {
+            Body::Text(text) => serde_json::from_str(text).unwrap(),
+            body => panic!("unexpected body {body:?}"),
+        };
+        assert_eq!(serde_json::json!([1, 2]), body["items"]);
+        let next = body["next_cursor"].as_str().unwrap();
+        assert_eq!(After { id: 2 }, paginator.decode(next).unwrap());
+
+        let res = paginator.page::<u8, After>(vec![], None).into_response().await;
+        assert_eq!(&Body::Text(r#"{"items":[]}"#.into()), res.body());
+    }
+}
@@ -0,0 +1,351 @@
+//! Parsing modes for the events sent by API Gateway and Application Load Balancers.
+//!
+//! `lambda_http` tries every supported trigger until one of them accepts the
+//! payload, and fills the fields that a payload doesn't have with defaults.
+//! That's convenient, but when a payload doesn't match any trigger, the error
+//! only says so, and a payload that is missing an important field, like the
+//! HTTP method, is still turned into a request.
+//!
+//! [`ParseMode::Strict`] rejects payloads that are missing the fields that
+//! identify a request, and [`RequestCodec`] reports, in both modes, which
+//! trigger the payload looks like, why, and what it is missing. The same
+//! information is available with [`diagnose`], to debug payloads captured from
+//! the logs.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{parse::ParseMode, service_fn, Error, Request};
+//!
+//! async fn hello(_req: Request) -> Result<&'static str, Error> {
+//!     Ok("hello")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run_with_parse_mode(service_fn(hello), ParseMode::Strict).await
+//! }
+//! ```
+use crate::request::RequestOrigin;
+use lambda_runtime::{
+    codec::{Codec, JsonCodec},
+    Error,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+/// How payloads that don't have all the fields of a trigger are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Fill the missing fields with defaults.
+    #[default]
+    Lenient,
+    /// Reject payloads that are missing the fields that identify a request.
+    Strict,
+}
+
+/// Explanation of the trigger that a payload looks like.
+#[derive(Debug, Clone, Default)]
+pub struct OriginDiagnosis {
+    /// Trigger that the payload looks like, if any.
+    pub origin: Option<RequestOrigin>,
+    /// Why the payload looks like that trigger.
+    pub evidence: Vec<String>,
+    /// Fields that the trigger requires, and that the payload doesn't have.
+    pub missing: Vec<String>,
+}
+
+impl OriginDiagnosis {
+    /// Return whether the payload looks like a trigger, and has all its required fields.
+    pub fn is_complete(&self) -> bool {
+        self.origin.is_some() && self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for OriginDiagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let origin = match &self.origin {
+            Some(origin) => origin_name(origin),
+            None => return write!(f, "payload did not match any known event"),
+        };
+        write!(
+            f,
+            "payload looks like {origin} event because {}",
+            self.evidence.join(", ")
+        )?;
+        if !self.missing.is_empty() {
+            let missing: Vec<String> = self.missing.iter().map(|field| format!("`{field}`")).collect();
+            write!(f, ", but is missing {}", missing.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn origin_name(origin: &RequestOrigin) -> &'static str {
+    match origin {
+        #[cfg(feature = "apigw_rest")]
+        RequestOrigin::ApiGatewayV1 => "an API Gateway REST API",
+        #[cfg(feature = "apigw_http")]
+        RequestOrigin::ApiGatewayV2 => "an API Gateway HTTP API or Function URL",
+        #[cfg(feature = "alb")]
+        RequestOrigin::Alb => "an Application Load Balancer",
+        #[cfg(feature = "apigw_websockets")]
+        RequestOrigin::WebSocket => "an API Gateway WebSocket API",
+        #[cfg(feature = "vpc_lattice")]
+        RequestOrigin::VpcLattice => "a VPC Lattice service",
+    }
+}
+
+/// Explain which trigger `payload` looks like, and which of its required fields are missing.
+///
+/// The checks run in this order:
+///
+/// * `method` is only sent by VPC Lattice.
+/// * `requestContext.elb` is only sent by Application Load Balancers.
+/// * `requestContext.connectionId` or `requestContext.eventType` are only sent by WebSocket APIs.
+/// * `version` set to `2.0`, or `requestContext.http`, are only sent by HTTP APIs and Function URLs.
+/// * `httpMethod` without any of the above is sent by REST APIs.
+pub fn diagnose(payload: &Value) -> OriginDiagnosis {
+    let context = &payload["requestContext"];
+    let mut diagnosis = OriginDiagnosis::default();
+
+    #[cfg(feature = "vpc_lattice")]
+    if !payload["method"].is_null() {
+        diagnosis.evidence.push("`method` is present".to_string());
+        let required: &[&str] = match payload["version"] == "2.0" {
+            true => &["method", "path", "requestContext"],
+            false => &["method", "raw_path"],
+        };
+        return diagnosis.check(RequestOrigin::VpcLattice, payload, required);
+    }
+
+    #[cfg(feature = "alb")]
+    if !context["elb"].is_null() {
+        diagnosis.evidence.push("`requestContext.elb` is present".to_string());
+        return diagnosis.check(
+            RequestOrigin::Alb,
+            payload,
+            &["httpMethod", "path", "requestContext.elb.targetGroupArn"],
+        );
+    }
+
+    #[cfg(feature = "apigw_websockets")]
+    for field in ["connectionId", "eventType"] {
+        if !context[field].is_null() {
+            diagnosis.evidence.push(format!("`requestContext.{field}` is present"));
+        }
+    }
+    #[cfg(feature = "apigw_websockets")]
+    if !diagnosis.evidence.is_empty() {
+        return diagnosis.check(
+            RequestOrigin::WebSocket,
+            payload,
+            &[
+                "requestContext.connectionId",
+                "requestContext.eventType",
+                "requestContext.routeKey",
+            ],
+        );
+    }
+
+    #[cfg(feature = "apigw_http")]
+    {
+        if payload["version"] == "2.0" {
+            diagnosis.evidence.push("`version` is `2.0`".to_string());
+        }
+        if !context["http"].is_null() {
+            diagnosis.evidence.push("`requestContext.http` is present".to_string());
+        }
+        if !diagnosis.evidence.is_empty() {
+            return diagnosis.check(
+                RequestOrigin::ApiGatewayV2,
+                payload,
+                &["rawPath", "requestContext.http.method"],
+            );
+        }
+    }
+
+    #[cfg(feature = "apigw_rest")]
+    if !payload["httpMethod"].is_null() {
+        diagnosis.evidence.push("`httpMethod` is present".to_string());
+        return diagnosis.check(
+            RequestOrigin::ApiGatewayV1,
+            payload,
+            &["httpMethod", "path", "requestContext"],
+        );
+    }
+
+    diagnosis
+}
+
+impl OriginDiagnosis {
+    fn check(mut self, origin: RequestOrigin, payload: &Value, required: &[&str]) -> Self {
+        self.origin = Some(origin);
+        self.missing = required
+            .iter()
+            .filter(|field| field.split('.').fold(payload, |value, key| &value[key]).is_null())
+            .map(|field| field.to_string())
+            .collect();
+        self
+    }
+}
+
+/// Error returned when a payload can't be parsed into a request.
+#[derive(Debug)]
+pub struct ParseError {
+    pub(crate) diagnosis: OriginDiagnosis,
+    pub(crate) source: Option<serde_json::Error>,
+}
+
+impl ParseError {
+    /// Return the diagnosis of the payload.
+    pub fn diagnosis(&self) -> &OriginDiagnosis {
+        &self.diagnosis
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.diagnosis)?;
+        if let Some(source) = &self.source {
+            write!(f, ": {source}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_ref().map(|err| err as _)
+    }
+}
+
+/// A [`Codec`] that parses events in a [`ParseMode`], and explains the payloads it rejects.
+///
+/// Responses are encoded with [`JsonCodec`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestCodec {
+    mode: ParseMode,
+    json: JsonCodec,
+}
+
+impl RequestCodec {
+    /// Create a codec that parses events in `mode`.
+    pub fn new(mode: ParseMode) -> Self {
+        RequestCodec {
+            mode,
+            json: JsonCodec::new(),
+        }
+    }
+}
+
+impl Codec for RequestCodec {
+    fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T, Error> {
+        let payload: Value = self.json.decode(body)?;
+        if self.mode == ParseMode::Strict {
+            let diagnosis = diagnose(&payload);
+            if !diagnosis.is_complete() {
+                return Err(ParseError {
+                    diagnosis,
+                    source: None,
+                }
+                .into());
+            }
+        }
+        T::deserialize(&payload).map_err(|err| {
+            ParseError {
+                diagnosis: diagnose(&payload),
+                source: Some(err),
+            }
+            .into()
+        })
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        self.json.encode(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::LambdaRequest;
+    use serde_json::json;
+
+    fn fixture(name: &str) -> Value {
+        let path = format!("tests/data/{name}.json");
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn fixtures_are_complete() {
+        for (name, origin) in [
+            ("alb_request", RequestOrigin::Alb),
+            ("apigw_proxy_request", RequestOrigin::ApiGatewayV1),
+            ("apigw_v2_proxy_request_minimal", RequestOrigin::ApiGatewayV2),
+            ("lambda_function_url_request", RequestOrigin::ApiGatewayV2),
+            ("vpc_lattice_v1_request", RequestOrigin::VpcLattice),
+            ("vpc_lattice_v2_request", RequestOrigin::VpcLattice),
+        ] {
+            let diagnosis = diagnose(&fixture(name));
+            assert!(diagnosis.is_complete(), "{name}: {diagnosis}");
+            assert_eq!(Some(origin), diagnosis.origin);
+        }
+    }
+
+    #[test]
+    fn explains_missing_fields() {
+        let mut payload = fixture("alb_request");
+        payload.as_object_mut().unwrap().remove("httpMethod");
+        let diagnosis = diagnose(&payload);
+        assert_eq!(
+            "payload looks like an Application Load Balancer event because `requestContext.elb` is present, \
+             but is missing `httpMethod`",
+            diagnosis.to_string()
+        );
+    }
+
+    #[test]
+    fn strict_mode_rejects_incomplete_payloads() {
+        let body = serde_json::to_vec(&json!({
+            "version": "2.0",
+            "requestContext": { "http": { "method": "GET" } },
+        }))
+        .unwrap();
+
+        let lenient = RequestCodec::new(ParseMode::Lenient).decode::<LambdaRequest>(&body);
+        assert!(lenient.is_ok(), "{lenient:?}");
+
+        let err = RequestCodec::new(ParseMode::Strict)
+            .decode::<LambdaRequest>(&body)
+            .unwrap_err();
+        let err = err.downcast::<ParseError>().unwrap();
+        assert_eq!(vec!["rawPath"], err.diagnosis().missing);
+    }
+
+    #[test]
+    fn strict_mode_rejects_unknown_payloads() {
+        // Every field of WebSocket events is optional, so they accept anything.
+        let body = br#"{"Records": []}"#;
+        let lenient = RequestCodec::new(ParseMode::Lenient).decode::<LambdaRequest>(body);
+        assert!(matches!(lenient, Ok(LambdaRequest::WebSocket(_))), "{lenient:?}");
+
+        let err = RequestCodec::new(ParseMode::Strict)
+            .decode::<LambdaRequest>(body)
+            .unwrap_err();
+        assert_eq!("payload did not match any known event", err.to_string());
+    }
+
+    #[test]
+    fn lenient_mode_explains_failures() {
+        let body = br#"{"httpMethod": 42}"#;
+        let err = RequestCodec::new(ParseMode::Lenient)
+            .decode::<LambdaRequest>(body)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("payload looks like an API Gateway REST API event because `httpMethod` is present"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,293 @@
+//! Normalization of request paths before routing.
+//!
+//! The path of a [`Request`] depends on the trigger: REST APIs and HTTP APIs
+//! with a named stage prefix it with `/{stage}`, HTTP APIs on the `$default`
+//! stage, function URLs and ALB don't, and clients are free to send
+//! `//orders/%34%32` for `/orders/42`. Routers that match the path of the URI
+//! then work with one trigger and break with another.
+//!
+//! [`PathLayer`] rewrites the path of the URI before it reaches the handler:
+//! - [`strip_stage`](PathLayer::strip_stage) removes the `/{stage}` prefix of API Gateway requests.
+//! - [`collapse_slashes`](PathLayer::collapse_slashes) replaces runs of slashes with one slash.
+//! - [`decode`](PathLayer::decode) decodes the percent-encoded characters that
+//!   don't need to be encoded in a path, and encodes the others with uppercase
+//!   digits, so each path has a single form. `%2F` is kept, so encoded slashes
+//!   don't create new segments, and so are the segments that would be decoded
+//!   into `.` or `..`.
+//!
+//! All of them are enabled by [`PathLayer::new`]. The path received by the
+//! function is still available with [`RequestExt::original_path`], and the path
+//! without the stage, as sent by API Gateway, with [`RequestExt::raw_http_path`].
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{path::PathLayer, service_fn, tower::Layer, Error, Request, RequestExt};
+//!
+//! async fn route(req: Request) -> Result<String, Error> {
+//!     Ok(format!("{} was received as {:?}", req.uri().path(), req.original_path()))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run(PathLayer::new().layer(service_fn(route))).await
+//! }
+//! ```
+use crate::{
+    ext::{extensions::OriginalPath, RequestExt},
+    request::RequestContext,
+    Request,
+};
+use http::uri::{PathAndQuery, Uri};
+use lambda_runtime::{tower::Layer, Service};
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, CONTROLS};
+use std::task::{Context as TaskContext, Poll};
+
+/// Characters that must stay percent-encoded in a path segment.
+const SEGMENT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// A [`Layer`] that normalizes the paths of requests.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone, Copy)]
+pub struct PathLayer {
+    strip_stage: bool,
+    collapse_slashes: bool,
+    decode: bool,
+}
+
+impl PathLayer {
+    /// Create a layer that strips the stage, collapses slashes, and decodes paths.
+    pub fn new() -> Self {
+        PathLayer {
+            strip_stage: true,
+            collapse_slashes: true,
+            decode: true,
+        }
+    }
+
+    /// Create a layer that keeps the paths as they are, and only records the
+    /// [original path](RequestExt::original_path).
+    pub fn raw() -> Self {
+        PathLayer {
+            strip_stage: false,
+            collapse_slashes: false,
+            decode: false,
+        }
+    }
+
+    /// Remove the `/{stage}` prefix that API Gateway adds to the paths of
+    /// stages other than `$default`.
+    pub fn strip_stage(self, strip_stage: bool) -> Self {
+        PathLayer { strip_stage, ..self }
+    }
+
+    /// Replace runs of slashes with a single slash.
+    pub fn collapse_slashes(self, collapse_slashes: bool) -> Self {
+        PathLayer {
+            collapse_slashes,
+            ..self
+        }
+    }
+
+    /// Decode the percent-encoded characters that don't need to be encoded.
+    pub fn decode(self, decode: bool) -> Self {
+        PathLayer { decode, ..self }
+    }
+
+    /// Normalize `path`, which has the prefix of `stage` when it's set.
+    pub fn normalize(&self, path: &str, stage: Option<&str>) -> String {
+        let mut path = path;
+        if let Some(stage) = stage.filter(|_| self.strip_stage) {
+            path = strip_stage(path, stage);
+        }
+        let segments = path.split('/').enumerate().filter(|(i, segment)| {
+            // The first segment is the empty string before the leading slash.
+            !self.collapse_slashes || *i == 0 || !segment.is_empty()
+        });
+
+        let mut normalized = String::with_capacity(path.len());
+        for (i, segment) in segments {
+            if i > 0 {
+                normalized.push('/');
+            }
+            match self.decode {
+                true => normalized.push_str(&decode_segment(segment)),
+                false => normalized.push_str(segment),
+            }
+        }
+        // Keep the trailing slash, and the root.
+        if path.ends_with('/') && !normalized.ends_with('/') {
+            normalized.push('/');
+        }
+        if !normalized.starts_with('/') {
+            normalized.insert(0, '/');
+        }
+        normalized
+    }
+}
+
+impl Default for PathLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn strip_stage<'a>(path: &'a str, stage: &str) -> &'a str {
+    match path.strip_prefix('/').and_then(|path| path.strip_prefix(stage)) {
+        Some("") => "/",
+        Some(rest) if rest.starts_with('/') => rest,
+        _ => path,
+    }
+}
+
+fn decode_segment(segment: &str) -> String {
+    let decoded: Vec<u8> = percent_decode_str(segment).collect();
+    // Decoding `%2E%2E` would let the segment escape its parent.
+    if decoded == b"." || decoded == b".." {
+        return segment.to_string();
+    }
+    percent_encode(&decoded, SEGMENT_ENCODE_SET).to_string()
+}
+
+/// The stage that API Gateway added to the path of the request.
+fn stage(req: &Request) -> Option<&str> {
+    let stage = match req.request_context_ref() {
+        #[cfg(feature = "apigw_rest")]
+        Some(RequestContext::ApiGatewayV1(ctx)) => ctx.stage.as_deref(),
+        #[cfg(feature = "apigw_http")]
+        Some(RequestContext::ApiGatewayV2(ctx)) => ctx.stage.as_deref(),
+        #[cfg(feature = "apigw_websockets")]
+        Some(RequestContext::WebSocket(ctx)) => ctx.stage.as_deref(),
+        _ => None,
+    };
+    stage.filter(|stage| *stage != "$default")
+}
+
+impl<S> Layer<S> for PathLayer {
+    type Service = PathService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PathService { inner, layer: *self }
+    }
+}
+
+/// A [`Service`] that normalizes the paths of requests.
+///
+/// See [`PathLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct PathService<S> {
+    inner: S,
+    layer: PathLayer,
+}
+
+impl<S> Service<Request> for PathService<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let original = req.uri().path().to_string();
+        let normalized = self.layer.normalize(&original, stage(&req));
+        if normalized != original {
+            let path_and_query = match req.uri().query() {
+                Some(query) => format!("{normalized}?{query}"),
+                None => normalized,
+            };
+            let mut parts = req.uri().clone().into_parts();
+            if let Ok(path_and_query) = PathAndQuery::try_from(path_and_query) {
+                parts.path_and_query = Some(path_and_query);
+                if let Ok(uri) = Uri::from_parts(parts) {
+                    *req.uri_mut() = uri;
+                }
+            }
+        }
+        req.extensions_mut().insert(OriginalPath(original));
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Body, Error};
+    use lambda_runtime::service_fn;
+
+    #[test]
+    fn collapses_slashes() {
+        let layer = PathLayer::raw().collapse_slashes(true);
+        assert_eq!("/a/b/", layer.normalize("//a///b//", None));
+        assert_eq!("/", layer.normalize("//", None));
+        assert_eq!("/a%2F%2Fb", layer.normalize("/a%2F%2Fb", None));
+    }
+
+    #[test]
+    fn decodes_percent_encoded_characters() {
+        let layer = PathLayer::raw().decode(true);
+        assert_eq!("/orders/42", layer.normalize("/orders/%34%32", None));
+        assert_eq!("/~user/a%20b", layer.normalize("/%7euser/a b", None));
+        assert_eq!("/caf%C3%A9", layer.normalize("/caf%c3%a9", None));
+        assert_eq!("/a%2Fb", layer.normalize("/a%2fb", None));
+        assert_eq!("/files/%2E%2E/secret", layer.normalize("/files/%2E%2E/secret", None));
+        assert_eq!("/100%25", layer.normalize("/100%", None));
+    }
+
+    #[test]
+    fn strips_the_stage() {
+        let layer = PathLayer::raw().strip_stage(true);
+        assert_eq!("/orders", layer.normalize("/prod/orders", Some("prod")));
+        assert_eq!("/", layer.normalize("/prod", Some("prod")));
+        assert_eq!(
+            "/production/orders",
+            layer.normalize("/production/orders", Some("prod"))
+        );
+        assert_eq!("/prod/orders", PathLayer::raw().normalize("/prod/orders", Some("prod")));
+    }
+
+    #[cfg(feature = "apigw_rest")]
+    #[tokio::test]
+    async fn rewrites_the_uri_and_keeps_the_original_path() {
+        use aws_lambda_events::apigw::ApiGatewayProxyRequestContext;
+
+        let mut service = PathLayer::new().layer(service_fn(|req: Request| async move {
+            Ok::<_, Error>(format!("{} {}", req.uri(), req.original_path().unwrap_or_default()))
+        }));
+        let context = ApiGatewayProxyRequestContext {
+            stage: Some("prod".into()),
+            ..Default::default()
+        };
+        let req = http::Request::builder()
+            .uri("https://example.com/prod//orders/%34%32?expand=items")
+            .body(Body::Empty)
+            .unwrap()
+            .with_request_context(RequestContext::ApiGatewayV1(context));
+
+        let response = service.call(req).await.unwrap();
+        assert_eq!(
+            "https://example.com/orders/42?expand=items /prod//orders/%34%32",
+            response
+        );
+    }
+}
@@ -0,0 +1,558 @@
+//! Conversions from HTTP requests and responses to the payloads of the triggers.
+//!
+//! The runtime converts the events of the triggers into [`Request`]s, and the
+//! [`Response`]s of handlers into the payloads that the triggers expect. This
+//! module goes the other way, so proxies, local servers and test harnesses can
+//! synthesize the events of a trigger from real HTTP traffic, and turn the
+//! response payload of a function back into an HTTP response.
+//!
+//! Requests that came from an event keep their request context, path
+//! parameters and stage variables, so converting them back gives an event close
+//! to the original one. Other requests get a request context with the method,
+//! path and domain name of the request.
+//!
+//! # Example
+//! ```
+//! use lambda_http::{http, payload, request::RequestOrigin, Body};
+//!
+//! let req = http::Request::builder()
+//!     .method("POST")
+//!     .uri("https://example.com/users?active=true")
+//!     .header("content-type", "application/json")
+//!     .body(Body::from(r#"{"name":"alice"}"#))
+//!     .unwrap();
+//! let event = payload::into_event(req, &RequestOrigin::ApiGatewayV2);
+//! assert_eq!("/users", event["rawPath"]);
+//! assert_eq!("active=true", event["rawQueryString"]);
+//!
+//! let payload = serde_json::json!({ "statusCode": 201, "body": "created" });
+//! let res = payload::from_response_payload(payload).unwrap();
+//! assert_eq!(201, res.status());
+//! ```
+use crate::{ext::RequestExt, request::RequestOrigin, response::LambdaResponse, Body, Request, Response};
+use base64::Engine;
+use http::{header::HOST, HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use serde_json::{error::Error as JsonError, Value};
+use std::collections::HashMap;
+
+#[cfg(any(
+    feature = "apigw_rest",
+    feature = "apigw_http",
+    feature = "apigw_websockets",
+    feature = "vpc_lattice"
+))]
+use crate::request::RequestContext;
+#[cfg(feature = "alb")]
+use aws_lambda_events::alb::AlbTargetGroupRequest;
+#[cfg(feature = "apigw_rest")]
+use aws_lambda_events::apigw::ApiGatewayProxyRequest;
+#[cfg(feature = "apigw_http")]
+use aws_lambda_events::apigw::ApiGatewayV2httpRequest;
+#[cfg(feature = "apigw_websockets")]
+use aws_lambda_events::apigw::ApiGatewayWebsocketProxyRequest;
+#[cfg(any(
+    feature = "apigw_rest",
+    feature = "apigw_http",
+    feature = "apigw_websockets",
+    feature = "vpc_lattice"
+))]
+use aws_lambda_events::query_map::QueryMap;
+#[cfg(feature = "vpc_lattice")]
+use aws_lambda_events::vpc_lattice::VpcLatticeRequestV2;
+
+/// Convert a request into the JSON event that `origin` would send for it.
+pub fn into_event(req: Request, origin: &RequestOrigin) -> Value {
+    let event = match origin {
+        #[cfg(feature = "apigw_rest")]
+        RequestOrigin::ApiGatewayV1 => serde_json::to_value(into_apigw_v1_request(req)),
+        #[cfg(feature = "apigw_http")]
+        RequestOrigin::ApiGatewayV2 => serde_json::to_value(into_apigw_v2_request(req)),
+        #[cfg(feature = "alb")]
+        RequestOrigin::Alb => serde_json::to_value(into_alb_request(req)),
+        #[cfg(feature = "apigw_websockets")]
+        RequestOrigin::WebSocket => serde_json::to_value(into_websocket_request(req)),
+        #[cfg(feature = "vpc_lattice")]
+        RequestOrigin::VpcLattice => serde_json::to_value(into_vpc_lattice_request(req)),
+    };
+    event.expect("events are always serializable")
+}
+
+/// Convert a request into an API Gateway REST API proxy event.
+#[cfg(feature = "apigw_rest")]
+pub fn into_apigw_v1_request(req: Request) -> ApiGatewayProxyRequest {
+    let path = path(&req);
+    let query = query(&req);
+    let path_parameters = path_parameters(&req);
+    let stage_variables = stage_variables(&req);
+    let mut request_context = match req.request_context_ref() {
+        Some(RequestContext::ApiGatewayV1(context)) => context.clone(),
+        _ => Default::default(),
+    };
+    let (parts, body) = req.into_parts();
+    let headers = headers(parts.headers, &parts.uri);
+    request_context.http_method = parts.method.clone();
+    request_context.path.get_or_insert_with(|| path.clone());
+    request_context.stage.get_or_insert_with(|| "$default".into());
+    if request_context.domain_name.is_none() {
+        request_context.domain_name = domain_name(&headers);
+    }
+    if request_context.identity.source_ip.is_none() {
+        request_context.identity.source_ip = source_ip(&headers);
+    }
+    let (body, is_base64_encoded) = body_payload(body);
+
+    ApiGatewayProxyRequest {
+        path: Some(path),
+        http_method: parts.method,
+        headers: headers.clone(),
+        multi_value_headers: headers,
+        query_string_parameters: query.clone(),
+        multi_value_query_string_parameters: query,
+        path_parameters,
+        stage_variables,
+        request_context,
+        body,
+        is_base64_encoded,
+        ..Default::default()
+    }
+}
+
+/// Convert a request into an API Gateway HTTP API, or Lambda function URL, event
+/// in the format 2.0.
+#[cfg(feature = "apigw_http")]
+pub fn into_apigw_v2_request(req: Request) -> ApiGatewayV2httpRequest {
+    use http::header::COOKIE;
+
+    let path = path(&req);
+    let query = query(&req);
+    let path_parameters = path_parameters(&req);
+    let stage_variables = stage_variables(&req);
+    let mut request_context = match req.request_context_ref() {
+        Some(RequestContext::ApiGatewayV2(context)) => context.clone(),
+        _ => Default::default(),
+    };
+    let (parts, body) = req.into_parts();
+    let mut headers = headers(parts.headers, &parts.uri);
+    // The cookies have their own attribute in this format, and the values of
+    // the other repeated headers are joined with commas.
+    let cookies: Vec<String> = headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(|cookie| cookie.trim().to_string())
+        .filter(|cookie| !cookie.is_empty())
+        .collect();
+    headers.remove(COOKIE);
+    let headers = join_repeated_headers(headers);
+
+    request_context.http.method = parts.method;
+    request_context.http.path.get_or_insert_with(|| path.clone());
+    request_context
+        .http
+        .protocol
+        .get_or_insert_with(|| format!("{:?}", parts.version));
+    if request_context.http.source_ip.is_none() {
+        request_context.http.source_ip = source_ip(&headers);
+    }
+    if request_context.http.user_agent.is_none() {
+        request_context.http.user_agent = header(&headers, http::header::USER_AGENT);
+    }
+    request_context.route_key.get_or_insert_with(|| "$default".into());
+    request_context.stage.get_or_insert_with(|| "$default".into());
+    if request_context.domain_name.is_none() {
+        request_context.domain_name = domain_name(&headers);
+    }
+    let (body, is_base64_encoded) = body_payload(body);
+
+    ApiGatewayV2httpRequest {
+        version: Some("2.0".into()),
+        route_key: request_context.route_key.clone(),
+        raw_path: Some(path),
+        raw_query_string: Some(parts.uri.query().unwrap_or_default().to_string()),
+        cookies: (!cookies.is_empty()).then_some(cookies),
+        headers,
+        query_string_parameters: query,
+        path_parameters,
+        stage_variables,
+        request_context,
+        body,
+        is_base64_encoded,
+    }
+}
+
+/// Convert a request into an Application Load Balancer event, with multi-value
+/// headers enabled on the target group.
+#[cfg(feature = "alb")]
+pub fn into_alb_request(req: Request) -> AlbTargetGroupRequest {
+    let path = path(&req);
+    let request_context = match req.request_context_ref() {
+        Some(crate::request::RequestContext::Alb(context)) => context.clone(),
+        _ => Default::default(),
+    };
+    let (parts, body) = req.into_parts();
+    // The load balancer sends the query string parameters as they are in the URL, encoded.
+    let mut query: HashMap<String, Vec<String>> = HashMap::new();
+    for pair in parts
+        .uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty())
+    {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        query.entry(name.to_string()).or_default().push(value.to_string());
+    }
+    let query = aws_lambda_events::query_map::QueryMap::from(query);
+    let headers = headers(parts.headers, &parts.uri);
+    let (body, is_base64_encoded) = body_payload(body);
+
+    AlbTargetGroupRequest {
+        http_method: parts.method,
+        path: Some(path),
+        query_string_parameters: query.clone(),
+        multi_value_query_string_parameters: query,
+        headers: headers.clone(),
+        multi_value_headers: headers,
+        request_context,
+        body,
+        is_base64_encoded,
+    }
+}
+
+/// Convert a request into an API Gateway WebSocket API event.
+#[cfg(feature = "apigw_websockets")]
+pub fn into_websocket_request(req: Request) -> ApiGatewayWebsocketProxyRequest {
+    let path = path(&req);
+    let query = query(&req);
+    let path_parameters = path_parameters(&req);
+    let stage_variables = stage_variables(&req);
+    let mut request_context = match req.request_context_ref() {
+        Some(RequestContext::WebSocket(context)) => context.clone(),
+        _ => Default::default(),
+    };
+    let (parts, body) = req.into_parts();
+    let headers = headers(parts.headers, &parts.uri);
+    request_context.stage.get_or_insert_with(|| "$default".into());
+    if request_context.domain_name.is_none() {
+        request_context.domain_name = domain_name(&headers);
+    }
+    let (body, is_base64_encoded) = body_payload(body);
+
+    ApiGatewayWebsocketProxyRequest {
+        path: Some(path),
+        http_method: Some(parts.method),
+        headers: headers.clone(),
+        multi_value_headers: headers,
+        query_string_parameters: query.clone(),
+        multi_value_query_string_parameters: query,
+        path_parameters,
+        stage_variables,
+        request_context,
+        body,
+        is_base64_encoded,
+        ..Default::default()
+    }
+}
+
+/// Convert a request into a VPC Lattice event in the format 2.0.
+#[cfg(feature = "vpc_lattice")]
+pub fn into_vpc_lattice_request(req: Request) -> VpcLatticeRequestV2 {
+    let path = path(&req);
+    let query = query(&req);
+    let request_context = match req.request_context_ref() {
+        Some(RequestContext::VpcLattice(context)) => context.clone(),
+        _ => Default::default(),
+    };
+    let (parts, body) = req.into_parts();
+    let headers = headers(parts.headers, &parts.uri);
+    let (body, is_base64_encoded) = body_payload(body);
+
+    VpcLatticeRequestV2 {
+        version: Some("2.0".into()),
+        method: parts.method,
+        path: Some(path),
+        headers,
+        query_string_parameters: query,
+        body,
+        is_base64_encoded,
+        request_context,
+    }
+}
+
+/// Convert a response into the JSON payload that a function answering `origin`
+/// returns for it.
+pub fn into_response_payload(res: Response<Body>, origin: &RequestOrigin) -> Value {
+    serde_json::to_value(LambdaResponse::from_response(origin, res)).expect("responses are always serializable")
+}
+
+/// Convert the JSON payload returned by a function into the HTTP response that
+/// the trigger would send to the client.
+///
+/// The payloads of API Gateway, Lambda function URLs and Application Load
+/// Balancers are accepted. Like API Gateway HTTP APIs, a payload without
+/// `statusCode` is sent as a `200 OK` JSON response.
+pub fn from_response_payload(payload: Value) -> Result<Response<Body>, JsonError> {
+    let is_structured = payload.as_object().map_or(false, |obj| obj.contains_key("statusCode"));
+    if !is_structured {
+        let body = match payload {
+            Value::String(text) => text,
+            other => other.to_string(),
+        };
+        let res = Response::builder()
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::Text(body))
+            .expect("unable to build http::Response");
+        return Ok(res);
+    }
+
+    let payload: ResponsePayload = serde_json::from_value(payload)?;
+    let mut builder = Response::builder().status(payload.status_code);
+    let headers = builder.headers_mut().expect("unable to build http::Response");
+    // Multi-value headers take precedence over the single ones with the same name.
+    let mut multi_value_headers = HeaderMap::new();
+    for (name, values) in payload.multi_value_headers {
+        for value in values {
+            append_header(&mut multi_value_headers, &name, &value);
+        }
+    }
+    for (name, value) in payload.headers {
+        append_header(headers, &name, &value);
+    }
+    for name in multi_value_headers.keys() {
+        headers.remove(name);
+    }
+    for (name, value) in multi_value_headers.iter() {
+        headers.append(name, value.clone());
+    }
+    for cookie in payload.cookies {
+        append_header(headers, http::header::SET_COOKIE.as_str(), &cookie);
+    }
+    let body = match payload.body {
+        None => Body::Empty,
+        Some(body) => Body::from_maybe_encoded(payload.is_base64_encoded, &body),
+    };
+    Ok(builder.body(body).expect("unable to build http::Response"))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponsePayload {
+    status_code: u16,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    multi_value_headers: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    cookies: Vec<String>,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    is_base64_encoded: bool,
+}
+
+fn append_header(headers: &mut HeaderMap, name: &str, value: &str) {
+    if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+        headers.append(name, value);
+    }
+}
+
+fn path(req: &Request) -> String {
+    match req.raw_http_path() {
+        "" => req.uri().path().to_string(),
+        raw_path => raw_path.to_string(),
+    }
+}
+
+#[cfg(any(
+    feature = "apigw_rest",
+    feature = "apigw_http",
+    feature = "apigw_websockets",
+    feature = "vpc_lattice"
+))]
+fn query(req: &Request) -> QueryMap {
+    req.uri()
+        .query()
+        .and_then(|query| query.parse().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(any(feature = "apigw_rest", feature = "apigw_http", feature = "apigw_websockets"))]
+fn path_parameters(req: &Request) -> HashMap<String, String> {
+    req.path_parameters_ref()
+        .map(|params| params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(any(feature = "apigw_rest", feature = "apigw_http", feature = "apigw_websockets"))]
+fn stage_variables(req: &Request) -> HashMap<String, String> {
+    req.stage_variables_ref()
+        .map(|vars| vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+        .unwrap_or_default()
+}
+
+/// Return the headers of the request, with a `host` header taken from the URI
+/// when the request doesn't have one.
+fn headers(mut headers: HeaderMap, uri: &http::Uri) -> HeaderMap {
+    if !headers.contains_key(HOST) {
+        if let Some(value) = uri.authority().and_then(|a| HeaderValue::from_str(a.as_str()).ok()) {
+            headers.insert(HOST, value);
+        }
+    }
+    headers
+}
+
+#[cfg(feature = "apigw_http")]
+fn join_repeated_headers(headers: HeaderMap) -> HeaderMap {
+    let mut joined = HeaderMap::new();
+    for name in headers.keys() {
+        let values: Vec<&str> = headers.get_all(name).iter().filter_map(|v| v.to_str().ok()).collect();
+        if let Ok(value) = HeaderValue::from_str(&values.join(",")) {
+            joined.insert(name.clone(), value);
+        }
+    }
+    joined
+}
+
+#[cfg(any(feature = "apigw_rest", feature = "apigw_http", feature = "apigw_websockets"))]
+fn header(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(String::from)
+}
+
+#[cfg(any(feature = "apigw_rest", feature = "apigw_http", feature = "apigw_websockets"))]
+fn domain_name(headers: &HeaderMap) -> Option<String> {
+    let host = header(headers, HOST)?;
+    Some(host.split(':').next().unwrap_or_default().to_string())
+}
+
+#[cfg(any(feature = "apigw_rest", feature = "apigw_http"))]
+fn source_ip(headers: &HeaderMap) -> Option<String> {
+    let forwarded_for = header(headers, HeaderName::from_static("x-forwarded-for"))?;
+    forwarded_for.split(',').next().map(|ip| ip.trim().to_string())
+}
+
+fn body_payload(body: Body) -> (Option<String>, bool) {
+    match body {
+        Body::Empty => (None, false),
+        Body::Text(text) => (Some(text), false),
+        Body::Binary(bytes) => (Some(base64::engine::general_purpose::STANDARD.encode(bytes)), true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> Request {
+        http::Request::builder()
+            .method("PUT")
+            .uri("https://example.com/items/1?tag=a%20b&tag=c")
+            .header("cookie", "a=1; b=2")
+            .header("accept", "text/html")
+            .header("accept", "application/json")
+            .body(Body::Binary(vec![0xff, 0x00]))
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "apigw_http")]
+    fn round_trips_apigw_v2_requests() {
+        let event = into_event(request(), &RequestOrigin::ApiGatewayV2);
+        assert_eq!(serde_json::json!(["a=1", "b=2"]), event["cookies"]);
+        assert_eq!("text/html,application/json", event["headers"]["accept"]);
+        assert_eq!("example.com", event["requestContext"]["domainName"]);
+
+        let req = crate::request::from_value(event).unwrap();
+        assert_eq!(http::Method::PUT, req.method());
+        assert_eq!("https://example.com/items/1?tag=a%20b&tag=c", req.uri());
+        assert_eq!(Some(vec!["a b", "c"]), req.query_string_parameters().all("tag"));
+        assert_eq!(&Body::Binary(vec![0xff, 0x00]), req.body());
+    }
+
+    #[test]
+    #[cfg(feature = "apigw_rest")]
+    fn round_trips_apigw_v1_requests() {
+        let event = into_event(request(), &RequestOrigin::ApiGatewayV1);
+        assert_eq!(
+            serde_json::json!(["text/html", "application/json"]),
+            event["multiValueHeaders"]["accept"]
+        );
+
+        let req = crate::request::from_value(event).unwrap();
+        assert_eq!(http::Method::PUT, req.method());
+        assert_eq!("/items/1", req.uri().path());
+        assert_eq!(Some(vec!["a b", "c"]), req.query_string_parameters().all("tag"));
+        assert_eq!("a=1; b=2", req.headers()["cookie"]);
+    }
+
+    #[test]
+    #[cfg(feature = "alb")]
+    fn keeps_the_query_encoded_for_alb() {
+        let event = into_alb_request(request());
+        assert_eq!(
+            Some(vec!["a%20b", "c"]),
+            event.multi_value_query_string_parameters.all("tag")
+        );
+
+        let req: Request = crate::request::LambdaRequest::Alb(event).into();
+        assert_eq!(Some(vec!["a b", "c"]), req.query_string_parameters().all("tag"));
+    }
+
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn round_trips_vpc_lattice_requests() {
+        let event = into_event(request(), &RequestOrigin::VpcLattice);
+        assert_eq!(
+            serde_json::json!(["text/html", "application/json"]),
+            event["headers"]["accept"]
+        );
+
+        let req = crate::request::from_value(event).unwrap();
+        assert_eq!(http::Method::PUT, req.method());
+        assert_eq!("https://example.com/items/1?tag=a+b&tag=c", req.uri());
+        assert_eq!(Some(vec!["a b", "c"]), req.query_string_parameters().all("tag"));
+        assert_eq!(&Body::Binary(vec![0xff, 0x00]), req.body());
+    }
+
+    #[test]
+    fn converts_response_payloads() {
+        let res = from_response_payload(serde_json::json!({
+            "statusCode": 404,
+            "headers": { "content-type": "text/plain", "x-single": "1" },
+            "multiValueHeaders": { "x-single": ["2", "3"] },
+            "cookies": ["a=1"],
+            "body": "bm90IGZvdW5k",
+            "isBase64Encoded": true
+        }))
+        .unwrap();
+        assert_eq!(404, res.status());
+        assert_eq!("text/plain", res.headers()["content-type"]);
+        let single: Vec<_> = res.headers().get_all("x-single").iter().collect();
+        assert_eq!(vec!["2", "3"], single);
+        assert_eq!("a=1", res.headers()["set-cookie"]);
+        assert_eq!(&Body::Binary(b"not found".to_vec()), res.body());
+
+        let res = from_response_payload(serde_json::json!({ "hello": "world" })).unwrap();
+        assert_eq!(200, res.status());
+        assert_eq!(&Body::Text(r#"{"hello":"world"}"#.into()), res.body());
+    }
+
+    #[test]
+    #[cfg(feature = "apigw_http")]
+    fn round_trips_responses() {
+        let res = Response::builder()
+            .status(302)
+            .header("location", "/next")
+            .header("set-cookie", "a=1")
+            .body(Body::Empty)
+            .unwrap();
+        let payload = into_response_payload(res, &RequestOrigin::ApiGatewayV2);
+        assert_eq!(serde_json::json!(["a=1"]), payload["cookies"]);
+
+        let res = from_response_payload(payload).unwrap();
+        assert_eq!(302, res.status());
+        assert_eq!("/next", res.headers()["location"]);
+        assert_eq!("a=1", res.headers()["set-cookie"]);
+    }
+}
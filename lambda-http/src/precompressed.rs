@@ -0,0 +1,325 @@
+//! Pre-compressed representations of responses, selected with `Accept-Encoding`.
+//!
+//! Static assets embedded in the binary can be compressed at build time, once,
+//! instead of at every request. A [`Precompressed`] response carries the
+//! identity representation of the content and its pre-encoded ones, like gzip
+//! and brotli. [`PrecompressedLayer`] sends the representation preferred by
+//! the `Accept-Encoding` header of the request, and sends the identity
+//! representation to clients that accept none of the others. Without the
+//! layer, responses always use the identity representation.
+//!
+//! [`ServeFiles::precompressed`](crate::fs::ServeFiles::precompressed) applies
+//! the same selection to files stored next to their `.br` and `.gz` variants.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{
+//!     precompressed::{Precompressed, PrecompressedLayer},
+//!     service_fn, tower::Layer, Error, Request,
+//! };
+//!
+//! static APP_JS: &[u8] = b"console.log('hello')";
+//! # static APP_JS_GZ: &[u8] = b"";
+//! # static APP_JS_BR: &[u8] = b"";
+//! // static APP_JS_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/app.js.gz"));
+//! // static APP_JS_BR: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/app.js.br"));
+//!
+//! async fn app(_req: Request) -> Result<Precompressed, Error> {
+//!     Ok(Precompressed::new("text/javascript; charset=utf-8", APP_JS)
+//!         .gzip(APP_JS_GZ)
+//!         .br(APP_JS_BR))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run(PrecompressedLayer.layer(service_fn(app))).await
+//! }
+//! ```
+use crate::{response::ResponseFuture, Body, IntoResponse, Request, Response};
+use futures::future::BoxFuture;
+use http::{
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+    HeaderValue,
+};
+use lambda_runtime::{tower::Layer, Service};
+use std::{
+    borrow::Cow,
+    future::ready,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+};
+
+/// Content codings of pre-compressed representations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// The content itself, without any coding.
+    Identity,
+    /// `gzip`
+    Gzip,
+    /// `br`, brotli
+    Brotli,
+    /// `zstd`, Zstandard
+    Zstd,
+}
+
+impl Encoding {
+    /// Return the token of this coding, as used in `Content-Encoding`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// Return the file extension of this coding, like `gz` for gzip.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Encoding::Identity => None,
+            Encoding::Gzip => Some("gz"),
+            Encoding::Brotli => Some("br"),
+            Encoding::Zstd => Some("zst"),
+        }
+    }
+
+    // Codings that compress better come first, to break ties between codings that the client likes as much.
+    fn preference(self) -> usize {
+        match self {
+            Encoding::Brotli => 0,
+            Encoding::Zstd => 1,
+            Encoding::Gzip => 2,
+            Encoding::Identity => 3,
+        }
+    }
+}
+
+/// Select the coding preferred by an `Accept-Encoding` header among the `available` ones.
+///
+/// Returns [`Encoding::Identity`] when the client accepts none of them.
+pub fn select(accept_encoding: Option<&str>, available: &[Encoding]) -> Encoding {
+    let accept_encoding = match accept_encoding {
+        Some(accept_encoding) => accept_encoding,
+        None => return Encoding::Identity,
+    };
+
+    let mut qualities: Vec<(&str, f32)> = Vec::new();
+    for coding in accept_encoding.split(',') {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        if name.is_empty() {
+            continue;
+        }
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        qualities.push((name, quality));
+    }
+    let quality = |encoding: Encoding| {
+        let explicit = qualities
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(encoding.as_str()))
+            .or_else(|| qualities.iter().find(|(name, _)| *name == "*"));
+        match (explicit, encoding) {
+            (Some((_, quality)), _) => *quality,
+            // Identity is acceptable unless it is explicitly refused.
+            (None, Encoding::Identity) => 0.001,
+            (None, _) => 0.0,
+        }
+    };
+
+    available
+        .iter()
+        .copied()
+        .filter(|encoding| *encoding != Encoding::Identity)
+        .map(|encoding| (encoding, quality(encoding)))
+        .filter(|(_, quality)| *quality > 0.0)
+        .min_by(|(a, qa), (b, qb)| qb.total_cmp(qa).then(a.preference().cmp(&b.preference())))
+        .filter(|(_, q)| *q >= quality(Encoding::Identity))
+        .map(|(encoding, _)| encoding)
+        .unwrap_or(Encoding::Identity)
+}
+
+type Variants = Vec<(Encoding, Cow<'static, [u8]>)>;
+
+/// Pre-encoded representations of a response, stored in its extensions.
+#[derive(Clone)]
+struct Representations(Arc<Variants>);
+
+/// A response with pre-compressed representations of its content.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct Precompressed {
+    content_type: Cow<'static, str>,
+    identity: Cow<'static, [u8]>,
+    variants: Variants,
+}
+
+impl Precompressed {
+    /// Create a response with the `identity` content, of type `content_type`.
+    pub fn new(content_type: impl Into<Cow<'static, str>>, identity: impl Into<Cow<'static, [u8]>>) -> Self {
+        Precompressed {
+            content_type: content_type.into(),
+            identity: identity.into(),
+            variants: Vec::new(),
+        }
+    }
+
+    /// Add the gzip representation of the content.
+    pub fn gzip(self, content: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.encoding(Encoding::Gzip, content)
+    }
+
+    /// Add the brotli representation of the content.
+    pub fn br(self, content: impl Into<Cow<'static, [u8]>>) -> Self {
+        self.encoding(Encoding::Brotli, content)
+    }
+
+    /// Add the representation of the content with `encoding`.
+    pub fn encoding(mut self, encoding: Encoding, content: impl Into<Cow<'static, [u8]>>) -> Self {
+        if encoding != Encoding::Identity {
+            self.variants.retain(|(existing, _)| *existing != encoding);
+            self.variants.push((encoding, content.into()));
+        }
+        self
+    }
+
+    /// Return the codings available for this response.
+    pub fn available(&self) -> Vec<Encoding> {
+        let mut available = vec![Encoding::Identity];
+        available.extend(self.variants.iter().map(|(encoding, _)| *encoding));
+        available
+    }
+}
+
+impl IntoResponse for Precompressed {
+    fn into_response(self) -> ResponseFuture {
+        let text = self.content_type.starts_with("text/") || self.content_type.starts_with("application/json");
+        let body = match String::from_utf8(self.identity.into_owned()) {
+            Ok(text_body) if text => Body::Text(text_body),
+            Ok(text_body) => Body::Binary(text_body.into_bytes()),
+            Err(err) => Body::Binary(err.into_bytes()),
+        };
+        let mut builder = Response::builder().header(CONTENT_TYPE, self.content_type.as_ref());
+        if !self.variants.is_empty() {
+            builder = builder
+                .header(VARY, ACCEPT_ENCODING.as_str())
+                .extension(Representations(Arc::new(self.variants)));
+        }
+        Box::pin(ready(builder.body(body).expect("unable to build http::Response")))
+    }
+}
+
+/// A [`Layer`] that sends the pre-compressed representation of [`Precompressed`] responses
+/// preferred by the client.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrecompressedLayer;
+
+impl<S> Layer<S> for PrecompressedLayer {
+    type Service = PrecompressedService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PrecompressedService { inner }
+    }
+}
+
+/// A [`Service`] that sends the pre-compressed representation of [`Precompressed`] responses
+/// preferred by the client.
+///
+/// See [`PrecompressedLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct PrecompressedService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for PrecompressedService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let accept_encoding = req
+            .headers()
+            .get(ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            let mut response = response.await;
+            let representations = match response.extensions_mut().remove::<Representations>() {
+                Some(representations) => representations,
+                None => return Ok(response),
+            };
+
+            let mut available = vec![Encoding::Identity];
+            available.extend(representations.0.iter().map(|(encoding, _)| *encoding));
+            let selected = select(accept_encoding.as_deref(), &available);
+            if let Some((encoding, content)) = representations.0.iter().find(|(encoding, _)| *encoding == selected) {
+                *response.body_mut() = Body::Binary(content.to_vec());
+                let headers = response.headers_mut();
+                headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+                headers.remove(CONTENT_LENGTH);
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_runtime::service_fn;
+
+    const ALL: [Encoding; 4] = [Encoding::Identity, Encoding::Gzip, Encoding::Brotli, Encoding::Zstd];
+
+    #[test]
+    fn selects_the_preferred_encoding() {
+        assert_eq!(Encoding::Identity, select(None, &ALL));
+        assert_eq!(Encoding::Gzip, select(Some("gzip"), &ALL));
+        assert_eq!(Encoding::Brotli, select(Some("gzip, deflate, br"), &ALL));
+        assert_eq!(Encoding::Gzip, select(Some("br;q=0.5, gzip"), &ALL));
+        assert_eq!(Encoding::Gzip, select(Some("gzip, deflate, br"), &ALL[..2]));
+        assert_eq!(Encoding::Identity, select(Some("deflate"), &ALL));
+        assert_eq!(Encoding::Identity, select(Some("gzip;q=0"), &ALL));
+        assert_eq!(Encoding::Brotli, select(Some("*"), &ALL));
+        assert_eq!(Encoding::Identity, select(Some("*;q=0, identity"), &ALL));
+    }
+
+    #[tokio::test]
+    async fn sends_the_selected_representation() {
+        let mut service = PrecompressedLayer.layer(service_fn(|_req: Request| async {
+            Ok::<_, lambda_runtime::Error>(
+                Precompressed::new("text/css; charset=utf-8", &b"body{}"[..])
+                    .gzip(&b"gzipped"[..])
+                    .br(&b"brotli"[..]),
+            )
+        }));
+
+        let mut req = Request::default();
+        req.headers_mut()
+            .insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        let res = service.call(req).await.unwrap();
+        assert_eq!("gzip", res.headers()[CONTENT_ENCODING]);
+        assert_eq!("accept-encoding", res.headers()[VARY]);
+        assert_eq!("text/css; charset=utf-8", res.headers()[CONTENT_TYPE]);
+        assert_eq!(&Body::Binary(b"gzipped".to_vec()), res.body());
+
+        let res = service.call(Request::default()).await.unwrap();
+        assert!(!res.headers().contains_key(CONTENT_ENCODING));
+        assert_eq!(&Body::Text("body{}".into()), res.body());
+    }
+}
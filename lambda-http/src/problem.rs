@@ -0,0 +1,363 @@
+//! Error responses in the Problem Details format of [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807).
+//!
+//! [`ProblemDetails`] is an `application/problem+json` response body, and an
+//! error that handlers can return. [`ProblemLayer`] turns the errors returned by
+//! a handler into problem responses, with the Lambda request id as the `instance`
+//! of the problem, so clients can quote it when they report an issue.
+//!
+//! Handlers that return a `ProblemDetails` error get that problem in the
+//! response. Other errors go through the mapping function of the layer, and
+//! become `500 Internal Server Error` problems when it doesn't map them. Their
+//! message is only included in the response when the layer
+//! [exposes details](ProblemLayer::expose_details).
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{
+//!     http::StatusCode, problem::{ProblemDetails, ProblemLayer}, service_fn, tower::Layer, Error, Request,
+//! };
+//!
+//! async fn order(req: Request) -> Result<String, Error> {
+//!     match req.uri().path() {
+//!         "/orders/42" => Ok("order 42".to_string()),
+//!         path => Err(ProblemDetails::new(StatusCode::NOT_FOUND)
+//!             .with_type("https://example.com/problems/unknown-order")
+//!             .with_detail(format!("no order at {path}"))
+//!             .into()),
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run(ProblemLayer::new().layer(service_fn(order))).await
+//! }
+//! ```
+use crate::{ext::RequestExt, Body, Error, IntoResponse, Request, Response};
+use futures::future::BoxFuture;
+use http::{header::CONTENT_TYPE, StatusCode};
+use lambda_runtime::{tower::Layer, Service};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    future::ready,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+};
+use tracing::error;
+
+/// Content type of problem responses.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+const ABOUT_BLANK: &str = "about:blank";
+
+fn is_about_blank(type_: &str) -> bool {
+    type_ == ABOUT_BLANK
+}
+
+/// A problem, described with the members of RFC 7807.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    /// URI reference that identifies the type of the problem, `about:blank` by default.
+    #[serde(rename = "type", default = "about_blank", skip_serializing_if = "is_about_blank")]
+    pub type_: String,
+    /// Short summary of the type of the problem.
+    pub title: String,
+    /// HTTP status code of the response.
+    pub status: u16,
+    /// Explanation specific to this occurrence of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// URI reference that identifies this occurrence of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Additional members of the problem.
+    #[serde(flatten)]
+    pub extensions: serde_json::Map<String, serde_json::Value>,
+}
+
+fn about_blank() -> String {
+    ABOUT_BLANK.to_string()
+}
+
+impl ProblemDetails {
+    /// Create a problem with `status`, titled after the reason phrase of the status code.
+    pub fn new(status: StatusCode) -> Self {
+        ProblemDetails {
+            type_: about_blank(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: None,
+            instance: None,
+            extensions: serde_json::Map::new(),
+        }
+    }
+
+    /// Set the type of the problem.
+    pub fn with_type(self, type_: impl Into<String>) -> Self {
+        ProblemDetails {
+            type_: type_.into(),
+            ..self
+        }
+    }
+
+    /// Set the title of the problem.
+    pub fn with_title(self, title: impl Into<String>) -> Self {
+        ProblemDetails {
+            title: title.into(),
+            ..self
+        }
+    }
+
+    /// Set the detail of the problem.
+    pub fn with_detail(self, detail: impl Into<String>) -> Self {
+        ProblemDetails {
+            detail: Some(detail.into()),
+            ..self
+        }
+    }
+
+    /// Set the instance of the problem.
+    pub fn with_instance(self, instance: impl Into<String>) -> Self {
+        ProblemDetails {
+            instance: Some(instance.into()),
+            ..self
+        }
+    }
+
+    /// Add a member to the problem.
+    pub fn with_extension(mut self, name: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.extensions.insert(name.into(), value.into());
+        self
+    }
+
+    /// Return the status code of the problem, `500` if it isn't a valid status code.
+    pub fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl fmt::Display for ProblemDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.status, self.title)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ": {detail}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ProblemDetails {}
+
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> crate::response::ResponseFuture {
+        let response = Response::builder()
+            .status(self.status_code())
+            .header(CONTENT_TYPE, PROBLEM_CONTENT_TYPE)
+            .body(Body::from(
+                serde_json::to_string(&self).expect("unable to serialize ProblemDetails"),
+            ))
+            .expect("unable to build http::Response");
+        Box::pin(ready(response))
+    }
+}
+
+type Mapper = Arc<dyn Fn(&Error) -> Option<ProblemDetails> + Send + Sync>;
+
+/// A [`Layer`] that turns handler errors into `application/problem+json` responses.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Default)]
+pub struct ProblemLayer {
+    mapper: Option<Mapper>,
+    expose_details: bool,
+}
+
+impl ProblemLayer {
+    /// Create a layer that turns errors into `500 Internal Server Error` problems.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map errors into problems with `mapper`.
+    ///
+    /// Errors that `mapper` returns `None` for become `500 Internal Server Error` problems.
+    pub fn map(self, mapper: impl Fn(&Error) -> Option<ProblemDetails> + Send + Sync + 'static) -> Self {
+        ProblemLayer {
+            mapper: Some(Arc::new(mapper)),
+            ..self
+        }
+    }
+
+    /// Include the message of unmapped errors as the detail of the problem.
+    ///
+    /// Error messages can contain internal details, only expose them to trusted clients.
+    pub fn expose_details(self, expose_details: bool) -> Self {
+        ProblemLayer { expose_details, ..self }
+    }
+
+    fn problem(&self, err: Error) -> ProblemDetails {
+        let err = match err.downcast::<ProblemDetails>() {
+            Ok(problem) => return *problem,
+            Err(err) => err,
+        };
+        if let Some(problem) = self.mapper.as_ref().and_then(|mapper| mapper(&err)) {
+            return problem;
+        }
+        error!("unhandled error: {err}");
+        let problem = ProblemDetails::new(StatusCode::INTERNAL_SERVER_ERROR);
+        match self.expose_details {
+            true => problem.with_detail(err.to_string()),
+            false => problem,
+        }
+    }
+}
+
+impl fmt::Debug for ProblemLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProblemLayer")
+            .field("mapper", &self.mapper.is_some())
+            .field("expose_details", &self.expose_details)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for ProblemLayer {
+    type Service = ProblemService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ProblemService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that turns handler errors into `application/problem+json` responses.
+///
+/// See [`ProblemLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct ProblemService<S> {
+    inner: S,
+    layer: ProblemLayer,
+}
+
+impl<S> Service<Request> for ProblemService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Into<Error> + Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let request_id = req.lambda_context_ref().map(|ctx| ctx.request_id.clone());
+        let layer = self.layer.clone();
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = match fut.await {
+                Ok(response) => response.into_response(),
+                Err(err) => {
+                    let mut problem = layer.problem(err.into());
+                    if problem.instance.is_none() {
+                        problem.instance = request_id;
+                    }
+                    problem.into_response()
+                }
+            };
+            Ok(response.await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_runtime::{service_fn, Context};
+    use serde_json::json;
+
+    fn request() -> Request {
+        let mut context = Context::default();
+        context.request_id = "8476a536-e9f4-11e8-9739-2dfe598c3fcd".to_string();
+        http::Request::builder().extension(context).body(Body::Empty).unwrap()
+    }
+
+    async fn problem(layer: ProblemLayer, err: Error) -> (StatusCode, serde_json::Value) {
+        let mut err = Some(err);
+        let mut service = layer.layer(service_fn(move |_req: Request| {
+            let err = Err::<&str, Error>(err.take().unwrap());
+            async move { err }
+        }));
+        let response = service.call(request()).await.unwrap();
+        assert_eq!(PROBLEM_CONTENT_TYPE, response.headers()[CONTENT_TYPE]);
+        let body = serde_json::from_slice(response.body()).unwrap();
+        (response.status(), body)
+    }
+
+    #[tokio::test]
+    async fn returns_problems_from_handlers() {
+        let err = ProblemDetails::new(StatusCode::NOT_FOUND)
+            .with_type("https://example.com/problems/unknown-order")
+            .with_detail("no order 42")
+            .with_extension("order", 42)
+            .into();
+        let (status, body) = problem(ProblemLayer::new(), err).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert_eq!(
+            json!({
+                "type": "https://example.com/problems/unknown-order",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "no order 42",
+                "instance": "8476a536-e9f4-11e8-9739-2dfe598c3fcd",
+                "order": 42,
+            }),
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn hides_the_details_of_unmapped_errors() {
+        let (status, body) = problem(ProblemLayer::new(), "connection refused".into()).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+        assert_eq!(
+            json!({
+                "title": "Internal Server Error",
+                "status": 500,
+                "instance": "8476a536-e9f4-11e8-9739-2dfe598c3fcd",
+            }),
+            body
+        );
+
+        let layer = ProblemLayer::new().expose_details(true);
+        let (_, body) = problem(layer, "connection refused".into()).await;
+        assert_eq!("connection refused", body["detail"]);
+    }
+
+    #[tokio::test]
+    async fn maps_errors_into_problems() {
+        let layer = ProblemLayer::new().map(|err| {
+            err.downcast_ref::<std::num::ParseIntError>()
+                .map(|err| ProblemDetails::new(StatusCode::BAD_REQUEST).with_detail(err.to_string()))
+        });
+        let err = "forty-two".parse::<u32>().unwrap_err().into();
+        let (status, body) = problem(layer, err).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert_eq!("invalid digit found in string", body["detail"]);
+    }
+
+    #[tokio::test]
+    async fn passes_responses_through() {
+        let mut service = ProblemLayer::new().layer(service_fn(|_req: Request| async { Ok::<_, Error>("ok") }));
+        let response = service.call(request()).await.unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(&Body::from("ok"), response.body());
+    }
+}
@@ -0,0 +1,340 @@
+//! Protocol Buffers payloads.
+//!
+//! [`RequestProtobufExt`] decodes `application/x-protobuf` request bodies into
+//! [prost](https://docs.rs/prost) messages, and [`Protobuf`] encodes messages into
+//! binary responses, which the runtime sends base64 encoded as API Gateway and
+//! Function URLs expect.
+//!
+//! Handlers that serve both JSON and protobuf clients can decode the body with
+//! [`RequestProtobufExt::negotiated_payload`], which reads the format set in the
+//! `Content-Type` header, and encode the response with the [`Format`] that the
+//! client accepts, returned by [`RequestProtobufExt::response_format`].
+//!
+//! This module requires the `protobuf` feature.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{
+//!     protobuf::RequestProtobufExt, service_fn, Body, Error, Request, Response,
+//! };
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Clone, PartialEq, prost::Message, Deserialize, Serialize)]
+//! struct Greeting {
+//!     #[prost(string, tag = "1")]
+//!     name: String,
+//! }
+//!
+//! async fn greet(req: Request) -> Result<Response<Body>, Error> {
+//!     let greeting: Greeting = req.negotiated_payload()?.unwrap_or_default();
+//!     let reply = Greeting { name: format!("hello {}", greeting.name) };
+//!     req.response_format().response(&reply)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run(service_fn(greet)).await
+//! }
+//! ```
+use crate::{response::ResponseFuture, Body, Error, IntoResponse, Request};
+use base64::Engine;
+use http::{
+    header::{ACCEPT, CONTENT_TYPE},
+    HeaderMap, Response,
+};
+use prost::Message;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{borrow::Cow, fmt};
+
+/// Content type of protobuf payloads.
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+const PROTOBUF_CONTENT_TYPES: [&str; 3] = [
+    PROTOBUF_CONTENT_TYPE,
+    "application/protobuf",
+    "application/vnd.google.protobuf",
+];
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Errors returned when a request body can't be decoded.
+#[derive(Debug)]
+pub enum ProtobufError {
+    /// Returned when `application/x-protobuf` bodies fail to decode a message
+    Decode(prost::DecodeError),
+    /// Returned when `application/json` bodies fail to deserialize a message
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for ProtobufError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtobufError::Decode(err) => write!(f, "failed to decode payload from {PROTOBUF_CONTENT_TYPE}: {err}"),
+            ProtobufError::Json(err) => write!(f, "failed to parse payload from {JSON_CONTENT_TYPE}: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ProtobufError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProtobufError::Decode(err) => Some(err),
+            ProtobufError::Json(err) => Some(err),
+        }
+    }
+}
+
+/// Formats of the payloads exchanged with a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `application/json`
+    Json,
+    /// `application/x-protobuf`
+    Protobuf,
+}
+
+impl Format {
+    /// Return the format of a body with a given `Content-Type` header, if it's JSON or protobuf.
+    pub fn from_content_type(headers: &HeaderMap) -> Option<Format> {
+        let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+        Format::from_media_type(content_type.split(';').next().unwrap_or_default().trim())
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Format> {
+        if media_type.eq_ignore_ascii_case(JSON_CONTENT_TYPE) {
+            Some(Format::Json)
+        } else if PROTOBUF_CONTENT_TYPES
+            .iter()
+            .any(|protobuf| media_type.eq_ignore_ascii_case(protobuf))
+        {
+            Some(Format::Protobuf)
+        } else {
+            None
+        }
+    }
+
+    /// Return the content type of this format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => JSON_CONTENT_TYPE,
+            Format::Protobuf => PROTOBUF_CONTENT_TYPE,
+        }
+    }
+
+    /// Encode `message` in this format and build a response with the matching `Content-Type` header.
+    pub fn response<M>(self, message: &M) -> Result<Response<Body>, Error>
+    where
+        M: Message + Serialize,
+    {
+        let body = match self {
+            Format::Json => Body::Text(serde_json::to_string(message)?),
+            Format::Protobuf => Body::Binary(message.encode_to_vec()),
+        };
+        Ok(Response::builder()
+            .header(CONTENT_TYPE, self.content_type())
+            .body(body)?)
+    }
+}
+
+/// Extensions to read protobuf payloads from `lambda_http::Request` structs.
+pub trait RequestProtobufExt {
+    /// Decode an `application/x-protobuf` body into a message.
+    ///
+    /// Bodies that arrive as text are expected to be base64 encoded, which is how
+    /// binary payloads are sent by clients that can't send raw bytes. Text that
+    /// isn't valid base64 is decoded as is.
+    ///
+    /// If the request has no body, or it has a different content type, `Ok(None)` is returned.
+    fn protobuf_payload<M>(&self) -> Result<Option<M>, ProtobufError>
+    where
+        M: Message + Default;
+
+    /// Decode a body into a message, as protobuf or as JSON depending on its `Content-Type` header.
+    ///
+    /// If the request has no body, or it has a different content type, `Ok(None)` is returned.
+    fn negotiated_payload<M>(&self) -> Result<Option<M>, ProtobufError>
+    where
+        M: Message + Default + DeserializeOwned;
+
+    /// Return the format of the response that the client prefers, according to its `Accept` header.
+    ///
+    /// Wildcards, and requests without an `Accept` header, get a response in the
+    /// same format as their body, or JSON if the body is neither JSON nor protobuf.
+    fn response_format(&self) -> Format;
+}
+
+impl RequestProtobufExt for Request {
+    fn protobuf_payload<M>(&self) -> Result<Option<M>, ProtobufError>
+    where
+        M: Message + Default,
+    {
+        if Format::from_content_type(self.headers()) != Some(Format::Protobuf) {
+            return Ok(None);
+        }
+        let bytes: Cow<'_, [u8]> = match self.body() {
+            Body::Empty => return Ok(None),
+            Body::Binary(bytes) => Cow::Borrowed(bytes),
+            Body::Text(text) => match base64::engine::general_purpose::STANDARD.decode(text) {
+                Ok(decoded) => Cow::Owned(decoded),
+                Err(_) => Cow::Borrowed(text.as_bytes()),
+            },
+        };
+        M::decode(bytes.as_ref()).map(Some).map_err(ProtobufError::Decode)
+    }
+
+    fn negotiated_payload<M>(&self) -> Result<Option<M>, ProtobufError>
+    where
+        M: Message + Default + DeserializeOwned,
+    {
+        match Format::from_content_type(self.headers()) {
+            Some(Format::Protobuf) => self.protobuf_payload(),
+            Some(Format::Json) if !self.body().is_empty() => serde_json::from_slice(self.body().as_ref())
+                .map(Some)
+                .map_err(ProtobufError::Json),
+            _ => Ok(None),
+        }
+    }
+
+    fn response_format(&self) -> Format {
+        let fallback = Format::from_content_type(self.headers()).unwrap_or(Format::Json);
+        let accept = match self.headers().get(ACCEPT).and_then(|accept| accept.to_str().ok()) {
+            Some(accept) => accept,
+            None => return fallback,
+        };
+
+        let mut preferred: Option<(Format, f32)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let media_type = params.next().unwrap_or_default().trim();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let format = match media_type {
+                "*/*" | "application/*" => fallback,
+                other => match Format::from_media_type(other) {
+                    Some(format) => format,
+                    None => continue,
+                },
+            };
+            let better = match preferred {
+                Some((_, q)) => quality > q,
+                None => quality > 0.0,
+            };
+            if better {
+                preferred = Some((format, quality));
+            }
+        }
+        preferred.map_or(fallback, |(format, _)| format)
+    }
+}
+
+/// A response with a message encoded as protobuf.
+///
+/// The message is sent as a binary body with the `application/x-protobuf` content type.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Protobuf<M>(pub M);
+
+impl<M: Message> IntoResponse for Protobuf<M> {
+    fn into_response(self) -> ResponseFuture {
+        let response = Response::builder()
+            .header(CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)
+            .body(Body::Binary(self.0.encode_to_vec()))
+            .expect("unable to build http::Response");
+        Box::pin(async move { response })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Clone, PartialEq, Message, Deserialize, Serialize)]
+    struct Greeting {
+        #[prost(string, tag = "1")]
+        name: String,
+        #[prost(uint32, tag = "2")]
+        times: u32,
+    }
+
+    fn greeting() -> Greeting {
+        Greeting {
+            name: "ferris".into(),
+            times: 3,
+        }
+    }
+
+    fn request(content_type: &str, accept: Option<&str>, body: Body) -> Request {
+        let mut builder = http::Request::builder().header(CONTENT_TYPE, content_type);
+        if let Some(accept) = accept {
+            builder = builder.header(ACCEPT, accept);
+        }
+        builder.body(body).unwrap()
+    }
+
+    #[test]
+    fn decodes_binary_and_base64_bodies() {
+        let bytes = greeting().encode_to_vec();
+        let binary = request(PROTOBUF_CONTENT_TYPE, None, Body::Binary(bytes.clone()));
+        assert_eq!(Some(greeting()), binary.protobuf_payload().unwrap());
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+        let text = request("application/protobuf", None, Body::Text(encoded));
+        assert_eq!(Some(greeting()), text.protobuf_payload().unwrap());
+
+        let json = request(JSON_CONTENT_TYPE, None, Body::Text("{}".into()));
+        assert_eq!(None, json.protobuf_payload::<Greeting>().unwrap());
+    }
+
+    #[test]
+    fn reports_invalid_messages() {
+        let req = request(PROTOBUF_CONTENT_TYPE, None, Body::Binary(vec![0x0a, 0x05, b'a']));
+        let err = req.protobuf_payload::<Greeting>().unwrap_err();
+        assert!(matches!(err, ProtobufError::Decode(_)), "{err}");
+    }
+
+    #[test]
+    fn negotiates_request_formats() {
+        let json = request(
+            "application/json; charset=utf-8",
+            None,
+            Body::Text(r#"{"name":"ferris","times":3}"#.into()),
+        );
+        assert_eq!(Some(greeting()), json.negotiated_payload().unwrap());
+
+        let protobuf = request(PROTOBUF_CONTENT_TYPE, None, Body::Binary(greeting().encode_to_vec()));
+        assert_eq!(Some(greeting()), protobuf.negotiated_payload().unwrap());
+    }
+
+    #[test]
+    fn negotiates_response_formats() {
+        let format = |content_type, accept| request(content_type, accept, Body::Empty).response_format();
+        assert_eq!(Format::Json, format(JSON_CONTENT_TYPE, None));
+        assert_eq!(Format::Protobuf, format(PROTOBUF_CONTENT_TYPE, None));
+        assert_eq!(Format::Protobuf, format(PROTOBUF_CONTENT_TYPE, Some("*/*")));
+        assert_eq!(Format::Json, format(PROTOBUF_CONTENT_TYPE, Some("application/json")));
+        assert_eq!(
+            Format::Protobuf,
+            format(
+                JSON_CONTENT_TYPE,
+                Some("application/json;q=0.5, application/x-protobuf")
+            )
+        );
+        assert_eq!(Format::Json, format("text/plain", Some("text/html")));
+    }
+
+    #[tokio::test]
+    async fn protobuf_responses_are_binary() {
+        let response = Protobuf(greeting()).into_response().await;
+        assert_eq!(PROTOBUF_CONTENT_TYPE, response.headers()[CONTENT_TYPE]);
+        match response.body() {
+            Body::Binary(bytes) => assert_eq!(greeting(), Greeting::decode(bytes.as_slice()).unwrap()),
+            other => panic!("unexpected body {other:?}"),
+        }
+
+        let response = Format::Json.response(&greeting()).unwrap();
+        assert_eq!(JSON_CONTENT_TYPE, response.headers()[CONTENT_TYPE]);
+        assert!(matches!(response.body(), Body::Text(_)));
+    }
+}
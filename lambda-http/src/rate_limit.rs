@@ -0,0 +1,438 @@
+//! Rate limiting keyed by the identity of the caller.
+//!
+//! [`RateLimitLayer`] gives every caller a token bucket. Each request takes a token
+//! from the bucket of its caller, and buckets are refilled at the rate set in their
+//! [`Quota`]. When a bucket is empty, the request is rejected with a
+//! `429 Too Many Requests` response and a `Retry-After` header, and the handler
+//! isn't called.
+//!
+//! Callers are identified by their source IP address by default. Other keys, like
+//! the [API key id](api_key_id), the [subject of a JWT](jwt_subject), or any value
+//! returned by a closure, can be set with [`RateLimitLayer::key`]. Requests without
+//! a key are not limited.
+//!
+//! Buckets are kept in the memory of the execution environment by default, with an
+//! [`InMemoryStore`]. Every concurrent execution environment has its own buckets, so
+//! the effective limit grows with the concurrency of the function, but it's enough
+//! to dampen abusive callers of a Function URL. Limits shared by all execution
+//! environments need a [`RateLimitStore`] backed by an external service, like
+//! DynamoDB or ElastiCache.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{rate_limit::{self, Quota, RateLimitLayer}, service_fn, tower::Layer, Error, Request};
+//!
+//! async fn handler(_req: Request) -> Result<&'static str, Error> {
+//!     Ok("hello")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let limit = RateLimitLayer::new(Quota::per_minute(60).burst(10)).key(rate_limit::api_key_id);
+//!     lambda_http::run(limit.layer(service_fn(handler))).await
+//! }
+//! ```
+use crate::{ext::RequestExt, request::RequestContext, request_log, Body, IntoResponse, Request, Response};
+use futures::future::BoxFuture;
+use http::{
+    header::{CONTENT_TYPE, RETRY_AFTER},
+    StatusCode,
+};
+use lambda_runtime::{tower::Layer, Error, Service};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant},
+};
+
+type KeyFn = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+/// Size of the token buckets, and how fast they're refilled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    burst: u32,
+    interval: Duration,
+}
+
+impl Quota {
+    /// Allow `requests` requests per second.
+    pub fn per_second(requests: u32) -> Self {
+        Self::with_period(requests, Duration::from_secs(1))
+    }
+
+    /// Allow `requests` requests per minute.
+    pub fn per_minute(requests: u32) -> Self {
+        Self::with_period(requests, Duration::from_secs(60))
+    }
+
+    /// Allow `requests` requests per `period`.
+    ///
+    /// Tokens are refilled one at a time, evenly spread over the period. The bucket
+    /// holds `requests` tokens, unless a different size is set with [`Quota::burst`].
+    pub fn with_period(requests: u32, period: Duration) -> Self {
+        let requests = requests.max(1);
+        Quota {
+            burst: requests,
+            interval: period / requests,
+        }
+    }
+
+    /// Set the number of requests that a caller can send at once, after being idle.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Return the number of tokens that a bucket holds.
+    pub fn burst_size(&self) -> u32 {
+        self.burst
+    }
+
+    /// Return the time it takes to refill one token.
+    pub fn replenish_interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+/// Whether a request can go through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// The request took a token and can go through.
+    Allow,
+    /// The bucket is empty. A token will be available after `retry_after`.
+    Limit {
+        /// Time until the next token is available.
+        retry_after: Duration,
+    },
+}
+
+/// Storage for the token buckets of every caller.
+pub trait RateLimitStore: Send + Sync {
+    /// Take a token from the bucket of `key`, refilled according to `quota`.
+    fn acquire<'a>(&'a self, key: &'a str, quota: &'a Quota) -> BoxFuture<'a, Result<Decision, Error>>;
+}
+
+/// [`RateLimitStore`] that keeps the buckets in memory.
+///
+/// Buckets that are full again are dropped once the store holds more than
+/// `max_keys` buckets, so memory usage is bounded by the number of active callers.
+#[derive(Debug)]
+pub struct InMemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    max_keys: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, quota: &Quota) {
+        let refilled = now.saturating_duration_since(self.updated).as_secs_f64() / quota.interval.as_secs_f64();
+        self.tokens = (self.tokens + refilled).min(quota.burst as f64);
+        self.updated = now;
+    }
+}
+
+impl InMemoryStore {
+    /// Create a new store that starts dropping full buckets after 10,000 callers.
+    pub fn new() -> Self {
+        Self::with_max_keys(10_000)
+    }
+
+    /// Create a new store that starts dropping full buckets after `max_keys` callers.
+    pub fn with_max_keys(max_keys: usize) -> Self {
+        InMemoryStore {
+            buckets: Mutex::new(HashMap::new()),
+            max_keys,
+        }
+    }
+
+    fn take(&self, key: &str, quota: &Quota, now: Instant) -> Decision {
+        let mut buckets = self.buckets.lock().expect("rate limit buckets poisoned");
+        if buckets.len() >= self.max_keys && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| {
+                bucket.refill(now, quota);
+                bucket.tokens < quota.burst as f64
+            });
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: quota.burst as f64,
+            updated: now,
+        });
+        bucket.refill(now, quota);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Decision::Allow
+        } else {
+            let retry_after = quota.interval.mul_f64(1.0 - bucket.tokens);
+            Decision::Limit { retry_after }
+        }
+    }
+}
+
+impl Default for InMemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimitStore for InMemoryStore {
+    fn acquire<'a>(&'a self, key: &'a str, quota: &'a Quota) -> BoxFuture<'a, Result<Decision, Error>> {
+        let decision = self.take(key, quota, Instant::now());
+        Box::pin(async move { Ok(decision) })
+    }
+}
+
+/// Identify callers by their [client IP](crate::RequestExt::client_ip), which
+/// ignores the `X-Forwarded-For` entries added by clients.
+pub fn source_ip(req: &Request) -> Option<String> {
+    request_log::source_ip(req)
+}
+
+/// Identify callers by the id of the API Gateway API key of the request.
+pub fn api_key_id(req: &Request) -> Option<String> {
+    match req.request_context_ref() {
+        #[cfg(feature = "apigw_rest")]
+        Some(RequestContext::ApiGatewayV1(ctx)) => ctx.identity.api_key_id.clone(),
+        #[cfg(feature = "apigw_websockets")]
+        Some(RequestContext::WebSocket(ctx)) => ctx.identity.api_key_id.clone(),
+        _ => None,
+    }
+}
+
+/// Identify callers by the `sub` claim of the JWT validated by an API Gateway authorizer.
+pub fn jwt_subject(req: &Request) -> Option<String> {
+    match req.request_context_ref() {
+        #[cfg(feature = "apigw_http")]
+        Some(RequestContext::ApiGatewayV2(ctx)) => ctx
+            .authorizer
+            .as_ref()
+            .and_then(|authorizer| authorizer.jwt.as_ref())
+            .and_then(|jwt| jwt.claims.get("sub").cloned()),
+        #[cfg(feature = "apigw_rest")]
+        Some(RequestContext::ApiGatewayV1(ctx)) => ctx
+            .authorizer
+            .get("claims")
+            .and_then(|claims| claims.get("sub"))
+            .and_then(|sub| sub.as_str())
+            .map(String::from),
+        _ => None,
+    }
+}
+
+/// A [`Layer`] that rejects requests from callers that go over their [`Quota`].
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    quota: Quota,
+    key: KeyFn,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimitLayer {
+    /// Create a new layer that limits every source IP address to `quota`, with an [`InMemoryStore`].
+    pub fn new(quota: Quota) -> Self {
+        RateLimitLayer {
+            quota,
+            key: Arc::new(source_ip),
+            store: Arc::new(InMemoryStore::new()),
+        }
+    }
+
+    /// Identify callers with `key`. Requests for which `key` returns `None` are not limited.
+    pub fn key<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = Arc::new(key);
+        self
+    }
+
+    /// Keep the token buckets in `store`.
+    pub fn store<T>(mut self, store: T) -> Self
+    where
+        T: RateLimitStore + 'static,
+    {
+        self.store = Arc::new(store);
+        self
+    }
+}
+
+impl fmt::Debug for RateLimitLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitLayer").field("quota", &self.quota).finish()
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            config: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that rejects requests from callers that go over their quota.
+///
+/// See [`RateLimitLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct RateLimit<S> {
+    inner: S,
+    config: RateLimitLayer,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Into<Error>,
+{
+    type Response = Response<Body>;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        // The inner service was driven to readiness, so it's the one that must handle the request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let key = (self.config.key)(&req);
+        let quota = self.config.quota;
+        let store = self.config.store.clone();
+
+        Box::pin(async move {
+            if let Some(key) = key {
+                if let Decision::Limit { retry_after } = store.acquire(&key, &quota).await? {
+                    return Ok(too_many_requests(retry_after));
+                }
+            }
+            let response = inner.call(req).await.map_err(Into::into)?.into_response();
+            Ok(response.await)
+        })
+    }
+}
+
+fn too_many_requests(retry_after: Duration) -> Response<Body> {
+    let mut seconds = retry_after.as_secs();
+    if retry_after.subsec_nanos() > 0 || seconds == 0 {
+        seconds += 1;
+    }
+    Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header(RETRY_AFTER, seconds)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from("Too Many Requests"))
+        .expect("unable to build http::Response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_fn;
+    use lambda_runtime::tower::ServiceExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn request(caller: Option<&str>) -> Request {
+        let mut builder = http::Request::builder();
+        if let Some(caller) = caller {
+            builder = builder.header("x-forwarded-for", caller);
+        }
+        builder.body(Body::Empty).unwrap()
+    }
+
+    #[tokio::test]
+    async fn limits_every_caller_separately() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let handler = service_fn(move |_req: Request| {
+            c.fetch_add(1, Ordering::SeqCst);
+            async move { Ok::<_, Error>("ok") }
+        });
+        let svc = RateLimitLayer::new(Quota::per_minute(60).burst(2)).layer(handler);
+
+        for _ in 0..2 {
+            let response = svc.clone().oneshot(request(Some("10.0.0.1"))).await.unwrap();
+            assert_eq!(StatusCode::OK, response.status());
+        }
+        let response = svc.clone().oneshot(request(Some("10.0.0.1"))).await.unwrap();
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+        assert_eq!("1", response.headers()[RETRY_AFTER]);
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+
+        let response = svc.clone().oneshot(request(Some("10.0.0.2"))).await.unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        for _ in 0..3 {
+            let response = svc.clone().oneshot(request(None)).await.unwrap();
+            assert_eq!(StatusCode::OK, response.status());
+        }
+    }
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let store = InMemoryStore::new();
+        let quota = Quota::per_second(2);
+        let start = Instant::now();
+
+        assert_eq!(Decision::Allow, store.take("caller", &quota, start));
+        assert_eq!(Decision::Allow, store.take("caller", &quota, start));
+        assert_eq!(
+            Decision::Limit {
+                retry_after: Duration::from_millis(500)
+            },
+            store.take("caller", &quota, start)
+        );
+        let later = start + Duration::from_millis(500);
+        assert_eq!(Decision::Allow, store.take("caller", &quota, later));
+    }
+
+    #[test]
+    fn full_buckets_are_dropped_over_the_key_limit() {
+        let store = InMemoryStore::with_max_keys(2);
+        let quota = Quota::per_second(1);
+        let start = Instant::now();
+
+        store.take("a", &quota, start);
+        store.take("b", &quota, start);
+        store.take("c", &quota, start + Duration::from_secs(5));
+        assert_eq!(1, store.buckets.lock().unwrap().len());
+    }
+
+    #[test]
+    #[cfg(feature = "apigw_http")]
+    fn reads_jwt_subjects() {
+        use aws_lambda_events::apigw::{
+            ApiGatewayV2httpRequestContext, ApiGatewayV2httpRequestContextAuthorizerDescription,
+            ApiGatewayV2httpRequestContextAuthorizerJwtDescription,
+        };
+
+        let jwt = ApiGatewayV2httpRequestContextAuthorizerJwtDescription {
+            claims: HashMap::from([("sub".to_string(), "user-1".to_string())]),
+            scopes: None,
+        };
+        let ctx = ApiGatewayV2httpRequestContext {
+            authorizer: Some(ApiGatewayV2httpRequestContextAuthorizerDescription {
+                jwt: Some(jwt),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let req = request(None).with_request_context(RequestContext::ApiGatewayV2(ctx));
+        assert_eq!(Some("user-1".to_string()), jwt_subject(&req));
+        assert_eq!(None, api_key_id(&req));
+    }
+}
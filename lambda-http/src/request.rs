@@ -17,6 +17,8 @@ use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyRequestCon
 use aws_lambda_events::apigw::{ApiGatewayV2httpRequest, ApiGatewayV2httpRequestContext};
 #[cfg(feature = "apigw_websockets")]
 use aws_lambda_events::apigw::{ApiGatewayWebsocketProxyRequest, ApiGatewayWebsocketProxyRequestContext};
+#[cfg(feature = "vpc_lattice")]
+use aws_lambda_events::vpc_lattice::{VpcLatticeRequestV1, VpcLatticeRequestV2, VpcLatticeRequestV2Context};
 use aws_lambda_events::{encodings::Body, query_map::QueryMap};
 use http::header::HeaderName;
 use http::{HeaderMap, HeaderValue};
@@ -36,6 +38,13 @@ use url::Url;
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
 pub enum LambdaRequest {
+    // VPC Lattice events are the only ones with a `method` field, and are tried
+    // first because most fields of the other events are optional. Only the
+    // format 2.0 has a `requestContext`.
+    #[cfg(feature = "vpc_lattice")]
+    VpcLatticeV2(VpcLatticeRequestV2),
+    #[cfg(feature = "vpc_lattice")]
+    VpcLatticeV1(VpcLatticeRequestV1),
     #[cfg(feature = "apigw_rest")]
     ApiGatewayV1(ApiGatewayProxyRequest),
     #[cfg(feature = "apigw_http")]
@@ -60,6 +69,8 @@ impl LambdaRequest {
             LambdaRequest::Alb { .. } => RequestOrigin::Alb,
             #[cfg(feature = "apigw_websockets")]
             LambdaRequest::WebSocket { .. } => RequestOrigin::WebSocket,
+            #[cfg(feature = "vpc_lattice")]
+            LambdaRequest::VpcLatticeV1 { .. } | LambdaRequest::VpcLatticeV2 { .. } => RequestOrigin::VpcLattice,
         }
     }
 }
@@ -68,8 +79,7 @@ impl LambdaRequest {
 pub type RequestFuture<'a, R, E> = Pin<Box<dyn Future<Output = Result<R, E>> + Send + 'a>>;
 
 /// Represents the origin from which the lambda was requested from.
-#[doc(hidden)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RequestOrigin {
     /// API Gateway request origin
     #[cfg(feature = "apigw_rest")]
@@ -83,6 +93,9 @@ pub enum RequestOrigin {
     /// API Gateway WebSocket
     #[cfg(feature = "apigw_websockets")]
     WebSocket,
+    /// VPC Lattice request origin
+    #[cfg(feature = "vpc_lattice")]
+    VpcLattice,
 }
 
 #[cfg(feature = "apigw_http")]
@@ -324,8 +337,91 @@ fn into_websocket_request(ag: ApiGatewayWebsocketProxyRequest) -> http::Request<
     req
 }
 
+#[cfg(feature = "vpc_lattice")]
+fn into_vpc_lattice_v1_request(lattice: VpcLatticeRequestV1) -> http::Request<Body> {
+    let host = lattice.headers.get(http::header::HOST).and_then(|s| s.to_str().ok());
+    let mut raw_path = lattice.raw_path.unwrap_or_default();
+    let mut query_string_parameters = lattice.query_string_parameters;
+    // Some targets receive the query string in `raw_path`.
+    if let Some((path, query)) = raw_path.split_once('?') {
+        if query_string_parameters.is_empty() {
+            query_string_parameters = query.parse().unwrap(); // this is Infallible
+        }
+        raw_path = path.to_string();
+    }
+
+    let builder = http::Request::builder()
+        .uri(build_request_uri(
+            &raw_path,
+            &lattice.headers,
+            host,
+            Some((&query_string_parameters, &QueryMap::default())),
+        ))
+        .extension(RawHttpPath(raw_path))
+        .extension(QueryStringParameters(query_string_parameters))
+        .extension(RequestContext::VpcLattice(VpcLatticeRequestV2Context::default()));
+
+    let mut headers = lattice.headers;
+    update_xray_trace_id_header(&mut headers);
+
+    let base64 = lattice.is_base64_encoded;
+    let mut req = builder
+        .body(
+            lattice
+                .body
+                .as_deref()
+                .filter(|b| !b.is_empty())
+                .map_or_else(Body::default, |b| Body::from_maybe_encoded(base64, b)),
+        )
+        .expect("failed to build request");
+
+    // no builder method that sets headers in batch
+    let _ = mem::replace(req.headers_mut(), headers);
+    let _ = mem::replace(req.method_mut(), lattice.method);
+
+    req
+}
+
+#[cfg(feature = "vpc_lattice")]
+fn into_vpc_lattice_v2_request(lattice: VpcLatticeRequestV2) -> http::Request<Body> {
+    let host = lattice.headers.get(http::header::HOST).and_then(|s| s.to_str().ok());
+    let raw_path = lattice.path.unwrap_or_default();
+
+    let builder = http::Request::builder()
+        .uri(build_request_uri(
+            &raw_path,
+            &lattice.headers,
+            host,
+            Some((&lattice.query_string_parameters, &QueryMap::default())),
+        ))
+        .extension(RawHttpPath(raw_path))
+        .extension(QueryStringParameters(lattice.query_string_parameters))
+        .extension(RequestContext::VpcLattice(lattice.request_context));
+
+    // the headers of this format are always multi-valued
+    let mut headers = lattice.headers;
+    update_xray_trace_id_header(&mut headers);
+
+    let base64 = lattice.is_base64_encoded;
+    let mut req = builder
+        .body(
+            lattice
+                .body
+                .as_deref()
+                .filter(|b| !b.is_empty())
+                .map_or_else(Body::default, |b| Body::from_maybe_encoded(base64, b)),
+        )
+        .expect("failed to build request");
+
+    // no builder method that sets headers in batch
+    let _ = mem::replace(req.headers_mut(), headers);
+    let _ = mem::replace(req.method_mut(), lattice.method);
+
+    req
+}
+
 #[cfg(any(feature = "apigw_rest", feature = "apigw_http", feature = "apigw_websockets"))]
-fn apigw_path_with_stage(stage: &Option<String>, path: &str) -> String {
+pub(crate) fn apigw_path_with_stage(stage: &Option<String>, path: &str) -> String {
     match stage {
         None => path.into(),
         Some(stage) if stage == "$default" => path.into(),
@@ -350,6 +446,9 @@ pub enum RequestContext {
     /// WebSocket request context
     #[cfg(feature = "apigw_websockets")]
     WebSocket(ApiGatewayWebsocketProxyRequestContext),
+    /// VPC Lattice request context, empty for the events in the format 1.0
+    #[cfg(feature = "vpc_lattice")]
+    VpcLattice(VpcLatticeRequestV2Context),
 }
 
 /// Converts LambdaRequest types into `http::Request<Body>` types
@@ -364,6 +463,10 @@ impl From<LambdaRequest> for http::Request<Body> {
             LambdaRequest::Alb(alb) => into_alb_request(alb),
             #[cfg(feature = "apigw_websockets")]
             LambdaRequest::WebSocket(ag) => into_websocket_request(ag),
+            #[cfg(feature = "vpc_lattice")]
+            LambdaRequest::VpcLatticeV1(lattice) => into_vpc_lattice_v1_request(lattice),
+            #[cfg(feature = "vpc_lattice")]
+            LambdaRequest::VpcLatticeV2(lattice) => into_vpc_lattice_v2_request(lattice),
         }
     }
 }
@@ -411,6 +514,11 @@ pub fn from_str(s: &str) -> Result<crate::Request, JsonError> {
     serde_json::from_str(s).map(LambdaRequest::into)
 }
 
+/// Deserializes a `Request` from a JSON value.
+pub fn from_value(value: serde_json::Value) -> Result<crate::Request, JsonError> {
+    serde_json::from_value(value).map(LambdaRequest::into)
+}
+
 fn x_forwarded_proto() -> HeaderName {
     HeaderName::from_static("x-forwarded-proto")
 }
@@ -599,6 +707,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn deserializes_vpc_lattice_request_events() {
+        // from the docs
+        // https://docs.aws.amazon.com/vpc-lattice/latest/ug/lambda-functions.html#receive-event-from-service
+        let input = include_str!("../tests/data/vpc_lattice_v1_request.json");
+        let req = from_str(input).expect("failed to parse request");
+        assert_eq!(req.method(), "GET");
+        assert_eq!(
+            req.uri(),
+            "https://pets-0123456789abcdef0.7d67968.vpc-lattice-svcs.us-east-1.on.aws/pets/42?verbose=true"
+        );
+        assert_eq!(req.body(), &Body::Empty);
+
+        let input = include_str!("../tests/data/vpc_lattice_v2_request.json");
+        let req = from_str(input).expect("failed to parse request");
+        assert_eq!(req.method(), "POST");
+        assert_eq!(
+            req.uri(),
+            "https://pets-0123456789abcdef0.7d67968.vpc-lattice-svcs.us-east-1.on.aws/pets?tag=dog&tag=good+boy"
+        );
+        assert_eq!(Some(vec!["dog", "good boy"]), req.query_string_parameters().all("tag"));
+        let custom: Vec<_> = req.headers().get_all("x-custom").iter().collect();
+        assert_eq!(vec!["a", "b"], custom);
+        assert_eq!(req.body(), &Body::Binary(br#"{"name":"Rex"}"#.to_vec()));
+
+        // Ensure this is a VPC Lattice request
+        let req_context = req.request_context_ref().expect("Request is missing RequestContext");
+        assert!(
+            matches!(req_context, RequestContext::VpcLattice(ctx) if ctx.identity.type_.as_deref() == Some("AWS_IAM")),
+            "expected VpcLattice context, got {req_context:?}"
+        );
+    }
+
     #[test]
     fn deserializes_alb_request_encoded_query_parameters_events() {
         // from the docs
@@ -0,0 +1,164 @@
+//! Request and trace ids in every response.
+//!
+//! [`RequestIdLayer`] adds an `x-request-id` header with the Lambda request id,
+//! and an `x-amzn-trace-id` header with the X-Ray trace id of the invocation, to
+//! the responses of a handler, so clients can quote them when they report a
+//! problem. Headers already set by the handler are left untouched.
+//!
+//! When the client sends its own `x-request-id`, the layer keeps it as the
+//! correlation id of the request, available with
+//! [`RequestExt::correlation_id`], so handlers can log it next to their own
+//! messages. Ids longer than [`MAX_CORRELATION_ID_LENGTH`] or with characters
+//! other than visible ASCII are ignored, they would let clients forge log lines.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{request_id::RequestIdLayer, service_fn, tower::Layer, Error, Request, RequestExt};
+//!
+//! async fn handler(req: Request) -> Result<&'static str, Error> {
+//!     tracing::info!(correlation_id = req.correlation_id(), "hello");
+//!     Ok("hello")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run(RequestIdLayer.layer(service_fn(handler))).await
+//! }
+//! ```
+use crate::{ext::extensions::CorrelationId, ext::RequestExt, Body, IntoResponse, Request, Response};
+use futures::future::BoxFuture;
+use http::{HeaderName, HeaderValue};
+use lambda_runtime::{tower::Layer, Service};
+use std::task::{Context as TaskContext, Poll};
+
+/// Header with the Lambda request id in responses, and the correlation id of the client in requests.
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+/// Header with the X-Ray trace id of the invocation.
+pub const X_AMZN_TRACE_ID: HeaderName = HeaderName::from_static("x-amzn-trace-id");
+/// Maximum length of the correlation ids accepted from clients.
+pub const MAX_CORRELATION_ID_LENGTH: usize = 128;
+
+/// A [`Layer`] that adds the request and trace ids of the invocation to the responses.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+/// A [`Service`] that adds the request and trace ids of the invocation to the responses.
+///
+/// See [`RequestIdLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for RequestIdService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        let correlation_id = req
+            .headers()
+            .get(X_REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_valid_correlation_id(id))
+            .map(String::from);
+        if let Some(id) = correlation_id {
+            req.extensions_mut().insert(CorrelationId(id));
+        }
+
+        let context = req.lambda_context_ref();
+        let request_id = context.and_then(|ctx| HeaderValue::from_str(&ctx.request_id).ok());
+        let trace_id = context
+            .and_then(|ctx| ctx.xray_trace_id.as_deref())
+            .and_then(|id| HeaderValue::from_str(id).ok());
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            let mut response = response.await;
+            let headers = response.headers_mut();
+            for (name, value) in [(X_REQUEST_ID, request_id), (X_AMZN_TRACE_ID, trace_id)] {
+                if let Some(value) = value {
+                    headers.entry(name).or_insert(value);
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+fn is_valid_correlation_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_CORRELATION_ID_LENGTH && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_runtime::{service_fn, Context};
+
+    fn request(correlation_id: Option<&str>) -> Request {
+        let mut context = Context::default();
+        context.request_id = "lambda-request-id".into();
+        context.xray_trace_id = Some("Root=1-5759e988-bd862e3fe1be46a994272793".into());
+        let mut req = Request::default().with_lambda_context(context);
+        if let Some(id) = correlation_id {
+            req.headers_mut().insert(X_REQUEST_ID, id.parse().unwrap());
+        }
+        req
+    }
+
+    #[tokio::test]
+    async fn stamps_the_ids_and_keeps_the_correlation_id() {
+        let mut service = RequestIdLayer.layer(service_fn(|req: Request| async move {
+            Ok::<_, lambda_runtime::Error>(req.correlation_id().unwrap_or_default().to_string())
+        }));
+
+        let res = service.call(request(Some("client-id-1"))).await.unwrap();
+        assert_eq!("lambda-request-id", res.headers()[X_REQUEST_ID]);
+        assert_eq!(
+            "Root=1-5759e988-bd862e3fe1be46a994272793",
+            res.headers()[X_AMZN_TRACE_ID]
+        );
+        assert_eq!(&Body::Text("client-id-1".into()), res.body());
+
+        let res = service.call(request(None)).await.unwrap();
+        assert_eq!(&Body::Text("lambda-request-id".into()), res.body());
+
+        let res = service.call(request(Some("forged id"))).await.unwrap();
+        assert_eq!(&Body::Text("lambda-request-id".into()), res.body());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_headers_of_the_handler() {
+        let mut service = RequestIdLayer.layer(service_fn(|_req: Request| async {
+            Response::builder()
+                .header(X_REQUEST_ID, "custom")
+                .body(Body::Empty)
+                .map_err(lambda_runtime::Error::from)
+        }));
+
+        let res = service.call(request(None)).await.unwrap();
+        assert_eq!("custom", res.headers()[X_REQUEST_ID]);
+        assert!(res.headers().contains_key(X_AMZN_TRACE_ID));
+    }
+}
@@ -0,0 +1,369 @@
+//! Structured access logs for HTTP invocations.
+//!
+//! [`RequestLogLayer`] emits one log line per invocation with the method, path,
+//! status code, latency, response size, source IP, request id, and user agent
+//! of the request, formatted as logfmt or JSON. Sensitive headers and body fields
+//! are masked with a [`Redactor`] before the line is written.
+use crate::{admin::AdminResponse, ext::RequestExt, Body, IntoResponse, Request, Response};
+use futures::future::BoxFuture;
+use http::header::USER_AGENT;
+use lambda_runtime::{
+    redact::{FieldRedactor, Redactor},
+    tower::Layer,
+    Service,
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::Instant,
+};
+
+type Sink = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Format of the log lines.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum LogFormat {
+    /// `key=value` pairs separated by spaces.
+    #[default]
+    Logfmt,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Information logged for every invocation.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+pub struct RequestLog {
+    /// The HTTP method of the request.
+    pub method: String,
+    /// The path of the request.
+    pub path: String,
+    /// The status code of the response.
+    pub status: u16,
+    /// The time spent in the handler, in milliseconds.
+    pub latency_ms: u128,
+    /// The size of the response body, in bytes.
+    pub bytes: usize,
+    /// The IP address of the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_ip: Option<String>,
+    /// The id of the Lambda invocation.
+    pub request_id: String,
+    /// The user agent of the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// The request headers, when header logging is enabled.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// The request body, when body logging is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl RequestLog {
+    /// Format the log line.
+    pub fn format(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Json => serde_json::to_string(self).expect("request log is always serializable"),
+            LogFormat::Logfmt => {
+                let mut line = String::new();
+                let _ = write!(
+                    line,
+                    "method={} path={} status={} latency_ms={} bytes={}",
+                    logfmt_value(&self.method),
+                    logfmt_value(&self.path),
+                    self.status,
+                    self.latency_ms,
+                    self.bytes
+                );
+                if let Some(source_ip) = &self.source_ip {
+                    let _ = write!(line, " source_ip={}", logfmt_value(source_ip));
+                }
+                let _ = write!(line, " request_id={}", logfmt_value(&self.request_id));
+                if let Some(user_agent) = &self.user_agent {
+                    let _ = write!(line, " user_agent={}", logfmt_value(user_agent));
+                }
+                for (name, value) in &self.headers {
+                    let _ = write!(line, " header.{}={}", name, logfmt_value(value));
+                }
+                if let Some(body) = &self.body {
+                    let _ = write!(line, " body={}", logfmt_value(body));
+                }
+                line
+            }
+        }
+    }
+}
+
+/// A [`Layer`] that logs one structured line for every HTTP invocation.
+///
+/// Lines are written to stdout by default, which sends them to CloudWatch Logs.
+/// Request headers and bodies are not logged unless [`RequestLogLayer::log_headers`]
+/// or [`RequestLogLayer::log_body`] are enabled. When they are, they go through the
+/// layer's [`Redactor`] first, which masks the headers listed in
+/// [`lambda_runtime::redact::SENSITIVE_HEADERS`] by default.
+///
+/// Responses of the [admin endpoints](crate::admin) are not logged.
+///
+/// # Example
+/// ```no_run
+/// use lambda_http::{request_log::LogFormat, service_fn, tower::ServiceBuilder, Error, Request, RequestLogLayer};
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let func = ServiceBuilder::new()
+///         .layer(RequestLogLayer::new().format(LogFormat::Json))
+///         .service(service_fn(|_: Request| async { Ok::<_, Error>("hello") }));
+///     lambda_http::run(func).await
+/// }
+/// ```
+#[derive(Clone)]
+pub struct RequestLogLayer {
+    format: LogFormat,
+    log_headers: bool,
+    log_body: bool,
+    redactor: Arc<dyn Redactor>,
+    sink: Sink,
+}
+
+impl RequestLogLayer {
+    /// Create a new layer that writes logfmt lines to stdout.
+    pub fn new() -> Self {
+        RequestLogLayer {
+            format: LogFormat::default(),
+            log_headers: false,
+            log_body: false,
+            redactor: Arc::new(FieldRedactor::default()),
+            sink: Arc::new(|line| println!("{line}")),
+        }
+    }
+
+    /// Set the format of the log lines.
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Include the request headers in the log lines.
+    pub fn log_headers(mut self, log_headers: bool) -> Self {
+        self.log_headers = log_headers;
+        self
+    }
+
+    /// Include the request body in the log lines.
+    pub fn log_body(mut self, log_body: bool) -> Self {
+        self.log_body = log_body;
+        self
+    }
+
+    /// Mask sensitive headers and body fields with `redactor`.
+    pub fn redactor<R>(mut self, redactor: R) -> Self
+    where
+        R: Redactor + 'static,
+    {
+        self.redactor = Arc::new(redactor);
+        self
+    }
+
+    /// Send the log lines to `sink` instead of stdout.
+    pub fn sink<F>(mut self, sink: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.sink = Arc::new(sink);
+        self
+    }
+}
+
+impl Default for RequestLogLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for RequestLogLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestLogLayer")
+            .field("format", &self.format)
+            .field("log_headers", &self.log_headers)
+            .field("log_body", &self.log_body)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for RequestLogLayer {
+    type Service = RequestLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestLogService {
+            inner,
+            config: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that logs one structured line for every HTTP invocation.
+///
+/// See [`RequestLogLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct RequestLogService<S> {
+    inner: S,
+    config: RequestLogLayer,
+}
+
+impl<S> Service<Request> for RequestLogService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let mut log = RequestLog {
+            method: req.method().to_string(),
+            path: req.uri().path().to_string(),
+            source_ip: source_ip(&req),
+            request_id: req
+                .lambda_context_ref()
+                .map(|ctx| ctx.request_id.clone())
+                .unwrap_or_default(),
+            user_agent: req
+                .headers()
+                .get(USER_AGENT)
+                .map(|v| String::from_utf8_lossy(v.as_bytes()).to_string()),
+            ..Default::default()
+        };
+
+        if self.config.log_headers {
+            for (name, value) in req.headers() {
+                let value = self
+                    .config
+                    .redactor
+                    .redact_header(name.as_str(), &String::from_utf8_lossy(value.as_bytes()));
+                log.headers
+                    .entry(name.to_string())
+                    .and_modify(|v| {
+                        v.push_str(", ");
+                        v.push_str(&value);
+                    })
+                    .or_insert(value);
+            }
+        }
+
+        if self.config.log_body {
+            log.body = Some(self.config.redactor.redact_body(req.body().as_ref()));
+        }
+
+        let format = self.config.format;
+        let sink = self.config.sink.clone();
+        let start = Instant::now();
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            let response = response.await;
+            if response.extensions().get::<AdminResponse>().is_some() {
+                return Ok(response);
+            }
+
+            log.status = response.status().as_u16();
+            log.latency_ms = start.elapsed().as_millis();
+            log.bytes = match response.body() {
+                Body::Empty => 0,
+                Body::Text(text) => text.len(),
+                Body::Binary(data) => data.len(),
+            };
+            sink(&log.format(format));
+
+            Ok(response)
+        })
+    }
+}
+
+// Client IP as seen by the trusted proxies, see the `forwarded` module.
+pub(crate) fn source_ip(req: &Request) -> Option<String> {
+    req.client_ip().map(|ip| ip.to_string())
+}
+
+fn logfmt_value(value: &str) -> String {
+    if !value.is_empty() && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+        return value.to_string();
+    }
+    format!("{value:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_runtime::{service_fn, tower::ServiceExt, Context};
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn logs_one_line_per_request() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let l = lines.clone();
+        let layer = RequestLogLayer::new()
+            .log_headers(true)
+            .log_body(true)
+            .redactor(FieldRedactor::default().header("x-secret").field("password"))
+            .sink(move |line| l.lock().unwrap().push(line.to_string()));
+        let service = layer.layer(service_fn(|_: Request| async { Ok::<_, String>("hello") }));
+
+        let mut ctx = Context::default();
+        ctx.request_id = "req-1".into();
+        let req = http::Request::builder()
+            .method("POST")
+            .uri("/users/1")
+            .header("user-agent", "curl/8.0")
+            .header("authorization", "Bearer token")
+            .header("x-secret", "shh")
+            .header("x-forwarded-for", "10.0.0.1, 10.0.0.2")
+            .body(Body::from(r#"{"user":"alice","password":"hunter2"}"#))
+            .unwrap()
+            .with_lambda_context(ctx);
+
+        let res = service.oneshot(req).await.unwrap();
+        assert_eq!(200, res.status().as_u16());
+
+        let lines = lines.lock().unwrap();
+        assert_eq!(1, lines.len());
+        let line = &lines[0];
+        assert!(line.starts_with("method=POST path=/users/1 status=200 latency_ms="));
+        assert!(line.contains(" bytes=5 source_ip=10.0.0.2 request_id=req-1 user_agent=curl/8.0"));
+        assert!(line.contains("header.authorization=[REDACTED]"));
+        assert!(line.contains("header.x-secret=[REDACTED]"));
+        assert!(line.ends_with(r#"body="{\"password\":\"[REDACTED]\",\"user\":\"alice\"}""#));
+    }
+
+    #[test]
+    fn format_json() {
+        let log = RequestLog {
+            method: "GET".into(),
+            path: "/".into(),
+            status: 404,
+            latency_ms: 3,
+            bytes: 0,
+            request_id: "req-1".into(),
+            user_agent: Some("Mozilla/5.0 (X11)".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            r#"{"method":"GET","path":"/","status":404,"latency_ms":3,"bytes":0,"request_id":"req-1","user_agent":"Mozilla/5.0 (X11)"}"#,
+            log.format(LogFormat::Json)
+        );
+        assert_eq!(
+            r#"method=GET path=/ status=404 latency_ms=3 bytes=0 request_id=req-1 user_agent="Mozilla/5.0 (X11)""#,
+            log.format(LogFormat::Logfmt)
+        );
+    }
+}
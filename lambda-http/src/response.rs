@@ -8,13 +8,19 @@ use aws_lambda_events::apigw::ApiGatewayProxyResponse;
 #[cfg(feature = "apigw_http")]
 use aws_lambda_events::apigw::ApiGatewayV2httpResponse;
 use aws_lambda_events::encodings::Body;
+#[cfg(feature = "vpc_lattice")]
+use aws_lambda_events::vpc_lattice::VpcLatticeResponse;
 use encoding_rs::Encoding;
 use http::header::CONTENT_ENCODING;
 use http::HeaderMap;
-use http::{header::CONTENT_TYPE, Response, StatusCode};
+use http::{
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE, LOCATION},
+    HeaderValue, Response, StatusCode,
+};
 use http_body::Body as HttpBody;
 use hyper::body::to_bytes;
 use mime::{Mime, CHARSET};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use serde::Serialize;
 use std::borrow::Cow;
 use std::future::ready;
@@ -35,6 +41,21 @@ const TEXT_ENCODING_PREFIXES: [&str; 5] = [
 
 const TEXT_ENCODING_SUFFIXES: [&str; 3] = ["+xml", "+yaml", "+json"];
 
+// Characters that are not `attr-char`s in RFC 5987, which must be percent-encoded in `filename*`.
+const FILENAME_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
 /// Representation of Lambda response
 #[doc(hidden)]
 #[derive(Serialize, Debug)]
@@ -46,11 +67,19 @@ pub enum LambdaResponse {
     ApiGatewayV2(ApiGatewayV2httpResponse),
     #[cfg(feature = "alb")]
     Alb(AlbTargetGroupResponse),
+    #[cfg(feature = "vpc_lattice")]
+    VpcLattice(VpcLatticeResponse),
 }
 
 /// Transformation from http type to internal type
 impl LambdaResponse {
     pub(crate) fn from_response(request_origin: &RequestOrigin, value: Response<Body>) -> Self {
+        #[cfg(feature = "sniff")]
+        let value = {
+            let mut value = value;
+            crate::sniff::set_content_type(&mut value);
+            value
+        };
         let (parts, bod) = value.into_parts();
         let (is_base64_encoded, body) = match bod {
             Body::Empty => (false, None),
@@ -79,7 +108,6 @@ impl LambdaResponse {
                 let cookies = headers
                     .get_all(SET_COOKIE)
                     .iter()
-                    .cloned()
                     .map(|v| v.to_str().ok().unwrap_or_default().to_string())
                     .collect();
                 headers.remove(SET_COOKIE);
@@ -114,6 +142,28 @@ impl LambdaResponse {
                 headers: headers.clone(),
                 multi_value_headers: headers,
             }),
+            #[cfg(feature = "vpc_lattice")]
+            RequestOrigin::VpcLattice => {
+                // VPC Lattice only accepts one value per header, so repeated headers are joined.
+                let mut joined = HeaderMap::new();
+                for name in headers.keys() {
+                    let values: Vec<&[u8]> = headers.get_all(name).iter().map(HeaderValue::as_bytes).collect();
+                    if let Ok(value) = HeaderValue::from_bytes(&values.join(&b", "[..])) {
+                        joined.insert(name.clone(), value);
+                    }
+                }
+                LambdaResponse::VpcLattice(VpcLatticeResponse {
+                    body,
+                    status_code: status_code as i64,
+                    is_base64_encoded,
+                    headers: joined,
+                    status_description: Some(format!(
+                        "{} {}",
+                        status_code,
+                        parts.status.canonical_reason().unwrap_or_default()
+                    )),
+                })
+            }
         }
     }
 }
@@ -244,6 +294,144 @@ impl IntoResponse for (StatusCode, serde_json::Value) {
     }
 }
 
+/// A redirect to another location.
+///
+/// Locations that aren't valid header values produce a `500 Internal Server Error` response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    status: StatusCode,
+    location: String,
+}
+
+impl Redirect {
+    /// Redirect with `303 See Other`, the client follows the redirect with a `GET` request.
+    pub fn to(location: impl Into<String>) -> Self {
+        Redirect::with_status(StatusCode::SEE_OTHER, location)
+    }
+
+    /// Redirect with `307 Temporary Redirect`, the client keeps the method and the body of the request.
+    pub fn temporary(location: impl Into<String>) -> Self {
+        Redirect::with_status(StatusCode::TEMPORARY_REDIRECT, location)
+    }
+
+    /// Redirect with `308 Permanent Redirect`, the client keeps the method and the body of the request.
+    pub fn permanent(location: impl Into<String>) -> Self {
+        Redirect::with_status(StatusCode::PERMANENT_REDIRECT, location)
+    }
+
+    fn with_status(status: StatusCode, location: impl Into<String>) -> Self {
+        Redirect {
+            status,
+            location: location.into(),
+        }
+    }
+
+    /// Return the status code of the redirect.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Return the location of the redirect.
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+}
+
+impl IntoResponse for Redirect {
+    fn into_response(self) -> ResponseFuture {
+        let response = match HeaderValue::try_from(self.location) {
+            Ok(location) => Response::builder()
+                .status(self.status)
+                .header(LOCATION, location)
+                .body(Body::Empty),
+            Err(_) => Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("invalid redirect location")),
+        };
+        Box::pin(ready(response.expect("unable to build http::Response")))
+    }
+}
+
+/// A `204 No Content` response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NoContent;
+
+impl IntoResponse for NoContent {
+    fn into_response(self) -> ResponseFuture {
+        Box::pin(ready(
+            Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Body::Empty)
+                .expect("unable to build http::Response"),
+        ))
+    }
+}
+
+/// A file that the client downloads, instead of displaying it.
+///
+/// The body is sent as binary, with a `Content-Disposition: attachment` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    body: Vec<u8>,
+    filename: String,
+    content_type: String,
+}
+
+impl Attachment {
+    /// Create an attachment with the `application/octet-stream` content type.
+    pub fn new(body: impl Into<Vec<u8>>, filename: impl Into<String>) -> Self {
+        Attachment {
+            body: body.into(),
+            filename: filename.into(),
+            content_type: "application/octet-stream".to_string(),
+        }
+    }
+
+    /// Set the content type of the file.
+    pub fn content_type(self, content_type: impl Into<String>) -> Self {
+        Attachment {
+            content_type: content_type.into(),
+            ..self
+        }
+    }
+
+    // Quote the name for clients that only read `filename`,
+    // and encode it in `filename*` when it isn't plain ASCII.
+    fn disposition(&self) -> String {
+        let fallback: String = self
+            .filename
+            .chars()
+            .map(|c| match c {
+                '"' | '\\' => '_',
+                c if c.is_ascii() && !c.is_ascii_control() => c,
+                _ => '_',
+            })
+            .collect();
+        if fallback == self.filename {
+            return format!("attachment; filename=\"{fallback}\"");
+        }
+        let encoded = utf8_percent_encode(&self.filename, FILENAME_ENCODE_SET);
+        format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+    }
+}
+
+impl IntoResponse for Attachment {
+    fn into_response(self) -> ResponseFuture {
+        let disposition = self.disposition();
+        let response = Response::builder()
+            .header(CONTENT_DISPOSITION, disposition)
+            .header(CONTENT_TYPE, self.content_type.as_str())
+            .body(Body::Binary(self.body))
+            .unwrap_or_else(|_| {
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::from("invalid attachment content type"))
+                    .expect("unable to build http::Response")
+            });
+        Box::pin(ready(response))
+    }
+}
+
 pub type ResponseFuture = Pin<Box<dyn Future<Output = Response<Body>> + Send>>;
 
 pub trait ConvertBody {
@@ -264,7 +452,11 @@ where
         let content_type = if let Some(value) = headers.get(CONTENT_TYPE) {
             value.to_str().unwrap_or_default()
         } else {
+            // Content-Type and Content-Encoding not set, infer them from the content
+            #[cfg(feature = "sniff")]
+            return convert_sniffed(self);
             // Content-Type and Content-Encoding not set, passthrough as utf8 text
+            #[cfg(not(feature = "sniff"))]
             return convert_to_text(self, "utf-8");
         };
 
@@ -299,6 +491,16 @@ where
     Box::pin(async move { Body::from(to_bytes(body).await.expect("unable to read bytes from body").to_vec()) })
 }
 
+#[cfg(feature = "sniff")]
+fn convert_sniffed<B>(body: B) -> BodyFuture
+where
+    B: HttpBody + Unpin + Send + 'static,
+    B::Data: Send,
+    B::Error: fmt::Debug,
+{
+    Box::pin(async move { crate::sniff::body(to_bytes(body).await.expect("unable to read bytes from body").to_vec()) })
+}
+
 fn convert_to_text<B>(body: B, content_type: &str) -> BodyFuture
 where
     B: HttpBody + Unpin + Send + 'static,
@@ -331,9 +533,12 @@ pub type BodyFuture = Pin<Box<dyn Future<Output = Body> + Send>>;
 
 #[cfg(test)]
 mod tests {
-    use super::{Body, IntoResponse, LambdaResponse, RequestOrigin, X_LAMBDA_HTTP_CONTENT_ENCODING};
+    use super::{
+        Attachment, Body, IntoResponse, LambdaResponse, NoContent, Redirect, RequestOrigin,
+        X_LAMBDA_HTTP_CONTENT_ENCODING,
+    };
     use http::{
-        header::{CONTENT_ENCODING, CONTENT_TYPE},
+        header::{CONTENT_DISPOSITION, CONTENT_ENCODING, CONTENT_TYPE, LOCATION},
         Response, StatusCode,
     };
     use hyper::Body as HyperBody;
@@ -341,6 +546,48 @@ mod tests {
 
     const SVG_LOGO: &str = include_str!("../tests/data/svg_logo.svg");
 
+    #[tokio::test]
+    async fn redirect_into_response() {
+        let response = Redirect::permanent("https://example.com/new").into_response().await;
+        assert_eq!(StatusCode::PERMANENT_REDIRECT, response.status());
+        assert_eq!("https://example.com/new", response.headers()[LOCATION]);
+        assert!(response.body().is_empty());
+
+        assert_eq!(StatusCode::SEE_OTHER, Redirect::to("/orders/42").status());
+        assert_eq!(StatusCode::TEMPORARY_REDIRECT, Redirect::temporary("/login").status());
+
+        let response = Redirect::to("/\n").into_response().await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    }
+
+    #[tokio::test]
+    async fn no_content_into_response() {
+        let response = NoContent.into_response().await;
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert!(response.body().is_empty());
+    }
+
+    #[tokio::test]
+    async fn attachment_into_response() {
+        let response = Attachment::new(vec![0x25, 0x50, 0x44, 0x46], "report.pdf")
+            .content_type("application/pdf")
+            .into_response()
+            .await;
+        assert_eq!("application/pdf", response.headers()[CONTENT_TYPE]);
+        assert_eq!(
+            r#"attachment; filename="report.pdf""#,
+            response.headers()[CONTENT_DISPOSITION]
+        );
+        assert_eq!(&Body::Binary(vec![0x25, 0x50, 0x44, 0x46]), response.body());
+
+        let response = Attachment::new("a,b", "résumé \"v2\".csv").into_response().await;
+        assert_eq!("application/octet-stream", response.headers()[CONTENT_TYPE]);
+        assert_eq!(
+            r#"attachment; filename="r_sum_ _v2_.csv"; filename*=UTF-8''r%C3%A9sum%C3%A9%20%22v2%22.csv"#,
+            response.headers()[CONTENT_DISPOSITION]
+        );
+    }
+
     #[tokio::test]
     async fn json_into_response() {
         let response = json!({ "hello": "lambda"}).into_response().await;
@@ -478,6 +725,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[cfg(not(feature = "sniff"))]
     async fn content_headers_unset() {
         // Drive the implementation by using `hyper::Body` instead of
         // of `aws_lambda_events::encodings::Body`
@@ -494,6 +742,33 @@ mod tests {
         )
     }
 
+    #[tokio::test]
+    #[cfg(feature = "sniff")]
+    async fn content_headers_unset_are_sniffed() {
+        let png = vec![
+            0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D, b'I', b'H', b'D', b'R',
+        ];
+        let response = Response::builder()
+            .body(HyperBody::from(png))
+            .expect("unable to build http::Response");
+        let response = response.into_response().await;
+        let response = LambdaResponse::from_response(&RequestOrigin::ApiGatewayV2, response);
+
+        let json = serde_json::to_value(&response).expect("failed to serialize to json");
+        assert_eq!("image/png", json["headers"]["content-type"]);
+        assert_eq!(true, json["isBase64Encoded"]);
+
+        let response = Response::builder()
+            .body(HyperBody::from("000000".as_bytes()))
+            .expect("unable to build http::Response");
+        let response = response.into_response().await;
+        let response = LambdaResponse::from_response(&RequestOrigin::ApiGatewayV2, response);
+
+        let json = serde_json::to_value(&response).expect("failed to serialize to json");
+        assert_eq!("text/plain; charset=utf-8", json["headers"]["content-type"]);
+        assert_eq!("000000", json["body"]);
+    }
+
     #[test]
     fn serialize_multi_value_headers() {
         let res = LambdaResponse::from_response(
@@ -511,6 +786,25 @@ mod tests {
         )
     }
 
+    #[test]
+    #[cfg(feature = "vpc_lattice")]
+    fn serialize_vpc_lattice_headers() {
+        let res = LambdaResponse::from_response(
+            &RequestOrigin::VpcLattice,
+            Response::builder()
+                .status(404)
+                .header("multi", "a")
+                .header("multi", "b")
+                .body(Body::from(()))
+                .expect("failed to create response"),
+        );
+        let json = serde_json::to_string(&res).expect("failed to serialize to json");
+        assert_eq!(
+            json,
+            r#"{"statusCode":404,"statusDescription":"404 Not Found","headers":{"multi":"a, b"},"isBase64Encoded":false}"#
+        )
+    }
+
     #[test]
     fn serialize_cookies() {
         let res = LambdaResponse::from_response(
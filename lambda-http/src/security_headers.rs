@@ -0,0 +1,353 @@
+//! Security headers for every response.
+//!
+//! [`SecurityHeadersLayer`] adds the headers of a [`SecurityHeaders`] policy to
+//! the responses of a handler, `Strict-Transport-Security`,
+//! `X-Content-Type-Options`, `Content-Security-Policy` and `Referrer-Policy` by
+//! default. Headers already set by the handler are left untouched, so a handler
+//! can still loosen a policy for a single response.
+//!
+//! The layer also removes hop-by-hop headers, like `Connection` or
+//! `Transfer-Encoding`, which only make sense on a single connection, and that
+//! API Gateway rejects or rewrites.
+//!
+//! Paths that need a different policy, like documentation pages that load
+//! scripts from a CDN, can be given their own policy with
+//! [`SecurityHeadersLayer::route`].
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{
+//!     security_headers::{SecurityHeaders, SecurityHeadersLayer},
+//!     http::HeaderValue,
+//!     service_fn, tower::Layer, Error, Request,
+//! };
+//!
+//! async fn page(_req: Request) -> Result<&'static str, Error> {
+//!     Ok("hello")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let docs = SecurityHeaders::new().content_security_policy(HeaderValue::from_static(
+//!         "default-src 'self'; script-src 'self' https://cdn.example.com",
+//!     ));
+//!     let layer = SecurityHeadersLayer::new(SecurityHeaders::new()).route("/docs", docs);
+//!     lambda_http::run(layer.layer(service_fn(page))).await
+//! }
+//! ```
+use crate::{Body, IntoResponse, Request, Response};
+use futures::future::BoxFuture;
+use http::{
+    header::{
+        HeaderName, CONNECTION, CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY, TE, TRAILER,
+        TRANSFER_ENCODING, UPGRADE, X_CONTENT_TYPE_OPTIONS,
+    },
+    HeaderMap, HeaderValue,
+};
+use lambda_runtime::{tower::Layer, Service};
+use std::{
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+
+const KEEP_ALIVE: HeaderName = HeaderName::from_static("keep-alive");
+const PROXY_CONNECTION: HeaderName = HeaderName::from_static("proxy-connection");
+
+/// Headers that only apply to a single connection.
+const HOP_BY_HOP: [HeaderName; 6] = [CONNECTION, KEEP_ALIVE, PROXY_CONNECTION, TE, TRAILER, UPGRADE];
+
+/// A set of headers added to responses.
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    headers: HeaderMap,
+}
+
+impl SecurityHeaders {
+    /// Create a policy with the recommended headers:
+    ///
+    /// * `Strict-Transport-Security: max-age=31536000; includeSubDomains`
+    /// * `X-Content-Type-Options: nosniff`
+    /// * `Content-Security-Policy: default-src 'self'; frame-ancestors 'none'`
+    /// * `Referrer-Policy: strict-origin-when-cross-origin`
+    pub fn new() -> Self {
+        SecurityHeaders::empty()
+            .hsts(Duration::from_secs(31_536_000), true)
+            .header(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"))
+            .content_security_policy(HeaderValue::from_static("default-src 'self'; frame-ancestors 'none'"))
+            .referrer_policy(HeaderValue::from_static("strict-origin-when-cross-origin"))
+    }
+
+    /// Create a policy without any header.
+    pub fn empty() -> Self {
+        SecurityHeaders {
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Set the `Strict-Transport-Security` header.
+    pub fn hsts(self, max_age: Duration, include_subdomains: bool) -> Self {
+        let mut value = format!("max-age={}", max_age.as_secs());
+        if include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        let value = HeaderValue::try_from(value).expect("max-age is always a valid header value");
+        self.header(STRICT_TRANSPORT_SECURITY, value)
+    }
+
+    /// Set the `Content-Security-Policy` header.
+    pub fn content_security_policy(self, value: HeaderValue) -> Self {
+        self.header(CONTENT_SECURITY_POLICY, value)
+    }
+
+    /// Set the `Referrer-Policy` header.
+    pub fn referrer_policy(self, value: HeaderValue) -> Self {
+        self.header(REFERRER_POLICY, value)
+    }
+
+    /// Set any other header.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Remove a header from the policy.
+    pub fn without(mut self, name: HeaderName) -> Self {
+        self.headers.remove(name);
+        self
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        for (name, value) in &self.headers {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Policies {
+    default: SecurityHeaders,
+    routes: Vec<(String, SecurityHeaders)>,
+    strip_hop_by_hop: bool,
+}
+
+impl Policies {
+    /// Return the policy of the longest route that matches `path`.
+    fn for_path(&self, path: &str) -> &SecurityHeaders {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| matches_prefix(path, prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, policy)| policy)
+            .unwrap_or(&self.default)
+    }
+}
+
+fn matches_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// Remove hop-by-hop headers, and the headers listed in `Connection`.
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::try_from(name.trim()).ok())
+        .collect();
+    for name in listed.iter().chain(HOP_BY_HOP.iter()) {
+        headers.remove(name);
+    }
+    // The body is always sent in full, chunked encoding is up to API Gateway.
+    headers.remove(TRANSFER_ENCODING);
+}
+
+/// A [`Layer`] that adds security headers to responses, and removes hop-by-hop headers.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct SecurityHeadersLayer {
+    policies: Policies,
+}
+
+impl SecurityHeadersLayer {
+    /// Create a layer that adds the headers of `policy` to every response.
+    pub fn new(policy: SecurityHeaders) -> Self {
+        SecurityHeadersLayer {
+            policies: Policies {
+                default: policy,
+                routes: Vec::new(),
+                strip_hop_by_hop: true,
+            },
+        }
+    }
+
+    /// Use `policy` for requests to `prefix`, and the paths below it.
+    ///
+    /// When several routes match a path, the longest one wins.
+    pub fn route(mut self, prefix: impl Into<String>, policy: SecurityHeaders) -> Self {
+        self.policies.routes.push((prefix.into(), policy));
+        self
+    }
+
+    /// Remove hop-by-hop headers from responses, enabled by default.
+    pub fn strip_hop_by_hop(mut self, strip: bool) -> Self {
+        self.policies.strip_hop_by_hop = strip;
+        self
+    }
+}
+
+impl Default for SecurityHeadersLayer {
+    fn default() -> Self {
+        Self::new(SecurityHeaders::new())
+    }
+}
+
+impl<S> Layer<S> for SecurityHeadersLayer {
+    type Service = SecurityHeadersService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SecurityHeadersService {
+            inner,
+            policies: Arc::new(self.policies.clone()),
+        }
+    }
+}
+
+/// A [`Service`] that adds security headers to responses.
+///
+/// See [`SecurityHeadersLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct SecurityHeadersService<S> {
+    inner: S,
+    policies: Arc<Policies>,
+}
+
+impl<S> Service<Request> for SecurityHeadersService<S>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let path = req.uri().path().to_string();
+        let policies = self.policies.clone();
+        let fut = self.inner.call(req);
+
+        Box::pin(async move {
+            let response = fut.await?.into_response();
+            let mut response = response.await;
+            let headers = response.headers_mut();
+            if policies.strip_hop_by_hop {
+                strip_hop_by_hop(headers);
+            }
+            policies.for_path(&path).apply(headers);
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use lambda_runtime::service_fn;
+
+    async fn send(layer: SecurityHeadersLayer, path: &str, headers: &[(&str, &str)]) -> Response<Body> {
+        let headers: Vec<(String, String)> = headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let mut service = layer.layer(service_fn(move |_req: Request| {
+            let mut builder = Response::builder();
+            for (name, value) in &headers {
+                builder = builder.header(name, value);
+            }
+            let response = builder.body(Body::from("ok")).unwrap();
+            async move { Ok::<_, Error>(response) }
+        }));
+        let req = http::Request::builder().uri(path).body(Body::Empty).unwrap();
+        service.call(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn adds_the_recommended_headers() {
+        let response = send(SecurityHeadersLayer::default(), "/", &[]).await;
+        let headers = response.headers();
+        assert_eq!(
+            "max-age=31536000; includeSubDomains",
+            headers[STRICT_TRANSPORT_SECURITY]
+        );
+        assert_eq!("nosniff", headers[X_CONTENT_TYPE_OPTIONS]);
+        assert_eq!(
+            "default-src 'self'; frame-ancestors 'none'",
+            headers[CONTENT_SECURITY_POLICY]
+        );
+        assert_eq!("strict-origin-when-cross-origin", headers[REFERRER_POLICY]);
+    }
+
+    #[tokio::test]
+    async fn keeps_headers_set_by_the_handler() {
+        let response = send(
+            SecurityHeadersLayer::default(),
+            "/",
+            &[("referrer-policy", "no-referrer")],
+        )
+        .await;
+        assert_eq!("no-referrer", response.headers()[REFERRER_POLICY]);
+    }
+
+    #[tokio::test]
+    async fn strips_hop_by_hop_headers() {
+        let headers = [
+            ("connection", "keep-alive, x-internal"),
+            ("keep-alive", "timeout=5"),
+            ("transfer-encoding", "chunked"),
+            ("x-internal", "1"),
+            ("x-request-id", "42"),
+        ];
+        let response = send(SecurityHeadersLayer::new(SecurityHeaders::empty()), "/", &headers).await;
+        let names: Vec<_> = response.headers().keys().map(HeaderName::as_str).collect();
+        assert_eq!(vec!["x-request-id"], names);
+
+        let layer = SecurityHeadersLayer::new(SecurityHeaders::empty()).strip_hop_by_hop(false);
+        let response = send(layer, "/", &headers).await;
+        assert_eq!(5, response.headers().len());
+    }
+
+    #[tokio::test]
+    async fn uses_the_policy_of_the_longest_route() {
+        let layer = SecurityHeadersLayer::default()
+            .route("/docs", SecurityHeaders::new().without(CONTENT_SECURITY_POLICY))
+            .route("/docs/api/", SecurityHeaders::empty());
+
+        let response = send(layer.clone(), "/docs/guide", &[]).await;
+        assert!(!response.headers().contains_key(CONTENT_SECURITY_POLICY));
+        assert!(response.headers().contains_key(STRICT_TRANSPORT_SECURITY));
+
+        let response = send(layer.clone(), "/docs/api", &[]).await;
+        assert!(response.headers().is_empty());
+
+        let response = send(layer, "/docsearch", &[]).await;
+        assert!(response.headers().contains_key(CONTENT_SECURITY_POLICY));
+    }
+}
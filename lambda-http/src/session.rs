@@ -0,0 +1,905 @@
+//! Cookie-based sessions.
+//!
+//! [`SessionLayer`] loads the session of every request from its session cookie,
+//! gives it to the handler as a [`Session`] extension, and saves it after the
+//! handler returns when it changed. Sessions are kept in one of two places:
+//! - In the cookie itself, [signed](SessionLayer::signed) so clients can't
+//!   change it, or [encrypted](SessionLayer::encrypted) so they can't read it
+//!   either. Nothing else is needed, but the data must fit in the 4KB of a
+//!   cookie, and sessions can't be revoked before they expire.
+//! - In a [`SessionStore`], with only a random session id in a signed cookie.
+//!   Stores are shared by every execution environment of the function: the
+//!   [`DynamoDbSessionStore`] keeps sessions in a DynamoDB table when the
+//!   `session_dynamodb` feature is enabled, and other services, like
+//!   ElastiCache, only need an implementation of [`SessionStore`].
+//!
+//! Calls to the store are bounded by the time left in the invocation, minus a
+//! [reserve](SessionLayer::reserve) to return the response, so a slow store
+//! fails the request instead of timing out the function.
+//!
+//! Session cookies are `HttpOnly`, `Secure`, `SameSite=Lax`, and expire
+//! [`max_age`](SessionLayer::max_age) after the last change of the session.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{
+//!     service_fn,
+//!     session::{Session, SessionKey, SessionLayer},
+//!     tower::Layer,
+//!     Error, Request,
+//! };
+//!
+//! async fn visits(req: Request) -> Result<String, Error> {
+//!     let session = req.extensions().get::<Session>().expect("session layer");
+//!     let visits = session.get::<u32>("visits").unwrap_or_default() + 1;
+//!     session.insert("visits", visits)?;
+//!     Ok(format!("{visits} visits"))
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let key = SessionKey::new(std::env::var("SESSION_SECRET")?.as_bytes())?;
+//!     lambda_http::run(SessionLayer::encrypted(key).layer(service_fn(visits))).await
+//! }
+//! ```
+use crate::{ext::RequestExt, Body, IntoResponse, Request, Response};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use futures::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use http::{
+    header::{COOKIE, SET_COOKIE},
+    HeaderMap, HeaderValue,
+};
+use lambda_runtime::{tower::Layer, Error, Service, TimeBudget};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context as TaskContext, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "session_dynamodb")]
+pub use dynamodb::DynamoDbSessionStore;
+
+/// The values of a session.
+pub type SessionData = serde_json::Map<String, Value>;
+
+/// Minimum length of the secrets of [`SessionKey`]s.
+pub const MIN_SECRET_LENGTH: usize = 32;
+/// Name of the session cookie, unless set with [`SessionLayer::cookie_name`].
+pub const DEFAULT_COOKIE_NAME: &str = "session";
+/// Size above which browsers drop cookies.
+const MAX_COOKIE_SIZE: usize = 4096;
+const NONCE_LENGTH: usize = 24;
+const TAG_LENGTH: usize = 16;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(key: &[u8]) -> HmacSha256 {
+    <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any size")
+}
+
+/// The key that signs and encrypts session cookies.
+///
+/// Separate keys for signing and encryption are derived from one secret, which
+/// must be at least [`MIN_SECRET_LENGTH`] random bytes, and the same in every
+/// execution environment of the function. Cookies are signed with HMAC-SHA256
+/// and encrypted with XChaCha20-Poly1305.
+#[derive(Clone)]
+pub struct SessionKey {
+    signing: [u8; 32],
+    encryption: [u8; 32],
+}
+
+impl SessionKey {
+    /// Derive the key from `secret`.
+    pub fn new(secret: &[u8]) -> Result<Self, InvalidKey> {
+        if secret.len() < MIN_SECRET_LENGTH {
+            return Err(InvalidKey { length: secret.len() });
+        }
+        let derive = |label: &[u8]| -> [u8; 32] {
+            let mut mac = mac(secret);
+            mac.update(label);
+            mac.finalize().into_bytes().into()
+        };
+        Ok(SessionKey {
+            signing: derive(b"lambda_http session signing"),
+            encryption: derive(b"lambda_http session encryption"),
+        })
+    }
+
+    pub(crate) fn tag(&self, parts: &[&[u8]]) -> HmacSha256 {
+        let mut mac = mac(&self.signing);
+        for part in parts {
+            // The length prefix keeps the boundaries of the parts in the tag.
+            mac.update(&(part.len() as u64).to_be_bytes());
+            mac.update(part);
+        }
+        mac
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.encryption.into())
+    }
+
+    fn sign(&self, name: &str, payload: &[u8]) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(payload);
+        let tag = self.tag(&[name.as_bytes(), payload.as_bytes()]).finalize().into_bytes();
+        format!("{payload}.{}", URL_SAFE_NO_PAD.encode(tag))
+    }
+
+    fn verify(&self, name: &str, value: &str) -> Option<Vec<u8>> {
+        let (payload, tag) = value.split_once('.')?;
+        let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+        self.tag(&[name.as_bytes(), payload.as_bytes()])
+            .verify_slice(&tag)
+            .ok()?;
+        URL_SAFE_NO_PAD.decode(payload).ok()
+    }
+
+    // The cookie name is the associated data, so a value sealed for one
+    // cookie can't be replayed in another.
+    fn encrypt(&self, name: &str, payload: &[u8]) -> String {
+        let nonce = random_bytes::<NONCE_LENGTH>();
+        let payload = Payload {
+            msg: payload,
+            aad: name.as_bytes(),
+        };
+        let ciphertext = self
+            .cipher()
+            .encrypt(XNonce::from_slice(&nonce), payload)
+            .expect("session payloads are far below the XChaCha20-Poly1305 limit");
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        URL_SAFE_NO_PAD.encode(sealed)
+    }
+
+    fn decrypt(&self, name: &str, value: &str) -> Option<Vec<u8>> {
+        let sealed = URL_SAFE_NO_PAD.decode(value).ok()?;
+        if sealed.len() < NONCE_LENGTH + TAG_LENGTH {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: name.as_bytes(),
+        };
+        self.cipher().decrypt(XNonce::from_slice(nonce), payload).ok()
+    }
+}
+
+impl fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SessionKey(..)")
+    }
+}
+
+/// Error returned for secrets shorter than [`MIN_SECRET_LENGTH`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidKey {
+    length: usize,
+}
+
+impl fmt::Display for InvalidKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "session secret of {} bytes, at least {MIN_SECRET_LENGTH} are required",
+            self.length
+        )
+    }
+}
+
+impl std::error::Error for InvalidKey {}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).expect("the system has a source of randomness");
+    bytes
+}
+
+/// A random token of 256 bits, encoded in base64url.
+pub(crate) fn random_token() -> String {
+    URL_SAFE_NO_PAD.encode(random_bytes::<32>())
+}
+
+/// The value of the cookie `name` sent with a request.
+pub(crate) fn request_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie, _)| *cookie == name)
+        .map(|(_, value)| value)
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Storage for the sessions of a [`SessionLayer`] created with [`SessionLayer::store`].
+pub trait SessionStore: Send + Sync {
+    /// Load the session `id`, unless it doesn't exist or expired.
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, Error>>;
+
+    /// Save the session `id`, which expires at `expires`.
+    fn save<'a>(&'a self, id: &'a str, data: &'a SessionData, expires: SystemTime) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Delete the session `id`.
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+/// [`SessionStore`] that keeps the sessions in memory.
+///
+/// Every execution environment has its own sessions, so this store is only
+/// useful for tests and local development.
+#[derive(Debug, Default)]
+pub struct InMemorySessionStore {
+    sessions: Mutex<HashMap<String, (SessionData, SystemTime)>>,
+}
+
+impl InMemorySessionStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn sessions(&self) -> MutexGuard<'_, HashMap<String, (SessionData, SystemTime)>> {
+        self.sessions.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl SessionStore for InMemorySessionStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, Error>> {
+        let mut sessions = self.sessions();
+        let now = SystemTime::now();
+        sessions.retain(|_, (_, expires)| *expires > now);
+        let data = sessions.get(id).map(|(data, _)| data.clone());
+        Box::pin(async move { Ok(data) })
+    }
+
+    fn save<'a>(&'a self, id: &'a str, data: &'a SessionData, expires: SystemTime) -> BoxFuture<'a, Result<(), Error>> {
+        self.sessions().insert(id.to_string(), (data.clone(), expires));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        self.sessions().remove(id);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    data: SessionData,
+    changed: bool,
+    renewed: bool,
+    destroyed: bool,
+}
+
+/// The session of a request, in the extensions of requests that went through
+/// a [`SessionLayer`].
+///
+/// Sessions are cheap to clone, and clones share the same values.
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    state: Arc<Mutex<State>>,
+}
+
+impl Session {
+    fn new(data: SessionData) -> Self {
+        Session {
+            state: Arc::new(Mutex::new(State {
+                data,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Return the value of `key`, unless it's missing or isn't a `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.state().data.get(key)?.clone();
+        serde_json::from_value(value).ok()
+    }
+
+    /// Set the value of `key`.
+    pub fn insert<T: Serialize>(&self, key: &str, value: T) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_value(value)?;
+        let mut state = self.state();
+        state.data.insert(key.to_string(), value);
+        state.changed = true;
+        Ok(())
+    }
+
+    /// Remove the value of `key`, and return it.
+    pub fn remove(&self, key: &str) -> Option<Value> {
+        let mut state = self.state();
+        let value = state.data.remove(key);
+        state.changed |= value.is_some();
+        value
+    }
+
+    /// Whether the session has no values.
+    pub fn is_empty(&self) -> bool {
+        self.state().data.is_empty()
+    }
+
+    /// Give the session a new id, and a new expiration time.
+    ///
+    /// Renew the session when the privileges of the user change, like after a
+    /// login, so an id set by an attacker before can't be used afterwards.
+    pub fn renew(&self) {
+        let mut state = self.state();
+        state.changed = true;
+        state.renewed = true;
+    }
+
+    /// Remove every value of the session, delete it from the store, and remove the cookie.
+    pub fn destroy(&self) {
+        let mut state = self.state();
+        state.data.clear();
+        state.destroyed = true;
+    }
+}
+
+/// The `SameSite` attribute of the session cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// The cookie is only sent with requests from the same site.
+    Strict,
+    /// The cookie is also sent when the user follows a link from another site.
+    Lax,
+    /// The cookie is sent with every request, including cross-site ones.
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        })
+    }
+}
+
+#[derive(Clone)]
+enum Storage {
+    Signed,
+    Encrypted,
+    Store(Arc<dyn SessionStore>),
+}
+
+/// The contents of the session cookie.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    /// Expiration time, in seconds since the Unix epoch.
+    exp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default, skip_serializing_if = "SessionData::is_empty")]
+    data: SessionData,
+}
+
+/// A [`Layer`] that gives a [`Session`] to requests.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone)]
+pub struct SessionLayer {
+    key: Arc<SessionKey>,
+    storage: Storage,
+    cookie_name: String,
+    path: String,
+    domain: Option<String>,
+    secure: bool,
+    same_site: SameSite,
+    max_age: Duration,
+    reserve: Duration,
+}
+
+impl SessionLayer {
+    fn new(key: SessionKey, storage: Storage) -> Self {
+        SessionLayer {
+            key: Arc::new(key),
+            storage,
+            cookie_name: DEFAULT_COOKIE_NAME.to_string(),
+            path: "/".to_string(),
+            domain: None,
+            secure: true,
+            same_site: SameSite::Lax,
+            max_age: Duration::from_secs(24 * 60 * 60),
+            reserve: Duration::from_millis(100),
+        }
+    }
+
+    /// Keep the sessions in cookies signed with `key`, that clients can read but not change.
+    pub fn signed(key: SessionKey) -> Self {
+        Self::new(key, Storage::Signed)
+    }
+
+    /// Keep the sessions in cookies encrypted with `key`, that clients can't read nor change.
+    pub fn encrypted(key: SessionKey) -> Self {
+        Self::new(key, Storage::Encrypted)
+    }
+
+    /// Keep the sessions in `store`, and their ids in cookies signed with `key`.
+    pub fn store<T>(key: SessionKey, store: T) -> Self
+    where
+        T: SessionStore + 'static,
+    {
+        Self::new(key, Storage::Store(Arc::new(store)))
+    }
+
+    /// Set the name of the session cookie, [`DEFAULT_COOKIE_NAME`] by default.
+    pub fn cookie_name(self, cookie_name: impl Into<String>) -> Self {
+        SessionLayer {
+            cookie_name: cookie_name.into(),
+            ..self
+        }
+    }
+
+    /// Set the path of the session cookie, `/` by default.
+    pub fn path(self, path: impl Into<String>) -> Self {
+        SessionLayer {
+            path: path.into(),
+            ..self
+        }
+    }
+
+    /// Set the domain of the session cookie, which is only sent to the host
+    /// of the request by default.
+    pub fn domain(self, domain: impl Into<String>) -> Self {
+        SessionLayer {
+            domain: Some(domain.into()),
+            ..self
+        }
+    }
+
+    /// Set whether the session cookie is only sent over HTTPS, `true` by default.
+    pub fn secure(self, secure: bool) -> Self {
+        SessionLayer { secure, ..self }
+    }
+
+    /// Set the `SameSite` attribute of the session cookie, [`SameSite::Lax`] by default.
+    pub fn same_site(self, same_site: SameSite) -> Self {
+        SessionLayer { same_site, ..self }
+    }
+
+    /// Set how long sessions last after their last change, one day by default.
+    pub fn max_age(self, max_age: Duration) -> Self {
+        SessionLayer { max_age, ..self }
+    }
+
+    /// Set the time of the invocation kept to return the response when the
+    /// store is called, 100ms by default.
+    pub fn reserve(self, reserve: Duration) -> Self {
+        SessionLayer { reserve, ..self }
+    }
+
+    fn cookie(&self, headers: &HeaderMap) -> Option<String> {
+        request_cookie(headers, &self.cookie_name).map(String::from)
+    }
+
+    fn open(&self, value: &str) -> Option<Envelope> {
+        let payload = match self.storage {
+            Storage::Encrypted => self.key.decrypt(&self.cookie_name, value)?,
+            Storage::Signed | Storage::Store(_) => self.key.verify(&self.cookie_name, value)?,
+        };
+        let envelope: Envelope = serde_json::from_slice(&payload).ok()?;
+        (envelope.exp > unix_time(SystemTime::now())).then_some(envelope)
+    }
+
+    fn seal(&self, envelope: &Envelope) -> String {
+        let payload = serde_json::to_vec(envelope).expect("session values are JSON values");
+        match self.storage {
+            Storage::Encrypted => self.key.encrypt(&self.cookie_name, &payload),
+            Storage::Signed | Storage::Store(_) => self.key.sign(&self.cookie_name, &payload),
+        }
+    }
+
+    fn set_cookie(&self, value: &str, max_age: Duration) -> String {
+        let mut cookie = format!(
+            "{}={value}; Path={}; Max-Age={}; HttpOnly; SameSite={}",
+            self.cookie_name,
+            self.path,
+            max_age.as_secs(),
+            self.same_site
+        );
+        if let Some(domain) = &self.domain {
+            cookie.push_str("; Domain=");
+            cookie.push_str(domain);
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+}
+
+impl fmt::Debug for SessionLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let storage = match self.storage {
+            Storage::Signed => "signed",
+            Storage::Encrypted => "encrypted",
+            Storage::Store(_) => "store",
+        };
+        f.debug_struct("SessionLayer")
+            .field("storage", &storage)
+            .field("cookie_name", &self.cookie_name)
+            .field("max_age", &self.max_age)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for SessionLayer {
+    type Service = SessionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SessionService {
+            inner,
+            config: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that gives a [`Session`] to requests.
+///
+/// See [`SessionLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct SessionService<S> {
+    inner: S,
+    config: SessionLayer,
+}
+
+/// Run a call to the store within the time left in the invocation.
+async fn bounded<T>(budget: Option<TimeBudget>, call: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+    match budget {
+        Some(budget) => budget.timeout(call).await?,
+        None => call.await,
+    }
+}
+
+impl<S> Service<Request> for SessionService<S>
+where
+    S: Service<Request> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Response: IntoResponse,
+    S::Error: Into<Error>,
+{
+    type Response = Response<Body>;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        // The inner service was driven to readiness, so it's the one that must handle the request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+        let budget = req
+            .lambda_context_ref()
+            .map(|ctx| ctx.time_budget().reserve(config.reserve));
+        let cookie = config.cookie(req.headers());
+        let envelope = cookie.as_deref().and_then(|value| config.open(value));
+
+        Box::pin(async move {
+            let mut id = None;
+            let data = match (&config.storage, envelope) {
+                (
+                    Storage::Store(store),
+                    Some(Envelope {
+                        id: Some(session_id), ..
+                    }),
+                ) => {
+                    let data = bounded(budget, store.load(&session_id)).await?;
+                    id = data.is_some().then_some(session_id);
+                    data.unwrap_or_default()
+                }
+                (Storage::Store(_), _) => SessionData::new(),
+                (_, Some(envelope)) => envelope.data,
+                (_, None) => SessionData::new(),
+            };
+            let session = Session::new(data);
+            req.extensions_mut().insert(session.clone());
+
+            let response = inner.call(req).await.map_err(Into::into)?.into_response();
+            let mut response = response.await;
+
+            let state = std::mem::take(&mut *session.state());
+            let value = if state.destroyed || (state.changed && state.data.is_empty()) {
+                if let (Storage::Store(store), Some(id)) = (&config.storage, &id) {
+                    bounded(budget, store.delete(id)).await?;
+                }
+                // Only remove the cookies that the client sent.
+                cookie.map(|_| (String::new(), Duration::ZERO))
+            } else if state.changed {
+                let expires = SystemTime::now() + config.max_age;
+                let mut envelope = Envelope {
+                    exp: unix_time(expires),
+                    id: None,
+                    data: SessionData::new(),
+                };
+                match &config.storage {
+                    Storage::Store(store) => {
+                        if let (true, Some(old)) = (state.renewed, &id) {
+                            bounded(budget, store.delete(old)).await?;
+                            id = None;
+                        }
+                        let id = id.unwrap_or_else(random_token);
+                        bounded(budget, store.save(&id, &state.data, expires)).await?;
+                        envelope.id = Some(id);
+                    }
+                    Storage::Signed | Storage::Encrypted => envelope.data = state.data,
+                }
+                Some((config.seal(&envelope), config.max_age))
+            } else {
+                None
+            };
+
+            if let Some((value, max_age)) = value {
+                let cookie = config.set_cookie(&value, max_age);
+                if cookie.len() > MAX_COOKIE_SIZE {
+                    tracing::warn!(
+                        size = cookie.len(),
+                        "the session cookie is larger than 4KB and will be dropped by browsers"
+                    );
+                }
+                let cookie = HeaderValue::try_from(cookie).expect("session cookies are valid header values");
+                response.headers_mut().append(SET_COOKIE, cookie);
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(feature = "session_dynamodb")]
+mod dynamodb {
+    use super::{unix_time, SessionData, SessionStore};
+    use aws_sdk_dynamodb::{types::AttributeValue, Client};
+    use futures::future::BoxFuture;
+    use lambda_runtime::Error;
+    use std::time::SystemTime;
+
+    /// [`SessionStore`] that keeps the sessions in a DynamoDB table.
+    ///
+    /// Every session is an item, with the session id as partition key, its
+    /// values as JSON in a `data` attribute, and its expiration time, in seconds
+    /// since the Unix epoch, in an `expires` attribute. Enable the
+    /// [time to live](https://docs.aws.amazon.com/amazondynamodb/latest/developerguide/TTL.html)
+    /// of the table on `expires` to delete the expired sessions. The name of the
+    /// key is `id` by default.
+    #[derive(Debug, Clone)]
+    pub struct DynamoDbSessionStore {
+        client: Client,
+        table_name: String,
+        key: String,
+    }
+
+    impl DynamoDbSessionStore {
+        /// Create a store that keeps the sessions in the table `table_name`.
+        pub fn new(client: Client, table_name: impl Into<String>) -> Self {
+            DynamoDbSessionStore {
+                client,
+                table_name: table_name.into(),
+                key: "id".into(),
+            }
+        }
+
+        /// Set the name of the partition key of the table.
+        pub fn key(self, key: impl Into<String>) -> Self {
+            DynamoDbSessionStore {
+                key: key.into(),
+                ..self
+            }
+        }
+    }
+
+    impl SessionStore for DynamoDbSessionStore {
+        fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, Error>> {
+            Box::pin(async move {
+                let output = self
+                    .client
+                    .get_item()
+                    .table_name(&self.table_name)
+                    .key(&self.key, AttributeValue::S(id.to_string()))
+                    .consistent_read(true)
+                    .send()
+                    .await?;
+                let item = match output.item {
+                    Some(item) => item,
+                    None => return Ok(None),
+                };
+                // The time to live deletes expired items eventually, not right away.
+                let expires = match item.get("expires") {
+                    Some(AttributeValue::N(expires)) => expires.parse::<u64>().unwrap_or_default(),
+                    _ => 0,
+                };
+                if expires <= unix_time(SystemTime::now()) {
+                    return Ok(None);
+                }
+                match item.get("data") {
+                    Some(AttributeValue::S(data)) => Ok(Some(serde_json::from_str(data)?)),
+                    _ => Ok(None),
+                }
+            })
+        }
+
+        fn save<'a>(
+            &'a self,
+            id: &'a str,
+            data: &'a SessionData,
+            expires: SystemTime,
+        ) -> BoxFuture<'a, Result<(), Error>> {
+            Box::pin(async move {
+                self.client
+                    .put_item()
+                    .table_name(&self.table_name)
+                    .item(&self.key, AttributeValue::S(id.to_string()))
+                    .item("data", AttributeValue::S(serde_json::to_string(data)?))
+                    .item("expires", AttributeValue::N(unix_time(expires).to_string()))
+                    .send()
+                    .await?;
+                Ok(())
+            })
+        }
+
+        fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+            Box::pin(async move {
+                self.client
+                    .delete_item()
+                    .table_name(&self.table_name)
+                    .key(&self.key, AttributeValue::S(id.to_string()))
+                    .send()
+                    .await?;
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_runtime::service_fn;
+
+    fn key() -> SessionKey {
+        SessionKey::new(&[7; MIN_SECRET_LENGTH]).unwrap()
+    }
+
+    fn counter(layer: SessionLayer) -> impl Service<Request, Response = Response<Body>, Error = Error> {
+        layer.layer(service_fn(|req: Request| async move {
+            let session = req.extensions().get::<Session>().unwrap().clone();
+            if req.uri().path() == "/logout" {
+                session.destroy();
+                return Ok::<_, Error>("bye".to_string());
+            }
+            let visits = session.get::<u32>("visits").unwrap_or_default() + 1;
+            session.insert("visits", visits)?;
+            Ok(visits.to_string())
+        }))
+    }
+
+    async fn visit(
+        service: &mut impl Service<Request, Response = Response<Body>, Error = Error>,
+        path: &str,
+        cookie: Option<&str>,
+    ) -> (String, Option<String>) {
+        let mut req = http::Request::builder().uri(path);
+        if let Some(cookie) = cookie {
+            req = req.header("cookie", format!("theme=dark; {cookie}"));
+        }
+        let res = service.call(req.body(Body::Empty).unwrap()).await.unwrap();
+        let set_cookie = res
+            .headers()
+            .get(SET_COOKIE)
+            .map(|value| value.to_str().unwrap().to_string());
+        let body = std::str::from_utf8(res.body()).unwrap().to_string();
+        (body, set_cookie)
+    }
+
+    fn cookie_pair(set_cookie: &str) -> &str {
+        set_cookie.split(';').next().unwrap()
+    }
+
+    #[test]
+    fn rejects_short_secrets() {
+        assert_eq!(Err(InvalidKey { length: 3 }), SessionKey::new(b"abc").map(|_| ()));
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let key = key();
+        let signed = key.sign("session", b"{}");
+        assert_eq!(Some(b"{}".to_vec()), key.verify("session", &signed));
+        assert_eq!(None, key.verify("other", &signed));
+        let forged = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(b"{\"admin\":1}"),
+            signed.split_once('.').unwrap().1
+        );
+        assert_eq!(None, key.verify("session", &forged));
+
+        let encrypted = key.encrypt("session", b"secret values");
+        assert!(!String::from_utf8_lossy(&URL_SAFE_NO_PAD.decode(&encrypted).unwrap()).contains("secret"));
+        assert_eq!(Some(b"secret values".to_vec()), key.decrypt("session", &encrypted));
+        assert_eq!(None, key.decrypt("other", &encrypted));
+        let mut tampered = URL_SAFE_NO_PAD.decode(&encrypted).unwrap();
+        tampered[NONCE_LENGTH] ^= 1;
+        assert_eq!(None, key.decrypt("session", &URL_SAFE_NO_PAD.encode(tampered)));
+    }
+
+    #[tokio::test]
+    async fn keeps_sessions_in_encrypted_cookies() {
+        let mut service = counter(SessionLayer::encrypted(key()));
+        let (body, set_cookie) = visit(&mut service, "/", None).await;
+        assert_eq!("1", body);
+        let set_cookie = set_cookie.unwrap();
+        assert!(set_cookie.starts_with("session="));
+        assert!(set_cookie.ends_with("; Path=/; Max-Age=86400; HttpOnly; SameSite=Lax; Secure"));
+
+        let (body, _) = visit(&mut service, "/", Some(cookie_pair(&set_cookie))).await;
+        assert_eq!("2", body);
+
+        let mut other = counter(SessionLayer::encrypted(SessionKey::new(&[8; 32]).unwrap()));
+        let (body, _) = visit(&mut other, "/", Some(cookie_pair(&set_cookie))).await;
+        assert_eq!("1", body);
+
+        let (_, removed) = visit(&mut service, "/logout", Some(cookie_pair(&set_cookie))).await;
+        assert!(removed.unwrap().starts_with("session=; Path=/; Max-Age=0;"));
+    }
+
+    #[tokio::test]
+    async fn keeps_sessions_in_stores() {
+        let store = Arc::new(InMemorySessionStore::new());
+        struct Shared(Arc<InMemorySessionStore>);
+        impl SessionStore for Shared {
+            fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, Error>> {
+                self.0.load(id)
+            }
+            fn save<'a>(
+                &'a self,
+                id: &'a str,
+                data: &'a SessionData,
+                expires: SystemTime,
+            ) -> BoxFuture<'a, Result<(), Error>> {
+                self.0.save(id, data, expires)
+            }
+            fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+                self.0.delete(id)
+            }
+        }
+
+        let mut service = counter(SessionLayer::store(key(), Shared(store.clone())));
+        let (_, set_cookie) = visit(&mut service, "/", None).await;
+        let cookie = set_cookie.unwrap();
+        let cookie = cookie_pair(&cookie);
+        assert_eq!(1, store.sessions().len());
+
+        let (body, _) = visit(&mut service, "/", Some(cookie)).await;
+        assert_eq!("2", body);
+        assert_eq!(1, store.sessions().len());
+
+        visit(&mut service, "/logout", Some(cookie)).await;
+        assert!(store.sessions().is_empty());
+        let (body, _) = visit(&mut service, "/", Some(cookie)).await;
+        assert_eq!("1", body);
+    }
+}
@@ -0,0 +1,144 @@
+//! Content type inference for responses without a `Content-Type`.
+//!
+//! Without a `Content-Type`, a response body can't be told apart as text or
+//! binary, and is sent as text, which corrupts images and archives that the
+//! handler returns as bytes. With the `sniff` feature, the body is inspected
+//! instead:
+//!
+//! * Bodies that start with the magic bytes of a known format, like PNG, PDF
+//!   or gzip, are sent as binary, with the content type of the format.
+//! * Other bodies that are valid UTF-8 are sent as text, as
+//!   `application/json` when they are JSON, `text/html` when they start like
+//!   an HTML document, and `text/plain` otherwise.
+//! * Anything else is sent as binary, as `application/octet-stream`.
+//!
+//! Handlers that set a `Content-Type` are never second-guessed.
+use crate::{Body, Response};
+use http::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+    HeaderValue,
+};
+use infer::MatcherType;
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Infer the content type of `body` from its content, `None` for empty bodies.
+pub fn content_type(body: &[u8]) -> Option<&'static str> {
+    if body.is_empty() {
+        return None;
+    }
+    if let Some(kind) = infer::get(body) {
+        if kind.matcher_type() != MatcherType::Text || std::str::from_utf8(body).is_err() {
+            return Some(kind.mime_type());
+        }
+    }
+    match std::str::from_utf8(body) {
+        Ok(text) => Some(text_content_type(text)),
+        Err(_) => Some(OCTET_STREAM),
+    }
+}
+
+/// Return whether `body` is text, and can be sent without base64 encoding.
+pub fn is_text(body: &[u8]) -> bool {
+    content_type(body).map_or(false, |content_type| {
+        content_type.starts_with("text/") || content_type == mime::APPLICATION_JSON.essence_str()
+    })
+}
+
+fn text_content_type(text: &str) -> &'static str {
+    let start = text.trim_start();
+    let lowercase = start
+        .get(..start.len().min(16))
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html") {
+        "text/html; charset=utf-8"
+    } else if (start.starts_with('{') || start.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(text).is_ok()
+    {
+        mime::APPLICATION_JSON.essence_str()
+    } else {
+        "text/plain; charset=utf-8"
+    }
+}
+
+/// Body of a response without a `Content-Type`, as text or binary depending on its content.
+pub(crate) fn body(bytes: Vec<u8>) -> Body {
+    if bytes.is_empty() {
+        return Body::Empty;
+    }
+    if !is_text(&bytes) {
+        return Body::Binary(bytes);
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => Body::Text(text),
+        Err(err) => Body::Binary(err.into_bytes()),
+    }
+}
+
+/// Set the `Content-Type` of a response that doesn't have one.
+///
+/// Encoded bodies are left alone, their content type is the one of the decoded content.
+pub(crate) fn set_content_type(response: &mut Response<Body>) {
+    if response.headers().contains_key(CONTENT_TYPE) || response.headers().contains_key(CONTENT_ENCODING) {
+        return;
+    }
+    let content_type = match response.body() {
+        Body::Empty => None,
+        Body::Text(text) => content_type(text.as_bytes()),
+        Body::Binary(bytes) => content_type(bytes),
+    };
+    if let Some(content_type) = content_type {
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = &[
+        0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D, b'I', b'H', b'D', b'R',
+    ];
+
+    #[test]
+    fn infers_content_types() {
+        assert_eq!(Some("image/png"), content_type(PNG));
+        assert_eq!(Some("application/pdf"), content_type(b"%PDF-1.7\n"));
+        assert_eq!(Some("application/json"), content_type(br#" {"ok": true}"#));
+        assert_eq!(
+            Some("text/html; charset=utf-8"),
+            content_type(b"<!DOCTYPE html><p>hi</p>")
+        );
+        assert_eq!(Some("text/plain; charset=utf-8"), content_type("héllo".as_bytes()));
+        assert_eq!(Some("text/plain; charset=utf-8"), content_type(b"{not json"));
+        assert_eq!(
+            Some("application/octet-stream"),
+            content_type(&[0xff, 0xfe, 0x00, 0x81])
+        );
+        assert_eq!(None, content_type(b""));
+    }
+
+    #[test]
+    fn chooses_text_or_binary_bodies() {
+        assert_eq!(Body::Binary(PNG.to_vec()), body(PNG.to_vec()));
+        assert_eq!(Body::Text("hello".to_string()), body(b"hello".to_vec()));
+        assert_eq!(Body::Empty, body(Vec::new()));
+    }
+
+    #[test]
+    fn keeps_the_content_type_of_the_handler() {
+        let mut response = Response::builder()
+            .header(CONTENT_TYPE, "application/x-custom")
+            .body(Body::Binary(PNG.to_vec()))
+            .unwrap();
+        set_content_type(&mut response);
+        assert_eq!("application/x-custom", response.headers()[CONTENT_TYPE]);
+
+        let mut response = Response::new(Body::Binary(PNG.to_vec()));
+        set_content_type(&mut response);
+        assert_eq!("image/png", response.headers()[CONTENT_TYPE]);
+    }
+}
@@ -0,0 +1,260 @@
+//! Server-Sent Events responses for functions that stream their responses.
+//!
+//! [`SseResponse`] builds a response with the `text/event-stream` content type,
+//! and a [`SseSender`] to send events to the client. Each event is sent as its
+//! own chunk, so clients receive it as soon as it's sent. Use this response
+//! with [`run_with_streaming_response`](crate::run_with_streaming_response).
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{sse::{Event, SseResponse}, service_fn, Error, Request};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_http::run_with_streaming_response(service_fn(progress)).await
+//! }
+//!
+//! async fn progress(_req: Request) -> Result<http::Response<hyper::Body>, Error> {
+//!     let (mut events, response) = SseResponse::new().build()?;
+//!
+//!     lambda_http::lambda_runtime::spawn_traced(async move {
+//!         for step in 1..=10 {
+//!             let event = Event::new().event("progress").data(format!("{}%", step * 10));
+//!             if events.send(event).await.is_err() {
+//!                 // the client is gone, stop working
+//!                 return;
+//!             }
+//!         }
+//!     });
+//!
+//!     Ok(response)
+//! }
+//! ```
+use bytes::Bytes;
+use http::{
+    header::{CACHE_CONTROL, CONTENT_TYPE},
+    HeaderMap, HeaderName, HeaderValue, Response, StatusCode,
+};
+use std::{fmt, fmt::Write, time::Duration};
+
+/// A single Server-Sent Event.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl Event {
+    /// Create an empty event.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the id of the event, which clients send back in the `Last-Event-ID` header when they reconnect.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Set the type of the event.
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Set the data of the event. Data with multiple lines is sent as multiple `data` fields.
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Set the data of the event to the JSON representation of `data`.
+    pub fn json_data<T: serde::Serialize>(self, data: &T) -> Result<Self, serde_json::Error> {
+        Ok(self.data(serde_json::to_string(data)?))
+    }
+
+    /// Set the time that clients wait before reconnecting.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Add a comment to the event. Comments are ignored by clients,
+    /// and are useful to keep connections alive.
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Encode the event in the `text/event-stream` format.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        if let Some(comment) = &self.comment {
+            for line in comment.lines() {
+                let _ = writeln!(out, ": {line}");
+            }
+        }
+        if let Some(id) = &self.id {
+            let _ = writeln!(out, "id: {}", single_line(id));
+        }
+        if let Some(event) = &self.event {
+            let _ = writeln!(out, "event: {}", single_line(event));
+        }
+        if let Some(retry) = self.retry {
+            let _ = writeln!(out, "retry: {}", retry.as_millis());
+        }
+        if let Some(data) = &self.data {
+            for line in data.split('\n') {
+                let _ = writeln!(out, "data: {}", line.trim_end_matches('\r'));
+            }
+        }
+        out.push('\n');
+        out
+    }
+}
+
+// Line breaks would end the field early, and corrupt the event.
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+/// Builder for Server-Sent Events responses.
+#[derive(Debug, Default)]
+pub struct SseResponse {
+    status: Option<StatusCode>,
+    headers: HeaderMap,
+}
+
+impl SseResponse {
+    /// Create a new builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the status code of the response, `200 OK` by default.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Add a header to the response.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Build the response, and the sender to send events through it.
+    pub fn build(self) -> Result<(SseSender, Response<hyper::Body>), http::Error> {
+        let (tx, body) = hyper::Body::channel();
+
+        let mut response = Response::builder()
+            .status(self.status.unwrap_or(StatusCode::OK))
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)?;
+        response.headers_mut().extend(self.headers);
+
+        Ok((SseSender { tx, closed: false }, response))
+    }
+}
+
+/// Error returned when an event can't be sent because the client disconnected.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Disconnected;
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the client disconnected from the event stream")
+    }
+}
+
+impl std::error::Error for Disconnected {}
+
+/// Sends events to the client of a [`SseResponse`].
+///
+/// The event stream ends when the sender is dropped.
+#[derive(Debug)]
+pub struct SseSender {
+    tx: hyper::body::Sender,
+    closed: bool,
+}
+
+impl SseSender {
+    /// Send an event to the client.
+    ///
+    /// Returns [`Disconnected`] once the response stream has been closed,
+    /// so the function can stop producing events.
+    pub async fn send(&mut self, event: Event) -> Result<(), Disconnected> {
+        if self.closed {
+            return Err(Disconnected);
+        }
+
+        let chunk = Bytes::from(event.encode());
+        if self.tx.send_data(chunk).await.is_err() {
+            self.closed = true;
+            return Err(Disconnected);
+        }
+        Ok(())
+    }
+
+    /// Send a comment, which clients ignore, to keep the connection alive.
+    pub async fn keep_alive(&mut self) -> Result<(), Disconnected> {
+        self.send(Event::new().comment("keep-alive")).await
+    }
+
+    /// Return `true` if a previous event couldn't be sent because the client disconnected.
+    pub fn is_disconnected(&self) -> bool {
+        self.closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_events() {
+        let event = Event::new()
+            .id("1")
+            .event("update")
+            .retry(Duration::from_secs(3))
+            .data("line one\nline two");
+        assert_eq!(
+            "id: 1\nevent: update\nretry: 3000\ndata: line one\ndata: line two\n\n",
+            event.encode()
+        );
+
+        let event = Event::new().event("bad\nname").comment("ping");
+        assert_eq!(": ping\nevent: badname\n\n", event.encode());
+
+        assert_eq!("data: \n\n", Event::new().data("").encode());
+    }
+
+    #[tokio::test]
+    async fn send_events() {
+        let (mut sender, response) = SseResponse::new().build().unwrap();
+        assert_eq!("text/event-stream", response.headers()[CONTENT_TYPE]);
+        assert_eq!("no-cache", response.headers()[CACHE_CONTROL]);
+
+        let task = tokio::spawn(async move {
+            sender.send(Event::new().data("one")).await.unwrap();
+            sender.send(Event::new().data("two")).await.unwrap();
+        });
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        task.await.unwrap();
+        assert_eq!("data: one\n\ndata: two\n\n", body);
+    }
+
+    #[tokio::test]
+    async fn detect_disconnected_clients() {
+        let (mut sender, response) = SseResponse::new().build().unwrap();
+        drop(response);
+
+        assert_eq!(Err(Disconnected), sender.send(Event::new().data("one")).await);
+        assert!(sender.is_disconnected());
+    }
+}
@@ -1,12 +1,11 @@
 use crate::tower::ServiceBuilder;
 use crate::Request;
 use crate::{request::LambdaRequest, RequestExt};
-pub use aws_lambda_events::encodings::Body as LambdaEventBody;
 use bytes::Bytes;
 pub use http::{self, Response};
 use http_body::Body;
 use lambda_runtime::LambdaEvent;
-pub use lambda_runtime::{self, service_fn, tower, Context, Error, Service};
+use lambda_runtime::{Error, Service};
 use std::fmt::{Debug, Display};
 
 /// Starts the Lambda Rust runtime and stream response back [Configure Lambda
@@ -18,7 +17,7 @@ pub async fn run_with_streaming_response<'a, S, B, E>(handler: S) -> Result<(),
 where
     S: Service<Request, Response = Response<B>, Error = E>,
     S::Future: Send + 'a,
-    E: Debug + Display,
+    E: Debug + Display + 'static,
     B: Body + Unpin + Send + 'static,
     B::Data: Into<Bytes> + Send,
     B::Error: Into<Error> + Send + Debug,
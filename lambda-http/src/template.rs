@@ -0,0 +1,260 @@
+//! HTML responses rendered with templates.
+//!
+//! [`Render`] abstracts over template engines, and [`Html`] turns any renderable
+//! value into a `text/html` response. The engines are integrated behind features:
+//!
+//! - `askama`: every [askama](https://docs.rs/askama) template implements [`Render`].
+//!   Askama compiles templates into the function binary, so there's nothing to load
+//!   at runtime.
+//! - `minijinja`: [`Templates`] compiles [minijinja](https://docs.rs/minijinja)
+//!   templates once and shares them across invocations. Create it in `main`,
+//!   before the runtime starts, so warm invocations reuse the compiled templates
+//!   instead of parsing them again.
+//!
+//! # Example
+//! ```ignore
+//! use lambda_http::{service_fn, template::{Html, Templates}, Error, IntoResponse, Request};
+//! use serde_json::json;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let templates = Templates::from_dir("templates")?;
+//!     lambda_http::run(service_fn(move |req: Request| {
+//!         let templates = templates.clone();
+//!         async move { Ok::<_, Error>(Html(templates.view("index.html", json!({ "path": req.uri().path() })))) }
+//!     }))
+//!     .await
+//! }
+//! ```
+use crate::{response::ResponseFuture, Body, Error, IntoResponse};
+use http::{header::CONTENT_TYPE, Response, StatusCode};
+use std::future::ready;
+
+/// Content type of HTML responses.
+pub const HTML_CONTENT_TYPE: &str = "text/html; charset=utf-8";
+
+/// Values that render to HTML.
+pub trait Render {
+    /// Render the value.
+    fn render(&self) -> Result<String, Error>;
+}
+
+/// A response with a rendered HTML body.
+///
+/// Rendering errors become `500 Internal Server Error` responses. Use
+/// [`Html::try_into_response`] to handle them in the handler instead.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Html<T>(pub T);
+
+impl<T: Render> Html<T> {
+    /// Render the body, and build a response with the `text/html` content type.
+    pub fn try_into_response(self) -> Result<Response<Body>, Error> {
+        let body = self.0.render()?;
+        Ok(Response::builder()
+            .header(CONTENT_TYPE, HTML_CONTENT_TYPE)
+            .body(body.into())?)
+    }
+}
+
+impl<T: Render> IntoResponse for Html<T> {
+    fn into_response(self) -> ResponseFuture {
+        let response = self.try_into_response().unwrap_or_else(|err| {
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(format!("unable to render template: {err}").into())
+                .expect("unable to build http::Response")
+        });
+        Box::pin(ready(response))
+    }
+}
+
+#[cfg(feature = "askama")]
+impl<T: askama::Template> Render for T {
+    fn render(&self) -> Result<String, Error> {
+        Ok(askama::Template::render(self)?)
+    }
+}
+
+#[cfg(feature = "minijinja")]
+pub use self::jinja::{Templates, View};
+
+#[cfg(feature = "minijinja")]
+mod jinja {
+    use super::Render;
+    use crate::Error;
+    use minijinja::{Environment, Value};
+    use serde::Serialize;
+    use std::{fmt, fs, path::Path, sync::Arc};
+
+    /// Compiled minijinja templates, shared across invocations.
+    ///
+    /// Cloning `Templates` is cheap, clones share the same compiled templates.
+    /// Templates with an `.html` extension escape their variables.
+    #[derive(Clone, Default)]
+    pub struct Templates {
+        env: Arc<Environment<'static>>,
+    }
+
+    impl Templates {
+        /// Create a set of templates from a minijinja environment,
+        /// to configure filters and globals.
+        pub fn new(env: Environment<'static>) -> Self {
+            Templates { env: Arc::new(env) }
+        }
+
+        /// Compile all the templates in `dir` and its subdirectories.
+        ///
+        /// Templates are named after their path relative to `dir`,
+        /// with `/` as separator, like `layouts/base.html`.
+        pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, Error> {
+            let mut env = Environment::new();
+            add_dir(&mut env, dir.as_ref(), "")?;
+            Ok(Templates::new(env))
+        }
+
+        /// Compile templates from `(name, source)` pairs.
+        pub fn from_sources<I, N, S>(sources: I) -> Result<Self, Error>
+        where
+            I: IntoIterator<Item = (N, S)>,
+            N: Into<String>,
+            S: Into<String>,
+        {
+            let mut env = Environment::new();
+            for (name, source) in sources {
+                env.add_template_owned(name.into(), source.into())?;
+            }
+            Ok(Templates::new(env))
+        }
+
+        /// Return the minijinja environment with the compiled templates.
+        pub fn environment(&self) -> &Environment<'static> {
+            &self.env
+        }
+
+        /// Return a view that renders the template `name` with `context`.
+        pub fn view(&self, name: impl Into<String>, context: impl Serialize) -> View {
+            View {
+                env: self.env.clone(),
+                name: name.into(),
+                context: Value::from_serialize(context),
+            }
+        }
+    }
+
+    fn add_dir(env: &mut Environment<'static>, dir: &Path, prefix: &str) -> Result<(), Error> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                add_dir(env, &entry.path(), &format!("{name}/"))?;
+            } else {
+                env.add_template_owned(name, fs::read_to_string(entry.path())?)?;
+            }
+        }
+        Ok(())
+    }
+
+    impl fmt::Debug for Templates {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_list()
+                .entries(self.env.templates().map(|(name, _)| name))
+                .finish()
+        }
+    }
+
+    /// A template of [`Templates`] with the context to render it.
+    #[derive(Clone)]
+    pub struct View {
+        env: Arc<Environment<'static>>,
+        name: String,
+        context: Value,
+    }
+
+    impl Render for View {
+        fn render(&self) -> Result<String, Error> {
+            Ok(self.env.get_template(&self.name)?.render(&self.context)?)
+        }
+    }
+
+    impl fmt::Debug for View {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("View")
+                .field("name", &self.name)
+                .field("context", &self.context)
+                .finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Greeting(&'static str);
+
+    impl Render for Greeting {
+        fn render(&self) -> Result<String, Error> {
+            match self.0 {
+                "" => Err("missing name".into()),
+                name => Ok(format!("<p>hello {name}</p>")),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn renders_html_responses() {
+        let response = Html(Greeting("ferris")).into_response().await;
+        assert_eq!(HTML_CONTENT_TYPE, response.headers()[CONTENT_TYPE]);
+        assert_eq!(&Body::Text("<p>hello ferris</p>".into()), response.body());
+
+        let response = Html(Greeting("")).into_response().await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert!(Html(Greeting("")).try_into_response().is_err());
+    }
+
+    #[cfg(feature = "askama")]
+    #[test]
+    fn renders_askama_templates() {
+        #[derive(askama::Template)]
+        #[template(source = "<p>hello {{ name }}</p>", ext = "html")]
+        struct Hello<'a> {
+            name: &'a str,
+        }
+
+        let hello = Hello { name: "<ferris>" };
+        assert_eq!("<p>hello &lt;ferris&gt;</p>", Render::render(&hello).unwrap());
+    }
+
+    #[cfg(feature = "minijinja")]
+    #[test]
+    fn renders_minijinja_templates() {
+        let templates = Templates::from_sources([
+            ("base.html", "<main>{% block body %}{% endblock %}</main>"),
+            (
+                "hello.html",
+                "{% extends 'base.html' %}{% block body %}hello {{ name }}{% endblock %}",
+            ),
+        ])
+        .unwrap();
+        let view = templates.view("hello.html", serde_json::json!({ "name": "<ferris>" }));
+        assert_eq!("<main>hello &lt;ferris&gt;</main>", view.render().unwrap());
+        assert!(templates.view("missing.html", ()).render().is_err());
+    }
+
+    #[cfg(feature = "minijinja")]
+    #[test]
+    fn loads_templates_from_directories() {
+        let dir = std::env::temp_dir().join(format!("lambda-http-templates-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("layouts")).unwrap();
+        std::fs::write(dir.join("layouts/base.html"), "[{% block body %}{% endblock %}]").unwrap();
+        std::fs::write(
+            dir.join("index.html"),
+            "{% extends 'layouts/base.html' %}{% block body %}index{% endblock %}",
+        )
+        .unwrap();
+
+        let templates = Templates::from_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!("[index]", templates.view("index.html", ()).render().unwrap());
+    }
+}
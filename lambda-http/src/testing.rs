@@ -0,0 +1,386 @@
+//! Builders of realistic requests for the tests of handlers.
+//!
+//! [`request`] builds the event that the chosen trigger would send, and converts
+//! it into a [`Request`] like the runtime does, so the request has the same
+//! extensions as in production: query string and path parameters, stage
+//! variables, the request context of the trigger, and the Lambda [`Context`].
+//!
+//! # Example
+//! ```
+//! use lambda_http::{request::RequestOrigin, testing, Context, RequestExt};
+//!
+//! let req = testing::request()
+//!     .method("POST")
+//!     .path("/users/42")
+//!     .query("verbose", "true")
+//!     .header("content-type", "application/json")
+//!     .path_parameter("id", "42")
+//!     .body(r#"{"name":"alice"}"#)
+//!     .context(Context::builder().request_id("req-1").build())
+//!     .origin(RequestOrigin::ApiGatewayV1)
+//!     .build();
+//!
+//! assert_eq!("/users/42", req.uri().path());
+//! assert_eq!(Some("true"), req.query_string_parameters().first("verbose"));
+//! assert_eq!("req-1", req.lambda_context().request_id);
+//! ```
+use crate::{
+    ext::RequestExt,
+    request::{LambdaRequest, RequestOrigin},
+    Body, Request,
+};
+use aws_lambda_events::query_map::QueryMap;
+use base64::Engine;
+use http::{header::HOST, HeaderMap, HeaderName, HeaderValue, Method};
+use lambda_runtime::Context;
+use std::collections::HashMap;
+
+const DOMAIN_NAME: &str = "id.execute-api.us-east-1.amazonaws.com";
+
+/// Create a builder of a `GET /` request sent by an API Gateway HTTP API, or
+/// the first trigger enabled in the features of the crate.
+pub fn request() -> RequestBuilder {
+    RequestBuilder::default()
+}
+
+/// Builder of test requests, created with [`request`].
+#[derive(Debug, Clone)]
+pub struct RequestBuilder {
+    method: Method,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: HeaderMap,
+    path_parameters: HashMap<String, String>,
+    stage_variables: HashMap<String, String>,
+    body: Body,
+    context: Option<Context>,
+    origin: RequestOrigin,
+}
+
+impl Default for RequestBuilder {
+    fn default() -> Self {
+        RequestBuilder {
+            method: Method::GET,
+            path: "/".into(),
+            query: Vec::new(),
+            headers: HeaderMap::new(),
+            path_parameters: HashMap::new(),
+            stage_variables: HashMap::new(),
+            body: Body::Empty,
+            context: None,
+            origin: default_origin(),
+        }
+    }
+}
+
+fn default_origin() -> RequestOrigin {
+    #[cfg(feature = "apigw_http")]
+    return RequestOrigin::ApiGatewayV2;
+    #[cfg(all(not(feature = "apigw_http"), feature = "apigw_rest"))]
+    return RequestOrigin::ApiGatewayV1;
+    #[cfg(all(not(feature = "apigw_http"), not(feature = "apigw_rest"), feature = "alb"))]
+    return RequestOrigin::Alb;
+    #[cfg(all(
+        not(feature = "apigw_http"),
+        not(feature = "apigw_rest"),
+        not(feature = "alb"),
+        feature = "apigw_websockets"
+    ))]
+    return RequestOrigin::WebSocket;
+    #[cfg(all(
+        not(feature = "apigw_http"),
+        not(feature = "apigw_rest"),
+        not(feature = "alb"),
+        not(feature = "apigw_websockets"),
+        feature = "vpc_lattice"
+    ))]
+    return RequestOrigin::VpcLattice;
+}
+
+impl RequestBuilder {
+    /// Set the method of the request.
+    ///
+    /// # Panics
+    ///
+    /// Panics when `method` isn't a valid HTTP method.
+    pub fn method(mut self, method: &str) -> Self {
+        self.method = method.parse().expect("invalid HTTP method");
+        self
+    }
+
+    /// Set the path of the request, without query string.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Add a query string parameter, that can be repeated.
+    pub fn query(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.query.push((name.into(), value.into()));
+        self
+    }
+
+    /// Add a header, that can be repeated.
+    ///
+    /// # Panics
+    ///
+    /// Panics when `name` or `value` aren't valid in a header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid header name");
+        let value = HeaderValue::from_str(value).expect("invalid header value");
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Set a path parameter, extracted by API Gateway from the route of the request.
+    pub fn path_parameter(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.path_parameters.insert(name.into(), value.into());
+        self
+    }
+
+    /// Set a stage variable of API Gateway.
+    pub fn stage_variable(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.stage_variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Set the body of the request.
+    pub fn body(mut self, body: impl Into<Body>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Set the Lambda context of the request, [`Context::builder`] by default.
+    pub fn context(mut self, context: Context) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// Set the trigger that sends the request.
+    pub fn origin(mut self, origin: RequestOrigin) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Build the request, through the event that the trigger would send.
+    pub fn build(self) -> Request {
+        let context = self.context.clone().unwrap_or_else(|| Context::builder().build());
+        let request: Request = self.into_event().into();
+        request.with_lambda_context(context)
+    }
+
+    fn into_event(mut self) -> LambdaRequest {
+        if !self.headers.contains_key(HOST) {
+            self.headers.insert(HOST, HeaderValue::from_static(DOMAIN_NAME));
+        }
+        let (body, is_base64_encoded) = match self.body {
+            Body::Empty => (None, false),
+            Body::Text(text) => (Some(text), false),
+            Body::Binary(bytes) => (Some(base64::engine::general_purpose::STANDARD.encode(bytes)), true),
+        };
+        let query: HashMap<String, Vec<String>> = self.query.iter().fold(HashMap::new(), |mut query, (name, value)| {
+            query.entry(name.clone()).or_default().push(value.clone());
+            query
+        });
+
+        match self.origin {
+            #[cfg(feature = "apigw_http")]
+            RequestOrigin::ApiGatewayV2 => {
+                use aws_lambda_events::apigw::{
+                    ApiGatewayV2httpRequest, ApiGatewayV2httpRequestContext,
+                    ApiGatewayV2httpRequestContextHttpDescription,
+                };
+                let raw_query_string = serde_urlencoded::to_string(&self.query).expect("unable to encode the query");
+                LambdaRequest::ApiGatewayV2(ApiGatewayV2httpRequest {
+                    version: Some("2.0".into()),
+                    route_key: Some("$default".into()),
+                    raw_path: Some(self.path.clone()),
+                    raw_query_string: Some(raw_query_string),
+                    headers: self.headers,
+                    query_string_parameters: QueryMap::from(query),
+                    path_parameters: self.path_parameters,
+                    stage_variables: self.stage_variables,
+                    body,
+                    is_base64_encoded,
+                    request_context: ApiGatewayV2httpRequestContext {
+                        route_key: Some("$default".into()),
+                        account_id: Some("123456789012".into()),
+                        stage: Some("$default".into()),
+                        request_id: Some("id".into()),
+                        apiid: Some("id".into()),
+                        domain_name: Some(DOMAIN_NAME.into()),
+                        domain_prefix: Some("id".into()),
+                        http: ApiGatewayV2httpRequestContextHttpDescription {
+                            method: self.method,
+                            path: Some(self.path),
+                            protocol: Some("HTTP/1.1".into()),
+                            source_ip: Some("192.0.2.1".into()),
+                            user_agent: Some("agent".into()),
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+            }
+            #[cfg(feature = "apigw_rest")]
+            RequestOrigin::ApiGatewayV1 => {
+                use aws_lambda_events::apigw::{ApiGatewayProxyRequest, ApiGatewayProxyRequestContext};
+                let mut request_context = ApiGatewayProxyRequestContext {
+                    account_id: Some("123456789012".into()),
+                    stage: Some("$default".into()),
+                    domain_name: Some(DOMAIN_NAME.into()),
+                    request_id: Some("id".into()),
+                    path: Some(self.path.clone()),
+                    http_method: self.method.clone(),
+                    apiid: Some("id".into()),
+                    ..Default::default()
+                };
+                request_context.identity.source_ip = Some("192.0.2.1".into());
+                LambdaRequest::ApiGatewayV1(ApiGatewayProxyRequest {
+                    path: Some(self.path),
+                    http_method: self.method,
+                    headers: self.headers,
+                    multi_value_query_string_parameters: QueryMap::from(query),
+                    path_parameters: self.path_parameters,
+                    stage_variables: self.stage_variables,
+                    request_context,
+                    body,
+                    is_base64_encoded,
+                    ..Default::default()
+                })
+            }
+            #[cfg(feature = "alb")]
+            RequestOrigin::Alb => {
+                use aws_lambda_events::alb::{AlbTargetGroupRequest, AlbTargetGroupRequestContext, ElbContext};
+                use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+                // The load balancer sends the query string parameters as they were in the URL, encoded.
+                let query: HashMap<String, Vec<String>> = query
+                    .into_iter()
+                    .map(|(name, values)| {
+                        let encode = |s: &str| utf8_percent_encode(s, NON_ALPHANUMERIC).to_string();
+                        (encode(&name), values.iter().map(|v| encode(v)).collect())
+                    })
+                    .collect();
+                LambdaRequest::Alb(AlbTargetGroupRequest {
+                    http_method: self.method,
+                    path: Some(self.path),
+                    multi_value_query_string_parameters: QueryMap::from(query),
+                    headers: self.headers,
+                    request_context: AlbTargetGroupRequestContext {
+                        elb: ElbContext {
+                            target_group_arn: Some(
+                                "arn:aws:elasticloadbalancing:us-east-1:123456789012:targetgroup/test/0123456789abcdef"
+                                    .into(),
+                            ),
+                        },
+                    },
+                    body,
+                    is_base64_encoded,
+                    ..Default::default()
+                })
+            }
+            #[cfg(feature = "apigw_websockets")]
+            RequestOrigin::WebSocket => {
+                use aws_lambda_events::apigw::{
+                    ApiGatewayWebsocketProxyRequest, ApiGatewayWebsocketProxyRequestContext,
+                };
+                LambdaRequest::WebSocket(ApiGatewayWebsocketProxyRequest {
+                    path: Some(self.path),
+                    http_method: Some(self.method),
+                    headers: self.headers,
+                    multi_value_query_string_parameters: QueryMap::from(query),
+                    path_parameters: self.path_parameters,
+                    stage_variables: self.stage_variables,
+                    request_context: ApiGatewayWebsocketProxyRequestContext {
+                        stage: Some("$default".into()),
+                        domain_name: Some(DOMAIN_NAME.into()),
+                        request_id: Some("id".into()),
+                        connection_id: Some("connection-id".into()),
+                        apiid: Some("id".into()),
+                        ..Default::default()
+                    },
+                    body,
+                    is_base64_encoded,
+                    ..Default::default()
+                })
+            }
+            #[cfg(feature = "vpc_lattice")]
+            RequestOrigin::VpcLattice => {
+                use aws_lambda_events::vpc_lattice::{VpcLatticeRequestV2, VpcLatticeRequestV2Context};
+                LambdaRequest::VpcLatticeV2(VpcLatticeRequestV2 {
+                    version: Some("2.0".into()),
+                    method: self.method,
+                    path: Some(self.path),
+                    headers: self.headers,
+                    query_string_parameters: QueryMap::from(query),
+                    body,
+                    is_base64_encoded,
+                    request_context: VpcLatticeRequestV2Context {
+                        service_arn: Some(
+                            "arn:aws:vpc-lattice:us-east-1:123456789012:service/svc-0123456789abcdef0".into(),
+                        ),
+                        region: Some("us-east-1".into()),
+                        ..Default::default()
+                    },
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::RequestContext;
+
+    #[test]
+    fn builds_requests_for_every_origin() {
+        let origins = [
+            #[cfg(feature = "apigw_http")]
+            RequestOrigin::ApiGatewayV2,
+            #[cfg(feature = "apigw_rest")]
+            RequestOrigin::ApiGatewayV1,
+            #[cfg(feature = "alb")]
+            RequestOrigin::Alb,
+            #[cfg(feature = "apigw_websockets")]
+            RequestOrigin::WebSocket,
+            #[cfg(feature = "vpc_lattice")]
+            RequestOrigin::VpcLattice,
+        ];
+        for origin in origins {
+            let req = request()
+                .method("PUT")
+                .path("/items/a b")
+                .query("tag", "x&y")
+                .query("tag", "z")
+                .header("x-custom", "1")
+                .body(vec![0xff, 0x00])
+                .origin(origin.clone())
+                .build();
+
+            assert_eq!(Method::PUT, req.method(), "{origin:?}");
+            assert_eq!("/items/a%20b", req.uri().path(), "{origin:?}");
+            assert_eq!(
+                Some(vec!["x&y", "z"]),
+                req.query_string_parameters().all("tag"),
+                "{origin:?}"
+            );
+            assert_eq!("1", req.headers()["x-custom"], "{origin:?}");
+            assert_eq!(&Body::Binary(vec![0xff, 0x00]), req.body(), "{origin:?}");
+            assert!(req.lambda_context_ref().is_some(), "{origin:?}");
+            let context_origin = match req.request_context() {
+                #[cfg(feature = "apigw_http")]
+                RequestContext::ApiGatewayV2(_) => RequestOrigin::ApiGatewayV2,
+                #[cfg(feature = "apigw_rest")]
+                RequestContext::ApiGatewayV1(_) => RequestOrigin::ApiGatewayV1,
+                #[cfg(feature = "alb")]
+                RequestContext::Alb(_) => RequestOrigin::Alb,
+                #[cfg(feature = "apigw_websockets")]
+                RequestContext::WebSocket(_) => RequestOrigin::WebSocket,
+                #[cfg(feature = "vpc_lattice")]
+                RequestContext::VpcLattice(_) => RequestOrigin::VpcLattice,
+            };
+            assert_eq!(origin, context_origin);
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Connection tracking and broadcasts for API Gateway WebSocket APIs.
+//!
+//! WebSocket APIs send the `$connect` and `$disconnect` events of every
+//! client to the function, which keeps the ids of the open connections in a
+//! [`ConnectionStore`] with [`track_connection`]. Messages are sent to the
+//! clients through the management API of the stage, at the URL returned by
+//! [`management_endpoint`]. [`broadcast`] sends a message to every stored
+//! connection, and removes the connections that are gone: API Gateway only
+//! reports a `$disconnect` on a best-effort basis.
+//!
+//! This crate doesn't ship an HTTPS client, so posting to a connection is
+//! delegated to the function with a [`PostToConnection`] implementation,
+//! usually wrapping the client of `aws-sdk-apigatewaymanagement`.
+//!
+//! Connections are stored in memory with an [`InMemoryConnectionStore`]. It
+//! only sees the connections of its execution environment, and is mostly
+//! useful for tests. Enable the `websocket_dynamodb` feature to store them in
+//! a DynamoDB table with [`DynamoDbConnectionStore`].
+//!
+//! # Example
+//! ```no_run
+//! use lambda_http::{
+//!     websocket::{self, ConnectionStore, InMemoryConnectionStore, PostError, PostToConnection},
+//!     service_fn, Error, Request, RequestPayloadExt,
+//! };
+//! use futures::future::BoxFuture;
+//!
+//! struct Management;
+//!
+//! impl PostToConnection for Management {
+//!     fn post<'a>(&'a self, connection_id: &'a str, data: &'a [u8]) -> BoxFuture<'a, Result<(), PostError>> {
+//!         // Call `post_to_connection` of the management API, and map its
+//!         // `GoneException` to `PostError::Gone`.
+//!         todo!()
+//!     }
+//! }
+//!
+//! async fn handler(store: &dyn ConnectionStore, req: Request) -> Result<&'static str, Error> {
+//!     if websocket::track_connection(store, &req).await? {
+//!         return Ok("");
+//!     }
+//!     let report = websocket::broadcast(store, &Management, req.body()).await?;
+//!     tracing::info!(sent = report.sent, gone = report.gone.len(), "message broadcast");
+//!     Ok("")
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let store = InMemoryConnectionStore::new();
+//!     lambda_http::run(service_fn(|req| handler(&store, req))).await
+//! }
+//! ```
+use crate::{ext::RequestExt, request::RequestContext, Request};
+use futures::{future::BoxFuture, stream, StreamExt};
+use lambda_runtime::Error;
+use std::{collections::BTreeSet, fmt, sync::Mutex};
+
+#[cfg(feature = "websocket_dynamodb")]
+pub use dynamodb::DynamoDbConnectionStore;
+
+/// Number of messages posted at the same time by [`broadcast`].
+pub const BROADCAST_CONCURRENCY: usize = 32;
+
+/// Storage for the ids of the open connections.
+pub trait ConnectionStore: Send + Sync {
+    /// Store the connection `connection_id`.
+    fn add<'a>(&'a self, connection_id: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Remove the connection `connection_id`. Removing a missing connection isn't an error.
+    fn remove<'a>(&'a self, connection_id: &'a str) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Return the ids of all the stored connections.
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, Error>>;
+}
+
+/// [`ConnectionStore`] that keeps the connections in memory.
+#[derive(Debug, Default)]
+pub struct InMemoryConnectionStore {
+    connections: Mutex<BTreeSet<String>>,
+}
+
+impl InMemoryConnectionStore {
+    /// Create a new empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn connections(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.connections.lock().expect("websocket connections poisoned")
+    }
+}
+
+impl ConnectionStore for InMemoryConnectionStore {
+    fn add<'a>(&'a self, connection_id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        self.connections().insert(connection_id.to_string());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove<'a>(&'a self, connection_id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+        self.connections().remove(connection_id);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, Error>> {
+        let connections = self.connections().iter().cloned().collect();
+        Box::pin(async { Ok(connections) })
+    }
+}
+
+/// Error returned when a message can't be posted to a connection.
+#[derive(Debug)]
+pub enum PostError {
+    /// The connection is closed, reported by the management API with a `410 Gone`
+    /// status and a `GoneException`.
+    Gone,
+    /// Any other error.
+    Other(Error),
+}
+
+impl fmt::Display for PostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostError::Gone => f.write_str("the connection is gone"),
+            PostError::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PostError {}
+
+impl From<Error> for PostError {
+    fn from(err: Error) -> Self {
+        PostError::Other(err)
+    }
+}
+
+/// Client of the management API of a WebSocket API stage.
+pub trait PostToConnection: Send + Sync {
+    /// Send `data` to the client of the connection `connection_id`.
+    fn post<'a>(&'a self, connection_id: &'a str, data: &'a [u8]) -> BoxFuture<'a, Result<(), PostError>>;
+}
+
+/// Outcome of a [`broadcast`].
+#[derive(Debug, Default)]
+pub struct BroadcastReport {
+    /// Number of connections the message was sent to.
+    pub sent: usize,
+    /// Connections that were gone, and have been removed from the store.
+    pub gone: Vec<String>,
+    /// Connections the message couldn't be sent to, with the error.
+    pub failed: Vec<(String, Error)>,
+}
+
+/// Send `data` to every connection of `store`, and remove the connections that are gone.
+///
+/// Errors of single connections don't stop the broadcast, they're returned in
+/// the [`BroadcastReport`]. Only the errors of the store are returned as errors.
+pub async fn broadcast(
+    store: &dyn ConnectionStore,
+    client: &dyn PostToConnection,
+    data: &[u8],
+) -> Result<BroadcastReport, Error> {
+    let connections = store.list().await?;
+    post_to_connections(store, client, connections, data).await
+}
+
+/// Send `data` to the connections `connection_ids`, and remove the connections that are gone from `store`.
+pub async fn post_to_connections(
+    store: &dyn ConnectionStore,
+    client: &dyn PostToConnection,
+    connection_ids: impl IntoIterator<Item = String>,
+    data: &[u8],
+) -> Result<BroadcastReport, Error> {
+    let mut results = stream::iter(connection_ids)
+        .map(|connection_id| async move {
+            let result = client.post(&connection_id, data).await;
+            (connection_id, result)
+        })
+        .buffer_unordered(BROADCAST_CONCURRENCY);
+
+    let mut report = BroadcastReport::default();
+    while let Some((connection_id, result)) = results.next().await {
+        match result {
+            Ok(()) => report.sent += 1,
+            Err(PostError::Gone) => report.gone.push(connection_id),
+            Err(PostError::Other(err)) => report.failed.push((connection_id, err)),
+        }
+    }
+    for connection_id in &report.gone {
+        store.remove(connection_id).await?;
+    }
+    Ok(report)
+}
+
+/// Add the connection of `$connect` events to `store`, and remove the connection of `$disconnect` events.
+///
+/// Return whether the request was one of these events, which don't carry messages.
+pub async fn track_connection(store: &dyn ConnectionStore, req: &Request) -> Result<bool, Error> {
+    let ctx = match req.request_context_ref() {
+        Some(RequestContext::WebSocket(ctx)) => ctx,
+        _ => return Ok(false),
+    };
+    let connection_id = match ctx.connection_id.as_deref() {
+        Some(connection_id) => connection_id,
+        None => return Ok(false),
+    };
+    match ctx.event_type.as_deref() {
+        Some("CONNECT") => store.add(connection_id).await?,
+        Some("DISCONNECT") => store.remove(connection_id).await?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Return the id of the connection of a WebSocket request.
+pub fn connection_id(req: &Request) -> Option<&str> {
+    match req.request_context_ref() {
+        Some(RequestContext::WebSocket(ctx)) => ctx.connection_id.as_deref(),
+        _ => None,
+    }
+}
+
+/// Return the URL of the management API of the stage of a WebSocket request,
+/// like `https://abcdef1234.execute-api.us-east-1.amazonaws.com/prod`.
+///
+/// Requests received on a custom domain name return a URL on that domain, which
+/// only works when the stage is mapped to the root of the domain.
+pub fn management_endpoint(req: &Request) -> Option<String> {
+    match req.request_context_ref() {
+        Some(RequestContext::WebSocket(ctx)) => {
+            let domain_name = ctx.domain_name.as_deref()?;
+            let endpoint = match ctx.stage.as_deref() {
+                Some(stage) if domain_name.ends_with(".amazonaws.com") => format!("https://{domain_name}/{stage}"),
+                _ => format!("https://{domain_name}"),
+            };
+            Some(endpoint)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(feature = "websocket_dynamodb")]
+mod dynamodb {
+    use super::ConnectionStore;
+    use aws_sdk_dynamodb::{types::AttributeValue, Client};
+    use futures::future::BoxFuture;
+    use lambda_runtime::Error;
+
+    /// [`ConnectionStore`] that keeps the connections in a DynamoDB table.
+    ///
+    /// Every connection is an item, with the connection id as partition key.
+    /// The name of the key is `connectionId` by default.
+    #[derive(Debug, Clone)]
+    pub struct DynamoDbConnectionStore {
+        client: Client,
+        table_name: String,
+        key: String,
+    }
+
+    impl DynamoDbConnectionStore {
+        /// Create a store that keeps the connections in the table `table_name`.
+        pub fn new(client: Client, table_name: impl Into<String>) -> Self {
+            DynamoDbConnectionStore {
+                client,
+                table_name: table_name.into(),
+                key: "connectionId".into(),
+            }
+        }
+
+        /// Set the name of the partition key of the table.
+        pub fn key(self, key: impl Into<String>) -> Self {
+            DynamoDbConnectionStore {
+                key: key.into(),
+                ..self
+            }
+        }
+    }
+
+    impl ConnectionStore for DynamoDbConnectionStore {
+        fn add<'a>(&'a self, connection_id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+            Box::pin(async move {
+                self.client
+                    .put_item()
+                    .table_name(&self.table_name)
+                    .item(&self.key, AttributeValue::S(connection_id.to_string()))
+                    .send()
+                    .await?;
+                Ok(())
+            })
+        }
+
+        fn remove<'a>(&'a self, connection_id: &'a str) -> BoxFuture<'a, Result<(), Error>> {
+            Box::pin(async move {
+                self.client
+                    .delete_item()
+                    .table_name(&self.table_name)
+                    .key(&self.key, AttributeValue::S(connection_id.to_string()))
+                    .send()
+                    .await?;
+                Ok(())
+            })
+        }
+
+        fn list(&self) -> BoxFuture<'_, Result<Vec<String>, Error>> {
+            Box::pin(async move {
+                let mut connections = Vec::new();
+                let mut start_key = None;
+                loop {
+                    let page = self
+                        .client
+                        .scan()
+                        .table_name(&self.table_name)
+                        .projection_expression("#key")
+                        .expression_attribute_names("#key", &self.key)
+                        .set_exclusive_start_key(start_key)
+                        .send()
+                        .await?;
+                    for mut item in page.items.unwrap_or_default() {
+                        if let Some(AttributeValue::S(connection_id)) = item.remove(&self.key) {
+                            connections.push(connection_id);
+                        }
+                    }
+                    start_key = page.last_evaluated_key;
+                    if start_key.is_none() {
+                        return Ok(connections);
+                    }
+                }
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::LambdaRequest;
+    use std::collections::HashMap;
+
+    struct Management {
+        gone: Vec<&'static str>,
+        sent: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    impl PostToConnection for Management {
+        fn post<'a>(&'a self, connection_id: &'a str, data: &'a [u8]) -> BoxFuture<'a, Result<(), PostError>> {
+            Box::pin(async move {
+                match connection_id {
+                    id if self.gone.contains(&id) => Err(PostError::Gone),
+                    "broken" => Err(PostError::Other("throttled".into())),
+                    id => {
+                        self.sent.lock().unwrap().insert(id.to_string(), data.to_vec());
+                        Ok(())
+                    }
+                }
+            })
+        }
+    }
+
+    fn websocket_request(event_type: &str) -> Request {
+        let data = include_str!("../tests/data/apigw_websocket_request.json");
+        let mut event: serde_json::Value = serde_json::from_str(data).unwrap();
+        event["requestContext"]["eventType"] = event_type.into();
+        let event: LambdaRequest = serde_json::from_value(event).unwrap();
+        event.into()
+    }
+
+    #[tokio::test]
+    async fn broadcasts_and_removes_gone_connections() {
+        let store = InMemoryConnectionStore::new();
+        for id in ["a", "b", "broken", "gone"] {
+            store.add(id).await.unwrap();
+        }
+        let client = Management {
+            gone: vec!["gone"],
+            sent: Mutex::default(),
+        };
+
+        let report = broadcast(&store, &client, b"hello").await.unwrap();
+        assert_eq!(2, report.sent);
+        assert_eq!(vec!["gone"], report.gone);
+        assert_eq!("broken", report.failed[0].0);
+        assert_eq!(Some(&b"hello".to_vec()), client.sent.lock().unwrap().get("a"));
+        assert_eq!(vec!["a", "b", "broken"], store.list().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn tracks_connections() {
+        let store = InMemoryConnectionStore::new();
+        let req = websocket_request("CONNECT");
+        let id = connection_id(&req).unwrap().to_string();
+
+        assert_eq!(
+            Some("https://abcdef1234.execute-api.us-east-1.amazonaws.com/prod"),
+            management_endpoint(&req).as_deref()
+        );
+        assert!(track_connection(&store, &req).await.unwrap());
+        assert_eq!(vec![id.clone()], store.list().await.unwrap());
+        assert!(!track_connection(&store, &websocket_request("MESSAGE")).await.unwrap());
+        assert!(track_connection(&store, &websocket_request("DISCONNECT"))
+            .await
+            .unwrap());
+        assert!(store.list().await.unwrap().is_empty());
+    }
+}
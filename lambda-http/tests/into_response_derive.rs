@@ -0,0 +1,69 @@
+#![cfg(feature = "macros")]
+
+use lambda_http::{http::StatusCode, Body, IntoResponse};
+use serde_json::json;
+use std::fmt;
+
+#[derive(IntoResponse)]
+enum ApiError {
+    #[response(status = 404)]
+    NotFound,
+    #[response(status = 400)]
+    BadRequest(String),
+    #[response(status = 422)]
+    Invalid { details: serde_json::Value },
+    #[response(status = 409, body = "conflict")]
+    Conflict { _id: u64, _version: u64 },
+    #[response(display)]
+    Internal(&'static str),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Internal(message) => write!(f, "internal error: {message}"),
+            _ => f.write_str("api error"),
+        }
+    }
+}
+
+async fn render(error: ApiError) -> (StatusCode, Body) {
+    let response = error.into_response().await;
+    (response.status(), response.into_body())
+}
+
+#[tokio::test]
+async fn unit_variants_have_an_empty_body() {
+    assert_eq!((StatusCode::NOT_FOUND, Body::Empty), render(ApiError::NotFound).await);
+}
+
+#[tokio::test]
+async fn single_field_variants_render_the_field() {
+    assert_eq!(
+        (StatusCode::BAD_REQUEST, Body::Text("missing name".to_string())),
+        render(ApiError::BadRequest("missing name".to_string())).await
+    );
+
+    let error = ApiError::Invalid {
+        details: json!({ "field": "name" }),
+    };
+    let response = error.into_response().await;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+    assert_eq!("application/json", response.headers()["content-type"]);
+    assert_eq!(&Body::Text(r#"{"field":"name"}"#.to_string()), response.body());
+}
+
+#[tokio::test]
+async fn variants_render_static_and_display_bodies() {
+    assert_eq!(
+        (StatusCode::CONFLICT, Body::Text("conflict".to_string())),
+        render(ApiError::Conflict { _id: 1, _version: 2 }).await
+    );
+    assert_eq!(
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Body::Text("internal error: disk full".to_string())
+        ),
+        render(ApiError::Internal("disk full")).await
+    );
+}
@@ -0,0 +1,333 @@
+//! Runtime API client built on top of hyper's HTTP client.
+use crate::{BoxSendFuture, Error, Transport, TransportFuture};
+use http::{request::Parts, uri::PathAndQuery, uri::Scheme, Request, Response, Uri};
+use hyper::{
+    body::HttpBody,
+    client::{connect::Connection, HttpConnector},
+    Body,
+};
+use std::{
+    convert::TryInto,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower_service::Service;
+use tracing::warn;
+
+type SharedExecutor = Arc<dyn hyper::rt::Executor<BoxSendFuture> + Send + Sync>;
+
+/// API client to interact with the AWS Lambda Runtime API.
+#[derive(Debug)]
+pub struct Client<C = HttpConnector> {
+    /// The runtime API URI
+    pub base: Uri,
+    /// The client that manages the API connections
+    pub client: hyper::Client<C>,
+    reconnects: Arc<AtomicU64>,
+}
+
+impl Client {
+    /// Create a builder struct to configure the client.
+    pub fn builder() -> ClientBuilder<HttpConnector> {
+        ClientBuilder {
+            connector: HttpConnector::new(),
+            uri: None,
+            executor: None,
+        }
+    }
+}
+
+impl<C> Client<C>
+where
+    C: hyper::client::connect::Connect + Sync + Send + Clone + 'static,
+{
+    /// Send a given request to the Runtime API.
+    /// Use the client's base URI to ensure the API endpoint is correct.
+    ///
+    /// Lambda freezes the execution environment between invocations, and the
+    /// pooled connection can be closed while it's frozen. When a request with a
+    /// buffered body fails because its connection was closed, it's sent again
+    /// once on a new connection. Streamed bodies can't be sent again.
+    pub async fn call(&self, req: Request<Body>) -> Result<Response<Body>, Error> {
+        let req = self.set_origin(req)?;
+        if req.body().size_hint().exact().is_none() {
+            return Ok(self.client.request(req).await?);
+        }
+
+        let (parts, body) = req.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        match self.client.request(copy_request(&parts, body.clone())).await {
+            Err(err) if is_stale_connection(&err) => {
+                let reconnects = self.reconnects.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!(
+                    reconnects,
+                    error = %err,
+                    "the Runtime API connection was closed, sending the request on a new connection"
+                );
+                Ok(self
+                    .client
+                    .request(Request::from_parts(parts, Body::from(body)))
+                    .await?)
+            }
+            response => Ok(response?),
+        }
+    }
+
+    /// Return the number of requests sent again because their connection was closed.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    /// Create a new client with a given base URI and HTTP connector.
+    pub fn with(base: Uri, connector: C) -> Self {
+        Self::with_executor(base, connector, None)
+    }
+
+    fn with_executor(base: Uri, connector: C, executor: Option<SharedExecutor>) -> Self {
+        let mut builder = hyper::Client::builder();
+        builder.http1_max_buf_size(1024 * 1024);
+        if let Some(executor) = executor {
+            builder.executor(ExecutorRef(executor));
+        }
+        let client = builder.build(connector);
+        Self {
+            base,
+            client,
+            reconnects: Arc::default(),
+        }
+    }
+
+    fn set_origin<B>(&self, req: Request<B>) -> Result<Request<B>, Error> {
+        let (mut parts, body) = req.into_parts();
+        let (scheme, authority, base_path) = {
+            let scheme = self.base.scheme().unwrap_or(&Scheme::HTTP);
+            let authority = self.base.authority().expect("Authority not found");
+            let base_path = self.base.path().trim_end_matches('/');
+            (scheme, authority, base_path)
+        };
+        let path = parts.uri.path_and_query().expect("PathAndQuery not found");
+        let pq: PathAndQuery = format!("{base_path}{path}").parse().expect("PathAndQuery invalid");
+
+        let uri = Uri::builder()
+            .scheme(scheme.as_ref())
+            .authority(authority.as_ref())
+            .path_and_query(pq)
+            .build()
+            .map_err(Box::new)?;
+
+        parts.uri = uri;
+        Ok(Request::from_parts(parts, body))
+    }
+}
+
+fn copy_request(parts: &Parts, body: hyper::body::Bytes) -> Request<Body> {
+    let mut req = Request::new(Body::from(body));
+    *req.method_mut() = parts.method.clone();
+    *req.uri_mut() = parts.uri.clone();
+    *req.version_mut() = parts.version;
+    *req.headers_mut() = parts.headers.clone();
+    req
+}
+
+// Whether a request failed because its pooled connection was already closed.
+fn is_stale_connection(err: &hyper::Error) -> bool {
+    if err.is_incomplete_message() || err.is_closed() || err.is_canceled() {
+        return true;
+    }
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            return matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
+impl<C> Transport for Client<C>
+where
+    C: hyper::client::connect::Connect + Sync + Send + Clone + 'static,
+{
+    fn call(&self, req: Request<Body>) -> TransportFuture<'_> {
+        Box::pin(Client::call(self, req))
+    }
+}
+
+/// Builder implementation to construct any Runtime API clients.
+pub struct ClientBuilder<C: Service<http::Uri> = hyper::client::HttpConnector> {
+    connector: C,
+    uri: Option<http::Uri>,
+    executor: Option<SharedExecutor>,
+}
+
+#[derive(Clone)]
+struct ExecutorRef(SharedExecutor);
+
+impl hyper::rt::Executor<BoxSendFuture> for ExecutorRef {
+    fn execute(&self, future: BoxSendFuture) {
+        self.0.execute(future)
+    }
+}
+
+impl<C> ClientBuilder<C>
+where
+    C: Service<http::Uri> + Clone + Send + Sync + Unpin + 'static,
+    <C as Service<http::Uri>>::Future: Unpin + Send,
+    <C as Service<http::Uri>>::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    <C as Service<http::Uri>>::Response: AsyncRead + AsyncWrite + Connection + Unpin + Send + 'static,
+{
+    /// Create a new builder with a given HTTP connector.
+    pub fn with_connector<C2>(self, connector: C2) -> ClientBuilder<C2>
+    where
+        C2: Service<http::Uri> + Clone + Send + Sync + Unpin + 'static,
+        <C2 as Service<http::Uri>>::Future: Unpin + Send,
+        <C2 as Service<http::Uri>>::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        <C2 as Service<http::Uri>>::Response: AsyncRead + AsyncWrite + Connection + Unpin + Send + 'static,
+    {
+        ClientBuilder {
+            connector,
+            uri: self.uri,
+            executor: self.executor,
+        }
+    }
+
+    /// Create a new builder with a given executor to drive the client's connections.
+    /// By default, connections are spawned on the current tokio runtime.
+    pub fn with_executor<E>(self, executor: E) -> Self
+    where
+        E: hyper::rt::Executor<BoxSendFuture> + Send + Sync + 'static,
+    {
+        Self {
+            executor: Some(Arc::new(executor)),
+            ..self
+        }
+    }
+
+    /// Create a new builder with a given base URI.
+    /// Inherits all other attributes from the existent builder.
+    pub fn with_endpoint(self, uri: http::Uri) -> Self {
+        Self { uri: Some(uri), ..self }
+    }
+
+    /// Create the new client to interact with the Runtime API.
+    pub fn build(self) -> Result<Client<C>, Error> {
+        let uri = match self.uri {
+            Some(uri) => uri,
+            None => {
+                let uri = std::env::var("AWS_LAMBDA_RUNTIME_API").expect("Missing AWS_LAMBDA_RUNTIME_API env var");
+                uri.try_into().expect("Unable to convert to URL")
+            }
+        };
+        Ok(Client::with_executor(uri, self.connector, self.executor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_request;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // Serve one request per connection, closing the first connection without a response.
+    async fn flaky_server() -> Uri {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap()).parse().unwrap();
+        tokio::spawn(async move {
+            let mut connections = 0;
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                connections += 1;
+                let mut buf = vec![0; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                if connections > 1 {
+                    let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                }
+            }
+        });
+        uri
+    }
+
+    #[tokio::test]
+    async fn retries_requests_on_closed_connections() {
+        let client = Client::builder().with_endpoint(flaky_server().await).build().unwrap();
+        let req = build_request()
+            .method("POST")
+            .uri("/2018-06-01/runtime/invocation/id/response")
+            .body(Body::from("{}"))
+            .unwrap();
+
+        let response = client.call(req).await.unwrap();
+        assert_eq!(200, response.status());
+        assert_eq!(1, client.reconnects());
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_streamed_bodies() {
+        let client = Client::builder().with_endpoint(flaky_server().await).build().unwrap();
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            let _ = sender.send_data("{}".into()).await;
+        });
+        let req = build_request()
+            .method("POST")
+            .uri("/2018-06-01/runtime/invocation/id/response")
+            .body(body)
+            .unwrap();
+
+        assert!(client.call(req).await.is_err());
+        assert_eq!(0, client.reconnects());
+    }
+
+    #[test]
+    fn test_set_origin() {
+        let base = "http://localhost:9001";
+        let client = Client::builder().with_endpoint(base.parse().unwrap()).build().unwrap();
+        let req = build_request()
+            .uri("/2018-06-01/runtime/invocation/next")
+            .body(())
+            .unwrap();
+        let req = client.set_origin(req).unwrap();
+        assert_eq!(
+            "http://localhost:9001/2018-06-01/runtime/invocation/next",
+            &req.uri().to_string()
+        );
+    }
+
+    #[test]
+    fn test_set_origin_with_base_path() {
+        let base = "http://localhost:9001/foo";
+        let client = Client::builder().with_endpoint(base.parse().unwrap()).build().unwrap();
+        let req = build_request()
+            .uri("/2018-06-01/runtime/invocation/next")
+            .body(())
+            .unwrap();
+        let req = client.set_origin(req).unwrap();
+        assert_eq!(
+            "http://localhost:9001/foo/2018-06-01/runtime/invocation/next",
+            &req.uri().to_string()
+        );
+
+        let base = "http://localhost:9001/foo/";
+        let client = Client::builder().with_endpoint(base.parse().unwrap()).build().unwrap();
+        let req = build_request()
+            .uri("/2018-06-01/runtime/invocation/next")
+            .body(())
+            .unwrap();
+        let req = client.set_origin(req).unwrap();
+        assert_eq!(
+            "http://localhost:9001/foo/2018-06-01/runtime/invocation/next",
+            &req.uri().to_string()
+        );
+    }
+}
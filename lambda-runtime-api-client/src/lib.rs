@@ -4,14 +4,27 @@
 
 //! This crate includes a base HTTP client to interact with
 //! the AWS Lambda Runtime API.
-use http::{uri::PathAndQuery, uri::Scheme, Request, Response, Uri};
-use hyper::{
-    client::{connect::Connection, HttpConnector},
-    Body,
-};
-use std::{convert::TryInto, fmt::Debug};
-use tokio::io::{AsyncRead, AsyncWrite};
-use tower_service::Service;
+//!
+//! Requests are sent through a [`Transport`]. On most targets, the transport is
+//! a [`Client`] built on top of hyper. When the crate is compiled for WASI
+//! (`wasm32-wasip2`), hyper's client is not available, and requests are sent
+//! with the `wasi:http` interface by a [`WasiTransport`] instead.
+//!
+//! The Runtime API is plain HTTP on a local address, the client never links a
+//! TLS library.
+use http::{Request, Response};
+use hyper::Body;
+use std::{future::Future, pin::Pin};
+
+#[cfg(not(target_os = "wasi"))]
+mod client;
+#[cfg(not(target_os = "wasi"))]
+pub use client::{Client, ClientBuilder};
+
+#[cfg(target_os = "wasi")]
+mod wasi;
+#[cfg(target_os = "wasi")]
+pub use crate::wasi::WasiTransport;
 
 const USER_AGENT_HEADER: &str = "User-Agent";
 const DEFAULT_USER_AGENT: &str = concat!("aws-lambda-rust/", env!("CARGO_PKG_VERSION"));
@@ -20,112 +33,20 @@ const CUSTOM_USER_AGENT: Option<&str> = option_env!("LAMBDA_RUNTIME_USER_AGENT")
 /// Error type that lambdas may result in
 pub type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
 
-/// API client to interact with the AWS Lambda Runtime API.
-#[derive(Debug)]
-pub struct Client<C = HttpConnector> {
-    /// The runtime API URI
-    pub base: Uri,
-    /// The client that manages the API connections
-    pub client: hyper::Client<C>,
-}
+/// Future spawned by the client to drive its connections.
+pub type BoxSendFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
 
-impl Client {
-    /// Create a builder struct to configure the client.
-    pub fn builder() -> ClientBuilder<HttpConnector> {
-        ClientBuilder {
-            connector: HttpConnector::new(),
-            uri: None,
-        }
-    }
-}
+/// Future returned by a [`Transport`].
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send + 'a>>;
 
-impl<C> Client<C>
-where
-    C: hyper::client::connect::Connect + Sync + Send + Clone + 'static,
-{
+/// Sends requests to the Runtime API.
+///
+/// The URI of the requests built with [`build_request`] only contains the path of
+/// the API endpoint. Transports are responsible for sending them to the address of
+/// the Runtime API.
+pub trait Transport: Send + Sync {
     /// Send a given request to the Runtime API.
-    /// Use the client's base URI to ensure the API endpoint is correct.
-    pub async fn call(&self, req: Request<Body>) -> Result<Response<Body>, Error> {
-        let req = self.set_origin(req)?;
-        let response = self.client.request(req).await?;
-        Ok(response)
-    }
-
-    /// Create a new client with a given base URI and HTTP connector.
-    pub fn with(base: Uri, connector: C) -> Self {
-        let client = hyper::Client::builder()
-            .http1_max_buf_size(1024 * 1024)
-            .build(connector);
-        Self { base, client }
-    }
-
-    fn set_origin<B>(&self, req: Request<B>) -> Result<Request<B>, Error> {
-        let (mut parts, body) = req.into_parts();
-        let (scheme, authority, base_path) = {
-            let scheme = self.base.scheme().unwrap_or(&Scheme::HTTP);
-            let authority = self.base.authority().expect("Authority not found");
-            let base_path = self.base.path().trim_end_matches('/');
-            (scheme, authority, base_path)
-        };
-        let path = parts.uri.path_and_query().expect("PathAndQuery not found");
-        let pq: PathAndQuery = format!("{base_path}{path}").parse().expect("PathAndQuery invalid");
-
-        let uri = Uri::builder()
-            .scheme(scheme.as_ref())
-            .authority(authority.as_ref())
-            .path_and_query(pq)
-            .build()
-            .map_err(Box::new)?;
-
-        parts.uri = uri;
-        Ok(Request::from_parts(parts, body))
-    }
-}
-
-/// Builder implementation to construct any Runtime API clients.
-pub struct ClientBuilder<C: Service<http::Uri> = hyper::client::HttpConnector> {
-    connector: C,
-    uri: Option<http::Uri>,
-}
-
-impl<C> ClientBuilder<C>
-where
-    C: Service<http::Uri> + Clone + Send + Sync + Unpin + 'static,
-    <C as Service<http::Uri>>::Future: Unpin + Send,
-    <C as Service<http::Uri>>::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-    <C as Service<http::Uri>>::Response: AsyncRead + AsyncWrite + Connection + Unpin + Send + 'static,
-{
-    /// Create a new builder with a given HTTP connector.
-    pub fn with_connector<C2>(self, connector: C2) -> ClientBuilder<C2>
-    where
-        C2: Service<http::Uri> + Clone + Send + Sync + Unpin + 'static,
-        <C2 as Service<http::Uri>>::Future: Unpin + Send,
-        <C2 as Service<http::Uri>>::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-        <C2 as Service<http::Uri>>::Response: AsyncRead + AsyncWrite + Connection + Unpin + Send + 'static,
-    {
-        ClientBuilder {
-            connector,
-            uri: self.uri,
-        }
-    }
-
-    /// Create a new builder with a given base URI.
-    /// Inherits all other attributes from the existent builder.
-    pub fn with_endpoint(self, uri: http::Uri) -> Self {
-        Self { uri: Some(uri), ..self }
-    }
-
-    /// Create the new client to interact with the Runtime API.
-    pub fn build(self) -> Result<Client<C>, Error> {
-        let uri = match self.uri {
-            Some(uri) => uri,
-            None => {
-                let uri = std::env::var("AWS_LAMBDA_RUNTIME_API").expect("Missing AWS_LAMBDA_RUNTIME_API env var");
-                uri.try_into().expect("Unable to convert to URL")
-            }
-        };
-        Ok(Client::with(uri, self.connector))
-    }
+    fn call(&self, req: Request<Body>) -> TransportFuture<'_>;
 }
 
 /// Create a request builder.
@@ -140,50 +61,3 @@ pub fn build_request() -> http::request::Builder {
     };
     http::Request::builder().header(USER_AGENT_HEADER, USER_AGENT)
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_set_origin() {
-        let base = "http://localhost:9001";
-        let client = Client::builder().with_endpoint(base.parse().unwrap()).build().unwrap();
-        let req = build_request()
-            .uri("/2018-06-01/runtime/invocation/next")
-            .body(())
-            .unwrap();
-        let req = client.set_origin(req).unwrap();
-        assert_eq!(
-            "http://localhost:9001/2018-06-01/runtime/invocation/next",
-            &req.uri().to_string()
-        );
-    }
-
-    #[test]
-    fn test_set_origin_with_base_path() {
-        let base = "http://localhost:9001/foo";
-        let client = Client::builder().with_endpoint(base.parse().unwrap()).build().unwrap();
-        let req = build_request()
-            .uri("/2018-06-01/runtime/invocation/next")
-            .body(())
-            .unwrap();
-        let req = client.set_origin(req).unwrap();
-        assert_eq!(
-            "http://localhost:9001/foo/2018-06-01/runtime/invocation/next",
-            &req.uri().to_string()
-        );
-
-        let base = "http://localhost:9001/foo/";
-        let client = Client::builder().with_endpoint(base.parse().unwrap()).build().unwrap();
-        let req = build_request()
-            .uri("/2018-06-01/runtime/invocation/next")
-            .body(())
-            .unwrap();
-        let req = client.set_origin(req).unwrap();
-        assert_eq!(
-            "http://localhost:9001/foo/2018-06-01/runtime/invocation/next",
-            &req.uri().to_string()
-        );
-    }
-}
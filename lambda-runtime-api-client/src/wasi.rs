@@ -0,0 +1,132 @@
+//! Runtime API transport for WASI components, built on top of `wasi:http`.
+use crate::{Error, Transport, TransportFuture};
+use http::{request::Parts, Method as HttpMethod, Request, Response, Uri};
+use hyper::Body;
+use std::convert::TryInto;
+use wasi::{
+    http::{
+        outgoing_handler,
+        types::{Fields, IncomingResponse, Method, OutgoingBody, OutgoingRequest, Scheme},
+    },
+    io::streams::StreamError,
+};
+
+// Maximum number of bytes that `blocking-write-and-flush` accepts in a single call.
+const WRITE_CHUNK_SIZE: usize = 4096;
+const READ_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Transport that sends requests to the Runtime API with the `wasi:http/outgoing-handler` interface.
+///
+/// Requests block the component until the response is received. Components handle
+/// one event at a time, so this doesn't delay any other work.
+#[derive(Debug, Clone)]
+pub struct WasiTransport {
+    base: Uri,
+}
+
+impl WasiTransport {
+    /// Create a new transport with a given base URI.
+    pub fn new(base: Uri) -> Self {
+        Self { base }
+    }
+
+    /// Create a new transport with the address in the `AWS_LAMBDA_RUNTIME_API` environment variable.
+    pub fn from_env() -> Result<Self, Error> {
+        let uri = std::env::var("AWS_LAMBDA_RUNTIME_API").map_err(|_| "Missing AWS_LAMBDA_RUNTIME_API env var")?;
+        Ok(Self::new(uri.try_into()?))
+    }
+
+    fn send(&self, parts: Parts, body: &[u8]) -> Result<Response<Body>, Error> {
+        let headers = parts
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+            .collect::<Vec<_>>();
+        let request = OutgoingRequest::new(Fields::from_list(&headers)?);
+        request
+            .set_method(&method(&parts.method))
+            .map_err(|_| "Invalid HTTP method")?;
+
+        let scheme = match self.base.scheme_str() {
+            Some("https") => Scheme::Https,
+            _ => Scheme::Http,
+        };
+        request.set_scheme(Some(&scheme)).map_err(|_| "Invalid URI scheme")?;
+        let authority = self.base.authority().ok_or("Authority not found")?;
+        request
+            .set_authority(Some(authority.as_str()))
+            .map_err(|_| "Invalid URI authority")?;
+        let base_path = self.base.path().trim_end_matches('/');
+        let path = parts.uri.path_and_query().ok_or("PathAndQuery not found")?;
+        request
+            .set_path_with_query(Some(&format!("{base_path}{path}")))
+            .map_err(|_| "Invalid URI path")?;
+
+        let outgoing_body = request.body().map_err(|_| "Request body already taken")?;
+        let future_response = outgoing_handler::handle(request, None)?;
+        {
+            let stream = outgoing_body.write().map_err(|_| "Request body stream already taken")?;
+            for chunk in body.chunks(WRITE_CHUNK_SIZE) {
+                stream.blocking_write_and_flush(chunk).map_err(|e| e.to_string())?;
+            }
+        }
+        OutgoingBody::finish(outgoing_body, None)?;
+
+        let response = loop {
+            match future_response.get() {
+                Some(result) => break result.map_err(|_| "Response already taken")??,
+                None => future_response.subscribe().block(),
+            }
+        };
+        read_response(response)
+    }
+}
+
+impl Transport for WasiTransport {
+    fn call(&self, req: Request<Body>) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let body = hyper::body::to_bytes(body).await?;
+            self.send(parts, &body)
+        })
+    }
+}
+
+fn method(method: &HttpMethod) -> Method {
+    match *method {
+        HttpMethod::GET => Method::Get,
+        HttpMethod::HEAD => Method::Head,
+        HttpMethod::POST => Method::Post,
+        HttpMethod::PUT => Method::Put,
+        HttpMethod::DELETE => Method::Delete,
+        HttpMethod::CONNECT => Method::Connect,
+        HttpMethod::OPTIONS => Method::Options,
+        HttpMethod::TRACE => Method::Trace,
+        HttpMethod::PATCH => Method::Patch,
+        ref other => Method::Other(other.to_string()),
+    }
+}
+
+fn read_response(response: IncomingResponse) -> Result<Response<Body>, Error> {
+    let mut builder = Response::builder().status(response.status());
+    for (name, value) in response.headers().entries() {
+        builder = builder.header(name, value);
+    }
+
+    let incoming_body = response.consume().map_err(|_| "Response body already taken")?;
+    let mut body = Vec::new();
+    {
+        let stream = incoming_body
+            .stream()
+            .map_err(|_| "Response body stream already taken")?;
+        loop {
+            match stream.blocking_read(READ_CHUNK_SIZE) {
+                Ok(chunk) => body.extend_from_slice(&chunk),
+                Err(StreamError::Closed) => break,
+                Err(e) => return Err(e.to_string().into()),
+            }
+        }
+    }
+
+    Ok(builder.body(Body::from(body))?)
+}
@@ -0,0 +1,300 @@
+#![deny(clippy::all, clippy::cargo)]
+#![allow(clippy::multiple_crate_versions)]
+#![warn(missing_docs, nonstandard_style, rust_2018_idioms)]
+
+//! Attribute macros that generate the `main` function of a Lambda function, and
+//! derive macros for HTTP responses and settings.
+//!
+//! Don't use this crate directly, enable the `macros` feature of `lambda_runtime`
+//! or `lambda_http`, and use `#[lambda_runtime::main]`, `#[lambda_http::handler]`,
+//! `#[derive(lambda_http::IntoResponse)]`, or `#[derive(lambda_runtime::settings::FromEnv)]`.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{meta::ParseNestedMeta, parse_macro_input, spanned::Spanned, DeriveInput, Error, ItemFn, LitBool};
+
+mod response;
+mod settings;
+
+/// Run an async function as the handler of a Lambda function.
+///
+/// The macro generates a `main` function that starts a Tokio runtime,
+/// initializes a `tracing` subscriber that writes to CloudWatch Logs, and calls
+/// `lambda_runtime::run(service_fn(handler))`.
+///
+/// # Arguments
+/// - `buffered`: send the response when the handler returns, the default.
+/// - `streaming`: stream the response with `lambda_runtime::run_with_streaming_response`.
+/// - `tracing = false`: don't initialize a `tracing` subscriber.
+///
+/// # Example
+/// ```ignore
+/// use lambda_runtime::{Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// #[lambda_runtime::main]
+/// async fn handler(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(args, item, Target::Runtime)
+}
+
+/// Run an async function as the handler of a Lambda function behind an HTTP
+/// event source.
+///
+/// The macro generates a `main` function that starts a Tokio runtime,
+/// initializes a `tracing` subscriber that writes to CloudWatch Logs, and calls
+/// `lambda_http::run(service_fn(handler))`.
+///
+/// # Arguments
+/// - `buffered`: send the response when the handler returns, the default.
+/// - `streaming`: stream the response with `lambda_http::run_with_streaming_response`.
+/// - `tracing = false`: don't initialize a `tracing` subscriber.
+///
+/// # Example
+/// ```ignore
+/// use lambda_http::{Error, IntoResponse, Request};
+///
+/// #[lambda_http::handler]
+/// async fn handler(_request: Request) -> Result<impl IntoResponse, Error> {
+///     Ok("hello")
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn handler(args: TokenStream, item: TokenStream) -> TokenStream {
+    expand(args, item, Target::Http)
+}
+
+/// Implement `lambda_http::IntoResponse` for an enum, with a status code per variant.
+///
+/// Each variant sets its status code with `#[response(status = <code>)]`, the
+/// default is `500`. The body of the response is:
+/// - empty for unit variants,
+/// - the response of the field, for variants with a single field,
+/// - a static body set with `#[response(body = "...")]`,
+/// - the `Display` output of the value, with `#[response(display)]`.
+///
+/// # Example
+/// ```ignore
+/// use lambda_http::IntoResponse;
+///
+/// #[derive(IntoResponse)]
+/// enum ApiError {
+///     #[response(status = 404)]
+///     NotFound,
+///     #[response(status = 400)]
+///     BadRequest(String),
+///     #[response(status = 409, body = "the order was updated by another request")]
+///     Conflict { id: u64, version: u64 },
+/// }
+/// ```
+#[proc_macro_derive(IntoResponse, attributes(response))]
+pub fn derive_into_response(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    response::derive(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Implement `lambda_runtime::settings::FromEnv` for a struct with named fields.
+///
+/// Every field is read from the environment variable with the name of the field
+/// in uppercase, and parsed with `FromStr`. Fields of type `Option<T>` are optional.
+///
+/// # Arguments
+/// - `#[env(prefix = "APP_")]` on the struct: prefix the names of all the variables.
+/// - `#[env(name = "VAR")]` on a field: read the field from `VAR`.
+/// - `#[env(default = "value")]` on a field: use `value` when the variable is not set.
+///
+/// # Example
+/// ```ignore
+/// use lambda_runtime::settings::FromEnv;
+///
+/// #[derive(FromEnv)]
+/// #[env(prefix = "ORDERS_")]
+/// struct Settings {
+///     table_name: String,
+///     #[env(default = "3")]
+///     max_retries: u32,
+///     #[env(name = "API_ENDPOINT")]
+///     endpoint: Option<String>,
+/// }
+/// ```
+#[proc_macro_derive(FromEnv, attributes(env))]
+pub fn derive_from_env(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    settings::derive(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Runtime,
+    Http,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Args {
+    streaming: bool,
+    tracing: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            streaming: false,
+            tracing: true,
+        }
+    }
+}
+
+impl Args {
+    fn parse(&mut self, meta: ParseNestedMeta<'_>) -> syn::Result<()> {
+        if meta.path.is_ident("buffered") {
+            self.streaming = false;
+        } else if meta.path.is_ident("streaming") {
+            self.streaming = true;
+        } else if meta.path.is_ident("tracing") {
+            self.tracing = meta.value()?.parse::<LitBool>()?.value;
+        } else {
+            return Err(meta.error("unsupported argument, expected `buffered`, `streaming`, or `tracing = <bool>`"));
+        }
+        Ok(())
+    }
+}
+
+fn expand(args: TokenStream, item: TokenStream, target: Target) -> TokenStream {
+    let mut parsed = Args::default();
+    let parser = syn::meta::parser(|meta| parsed.parse(meta));
+    parse_macro_input!(args with parser);
+    let function = parse_macro_input!(item as ItemFn);
+    wiring(parsed, &function, target)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn wiring(args: Args, function: &ItemFn, target: Target) -> syn::Result<TokenStream2> {
+    let signature = &function.sig;
+    if signature.asyncness.is_none() {
+        return Err(Error::new(
+            signature.fn_token.span(),
+            "the handler must be an async function",
+        ));
+    }
+    if signature.ident == "main" {
+        return Err(Error::new(
+            signature.ident.span(),
+            "the handler can't be named `main`, the macro generates the `main` function",
+        ));
+    }
+    if signature.inputs.len() != 1 {
+        return Err(Error::new(
+            signature.inputs.span(),
+            "the handler must take the event as its only argument",
+        ));
+    }
+
+    let (krate, runtime) = match target {
+        Target::Runtime => (quote!(::lambda_runtime), quote!(::lambda_runtime)),
+        Target::Http => (quote!(::lambda_http), quote!(::lambda_http::lambda_runtime)),
+    };
+    let run = match args.streaming {
+        true => quote!(#krate::run_with_streaming_response),
+        false => quote!(#krate::run),
+    };
+    let name = &signature.ident;
+    let tracing = args.tracing;
+
+    Ok(quote! {
+        #function
+
+        fn main() -> ::core::result::Result<(), #runtime::Error> {
+            #runtime::__private::start(#tracing, async {
+                #run(#krate::service_fn(#name)).await
+            })
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(args: Args, function: TokenStream2, target: Target) -> syn::Result<String> {
+        let function = syn::parse2::<ItemFn>(function).unwrap();
+        wiring(args, &function, target).map(|tokens| tokens.to_string())
+    }
+
+    #[test]
+    fn generates_the_main_function() {
+        let function = quote!(
+            async fn handler(event: LambdaEvent<Value>) -> Result<Value, Error> {
+                Ok(event.payload)
+            }
+        );
+        let expanded = expand(Args::default(), function, Target::Runtime).unwrap();
+        assert!(expanded.contains("fn main ()"), "{expanded}");
+        assert!(
+            expanded.contains(":: lambda_runtime :: __private :: start (true"),
+            "{expanded}"
+        );
+        assert!(
+            expanded.contains(":: lambda_runtime :: run (:: lambda_runtime :: service_fn (handler))"),
+            "{expanded}"
+        );
+    }
+
+    #[test]
+    fn selects_the_streaming_mode() {
+        let function = quote!(
+            async fn handler(request: Request) -> Result<Response<Body>, Error> {
+                unimplemented!()
+            }
+        );
+        let args = Args {
+            streaming: true,
+            tracing: false,
+        };
+        let expanded = expand(args, function, Target::Http).unwrap();
+        assert!(
+            expanded.contains(":: lambda_http :: lambda_runtime :: __private :: start (false"),
+            "{expanded}"
+        );
+        assert!(
+            expanded.contains(":: lambda_http :: run_with_streaming_response (:: lambda_http :: service_fn (handler))"),
+            "{expanded}"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_handlers() {
+        let cases = [
+            (
+                quote!(
+                    fn handler(event: Value) {}
+                ),
+                "async function",
+            ),
+            (
+                quote!(
+                    async fn main(event: Value) {}
+                ),
+                "can't be named `main`",
+            ),
+            (
+                quote!(
+                    async fn handler(event: Value, context: Context) {}
+                ),
+                "only argument",
+            ),
+        ];
+        for (function, message) in cases {
+            let err = expand(Args::default(), function, Target::Runtime).unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{spanned::Spanned, Data, DeriveInput, Error, Fields, LitInt, LitStr, Variant};
+
+// How the body of a variant is rendered.
+enum Body {
+    // No body, for unit variants.
+    Empty,
+    // The response of the only field of the variant.
+    Field,
+    // A static body set with `body = "..."`.
+    Static(LitStr),
+    // The `Display` output of the value, set with `display`.
+    Display,
+}
+
+struct Response {
+    status: u16,
+    body: Body,
+}
+
+impl Response {
+    fn parse(variant: &Variant) -> syn::Result<Self> {
+        let mut status = 500;
+        let mut body = None;
+        for attr in variant.attrs.iter().filter(|attr| attr.path().is_ident("response")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("status") {
+                    let lit = meta.value()?.parse::<LitInt>()?;
+                    status = lit.base10_parse::<u16>()?;
+                    if !(100..=999).contains(&status) {
+                        return Err(Error::new(lit.span(), "status codes must be between 100 and 999"));
+                    }
+                } else if meta.path.is_ident("body") {
+                    body = Some(Body::Static(meta.value()?.parse()?));
+                } else if meta.path.is_ident("display") {
+                    body = Some(Body::Display);
+                } else {
+                    return Err(
+                        meta.error("unsupported argument, expected `status = <code>`, `body = \"...\"`, or `display`")
+                    );
+                }
+                Ok(())
+            })?;
+        }
+
+        let body = match (body, &variant.fields) {
+            (Some(body), _) => body,
+            (None, Fields::Unit) => Body::Empty,
+            (None, fields) if fields.len() == 1 => Body::Field,
+            (None, fields) => {
+                return Err(Error::new(
+                    fields.span(),
+                    "variants with several fields need `#[response(body = \"...\")]` or `#[response(display)]`",
+                ))
+            }
+        };
+        Ok(Response { status, body })
+    }
+}
+
+pub(crate) fn derive(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let variants = match &input.data {
+        Data::Enum(data) => &data.variants,
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "`IntoResponse` can only be derived for enums",
+            ))
+        }
+    };
+    if variants.is_empty() {
+        return Err(Error::new(
+            input.ident.span(),
+            "`IntoResponse` needs at least one variant",
+        ));
+    }
+
+    let mut arms = Vec::with_capacity(variants.len());
+    for variant in variants {
+        let Response { status, body } = Response::parse(variant)?;
+        let ident = &variant.ident;
+        let arm = match body {
+            Body::Empty => quote! {
+                Self::#ident { .. } => (#status, ::std::boxed::Box::pin(::std::future::ready(
+                    ::lambda_http::Response::new(::lambda_http::Body::Empty)
+                )))
+            },
+            Body::Field => match &variant.fields {
+                Fields::Named(fields) => {
+                    let field = &fields.named[0].ident;
+                    quote!(Self::#ident { #field: field } => (#status, ::lambda_http::IntoResponse::into_response(field)))
+                }
+                _ => quote!(Self::#ident(field) => (#status, ::lambda_http::IntoResponse::into_response(field))),
+            },
+            Body::Static(body) => quote! {
+                Self::#ident { .. } => (#status, ::lambda_http::IntoResponse::into_response(#body))
+            },
+            Body::Display => quote! {
+                value @ Self::#ident { .. } => (#status, ::lambda_http::IntoResponse::into_response(
+                    ::std::string::ToString::to_string(&value)
+                ))
+            },
+        };
+        arms.push(arm);
+    }
+
+    let future = quote! {
+        ::std::pin::Pin<
+            ::std::boxed::Box<
+                dyn ::std::future::Future<Output = ::lambda_http::Response<::lambda_http::Body>> + ::std::marker::Send,
+            >,
+        >
+    };
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::lambda_http::IntoResponse for #name #ty_generics #where_clause {
+            fn into_response(self) -> #future {
+                let (status, response): (u16, #future) = match self {
+                    #(#arms,)*
+                };
+                ::std::boxed::Box::pin(async move {
+                    let mut response = response.await;
+                    *response.status_mut() = ::lambda_http::http::StatusCode::from_u16(status)
+                        .expect("status codes are checked at compile time");
+                    response
+                })
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(input: TokenStream) -> syn::Result<String> {
+        derive(&syn::parse2(input).unwrap()).map(|tokens| tokens.to_string())
+    }
+
+    #[test]
+    fn maps_variants_to_status_codes() {
+        let expanded = expand(quote! {
+            enum ApiError {
+                #[response(status = 404)]
+                NotFound,
+                #[response(status = 400)]
+                BadRequest(String),
+                #[response(status = 409, body = "conflict")]
+                Conflict { id: u64, version: u64 },
+                #[response(display)]
+                Internal(std::io::Error),
+            }
+        })
+        .unwrap();
+        for status in ["(404u16 ,", "(400u16 ,", "(409u16 ,", "(500u16 ,"] {
+            assert!(expanded.contains(status), "{status}: {expanded}");
+        }
+        assert!(expanded.contains("Self :: BadRequest (field)"), "{expanded}");
+        assert!(expanded.contains("into_response (\"conflict\")"), "{expanded}");
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let cases = [
+            (
+                quote!(
+                    struct NotFound;
+                ),
+                "only be derived for enums",
+            ),
+            (
+                quote!(
+                    enum E {
+                        #[response(status = 42)]
+                        A,
+                    }
+                ),
+                "between 100 and 999",
+            ),
+            (
+                quote!(
+                    enum E {
+                        #[response(code = 404)]
+                        A,
+                    }
+                ),
+                "unsupported argument",
+            ),
+            (
+                quote!(
+                    enum E {
+                        A(u64, u64),
+                    }
+                ),
+                "several fields",
+            ),
+        ];
+        for (input, message) in cases {
+            let err = expand(input).unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+    }
+}
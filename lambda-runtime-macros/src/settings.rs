@@ -0,0 +1,211 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{
+    spanned::Spanned, Attribute, Data, DeriveInput, Error, Field, Fields, GenericArgument, LitStr, PathArguments, Type,
+};
+
+// How the value of a field is read.
+enum Read {
+    // The variable must be set.
+    Required,
+    // The field is an `Option`, unset when the variable is not set.
+    Optional,
+    // The default value of the field, parsed when the variable is not set.
+    Default(LitStr),
+}
+
+fn parse_attrs(
+    attrs: &[Attribute],
+    mut parse: impl FnMut(syn::meta::ParseNestedMeta<'_>) -> syn::Result<()>,
+) -> syn::Result<()> {
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("env")) {
+        attr.parse_nested_meta(&mut parse)?;
+    }
+    Ok(())
+}
+
+fn prefix(input: &DeriveInput) -> syn::Result<String> {
+    let mut prefix = String::new();
+    parse_attrs(&input.attrs, |meta| {
+        if meta.path.is_ident("prefix") {
+            prefix = meta.value()?.parse::<LitStr>()?.value();
+            Ok(())
+        } else {
+            Err(meta.error("unsupported argument, expected `prefix = \"...\"`"))
+        }
+    })?;
+    Ok(prefix)
+}
+
+// The `T` of a field of type `Option<T>`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(path) => path,
+        _ => return None,
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn field(field: &Field, prefix: &str) -> syn::Result<(String, Read)> {
+    let ident = field.ident.as_ref().expect("fields are named");
+    let mut name = None;
+    let mut default = None;
+    parse_attrs(&field.attrs, |meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<LitStr>()?.value());
+        } else if meta.path.is_ident("default") {
+            default = Some(meta.value()?.parse::<LitStr>()?);
+        } else {
+            return Err(meta.error("unsupported argument, expected `name = \"...\"` or `default = \"...\"`"));
+        }
+        Ok(())
+    })?;
+
+    let name = name.unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_uppercase());
+    let read = match (default, option_inner(&field.ty)) {
+        (Some(default), Some(_)) => {
+            return Err(Error::new(default.span(), "optional fields can't have a default value"))
+        }
+        (Some(default), None) => Read::Default(default),
+        (None, Some(_)) => Read::Optional,
+        (None, None) => Read::Required,
+    };
+    Ok((format!("{prefix}{name}"), read))
+}
+
+pub(crate) fn derive(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    data.fields.span(),
+                    "`FromEnv` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "`FromEnv` can only be derived for structs",
+            ))
+        }
+    };
+    let prefix = prefix(input)?;
+
+    let mut reads = Vec::with_capacity(fields.len());
+    let mut inits = Vec::with_capacity(fields.len());
+    for (i, field) in fields.iter().enumerate() {
+        let (name, read) = self::field(field, &prefix)?;
+        let ident = field.ident.as_ref().expect("fields are named");
+        let var = format_ident!("field{}", i);
+        let ty = &field.ty;
+        let read = match read {
+            Read::Required => quote!(reader.required::<#ty>(#name)),
+            Read::Optional => {
+                let inner = option_inner(ty).expect("optional fields are options");
+                quote!(reader.optional::<#inner>(#name))
+            }
+            Read::Default(default) => quote!(reader.or_default::<#ty>(#name, #default)),
+        };
+        reads.push(quote!(let #var = #read;));
+        inits.push(quote!(#ident: #var?));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::lambda_runtime::settings::FromEnv for #name #ty_generics #where_clause {
+            fn read(
+                reader: &mut ::lambda_runtime::settings::EnvReader<'_>,
+            ) -> ::core::option::Option<Self> {
+                #(#reads)*
+                ::core::option::Option::Some(Self { #(#inits,)* })
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(input: TokenStream) -> syn::Result<String> {
+        derive(&syn::parse2(input).unwrap()).map(|tokens| tokens.to_string())
+    }
+
+    #[test]
+    fn reads_every_field() {
+        let expanded = expand(quote! {
+            #[env(prefix = "ORDERS_")]
+            struct Settings {
+                table_name: String,
+                #[env(default = "3")]
+                max_retries: u32,
+                #[env(name = "API_ENDPOINT")]
+                endpoint: Option<String>,
+            }
+        })
+        .unwrap();
+        for read in [
+            "reader . required :: < String > (\"ORDERS_TABLE_NAME\")",
+            "reader . or_default :: < u32 > (\"ORDERS_MAX_RETRIES\" , \"3\")",
+            "reader . optional :: < String > (\"ORDERS_API_ENDPOINT\")",
+        ] {
+            assert!(expanded.contains(read), "{read}: {expanded}");
+        }
+        assert!(expanded.contains("table_name : field0 ?"), "{expanded}");
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let cases = [
+            (
+                quote!(
+                    struct Settings(String);
+                ),
+                "named fields",
+            ),
+            (
+                quote!(
+                    enum Settings {
+                        A,
+                    }
+                ),
+                "only be derived for structs",
+            ),
+            (
+                quote!(
+                    struct Settings {
+                        #[env(default = "x")]
+                        endpoint: Option<String>,
+                    }
+                ),
+                "can't have a default",
+            ),
+            (
+                quote!(
+                    struct Settings {
+                        #[env(rename = "X")]
+                        endpoint: String,
+                    }
+                ),
+                "unsupported argument",
+            ),
+        ];
+        for (input, message) in cases {
+            let err = expand(input).unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Measure the overhead that the runtime adds to every invocation.
+//!
+//! The runtime runs against an in-memory Runtime API, so the numbers exclude
+//! the network, and only include the work done by the crate:
+//!
+//! ```text
+//! cargo bench -p lambda_runtime --features bench
+//! ```
+use hyper::{Body, Method, Request, Response, StatusCode};
+use lambda_runtime::{bench::InvocationTimings, service_fn, Error, LambdaEvent, RuntimeBuilder};
+use lambda_runtime_api_client::{Transport, TransportFuture};
+use serde_json::{json, Value};
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const INVOCATIONS: usize = 10_000;
+
+type Phase = fn(&InvocationTimings) -> Duration;
+
+/// Runtime API that serves the same event a fixed number of times.
+struct Events {
+    payload: Vec<u8>,
+    remaining: AtomicUsize,
+}
+
+impl Transport for Events {
+    fn call(&self, req: Request<Body>) -> TransportFuture<'_> {
+        Box::pin(async move {
+            if req.method() != Method::GET {
+                return Ok(Response::builder().status(StatusCode::ACCEPTED).body(Body::empty())?);
+            }
+            let remaining = self.remaining.fetch_sub(1, Ordering::SeqCst);
+            if remaining == 0 {
+                return Err("no more events".into());
+            }
+            let deadline = SystemTime::now().duration_since(UNIX_EPOCH)? + Duration::from_secs(60);
+            Ok(Response::builder()
+                .header("lambda-runtime-aws-request-id", format!("request-{remaining}"))
+                .header("lambda-runtime-deadline-ms", deadline.as_millis().to_string())
+                .header(
+                    "lambda-runtime-invoked-function-arn",
+                    "arn:aws:lambda:us-east-1:123456789012:function:bench",
+                )
+                .body(Body::from(self.payload.clone()))?)
+        })
+    }
+}
+
+fn payload() -> Vec<u8> {
+    let items = (0..100)
+        .map(|i| json!({ "id": i, "name": format!("item-{i}"), "tags": ["a", "b", "c"], "price": 9.99 }))
+        .collect::<Vec<_>>();
+    serde_json::to_vec(&json!({ "order": "order-1", "items": items })).unwrap()
+}
+
+fn percentile(timings: &[InvocationTimings], phase: Phase, p: f64) -> Duration {
+    let mut values = timings.iter().map(phase).collect::<Vec<_>>();
+    values.sort();
+    values[((values.len() - 1) as f64 * p) as usize]
+}
+
+fn main() -> Result<(), Error> {
+    env::set_var("AWS_LAMBDA_FUNCTION_NAME", "bench");
+    env::set_var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE", "128");
+    env::set_var("AWS_LAMBDA_FUNCTION_VERSION", "$LATEST");
+
+    let timings = Arc::new(Mutex::new(Vec::with_capacity(INVOCATIONS)));
+    let recorded = timings.clone();
+    let events = Events {
+        payload: payload(),
+        remaining: AtomicUsize::new(INVOCATIONS),
+    };
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let handler = service_fn(|event: LambdaEvent<Value>| async { Ok::<_, Error>(event.payload) });
+            // The run ends with an error when the events run out.
+            let _ = RuntimeBuilder::new()
+                .with_recorder(move |t: &InvocationTimings| recorded.lock().unwrap().push(t.clone()))
+                .run_with_transport(handler, events)
+                .await;
+        });
+
+    let timings = timings.lock().unwrap();
+    let phases: [(&str, Phase); 7] = [
+        ("next event", |t| t.next_event),
+        ("decode", |t| t.decode),
+        ("handler", |t| t.handler),
+        ("encode", |t| t.encode),
+        ("respond", |t| t.respond),
+        ("overhead", InvocationTimings::overhead),
+        ("total", InvocationTimings::total),
+    ];
+    println!("{} invocations", timings.len());
+    println!("{:<12}{:>12}{:>12}", "phase", "p50", "p99");
+    for (name, phase) in phases {
+        println!(
+            "{:<12}{:>12?}{:>12?}",
+            name,
+            percentile(&timings, phase, 0.5),
+            percentile(&timings, phase, 0.99)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! The runtime emits a `WARN` event with the `lambda_runtime::alarms` target every
+//! time an invocation crosses one of the thresholds set with
+//! [`RuntimeBuilder::with_alarms`](crate::RuntimeBuilder::with_alarms). The events
+//! have an `alarm` field with the name of the threshold, the measured `value`, and
+//! the `threshold` itself, so CloudWatch Logs metric filters can count them without
+//! instrumenting the handler.
+//!
+//! The thresholds apply to streamed responses too, when the runtime is started with
+//! [`RuntimeBuilder::run_with_streaming_response`](crate::RuntimeBuilder::run_with_streaming_response).
+//! The size of a streamed response is the number of bytes of its body sent over the
+//! stream, and the duration of the handler ends when it returns the response, before
+//! the body is streamed.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{alarms::Alarms, service_fn, Error, LambdaEvent, RuntimeBuilder};
+//! use serde_json::Value;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let alarms = Alarms::new()
+//!         .request_size(4 * 1024 * 1024)
+//!         .response_size(4 * 1024 * 1024)
+//!         .handler_duration(80);
+//!
+//!     RuntimeBuilder::new()
+//!         .with_alarms(alarms)
+//!         .run(service_fn(func))
+//!         .await
+//! }
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//! ```
+use std::time::Duration;
+use tracing::warn;
+
+/// Name of the alarm on the size of the events.
+pub const REQUEST_SIZE: &str = "request_size";
+
+/// Name of the alarm on the size of the responses.
+pub const RESPONSE_SIZE: &str = "response_size";
+
+/// Name of the alarm on the duration of the handler.
+pub const HANDLER_DURATION: &str = "handler_duration";
+
+/// Thresholds that emit an alarm event when they're crossed.
+///
+/// All the thresholds are disabled by default.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Alarms {
+    request_size: Option<usize>,
+    response_size: Option<usize>,
+    handler_duration: Option<u8>,
+}
+
+impl Alarms {
+    /// Create a set of alarms without thresholds.
+    pub fn new() -> Self {
+        Alarms::default()
+    }
+
+    /// Emit an alarm when an event is larger than `bytes`.
+    pub fn request_size(self, bytes: usize) -> Self {
+        Alarms {
+            request_size: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Emit an alarm when a response is larger than `bytes`.
+    pub fn response_size(self, bytes: usize) -> Self {
+        Alarms {
+            response_size: Some(bytes),
+            ..self
+        }
+    }
+
+    /// Emit an alarm when the handler takes more than `percent` of the time
+    /// that the invocation had left when the event was received.
+    ///
+    /// Values over `100` are capped at `100`.
+    pub fn handler_duration(self, percent: u8) -> Self {
+        Alarms {
+            handler_duration: Some(percent.min(100)),
+            ..self
+        }
+    }
+
+    /// Return `true` if no threshold is set.
+    pub fn is_disabled(&self) -> bool {
+        *self == Alarms::default()
+    }
+
+    pub(crate) fn check_request(&self, request_id: &str, size: usize) {
+        if let Some(threshold) = self.request_size.filter(|threshold| size > *threshold) {
+            warn!(
+                alarm = REQUEST_SIZE,
+                requestId = request_id,
+                value = size,
+                threshold,
+                "event size crossed the alarm threshold"
+            );
+        }
+    }
+
+    pub(crate) fn check_response(&self, request_id: &str, size: usize) {
+        if let Some(threshold) = self.response_size.filter(|threshold| size > *threshold) {
+            warn!(
+                alarm = RESPONSE_SIZE,
+                requestId = request_id,
+                value = size,
+                threshold,
+                "response size crossed the alarm threshold"
+            );
+        }
+    }
+
+    pub(crate) fn check_duration(&self, request_id: &str, elapsed: Duration, available: Duration) {
+        let percent = match self.handler_duration {
+            Some(percent) => percent,
+            None => return,
+        };
+        let threshold = available.mul_f64(f64::from(percent) / 100.0);
+        if elapsed > threshold {
+            warn!(
+                alarm = HANDLER_DURATION,
+                requestId = request_id,
+                value = elapsed.as_millis() as u64,
+                threshold = threshold.as_millis() as u64,
+                "handler duration crossed the alarm threshold"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_are_disabled_by_default() {
+        assert!(Alarms::new().is_disabled());
+        assert!(!Alarms::new().request_size(1).is_disabled());
+        assert_eq!(Some(100), Alarms::new().handler_duration(150).handler_duration);
+    }
+}
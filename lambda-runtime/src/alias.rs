@@ -0,0 +1,191 @@
+use crate::LambdaEvent;
+use futures::future::{BoxFuture, Either};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::Service;
+use tracing::debug;
+
+/// Change of the qualifier used to invoke the function, between two invocations.
+///
+/// The qualifier is the alias or the version at the end of the invoked function ARN,
+/// see [`crate::Context::qualifier`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AliasChange {
+    /// The qualifier of the previous invocation, `None` for the first invocation
+    /// or when the previous invocation used an unqualified ARN.
+    pub previous: Option<String>,
+    /// The qualifier of the invocation about to start, `None` for an unqualified ARN.
+    pub current: Option<String>,
+}
+
+impl AliasChange {
+    /// The alias of the invocation about to start, without numeric versions and `$LATEST`.
+    pub fn alias(&self) -> Option<&str> {
+        self.current
+            .as_deref()
+            .filter(|q| *q != "$LATEST" && !q.bytes().all(|b| b.is_ascii_digit()))
+    }
+}
+
+type Hook = Arc<dyn Fn(AliasChange) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Callback that re-resolves the alias-scoped configuration of a function,
+/// like feature flags and parameters, when the qualifier of the invocations changes.
+///
+/// Register it with [`crate::RuntimeBuilder::on_alias_change`].
+#[derive(Clone)]
+pub struct OnAliasChange(Hook);
+
+impl OnAliasChange {
+    /// Create a hook that runs `f`, and waits for the future it returns.
+    pub fn new<F, Fut>(f: F) -> Self
+    where
+        F: Fn(AliasChange) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        OnAliasChange(Arc::new(move |change| Box::pin(f(change))))
+    }
+}
+
+impl fmt::Debug for OnAliasChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnAliasChange").finish()
+    }
+}
+
+/// Service that runs an [`OnAliasChange`] hook before the handler, when the
+/// qualifier of an invocation differs from the one of the previous invocation.
+///
+/// The hook also runs before the first invocation, so the configuration can be
+/// resolved once the alias is known.
+pub(crate) struct AliasWatch<S> {
+    inner: S,
+    hook: Option<OnAliasChange>,
+    last: Option<Option<String>>,
+}
+
+impl<S> AliasWatch<S> {
+    pub(crate) fn new(inner: S, hook: Option<OnAliasChange>) -> Self {
+        AliasWatch {
+            inner,
+            hook,
+            last: None,
+        }
+    }
+}
+
+impl<S, A> Service<LambdaEvent<A>> for AliasWatch<S>
+where
+    S: Service<LambdaEvent<A>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<S::Future, AfterHook<S::Future>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaEvent<A>) -> Self::Future {
+        let hook = match &self.hook {
+            Some(hook) => hook,
+            None => return Either::Left(self.inner.call(req)),
+        };
+
+        let current = req.context.qualifier().map(String::from);
+        if self.last.as_ref() == Some(&current) {
+            return Either::Left(self.inner.call(req));
+        }
+
+        let previous = self.last.replace(current.clone()).flatten();
+        debug!(?previous, ?current, "the qualifier of the function changed");
+        let hook = (hook.0)(AliasChange { previous, current });
+        Either::Right(AfterHook {
+            hook: Some(hook),
+            inner: Box::pin(self.inner.call(req)),
+        })
+    }
+}
+
+/// Future of the handler, polled once the [`OnAliasChange`] hook is done.
+pub(crate) struct AfterHook<F> {
+    hook: Option<BoxFuture<'static, ()>>,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for AfterHook<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Some(hook) = self.hook.as_mut() {
+            match hook.as_mut().poll(cx) {
+                Poll::Ready(()) => self.hook = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, Context, Error};
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    fn event(arn: &str) -> LambdaEvent<()> {
+        let context = Context {
+            invoked_function_arn: arn.into(),
+            ..Default::default()
+        };
+        LambdaEvent::new((), context)
+    }
+
+    #[tokio::test]
+    async fn runs_the_hook_before_the_handler_when_the_qualifier_changes() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let hook_log = log.clone();
+        let hook = OnAliasChange::new(move |change: AliasChange| {
+            let log = hook_log.clone();
+            async move {
+                tokio::task::yield_now().await;
+                let entry = format!("{:?} -> {:?}", change.previous, change.alias());
+                log.lock().unwrap().push(entry);
+            }
+        });
+        let handler_log = log.clone();
+        let handler = service_fn(move |_: LambdaEvent<()>| {
+            let log = handler_log.clone();
+            async move {
+                log.lock().unwrap().push("handler".to_string());
+                Ok::<_, Error>(())
+            }
+        });
+        let mut service = AliasWatch::new(handler, Some(hook));
+
+        let arn = "arn:aws:lambda:us-east-1:123456789012:function:my-function";
+        for qualifier in [":blue", ":blue", ":green", ":7", ""] {
+            let req = event(&format!("{arn}{qualifier}"));
+            service.ready().await.unwrap().call(req).await.unwrap();
+        }
+
+        let expected = vec![
+            "None -> Some(\"blue\")",
+            "handler",
+            "handler",
+            "Some(\"blue\") -> Some(\"green\")",
+            "handler",
+            "Some(\"green\") -> None",
+            "handler",
+            "Some(\"7\") -> None",
+            "handler",
+        ];
+        assert_eq!(expected, *log.lock().unwrap());
+    }
+}
@@ -0,0 +1,133 @@
+//! The system allocator of the Lambda images is glibc's, which keeps freed
+//! memory in per-thread arenas and fragments under the allocation patterns of
+//! async handlers. jemalloc and mimalloc usually start faster, allocate faster,
+//! and keep the resident memory of warm execution environments lower.
+//!
+//! The `jemalloc` and `mimalloc` features install one of them as the global
+//! allocator of the binary, so they must only be enabled by the crate of the
+//! function, not by another library. When both are enabled, like with
+//! `--all-features`, jemalloc is installed and mimalloc is unused. With jemalloc, the
+//! runtime also sets its [`malloc_conf`] for Lambda: two arenas, because functions
+//! have a few vCPUs at most, no background thread, because it would be frozen
+//! between invocations, and a short decay of dirty pages, so memory freed by an
+//! invocation is given back before the next one without purging on every free.
+//! The `_RJEM_MALLOC_CONF` environment variable overrides these options. mimalloc
+//! is used with its defaults, which already return memory promptly, and reads its
+//! options from the `MIMALLOC_*` environment variables.
+//!
+//! The [`AllocatorSampler`] adds the statistics of the allocator to the samples of
+//! [`MemoryLayer`](crate::memory::MemoryLayer), which uses it by default when one
+//! of the features is enabled.
+use crate::memory::{MemorySample, MemorySampler, ProcStatusSampler};
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+/// Options that jemalloc reads when it starts, tuned for Lambda.
+#[cfg(feature = "jemalloc")]
+#[allow(non_upper_case_globals)]
+#[export_name = "_rjem_malloc_conf"]
+pub static malloc_conf: &[u8; 71] = b"narenas:2,background_thread:false,dirty_decay_ms:1000,muzzy_decay_ms:0\0";
+
+/// The name of the global allocator installed by the runtime.
+#[cfg(feature = "jemalloc")]
+pub const NAME: &str = "jemalloc";
+
+/// The name of the global allocator installed by the runtime.
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+pub const NAME: &str = "mimalloc";
+
+/// Sampler that reports the memory allocated by the global allocator as the heap,
+/// with the resident set size from `/proc/self/status`.
+///
+/// It reads the statistics of the allocator named by [`NAME`], jemalloc when
+/// both features are enabled.
+/// With jemalloc, the heap is the memory allocated by the application. With
+/// mimalloc, it's the memory committed by the allocator, which includes the
+/// memory it keeps for future allocations.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocatorSampler;
+
+impl MemorySampler for AllocatorSampler {
+    fn sample(&self) -> Option<MemorySample> {
+        let heap = heap()?;
+        let sample = ProcStatusSampler.sample().or_else(fallback)?;
+        Some(sample.with_heap(heap))
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+fn heap() -> Option<u64> {
+    use tikv_jemalloc_ctl::{epoch, stats};
+
+    // Statistics are cached until the epoch advances.
+    epoch::advance().ok()?;
+    Some(stats::allocated::read().ok()? as u64)
+}
+
+#[cfg(feature = "jemalloc")]
+fn fallback() -> Option<MemorySample> {
+    Some(MemorySample::new(
+        tikv_jemalloc_ctl::stats::resident::read().ok()? as u64
+    ))
+}
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+fn heap() -> Option<u64> {
+    Some(process_info().1 as u64)
+}
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+fn fallback() -> Option<MemorySample> {
+    let (rss, _, peak_rss) = process_info();
+    Some(MemorySample::new(rss as u64).with_peak(peak_rss as u64))
+}
+
+// The current resident set size, the current committed memory, and the peak
+// resident set size, as tracked by mimalloc.
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+fn process_info() -> (usize, usize, usize) {
+    let (mut elapsed, mut user, mut system) = (0, 0, 0);
+    let (mut rss, mut peak_rss, mut commit, mut peak_commit, mut faults) = (0, 0, 0, 0, 0);
+    // SAFETY: every pointer points to a valid `usize`.
+    unsafe {
+        libmimalloc_sys::mi_process_info(
+            &mut elapsed,
+            &mut user,
+            &mut system,
+            &mut rss,
+            &mut peak_rss,
+            &mut commit,
+            &mut peak_commit,
+            &mut faults,
+        );
+    }
+    (rss, commit, peak_rss)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_the_heap_of_the_allocator() {
+        let before = AllocatorSampler.sample().unwrap();
+        let buffer = vec![1u8; 8 * 1024 * 1024];
+        let after = AllocatorSampler.sample().unwrap();
+        assert!(after.heap.unwrap() >= before.heap.unwrap() + buffer.len() as u64 / 2);
+        assert!(after.resident > 0);
+        drop(buffer);
+    }
+
+    #[test]
+    #[cfg(feature = "jemalloc")]
+    fn tunes_jemalloc() {
+        assert_eq!(2, tikv_jemalloc_ctl::opt::narenas::read().unwrap());
+        assert!(!tikv_jemalloc_ctl::opt::background_thread::read().unwrap());
+    }
+}
@@ -0,0 +1,221 @@
+#[cfg(feature = "bench")]
+use serde_json::{json, Map, Value};
+#[cfg(feature = "bench")]
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use std::{sync::Arc, time::Duration};
+
+/// The time that the runtime spent in every phase of an invocation.
+///
+/// Timings are measured by the runtime's event loop, and reported to the
+/// [`Recorder`] configured with
+/// [`RuntimeBuilder::with_recorder`](crate::RuntimeBuilder::with_recorder).
+/// Invocations with events that can't be decoded are not reported.
+///
+/// Streamed responses are reported too, when the runtime is started with
+/// [`RuntimeBuilder::run_with_streaming_response`](crate::RuntimeBuilder::run_with_streaming_response).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct InvocationTimings {
+    /// The AWS request ID of the invocation.
+    pub request_id: String,
+    /// Whether this was the first invocation handled by the runtime.
+    pub cold_start: bool,
+    /// The time between sending the previous response and receiving this event.
+    /// Functions are frozen while they wait for events, so this is mostly the
+    /// round trip to the Runtime API.
+    pub next_event: Duration,
+    /// The time to decode the payload of the event.
+    pub decode: Duration,
+    /// The time that the handler took to be ready and to return a result.
+    pub handler: Duration,
+    /// The time to encode the response of the handler.
+    /// Always zero for streamed responses, which are encoded while they're sent.
+    pub encode: Duration,
+    /// The time to send the response to the Runtime API.
+    /// For streamed responses, this is the time until the Runtime API accepts the stream.
+    pub respond: Duration,
+    /// The duration of the init phase of the execution environment, reported
+    /// with the first invocation only. See [`Context::init_duration`](crate::Context::init_duration).
+    pub init_duration: Option<Duration>,
+}
+
+impl InvocationTimings {
+    /// The time that the runtime spent on the invocation, outside of the handler.
+    pub fn overhead(&self) -> Duration {
+        self.next_event + self.decode + self.encode + self.respond
+    }
+
+    /// The time spent on the invocation, including the handler.
+    pub fn total(&self) -> Duration {
+        self.overhead() + self.handler
+    }
+}
+
+/// Destination of the timings of every invocation.
+///
+/// Recorders are called in the event loop after the response is sent, so they
+/// should not block. Closures that take `&InvocationTimings` are recorders.
+pub trait Recorder: Send + Sync {
+    /// Record the timings of one invocation.
+    fn record(&self, timings: &InvocationTimings);
+}
+
+impl<F> Recorder for F
+where
+    F: Fn(&InvocationTimings) + Send + Sync,
+{
+    fn record(&self, timings: &InvocationTimings) {
+        self(timings)
+    }
+}
+
+pub(crate) type SharedRecorder = Arc<dyn Recorder>;
+
+/// Recorder that prints the timings in the
+/// [CloudWatch Embedded Metric Format](https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html).
+///
+/// Metrics are published with the function name and memory size as
+/// dimensions, so the overhead of the same function can be compared across
+/// memory configurations.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{bench::EmfRecorder, service_fn, Error, LambdaEvent, RuntimeBuilder};
+/// use serde_json::Value;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     RuntimeBuilder::new()
+///         .with_recorder(EmfRecorder::new("MyApp/Runtime"))
+///         .run(service_fn(func))
+///         .await
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+#[cfg(feature = "bench")]
+#[derive(Debug, Clone)]
+pub struct EmfRecorder {
+    namespace: String,
+    function_name: String,
+    memory_size: String,
+}
+
+#[cfg(feature = "bench")]
+const METRICS: [&str; 7] = [
+    "NextEvent",
+    "Decode",
+    "Handler",
+    "Encode",
+    "Respond",
+    "Overhead",
+    "Total",
+];
+
+#[cfg(feature = "bench")]
+impl EmfRecorder {
+    /// Create a recorder that publishes metrics in `namespace`, for the function
+    /// described by the Lambda environment variables.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        EmfRecorder {
+            namespace: namespace.into(),
+            function_name: env::var("AWS_LAMBDA_FUNCTION_NAME").unwrap_or_default(),
+            memory_size: env::var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE").unwrap_or_default(),
+        }
+    }
+
+    /// Format the timings of an invocation as an EMF document.
+    pub fn format(&self, timings: &InvocationTimings) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut metrics = METRICS.to_vec();
+        let mut values = vec![
+            timings.next_event,
+            timings.decode,
+            timings.handler,
+            timings.encode,
+            timings.respond,
+            timings.overhead(),
+            timings.total(),
+        ];
+        if let Some(init_duration) = timings.init_duration {
+            metrics.push("InitDuration");
+            values.push(init_duration);
+        }
+
+        let mut document = Map::new();
+        document.insert(
+            "_aws".into(),
+            json!({
+                "Timestamp": timestamp,
+                "CloudWatchMetrics": [{
+                    "Namespace": self.namespace,
+                    "Dimensions": [["FunctionName", "MemorySize"]],
+                    "Metrics": metrics
+                        .iter()
+                        .map(|name| json!({ "Name": name, "Unit": "Microseconds" }))
+                        .collect::<Vec<_>>(),
+                }],
+            }),
+        );
+        document.insert("FunctionName".into(), self.function_name.clone().into());
+        document.insert("MemorySize".into(), self.memory_size.clone().into());
+        document.insert("RequestId".into(), timings.request_id.clone().into());
+        document.insert("ColdStart".into(), timings.cold_start.into());
+        for (name, value) in metrics.iter().zip(values) {
+            document.insert((*name).into(), (value.as_micros() as u64).into());
+        }
+        Value::Object(document).to_string()
+    }
+}
+
+#[cfg(feature = "bench")]
+impl Recorder for EmfRecorder {
+    fn record(&self, timings: &InvocationTimings) {
+        println!("{}", self.format(timings));
+    }
+}
+
+#[cfg(all(test, feature = "bench"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_timings_as_emf() {
+        let recorder = EmfRecorder {
+            namespace: "Test".into(),
+            function_name: "my-function".into(),
+            memory_size: "128".into(),
+        };
+        let timings = InvocationTimings {
+            request_id: "my-id".into(),
+            cold_start: true,
+            next_event: Duration::from_micros(100),
+            decode: Duration::from_micros(10),
+            handler: Duration::from_micros(1000),
+            encode: Duration::from_micros(20),
+            respond: Duration::from_micros(200),
+            init_duration: Some(Duration::from_millis(120)),
+        };
+
+        let document: Value = serde_json::from_str(&recorder.format(&timings)).unwrap();
+        let metrics = &document["_aws"]["CloudWatchMetrics"][0];
+        assert_eq!("Test", metrics["Namespace"]);
+        assert_eq!(json!([["FunctionName", "MemorySize"]]), metrics["Dimensions"]);
+        assert_eq!(8, metrics["Metrics"].as_array().unwrap().len());
+        assert_eq!("my-function", document["FunctionName"]);
+        assert_eq!("128", document["MemorySize"]);
+        assert_eq!(true, document["ColdStart"]);
+        assert_eq!(1000, document["Handler"]);
+        assert_eq!(330, document["Overhead"]);
+        assert_eq!(1330, document["Total"]);
+        assert_eq!(120_000, document["InitDuration"]);
+    }
+}
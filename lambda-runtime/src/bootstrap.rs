@@ -0,0 +1,181 @@
+//! Container images start the function with the `ENTRYPOINT` of the image, and
+//! pass the `CMD` of the image, or the `ImageConfig.Command` of the function, as
+//! its arguments. A [`Bootstrap`] uses that command to select one of several
+//! handlers compiled in the same binary, so one image can serve many functions.
+//!
+//! When the image runs outside of Lambda, for instance with `docker run`, there
+//! is no Runtime API to talk to. If the [Runtime Interface Emulator] is installed
+//! in the image, the bootstrap starts the function under it, so the container
+//! accepts invocations on port 8080 like the AWS base images do. Otherwise the
+//! function runs in [`ExecutionMode::Local`].
+//!
+//! [Runtime Interface Emulator]: https://github.com/aws/aws-lambda-runtime-interface-emulator
+use crate::{selected_handler, Error, ExecutionMode, HandlerRegistry, LambdaEvent};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt,
+    future::Future,
+    path::{Path, PathBuf},
+};
+use tower::Service;
+use tracing::info;
+
+/// Environment variable with the path of the Runtime Interface Emulator.
+pub const RIE_PATH_VAR: &str = "LAMBDA_RIE_PATH";
+
+/// Path where the AWS base images install the Runtime Interface Emulator.
+pub const DEFAULT_RIE_PATH: &str = "/usr/local/bin/aws-lambda-rie";
+
+// Set on the process started under the emulator, to not start it again.
+const UNDER_RIE_VAR: &str = "LAMBDA_BOOTSTRAP_UNDER_RIE";
+
+/// Return the name of the handler to run, from [`selected_handler`], or from
+/// the first argument of the process.
+pub fn handler_name() -> Option<String> {
+    selected_handler().or_else(|| env::args().nth(1).filter(|name| !name.is_empty()))
+}
+
+/// Entrypoint of a binary that serves one or more functions from a container image.
+///
+/// Handlers are registered in a [`HandlerRegistry`], and the bootstrap runs the
+/// one selected with [`handler_name`].
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{bootstrap::Bootstrap, service_fn, Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// async fn resize(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// async fn thumbnail(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// // With `ENTRYPOINT ["/bootstrap"]` in the image, and the command of
+/// // each function set to `resize` or `thumbnail`.
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     Bootstrap::new()
+///         .handler("resize", service_fn(resize))
+///         .handler("thumbnail", service_fn(thumbnail))
+///         .run()
+///         .await
+/// }
+/// ```
+pub struct Bootstrap {
+    registry: HandlerRegistry,
+    rie: Option<PathBuf>,
+}
+
+impl Bootstrap {
+    /// Create a bootstrap without handlers.
+    ///
+    /// The Runtime Interface Emulator is looked up in [`RIE_PATH_VAR`], or in
+    /// [`DEFAULT_RIE_PATH`].
+    pub fn new() -> Self {
+        let rie = env::var_os(RIE_PATH_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_RIE_PATH));
+        Bootstrap {
+            registry: HandlerRegistry::new(),
+            rie: Some(rie),
+        }
+    }
+
+    /// Register a handler under `name`.
+    pub fn handler<A, B, F>(self, name: impl Into<String>, handler: F) -> Self
+    where
+        F: Service<LambdaEvent<A>> + 'static,
+        F::Future: Future<Output = Result<B, F::Error>>,
+        F::Error: fmt::Debug + fmt::Display + 'static,
+        A: for<'de> Deserialize<'de> + 'static,
+        B: Serialize + 'static,
+    {
+        Bootstrap {
+            registry: self.registry.register(name, handler),
+            ..self
+        }
+    }
+
+    /// Use the handlers of a registry, replacing the registered ones.
+    pub fn registry(self, registry: HandlerRegistry) -> Self {
+        Bootstrap { registry, ..self }
+    }
+
+    /// Set the path of the Runtime Interface Emulator.
+    pub fn rie(self, path: impl Into<PathBuf>) -> Self {
+        Bootstrap {
+            rie: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// Never start the Runtime Interface Emulator.
+    pub fn without_rie(self) -> Self {
+        Bootstrap { rie: None, ..self }
+    }
+
+    /// Return the names of the registered handlers.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.registry.names()
+    }
+
+    /// Run the handler selected by [`handler_name`].
+    ///
+    /// Outside of Lambda, the process starts again under the Runtime Interface
+    /// Emulator when it's installed, with the same arguments.
+    pub async fn run(self) -> Result<(), Error> {
+        let launch = self.registry.select(handler_name())?;
+        if let Some(rie) = self.rie.as_deref().filter(|_| needs_rie()) {
+            if rie.is_file() {
+                return run_under_rie(rie);
+            }
+        }
+        launch().await
+    }
+}
+
+impl Default for Bootstrap {
+    fn default() -> Self {
+        Bootstrap::new()
+    }
+}
+
+impl fmt::Debug for Bootstrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bootstrap")
+            .field("registry", &self.registry)
+            .field("rie", &self.rie)
+            .finish()
+    }
+}
+
+fn needs_rie() -> bool {
+    ExecutionMode::detect() == ExecutionMode::Local && env::var_os(UNDER_RIE_VAR).is_none()
+}
+
+// Start the emulator with this binary as the bootstrap of the function. On Unix
+// the emulator replaces the current process, like `exec` in a shell entrypoint.
+fn run_under_rie(rie: &Path) -> Result<(), Error> {
+    let exe = env::current_exe()?;
+    info!(rie = %rie.display(), "no Runtime API, starting the function under the Runtime Interface Emulator");
+    let mut command = std::process::Command::new(rie);
+    command.arg(exe).args(env::args_os().skip(1)).env(UNDER_RIE_VAR, "1");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        Err(command.exec().into())
+    }
+    #[cfg(not(unix))]
+    {
+        let status = command.status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("the Runtime Interface Emulator exited with {status}").into())
+        }
+    }
+}
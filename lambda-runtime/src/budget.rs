@@ -0,0 +1,165 @@
+use std::{
+    fmt,
+    future::Future,
+    time::{Duration, SystemTime},
+};
+
+/// The time left to handle an invocation, derived from its deadline.
+///
+/// Budgets are created with [`Context::time_budget`](crate::Context::time_budget),
+/// and can be split into phases with their own deadlines, so every step of the
+/// handler gets a timeout that matches the time that the invocation has left,
+/// instead of a fixed value that doesn't know about the function's timeout.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, Error, LambdaEvent};
+/// use serde_json::Value;
+/// use std::time::Duration;
+///
+/// async fn fetch(_id: &str) -> Value {
+///     # unimplemented!()
+/// }
+///
+/// async fn save(_value: &Value) {
+///     # unimplemented!()
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     // Keep 200ms to report the result, and give 70% of the rest to the
+///     // downstream call, 20% to persistence, and 10% as a reserve.
+///     let budget = event.context.time_budget().reserve(Duration::from_millis(200));
+///     let [downstream, persistence, _reserve] = budget.split([70, 20, 10]);
+///
+///     let value = downstream.timeout(fetch("order-1")).await?;
+///     persistence.timeout(save(&value)).await?;
+///     Ok(value)
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeBudget {
+    deadline: SystemTime,
+}
+
+impl TimeBudget {
+    /// Create a budget that ends at `deadline`.
+    pub fn new(deadline: SystemTime) -> Self {
+        TimeBudget { deadline }
+    }
+
+    /// The time when this budget ends.
+    pub fn deadline(&self) -> SystemTime {
+        self.deadline
+    }
+
+    /// The time left before the end of this budget.
+    pub fn remaining(&self) -> Duration {
+        self.deadline.duration_since(SystemTime::now()).unwrap_or_default()
+    }
+
+    /// Whether this budget has no time left.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    /// Return a budget that ends `reserve` before this one, to keep time for the work
+    /// that must happen after it, like reporting the result of the invocation.
+    pub fn reserve(&self, reserve: Duration) -> TimeBudget {
+        let now = SystemTime::now();
+        let deadline = self
+            .deadline
+            .checked_sub(reserve)
+            .filter(|deadline| *deadline > now)
+            .unwrap_or(now);
+        TimeBudget { deadline }
+    }
+
+    /// Split the time left into consecutive phases, proportional to `weights`.
+    ///
+    /// Every phase ends at a fixed point in time, so the time that a phase doesn't
+    /// use is given to the phases after it. The last phase ends with this budget.
+    pub fn split<const N: usize>(&self, weights: [u32; N]) -> [TimeBudget; N] {
+        let now = SystemTime::now();
+        let remaining = self.remaining();
+        let total = weights.iter().map(|w| u64::from(*w)).sum::<u64>().max(1);
+
+        let mut cumulative = 0u64;
+        let mut phases = [*self; N];
+        for (phase, weight) in phases.iter_mut().zip(weights) {
+            cumulative += u64::from(weight);
+            if cumulative < total {
+                phase.deadline = now + remaining.mul_f64(cumulative as f64 / total as f64);
+            }
+        }
+        phases
+    }
+
+    /// Run `future` until it completes, or until the end of this budget.
+    pub async fn timeout<F: Future>(&self, future: F) -> Result<F::Output, BudgetExceeded> {
+        let remaining = self.remaining();
+        tokio::time::timeout(remaining, future)
+            .await
+            .map_err(|_| BudgetExceeded { budget: remaining })
+    }
+}
+
+/// Error returned when a future doesn't complete before the end of its [`TimeBudget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    budget: Duration,
+}
+
+impl BudgetExceeded {
+    /// The time that the future had to complete.
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "time budget of {}ms exceeded", self.budget.as_millis())
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn within(expected: Duration, actual: Duration) -> bool {
+        let tolerance = Duration::from_millis(50);
+        actual <= expected && expected - actual <= tolerance
+    }
+
+    #[test]
+    fn splits_the_remaining_time() {
+        let budget = TimeBudget::new(SystemTime::now() + Duration::from_secs(10));
+        let [first, second, third] = budget.split([70, 20, 10]);
+        assert!(within(Duration::from_secs(7), first.remaining()), "{first:?}");
+        assert!(within(Duration::from_secs(9), second.remaining()), "{second:?}");
+        assert_eq!(budget.deadline(), third.deadline());
+    }
+
+    #[test]
+    fn reserves_time_at_the_end() {
+        let budget = TimeBudget::new(SystemTime::now() + Duration::from_secs(1));
+        assert!(within(
+            Duration::from_millis(800),
+            budget.reserve(Duration::from_millis(200)).remaining()
+        ));
+        assert!(budget.reserve(Duration::from_secs(2)).is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn futures_time_out_at_the_end_of_the_budget() {
+        let budget = TimeBudget::new(SystemTime::now() + Duration::from_millis(10));
+        let err = budget.timeout(futures::future::pending::<()>()).await.unwrap_err();
+        assert!(err.budget() <= Duration::from_millis(10));
+        assert_eq!(Ok(1), budget.timeout(async { 1 }).await);
+
+        let past = TimeBudget::new(SystemTime::UNIX_EPOCH);
+        assert!(past.timeout(futures::future::pending::<()>()).await.is_err());
+    }
+}
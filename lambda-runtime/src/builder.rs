@@ -0,0 +1,231 @@
+use crate::{
+    alarms::Alarms,
+    alias::{AliasChange, AliasWatch, OnAliasChange},
+    bench::SharedRecorder,
+    codec::{Codec, JsonCodec},
+    incoming,
+    json::JsonPolicy,
+    local, runtime_client, Config, Error, ExecutionMode, LambdaEvent, Resources, Runtime, TokioExecutor,
+};
+use bytes::Bytes;
+use hyper::body::HttpBody;
+use lambda_runtime_api_client::Transport;
+use serde::{Deserialize, Serialize};
+use std::{fmt, future::Future, sync::Arc};
+use tower::Service;
+use tracing::trace;
+
+/// Builder to configure the runtime before it starts polling for events.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{
+///     json::{JsonPolicy, NonFiniteFloats},
+///     service_fn, Error, LambdaEvent, RuntimeBuilder,
+/// };
+/// use serde_json::Value;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     RuntimeBuilder::new()
+///         .with_json_policy(JsonPolicy::new().non_finite_floats(NonFiniteFloats::Reject))
+///         .run(service_fn(func))
+///         .await
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+pub struct RuntimeBuilder<C = JsonCodec> {
+    codec: C,
+    recorder: Option<SharedRecorder>,
+    resources: Option<Resources>,
+    alarms: Alarms,
+    on_alias_change: Option<OnAliasChange>,
+}
+
+impl<C: fmt::Debug> fmt::Debug for RuntimeBuilder<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RuntimeBuilder")
+            .field("codec", &self.codec)
+            .field("recorder", &self.recorder.is_some())
+            .field("resources", &self.resources)
+            .field("alarms", &self.alarms)
+            .field("on_alias_change", &self.on_alias_change.is_some())
+            .finish()
+    }
+}
+
+impl RuntimeBuilder {
+    /// Create a new builder with the default configuration.
+    pub fn new() -> Self {
+        RuntimeBuilder {
+            codec: JsonCodec::new(),
+            recorder: None,
+            resources: None,
+            alarms: Alarms::default(),
+            on_alias_change: None,
+        }
+    }
+
+    /// Set the policy to serialize the responses of the function.
+    pub fn with_json_policy(self, policy: JsonPolicy) -> Self {
+        RuntimeBuilder {
+            codec: self.codec.with_policy(policy),
+            recorder: self.recorder,
+            resources: self.resources,
+            alarms: self.alarms,
+            on_alias_change: self.on_alias_change,
+        }
+    }
+}
+
+impl Default for RuntimeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Codec> RuntimeBuilder<C> {
+    /// Set the codec to decode events and encode responses.
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> RuntimeBuilder<C2> {
+        RuntimeBuilder {
+            codec,
+            recorder: self.recorder,
+            resources: self.resources,
+            alarms: self.alarms,
+            on_alias_change: self.on_alias_change,
+        }
+    }
+
+    /// Report the timings of every invocation to `recorder`.
+    #[cfg(feature = "bench")]
+    pub fn with_recorder(self, recorder: impl crate::bench::Recorder + 'static) -> Self {
+        RuntimeBuilder {
+            recorder: Some(Arc::new(recorder)),
+            ..self
+        }
+    }
+
+    /// Close `resources` when the process receives `SIGTERM`, or when the runtime
+    /// stops with an error.
+    pub fn with_resources(self, resources: Resources) -> Self {
+        RuntimeBuilder {
+            resources: Some(resources),
+            ..self
+        }
+    }
+
+    /// Emit an alarm event when an invocation crosses one of the thresholds of `alarms`.
+    pub fn with_alarms(self, alarms: Alarms) -> Self {
+        RuntimeBuilder { alarms, ..self }
+    }
+
+    /// Run `hook` before the handler when the alias or version used to invoke the
+    /// function differs from the one of the previous invocation, and before the first invocation.
+    ///
+    /// Use it to re-resolve configuration scoped to an alias, like feature flags or parameters.
+    /// The invocation waits for the future returned by `hook`.
+    pub fn on_alias_change<H, Fut>(self, hook: H) -> Self
+    where
+        H: Fn(AliasChange) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        RuntimeBuilder {
+            on_alias_change: Some(OnAliasChange::new(hook)),
+            ..self
+        }
+    }
+
+    /// Starts the Lambda Rust runtime with this configuration, and begins polling for events on the
+    /// [Lambda Runtime APIs](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html).
+    pub async fn run<A, B, F>(self, handler: F) -> Result<(), Error>
+    where
+        F: Service<LambdaEvent<A>>,
+        F::Future: Future<Output = Result<B, F::Error>>,
+        F::Error: fmt::Debug + fmt::Display + 'static,
+        A: for<'de> Deserialize<'de>,
+        B: Serialize,
+    {
+        if ExecutionMode::detect() == ExecutionMode::Local {
+            let handler = AliasWatch::new(handler, self.on_alias_change);
+            return match self.resources {
+                Some(resources) => resources.supervise(local::run(handler, self.codec)).await,
+                None => local::run(handler, self.codec).await,
+            };
+        }
+        let client = runtime_client().expect("Unable to create a runtime client");
+        self.run_with_transport(handler, client).await
+    }
+
+    /// Starts the Lambda Rust runtime with this configuration, and streams the responses
+    /// of the handler back like [`run_with_streaming_response`](crate::run_with_streaming_response).
+    ///
+    /// The response size alarm is checked against the bytes of the body sent over
+    /// the stream, once the stream ends.
+    pub async fn run_with_streaming_response<A, B, F>(self, handler: F) -> Result<(), Error>
+    where
+        F: Service<LambdaEvent<A>>,
+        F::Future: Future<Output = Result<http::Response<B>, F::Error>>,
+        F::Error: fmt::Debug + fmt::Display + 'static,
+        A: for<'de> Deserialize<'de>,
+        B: HttpBody + Unpin + Send + 'static,
+        B::Data: Into<Bytes> + Send,
+        B::Error: Into<Error> + Send + fmt::Debug,
+    {
+        trace!("Loading config from env");
+        let config = Config::from_env()?;
+        let client = runtime_client().expect("Unable to create a runtime client");
+        let runtime = Runtime {
+            client,
+            config,
+            executor: Arc::new(TokioExecutor),
+            codec: self.codec,
+            recorder: self.recorder,
+            alarms: self.alarms,
+        };
+
+        let handler = AliasWatch::new(handler, self.on_alias_change);
+        let client = &runtime.client;
+        let incoming = incoming(client);
+        match self.resources {
+            Some(resources) => {
+                resources
+                    .supervise(runtime.run_with_streaming_response(incoming, handler))
+                    .await
+            }
+            None => runtime.run_with_streaming_response(incoming, handler).await,
+        }
+    }
+
+    /// Starts the Lambda Rust runtime with this configuration and a custom [`Transport`].
+    pub async fn run_with_transport<A, B, F, T>(self, handler: F, transport: T) -> Result<(), Error>
+    where
+        F: Service<LambdaEvent<A>>,
+        F::Future: Future<Output = Result<B, F::Error>>,
+        F::Error: fmt::Debug + fmt::Display + 'static,
+        A: for<'de> Deserialize<'de>,
+        B: Serialize,
+        T: Transport,
+    {
+        trace!("Loading config from env");
+        let config = Config::from_env()?;
+        let runtime = Runtime {
+            client: transport,
+            config,
+            executor: Arc::new(TokioExecutor),
+            codec: self.codec,
+            recorder: self.recorder,
+            alarms: self.alarms,
+        };
+
+        let handler = AliasWatch::new(handler, self.on_alias_change);
+        let client = &runtime.client;
+        let incoming = incoming(client);
+        match self.resources {
+            Some(resources) => resources.supervise(runtime.run(incoming, handler)).await,
+            None => runtime.run(incoming, handler).await,
+        }
+    }
+}
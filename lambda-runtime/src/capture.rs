@@ -0,0 +1,361 @@
+//! Capture of the events that make the handler fail, to replay them later.
+//!
+//! [`CaptureLayer`] keeps the raw payload of every event until the handler
+//! returns. When the handler, or the deserialization of the payload, fails, it
+//! saves a [`Capture`] with the payload and the error in a [`CheckpointStore`],
+//! usually backed by S3, or logs it base64 encoded when it's smaller than a
+//! size cap.
+//!
+//! Saved captures are replayed with [`replay`], and logged ones are decoded with
+//! [`Capture::decode`], to reproduce the failure in a test.
+//!
+//! Payloads can contain personal data. Only capture events in environments
+//! where they can be kept, and expire the objects of the S3 prefix.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{capture::CaptureLayer, checkpoint::InMemoryStore, service_fn, tower::Layer, Error, LambdaEvent};
+//! use serde_json::Value;
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     // Use a store backed by S3 in real functions.
+//!     let layer = CaptureLayer::store(InMemoryStore::new(), "captures/");
+//!     lambda_runtime::run(layer.layer(service_fn(func))).await
+//! }
+//! ```
+use crate::{checkpoint::CheckpointStore, deserializer, Context, Error, LambdaEvent};
+use base64::Engine;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+};
+use tower::{Layer, Service};
+use tracing::{error, warn};
+
+/// An event that made the handler fail.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capture {
+    /// Id of the invocation that failed.
+    pub request_id: String,
+    /// ARN used to invoke the function.
+    pub invoked_function_arn: String,
+    /// Error returned by the handler.
+    pub error: String,
+    /// Raw payload of the event.
+    pub payload: Value,
+}
+
+impl Capture {
+    /// Decode a capture from the base64 encoded form written to the logs.
+    pub fn decode(encoded: &str) -> Result<Self, Error> {
+        let json = base64::engine::general_purpose::STANDARD.decode(encoded.trim())?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    /// Build the event of the failed invocation again.
+    ///
+    /// The context only has the request id and the function ARN of the invocation.
+    pub fn into_event(self) -> LambdaEvent<Value> {
+        let context = Context {
+            request_id: self.request_id,
+            invoked_function_arn: self.invoked_function_arn,
+            ..Default::default()
+        };
+        LambdaEvent::new(self.payload, context)
+    }
+}
+
+#[derive(Clone)]
+enum Target {
+    Store {
+        store: Arc<dyn CheckpointStore + Send + Sync>,
+        prefix: String,
+    },
+    Log {
+        max_bytes: usize,
+    },
+}
+
+impl Target {
+    async fn save(&self, capture: Capture) {
+        let json = serde_json::to_vec(&capture).expect("captures are always serializable");
+        match self {
+            Target::Store { store, prefix } => {
+                let key = format!("{prefix}{}.json", capture.request_id);
+                match store.save(&key, json).await {
+                    Ok(()) => error!(key, "handler failed, event captured"),
+                    Err(err) => warn!(key, "handler failed, unable to save the captured event: {err}"),
+                }
+            }
+            Target::Log { max_bytes } if json.len() > *max_bytes => {
+                warn!(
+                    size = json.len(),
+                    max_bytes, "handler failed, event too large to be captured in the logs"
+                );
+            }
+            Target::Log { .. } => {
+                let capture = base64::engine::general_purpose::STANDARD.encode(json);
+                error!(capture, "handler failed, event captured");
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Store { prefix, .. } => f.debug_struct("Store").field("prefix", prefix).finish(),
+            Target::Log { max_bytes } => f.debug_struct("Log").field("max_bytes", max_bytes).finish(),
+        }
+    }
+}
+
+/// A [`Layer`] that captures the events that make the handler fail.
+///
+/// See the [module documentation](self) for details.
+pub struct CaptureLayer<A> {
+    target: Target,
+    _payload: PhantomData<fn(A)>,
+}
+
+impl<A> CaptureLayer<A> {
+    /// Save captures in `store`, with the request id of the invocation under `prefix` as key.
+    pub fn store(store: impl CheckpointStore + Send + Sync + 'static, prefix: impl Into<String>) -> Self {
+        CaptureLayer {
+            target: Target::Store {
+                store: Arc::new(store),
+                prefix: prefix.into(),
+            },
+            _payload: PhantomData,
+        }
+    }
+
+    /// Log captures base64 encoded, when they aren't larger than `max_bytes`.
+    pub fn log(max_bytes: usize) -> Self {
+        CaptureLayer {
+            target: Target::Log { max_bytes },
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl<A> Clone for CaptureLayer<A> {
+    fn clone(&self) -> Self {
+        CaptureLayer {
+            target: self.target.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl<A> fmt::Debug for CaptureLayer<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureLayer").field("target", &self.target).finish()
+    }
+}
+
+impl<S, A> Layer<S> for CaptureLayer<A> {
+    type Service = CaptureService<S, A>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CaptureService {
+            inner,
+            target: self.target.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+
+/// A [`Service`] that captures the events that make the handler fail.
+///
+/// See [`CaptureLayer`] for details.
+pub struct CaptureService<S, A> {
+    inner: S,
+    target: Target,
+    _payload: PhantomData<fn(A)>,
+}
+
+impl<S: Clone, A> Clone for CaptureService<S, A> {
+    fn clone(&self) -> Self {
+        CaptureService {
+            inner: self.inner.clone(),
+            target: self.target.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl<S: fmt::Debug, A> fmt::Debug for CaptureService<S, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureService")
+            .field("inner", &self.inner)
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
+impl<S, A> Service<LambdaEvent<Value>> for CaptureService<S, A>
+where
+    S: Service<LambdaEvent<A>>,
+    S::Future: Send + 'static,
+    S::Error: Into<Error>,
+    A: for<'de> Deserialize<'de>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<S::Response, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: LambdaEvent<Value>) -> Self::Future {
+        let target = self.target.clone();
+        let mut capture = Capture {
+            request_id: req.context.request_id.clone(),
+            invoked_function_arn: req.context.invoked_function_arn.clone(),
+            error: String::new(),
+            payload: req.payload.clone(),
+        };
+        let fut = deserializer::deserialize_value(req.payload, req.context).map(|event| self.inner.call(event));
+
+        Box::pin(async move {
+            let err: Error = match fut {
+                Ok(fut) => match fut.await {
+                    Ok(response) => return Ok(response),
+                    Err(err) => err.into(),
+                },
+                Err(err) => err.into(),
+            };
+            capture.error = err.to_string();
+            target.save(capture).await;
+            Err(err)
+        })
+    }
+}
+
+/// Load the capture saved with `key` in `store`, and send its event to `handler` again.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{capture, checkpoint::InMemoryStore, service_fn, Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     // Use the S3 store that the function saves captures in.
+///     let store = InMemoryStore::new();
+///     let key = "captures/8476a536-e9f4-11e8-9739-2dfe598c3fcd.json";
+///     capture::replay(&store, key, service_fn(func)).await?;
+///     Ok(())
+/// }
+/// ```
+pub async fn replay<S, A>(store: &impl CheckpointStore, key: &str, mut handler: S) -> Result<S::Response, Error>
+where
+    S: Service<LambdaEvent<A>>,
+    S::Error: Into<Error>,
+    A: for<'de> Deserialize<'de>,
+{
+    let capture = match store.load(key).await? {
+        Some(capture) => serde_json::from_slice::<Capture>(&capture)?,
+        None => return Err(format!("no capture saved with the key `{key}`").into()),
+    };
+    let event = capture.into_event();
+    let event = deserializer::deserialize_value(event.payload, event.context)?;
+    futures::future::poll_fn(|cx| handler.poll_ready(cx))
+        .await
+        .map_err(Into::into)?;
+    handler.call(event).await.map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{checkpoint::InMemoryStore, service_fn};
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Order {
+        id: u32,
+    }
+
+    async fn process(event: LambdaEvent<Order>) -> Result<u32, Error> {
+        match event.payload.id {
+            42 => Err("order 42 is corrupted".into()),
+            id => Ok(id),
+        }
+    }
+
+    fn event(payload: Value) -> LambdaEvent<Value> {
+        let context = Context {
+            request_id: "8476a536-e9f4-11e8-9739-2dfe598c3fcd".to_string(),
+            ..Default::default()
+        };
+        LambdaEvent::new(payload, context)
+    }
+
+    #[tokio::test]
+    async fn failed_events_are_saved_and_replayed() {
+        let store = InMemoryStore::new();
+        let mut service = CaptureLayer::store(store.clone(), "captures/").layer(service_fn(process));
+
+        assert_eq!(7, service.call(event(json!({ "id": 7 }))).await.unwrap());
+        let key = "captures/8476a536-e9f4-11e8-9739-2dfe598c3fcd.json";
+        assert!(store.load(key).await.unwrap().is_none());
+
+        let err = service.call(event(json!({ "id": 42 }))).await.unwrap_err();
+        assert_eq!("order 42 is corrupted", err.to_string());
+        let capture: Capture = serde_json::from_slice(&store.load(key).await.unwrap().unwrap()).unwrap();
+        assert_eq!("order 42 is corrupted", capture.error);
+        assert_eq!(json!({ "id": 42 }), capture.payload);
+
+        let err = replay(&store, key, service_fn(process)).await.unwrap_err();
+        assert_eq!("order 42 is corrupted", err.to_string());
+    }
+
+    #[tokio::test]
+    async fn invalid_payloads_are_captured() {
+        let store = InMemoryStore::new();
+        let mut service = CaptureLayer::store(store.clone(), "").layer(service_fn(process));
+
+        service.call(event(json!({ "id": "42" }))).await.unwrap_err();
+        let capture = store
+            .load("8476a536-e9f4-11e8-9739-2dfe598c3fcd.json")
+            .await
+            .unwrap()
+            .unwrap();
+        let capture: Capture = serde_json::from_slice(&capture).unwrap();
+        assert!(capture.error.contains("invalid type"), "{}", capture.error);
+    }
+
+    #[test]
+    fn logged_captures_are_decoded() {
+        let capture = Capture {
+            request_id: "8476a536-e9f4-11e8-9739-2dfe598c3fcd".to_string(),
+            invoked_function_arn: "arn:aws:lambda:us-east-1:123456789012:function:orders".to_string(),
+            error: "order 42 is corrupted".to_string(),
+            payload: json!({ "id": 42 }),
+        };
+        let encoded = base64::engine::general_purpose::STANDARD.encode(serde_json::to_vec(&capture).unwrap());
+        assert_eq!(capture, Capture::decode(&encoded).unwrap());
+
+        let event = capture.into_event();
+        assert_eq!("8476a536-e9f4-11e8-9739-2dfe598c3fcd", event.context.request_id);
+        assert_eq!(json!({ "id": 42 }), event.payload);
+    }
+}
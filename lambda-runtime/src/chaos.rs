@@ -0,0 +1,629 @@
+//! Injection of faults into a share of the invocations, to test how the callers
+//! of a function, its retries and its alarms behave when it misbehaves.
+//!
+//! [`ChaosLayer`] can inject three kinds of [`Faults`], each into its own
+//! percentage of the invocations:
+//! * latency, which delays the call of the handler,
+//! * errors, which fail the invocation with an [`InjectedFault`] without calling
+//!   the handler,
+//! * corruption, which damages the response of the handler with [`Corrupt`].
+//!
+//! The faults are read from a JSON document, like the fault injection actions of
+//! AWS FIS, so they can be turned on and off without deploying the function:
+//! ```json
+//! {
+//!   "latency": { "percentage": 10, "duration_ms": 2000 },
+//!   "error": { "percentage": 5, "message": "downstream unavailable" },
+//!   "corruption": { "percentage": 1 }
+//! }
+//! ```
+//! [`ChaosLayer::from_env`] reads the document from the [`CHAOS_VAR`]
+//! environment variable. [`ChaosLayer::appconfig`] reads it from an AWS AppConfig
+//! configuration profile, through the AppConfig Lambda extension, and refreshes
+//! it periodically while the execution environment runs.
+//!
+//! Invocations go straight to the handler when no fault is configured, so the
+//! layer can stay in production code. Without the AppConfig source, that only
+//! costs the check of the configured percentages.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{chaos::ChaosLayer, service_fn, tower::Layer, Error, LambdaEvent};
+//! use serde_json::Value;
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     // Set `LAMBDA_CHAOS={"error":{"percentage":10}}` on the function to fail 10% of the invocations.
+//!     let func = ChaosLayer::from_env().layer(service_fn(func));
+//!     lambda_runtime::run(func).await
+//! }
+//! ```
+use crate::Error;
+use bytes::Bytes;
+use futures::future::{BoxFuture, Either, ErrInto, TryFutureExt};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::hash_map::RandomState,
+    env, fmt,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::{Layer, Service};
+use tracing::{info, warn};
+
+/// Environment variable with the JSON document of the faults to inject.
+pub const CHAOS_VAR: &str = "LAMBDA_CHAOS";
+
+/// Environment variable with the port of the AppConfig Lambda extension.
+pub const APPCONFIG_PORT_VAR: &str = "AWS_APPCONFIG_EXTENSION_HTTP_PORT";
+
+/// Port of the AppConfig Lambda extension by default.
+pub const DEFAULT_APPCONFIG_PORT: u16 = 2772;
+
+/// Interval between two reads of the AppConfig configuration by default.
+pub const DEFAULT_REFRESH: Duration = Duration::from_secs(30);
+
+/// Message of the injected errors by default.
+pub const DEFAULT_ERROR_MESSAGE: &str = "fault injected by ChaosLayer";
+
+/// The faults injected by a [`ChaosLayer`].
+///
+/// A fault with a percentage of 100 or more is injected into every invocation.
+/// Faults are drawn independently, so an invocation can be delayed and then fail.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Faults {
+    latency: Option<LatencyFault>,
+    error: Option<ErrorFault>,
+    corruption: Option<CorruptionFault>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct LatencyFault {
+    percentage: f64,
+    duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct ErrorFault {
+    percentage: f64,
+    #[serde(default = "default_message")]
+    message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct CorruptionFault {
+    percentage: f64,
+}
+
+fn default_message() -> String {
+    DEFAULT_ERROR_MESSAGE.to_string()
+}
+
+impl Faults {
+    /// Create an empty set of faults, which injects nothing.
+    pub fn new() -> Self {
+        Faults::default()
+    }
+
+    /// Delay `percentage` percent of the invocations by `duration`.
+    pub fn latency(self, percentage: f64, duration: Duration) -> Self {
+        Faults {
+            latency: Some(LatencyFault {
+                percentage,
+                duration_ms: duration.as_millis() as u64,
+            }),
+            ..self
+        }
+    }
+
+    /// Fail `percentage` percent of the invocations with an error with `message`.
+    pub fn error(self, percentage: f64, message: impl Into<String>) -> Self {
+        Faults {
+            error: Some(ErrorFault {
+                percentage,
+                message: message.into(),
+            }),
+            ..self
+        }
+    }
+
+    /// Corrupt the responses of `percentage` percent of the invocations.
+    pub fn corruption(self, percentage: f64) -> Self {
+        Faults {
+            corruption: Some(CorruptionFault { percentage }),
+            ..self
+        }
+    }
+
+    /// Parse a JSON document of faults, see the [module documentation](crate::chaos).
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Return true if any fault can be injected.
+    pub fn is_active(&self) -> bool {
+        self.latency.as_ref().map_or(false, |fault| fault.percentage > 0.0)
+            || self.error.as_ref().map_or(false, |fault| fault.percentage > 0.0)
+            || self.corruption.as_ref().map_or(false, |fault| fault.percentage > 0.0)
+    }
+
+    async fn inject<F, T, E>(&self, fut: F) -> Result<T, Error>
+    where
+        F: Future<Output = Result<T, E>>,
+        T: Corrupt,
+        E: Into<Error>,
+    {
+        if let Some(fault) = self.latency.as_ref().filter(|fault| drawn(fault.percentage)) {
+            info!(duration_ms = fault.duration_ms, "injecting latency");
+            tokio::time::sleep(Duration::from_millis(fault.duration_ms)).await;
+        }
+        if let Some(fault) = self.error.as_ref().filter(|fault| drawn(fault.percentage)) {
+            info!(message = %fault.message, "injecting an error");
+            return Err(InjectedFault {
+                message: fault.message.clone(),
+            }
+            .into());
+        }
+        let mut response = fut.await.map_err(Into::into)?;
+        if self.corruption.as_ref().map_or(false, |fault| drawn(fault.percentage)) {
+            info!("injecting a corrupted response");
+            response.corrupt(random());
+        }
+        Ok(response)
+    }
+}
+
+/// Error returned by the invocations that fail with an injected error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InjectedFault {
+    message: String,
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for InjectedFault {}
+
+/// Responses that a [`ChaosLayer`] can corrupt.
+///
+/// The default implementation leaves the response unchanged, so handlers that
+/// return their own types can use the other faults with an empty implementation.
+pub trait Corrupt {
+    /// Damage the response. `seed` is random, to vary the damage from one
+    /// invocation to the next.
+    fn corrupt(&mut self, seed: u64) {
+        let _ = seed;
+    }
+}
+
+impl Corrupt for () {}
+
+/// Replaces a leaf of the document with `null`, or a `null` leaf with a string.
+impl Corrupt for Value {
+    fn corrupt(&mut self, seed: u64) {
+        let mut value = self;
+        let mut seed = seed;
+        loop {
+            let len = match value {
+                Value::Array(items) => items.len(),
+                Value::Object(fields) => fields.len(),
+                _ => 0,
+            };
+            if len == 0 {
+                break;
+            }
+            let index = (seed % len as u64) as usize;
+            seed = seed.rotate_right(7);
+            value = match value {
+                Value::Array(items) => &mut items[index],
+                Value::Object(fields) => fields.values_mut().nth(index).expect("index within the object"),
+                _ => unreachable!(),
+            };
+        }
+        *value = match value {
+            Value::Null => Value::String("\u{fffd}".into()),
+            _ => Value::Null,
+        };
+    }
+}
+
+/// Truncates the string at a random character.
+impl Corrupt for String {
+    fn corrupt(&mut self, seed: u64) {
+        if self.is_empty() {
+            self.push('\u{fffd}');
+            return;
+        }
+        let mut len = (seed % self.len() as u64) as usize;
+        while !self.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.truncate(len);
+    }
+}
+
+/// Truncates the bytes at a random position.
+impl Corrupt for Vec<u8> {
+    fn corrupt(&mut self, seed: u64) {
+        if self.is_empty() {
+            self.push(0xff);
+            return;
+        }
+        self.truncate((seed % self.len() as u64) as usize);
+    }
+}
+
+/// Truncates the bytes at a random position.
+impl Corrupt for Bytes {
+    fn corrupt(&mut self, seed: u64) {
+        if self.is_empty() {
+            *self = Bytes::from_static(&[0xff]);
+            return;
+        }
+        self.truncate((seed % self.len() as u64) as usize);
+    }
+}
+
+// Random number from the randomly keyed hasher of the standard library.
+fn random() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+fn drawn(percentage: f64) -> bool {
+    percentage > 0.0 && (random() >> 11) as f64 / (1u64 << 53) as f64 * 100.0 < percentage
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[derive(Debug)]
+struct AppConfig {
+    client: lambda_runtime_api_client::Client,
+    path: String,
+    schedule: Mutex<Schedule>,
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[derive(Debug)]
+struct Schedule {
+    every: Duration,
+    next: Option<std::time::Instant>,
+}
+
+#[cfg(not(target_os = "wasi"))]
+impl AppConfig {
+    // Claim the next read of the configuration, if it's due.
+    fn due(&self) -> bool {
+        let now = std::time::Instant::now();
+        let mut schedule = self.schedule.lock().expect("appconfig schedule poisoned");
+        if schedule.next.map_or(false, |next| now < next) {
+            return false;
+        }
+        schedule.next = Some(now + schedule.every);
+        true
+    }
+
+    async fn read(&self) -> Result<Faults, Error> {
+        let req = lambda_runtime_api_client::build_request()
+            .uri(self.path.as_str())
+            .body(hyper::Body::empty())?;
+        let res = self.client.call(req).await?;
+        let status = res.status();
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        if !status.is_success() {
+            return Err(format!("AppConfig returned {status}: {}", String::from_utf8_lossy(&body)).into());
+        }
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    faults: Mutex<Arc<Faults>>,
+    #[cfg(not(target_os = "wasi"))]
+    appconfig: Option<AppConfig>,
+}
+
+impl Shared {
+    fn faults(&self) -> Arc<Faults> {
+        self.faults.lock().expect("chaos faults poisoned").clone()
+    }
+
+    #[cfg(not(target_os = "wasi"))]
+    fn refresh_due(&self) -> bool {
+        self.appconfig.as_ref().map_or(false, AppConfig::due)
+    }
+
+    #[cfg(target_os = "wasi")]
+    fn refresh_due(&self) -> bool {
+        false
+    }
+
+    #[cfg(not(target_os = "wasi"))]
+    async fn refresh(&self) -> Arc<Faults> {
+        let appconfig = match &self.appconfig {
+            Some(appconfig) => appconfig,
+            None => return self.faults(),
+        };
+        match appconfig.read().await {
+            Ok(faults) => {
+                let faults = Arc::new(faults);
+                *self.faults.lock().expect("chaos faults poisoned") = faults.clone();
+                faults
+            }
+            Err(err) => {
+                warn!(error = %err, "unable to read the faults from AppConfig, keeping the previous ones");
+                self.faults()
+            }
+        }
+    }
+
+    #[cfg(target_os = "wasi")]
+    async fn refresh(&self) -> Arc<Faults> {
+        self.faults()
+    }
+}
+
+/// A [`Layer`] that injects faults into a share of the invocations of its inner handler.
+///
+/// See the [module documentation](crate::chaos) for details.
+#[derive(Debug, Clone)]
+pub struct ChaosLayer {
+    shared: Arc<Shared>,
+}
+
+impl ChaosLayer {
+    /// Create a layer that injects `faults`.
+    pub fn new(faults: Faults) -> Self {
+        ChaosLayer {
+            shared: Arc::new(Shared {
+                faults: Mutex::new(Arc::new(faults)),
+                #[cfg(not(target_os = "wasi"))]
+                appconfig: None,
+            }),
+        }
+    }
+
+    /// Create a layer that injects the faults of the JSON document in
+    /// [`CHAOS_VAR`], or none.
+    ///
+    /// An invalid document is logged, and no fault is injected.
+    pub fn from_env() -> Self {
+        let faults = match env::var(CHAOS_VAR) {
+            Ok(json) if !json.trim().is_empty() => Faults::from_json(&json).unwrap_or_else(|err| {
+                warn!(error = %err, "invalid {CHAOS_VAR}, no fault will be injected");
+                Faults::default()
+            }),
+            _ => Faults::default(),
+        };
+        ChaosLayer::new(faults)
+    }
+
+    /// Create a layer that injects the faults of an AppConfig configuration
+    /// profile, read from the AppConfig Lambda extension.
+    ///
+    /// The profile is read on the first invocation, and again every
+    /// [`DEFAULT_REFRESH`]. The invocation that reads it waits for the extension.
+    /// No fault is injected until the profile has been read once.
+    #[cfg(not(target_os = "wasi"))]
+    pub fn appconfig(application: &str, environment: &str, configuration: &str) -> Self {
+        let port = env::var(APPCONFIG_PORT_VAR)
+            .ok()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(DEFAULT_APPCONFIG_PORT);
+        let endpoint = format!("http://localhost:{port}")
+            .parse()
+            .expect("valid AppConfig extension URI");
+        ChaosLayer::appconfig_at(
+            endpoint,
+            format!("/applications/{application}/environments/{environment}/configurations/{configuration}"),
+        )
+    }
+
+    #[cfg(not(target_os = "wasi"))]
+    fn appconfig_at(endpoint: http::Uri, path: String) -> Self {
+        let client = lambda_runtime_api_client::Client::builder()
+            .with_endpoint(endpoint)
+            .build()
+            .expect("unable to create the AppConfig client");
+        ChaosLayer {
+            shared: Arc::new(Shared {
+                faults: Mutex::new(Arc::new(Faults::default())),
+                appconfig: Some(AppConfig {
+                    client,
+                    path,
+                    schedule: Mutex::new(Schedule {
+                        every: DEFAULT_REFRESH,
+                        next: None,
+                    }),
+                }),
+            }),
+        }
+    }
+
+    /// Read the AppConfig profile every `refresh` instead of [`DEFAULT_REFRESH`].
+    ///
+    /// This has no effect on layers that don't read their faults from AppConfig.
+    pub fn refresh(self, refresh: Duration) -> Self {
+        #[cfg(not(target_os = "wasi"))]
+        if let Some(appconfig) = &self.shared.appconfig {
+            appconfig.schedule.lock().expect("appconfig schedule poisoned").every = refresh;
+        }
+        #[cfg(target_os = "wasi")]
+        let _ = refresh;
+        self
+    }
+
+    /// Return the faults that are injected.
+    pub fn faults(&self) -> Arc<Faults> {
+        self.shared.faults()
+    }
+}
+
+impl<S> Layer<S> for ChaosLayer {
+    type Service = Chaos<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Chaos {
+            inner,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that injects faults into a share of the invocations of its inner handler.
+///
+/// See [`ChaosLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct Chaos<S> {
+    inner: S,
+    shared: Arc<Shared>,
+}
+
+impl<S, R> Service<R> for Chaos<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+    S::Response: Corrupt + Send + 'static,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = Either<ErrInto<S::Future, Error>, BoxFuture<'static, Result<S::Response, Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let refresh = self.shared.refresh_due();
+        let faults = self.shared.faults();
+        if !refresh && !faults.is_active() {
+            return Either::Left(self.inner.call(req).err_into());
+        }
+
+        let shared = self.shared.clone();
+        let fut = self.inner.call(req);
+        Either::Right(Box::pin(async move {
+            let faults = if refresh { shared.refresh().await } else { faults };
+            faults.inject(fut).await
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_fn;
+    use serde_json::json;
+    use std::time::Instant;
+    use tower::ServiceExt;
+
+    async fn echo(value: Value) -> Result<Value, Error> {
+        Ok(value)
+    }
+
+    #[test]
+    fn parses_faults() {
+        let faults =
+            Faults::from_json(r#"{"latency": {"percentage": 10, "duration_ms": 200}, "error": {"percentage": 5}}"#)
+                .unwrap();
+        assert_eq!(
+            Faults::new()
+                .latency(10.0, Duration::from_millis(200))
+                .error(5.0, DEFAULT_ERROR_MESSAGE),
+            faults
+        );
+        assert!(faults.is_active());
+        assert!(!Faults::from_json("{}").unwrap().is_active());
+        assert!(!Faults::new().error(0.0, "never").is_active());
+        assert!(Faults::from_json(r#"{"error": {}}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn injects_faults() {
+        let layer = ChaosLayer::new(Faults::new().error(100.0, "injected"));
+        let err = layer.layer(service_fn(echo)).oneshot(json!(1)).await.unwrap_err();
+        assert_eq!("injected", err.to_string());
+        assert!(err.downcast_ref::<InjectedFault>().is_some());
+
+        let layer = ChaosLayer::new(Faults::new().latency(100.0, Duration::from_millis(50)));
+        let start = Instant::now();
+        let res = layer.layer(service_fn(echo)).oneshot(json!(1)).await.unwrap();
+        assert_eq!(json!(1), res);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let layer = ChaosLayer::new(Faults::new().corruption(100.0));
+        let res = layer
+            .layer(service_fn(echo))
+            .oneshot(json!({"order": {"id": 1}}))
+            .await
+            .unwrap();
+        assert_eq!(json!({"order": {"id": null}}), res);
+
+        let layer = ChaosLayer::new(Faults::new());
+        let res = layer.layer(service_fn(echo)).oneshot(json!(1)).await.unwrap();
+        assert_eq!(json!(1), res);
+    }
+
+    #[test]
+    fn corrupts_responses() {
+        let mut value = json!([1, {"a": null}]);
+        value.corrupt(1);
+        assert_eq!(json!([1, {"a": "\u{fffd}"}]), value);
+
+        let mut text = String::from("héllo");
+        text.corrupt(2);
+        assert_eq!("h", text);
+
+        let mut bytes = vec![1, 2, 3];
+        bytes.corrupt(5);
+        assert_eq!(vec![1, 2], bytes);
+    }
+
+    #[tokio::test]
+    async fn reads_faults_from_appconfig() {
+        use hyper::{server::conn::Http, service::service_fn as hyper_fn, Body, Response};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap()).parse().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(Http::new().serve_connection(
+                    stream,
+                    hyper_fn(|req: hyper::Request<Body>| async move {
+                        assert_eq!("/applications/app/environments/prod/configurations/chaos", req.uri());
+                        Ok::<_, Error>(Response::new(Body::from(r#"{"error": {"percentage": 100}}"#)))
+                    }),
+                ));
+            }
+        });
+
+        let layer = ChaosLayer::appconfig_at(
+            endpoint,
+            "/applications/app/environments/prod/configurations/chaos".into(),
+        );
+        let mut service = layer.layer(service_fn(echo));
+        let err = service.ready().await.unwrap().call(json!(1)).await.unwrap_err();
+        assert_eq!(DEFAULT_ERROR_MESSAGE, err.to_string());
+        assert!(layer.faults().is_active());
+    }
+}
@@ -0,0 +1,360 @@
+//! Jobs that take longer than the function's timeout can save their progress
+//! in a [`Checkpoint`] when the invocation's [`TimeBudget`] runs out, invoke
+//! the function again with [`reinvoke`], and resume from the checkpoint in the
+//! next invocation. Checkpoints are kept in a [`CheckpointStore`], usually
+//! backed by S3 or DynamoDB through the AWS SDK.
+use crate::{
+    invoke::{InvokeError, InvokeTransport, Invoker},
+    Context, Error, TimeBudget,
+};
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Storage for the checkpoints of jobs.
+///
+/// # Example
+/// ```ignore
+/// use aws_sdk_s3::primitives::ByteStream;
+/// use futures::future::BoxFuture;
+/// use lambda_runtime::{checkpoint::CheckpointStore, Error};
+///
+/// struct S3Store {
+///     client: aws_sdk_s3::Client,
+///     bucket: String,
+/// }
+///
+/// impl CheckpointStore for S3Store {
+///     fn load(&self, key: &str) -> BoxFuture<'_, Result<Option<Vec<u8>>, Error>> {
+///         let req = self.client.get_object().bucket(&self.bucket).key(key);
+///         Box::pin(async move {
+///             match req.send().await {
+///                 Ok(output) => Ok(Some(output.body.collect().await?.to_vec())),
+///                 Err(err) if err.as_service_error().map_or(false, |e| e.is_no_such_key()) => Ok(None),
+///                 Err(err) => Err(err.into()),
+///             }
+///         })
+///     }
+///
+///     fn save(&self, key: &str, checkpoint: Vec<u8>) -> BoxFuture<'_, Result<(), Error>> {
+///         let req = self.client.put_object().bucket(&self.bucket).key(key);
+///         Box::pin(async move {
+///             req.body(ByteStream::from(checkpoint)).send().await?;
+///             Ok(())
+///         })
+///     }
+///
+///     fn delete(&self, key: &str) -> BoxFuture<'_, Result<(), Error>> {
+///         let req = self.client.delete_object().bucket(&self.bucket).key(key);
+///         Box::pin(async move {
+///             req.send().await?;
+///             Ok(())
+///         })
+///     }
+/// }
+/// ```
+pub trait CheckpointStore {
+    /// Load the checkpoint saved with `key`, if there is one.
+    fn load(&self, key: &str) -> BoxFuture<'_, Result<Option<Vec<u8>>, Error>>;
+
+    /// Save the checkpoint with `key`, replacing the previous one.
+    fn save(&self, key: &str, checkpoint: Vec<u8>) -> BoxFuture<'_, Result<(), Error>>;
+
+    /// Delete the checkpoint saved with `key`.
+    fn delete(&self, key: &str) -> BoxFuture<'_, Result<(), Error>>;
+}
+
+/// [`CheckpointStore`] that keeps checkpoints in memory, for tests and local runs.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryStore {
+    checkpoints: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl InMemoryStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn checkpoints(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<u8>>> {
+        self.checkpoints.lock().expect("checkpoint store lock poisoned")
+    }
+}
+
+impl CheckpointStore for InMemoryStore {
+    fn load(&self, key: &str) -> BoxFuture<'_, Result<Option<Vec<u8>>, Error>> {
+        let checkpoint = self.checkpoints().get(key).cloned();
+        Box::pin(async move { Ok(checkpoint) })
+    }
+
+    fn save(&self, key: &str, checkpoint: Vec<u8>) -> BoxFuture<'_, Result<(), Error>> {
+        self.checkpoints().insert(key.to_string(), checkpoint);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn delete(&self, key: &str) -> BoxFuture<'_, Result<(), Error>> {
+        self.checkpoints().remove(key);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Error returned when a checkpoint can't be loaded or saved.
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// The state couldn't be serialized or deserialized.
+    Serde(serde_json::Error),
+    /// The store failed to load, save, or delete the checkpoint.
+    Store(Error),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Serde(err) => write!(f, "invalid checkpoint: {err}"),
+            CheckpointError::Store(err) => write!(f, "checkpoint store failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CheckpointError::Serde(err) => Some(err),
+            CheckpointError::Store(err) => Some(err.as_ref()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Saved<T> {
+    resumptions: u32,
+    state: T,
+}
+
+/// The state of a job that continues across invocations.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{
+///     checkpoint::{self, Checkpoint, CheckpointStore},
+///     invoke::{InvokeTransport, Invoker},
+///     Error, LambdaEvent,
+/// };
+/// use serde::{Deserialize, Serialize};
+/// use std::time::Duration;
+///
+/// #[derive(Default, Deserialize, Serialize)]
+/// struct Progress {
+///     next_page: u32,
+/// }
+///
+/// #[derive(Deserialize, Serialize)]
+/// struct Job {
+///     id: String,
+///     pages: u32,
+/// }
+///
+/// async fn export_page(_job: &Job, _page: u32) -> Result<(), Error> {
+///     # unimplemented!()
+/// }
+///
+/// async fn func<S: CheckpointStore, T: InvokeTransport>(
+///     store: S,
+///     invoker: &Invoker<T>,
+///     event: LambdaEvent<Job>,
+/// ) -> Result<(), Error> {
+///     let job = event.payload;
+///     let mut checkpoint = Checkpoint::<Progress, _>::load(store, &job.id).await?;
+///     // Keep 5 seconds to save the checkpoint and invoke the function again.
+///     let budget = event.context.time_budget().reserve(Duration::from_secs(5));
+///
+///     while checkpoint.state().next_page < job.pages {
+///         if checkpoint.save_if_exhausted(&budget).await? {
+///             checkpoint::reinvoke(invoker, &event.context, &job).await?;
+///             return Ok(());
+///         }
+///         export_page(&job, checkpoint.state().next_page).await?;
+///         checkpoint.state_mut().next_page += 1;
+///     }
+///     checkpoint.clear().await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Checkpoint<T, S> {
+    key: String,
+    store: S,
+    state: T,
+    resumptions: u32,
+    resumed: bool,
+}
+
+impl<T, S> Checkpoint<T, S>
+where
+    T: Serialize + DeserializeOwned + Default,
+    S: CheckpointStore,
+{
+    /// Load the checkpoint saved with `key`, or start from the default state.
+    pub async fn load(store: S, key: impl Into<String>) -> Result<Self, CheckpointError> {
+        let key = key.into();
+        let saved = store.load(&key).await.map_err(CheckpointError::Store)?;
+        let (state, resumptions, resumed) = match saved {
+            Some(bytes) => {
+                let saved: Saved<T> = serde_json::from_slice(&bytes).map_err(CheckpointError::Serde)?;
+                (saved.state, saved.resumptions, true)
+            }
+            None => (T::default(), 0, false),
+        };
+        Ok(Checkpoint {
+            key,
+            store,
+            state,
+            resumptions,
+            resumed,
+        })
+    }
+
+    /// The key of the checkpoint in the store.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The state of the job.
+    pub fn state(&self) -> &T {
+        &self.state
+    }
+
+    /// The state of the job, to record progress.
+    pub fn state_mut(&mut self) -> &mut T {
+        &mut self.state
+    }
+
+    /// Whether the state was loaded from a previous invocation.
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// The number of previous invocations that saved the job to continue it
+    /// in another invocation. Use it to stop jobs that never finish.
+    pub fn resumptions(&self) -> u32 {
+        self.resumptions
+    }
+
+    /// Save the state, to resume the job in another invocation.
+    pub async fn save(&self) -> Result<(), CheckpointError> {
+        let saved = Saved {
+            resumptions: self.resumptions + 1,
+            state: &self.state,
+        };
+        let bytes = serde_json::to_vec(&saved).map_err(CheckpointError::Serde)?;
+        self.store.save(&self.key, bytes).await.map_err(CheckpointError::Store)
+    }
+
+    /// Save the state if `budget` has no time left. Returns whether the
+    /// state was saved, and the invocation should stop.
+    pub async fn save_if_exhausted(&self, budget: &TimeBudget) -> Result<bool, CheckpointError> {
+        if !budget.is_exhausted() {
+            return Ok(false);
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// Delete the checkpoint once the job is complete.
+    pub async fn clear(self) -> Result<T, CheckpointError> {
+        self.store.delete(&self.key).await.map_err(CheckpointError::Store)?;
+        Ok(self.state)
+    }
+}
+
+/// Invoke the current function again, asynchronously, with `payload`, to
+/// continue a job from its checkpoint.
+pub async fn reinvoke<T, P>(invoker: &Invoker<T>, ctx: &Context, payload: &P) -> Result<(), InvokeError>
+where
+    T: InvokeTransport,
+    P: Serialize,
+{
+    invoker.invoke_async(ctx, &ctx.invoked_function_arn, payload).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invoke::{InvocationType, InvokeRequest, InvokeResponse};
+    use std::time::{Duration, SystemTime};
+
+    #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+    struct Progress {
+        next: u32,
+    }
+
+    #[tokio::test]
+    async fn resumes_from_the_saved_state() {
+        let store = InMemoryStore::new();
+
+        let mut checkpoint = Checkpoint::<Progress, _>::load(store.clone(), "job-1").await.unwrap();
+        assert!(!checkpoint.is_resumed());
+        checkpoint.state_mut().next = 3;
+        checkpoint.save().await.unwrap();
+
+        let checkpoint = Checkpoint::<Progress, _>::load(store.clone(), "job-1").await.unwrap();
+        assert!(checkpoint.is_resumed());
+        assert_eq!(1, checkpoint.resumptions());
+        assert_eq!(Progress { next: 3 }, *checkpoint.state());
+
+        assert_eq!(Progress { next: 3 }, checkpoint.clear().await.unwrap());
+        let checkpoint = Checkpoint::<Progress, _>::load(store, "job-1").await.unwrap();
+        assert!(!checkpoint.is_resumed());
+    }
+
+    #[tokio::test]
+    async fn saves_when_the_budget_is_exhausted() {
+        let store = InMemoryStore::new();
+        let checkpoint = Checkpoint::<Progress, _>::load(store.clone(), "job-1").await.unwrap();
+
+        let budget = TimeBudget::new(SystemTime::now() + Duration::from_secs(60));
+        assert!(!checkpoint.save_if_exhausted(&budget).await.unwrap());
+        assert!(store.load("job-1").await.unwrap().is_none());
+
+        let budget = TimeBudget::new(SystemTime::UNIX_EPOCH);
+        assert!(checkpoint.save_if_exhausted(&budget).await.unwrap());
+        assert!(store.load("job-1").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn reinvokes_the_current_function() {
+        #[derive(Default, Clone)]
+        struct MockTransport(Arc<Mutex<Vec<InvokeRequest>>>);
+
+        impl InvokeTransport for MockTransport {
+            fn invoke(&self, req: InvokeRequest) -> BoxFuture<'_, Result<InvokeResponse, Error>> {
+                self.0.lock().unwrap().push(req);
+                Box::pin(async {
+                    Ok(InvokeResponse {
+                        status_code: 202,
+                        ..Default::default()
+                    })
+                })
+            }
+        }
+
+        let transport = MockTransport::default();
+        let invoker = Invoker::new(transport.clone());
+        let ctx = Context {
+            invoked_function_arn: "arn:aws:lambda:us-east-1:123456789012:function:export:live".into(),
+            ..Default::default()
+        };
+        reinvoke(&invoker, &ctx, &serde_json::json!({"id": "job-1"}))
+            .await
+            .unwrap();
+
+        let requests = transport.0.lock().unwrap();
+        assert_eq!(ctx.invoked_function_arn, requests[0].function_name);
+        assert_eq!(InvocationType::Event, requests[0].invocation_type);
+        assert_eq!(br#"{"id":"job-1"}"#.to_vec(), requests[0].payload);
+    }
+}
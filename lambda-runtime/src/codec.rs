@@ -0,0 +1,157 @@
+//! The Runtime API frames every event and response as an HTTP body. A [`Codec`]
+//! turns the bytes of those bodies into the payload type of the function, and its
+//! responses back into bytes. The default [`JsonCodec`] reads and writes JSON.
+//!
+//! Functions that are only invoked by other functions can use their own codec,
+//! for example to exchange CBOR or MessagePack documents. The Invoke API still
+//! requires payloads to be JSON, so those codecs usually wrap their documents in
+//! a JSON string.
+//!
+//! # Example
+//! ```ignore
+//! use base64::Engine;
+//! use lambda_runtime::{codec::Codec, service_fn, Error, LambdaEvent, RuntimeBuilder};
+//! use serde::{de::DeserializeOwned, Serialize};
+//!
+//! /// MessagePack documents sent as base64 encoded JSON strings.
+//! struct MessagePackCodec;
+//!
+//! impl Codec for MessagePackCodec {
+//!     fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T, Error> {
+//!         let encoded: String = serde_json::from_slice(body)?;
+//!         let document = base64::engine::general_purpose::STANDARD.decode(encoded)?;
+//!         Ok(rmp_serde::from_slice(&document)?)
+//!     }
+//!
+//!     fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+//!         let document = rmp_serde::to_vec_named(value)?;
+//!         let encoded = base64::engine::general_purpose::STANDARD.encode(document);
+//!         Ok(serde_json::to_vec(&encoded)?)
+//!     }
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     RuntimeBuilder::new()
+//!         .with_codec(MessagePackCodec)
+//!         .run(service_fn(|event: LambdaEvent<Vec<u32>>| async move {
+//!             Ok::<_, Error>(event.payload.iter().sum::<u32>())
+//!         }))
+//!         .await
+//! }
+//! ```
+use crate::{deserializer, json::JsonPolicy, serializer, Error};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Converts the bodies of events and responses from and to the types used by the function.
+pub trait Codec {
+    /// Decode the body of an event into the payload type of the function.
+    fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T, Error>;
+
+    /// Encode a response of the function into the body sent to the Runtime API.
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error>;
+}
+
+/// Codec that reads and writes JSON documents.
+///
+/// Errors report the path of the value that couldn't be decoded or encoded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec {
+    policy: JsonPolicy,
+}
+
+impl JsonCodec {
+    /// Create a codec with the default [`JsonPolicy`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the policy to encode responses.
+    pub fn with_policy(self, policy: JsonPolicy) -> Self {
+        JsonCodec { policy }
+    }
+}
+
+impl Codec for JsonCodec {
+    fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T, Error> {
+        Ok(deserializer::deserialize(body)?)
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        Ok(serializer::serialize(value, self.policy)?)
+    }
+}
+
+/// Codec that reads and writes JSON documents with [simd-json](https://docs.rs/simd-json).
+///
+/// It's usually faster than [`JsonCodec`] on large documents, but its errors don't
+/// report paths, and it doesn't apply a [`JsonPolicy`]. Compare both on the events
+/// of a function with `CompareCodec`, from the `codec_compare` feature, before
+/// switching.
+#[cfg(feature = "simd_json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimdJsonCodec;
+
+#[cfg(feature = "simd_json")]
+impl Codec for SimdJsonCodec {
+    fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T, Error> {
+        // simd-json parses in place, so it needs its own copy of the body.
+        let mut body = body.to_vec();
+        Ok(simd_json::serde::from_slice(&mut body)?)
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        Ok(simd_json::serde::to_vec(value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    // Documents sent as JSON strings, like the codecs for binary formats do.
+    struct StringCodec;
+
+    impl Codec for StringCodec {
+        fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T, Error> {
+            let document: String = serde_json::from_slice(body)?;
+            Ok(serde_json::from_str(&document)?)
+        }
+
+        fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+            let document = serde_json::to_string(value)?;
+            Ok(serde_json::to_vec(&document)?)
+        }
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[test]
+    fn custom_codecs_frame_documents() {
+        let point: Point = StringCodec.decode(br#""{\"x\":1,\"y\":2}""#).unwrap();
+        assert_eq!(Point { x: 1, y: 2 }, point);
+        assert_eq!(br#""{\"x\":1,\"y\":2}""#.to_vec(), StringCodec.encode(&point).unwrap());
+    }
+
+    #[test]
+    fn json_codec_reports_paths() {
+        let err = JsonCodec::new().decode::<Point>(br#"{"x":1,"y":"2"}"#).unwrap_err();
+        assert!(err.to_string().contains("[y]"), "{err}");
+    }
+
+    #[test]
+    #[cfg(feature = "simd_json")]
+    fn simd_json_codec_round_trips() {
+        let point: Point = SimdJsonCodec.decode(br#"{"x":1,"y":2}"#).unwrap();
+        assert_eq!(Point { x: 1, y: 2 }, point);
+        assert_eq!(
+            JsonCodec::new().encode(&point).unwrap(),
+            SimdJsonCodec.encode(&point).unwrap()
+        );
+    }
+}
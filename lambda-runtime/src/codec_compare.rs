@@ -0,0 +1,366 @@
+//! An experiment to choose the codec of a function from its own events.
+//!
+//! [`CompareCodec`] decodes events and encodes responses with a primary codec,
+//! like [`JsonCodec`](crate::codec::JsonCodec). On sampled invocations it also
+//! runs a candidate codec, like `SimdJsonCodec` with the `simd_json` feature, on
+//! the same bodies and values, and reports the time that both took to a
+//! [`CodecRecorder`]. The function only ever uses the results of the primary
+//! codec, so the candidate can't change its behavior, but sampled invocations pay
+//! for both codecs. Without sampling, the only cost is an atomic counter.
+//!
+//! # Example
+//! ```ignore
+//! use lambda_runtime::{
+//!     codec::{JsonCodec, SimdJsonCodec},
+//!     codec_compare::{CompareCodec, EmfCodecRecorder},
+//!     service_fn, Error, LambdaEvent, RuntimeBuilder,
+//! };
+//! use serde_json::Value;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     // Compare the codecs on one invocation out of 100.
+//!     let codec = CompareCodec::new(JsonCodec::new(), SimdJsonCodec)
+//!         .sample_every(100)
+//!         .with_recorder(EmfCodecRecorder::new("MyApp/Codecs"));
+//!
+//!     RuntimeBuilder::new()
+//!         .with_codec(codec)
+//!         .run(service_fn(func))
+//!         .await
+//! }
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//! ```
+use crate::{codec::Codec, Error};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    env, fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The operation of a codec measured by a [`CompareCodec`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecOperation {
+    /// Decoding the body of an event.
+    Decode,
+    /// Encoding the response of the function.
+    Encode,
+}
+
+impl fmt::Display for CodecOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecOperation::Decode => f.write_str("Decode"),
+            CodecOperation::Encode => f.write_str("Encode"),
+        }
+    }
+}
+
+/// The time that two codecs took on the same body or value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CodecTimings {
+    /// The measured operation.
+    pub operation: CodecOperation,
+    /// The size of the body that was decoded, or of the body encoded by the primary codec.
+    pub bytes: usize,
+    /// The time that the primary codec took.
+    pub primary: Duration,
+    /// The time that the candidate codec took.
+    pub candidate: Duration,
+    /// Whether the candidate codec returned an error where the primary one didn't.
+    pub candidate_failed: bool,
+    /// Whether both codecs encoded the same bytes. Always `true` for decoding.
+    pub same_output: bool,
+}
+
+impl CodecTimings {
+    /// How many times faster the candidate codec was than the primary one.
+    pub fn speedup(&self) -> f64 {
+        self.primary.as_secs_f64() / self.candidate.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Destination of the timings of the sampled invocations of a [`CompareCodec`].
+///
+/// Closures that take `&CodecTimings` are recorders.
+pub trait CodecRecorder: Send + Sync {
+    /// Record the timings of one operation.
+    fn record(&self, timings: &CodecTimings);
+}
+
+impl<F> CodecRecorder for F
+where
+    F: Fn(&CodecTimings) + Send + Sync,
+{
+    fn record(&self, timings: &CodecTimings) {
+        self(timings)
+    }
+}
+
+/// A [`Codec`] that compares a candidate codec with the primary one on sampled invocations.
+///
+/// See the [module documentation](self) for details.
+pub struct CompareCodec<P, C> {
+    primary: P,
+    candidate: C,
+    every: u64,
+    count: AtomicU64,
+    recorder: Arc<dyn CodecRecorder>,
+}
+
+impl<P, C> CompareCodec<P, C> {
+    /// Compare `candidate` with `primary` on every invocation, and print the timings
+    /// in the Embedded Metric Format in the `LambdaRuntime/Codecs` namespace.
+    pub fn new(primary: P, candidate: C) -> Self {
+        CompareCodec {
+            primary,
+            candidate,
+            every: 1,
+            count: AtomicU64::new(0),
+            recorder: Arc::new(EmfCodecRecorder::new("LambdaRuntime/Codecs")),
+        }
+    }
+
+    /// Compare the codecs on one operation out of `every`. `0` never compares them.
+    pub fn sample_every(self, every: u64) -> Self {
+        CompareCodec { every, ..self }
+    }
+
+    /// Report the timings to `recorder`.
+    pub fn with_recorder(self, recorder: impl CodecRecorder + 'static) -> Self {
+        CompareCodec {
+            recorder: Arc::new(recorder),
+            ..self
+        }
+    }
+
+    fn sampled(&self) -> bool {
+        self.count.fetch_add(1, Ordering::Relaxed).checked_rem(self.every) == Some(0)
+    }
+}
+
+impl<P: fmt::Debug, C: fmt::Debug> fmt::Debug for CompareCodec<P, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompareCodec")
+            .field("primary", &self.primary)
+            .field("candidate", &self.candidate)
+            .field("every", &self.every)
+            .finish()
+    }
+}
+
+impl<P: Codec, C: Codec> Codec for CompareCodec<P, C> {
+    fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T, Error> {
+        if !self.sampled() {
+            return self.primary.decode(body);
+        }
+        let start = Instant::now();
+        let value = self.primary.decode(body)?;
+        let primary = start.elapsed();
+
+        let start = Instant::now();
+        let candidate_failed = self.candidate.decode::<T>(body).is_err();
+        let candidate = start.elapsed();
+
+        self.recorder.record(&CodecTimings {
+            operation: CodecOperation::Decode,
+            bytes: body.len(),
+            primary,
+            candidate,
+            candidate_failed,
+            same_output: true,
+        });
+        Ok(value)
+    }
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        if !self.sampled() {
+            return self.primary.encode(value);
+        }
+        let start = Instant::now();
+        let body = self.primary.encode(value)?;
+        let primary = start.elapsed();
+
+        let start = Instant::now();
+        let other = self.candidate.encode(value);
+        let candidate = start.elapsed();
+
+        self.recorder.record(&CodecTimings {
+            operation: CodecOperation::Encode,
+            bytes: body.len(),
+            primary,
+            candidate,
+            candidate_failed: other.is_err(),
+            same_output: other.map_or(false, |other| other == body),
+        });
+        Ok(body)
+    }
+}
+
+/// Recorder that prints the timings in the
+/// [CloudWatch Embedded Metric Format](https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html).
+///
+/// Metrics are published with the function name and the operation as dimensions.
+#[derive(Debug, Clone)]
+pub struct EmfCodecRecorder {
+    namespace: String,
+    function_name: String,
+}
+
+const METRICS: [(&str, &str); 4] = [
+    ("PrimaryTime", "Microseconds"),
+    ("CandidateTime", "Microseconds"),
+    ("Speedup", "None"),
+    ("Bytes", "Bytes"),
+];
+
+impl EmfCodecRecorder {
+    /// Create a recorder that publishes metrics in `namespace`, for the function
+    /// described by the Lambda environment variables.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        EmfCodecRecorder {
+            namespace: namespace.into(),
+            function_name: env::var("AWS_LAMBDA_FUNCTION_NAME").unwrap_or_default(),
+        }
+    }
+
+    /// Format the timings of an operation as an EMF document.
+    pub fn format(&self, timings: &CodecTimings) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let values = [
+            Value::from(timings.primary.as_micros() as u64),
+            Value::from(timings.candidate.as_micros() as u64),
+            Value::from(timings.speedup()),
+            Value::from(timings.bytes),
+        ];
+
+        let mut document = Map::new();
+        document.insert(
+            "_aws".into(),
+            json!({
+                "Timestamp": timestamp,
+                "CloudWatchMetrics": [{
+                    "Namespace": self.namespace,
+                    "Dimensions": [["FunctionName", "Operation"]],
+                    "Metrics": METRICS
+                        .iter()
+                        .map(|(name, unit)| json!({ "Name": name, "Unit": unit }))
+                        .collect::<Vec<_>>(),
+                }],
+            }),
+        );
+        document.insert("FunctionName".into(), self.function_name.clone().into());
+        document.insert("Operation".into(), timings.operation.to_string().into());
+        document.insert("CandidateFailed".into(), timings.candidate_failed.into());
+        document.insert("SameOutput".into(), timings.same_output.into());
+        for ((name, _), value) in METRICS.iter().zip(values) {
+            document.insert((*name).into(), value);
+        }
+        Value::Object(document).to_string()
+    }
+}
+
+impl CodecRecorder for EmfCodecRecorder {
+    fn record(&self, timings: &CodecTimings) {
+        println!("{}", self.format(timings));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::JsonCodec;
+    use std::sync::Mutex;
+
+    // Codec that only reads and writes JSON strings.
+    #[derive(Debug)]
+    struct StringsOnly;
+
+    impl Codec for StringsOnly {
+        fn decode<T: DeserializeOwned>(&self, body: &[u8]) -> Result<T, Error> {
+            match body.first() {
+                Some(b'"') => Ok(serde_json::from_slice(body)?),
+                _ => Err("not a string".into()),
+            }
+        }
+
+        fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+            Ok(serde_json::to_vec(&serde_json::to_string(value)?)?)
+        }
+    }
+
+    fn recorded() -> (Arc<Mutex<Vec<CodecTimings>>>, impl CodecRecorder) {
+        let timings = Arc::new(Mutex::new(Vec::new()));
+        let recorder = {
+            let timings = timings.clone();
+            move |t: &CodecTimings| timings.lock().unwrap().push(t.clone())
+        };
+        (timings, recorder)
+    }
+
+    #[test]
+    fn compares_sampled_operations() {
+        let (timings, recorder) = recorded();
+        let codec = CompareCodec::new(JsonCodec::new(), StringsOnly)
+            .sample_every(2)
+            .with_recorder(recorder);
+
+        for _ in 0..4 {
+            let value: Value = codec.decode(br#"{"a":1}"#).unwrap();
+            assert_eq!(json!({"a": 1}), value);
+        }
+        assert_eq!(br#"{"a":1}"#.to_vec(), codec.encode(&json!({"a": 1})).unwrap());
+
+        let timings = timings.lock().unwrap();
+        assert_eq!(3, timings.len());
+        assert!(timings[..2]
+            .iter()
+            .all(|t| t.operation == CodecOperation::Decode && t.candidate_failed && t.bytes == 7));
+        assert_eq!(CodecOperation::Encode, timings[2].operation);
+        assert!(!timings[2].candidate_failed);
+        assert!(!timings[2].same_output);
+    }
+
+    #[test]
+    fn never_compares_with_a_zero_rate() {
+        let (timings, recorder) = recorded();
+        let codec = CompareCodec::new(JsonCodec::new(), StringsOnly)
+            .sample_every(0)
+            .with_recorder(recorder);
+        let _: Value = codec.decode(b"1").unwrap();
+        codec.encode(&1).unwrap();
+        assert!(timings.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn formats_emf_documents() {
+        let recorder = EmfCodecRecorder::new("Test/Codecs");
+        let timings = CodecTimings {
+            operation: CodecOperation::Encode,
+            bytes: 128,
+            primary: Duration::from_micros(40),
+            candidate: Duration::from_micros(20),
+            candidate_failed: false,
+            same_output: true,
+        };
+        let document: Value = serde_json::from_str(&recorder.format(&timings)).unwrap();
+        assert_eq!("Test/Codecs", document["_aws"]["CloudWatchMetrics"][0]["Namespace"]);
+        assert_eq!("Encode", document["Operation"]);
+        assert_eq!(40, document["PrimaryTime"]);
+        assert_eq!(20, document["CandidateTime"]);
+        assert_eq!(2.0, document["Speedup"]);
+        assert_eq!(true, document["SameOutput"]);
+    }
+}
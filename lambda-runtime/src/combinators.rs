@@ -0,0 +1,355 @@
+use crate::{Context, LambdaEvent};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{self, Poll},
+};
+use tower::{
+    util::{MapErr, MapResponse},
+    Service,
+};
+
+/// An invocation of a handler that carries the Lambda [`Context`].
+///
+/// Implemented for [`LambdaEvent`], and for HTTP requests that keep the
+/// context in their extensions, like the requests of `lambda_http`.
+pub trait Invocation {
+    /// Return the context of the invocation, if it has one.
+    fn context(&self) -> Option<&Context>;
+
+    /// Replace the context of the invocation.
+    fn set_context(&mut self, context: Context);
+}
+
+impl<A> Invocation for LambdaEvent<A> {
+    fn context(&self) -> Option<&Context> {
+        Some(&self.context)
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.context = context;
+    }
+}
+
+impl<B> Invocation for http::Request<B> {
+    fn context(&self) -> Option<&Context> {
+        self.extensions().get::<Context>()
+    }
+
+    fn set_context(&mut self, context: Context) {
+        self.extensions_mut().insert(context);
+    }
+}
+
+/// Combinators to compose handlers without implementing [`Service`] by hand.
+///
+/// They mirror the ones of [`tower::ServiceExt`], specialized for handlers:
+/// the [`Context`] of the invocation is never lost or replaced on the way, and
+/// the combinators that run after the handler receive it. Their methods have
+/// the same names as the ones of `ServiceExt`, so only one of the two traits can
+/// be imported in a module.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, Error, HandlerExt, LambdaEvent};
+/// use serde_json::{json, Value};
+///
+/// async fn greet(event: LambdaEvent<String>) -> Result<String, Error> {
+///     Ok(format!("hello {}", event.payload))
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let func = service_fn(greet)
+///         .map_request(|event: LambdaEvent<Value>| {
+///             let name = event.payload["name"].as_str().unwrap_or("stranger").to_string();
+///             LambdaEvent::new(name, event.context)
+///         })
+///         .map_response(|greeting| json!({ "greeting": greeting }))
+///         .or_else(|err, ctx| async move {
+///             Ok(json!({ "error": err.to_string(), "request_id": ctx.request_id }))
+///         });
+///     lambda_runtime::run(func).await
+/// }
+/// ```
+pub trait HandlerExt<R: Invocation>: Service<R> + Sized {
+    /// Convert the invocations with `f` before they reach the handler.
+    ///
+    /// The context of the original invocation is restored on the converted one.
+    fn map_request<R0, F>(self, f: F) -> MapRequest<Self, F>
+    where
+        R0: Invocation,
+        F: FnMut(R0) -> R,
+    {
+        MapRequest { inner: self, f }
+    }
+
+    /// Convert the responses of the handler with `f`.
+    fn map_response<F, B>(self, f: F) -> MapResponse<Self, F>
+    where
+        F: FnOnce(Self::Response) -> B + Clone,
+    {
+        MapResponse::new(self, f)
+    }
+
+    /// Convert the errors of the handler with `f`.
+    fn map_err<F, E>(self, f: F) -> MapErr<Self, F>
+    where
+        F: FnOnce(Self::Error) -> E + Clone,
+    {
+        MapErr::new(self, f)
+    }
+
+    /// Run `f` with the response of the handler and the context of the invocation,
+    /// when the handler succeeds.
+    fn and_then<F, Fut, B>(self, f: F) -> AndThen<Self, F>
+    where
+        F: FnOnce(Self::Response, Context) -> Fut + Clone,
+        Fut: Future<Output = Result<B, Self::Error>>,
+    {
+        AndThen { inner: self, f }
+    }
+
+    /// Run `fallback` with the error of the handler and the context of the invocation,
+    /// when the handler fails.
+    fn or_else<F, Fut>(self, fallback: F) -> OrElse<Self, F>
+    where
+        F: FnOnce(Self::Error, Context) -> Fut + Clone,
+        Fut: Future<Output = Result<Self::Response, Self::Error>>,
+    {
+        OrElse {
+            inner: self,
+            f: fallback,
+        }
+    }
+}
+
+impl<S, R> HandlerExt<R> for S
+where
+    S: Service<R>,
+    R: Invocation,
+{
+}
+
+/// Handler returned by [`HandlerExt::map_request`].
+#[derive(Debug, Clone)]
+pub struct MapRequest<S, F> {
+    inner: S,
+    f: F,
+}
+
+impl<S, F, R0, R> Service<R0> for MapRequest<S, F>
+where
+    S: Service<R>,
+    F: FnMut(R0) -> R,
+    R0: Invocation,
+    R: Invocation,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R0) -> Self::Future {
+        let context = req.context().cloned();
+        let mut req = (self.f)(req);
+        if let Some(context) = context {
+            req.set_context(context);
+        }
+        self.inner.call(req)
+    }
+}
+
+/// Handler returned by [`HandlerExt::and_then`].
+#[derive(Debug, Clone)]
+pub struct AndThen<S, F> {
+    inner: S,
+    f: F,
+}
+
+impl<S, F, Fut, R, B> Service<R> for AndThen<S, F>
+where
+    S: Service<R>,
+    F: FnOnce(S::Response, Context) -> Fut + Clone,
+    Fut: Future<Output = Result<B, S::Error>>,
+    R: Invocation,
+{
+    type Response = B;
+    type Error = S::Error;
+    type Future = AndThenFuture<S::Future, F, Fut>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let context = req.context().cloned().unwrap_or_default();
+        AndThenFuture {
+            state: State::First(Box::pin(self.inner.call(req)), Some((self.f.clone(), context))),
+        }
+    }
+}
+
+/// Handler returned by [`HandlerExt::or_else`].
+#[derive(Debug, Clone)]
+pub struct OrElse<S, F> {
+    inner: S,
+    f: F,
+}
+
+impl<S, F, Fut, R> Service<R> for OrElse<S, F>
+where
+    S: Service<R>,
+    F: FnOnce(S::Error, Context) -> Fut + Clone,
+    Fut: Future<Output = Result<S::Response, S::Error>>,
+    R: Invocation,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = OrElseFuture<S::Future, F, Fut>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let context = req.context().cloned().unwrap_or_default();
+        OrElseFuture {
+            state: State::First(Box::pin(self.inner.call(req)), Some((self.f.clone(), context))),
+        }
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+enum State<F1, F, F2> {
+    First(Pin<Box<F1>>, Option<(F, Context)>),
+    Second(Pin<Box<F2>>),
+    Done,
+}
+
+// The futures are boxed, and the closure is never pinned, it's moved out before it's called.
+impl<F1, F, F2> Unpin for State<F1, F, F2> {}
+
+/// Future returned by [`AndThen`].
+pub struct AndThenFuture<F1, F, F2> {
+    state: State<F1, F, F2>,
+}
+
+impl<F1, F, F2, T, B, E> Future for AndThenFuture<F1, F, F2>
+where
+    F1: Future<Output = Result<T, E>>,
+    F: FnOnce(T, Context) -> F2,
+    F2: Future<Output = Result<B, E>>,
+{
+    type Output = Result<B, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match &mut self.state {
+                State::First(first, next) => match first.as_mut().poll(cx) {
+                    Poll::Ready(Ok(response)) => {
+                        let (f, context) = next.take().expect("polled after completion");
+                        self.state = State::Second(Box::pin(f(response, context)));
+                    }
+                    Poll::Ready(Err(err)) => {
+                        self.state = State::Done;
+                        return Poll::Ready(Err(err));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                State::Second(second) => return second.as_mut().poll(cx),
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+/// Future returned by [`OrElse`].
+pub struct OrElseFuture<F1, F, F2> {
+    state: State<F1, F, F2>,
+}
+
+impl<F1, F, F2, T, E> Future for OrElseFuture<F1, F, F2>
+where
+    F1: Future<Output = Result<T, E>>,
+    F: FnOnce(E, Context) -> F2,
+    F2: Future<Output = Result<T, E>>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match &mut self.state {
+                State::First(first, next) => match first.as_mut().poll(cx) {
+                    Poll::Ready(Ok(response)) => {
+                        self.state = State::Done;
+                        return Poll::Ready(Ok(response));
+                    }
+                    Poll::Ready(Err(err)) => {
+                        let (f, context) = next.take().expect("polled after completion");
+                        self.state = State::Second(Box::pin(f(err, context)));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                State::Second(second) => return second.as_mut().poll(cx),
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, Error};
+
+    fn event(payload: &str) -> LambdaEvent<String> {
+        let context = Context {
+            request_id: "req-1".into(),
+            ..Default::default()
+        };
+        LambdaEvent::new(payload.to_string(), context)
+    }
+
+    #[tokio::test]
+    async fn map_request_keeps_the_context() {
+        let handler = service_fn(|event: LambdaEvent<usize>| async move {
+            Ok::<_, Error>(format!("{} {}", event.payload, event.context.request_id))
+        });
+        let mut handler =
+            handler.map_request(|event: LambdaEvent<String>| LambdaEvent::new(event.payload.len(), Context::default()));
+
+        let res = handler.call(event("four")).await.unwrap();
+        assert_eq!("4 req-1", res);
+
+        let handler = service_fn(|req: http::Request<()>| async move {
+            Ok::<_, Error>(req.context().map(|ctx| ctx.request_id.clone()))
+        });
+        let mut handler = handler.map_request(|_req: http::Request<String>| http::Request::new(()));
+        let mut req = http::Request::new(String::new());
+        req.set_context(event("").context);
+        let res = handler.call(req).await.unwrap();
+        assert_eq!(Some("req-1".to_string()), res);
+    }
+
+    #[tokio::test]
+    async fn and_then_and_or_else_receive_the_context() {
+        let handler = service_fn(|event: LambdaEvent<String>| async move {
+            match event.payload.as_str() {
+                "fail" => Err(Error::from("boom")),
+                payload => Ok(payload.to_uppercase()),
+            }
+        });
+        let mut handler = handler
+            .and_then(|res: String, ctx: Context| async move { Ok(format!("{res} from {}", ctx.request_id)) })
+            .or_else(|err: Error, ctx: Context| async move { Ok(format!("{err} in {}", ctx.request_id)) });
+
+        let res = handler.call(event("ok")).await.unwrap();
+        assert_eq!("OK from req-1", res);
+        let res = handler.call(event("fail")).await.unwrap();
+        assert_eq!("boom in req-1", res);
+    }
+}
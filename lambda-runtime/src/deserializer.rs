@@ -32,12 +32,23 @@ impl Error for DeserializeError {
 }
 
 /// Deserialize the data sent to the function into the type that the function receives.
-pub(crate) fn deserialize<T>(body: &[u8], context: Context) -> Result<LambdaEvent<T>, DeserializeError>
+pub(crate) fn deserialize<T>(body: &[u8]) -> Result<T, DeserializeError>
 where
     T: for<'de> Deserialize<'de>,
 {
     let jd = &mut serde_json::Deserializer::from_slice(body);
-    serde_path_to_error::deserialize(jd)
+    serde_path_to_error::deserialize(jd).map_err(|inner| DeserializeError { inner })
+}
+
+/// Deserialize a JSON value into the type that the function receives.
+pub(crate) fn deserialize_value<T>(
+    value: serde_json::Value,
+    context: Context,
+) -> Result<LambdaEvent<T>, DeserializeError>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    serde_path_to_error::deserialize(value)
         .map(|payload| LambdaEvent::new(payload, context))
         .map_err(|inner| DeserializeError { inner })
 }
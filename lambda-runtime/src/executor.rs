@@ -0,0 +1,111 @@
+//! The runtime doesn't depend on a specific async runtime to spawn tasks and to
+//! wait for timers. It uses an [`Executor`], which is backed by tokio by default.
+//! Functions that use a different async runtime, like async-std, smol, or a custom
+//! single-threaded executor, can provide their own implementation to
+//! [`run_with_executor`](crate::run_with_executor), together with a connector for
+//! the Runtime API built on top of that runtime's network primitives.
+use futures::future::BoxFuture;
+use std::{sync::Arc, time::Duration};
+
+/// Spawns tasks and creates timers for the runtime.
+pub trait Executor: Send + Sync {
+    /// Run `future` in the background.
+    fn spawn(&self, future: BoxFuture<'static, ()>);
+
+    /// Return a future that completes after `duration`.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// [`Executor`] backed by the tokio runtime that the function is running on.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioExecutor;
+
+impl Executor for TokioExecutor {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        tokio::spawn(future);
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+impl<E: Executor + ?Sized> Executor for Arc<E> {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        (**self).spawn(future)
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        (**self).sleep(duration)
+    }
+}
+
+/// Shared handle to the executor of a runtime.
+pub(crate) type SharedExecutor = Arc<dyn Executor>;
+
+/// Adapter to spawn the HTTP client's connection tasks on the runtime's executor.
+#[cfg(not(target_os = "wasi"))]
+#[derive(Clone)]
+pub(crate) struct HyperExecutor(pub(crate) SharedExecutor);
+
+#[cfg(not(target_os = "wasi"))]
+impl<F> hyper::rt::Executor<F> for HyperExecutor
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    fn execute(&self, future: F) {
+        self.0.spawn(Box::pin(future))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TaskSet;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::SystemTime,
+    };
+
+    #[derive(Default)]
+    struct CountingExecutor {
+        spawned: AtomicUsize,
+        timers: AtomicUsize,
+    }
+
+    impl Executor for CountingExecutor {
+        fn spawn(&self, future: BoxFuture<'static, ()>) {
+            self.spawned.fetch_add(1, Ordering::SeqCst);
+            std::thread::spawn(move || futures::executor::block_on(future));
+        }
+
+        fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+            self.timers.fetch_add(1, Ordering::SeqCst);
+            let (tx, rx) = futures::channel::oneshot::channel::<()>();
+            std::thread::spawn(move || {
+                std::thread::sleep(duration);
+                let _ = tx.send(());
+            });
+            Box::pin(async move {
+                let _ = rx.await;
+            })
+        }
+    }
+
+    #[test]
+    fn task_sets_run_without_tokio() {
+        let executor = Arc::new(CountingExecutor::default());
+        let tasks = TaskSet::new(executor.clone());
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let d = done.clone();
+        tasks.spawn(async move {
+            d.fetch_add(1, Ordering::SeqCst);
+        });
+        futures::executor::block_on(tasks.finish(SystemTime::now() + Duration::from_secs(5)));
+
+        assert_eq!(1, done.load(Ordering::SeqCst));
+        assert_eq!(1, executor.spawned.load(Ordering::SeqCst));
+        assert_eq!(1, executor.timers.load(Ordering::SeqCst));
+    }
+}
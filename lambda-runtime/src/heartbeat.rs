@@ -0,0 +1,190 @@
+use crate::{
+    executor::{Executor, SharedExecutor, TokioExecutor},
+    Context, TaskSet,
+};
+use futures::future::{FutureExt, RemoteHandle};
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::{info, Instrument, Span};
+
+/// Periodic liveness signal for long-running invocations.
+///
+/// A heartbeat runs in the background, calling a function every `interval`
+/// to emit progress logs, metrics, or Step Functions heartbeats. It stops when
+/// the [`HeartbeatGuard`] returned by [`start`](Heartbeat::start) is dropped,
+/// usually when the handler finishes, or when the invocation deadline is near.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{Error, Heartbeat, LambdaEvent};
+/// use serde_json::Value;
+/// use std::time::Duration;
+///
+/// async fn process(_event: Value) -> Result<Value, Error> {
+///     # unimplemented!()
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     // Logs the progress every 10 seconds until the handler returns.
+///     let _heartbeat = Heartbeat::new(Duration::from_secs(10)).log(&event.context);
+///     process(event.payload).await
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    interval: Duration,
+    reserve: Duration,
+}
+
+/// A heartbeat emitted while an invocation is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Beat {
+    /// The number of heartbeats emitted so far, starting at 1.
+    pub count: u64,
+    /// The time since the heartbeat started.
+    pub elapsed: Duration,
+    /// The time left before the invocation deadline.
+    pub remaining: Duration,
+}
+
+impl Heartbeat {
+    /// Create a heartbeat emitted every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Heartbeat {
+            interval,
+            reserve: Duration::from_millis(500),
+        }
+    }
+
+    /// Stop the heartbeat when less than `reserve` is left before the
+    /// invocation deadline. The default is 500ms.
+    pub fn stop_before(self, reserve: Duration) -> Self {
+        Heartbeat { reserve, ..self }
+    }
+
+    /// Start calling `beat` every interval, until the guard is dropped or the
+    /// deadline is near.
+    ///
+    /// The task runs in the caller's tracing span, on the executor of the
+    /// current invocation. The runtime doesn't wait for it before it sends
+    /// the response.
+    pub fn start<F, Fut>(self, ctx: &Context, mut beat: F) -> HeartbeatGuard
+    where
+        F: FnMut(Beat) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let executor: SharedExecutor = match TaskSet::current() {
+            Some(tasks) => tasks.executor().clone(),
+            None => Arc::new(TokioExecutor),
+        };
+        let stop_at = ctx.deadline().checked_sub(self.reserve).unwrap_or(ctx.deadline());
+        let interval = self.interval;
+
+        let timer = executor.clone();
+        let task = async move {
+            let started = Instant::now();
+            let mut count = 0;
+            loop {
+                timer.sleep(interval).await;
+                let remaining = match stop_at.duration_since(SystemTime::now()) {
+                    Ok(remaining) if !remaining.is_zero() => remaining,
+                    _ => return,
+                };
+                count += 1;
+                beat(Beat {
+                    count,
+                    elapsed: started.elapsed(),
+                    remaining,
+                })
+                .await;
+            }
+        };
+        let (task, handle) = task.instrument(Span::current()).remote_handle();
+        executor.spawn(Box::pin(task));
+        HeartbeatGuard { _handle: handle }
+    }
+
+    /// Start logging the progress of the invocation every interval.
+    pub fn log(self, ctx: &Context) -> HeartbeatGuard {
+        self.start(ctx, |beat| {
+            info!(
+                heartbeat = beat.count,
+                elapsed_ms = beat.elapsed.as_millis() as u64,
+                remaining_ms = beat.remaining.as_millis() as u64,
+                "invocation still running"
+            );
+            futures::future::ready(())
+        })
+    }
+}
+
+/// Keeps a [`Heartbeat`] running. The heartbeat stops when the guard is dropped.
+#[derive(Debug)]
+#[must_use = "the heartbeat stops when the guard is dropped"]
+pub struct HeartbeatGuard {
+    _handle: RemoteHandle<()>,
+}
+
+impl HeartbeatGuard {
+    /// Stop the heartbeat.
+    pub fn stop(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    fn context(timeout: Duration) -> Context {
+        let deadline = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap() + timeout;
+        Context {
+            deadline: deadline.as_millis() as u64,
+            ..Default::default()
+        }
+    }
+
+    fn counter() -> (
+        Arc<AtomicU64>,
+        impl FnMut(Beat) -> futures::future::Ready<()> + Send + 'static,
+    ) {
+        let count = Arc::new(AtomicU64::new(0));
+        let beats = count.clone();
+        (count, move |beat: Beat| {
+            beats.store(beat.count, Ordering::SeqCst);
+            futures::future::ready(())
+        })
+    }
+
+    #[tokio::test]
+    async fn stops_when_the_guard_is_dropped() {
+        let (count, beat) = counter();
+        let guard = Heartbeat::new(Duration::from_millis(10)).start(&context(Duration::from_secs(10)), beat);
+        tokio::time::sleep(Duration::from_millis(55)).await;
+        guard.stop();
+
+        let stopped = count.load(Ordering::SeqCst);
+        assert!(stopped >= 2, "{stopped}");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(stopped, count.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn stops_before_the_deadline() {
+        let (count, beat) = counter();
+        let _guard = Heartbeat::new(Duration::from_millis(10))
+            .stop_before(Duration::from_millis(100))
+            .start(&context(Duration::from_millis(150)), beat);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let stopped = count.load(Ordering::SeqCst);
+        assert!(stopped <= 5, "{stopped}");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(stopped, count.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,564 @@
+use crate::{
+    requests::{InitErrorRequest, IntoRequest},
+    runtime_client,
+    settings::{EnvError, FromEnv},
+    BudgetExceeded, Context, Diagnostic, Error, ExecutionMode, TimeBudget,
+};
+use futures::future::BoxFuture;
+use lambda_runtime_api_client::Transport;
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
+use tracing::{debug, error, warn};
+
+/// Time that Lambda gives a function to initialize before it restarts it.
+pub const DEFAULT_INIT_BUDGET: Duration = Duration::from_secs(10);
+
+/// Concurrent initialization of the resources of a function, like SDK clients or
+/// configuration fetched from a parameter store, within a time budget.
+///
+/// Every initializer is wrapped in a named [`step`](Init::step), and the steps run
+/// concurrently in the future passed to [`run`](Init::run), usually with
+/// `tokio::try_join!`, so the init phase lasts as long as the slowest step instead
+/// of the sum of all of them.
+///
+/// When a step fails, or when the steps don't complete within the budget, the
+/// error is logged and reported to the `/runtime/init/error` endpoint of the
+/// Runtime API, so the failed step appears in the error of the invocation that
+/// triggered the cold start. The function must then exit without polling for events,
+/// which `?` does in `main`.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, Error, Init, LambdaEvent};
+/// use serde_json::Value;
+/// use std::time::Duration;
+///
+/// async fn connect() -> Result<String, Error> {
+///     Ok("a database client".to_string())
+/// }
+///
+/// async fn fetch_config() -> Result<Value, Error> {
+///     Ok(Value::Null)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let init = Init::new().budget(Duration::from_secs(3));
+///     let (client, config) = init
+///         .run(async { tokio::try_join!(init.step("database", connect()), init.step("config", fetch_config())) })
+///         .await?;
+///
+///     lambda_runtime::run(service_fn(|event: LambdaEvent<Value>| {
+///         let (_client, _config) = (&client, &config);
+///         async move { Ok::<_, Error>(event.payload) }
+///     }))
+///     .await
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Init {
+    budget: Duration,
+    pending: Arc<Mutex<Vec<String>>>,
+}
+
+impl Init {
+    /// Create an initialization with the [`DEFAULT_INIT_BUDGET`].
+    pub fn new() -> Self {
+        Init {
+            budget: DEFAULT_INIT_BUDGET,
+            pending: Arc::default(),
+        }
+    }
+
+    /// Set the total time that the steps have to complete.
+    pub fn budget(self, budget: Duration) -> Self {
+        Init { budget, ..self }
+    }
+
+    /// Wrap the initializer `fut` in a step named `name`, which tells what failed
+    /// or didn't complete in the errors of [`run`](Init::run).
+    pub fn step<T, E, Fut>(
+        &self,
+        name: impl Into<String>,
+        fut: Fut,
+    ) -> impl Future<Output = Result<T, InitError>> + Send + 'static
+    where
+        Fut: Future<Output = Result<T, E>> + Send + 'static,
+        E: Into<Error>,
+    {
+        let name = name.into();
+        let pending = self.pending.clone();
+        pending.lock().unwrap().push(name.clone());
+        async move {
+            let start = Instant::now();
+            let result = fut.await;
+            pending.lock().unwrap().retain(|pending| pending != &name);
+            debug!(step = %name, elapsed = ?start.elapsed(), ok = result.is_ok(), "init step completed");
+            result.map_err(|source| InitError::Failed {
+                step: name,
+                source: source.into(),
+            })
+        }
+    }
+
+    /// Read the settings of the function from the environment, and report all
+    /// the missing or invalid variables to the Runtime API at once.
+    ///
+    /// See the [`settings`](crate::settings) module for details.
+    pub async fn env<T: FromEnv>(&self) -> Result<T, InitError> {
+        self.run(async { T::from_env().map_err(InitError::Env) }).await
+    }
+
+    /// Run the steps of `fut` within the budget, and report their failure to the Runtime API.
+    pub async fn run<T>(&self, fut: impl Future<Output = Result<T, InitError>>) -> Result<T, InitError> {
+        let start = Instant::now();
+        let result = match tokio::time::timeout(self.budget, fut).await {
+            Ok(result) => result,
+            Err(_) => Err(InitError::TimedOut {
+                budget: self.budget,
+                pending: self.pending.lock().unwrap().clone(),
+            }),
+        };
+        match &result {
+            Ok(_) => debug!(elapsed = ?start.elapsed(), "init completed"),
+            Err(err) => {
+                error!(error = %err, "init failed");
+                if ExecutionMode::detect() != ExecutionMode::Local {
+                    match runtime_client() {
+                        Ok(client) => report(&client, err).await,
+                        Err(err) => warn!(error = %err, "unable to report the init error"),
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+impl Default for Init {
+    fn default() -> Self {
+        Init::new()
+    }
+}
+
+impl fmt::Debug for Init {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Init")
+            .field("budget", &self.budget)
+            .field("pending", &self.pending.lock().unwrap())
+            .finish()
+    }
+}
+
+/// Error of an [`Init`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum InitError {
+    /// A step returned an error.
+    Failed {
+        /// The name of the step.
+        step: String,
+        /// The error of the step.
+        source: Error,
+    },
+    /// The steps didn't complete within the budget.
+    TimedOut {
+        /// The budget of the initialization.
+        budget: Duration,
+        /// The names of the steps that didn't complete.
+        pending: Vec<String>,
+    },
+    /// Environment variables are missing or invalid.
+    Env(EnvError),
+}
+
+impl InitError {
+    fn diagnostic(&self) -> Diagnostic {
+        match self {
+            InitError::Failed { .. } => Diagnostic::new("Runtime.InitStepFailed", self.to_string()),
+            InitError::TimedOut { .. } => Diagnostic::new("Runtime.InitTimeout", self.to_string()),
+            InitError::Env(_) => Diagnostic::new("Runtime.InvalidEnvironment", self.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::Failed { step, source } => write!(f, "init step `{step}` failed: {source}"),
+            InitError::TimedOut { budget, pending } => write!(
+                f,
+                "init didn't complete within {budget:?}, waiting for: {}",
+                pending.join(", ")
+            ),
+            InitError::Env(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for InitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InitError::Failed { source, .. } => Some(source.as_ref()),
+            InitError::TimedOut { .. } => None,
+            InitError::Env(err) => Some(err),
+        }
+    }
+}
+
+/// A value constructed on the first invocation that uses it, instead of in the
+/// init phase.
+///
+/// Construction happens once, even when concurrent invocations ask for the value
+/// at the same time: the other invocations wait for it. The time it takes counts
+/// against the deadline of the invocation that waits for the value, and when the
+/// deadline comes first, [`get`](LazyInit::get) returns a
+/// [`LazyInitError::DeadlineExceeded`] instead of letting Lambda time the
+/// invocation out. Failed or interrupted constructions are not cached, so the next
+/// invocation tries again.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, Error, LambdaEvent, LazyInit};
+/// use serde_json::Value;
+/// use std::time::Duration;
+///
+/// async fn load_model() -> Result<Vec<u8>, Error> {
+///     Ok(vec![])
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let model = LazyInit::new("model", load_model);
+///     let model = &model;
+///     lambda_runtime::run(service_fn(|event: LambdaEvent<Value>| async move {
+///         // Keep 500ms to answer once the model is loaded.
+///         let budget = event.context.time_budget().reserve(Duration::from_millis(500));
+///         let model = model.get_within(budget).await?;
+///         Ok::<_, Error>(Value::from(model.len()))
+///     }))
+///     .await
+/// }
+/// ```
+pub struct LazyInit<T> {
+    name: String,
+    cell: OnceCell<T>,
+    init: Box<dyn Fn() -> BoxFuture<'static, Result<T, Error>> + Send + Sync>,
+}
+
+impl<T> LazyInit<T> {
+    /// Create a value named `name`, constructed by `init`.
+    pub fn new<F, Fut, E>(name: impl Into<String>, init: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, E>> + Send + 'static,
+        E: Into<Error>,
+    {
+        LazyInit {
+            name: name.into(),
+            cell: OnceCell::new(),
+            init: Box::new(move || {
+                let fut = init();
+                Box::pin(async move { fut.await.map_err(Into::into) })
+            }),
+        }
+    }
+
+    /// Return the value, and construct it before the deadline of the invocation
+    /// if it doesn't exist yet.
+    pub async fn get(&self, context: &Context) -> Result<&T, LazyInitError> {
+        self.get_within(context.time_budget()).await
+    }
+
+    /// Return the value, and construct it before the end of `budget` if it
+    /// doesn't exist yet.
+    pub async fn get_within(&self, budget: TimeBudget) -> Result<&T, LazyInitError> {
+        if let Some(value) = self.cell.get() {
+            return Ok(value);
+        }
+        let start = Instant::now();
+        let init = self.cell.get_or_try_init(|| async {
+            let value = (self.init)().await;
+            debug!(name = %self.name, elapsed = ?start.elapsed(), ok = value.is_ok(), "lazy init completed");
+            value
+        });
+        match budget.timeout(init).await {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(source)) => Err(LazyInitError::Failed {
+                name: self.name.clone(),
+                source,
+            }),
+            Err(exceeded) => Err(LazyInitError::DeadlineExceeded {
+                name: self.name.clone(),
+                exceeded,
+            }),
+        }
+    }
+
+    /// Return the value if it's already constructed.
+    pub fn get_if_initialized(&self) -> Option<&T> {
+        self.cell.get()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LazyInit<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyInit")
+            .field("name", &self.name)
+            .field("value", &self.cell.get())
+            .finish()
+    }
+}
+
+/// Error of a [`LazyInit`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum LazyInitError {
+    /// The construction of the value returned an error.
+    Failed {
+        /// The name of the value.
+        name: String,
+        /// The error of the construction.
+        source: Error,
+    },
+    /// The value wasn't constructed before the deadline.
+    DeadlineExceeded {
+        /// The name of the value.
+        name: String,
+        /// The time that the construction had.
+        exceeded: BudgetExceeded,
+    },
+}
+
+impl fmt::Display for LazyInitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LazyInitError::Failed { name, source } => write!(f, "initialization of `{name}` failed: {source}"),
+            LazyInitError::DeadlineExceeded { name, exceeded } => {
+                write!(f, "initialization of `{name}` didn't complete in time: {exceeded}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LazyInitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LazyInitError::Failed { source, .. } => Some(source.as_ref()),
+            LazyInitError::DeadlineExceeded { exceeded, .. } => Some(exceeded),
+        }
+    }
+}
+
+/// The time since the process started, read from `/proc`, when it's available.
+///
+/// Linux reports the start time of processes in clock ticks, which are 10ms on
+/// the architectures supported by Lambda.
+pub(crate) fn process_age() -> Option<Duration> {
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+    process_age_from(&stat, &uptime)
+}
+
+fn process_age_from(stat: &str, uptime: &str) -> Option<Duration> {
+    const TICKS_PER_SECOND: f64 = 100.0;
+    // The name of the process, in parentheses, can contain spaces, so the fields
+    // are counted from the last parenthesis. The start time is the 22nd field.
+    let fields = stat.rsplit_once(')')?.1;
+    let start_ticks: f64 = fields.split_whitespace().nth(19)?.parse().ok()?;
+    let uptime: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+    let age = uptime - start_ticks / TICKS_PER_SECOND;
+    Some(Duration::from_secs_f64(age.max(0.0)))
+}
+
+async fn report<T: Transport>(client: &T, err: &InitError) {
+    let req = InitErrorRequest {
+        diagnostic: err.diagnostic(),
+    };
+    let result = match req.into_req() {
+        Ok(req) => client.call(req).await.map(|_| ()),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        warn!(error = %err, "unable to report the init error");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{Body, Request, Response};
+    use lambda_runtime_api_client::TransportFuture;
+
+    #[tokio::test]
+    async fn runs_steps_concurrently() {
+        let init = Init::new().budget(Duration::from_millis(500));
+        let slow = |value| async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok::<_, Error>(value)
+        };
+        let start = Instant::now();
+        let (a, b) = init
+            .run(async { tokio::try_join!(init.step("a", slow(1)), init.step("b", slow(2))) })
+            .await
+            .unwrap();
+        assert_eq!((1, 2), (a, b));
+        assert!(start.elapsed() < Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn reports_the_failed_step() {
+        let init = Init::new();
+        let err = init
+            .run(async {
+                tokio::try_join!(
+                    init.step("config", async { Ok::<_, Error>(()) }),
+                    init.step("database", async { Err::<(), _>("connection refused") }),
+                )
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, InitError::Failed { step, .. } if step == "database"));
+        assert_eq!("init step `database` failed: connection refused", err.to_string());
+    }
+
+    #[tokio::test]
+    async fn reports_invalid_environments() {
+        #[derive(Debug)]
+        struct Settings;
+
+        impl FromEnv for Settings {
+            fn read(reader: &mut crate::settings::EnvReader<'_>) -> Option<Self> {
+                let table: Option<String> = reader.required("LAMBDA_RUNTIME_TEST_MISSING_TABLE");
+                let bucket: Option<String> = reader.required("LAMBDA_RUNTIME_TEST_MISSING_BUCKET");
+                table.zip(bucket).map(|_| Settings)
+            }
+        }
+
+        let err = Init::new().env::<Settings>().await.unwrap_err();
+        assert_eq!("Runtime.InvalidEnvironment", err.diagnostic().error_type());
+        assert_eq!(
+            "invalid environment: LAMBDA_RUNTIME_TEST_MISSING_TABLE is missing, \
+             LAMBDA_RUNTIME_TEST_MISSING_BUCKET is missing",
+            err.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_the_pending_steps_on_timeout() {
+        let init = Init::new().budget(Duration::from_millis(50));
+        let err = init
+            .run(async {
+                tokio::try_join!(
+                    init.step("config", async { Ok::<_, Error>(()) }),
+                    init.step("database", async {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        Ok::<_, Error>(())
+                    }),
+                )
+            })
+            .await
+            .unwrap_err();
+        match err {
+            InitError::TimedOut { pending, .. } => assert_eq!(vec!["database"], pending),
+            err => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn posts_to_the_init_error_endpoint() {
+        struct Recorder(Mutex<Vec<Request<Body>>>);
+
+        impl Transport for Recorder {
+            fn call(&self, req: Request<Body>) -> TransportFuture<'_> {
+                self.0.lock().unwrap().push(req);
+                Box::pin(async { Ok(Response::new(Body::empty())) })
+            }
+        }
+
+        let recorder = Recorder(Mutex::default());
+        let err = InitError::TimedOut {
+            budget: Duration::from_secs(1),
+            pending: vec!["database".to_string()],
+        };
+        report(&recorder, &err).await;
+
+        let req = recorder.0.lock().unwrap().pop().unwrap();
+        assert_eq!("/2018-06-01/runtime/init/error", req.uri().path());
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let diagnostic: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("Runtime.InitTimeout", diagnostic["errorType"]);
+        assert_eq!(
+            "init didn't complete within 1s, waiting for: database",
+            diagnostic["errorMessage"]
+        );
+    }
+
+    #[test]
+    fn measures_the_age_of_the_process() {
+        let stat = "14551 (my (func)) R 14546 14551 14546 0 -1 4194304 99 0 0 0 0 0 0 0 20 0 1 0 995237 2703360 315";
+        let age = process_age_from(stat, "9962.37 3142.06\n").unwrap();
+        assert_eq!(Duration::from_millis(10_000), age);
+        assert!(process_age().is_some());
+    }
+
+    #[tokio::test]
+    async fn lazy_init_constructs_once() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let lazy = LazyInit::new("counter", move || {
+            let calls = counter.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok::<_, Error>(calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst))
+            }
+        });
+        assert!(lazy.get_if_initialized().is_none());
+
+        let budget = TimeBudget::new(std::time::SystemTime::now() + Duration::from_secs(1));
+        let (a, b) = tokio::join!(lazy.get_within(budget), lazy.get_within(budget));
+        assert_eq!((0, 0), (*a.unwrap(), *b.unwrap()));
+        assert_eq!(1, calls.load(std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(Some(&0), lazy.get_if_initialized());
+    }
+
+    #[tokio::test]
+    async fn lazy_init_respects_the_deadline() {
+        let lazy = LazyInit::new("slow", || async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok::<_, Error>(1)
+        });
+        let short = TimeBudget::new(std::time::SystemTime::now() + Duration::from_millis(20));
+        let err = lazy.get_within(short).await.unwrap_err();
+        assert!(matches!(&err, LazyInitError::DeadlineExceeded { name, .. } if name == "slow"));
+        assert!(lazy.get_if_initialized().is_none());
+
+        let long = TimeBudget::new(std::time::SystemTime::now() + Duration::from_secs(1));
+        assert_eq!(1, *lazy.get_within(long).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn lazy_init_retries_after_a_failure() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let lazy = LazyInit::new("flaky", move || {
+            let calls = calls.clone();
+            async move {
+                match calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                    0 => Err("unavailable"),
+                    _ => Ok("ready"),
+                }
+            }
+        });
+        let context = Context::default();
+        let budget = TimeBudget::new(std::time::SystemTime::now() + Duration::from_secs(1));
+        let err = lazy.get_within(budget).await.unwrap_err();
+        assert_eq!("initialization of `flaky` failed: unavailable", err.to_string());
+        assert_eq!("ready", *lazy.get_within(budget).await.unwrap());
+        assert_eq!("ready", *lazy.get(&context).await.unwrap());
+    }
+}
@@ -0,0 +1,404 @@
+//! The [`Invoker`] takes care of serializing the payload, propagating the
+//! caller's client context and X-Ray trace header, and decoding the function's
+//! response or error. Sending the request to the Lambda API is delegated to an
+//! [`InvokeTransport`], usually backed by the AWS SDK, so this crate doesn't
+//! need to know how to sign requests.
+use crate::{
+    limits::{InvokeMode, PayloadTooLarge},
+    Context, Error,
+};
+use base64::Engine;
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+
+/// Maximum size in bytes of the base64 encoded client context accepted by the Invoke API.
+pub const MAX_CLIENT_CONTEXT_SIZE: usize = 3583;
+
+/// How the target function is invoked.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InvocationType {
+    /// Wait for the function to finish and return its response.
+    RequestResponse,
+    /// Queue the event and return immediately.
+    Event,
+    /// Validate the parameters and permissions without running the function.
+    DryRun,
+}
+
+impl InvocationType {
+    /// Return the name of the invocation type as expected by the Invoke API.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvocationType::RequestResponse => "RequestResponse",
+            InvocationType::Event => "Event",
+            InvocationType::DryRun => "DryRun",
+        }
+    }
+}
+
+/// Request sent to the Lambda Invoke API.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InvokeRequest {
+    /// The name or ARN of the function to invoke.
+    pub function_name: String,
+    /// The version or alias of the function to invoke.
+    pub qualifier: Option<String>,
+    /// How the function is invoked.
+    pub invocation_type: InvocationType,
+    /// The base64 encoded client context to send to the function.
+    pub client_context: Option<String>,
+    /// The X-Ray trace header of the caller.
+    pub trace_header: Option<String>,
+    /// The serialized payload.
+    pub payload: Vec<u8>,
+}
+
+/// Response received from the Lambda Invoke API.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct InvokeResponse {
+    /// The HTTP status code returned by the Invoke API.
+    pub status_code: u16,
+    /// Set when the function returned an error, usually `Unhandled`.
+    pub function_error: Option<String>,
+    /// The version of the function that was executed.
+    pub executed_version: Option<String>,
+    /// The serialized response, or error, returned by the function.
+    pub payload: Vec<u8>,
+}
+
+/// Sends invocation requests to the Lambda API.
+///
+/// Transports must send [`InvokeRequest::trace_header`] in the `X-Amzn-Trace-Id`
+/// header of the request, otherwise the invoked function starts a new X-Ray trace
+/// instead of continuing the trace of the caller.
+///
+/// # Example
+/// ```ignore
+/// use aws_sdk_lambda::{primitives::Blob, types::InvocationType as SdkInvocationType};
+/// use futures::future::BoxFuture;
+/// use lambda_runtime::{invoke::{InvokeRequest, InvokeResponse, InvokeTransport}, Error};
+///
+/// struct SdkTransport(aws_sdk_lambda::Client);
+///
+/// impl InvokeTransport for SdkTransport {
+///     fn invoke(&self, req: InvokeRequest) -> BoxFuture<'_, Result<InvokeResponse, Error>> {
+///         Box::pin(async move {
+///             let trace_header = req.trace_header;
+///             let output = self
+///                 .0
+///                 .invoke()
+///                 .function_name(req.function_name)
+///                 .set_qualifier(req.qualifier)
+///                 .invocation_type(SdkInvocationType::from(req.invocation_type.as_str()))
+///                 .set_client_context(req.client_context)
+///                 .payload(Blob::new(req.payload))
+///                 .customize()
+///                 .mutate_request(move |http| {
+///                     // Continue the X-Ray trace of the caller in the invoked function.
+///                     if let Some(trace_header) = &trace_header {
+///                         http.headers_mut().insert("X-Amzn-Trace-Id", trace_header.clone());
+///                     }
+///                 })
+///                 .send()
+///                 .await?;
+///             Ok(InvokeResponse {
+///                 status_code: output.status_code() as u16,
+///                 function_error: output.function_error().map(String::from),
+///                 executed_version: output.executed_version().map(String::from),
+///                 payload: output.payload().map(|p| p.as_ref().to_vec()).unwrap_or_default(),
+///             })
+///         })
+///     }
+/// }
+/// ```
+pub trait InvokeTransport {
+    /// Send the request to the Invoke API.
+    fn invoke(&self, req: InvokeRequest) -> BoxFuture<'_, Result<InvokeResponse, Error>>;
+}
+
+/// Error returned when a typed invocation fails.
+#[derive(Debug)]
+pub enum InvokeError {
+    /// The payload couldn't be serialized.
+    Serialize(serde_json::Error),
+    /// The client context is larger than the Invoke API allows.
+    ClientContextTooLarge(usize),
+    /// The payload is larger than the Invoke API allows for the invocation type.
+    PayloadTooLarge(PayloadTooLarge),
+    /// The transport failed to send the request.
+    Transport(Error),
+    /// The function returned an error.
+    Function {
+        /// The error type reported by the function.
+        error_type: String,
+        /// The error message reported by the function.
+        error_message: String,
+    },
+    /// The response couldn't be deserialized into the expected type.
+    Deserialize(serde_json::Error),
+}
+
+impl fmt::Display for InvokeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvokeError::Serialize(err) => write!(f, "failed to serialize the invocation payload: {err}"),
+            InvokeError::ClientContextTooLarge(size) => write!(
+                f,
+                "the encoded client context is {size} bytes, the maximum size is {MAX_CLIENT_CONTEXT_SIZE} bytes"
+            ),
+            InvokeError::PayloadTooLarge(err) => write!(f, "failed to invoke the function: {err}"),
+            InvokeError::Transport(err) => write!(f, "failed to invoke the function: {err}"),
+            InvokeError::Function {
+                error_type,
+                error_message,
+            } => write!(f, "the function returned an error: {error_type}: {error_message}"),
+            InvokeError::Deserialize(err) => write!(f, "failed to deserialize the function response: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for InvokeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InvokeError::Serialize(err) | InvokeError::Deserialize(err) => Some(err),
+            InvokeError::PayloadTooLarge(err) => Some(err),
+            InvokeError::Transport(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FunctionError {
+    #[serde(default)]
+    error_type: String,
+    #[serde(default)]
+    error_message: String,
+}
+
+/// Client to invoke other Lambda functions with typed payloads.
+///
+/// The caller's [`Context`] is used to propagate the client context and the
+/// X-Ray trace header to the invoked function.
+#[derive(Debug, Clone)]
+pub struct Invoker<T> {
+    transport: T,
+}
+
+impl<T> Invoker<T>
+where
+    T: InvokeTransport,
+{
+    /// Create a new invoker with the given transport.
+    pub fn new(transport: T) -> Self {
+        Invoker { transport }
+    }
+
+    /// Invoke a function synchronously and deserialize its response.
+    pub async fn invoke<P, R>(&self, ctx: &Context, function_name: &str, payload: &P) -> Result<R, InvokeError>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let req = build_request(ctx, function_name, InvocationType::RequestResponse, payload)?;
+        let res = self.transport.invoke(req).await.map_err(InvokeError::Transport)?;
+
+        if res.function_error.is_some() {
+            let err: FunctionError = serde_json::from_slice(&res.payload).unwrap_or_else(|_| FunctionError {
+                error_type: res.function_error.clone().unwrap_or_default(),
+                error_message: String::from_utf8_lossy(&res.payload).to_string(),
+            });
+            return Err(InvokeError::Function {
+                error_type: err.error_type,
+                error_message: err.error_message,
+            });
+        }
+
+        serde_json::from_slice(&res.payload).map_err(InvokeError::Deserialize)
+    }
+
+    /// Queue an event for a function without waiting for its response.
+    pub async fn invoke_async<P>(&self, ctx: &Context, function_name: &str, payload: &P) -> Result<(), InvokeError>
+    where
+        P: Serialize,
+    {
+        let req = build_request(ctx, function_name, InvocationType::Event, payload)?;
+        self.transport.invoke(req).await.map_err(InvokeError::Transport)?;
+        Ok(())
+    }
+}
+
+/// Build the request to invoke a function, propagating the client
+/// context and X-Ray trace header from the given [`Context`].
+pub fn build_request<P>(
+    ctx: &Context,
+    function_name: &str,
+    invocation_type: InvocationType,
+    payload: &P,
+) -> Result<InvokeRequest, InvokeError>
+where
+    P: Serialize,
+{
+    let payload = serde_json::to_vec(payload).map_err(InvokeError::Serialize)?;
+    let mode = match invocation_type {
+        InvocationType::Event => InvokeMode::Async,
+        InvocationType::RequestResponse | InvocationType::DryRun => InvokeMode::Buffered,
+    };
+    mode.check(payload.len()).map_err(InvokeError::PayloadTooLarge)?;
+
+    let client_context = match &ctx.client_context {
+        Some(client_context) => {
+            let json = serde_json::to_vec(client_context).map_err(InvokeError::Serialize)?;
+            let encoded = base64::engine::general_purpose::STANDARD.encode(json);
+            if encoded.len() > MAX_CLIENT_CONTEXT_SIZE {
+                return Err(InvokeError::ClientContextTooLarge(encoded.len()));
+            }
+            Some(encoded)
+        }
+        None => None,
+    };
+
+    let (function_name, qualifier) = split_qualifier(function_name);
+
+    Ok(InvokeRequest {
+        function_name,
+        qualifier,
+        invocation_type,
+        client_context,
+        trace_header: ctx.xray_trace_id.clone(),
+        payload,
+    })
+}
+
+// Split `name:qualifier` into its parts, leaving full ARNs untouched.
+fn split_qualifier(function_name: &str) -> (String, Option<String>) {
+    if function_name.starts_with("arn:") {
+        return (function_name.to_string(), None);
+    }
+    match function_name.split_once(':') {
+        Some((name, qualifier)) => (name.to_string(), Some(qualifier.to_string())),
+        None => (function_name.to_string(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientApplication, ClientContext};
+    use serde_json::{json, Value};
+    use std::{collections::HashMap, sync::Mutex};
+
+    #[derive(Default)]
+    struct MockTransport {
+        requests: Mutex<Vec<InvokeRequest>>,
+        response: InvokeResponse,
+    }
+
+    impl InvokeTransport for MockTransport {
+        fn invoke(&self, req: InvokeRequest) -> BoxFuture<'_, Result<InvokeResponse, Error>> {
+            self.requests.lock().unwrap().push(req);
+            let res = self.response.clone();
+            Box::pin(async move { Ok(res) })
+        }
+    }
+
+    fn context() -> Context {
+        Context {
+            xray_trace_id: Some("Root=1-5bef4de7-ad49b0e87f6ef6c87fc2e700;Parent=9a9197af755a6419".into()),
+            client_context: Some(ClientContext {
+                client: ClientApplication {
+                    app_title: "app".into(),
+                    ..Default::default()
+                },
+                custom: HashMap::new(),
+                environment: HashMap::new(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn invoke_propagates_context() {
+        let transport = MockTransport {
+            response: InvokeResponse {
+                status_code: 200,
+                payload: br#"{"ok":true}"#.to_vec(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let invoker = Invoker::new(transport);
+
+        let res: Value = invoker
+            .invoke(&context(), "target:live", &json!({"a": 1}))
+            .await
+            .unwrap();
+        assert_eq!(json!({"ok": true}), res);
+
+        let req = invoker.transport.requests.lock().unwrap().pop().unwrap();
+        assert_eq!("target", req.function_name);
+        assert_eq!(Some("live".to_string()), req.qualifier);
+        assert_eq!(InvocationType::RequestResponse, req.invocation_type);
+        assert_eq!(br#"{"a":1}"#.to_vec(), req.payload);
+        assert_eq!(context().xray_trace_id, req.trace_header);
+
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(req.client_context.unwrap())
+            .unwrap();
+        let client_context: ClientContext = serde_json::from_slice(&decoded).unwrap();
+        assert_eq!("app", client_context.client.app_title);
+    }
+
+    #[tokio::test]
+    async fn invoke_returns_function_errors() {
+        let transport = MockTransport {
+            response: InvokeResponse {
+                status_code: 200,
+                function_error: Some("Unhandled".into()),
+                payload: br#"{"errorType":"MyError","errorMessage":"boom"}"#.to_vec(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let invoker = Invoker::new(transport);
+
+        let err = invoker
+            .invoke::<_, Value>(&Context::default(), "target", &json!({}))
+            .await
+            .unwrap_err();
+        match err {
+            InvokeError::Function {
+                error_type,
+                error_message,
+            } => {
+                assert_eq!("MyError", error_type);
+                assert_eq!("boom", error_message);
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn invoke_async_checks_payload_limit() {
+        let invoker = Invoker::new(MockTransport::default());
+        let payload = "a".repeat(crate::limits::ASYNC_PAYLOAD_LIMIT);
+
+        let err = invoker
+            .invoke_async(&Context::default(), "target", &payload)
+            .await
+            .unwrap_err();
+        match err {
+            InvokeError::PayloadTooLarge(err) => assert_eq!(InvokeMode::Async, err.mode),
+            other => panic!("unexpected error {other:?}"),
+        }
+        assert!(invoker.transport.requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn arns_are_not_split() {
+        let arn = "arn:aws:lambda:us-east-1:123456789012:function:target:live";
+        assert_eq!((arn.to_string(), None), split_qualifier(arn));
+    }
+}
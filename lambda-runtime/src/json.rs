@@ -0,0 +1,533 @@
+//! serde_json writes non-finite floats as `null`, and integers of any size as JSON
+//! numbers. Some services reject these documents, or silently lose precision on
+//! integers above 2^53 because they parse every number as a double. A [`JsonPolicy`]
+//! changes how those values are written in the responses of a function.
+//!
+//! Incoming events are parsed by serde_json as usual. JSON documents can't contain
+//! non-finite floats, and integers up to `u64::MAX` are read without loss.
+//!
+//! # Example
+//! ```
+//! use lambda_runtime::json::{JsonPolicy, LargeIntegers, NonFiniteFloats};
+//! use serde_json::json;
+//!
+//! let policy = JsonPolicy::new()
+//!     .non_finite_floats(NonFiniteFloats::Stringify)
+//!     .large_integers(LargeIntegers::Stringify);
+//! let body = policy.to_vec(&(f64::NAN, u64::MAX, 42)).unwrap();
+//! assert_eq!(json!(["NaN", "18446744073709551615", 42]), serde_json::from_slice::<serde_json::Value>(&body).unwrap());
+//! ```
+use serde::{
+    ser::{
+        Error as _, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+        SerializeTupleStruct, SerializeTupleVariant,
+    },
+    Serialize, Serializer,
+};
+
+/// Largest integer that can be represented exactly by a double, `2^53 - 1`.
+pub const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// How `NaN`, `Infinity`, and `-Infinity` are written.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum NonFiniteFloats {
+    /// Write `null`, like serde_json does.
+    #[default]
+    Null,
+    /// Fail the serialization, which reports an error for the invocation.
+    Reject,
+    /// Write the strings `"NaN"`, `"Infinity"`, and `"-Infinity"`.
+    Stringify,
+}
+
+/// How integers above [`MAX_SAFE_INTEGER`], or below its negation, are written.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum LargeIntegers {
+    /// Write the integer as a JSON number, like serde_json does.
+    #[default]
+    Number,
+    /// Fail the serialization, which reports an error for the invocation.
+    Reject,
+    /// Write the integer as a string, for example `"18446744073709551615"`.
+    Stringify,
+}
+
+/// Policy applied to the values that serde_json writes in a way other services can't read.
+///
+/// The default policy keeps serde_json's behavior.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct JsonPolicy {
+    non_finite_floats: NonFiniteFloats,
+    large_integers: LargeIntegers,
+}
+
+impl JsonPolicy {
+    /// Create a policy that keeps serde_json's behavior.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how non-finite floats are written.
+    pub fn non_finite_floats(mut self, policy: NonFiniteFloats) -> Self {
+        self.non_finite_floats = policy;
+        self
+    }
+
+    /// Set how integers that can't be represented exactly by a double are written.
+    pub fn large_integers(mut self, policy: LargeIntegers) -> Self {
+        self.large_integers = policy;
+        self
+    }
+
+    /// Serialize `value` as JSON with this policy.
+    pub fn to_vec<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, serde_json::Error> {
+        let mut body = Vec::new();
+        self.serialize(value, &mut serde_json::Serializer::new(&mut body))?;
+        Ok(body)
+    }
+
+    /// Serialize `value` into `serializer` with this policy.
+    pub fn serialize<T, S>(&self, value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize + ?Sized,
+        S: Serializer,
+    {
+        if *self == JsonPolicy::default() {
+            return value.serialize(serializer);
+        }
+        value.serialize(PolicySerializer {
+            inner: serializer,
+            policy: *self,
+        })
+    }
+
+    fn float<S: Serializer>(&self, value: f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            return serializer.serialize_f64(value);
+        }
+        match self.non_finite_floats {
+            NonFiniteFloats::Null => serializer.serialize_f64(value),
+            NonFiniteFloats::Reject => Err(S::Error::custom(format!("non-finite float {value}"))),
+            NonFiniteFloats::Stringify if value.is_nan() => serializer.serialize_str("NaN"),
+            NonFiniteFloats::Stringify if value > 0.0 => serializer.serialize_str("Infinity"),
+            NonFiniteFloats::Stringify => serializer.serialize_str("-Infinity"),
+        }
+    }
+
+    fn large_integer<S: Serializer>(&self, value: impl ToString, serializer: S) -> Result<S::Ok, S::Error> {
+        let value = value.to_string();
+        match self.large_integers {
+            LargeIntegers::Number => unreachable!("integers are written as numbers by the caller"),
+            LargeIntegers::Reject => Err(S::Error::custom(format!(
+                "integer {value} can't be represented exactly by a double"
+            ))),
+            LargeIntegers::Stringify => serializer.serialize_str(&value),
+        }
+    }
+
+    fn is_large(&self, magnitude: u128) -> bool {
+        self.large_integers != LargeIntegers::Number && magnitude > MAX_SAFE_INTEGER as u128
+    }
+}
+
+// Serializes a nested value with the same policy.
+struct WithPolicy<'a, T: ?Sized>(&'a T, JsonPolicy);
+
+impl<'a, T: Serialize + ?Sized> Serialize for WithPolicy<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(PolicySerializer {
+            inner: serializer,
+            policy: self.1,
+        })
+    }
+}
+
+struct PolicySerializer<S> {
+    inner: S,
+    policy: JsonPolicy,
+}
+
+struct Compound<C> {
+    inner: C,
+    policy: JsonPolicy,
+}
+
+impl<S: Serializer> Serializer for PolicySerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<S::SerializeSeq>;
+    type SerializeTuple = Compound<S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<S::SerializeTupleVariant>;
+    type SerializeMap = Compound<S::SerializeMap>;
+    type SerializeStruct = Compound<S::SerializeStruct>;
+    type SerializeStructVariant = Compound<S::SerializeStructVariant>;
+
+    fn serialize_bool(self, v: bool) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i32(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<S::Ok, S::Error> {
+        if self.policy.is_large(v.unsigned_abs() as u128) {
+            return self.policy.large_integer(v, self.inner);
+        }
+        self.inner.serialize_i64(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<S::Ok, S::Error> {
+        if self.policy.is_large(v.unsigned_abs()) {
+            return self.policy.large_integer(v, self.inner);
+        }
+        self.inner.serialize_i128(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u32(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        if self.policy.is_large(v as u128) {
+            return self.policy.large_integer(v, self.inner);
+        }
+        self.inner.serialize_u64(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<S::Ok, S::Error> {
+        if self.policy.is_large(v) {
+            return self.policy.large_integer(v, self.inner);
+        }
+        self.inner.serialize_u128(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        if v.is_finite() {
+            return self.inner.serialize_f32(v);
+        }
+        self.policy.float(v as f64, self.inner)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        self.policy.float(v, self.inner)
+    }
+
+    fn serialize_char(self, v: char) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_char(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_some(&WithPolicy(value, self.policy))
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_newtype_struct(name, &WithPolicy(value, self.policy))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_newtype_variant(name, variant_index, variant, &WithPolicy(value, self.policy))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        let policy = self.policy;
+        Ok(Compound {
+            inner: self.inner.serialize_seq(len)?,
+            policy,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        let policy = self.policy;
+        Ok(Compound {
+            inner: self.inner.serialize_tuple(len)?,
+            policy,
+        })
+    }
+
+    fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeTupleStruct, S::Error> {
+        let policy = self.policy;
+        Ok(Compound {
+            inner: self.inner.serialize_tuple_struct(name, len)?,
+            policy,
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        let policy = self.policy;
+        Ok(Compound {
+            inner: self.inner.serialize_tuple_variant(name, variant_index, variant, len)?,
+            policy,
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        let policy = self.policy;
+        Ok(Compound {
+            inner: self.inner.serialize_map(len)?,
+            policy,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct, S::Error> {
+        let policy = self.policy;
+        Ok(Compound {
+            inner: self.inner.serialize_struct(name, len)?,
+            policy,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        let policy = self.policy;
+        Ok(Compound {
+            inner: self.inner.serialize_struct_variant(name, variant_index, variant, len)?,
+            policy,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<C: SerializeSeq> SerializeSeq for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_element(&WithPolicy(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTuple> SerializeTuple for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_element(&WithPolicy(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleStruct> SerializeTupleStruct for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_field(&WithPolicy(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleVariant> SerializeTupleVariant for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_field(&WithPolicy(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeMap> SerializeMap for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+        self.inner.serialize_key(&WithPolicy(key, self.policy))
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_value(&WithPolicy(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStruct> SerializeStruct for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_field(key, &WithPolicy(value, self.policy))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStructVariant> SerializeStructVariant for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_field(key, &WithPolicy(value, self.policy))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Measurement {
+        id: u64,
+        offset: i64,
+        values: Vec<f64>,
+        ratio: Option<f32>,
+        labels: BTreeMap<u64, String>,
+    }
+
+    fn measurement() -> Measurement {
+        Measurement {
+            id: u64::MAX,
+            offset: -(1 << 60),
+            values: vec![1.5, f64::NAN, f64::INFINITY, f64::NEG_INFINITY],
+            ratio: Some(f32::NAN),
+            labels: BTreeMap::from([(u64::MAX, "max".to_string())]),
+        }
+    }
+
+    fn to_value(policy: JsonPolicy) -> Result<Value, serde_json::Error> {
+        Ok(serde_json::from_slice(&policy.to_vec(&measurement())?).unwrap())
+    }
+
+    #[test]
+    fn default_policy_matches_serde_json() {
+        assert_eq!(
+            serde_json::to_vec(&measurement()).unwrap(),
+            JsonPolicy::default().to_vec(&measurement()).unwrap()
+        );
+    }
+
+    #[test]
+    fn stringify_values() {
+        let policy = JsonPolicy::new()
+            .non_finite_floats(NonFiniteFloats::Stringify)
+            .large_integers(LargeIntegers::Stringify);
+        assert_eq!(
+            json!({
+                "id": "18446744073709551615",
+                "offset": "-1152921504606846976",
+                "values": [1.5, "NaN", "Infinity", "-Infinity"],
+                "ratio": "NaN",
+                "labels": {"18446744073709551615": "max"},
+            }),
+            to_value(policy).unwrap()
+        );
+
+        let policy = JsonPolicy::new().large_integers(LargeIntegers::Stringify);
+        assert_eq!(json!([MAX_SAFE_INTEGER, "9007199254740992"]), {
+            let body = policy.to_vec(&(MAX_SAFE_INTEGER, MAX_SAFE_INTEGER + 1)).unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()
+        });
+    }
+
+    #[test]
+    fn reject_values() {
+        let err = to_value(JsonPolicy::new().non_finite_floats(NonFiniteFloats::Reject)).unwrap_err();
+        assert_eq!("non-finite float NaN", err.to_string());
+
+        let err = to_value(JsonPolicy::new().large_integers(LargeIntegers::Reject)).unwrap_err();
+        assert_eq!(
+            "integer 18446744073709551615 can't be represented exactly by a double",
+            err.to_string()
+        );
+    }
+}
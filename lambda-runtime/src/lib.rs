@@ -8,12 +8,8 @@
 //! then be passed to the the `lambda_runtime::run` function, which launches
 //! and runs the Lambda runtime.
 use futures::FutureExt;
-use hyper::{
-    client::{connect::Connection, HttpConnector},
-    http::Request,
-    Body,
-};
-use lambda_runtime_api_client::Client;
+use hyper::{body::HttpBody, http::Request, Body};
+use lambda_runtime_api_client::Transport;
 use serde::{Deserialize, Serialize};
 use std::{
     convert::TryFrom,
@@ -21,25 +17,145 @@ use std::{
     fmt::{self, Debug, Display},
     future::Future,
     panic,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
-use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_stream::{Stream, StreamExt};
 pub use tower::{self, service_fn, Service};
 use tower::{util::ServiceFn, ServiceExt};
-use tracing::{error, trace, Instrument};
-
+use tracing::{error, trace, warn, Instrument};
+
+/// Alarms on the size of the payloads and on the duration of the handler.
+pub mod alarms;
+/// jemalloc or mimalloc as the global allocator.
+#[cfg(any(feature = "jemalloc", feature = "mimalloc"))]
+pub mod allocator;
+/// Measurement of the runtime overhead of every invocation.
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(not(feature = "bench"))]
+#[allow(dead_code)]
+mod bench;
+/// Entrypoint for container images with several handlers.
+#[cfg(feature = "bootstrap")]
+pub mod bootstrap;
+/// Capture of the events that make the handler fail, to replay them later.
+pub mod capture;
+/// Injection of latency, errors and corrupted responses for resilience tests.
+#[cfg(feature = "chaos")]
+pub mod chaos;
+/// Checkpoints to resume long jobs across invocations.
+pub mod checkpoint;
+/// Codecs to read events and write responses.
+pub mod codec;
+/// Comparison of two codecs on sampled invocations.
+#[cfg(feature = "codec_compare")]
+pub mod codec_compare;
 mod deserializer;
+/// Executor abstraction to run the runtime on any async runtime.
+pub mod executor;
+/// Typed invocation of other Lambda functions.
+pub mod invoke;
+/// Policies to serialize values that other services can't read.
+pub mod json;
+/// Payload size limits per invoke mode.
+pub mod limits;
+/// Memory usage reporting for every invocation.
+#[cfg(feature = "memory")]
+pub mod memory;
+/// Sampling CPU profiler for a share of the invocations.
+#[cfg(feature = "profiling")]
+pub mod profiling;
+/// Masking of sensitive data in logs.
+pub mod redact;
 mod requests;
+/// Circuit breakers and bulkheads for downstream calls.
+pub mod resilience;
+/// Retries with idempotency keys for outbound HTTP requests.
+pub mod retry;
+/// Validation of events against JSON Schemas.
+#[cfg(feature = "schema")]
+pub mod schema;
+/// Size-budgeted cache of files in the ephemeral storage.
+pub mod scratch;
+mod serializer;
+/// Settings of the function read from environment variables and validated in the init phase.
+pub mod settings;
+/// Mirrored and canary invocations of a second handler, for handler migrations.
+pub mod shadow;
 #[cfg(test)]
 mod simulated;
+/// Task tokens and errors for Step Functions state machines.
+pub mod step_functions;
+/// Buffered writer for streaming responses, with flush and backpressure control.
+pub mod stream_writer;
+/// Export of sanitized copies of the invocations, for analytics and regression corpora.
+pub mod traffic;
 /// Types available to a Lambda function.
 mod types;
+/// Versioned payloads that are upgraded to the latest schema when they're deserialized.
+pub mod versioned;
+/// Detection of handlers that block the executor thread.
+#[cfg(feature = "watchdog")]
+pub mod watchdog;
 
 mod streaming;
-pub use streaming::run_with_streaming_response;
+pub use streaming::{run_with_streaming_response, StreamErrorHook, StreamFailure, StreamingFormat};
+
+mod budget;
+pub use budget::{BudgetExceeded, TimeBudget};
+
+mod local;
+pub use local::{ExecutionMode, LOCAL_EVENT_FILE_VAR, LOCAL_PORT_VAR};
+
+mod tasks;
+pub use tasks::{defer, spawn_traced, TaskSet};
+
+mod heartbeat;
+pub use heartbeat::{Beat, Heartbeat, HeartbeatGuard};
+
+mod registry;
+pub use registry::{selected_handler, HandlerRegistry, UnknownHandler, HANDLER_OVERRIDE_VAR, HANDLER_VAR};
+
+mod router;
+pub use router::{AliasRouter, UnknownQualifier};
+
+mod alias;
+pub use alias::{AliasChange, OnAliasChange};
+
+mod combinators;
+pub use combinators::{AndThen, AndThenFuture, HandlerExt, Invocation, MapRequest, OrElse, OrElseFuture};
+
+mod warmup;
+pub use warmup::{Warmup, WarmupLayer};
+
+mod init;
+pub use init::{Init, InitError, LazyInit, LazyInitError, DEFAULT_INIT_BUDGET};
+
+mod resources;
+pub use resources::{ClosedResources, Resources, SHUTDOWN_BUDGET};
+
+mod builder;
+pub use builder::RuntimeBuilder;
+
+#[cfg(feature = "macros")]
+mod macros;
+#[cfg(feature = "macros")]
+pub use lambda_runtime_macros::main;
+
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::macros::start;
+}
 
+use codec::{Codec, JsonCodec};
+use deserializer::DeserializeError;
+use executor::{SharedExecutor, TokioExecutor};
+use limits::PayloadTooLarge;
 use requests::{EventCompletionRequest, EventErrorRequest, IntoRequest, NextEventRequest};
-pub use types::{Context, LambdaEvent};
+use serializer::SerializeError;
+pub use types::{ClientApplication, ClientContext, CognitoIdentity, Context, ContextBuilder, Diagnostic, LambdaEvent};
 
 /// Error type that lambdas may result in
 pub type Error = lambda_runtime_api_client::Error;
@@ -85,18 +201,16 @@ where
     service_fn(move |req: LambdaEvent<A>| f(req.payload, req.context))
 }
 
-struct Runtime<C: Service<http::Uri> = HttpConnector> {
-    client: Client<C>,
+struct Runtime<T, C = JsonCodec> {
+    client: T,
     config: Config,
+    executor: SharedExecutor,
+    codec: C,
+    recorder: Option<bench::SharedRecorder>,
+    alarms: alarms::Alarms,
 }
 
-impl<C> Runtime<C>
-where
-    C: Service<http::Uri> + Clone + Send + Sync + Unpin + 'static,
-    C::Future: Unpin + Send,
-    C::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-    C::Response: AsyncRead + AsyncWrite + Connection + Unpin + Send + 'static,
-{
+impl<T: Transport, C: Codec> Runtime<T, C> {
     async fn run<F, A, B>(
         &self,
         incoming: impl Stream<Item = Result<http::Response<hyper::Body>, Error>> + Send,
@@ -105,14 +219,19 @@ where
     where
         F: Service<LambdaEvent<A>>,
         F::Future: Future<Output = Result<B, F::Error>>,
-        F::Error: fmt::Debug + fmt::Display,
+        F::Error: fmt::Debug + fmt::Display + 'static,
         A: for<'de> Deserialize<'de>,
         B: Serialize,
     {
         let client = &self.client;
+        let mut first_invocation = true;
+        // The init phase ends when the runtime asks for the first event.
+        let init_duration = init::process_age();
+        let mut waiting = Instant::now();
         tokio::pin!(incoming);
         while let Some(next_event_response) = incoming.next().await {
             trace!("New event arrived (run loop)");
+            let next_event = waiting.elapsed();
             let event = next_event_response?;
             let (parts, body) = event.into_parts();
 
@@ -124,18 +243,29 @@ where
                 continue;
             }
 
-            let ctx: Context = Context::try_from(parts.headers)?;
+            let cold_start = std::mem::replace(&mut first_invocation, false);
+            let mut ctx: Context = Context::try_from(parts.headers)?;
+            ctx.cold_start = cold_start;
+            ctx.init_duration = init_duration.filter(|_| cold_start);
             let ctx: Context = ctx.with_config(&self.config);
             let request_id = &ctx.request_id.clone();
+            let init_duration = ctx.init_duration;
+            let deadline = ctx.deadline();
+            let available = deadline.duration_since(SystemTime::now()).unwrap_or_default();
 
             let request_span = match &ctx.xray_trace_id {
                 Some(trace_id) => {
                     env::set_var("_X_AMZN_TRACE_ID", trace_id);
-                    tracing::info_span!("Lambda runtime invoke", requestId = request_id, xrayTraceId = trace_id)
+                    tracing::info_span!(
+                        "Lambda runtime invoke",
+                        requestId = request_id,
+                        xrayTraceId = trace_id,
+                        coldStart = cold_start
+                    )
                 }
                 None => {
                     env::remove_var("_X_AMZN_TRACE_ID");
-                    tracing::info_span!("Lambda runtime invoke", requestId = request_id)
+                    tracing::info_span!("Lambda runtime invoke", requestId = request_id, coldStart = cold_start)
                 }
             };
 
@@ -143,6 +273,7 @@ where
             async {
                 let body = hyper::body::to_bytes(body).await?;
                 trace!("response body - {}", std::str::from_utf8(&body)?);
+                self.alarms.check_request(request_id, body.len());
 
                 #[cfg(debug_assertions)]
                 if parts.status.is_server_error() {
@@ -150,23 +281,31 @@ where
                     return Err(parts.status.to_string().into());
                 }
 
-                let lambda_event = match deserializer::deserialize(&body, ctx) {
-                    Ok(lambda_event) => lambda_event,
+                let decoding = Instant::now();
+                let lambda_event = match self.codec.decode(&body) {
+                    Ok(payload) => LambdaEvent::new(payload, ctx),
                     Err(err) => {
-                        let req = build_event_error_request(request_id, err)?;
+                        let req = build_codec_error_request(request_id, err)?;
                         client.call(req).await.expect("Unable to send response to Runtime APIs");
                         return Ok(());
                     }
                 };
 
+                let decode = decoding.elapsed();
+
+                let tasks = TaskSet::new(self.executor.clone());
+                let started = Instant::now();
+                let mut encode = Duration::ZERO;
                 let req = match handler.ready().await {
                     Ok(handler) => {
                         // Catches panics outside of a `Future`
-                        let task = panic::catch_unwind(panic::AssertUnwindSafe(|| handler.call(lambda_event)));
+                        let task = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                            tasks.sync_scope(|| handler.call(lambda_event))
+                        }));
 
                         let task = match task {
                             // Catches panics inside of the `Future`
-                            Ok(task) => panic::AssertUnwindSafe(task).catch_unwind().await,
+                            Ok(task) => panic::AssertUnwindSafe(tasks.scope(task)).catch_unwind().await,
                             Err(err) => Err(err),
                         };
 
@@ -174,11 +313,15 @@ where
                             Ok(response) => match response {
                                 Ok(response) => {
                                     trace!("Ok response from handler (run loop)");
-                                    EventCompletionRequest {
-                                        request_id,
-                                        body: response,
+                                    let encoding = Instant::now();
+                                    let req = build_event_completion_request(request_id, response, &self.codec);
+                                    encode = encoding.elapsed();
+                                    if let Ok(req) = &req {
+                                        if let Some(size) = HttpBody::size_hint(req.body()).exact() {
+                                            self.alarms.check_response(request_id, size as usize);
+                                        }
                                     }
-                                    .into_req()
+                                    req
                                 }
                                 Err(err) => build_event_error_request(request_id, err),
                             },
@@ -197,23 +340,37 @@ where
                     Err(err) => build_event_error_request(request_id, err),
                 }?;
 
+                let handler_time = started.elapsed() - encode;
+                self.alarms.check_duration(request_id, handler_time, available);
+
+                tasks.finish(deadline).await;
+                let responding = Instant::now();
                 client.call(req).await.expect("Unable to send response to Runtime APIs");
+                if let Some(recorder) = &self.recorder {
+                    recorder.record(&bench::InvocationTimings {
+                        request_id: request_id.clone(),
+                        cold_start,
+                        next_event,
+                        decode,
+                        handler: handler_time,
+                        encode,
+                        respond: responding.elapsed(),
+                        init_duration,
+                    });
+                }
+                tasks.start_deferred();
+                tasks.finish(deadline).await;
                 Ok::<(), Error>(())
             }
             .instrument(request_span)
             .await?;
+            waiting = Instant::now();
         }
         Ok(())
     }
 }
 
-fn incoming<C>(client: &Client<C>) -> impl Stream<Item = Result<http::Response<hyper::Body>, Error>> + Send + '_
-where
-    C: Service<http::Uri> + Clone + Send + Sync + Unpin + 'static,
-    <C as Service<http::Uri>>::Future: Unpin + Send,
-    <C as Service<http::Uri>>::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-    <C as Service<http::Uri>>::Response: AsyncRead + AsyncWrite + Connection + Unpin + Send + 'static,
-{
+fn incoming<T: Transport>(client: &T) -> impl Stream<Item = Result<http::Response<hyper::Body>, Error>> + Send + '_ {
     async_stream::stream! {
         loop {
             trace!("Waiting for next event (incoming loop)");
@@ -247,14 +404,135 @@ pub async fn run<A, B, F>(handler: F) -> Result<(), Error>
 where
     F: Service<LambdaEvent<A>>,
     F::Future: Future<Output = Result<B, F::Error>>,
-    F::Error: fmt::Debug + fmt::Display,
+    F::Error: fmt::Debug + fmt::Display + 'static,
+    A: for<'de> Deserialize<'de>,
+    B: Serialize,
+{
+    if ExecutionMode::detect() == ExecutionMode::Local {
+        return local::run(handler, JsonCodec::new()).await;
+    }
+    let client = runtime_client().expect("Unable to create a runtime client");
+    run_with_transport(handler, client).await
+}
+
+/// Starts the Lambda Rust runtime with a custom [`Transport`], and begins polling for events
+/// on the [Lambda Runtime APIs](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html).
+///
+/// The transport sends the runtime's requests to the Runtime API, which allows
+/// running functions on platforms where hyper's HTTP client is not available.
+pub async fn run_with_transport<A, B, F, T>(handler: F, transport: T) -> Result<(), Error>
+where
+    F: Service<LambdaEvent<A>>,
+    F::Future: Future<Output = Result<B, F::Error>>,
+    F::Error: fmt::Debug + fmt::Display + 'static,
     A: for<'de> Deserialize<'de>,
     B: Serialize,
+    T: Transport,
 {
     trace!("Loading config from env");
     let config = Config::from_env()?;
-    let client = Client::builder().build().expect("Unable to create a runtime client");
-    let runtime = Runtime { client, config };
+    let runtime = Runtime {
+        client: transport,
+        config,
+        executor: Arc::new(TokioExecutor),
+        codec: JsonCodec::new(),
+        recorder: None,
+        alarms: alarms::Alarms::default(),
+    };
+
+    let client = &runtime.client;
+    let incoming = incoming(client);
+    runtime.run(incoming, handler).await
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn runtime_client() -> Result<impl Transport, Error> {
+    lambda_runtime_api_client::Client::builder().build()
+}
+
+#[cfg(target_os = "wasi")]
+fn runtime_client() -> Result<impl Transport, Error> {
+    lambda_runtime_api_client::WasiTransport::from_env()
+}
+
+/// Starts the Lambda Rust runtime on a single-threaded tokio runtime, and blocks
+/// the current thread until it finishes.
+///
+/// Lambda sends one event at a time to each execution environment, so a
+/// multi-threaded scheduler rarely pays off for small functions. This function runs
+/// the whole poll, handle, and respond loop on the current thread, inside a
+/// [`tokio::task::LocalSet`]. The handler's future doesn't need to be `Send`,
+/// so it can hold `Rc`, `RefCell`, and other non thread-safe types, and spawn
+/// non `Send` tasks with [`tokio::task::spawn_local`]. Tasks spawned with
+/// [`spawn_traced`] must still be `Send`.
+///
+/// Call this function from a regular `main` function, not from `#[tokio::main]`.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, Error, LambdaEvent};
+/// use serde_json::Value;
+/// use std::rc::Rc;
+///
+/// fn main() -> Result<(), Error> {
+///     let greeting = Rc::new(String::from("hello"));
+///     lambda_runtime::run_local(service_fn(move |event: LambdaEvent<Value>| {
+///         let greeting = greeting.clone();
+///         async move { Ok::<_, Error>(format!("{greeting} {}", event.payload)) }
+///     }))
+/// }
+/// ```
+pub fn run_local<A, B, F>(handler: F) -> Result<(), Error>
+where
+    F: Service<LambdaEvent<A>>,
+    F::Future: Future<Output = Result<B, F::Error>>,
+    F::Error: fmt::Debug + fmt::Display + 'static,
+    A: for<'de> Deserialize<'de>,
+    B: Serialize,
+{
+    let rt = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let local = tokio::task::LocalSet::new();
+    local.block_on(&rt, run(handler))
+}
+
+/// Starts the Lambda Rust runtime on a custom [`Executor`], and begins polling for events
+/// on the [Lambda Runtime APIs](https://docs.aws.amazon.com/lambda/latest/dg/runtimes-api.html).
+///
+/// The executor spawns the runtime's tasks and creates its timers, and the connector
+/// opens the connections to the Runtime API. Together, they allow running functions
+/// without a tokio runtime, for example with `async-std`, `smol`, or a single-threaded
+/// executor, by passing the future returned by this function to that runtime's `block_on`.
+#[cfg(not(target_os = "wasi"))]
+pub async fn run_with_executor<A, B, F, E, C>(handler: F, executor: E, connector: C) -> Result<(), Error>
+where
+    F: Service<LambdaEvent<A>>,
+    F::Future: Future<Output = Result<B, F::Error>>,
+    F::Error: fmt::Debug + fmt::Display + 'static,
+    A: for<'de> Deserialize<'de>,
+    B: Serialize,
+    E: executor::Executor + 'static,
+    C: Service<http::Uri> + Clone + Send + Sync + Unpin + 'static,
+    C::Future: Unpin + Send,
+    C::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    C::Response:
+        tokio::io::AsyncRead + tokio::io::AsyncWrite + hyper::client::connect::Connection + Unpin + Send + 'static,
+{
+    trace!("Loading config from env");
+    let config = Config::from_env()?;
+    let executor: SharedExecutor = Arc::new(executor);
+    let client = lambda_runtime_api_client::Client::builder()
+        .with_connector(connector)
+        .with_executor(executor::HyperExecutor(executor.clone()))
+        .build()
+        .expect("Unable to create a runtime client");
+    let runtime = Runtime {
+        client,
+        config,
+        executor,
+        codec: JsonCodec::new(),
+        recorder: None,
+        alarms: alarms::Alarms::default(),
+    };
 
     let client = &runtime.client;
     let incoming = incoming(client);
@@ -265,15 +543,75 @@ fn type_name_of_val<T>(_: T) -> &'static str {
     std::any::type_name::<T>()
 }
 
+fn build_event_completion_request<T, C>(request_id: &str, body: T, codec: &C) -> Result<Request<Body>, Error>
+where
+    T: Serialize,
+    C: Codec,
+{
+    let req = EventCompletionRequest {
+        request_id,
+        body,
+        codec,
+    }
+    .into_req();
+    let err = match req {
+        Ok(req) => return Ok(req),
+        Err(err) => err,
+    };
+    // Report oversized responses as function errors, so the caller
+    // knows which limit was exceeded instead of getting an opaque 413.
+    match err.downcast::<PayloadTooLarge>() {
+        Ok(err) => build_event_error_request(request_id, *err),
+        // Report responses that can't be encoded as function errors too,
+        // a single bad value must not take down the execution environment.
+        Err(err) => build_codec_error_request(request_id, err),
+    }
+}
+
+// Codecs return boxed errors, the errors of the JSON codec are unboxed
+// so they're reported with their own type name.
+fn build_codec_error_request(request_id: &str, err: Error) -> Result<Request<Body>, Error> {
+    let err = match err.downcast::<DeserializeError>() {
+        Ok(err) => return build_event_error_request(request_id, *err),
+        Err(err) => err,
+    };
+    match err.downcast::<SerializeError>() {
+        Ok(err) => build_event_error_request(request_id, *err),
+        Err(err) => build_event_error_request(request_id, err),
+    }
+}
+
 fn build_event_error_request<T>(request_id: &str, err: T) -> Result<Request<Body>, Error>
 where
-    T: Display + Debug,
+    T: Display + Debug + 'static,
 {
     error!("{:?}", err); // logs the error in CloudWatch
-    let error_type = type_name_of_val(&err);
-    let msg = format!("{err}");
+    let diagnostic = diagnostic_for(&err);
+    if diagnostic.error_message().len() > limits::ERROR_MESSAGE_LIMIT {
+        warn!(
+            size = diagnostic.error_message().len(),
+            limit = limits::ERROR_MESSAGE_LIMIT,
+            "error message truncated in the invocation error, the complete error is in the previous log line"
+        );
+    }
 
-    EventErrorRequest::new(request_id, error_type, &msg).into_req()
+    EventErrorRequest { request_id, diagnostic }.into_req()
+}
+
+// Handlers choose the reported error by failing with a `Diagnostic`,
+// either directly or boxed in an `Error`.
+pub(crate) fn diagnostic_for<T: Display + 'static>(err: &T) -> Diagnostic {
+    let any = err as &dyn std::any::Any;
+    let diagnostic = match any.downcast_ref::<Diagnostic>() {
+        Some(diagnostic) => Some(diagnostic),
+        None => any
+            .downcast_ref::<Error>()
+            .and_then(|err| err.downcast_ref::<Diagnostic>()),
+    };
+    match diagnostic {
+        Some(diagnostic) => diagnostic.clone(),
+        None => Diagnostic::new(type_name_of_val(err), err.to_string()),
+    }
 }
 
 #[cfg(test)]
@@ -290,7 +628,7 @@ mod endpoint_tests {
     use futures::future::BoxFuture;
     use http::{uri::PathAndQuery, HeaderValue, Method, Request, Response, StatusCode, Uri};
     use hyper::{server::conn::Http, service::service_fn, Body};
-    use lambda_runtime_api_client::Client;
+    use lambda_runtime_api_client::{Client, Transport};
     use serde_json::json;
     use simulated::DuplexStreamWrapper;
     use std::{convert::TryFrom, env};
@@ -430,6 +768,7 @@ mod endpoint_tests {
         let req = EventCompletionRequest {
             request_id: "156cb537-e2d4-11e8-9b34-d36013741fb9",
             body: "done",
+            codec: &crate::codec::JsonCodec::new(),
         };
         let req = req.into_req()?;
 
@@ -460,10 +799,7 @@ mod endpoint_tests {
 
         let req = EventErrorRequest {
             request_id: "156cb537-e2d4-11e8-9b34-d36013741fb9",
-            diagnostic: Diagnostic {
-                error_type: "InvalidEventDataError",
-                error_message: "Error parsing event data",
-            },
+            diagnostic: Diagnostic::new("InvalidEventDataError", "Error parsing event data"),
         };
         let req = req.into_req()?;
         let rsp = client.call(req).await?;
@@ -478,6 +814,170 @@ mod endpoint_tests {
         }
     }
 
+    #[test]
+    fn serialization_errors_are_reported_as_function_errors() {
+        let body = std::collections::HashMap::from([((1, 2), 3)]);
+        let req = crate::build_event_completion_request("id", body, &crate::codec::JsonCodec::new()).unwrap();
+        assert_eq!("/2018-06-01/runtime/invocation/id/error", req.uri().path());
+    }
+
+    #[tokio::test]
+    async fn diagnostics_choose_the_reported_error() {
+        let diagnostic = Diagnostic::new("Order.Missing", "no order").with_function_error_type("Function.Invalid");
+        let err: Error = diagnostic.into();
+        let req = crate::build_event_error_request("id", err).unwrap();
+        assert_eq!("Function.Invalid", req.headers()["lambda-runtime-function-error-type"]);
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json!({"errorType": "Order.Missing", "errorMessage": "no order"}), body);
+
+        let req =
+            crate::build_event_error_request("id", std::io::Error::new(std::io::ErrorKind::Other, "boom")).unwrap();
+        assert_eq!("unhandled", req.headers()["lambda-runtime-function-error-type"]);
+
+        let diagnostic = Diagnostic::new("Order.Missing", "no order").with_function_error_type("Function.Invalid");
+        let req = crate::build_event_error_request("id", diagnostic.clone()).unwrap();
+        assert_eq!("Function.Invalid", req.headers()["lambda-runtime-function-error-type"]);
+
+        // A diagnostic wrapped in a longer message isn't the reported error.
+        let err = format!("retries exhausted: {diagnostic}");
+        assert_ne!("Order.Missing", crate::diagnostic_for(&err).error_type());
+    }
+
+    #[tokio::test]
+    async fn run_with_custom_transport() -> Result<(), Error> {
+        // Answers the runtime's requests in memory, without an HTTP client.
+        #[derive(Default)]
+        struct InMemoryTransport(std::sync::Mutex<Vec<String>>);
+
+        impl Transport for InMemoryTransport {
+            fn call(&self, req: Request<Body>) -> lambda_runtime_api_client::TransportFuture<'_> {
+                self.0.lock().unwrap().push(req.uri().path().to_string());
+                Box::pin(handle_incoming(req))
+            }
+        }
+
+        let runtime = Runtime {
+            client: InMemoryTransport::default(),
+            config: crate::Config::default(),
+            executor: std::sync::Arc::new(crate::executor::TokioExecutor),
+            codec: crate::codec::JsonCodec::new(),
+            recorder: None,
+            alarms: crate::alarms::Alarms::default(),
+        };
+        let incoming = incoming(&runtime.client).take(1);
+        let f =
+            crate::service_fn(
+                |event: crate::LambdaEvent<serde_json::Value>| async move { Ok::<_, Error>(event.payload) },
+            );
+        runtime.run(incoming, f).await?;
+
+        assert_eq!(
+            vec![
+                "/2018-06-01/runtime/invocation/next",
+                "/2018-06-01/runtime/invocation/8476a536-e9f4-11e8-9739-2dfe598c3fcd/response",
+            ],
+            *runtime.client.0.lock().unwrap()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn run_waits_for_nested_background_tasks() -> Result<(), Error> {
+        // Logs the runtime's requests and the end of the background task in order,
+        // with a deadline far enough away that the task isn't aborted.
+        #[derive(Clone, Default)]
+        struct InMemoryTransport(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+        impl Transport for InMemoryTransport {
+            fn call(&self, req: Request<Body>) -> lambda_runtime_api_client::TransportFuture<'_> {
+                self.0.lock().unwrap().push(req.uri().path().to_string());
+                Box::pin(async move {
+                    let mut rsp = handle_incoming(req).await?;
+                    let deadline = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+                    let deadline = deadline.duration_since(std::time::UNIX_EPOCH)?.as_millis();
+                    rsp.headers_mut()
+                        .insert("lambda-runtime-deadline-ms", HeaderValue::from(deadline as u64));
+                    Ok(rsp)
+                })
+            }
+        }
+
+        let log = InMemoryTransport::default();
+        let runtime = Runtime {
+            client: log.clone(),
+            config: crate::Config::default(),
+            executor: std::sync::Arc::new(crate::executor::TokioExecutor),
+            codec: crate::codec::JsonCodec::new(),
+            recorder: None,
+            alarms: crate::alarms::Alarms::default(),
+        };
+        let incoming = incoming(&runtime.client).take(1);
+        let f = crate::service_fn(|event: crate::LambdaEvent<serde_json::Value>| {
+            let log = log.clone();
+            async move {
+                crate::spawn_traced(async move {
+                    crate::spawn_traced(async move {
+                        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                        log.0.lock().unwrap().push("inner task".to_string());
+                    });
+                });
+                Ok::<_, Error>(event.payload)
+            }
+        });
+        runtime.run(incoming, f).await?;
+
+        assert_eq!(
+            vec![
+                "/2018-06-01/runtime/invocation/next",
+                "inner task",
+                "/2018-06-01/runtime/invocation/8476a536-e9f4-11e8-9739-2dfe598c3fcd/response",
+            ],
+            *runtime.client.0.lock().unwrap()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn run_records_invocation_timings() -> Result<(), Error> {
+        struct InMemoryTransport;
+
+        impl Transport for InMemoryTransport {
+            fn call(&self, req: Request<Body>) -> lambda_runtime_api_client::TransportFuture<'_> {
+                Box::pin(handle_incoming(req))
+            }
+        }
+
+        let timings = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = timings.clone();
+        let runtime = Runtime {
+            client: InMemoryTransport,
+            config: crate::Config::default(),
+            executor: std::sync::Arc::new(crate::executor::TokioExecutor),
+            codec: crate::codec::JsonCodec::new(),
+            recorder: Some(std::sync::Arc::new(move |t: &crate::bench::InvocationTimings| {
+                recorded.lock().unwrap().push(t.clone())
+            })),
+            alarms: crate::alarms::Alarms::default(),
+        };
+        let incoming = incoming(&runtime.client).take(2);
+        let f =
+            crate::service_fn(
+                |event: crate::LambdaEvent<serde_json::Value>| async move { Ok::<_, Error>(event.payload) },
+            );
+        runtime.run(incoming, f).await?;
+
+        let timings = timings.lock().unwrap();
+        assert_eq!(2, timings.len());
+        assert_eq!("8476a536-e9f4-11e8-9739-2dfe598c3fcd", timings[0].request_id);
+        assert!(timings[0].cold_start);
+        assert!(timings[0].init_duration.is_some());
+        assert!(!timings[1].cold_start);
+        assert_eq!(None, timings[1].init_duration);
+        assert_eq!(timings[0].total(), timings[0].overhead() + timings[0].handler);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn successful_end_to_end_run() -> Result<(), Error> {
         let (client, server) = io::duplex(64);
@@ -522,7 +1022,14 @@ mod endpoint_tests {
         }
         let config = crate::Config::from_env().expect("Failed to read env vars");
 
-        let runtime = Runtime { client, config };
+        let runtime = Runtime {
+            client,
+            config,
+            executor: std::sync::Arc::new(crate::executor::TokioExecutor),
+            codec: crate::codec::JsonCodec::new(),
+            recorder: None,
+            alarms: crate::alarms::Alarms::default(),
+        };
         let client = &runtime.client;
         let incoming = incoming(client).take(1);
         runtime.run(incoming, f).await?;
@@ -565,7 +1072,14 @@ mod endpoint_tests {
             log_group: "test_log".to_string(),
         };
 
-        let runtime = Runtime { client, config };
+        let runtime = Runtime {
+            client,
+            config,
+            executor: std::sync::Arc::new(crate::executor::TokioExecutor),
+            codec: crate::codec::JsonCodec::new(),
+            recorder: None,
+            alarms: crate::alarms::Alarms::default(),
+        };
         let client = &runtime.client;
         let incoming = incoming(client).take(1);
         runtime.run(incoming, f).await?;
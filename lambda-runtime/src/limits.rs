@@ -0,0 +1,158 @@
+//! The Lambda service rejects payloads that exceed these limits with an opaque
+//! `413` status code. The runtime checks the responses that it sends against the
+//! limit of the invoke mode in use, and reports a [`PayloadTooLarge`] error to the
+//! caller instead, with the measured size of the payload.
+use std::{borrow::Cow, fmt};
+
+/// Maximum size in bytes of a buffered invocation request or response.
+pub const BUFFERED_PAYLOAD_LIMIT: usize = 6 * 1024 * 1024;
+
+/// Maximum size in bytes of a streamed invocation response.
+pub const STREAMING_PAYLOAD_LIMIT: usize = 20 * 1024 * 1024;
+
+/// Maximum size in bytes of an asynchronous invocation event.
+pub const ASYNC_PAYLOAD_LIMIT: usize = 256 * 1024;
+
+/// Maximum size in bytes of the error message reported to the Runtime API.
+///
+/// Longer messages are truncated, the complete error is written to the function's logs.
+pub const ERROR_MESSAGE_LIMIT: usize = 64 * 1024;
+
+/// Maximum size in bytes of the error type reported to the Runtime API.
+pub const ERROR_TYPE_LIMIT: usize = 1024;
+
+/// How a function is invoked, which determines the maximum payload size.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InvokeMode {
+    /// Synchronous invocation with a buffered response.
+    Buffered,
+    /// Synchronous invocation with a streamed response.
+    Streaming,
+    /// Asynchronous invocation, where the event is queued by the Lambda service.
+    Async,
+}
+
+impl InvokeMode {
+    /// Return the maximum payload size in bytes for this invoke mode.
+    pub fn limit(&self) -> usize {
+        match self {
+            InvokeMode::Buffered => BUFFERED_PAYLOAD_LIMIT,
+            InvokeMode::Streaming => STREAMING_PAYLOAD_LIMIT,
+            InvokeMode::Async => ASYNC_PAYLOAD_LIMIT,
+        }
+    }
+
+    /// Check that a payload of `size` bytes fits in the limit of this invoke mode.
+    pub fn check(&self, size: usize) -> Result<(), PayloadTooLarge> {
+        if size > self.limit() {
+            return Err(PayloadTooLarge { mode: *self, size });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for InvokeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InvokeMode::Buffered => "buffered",
+            InvokeMode::Streaming => "streaming",
+            InvokeMode::Async => "async",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Error returned when a payload exceeds the limit of its invoke mode.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PayloadTooLarge {
+    /// The invoke mode whose limit was exceeded.
+    pub mode: InvokeMode,
+    /// The measured size of the payload in bytes.
+    pub size: usize,
+}
+
+impl PayloadTooLarge {
+    /// Return the limit in bytes that was exceeded.
+    pub fn limit(&self) -> usize {
+        self.mode.limit()
+    }
+}
+
+impl fmt::Display for PayloadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the payload is {} bytes, which exceeds the {} payload limit of {} bytes",
+            self.size,
+            self.mode,
+            self.limit()
+        )
+    }
+}
+
+impl std::error::Error for PayloadTooLarge {}
+
+/// Truncate `message` to at most `limit` bytes, on a UTF-8 character boundary.
+///
+/// Truncated messages end with an indicator of the number of bytes that were removed.
+///
+/// # Example
+/// ```
+/// use lambda_runtime::limits::truncate_message;
+///
+/// assert_eq!("short", truncate_message("short", 32));
+/// assert_eq!("aaaaaaaaa... [41 bytes truncated]", truncate_message(&"a".repeat(50), 33));
+/// ```
+pub fn truncate_message(message: &str, limit: usize) -> Cow<'_, str> {
+    if message.len() <= limit {
+        return Cow::Borrowed(message);
+    }
+
+    // The indicator can't be longer than the one for the whole message,
+    // reserve that much room so the result always fits in the limit.
+    let reserved = truncation_indicator(message.len()).len();
+    let mut end = limit.saturating_sub(reserved);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let mut truncated = message[..end].to_string();
+    truncated.push_str(&truncation_indicator(message.len() - end));
+    Cow::Owned(truncated)
+}
+
+fn truncation_indicator(removed: usize) -> String {
+    format!("... [{removed} bytes truncated]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_payload_limits() {
+        assert!(InvokeMode::Buffered.check(BUFFERED_PAYLOAD_LIMIT).is_ok());
+        assert!(InvokeMode::Streaming.check(BUFFERED_PAYLOAD_LIMIT + 1).is_ok());
+
+        let err = InvokeMode::Async.check(ASYNC_PAYLOAD_LIMIT + 1).unwrap_err();
+        assert_eq!(ASYNC_PAYLOAD_LIMIT, err.limit());
+        assert_eq!(
+            "the payload is 262145 bytes, which exceeds the async payload limit of 262144 bytes",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn truncate_messages_on_char_boundaries() {
+        let message = "é".repeat(100);
+        let truncated = truncate_message(&message, 50);
+        assert!(truncated.len() <= 50);
+        assert!(truncated.starts_with("éééééééééé"));
+        assert!(truncated.ends_with("... [176 bytes truncated]"));
+
+        assert_eq!(
+            Cow::Borrowed(message.as_str()),
+            truncate_message(&message, message.len())
+        );
+    }
+}
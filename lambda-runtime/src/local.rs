@@ -0,0 +1,353 @@
+use crate::{alarms::Alarms, codec::Codec, executor::TokioExecutor, incoming, Config, Error, LambdaEvent, Runtime};
+use bytes::Bytes;
+use http::{Method, Request, Response, StatusCode};
+use hyper::Body;
+use lambda_runtime_api_client::{Transport, TransportFuture};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    env, fmt,
+    future::Future,
+    io::Read,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::sync::{mpsc, oneshot};
+use tower::Service;
+use tracing::{debug, info};
+
+/// Environment variable with the path of a file with the events to send to the function in local mode.
+pub const LOCAL_EVENT_FILE_VAR: &str = "LAMBDA_EVENT_FILE";
+
+/// Environment variable with the port of the HTTP endpoint that receives events in local mode.
+pub const LOCAL_PORT_VAR: &str = "LAMBDA_LOCAL_PORT";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(900);
+const LOCAL_FUNCTION_ARN: &str = "arn:aws:lambda:us-east-1:000000000000:function:local";
+
+/// Where the function is running, detected from the environment variables set by Lambda.
+///
+/// [`run`](crate::run) reads this mode when the function starts. Functions that run
+/// in [`ExecutionMode::Local`] don't talk to a Runtime API. They read their events
+/// from a file set in the `LAMBDA_EVENT_FILE` environment variable, or from the
+/// standard input, and print their responses to the standard output, so they can be
+/// debugged with `cargo run < event.json`. Files and inputs can hold several JSON
+/// documents, which are sent to the function one after the other.
+///
+/// When the `LAMBDA_LOCAL_PORT` environment variable is set, local functions listen
+/// for events on that port instead. Every HTTP request sent to `127.0.0.1:<port>`
+/// is an invocation, with the request body as the event, and gets the response of
+/// the function, or its error with a `500` status code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// Running in the Lambda service.
+    Lambda,
+    /// Running under the Runtime Interface Emulator, which implements the Runtime API
+    /// but doesn't set the `AWS_LAMBDA_INITIALIZATION_TYPE` environment variable.
+    Emulator,
+    /// Running without a Runtime API, because `AWS_LAMBDA_RUNTIME_API` is not set.
+    Local,
+}
+
+impl ExecutionMode {
+    /// Detect the execution mode from the environment variables of the process.
+    pub fn detect() -> Self {
+        if env::var_os("AWS_LAMBDA_RUNTIME_API").is_none() {
+            ExecutionMode::Local
+        } else if env::var_os("AWS_LAMBDA_INITIALIZATION_TYPE").is_none() {
+            ExecutionMode::Emulator
+        } else {
+            ExecutionMode::Lambda
+        }
+    }
+}
+
+/// Run `handler` without a Runtime API, with the events set in the environment.
+pub(crate) async fn run<A, B, F, C>(handler: F, codec: C) -> Result<(), Error>
+where
+    F: Service<LambdaEvent<A>>,
+    F::Future: Future<Output = Result<B, F::Error>>,
+    F::Error: fmt::Debug + fmt::Display + 'static,
+    A: for<'de> Deserialize<'de>,
+    B: Serialize,
+    C: Codec,
+{
+    let source = match env::var(LOCAL_PORT_VAR) {
+        Ok(port) => http_source(port.parse()?).await?,
+        Err(_) => Source::Events(Mutex::new(read_events()?)),
+    };
+    let runtime = Runtime {
+        client: LocalTransport::new(source),
+        config: local_config(),
+        executor: Arc::new(TokioExecutor),
+        codec,
+        recorder: None,
+        alarms: Alarms::default(),
+    };
+
+    let result = runtime.run(incoming(&runtime.client), handler).await;
+    match result {
+        Err(err) if err.is::<EventsExhausted>() => {}
+        other => return other,
+    }
+    match runtime.client.failures.load(Ordering::SeqCst) {
+        0 => Ok(()),
+        failures => Err(format!("{failures} local invocation(s) failed").into()),
+    }
+}
+
+fn local_config() -> Config {
+    Config {
+        function_name: env::var("AWS_LAMBDA_FUNCTION_NAME").unwrap_or_else(|_| "local".to_string()),
+        memory: env::var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE")
+            .ok()
+            .and_then(|memory| memory.parse().ok())
+            .unwrap_or(128),
+        version: env::var("AWS_LAMBDA_FUNCTION_VERSION").unwrap_or_else(|_| "$LATEST".to_string()),
+        log_stream: env::var("AWS_LAMBDA_LOG_STREAM_NAME").unwrap_or_default(),
+        log_group: env::var("AWS_LAMBDA_LOG_GROUP_NAME").unwrap_or_default(),
+    }
+}
+
+// Read every JSON document in the event file, or in the standard input.
+fn read_events() -> Result<VecDeque<Bytes>, Error> {
+    let input = match env::var(LOCAL_EVENT_FILE_VAR) {
+        Ok(path) => {
+            info!(path, "running locally, reading events from file");
+            std::fs::read(path)?
+        }
+        Err(_) => {
+            info!("running locally, reading events from stdin");
+            let mut input = Vec::new();
+            std::io::stdin().read_to_end(&mut input)?;
+            input
+        }
+    };
+    split_events(&input)
+}
+
+fn split_events(input: &[u8]) -> Result<VecDeque<Bytes>, Error> {
+    let mut events = VecDeque::new();
+    let mut documents = serde_json::Deserializer::from_slice(input).into_iter::<serde::de::IgnoredAny>();
+    loop {
+        let start = documents.byte_offset();
+        match documents.next() {
+            Some(document) => document?,
+            None => break,
+        };
+        let event = String::from_utf8_lossy(&input[start..documents.byte_offset()]);
+        events.push_back(Bytes::from(event.trim().to_string()));
+    }
+    Ok(events)
+}
+
+/// Returned by the local transport when there are no events left.
+#[derive(Debug)]
+struct EventsExhausted;
+
+impl fmt::Display for EventsExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("no local events left")
+    }
+}
+
+impl std::error::Error for EventsExhausted {}
+
+type Invocation = (Bytes, oneshot::Sender<(StatusCode, Bytes)>);
+
+enum Source {
+    Events(Mutex<VecDeque<Bytes>>),
+    #[cfg_attr(target_os = "wasi", allow(dead_code))]
+    Http(tokio::sync::Mutex<mpsc::Receiver<Invocation>>),
+}
+
+/// Answers the requests of the runtime like the Runtime API, with local events.
+struct LocalTransport {
+    source: Source,
+    pending: Mutex<Option<oneshot::Sender<(StatusCode, Bytes)>>>,
+    invocations: AtomicU64,
+    failures: AtomicU64,
+    timeout: Duration,
+}
+
+impl LocalTransport {
+    fn new(source: Source) -> Self {
+        let timeout = env::var("AWS_LAMBDA_FUNCTION_TIMEOUT")
+            .ok()
+            .and_then(|timeout| timeout.parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TIMEOUT);
+        LocalTransport {
+            source,
+            pending: Mutex::new(None),
+            invocations: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            timeout,
+        }
+    }
+
+    async fn next_event(&self) -> Result<Response<Body>, Error> {
+        let event = match &self.source {
+            Source::Events(events) => events.lock().expect("local events poisoned").pop_front(),
+            Source::Http(invocations) => invocations.lock().await.recv().await.map(|(event, reply)| {
+                *self.pending.lock().expect("local invocation poisoned") = Some(reply);
+                event
+            }),
+        };
+        let event = event.ok_or(EventsExhausted)?;
+
+        let invocation = self.invocations.fetch_add(1, Ordering::SeqCst) + 1;
+        let deadline = (SystemTime::now() + self.timeout)
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis();
+        debug!(invocation, "sending local event");
+        Ok(Response::builder()
+            .header("lambda-runtime-aws-request-id", format!("local-{invocation}"))
+            .header("lambda-runtime-deadline-ms", deadline.to_string())
+            .header("lambda-runtime-invoked-function-arn", LOCAL_FUNCTION_ARN)
+            .body(Body::from(event))?)
+    }
+
+    fn complete(&self, status: StatusCode, body: Bytes) {
+        let reply = self.pending.lock().expect("local invocation poisoned").take();
+        match reply {
+            Some(reply) => {
+                let _ = reply.send((status, body));
+            }
+            None if status.is_success() => println!("{}", String::from_utf8_lossy(&body)),
+            None => eprintln!("{}", String::from_utf8_lossy(&body)),
+        }
+    }
+}
+
+impl Transport for LocalTransport {
+    fn call(&self, req: Request<Body>) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let path = req.uri().path().to_string();
+            if req.method() == Method::GET && path.ends_with("/invocation/next") {
+                return self.next_event().await;
+            }
+
+            let body = hyper::body::to_bytes(req.into_body()).await?;
+            if path.ends_with("/response") {
+                self.complete(StatusCode::OK, body);
+            } else if path.ends_with("/error") {
+                self.failures.fetch_add(1, Ordering::SeqCst);
+                self.complete(StatusCode::INTERNAL_SERVER_ERROR, body);
+            } else {
+                return Ok(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty())?);
+            }
+            Ok(Response::builder().status(StatusCode::ACCEPTED).body(Body::empty())?)
+        })
+    }
+}
+
+// Listen for invocations on `127.0.0.1:<port>`.
+#[cfg(not(target_os = "wasi"))]
+async fn http_source(port: u16) -> Result<Source, Error> {
+    use hyper::{header::CONTENT_TYPE, server::conn::Http, service::service_fn};
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
+    info!(port, "running locally, send events to http://127.0.0.1:{port}");
+    let (tx, rx) = mpsc::channel::<Invocation>(1);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let tx = tx.clone();
+            let service = service_fn(move |req: Request<Body>| {
+                let tx = tx.clone();
+                async move {
+                    let event = hyper::body::to_bytes(req.into_body()).await?;
+                    let (reply, outcome) = oneshot::channel();
+                    tx.send((event, reply)).await.map_err(|_| "local runtime stopped")?;
+                    let (status, body) = outcome.await?;
+                    Response::builder()
+                        .status(status)
+                        .header(CONTENT_TYPE, "application/json")
+                        .body(Body::from(body))
+                        .map_err(Error::from)
+                }
+            });
+            tokio::spawn(Http::new().http1_only(true).serve_connection(stream, service));
+        }
+    });
+    Ok(Source::Http(tokio::sync::Mutex::new(rx)))
+}
+
+#[cfg(target_os = "wasi")]
+async fn http_source(_port: u16) -> Result<Source, Error> {
+    Err(format!("{LOCAL_PORT_VAR} is not supported on WASI, send events through stdin instead").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec::JsonCodec, service_fn};
+    use serde_json::Value;
+
+    fn runtime(source: Source) -> Runtime<LocalTransport> {
+        Runtime {
+            client: LocalTransport::new(source),
+            config: local_config(),
+            executor: Arc::new(TokioExecutor),
+            codec: JsonCodec::new(),
+            recorder: None,
+            alarms: Alarms::default(),
+        }
+    }
+
+    async fn run_events(runtime: &Runtime<LocalTransport>) -> Result<(), Error> {
+        let handler = service_fn(|event: LambdaEvent<Value>| async move {
+            match event.payload.get("fail") {
+                Some(_) => Err(Error::from("failed")),
+                None => Ok(event.context.request_id),
+            }
+        });
+        runtime.run(incoming(&runtime.client), handler).await
+    }
+
+    #[test]
+    fn splits_concatenated_documents() {
+        let events = split_events(b"{\"a\": 1}\n{\"b\": [2]}  3").unwrap();
+        let events = events
+            .iter()
+            .map(|e| std::str::from_utf8(e).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["{\"a\": 1}", "{\"b\": [2]}", "3"], events);
+        assert!(split_events(b"{\"a\": ").is_err());
+    }
+
+    #[tokio::test]
+    async fn runs_every_local_event() {
+        let events = split_events(br#"{"id": 1} {"fail": true} {"id": 3}"#).unwrap();
+        let runtime = runtime(Source::Events(Mutex::new(events)));
+        let err = run_events(&runtime).await.unwrap_err();
+        assert!(err.is::<EventsExhausted>(), "{err}");
+        assert_eq!(3, runtime.client.invocations.load(Ordering::SeqCst));
+        assert_eq!(1, runtime.client.failures.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn answers_http_invocations() {
+        let (tx, rx) = mpsc::channel(1);
+        let runtime = runtime(Source::Http(tokio::sync::Mutex::new(rx)));
+        let (reply, outcome) = oneshot::channel();
+        tx.send((Bytes::from_static(b"{}"), reply)).await.unwrap();
+        drop(tx);
+
+        let err = run_events(&runtime).await.unwrap_err();
+        assert!(err.is::<EventsExhausted>(), "{err}");
+        let (status, body) = outcome.await.unwrap();
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(&b"\"local-1\""[..], &body[..]);
+    }
+
+    #[test]
+    fn detects_local_mode() {
+        if env::var_os("AWS_LAMBDA_RUNTIME_API").is_none() {
+            assert_eq!(ExecutionMode::Local, ExecutionMode::detect());
+        }
+    }
+}
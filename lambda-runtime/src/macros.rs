@@ -0,0 +1,27 @@
+use crate::Error;
+use std::future::Future;
+
+/// Start the runtime of a function defined with `#[lambda_runtime::main]`.
+///
+/// The `tracing` subscriber is configured for CloudWatch Logs: without colors,
+/// timestamps, or module names, and at the `INFO` level.
+pub fn start<F>(tracing: bool, future: F) -> Result<(), Error>
+where
+    F: Future<Output = Result<(), Error>>,
+{
+    if tracing {
+        // A subscriber set by a dependency takes precedence.
+        let _ = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::INFO)
+            .with_target(false)
+            .with_ansi(false)
+            .without_time()
+            .try_init();
+    }
+
+    #[cfg(not(target_os = "wasi"))]
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    #[cfg(target_os = "wasi")]
+    let mut builder = tokio::runtime::Builder::new_current_thread();
+    builder.enable_all().build()?.block_on(future)
+}
@@ -0,0 +1,472 @@
+//! Lambda bills functions by their configured memory, but only reports the peak
+//! memory of the execution environment in the `REPORT` log line. [`MemoryLayer`]
+//! samples the memory of the process before and after every invocation, so the
+//! memory used by each event, and the trend across warm invocations, can be
+//! charted next to the configured size to right-size the function.
+//!
+//! Samples come from a [`MemorySampler`]. The default [`ProcStatusSampler`] reads
+//! the resident set size and its high-water mark from `/proc/self/status`.
+//! Allocators that keep their own statistics, like jemalloc, can report the size
+//! of the heap with their own sampler. With the `jemalloc` or `mimalloc` features,
+//! the layer uses the `AllocatorSampler` of the installed allocator by default.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{
+//!     memory::{EmfMemoryRecorder, MemoryLayer},
+//!     service_fn,
+//!     tower::Layer,
+//!     Error, LambdaEvent,
+//! };
+//! use serde_json::Value;
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let func = MemoryLayer::new()
+//!         .with_recorder(EmfMemoryRecorder::new("MyApp/Memory"))
+//!         .layer(service_fn(func));
+//!     lambda_runtime::run(func).await
+//! }
+//! ```
+use crate::LambdaEvent;
+use serde_json::{json, Map, Value};
+use std::{
+    env, fmt, fs,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tower::{Layer, Service};
+use tracing::info;
+
+/// The memory of the process at one point in time, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MemorySample {
+    /// The resident set size of the process.
+    pub resident: u64,
+    /// The highest resident set size of the process since it started, when the sampler knows it.
+    pub peak: Option<u64>,
+    /// The memory allocated on the heap, when the sampler knows it.
+    pub heap: Option<u64>,
+}
+
+impl MemorySample {
+    /// Create a sample with the resident set size of the process.
+    pub fn new(resident: u64) -> Self {
+        MemorySample {
+            resident,
+            ..Default::default()
+        }
+    }
+
+    /// Set the highest resident set size of the process.
+    pub fn with_peak(self, peak: u64) -> Self {
+        MemorySample {
+            peak: Some(peak),
+            ..self
+        }
+    }
+
+    /// Set the memory allocated on the heap.
+    pub fn with_heap(self, heap: u64) -> Self {
+        MemorySample {
+            heap: Some(heap),
+            ..self
+        }
+    }
+}
+
+/// Source of the [`MemorySample`]s of a [`MemoryLayer`].
+pub trait MemorySampler: Send + Sync {
+    /// Sample the memory of the process, or return `None` when it's not available.
+    fn sample(&self) -> Option<MemorySample>;
+}
+
+/// Sampler that reads `VmRSS` and `VmHWM` from `/proc/self/status`.
+///
+/// It returns `None` on platforms without a `/proc` file system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcStatusSampler;
+
+impl MemorySampler for ProcStatusSampler {
+    fn sample(&self) -> Option<MemorySample> {
+        parse_status(&fs::read_to_string("/proc/self/status").ok()?)
+    }
+}
+
+fn parse_status(status: &str) -> Option<MemorySample> {
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .and_then(|value| value.trim().strip_suffix("kB"))
+            .and_then(|kb| kb.trim().parse::<u64>().ok())
+            .map(|kb| kb * 1024)
+    };
+    let mut sample = MemorySample::new(field("VmRSS")?);
+    sample.peak = field("VmHWM");
+    Some(sample)
+}
+
+/// The memory of the process around one invocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MemoryReport {
+    /// The AWS request ID of the invocation.
+    pub request_id: String,
+    /// The memory before the handler was called.
+    pub before: MemorySample,
+    /// The memory after the handler completed.
+    pub after: MemorySample,
+    /// The memory configured for the function, in bytes, when it's known.
+    pub limit: Option<u64>,
+}
+
+impl MemoryReport {
+    /// The change of the resident set size during the invocation, in bytes.
+    pub fn resident_delta(&self) -> i64 {
+        self.after.resident as i64 - self.before.resident as i64
+    }
+
+    /// The change of the heap during the invocation, in bytes, when the sampler knows it.
+    pub fn heap_delta(&self) -> Option<i64> {
+        Some(self.after.heap? as i64 - self.before.heap? as i64)
+    }
+
+    /// The highest resident set size of the process, or the current one when it's not known.
+    pub fn peak(&self) -> u64 {
+        self.after.peak.unwrap_or(self.after.resident)
+    }
+
+    /// The share of the configured memory used at the peak, in percent.
+    pub fn peak_utilization(&self) -> Option<f64> {
+        let limit = self.limit.filter(|limit| *limit > 0)?;
+        Some(self.peak() as f64 * 100.0 / limit as f64)
+    }
+}
+
+/// Destination of the [`MemoryReport`]s of a [`MemoryLayer`].
+///
+/// Recorders are called after the handler completes, so they should not block.
+/// Closures that take `&MemoryReport` are recorders.
+pub trait MemoryRecorder: Send + Sync {
+    /// Record the memory of one invocation.
+    fn record(&self, report: &MemoryReport);
+}
+
+impl<F> MemoryRecorder for F
+where
+    F: Fn(&MemoryReport) + Send + Sync,
+{
+    fn record(&self, report: &MemoryReport) {
+        self(report)
+    }
+}
+
+/// Recorder that logs the reports with `tracing`, at the `INFO` level.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogMemoryRecorder;
+
+impl MemoryRecorder for LogMemoryRecorder {
+    fn record(&self, report: &MemoryReport) {
+        info!(
+            request_id = %report.request_id,
+            resident = report.after.resident,
+            resident_delta = report.resident_delta(),
+            peak = report.peak(),
+            heap = report.after.heap,
+            peak_utilization = report.peak_utilization(),
+            "invocation memory"
+        );
+    }
+}
+
+/// Recorder that prints the reports in the
+/// [CloudWatch Embedded Metric Format](https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html).
+///
+/// Metrics are published with the function name and memory size as dimensions.
+#[derive(Debug, Clone)]
+pub struct EmfMemoryRecorder {
+    namespace: String,
+    function_name: String,
+    memory_size: String,
+}
+
+impl EmfMemoryRecorder {
+    /// Create a recorder that publishes metrics in `namespace`, for the function
+    /// described by the Lambda environment variables.
+    pub fn new(namespace: impl Into<String>) -> Self {
+        EmfMemoryRecorder {
+            namespace: namespace.into(),
+            function_name: env::var("AWS_LAMBDA_FUNCTION_NAME").unwrap_or_default(),
+            memory_size: env::var("AWS_LAMBDA_FUNCTION_MEMORY_SIZE").unwrap_or_default(),
+        }
+    }
+
+    /// Format a report as an EMF document.
+    pub fn format(&self, report: &MemoryReport) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut metrics = vec![
+            ("Resident", "Bytes", Value::from(report.after.resident)),
+            ("ResidentDelta", "Bytes", Value::from(report.resident_delta())),
+            ("PeakResident", "Bytes", Value::from(report.peak())),
+        ];
+        if let (Some(heap), Some(delta)) = (report.after.heap, report.heap_delta()) {
+            metrics.push(("Heap", "Bytes", Value::from(heap)));
+            metrics.push(("HeapDelta", "Bytes", Value::from(delta)));
+        }
+        if let Some(utilization) = report.peak_utilization() {
+            metrics.push(("PeakUtilization", "Percent", Value::from(utilization)));
+        }
+
+        let mut document = Map::new();
+        document.insert(
+            "_aws".into(),
+            json!({
+                "Timestamp": timestamp,
+                "CloudWatchMetrics": [{
+                    "Namespace": self.namespace,
+                    "Dimensions": [["FunctionName", "MemorySize"]],
+                    "Metrics": metrics
+                        .iter()
+                        .map(|(name, unit, _)| json!({ "Name": name, "Unit": unit }))
+                        .collect::<Vec<_>>(),
+                }],
+            }),
+        );
+        document.insert("FunctionName".into(), self.function_name.clone().into());
+        document.insert("MemorySize".into(), self.memory_size.clone().into());
+        document.insert("RequestId".into(), report.request_id.clone().into());
+        for (name, _, value) in metrics {
+            document.insert(name.into(), value);
+        }
+        Value::Object(document).to_string()
+    }
+}
+
+impl MemoryRecorder for EmfMemoryRecorder {
+    fn record(&self, report: &MemoryReport) {
+        println!("{}", self.format(report));
+    }
+}
+
+/// A [`Layer`] that reports the memory of the process around every invocation.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone)]
+pub struct MemoryLayer {
+    sampler: Arc<dyn MemorySampler>,
+    recorder: Arc<dyn MemoryRecorder>,
+}
+
+impl MemoryLayer {
+    /// Create a layer that samples `/proc/self/status`, or the global allocator
+    /// installed by the runtime, and logs the reports.
+    pub fn new() -> Self {
+        #[cfg(any(feature = "jemalloc", feature = "mimalloc"))]
+        let sampler: Arc<dyn MemorySampler> = Arc::new(crate::allocator::AllocatorSampler);
+        #[cfg(not(any(feature = "jemalloc", feature = "mimalloc")))]
+        let sampler: Arc<dyn MemorySampler> = Arc::new(ProcStatusSampler);
+        MemoryLayer {
+            sampler,
+            recorder: Arc::new(LogMemoryRecorder),
+        }
+    }
+
+    /// Sample the memory with `sampler`.
+    pub fn with_sampler(self, sampler: impl MemorySampler + 'static) -> Self {
+        MemoryLayer {
+            sampler: Arc::new(sampler),
+            ..self
+        }
+    }
+
+    /// Send the reports to `recorder`.
+    pub fn with_recorder(self, recorder: impl MemoryRecorder + 'static) -> Self {
+        MemoryLayer {
+            recorder: Arc::new(recorder),
+            ..self
+        }
+    }
+}
+
+impl Default for MemoryLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for MemoryLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryLayer").finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for MemoryLayer {
+    type Service = Memory<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Memory {
+            inner,
+            sampler: self.sampler.clone(),
+            recorder: self.recorder.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that reports the memory of the process around the invocations of its inner handler.
+///
+/// See [`MemoryLayer`] for details.
+#[derive(Clone)]
+pub struct Memory<S> {
+    inner: S,
+    sampler: Arc<dyn MemorySampler>,
+    recorder: Arc<dyn MemoryRecorder>,
+}
+
+impl<S: fmt::Debug> fmt::Debug for Memory<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Memory")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, A> Service<LambdaEvent<A>> for Memory<S>
+where
+    S: Service<LambdaEvent<A>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Measured<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaEvent<A>) -> Self::Future {
+        let limit = req.context.env_config.memory;
+        let request_id = req.context.request_id.clone();
+        let before = self.sampler.sample();
+        Measured {
+            inner: Box::pin(self.inner.call(req)),
+            pending: before.map(|before| Pending {
+                request_id,
+                before,
+                limit: u64::try_from(limit)
+                    .ok()
+                    .filter(|limit| *limit > 0)
+                    .map(|mb| mb * 1024 * 1024),
+                sampler: self.sampler.clone(),
+                recorder: self.recorder.clone(),
+            }),
+        }
+    }
+}
+
+/// The future of a [`Memory`] service.
+pub struct Measured<F> {
+    inner: Pin<Box<F>>,
+    pending: Option<Pending>,
+}
+
+struct Pending {
+    request_id: String,
+    before: MemorySample,
+    limit: Option<u64>,
+    sampler: Arc<dyn MemorySampler>,
+    recorder: Arc<dyn MemoryRecorder>,
+}
+
+impl<F> fmt::Debug for Measured<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Measured").finish_non_exhaustive()
+    }
+}
+
+impl<F: Future> Future for Measured<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let output = futures::ready!(self.inner.as_mut().poll(cx));
+        if let Some(pending) = self.pending.take() {
+            if let Some(after) = pending.sampler.sample() {
+                pending.recorder.record(&MemoryReport {
+                    request_id: pending.request_id,
+                    before: pending.before,
+                    after,
+                    limit: pending.limit,
+                });
+            }
+        }
+        Poll::Ready(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, Config, Context as LambdaContext, Error};
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    };
+    use tower::ServiceExt;
+
+    // Sampler that grows by 1MiB every time it's sampled.
+    struct Growing(AtomicU64);
+
+    impl MemorySampler for Growing {
+        fn sample(&self) -> Option<MemorySample> {
+            let resident = self.0.fetch_add(1024 * 1024, Ordering::SeqCst);
+            Some(MemorySample::new(resident).with_heap(resident / 2))
+        }
+    }
+
+    #[test]
+    fn parses_proc_status() {
+        let status = "Name:\tbootstrap\nVmHWM:\t   20480 kB\nVmRSS:\t   10240 kB\nThreads:\t2\n";
+        let sample = parse_status(status).unwrap();
+        assert_eq!(10 * 1024 * 1024, sample.resident);
+        assert_eq!(Some(20 * 1024 * 1024), sample.peak);
+        assert!(parse_status("Name:\tbootstrap\n").is_none());
+    }
+
+    #[tokio::test]
+    async fn reports_the_memory_of_invocations() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let recorded = reports.clone();
+        let handler = MemoryLayer::new()
+            .with_sampler(Growing(AtomicU64::new(64 * 1024 * 1024)))
+            .with_recorder(move |report: &MemoryReport| recorded.lock().unwrap().push(report.clone()))
+            .layer(service_fn(|_: LambdaEvent<()>| async { Ok::<_, Error>(()) }));
+
+        let config = Config {
+            memory: 128,
+            ..Default::default()
+        };
+        let context = LambdaContext::builder().request_id("id").env_config(config).build();
+        handler.oneshot(LambdaEvent::new((), context)).await.unwrap();
+
+        let reports = reports.lock().unwrap();
+        let report = &reports[0];
+        assert_eq!("id", report.request_id);
+        assert_eq!(1024 * 1024, report.resident_delta());
+        assert_eq!(Some(512 * 1024), report.heap_delta());
+        assert_eq!(Some(50.78125), report.peak_utilization());
+
+        let document: Value = serde_json::from_str(&EmfMemoryRecorder::new("Test").format(report)).unwrap();
+        assert_eq!(1024 * 1024, document["ResidentDelta"]);
+        assert_eq!(50.78125, document["PeakUtilization"]);
+    }
+}
@@ -0,0 +1,354 @@
+//! Profilers that attach to a process don't work in Lambda, where functions
+//! can't be reached from the outside and are frozen between invocations.
+//! [`ProfilingLayer`] runs the sampling profiler of
+//! [pprof-rs](https://docs.rs/pprof) inside the function instead, for a share of
+//! the invocations, and writes a flamegraph and a pprof profile of each one to a
+//! [`ProfileSink`].
+//!
+//! The profiler samples the CPU time of the process, with a timer that only runs
+//! while the process runs, and it's started when the handler is called and
+//! stopped when the handler completes. The freezes of the execution environment
+//! between invocations never show up in the profiles, and the time that the
+//! handler spent waiting on I/O only shows up in the wall time of the profile.
+//!
+//! The share of profiled invocations is read from the
+//! `LAMBDA_PROFILING_PERCENTAGE` environment variable, so profiling can be turned
+//! on for a deployed function by changing its configuration. Profiles are written
+//! to the directory in `LAMBDA_PROFILING_DIR`, or `/tmp/profiles`. To keep them
+//! after the execution environment is recycled, implement a [`ProfileSink`] that
+//! uploads them, for example to S3 with the AWS SDK.
+//!
+//! Building the profile of an invocation takes time, because its stacks must be
+//! symbolized, and it delays the response of the profiled invocations.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{profiling::ProfilingLayer, service_fn, tower::Layer, Error, LambdaEvent};
+//! use serde_json::Value;
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     // Set `LAMBDA_PROFILING_PERCENTAGE=5` on the function to profile 5% of the invocations.
+//!     let func = ProfilingLayer::from_env().layer(service_fn(func));
+//!     lambda_runtime::run(func).await
+//! }
+//! ```
+use crate::{Error, LambdaEvent};
+use futures::future::{BoxFuture, Either, FutureExt};
+use pprof::{protos::Message, ProfilerGuard, ProfilerGuardBuilder};
+use std::{
+    env, fmt, fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower::{Layer, Service};
+use tracing::{debug, warn};
+
+/// Environment variable with the percentage of invocations to profile.
+pub const PROFILING_PERCENTAGE_VAR: &str = "LAMBDA_PROFILING_PERCENTAGE";
+
+/// Environment variable with the directory where [`DirectorySink`] writes profiles.
+pub const PROFILING_DIR_VAR: &str = "LAMBDA_PROFILING_DIR";
+
+/// Directory where [`DirectorySink`] writes profiles by default.
+pub const DEFAULT_PROFILING_DIR: &str = "/tmp/profiles";
+
+/// Frequency of the samples of the profiler by default, in Hz.
+pub const DEFAULT_FREQUENCY: i32 = 99;
+
+// Libraries that can't be unwound safely from the signal handler of the profiler.
+const BLOCKLIST: [&str; 4] = ["libc", "libgcc", "pthread", "vdso"];
+
+/// The profile of one invocation.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Profile {
+    /// The AWS request ID of the invocation.
+    pub request_id: String,
+    /// The time between the call of the handler and its completion.
+    pub wall_time: Duration,
+    /// The flamegraph of the samples, as an SVG document.
+    pub flamegraph: Vec<u8>,
+    /// The samples in the pprof protobuf format, for `go tool pprof` and other viewers.
+    pub pprof: Vec<u8>,
+}
+
+/// Destination of the profiles of a [`ProfilingLayer`].
+pub trait ProfileSink: Send + Sync {
+    /// Write the profile of an invocation.
+    fn write(&self, profile: Profile) -> BoxFuture<'_, Result<(), Error>>;
+}
+
+/// Sink that writes the profiles of every invocation to
+/// `<request id>.svg` and `<request id>.pb` files in a directory.
+#[derive(Debug, Clone)]
+pub struct DirectorySink {
+    directory: PathBuf,
+}
+
+impl DirectorySink {
+    /// Create a sink that writes profiles in `directory`.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        DirectorySink {
+            directory: directory.into(),
+        }
+    }
+
+    /// Create a sink that writes profiles in the directory of [`PROFILING_DIR_VAR`],
+    /// or in [`DEFAULT_PROFILING_DIR`].
+    pub fn from_env() -> Self {
+        let directory = env::var_os(PROFILING_DIR_VAR).unwrap_or_else(|| DEFAULT_PROFILING_DIR.into());
+        DirectorySink::new(directory)
+    }
+}
+
+impl ProfileSink for DirectorySink {
+    fn write(&self, profile: Profile) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move {
+            fs::create_dir_all(&self.directory)?;
+            let path = self.directory.join(&profile.request_id);
+            fs::write(path.with_extension("svg"), &profile.flamegraph)?;
+            fs::write(path.with_extension("pb"), &profile.pprof)?;
+            debug!(path = %path.display(), "profile written");
+            Ok(())
+        })
+    }
+}
+
+/// A [`Layer`] that profiles a share of the invocations of its inner handler.
+///
+/// See the [module documentation](crate::profiling) for details.
+#[derive(Clone)]
+pub struct ProfilingLayer {
+    percentage: f64,
+    frequency: i32,
+    sink: Arc<dyn ProfileSink>,
+}
+
+impl ProfilingLayer {
+    /// Create a layer that profiles `percentage` percent of the invocations, and
+    /// writes the profiles with [`DirectorySink::from_env`].
+    pub fn new(percentage: f64) -> Self {
+        ProfilingLayer {
+            percentage: percentage.clamp(0.0, 100.0),
+            frequency: DEFAULT_FREQUENCY,
+            sink: Arc::new(DirectorySink::from_env()),
+        }
+    }
+
+    /// Create a layer that profiles the percentage of invocations set in
+    /// [`PROFILING_PERCENTAGE_VAR`], or none.
+    pub fn from_env() -> Self {
+        let percentage = env::var(PROFILING_PERCENTAGE_VAR)
+            .ok()
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|percentage| percentage.is_finite())
+            .unwrap_or_default();
+        ProfilingLayer::new(percentage)
+    }
+
+    /// Sample the stacks `frequency` times per second of CPU time instead of [`DEFAULT_FREQUENCY`].
+    pub fn frequency(self, frequency: i32) -> Self {
+        ProfilingLayer { frequency, ..self }
+    }
+
+    /// Write the profiles to `sink`.
+    pub fn with_sink(self, sink: impl ProfileSink + 'static) -> Self {
+        ProfilingLayer {
+            sink: Arc::new(sink),
+            ..self
+        }
+    }
+}
+
+impl fmt::Debug for ProfilingLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProfilingLayer")
+            .field("percentage", &self.percentage)
+            .field("frequency", &self.frequency)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Layer<S> for ProfilingLayer {
+    type Service = Profiling<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Profiling {
+            inner,
+            percentage: self.percentage,
+            frequency: self.frequency,
+            sink: self.sink.clone(),
+            count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+/// A [`Service`] that profiles a share of the invocations of its inner handler.
+///
+/// See [`ProfilingLayer`] for details.
+#[derive(Clone)]
+pub struct Profiling<S> {
+    inner: S,
+    percentage: f64,
+    frequency: i32,
+    sink: Arc<dyn ProfileSink>,
+    count: Arc<AtomicU64>,
+}
+
+impl<S: fmt::Debug> fmt::Debug for Profiling<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profiling")
+            .field("inner", &self.inner)
+            .field("percentage", &self.percentage)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Profiling<S> {
+    // Spread the profiled invocations evenly: the invocation `n` is profiled when
+    // it makes the number of profiled invocations reach `n * percentage / 100`.
+    fn sampled(&self) -> bool {
+        if self.percentage <= 0.0 {
+            return false;
+        }
+        let n = self.count.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.percentage / 100.0).floor() > (n * self.percentage / 100.0).floor()
+    }
+}
+
+// The profiler is global to the process, so only one invocation is profiled at a time.
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+impl<S, A> Service<LambdaEvent<A>> for Profiling<S>
+where
+    S: Service<LambdaEvent<A>>,
+    S::Future: Send + 'static,
+    S::Response: Send,
+    S::Error: Send,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<S::Future, BoxFuture<'static, Result<S::Response, S::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaEvent<A>) -> Self::Future {
+        if !self.sampled() || PROFILING.swap(true, Ordering::AcqRel) {
+            return Either::Left(self.inner.call(req));
+        }
+        let guard = match ProfilerGuardBuilder::default()
+            .frequency(self.frequency)
+            .blocklist(&BLOCKLIST)
+            .build()
+        {
+            Ok(guard) => guard,
+            Err(err) => {
+                warn!(error = %err, "unable to start the profiler");
+                PROFILING.store(false, Ordering::Release);
+                return Either::Left(self.inner.call(req));
+            }
+        };
+
+        let request_id = req.context.request_id.clone();
+        let sink = self.sink.clone();
+        let fut = self.inner.call(req);
+        Either::Right(
+            async move {
+                let start = Instant::now();
+                let result = fut.await;
+                let profile = build_profile(guard, request_id, start.elapsed());
+                PROFILING.store(false, Ordering::Release);
+                match profile {
+                    Ok(profile) => {
+                        if let Err(err) = sink.write(profile).await {
+                            warn!(error = %err, "unable to write the profile");
+                        }
+                    }
+                    Err(err) => warn!(error = %err, "unable to build the profile"),
+                }
+                result
+            }
+            .boxed(),
+        )
+    }
+}
+
+fn build_profile(guard: ProfilerGuard<'static>, request_id: String, wall_time: Duration) -> Result<Profile, Error> {
+    let report = guard.report().build()?;
+    drop(guard);
+    let mut flamegraph = Vec::new();
+    report.flamegraph(&mut flamegraph)?;
+    let pprof = report.pprof()?.encode_to_vec();
+    Ok(Profile {
+        request_id,
+        wall_time,
+        flamegraph,
+        pprof,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, Context as LambdaContext};
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    #[derive(Default)]
+    struct Collect(Mutex<Vec<Profile>>);
+
+    impl ProfileSink for Arc<Collect> {
+        fn write(&self, profile: Profile) -> BoxFuture<'_, Result<(), Error>> {
+            self.0.lock().unwrap().push(profile);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    async fn busy(_: LambdaEvent<()>) -> Result<u64, Error> {
+        let start = Instant::now();
+        let mut n = 0u64;
+        while start.elapsed() < Duration::from_millis(200) {
+            n = n.wrapping_mul(31).wrapping_add(7);
+        }
+        Ok(n)
+    }
+
+    #[test]
+    fn samples_a_percentage_of_invocations() {
+        let profiling = ProfilingLayer::new(25.0).layer(());
+        let sampled = (0..100).filter(|_| profiling.sampled()).count();
+        assert_eq!(25, sampled);
+
+        let profiling = ProfilingLayer::new(0.0).layer(());
+        assert!(!(0..100).any(|_| profiling.sampled()));
+    }
+
+    #[tokio::test]
+    async fn profiles_invocations() {
+        let profiles = Arc::new(Collect::default());
+        let handler = ProfilingLayer::new(100.0)
+            .with_sink(profiles.clone())
+            .layer(service_fn(busy));
+        let context = LambdaContext::builder().request_id("profiled").build();
+        handler.oneshot(LambdaEvent::new((), context)).await.unwrap();
+
+        let profiles = profiles.0.lock().unwrap();
+        let profile = &profiles[0];
+        assert_eq!("profiled", profile.request_id);
+        assert!(profile.wall_time >= Duration::from_millis(200));
+        assert!(profile.flamegraph.starts_with(b"<?xml"));
+        assert!(!profile.pprof.is_empty());
+    }
+}
@@ -0,0 +1,161 @@
+//! Layers that write requests, responses, or errors to logs, or record them
+//! anywhere else, use a [`Redactor`] to mask sensitive headers and JSON fields
+//! before anything leaves the function.
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Placeholder written instead of redacted values.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Headers redacted by [`FieldRedactor::default`].
+pub const SENSITIVE_HEADERS: [&str; 6] = [
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+    "x-amz-security-token",
+    "x-api-key",
+];
+
+/// Masks sensitive data before it's written to logs.
+pub trait Redactor: Send + Sync {
+    /// Return `true` if the value of the header `name` must be masked.
+    ///
+    /// Header names are always lowercase.
+    fn is_sensitive_header(&self, name: &str) -> bool;
+
+    /// Mask the sensitive fields of a JSON document in place.
+    fn redact_json(&self, value: &mut Value);
+
+    /// Return the value of the header `name`, masked if it's sensitive.
+    fn redact_header(&self, name: &str, value: &str) -> String {
+        if self.is_sensitive_header(&name.to_ascii_lowercase()) {
+            REDACTED.to_string()
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// Return a body with its sensitive JSON fields masked.
+    ///
+    /// Bodies that are not JSON documents are masked entirely,
+    /// because their content can't be inspected.
+    fn redact_body(&self, body: &[u8]) -> String {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(mut value) => {
+                self.redact_json(&mut value);
+                value.to_string()
+            }
+            Err(_) if body.is_empty() => String::new(),
+            Err(_) => REDACTED.to_string(),
+        }
+    }
+}
+
+/// A [`Redactor`] that masks headers and JSON fields by name.
+///
+/// JSON fields are matched by key at any depth of the document.
+///
+/// # Example
+/// ```
+/// use lambda_runtime::redact::{FieldRedactor, Redactor};
+/// use serde_json::json;
+///
+/// let redactor = FieldRedactor::default().field("password");
+/// let mut value = json!({"user": {"name": "alice", "password": "hunter2"}});
+/// redactor.redact_json(&mut value);
+/// assert_eq!(json!({"user": {"name": "alice", "password": "[REDACTED]"}}), value);
+/// assert_eq!("[REDACTED]", redactor.redact_header("Authorization", "Bearer token"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FieldRedactor {
+    headers: HashSet<String>,
+    fields: HashSet<String>,
+}
+
+impl FieldRedactor {
+    /// Create a redactor that doesn't mask anything.
+    pub fn empty() -> Self {
+        FieldRedactor {
+            headers: HashSet::new(),
+            fields: HashSet::new(),
+        }
+    }
+
+    /// Mask the value of the header `name`.
+    pub fn header(mut self, name: &str) -> Self {
+        self.headers.insert(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Mask the value of the JSON fields with the key `name`.
+    pub fn field(mut self, name: impl Into<String>) -> Self {
+        self.fields.insert(name.into());
+        self
+    }
+}
+
+impl Default for FieldRedactor {
+    /// Create a redactor that masks the headers in [`SENSITIVE_HEADERS`].
+    fn default() -> Self {
+        SENSITIVE_HEADERS
+            .iter()
+            .fold(FieldRedactor::empty(), |redactor, name| redactor.header(name))
+    }
+}
+
+impl Redactor for FieldRedactor {
+    fn is_sensitive_header(&self, name: &str) -> bool {
+        self.headers.contains(name)
+    }
+
+    fn redact_json(&self, value: &mut Value) {
+        if self.fields.is_empty() {
+            return;
+        }
+
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.fields.contains(key) {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact_json(value);
+                    }
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.redact_json(value)),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redact_nested_fields() {
+        let redactor = FieldRedactor::empty().field("ssn").field("token");
+        let body = br#"{"users":[{"name":"a","ssn":"123"},{"name":"b","ssn":"456"}],"auth":{"token":"t"}}"#;
+        let redacted: Value = serde_json::from_str(&redactor.redact_body(body)).unwrap();
+        assert_eq!(
+            json!({
+                "users": [{"name": "a", "ssn": REDACTED}, {"name": "b", "ssn": REDACTED}],
+                "auth": {"token": REDACTED}
+            }),
+            redacted
+        );
+        assert_eq!(REDACTED, redactor.redact_body(b"ssn=123"));
+        assert_eq!("", redactor.redact_body(b""));
+    }
+
+    #[test]
+    fn redact_default_headers() {
+        let redactor = FieldRedactor::default().header("X-Custom-Secret");
+        assert_eq!(REDACTED, redactor.redact_header("Cookie", "session=1"));
+        assert_eq!(REDACTED, redactor.redact_header("x-custom-secret", "shh"));
+        assert_eq!("curl/8.0", redactor.redact_header("user-agent", "curl/8.0"));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{run, Error, LambdaEvent};
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::{Deserialize, Serialize};
+use std::{env, fmt, future::Future};
+use tower::Service;
+
+/// Environment variable with the name of the handler to run.
+///
+/// Lambda sets it from the `Handler` setting of functions deployed as zip
+/// files, and the AWS base images set it from the command of the image.
+pub const HANDLER_VAR: &str = "_HANDLER";
+
+/// Environment variable that selects the handler to run, instead of [`HANDLER_VAR`].
+pub const HANDLER_OVERRIDE_VAR: &str = "LAMBDA_HANDLER";
+
+/// Return the name of the handler to run, from the [`HANDLER_OVERRIDE_VAR`]
+/// environment variable, or from [`HANDLER_VAR`].
+pub fn selected_handler() -> Option<String> {
+    [HANDLER_OVERRIDE_VAR, HANDLER_VAR]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|name| !name.is_empty())
+}
+
+/// Error returned when the handler to run can't be selected.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnknownHandler {
+    /// The requested handler, if any.
+    pub name: Option<String>,
+    /// The names of the registered handlers.
+    pub available: Vec<String>,
+}
+
+impl fmt::Display for UnknownHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let available = self.available.join(", ");
+        match &self.name {
+            Some(name) => write!(f, "no handler named `{name}`, expected one of: {available}"),
+            None => write!(
+                f,
+                "no handler selected with `{HANDLER_OVERRIDE_VAR}` or `{HANDLER_VAR}`, expected one of: {available}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UnknownHandler {}
+
+pub(crate) type Launch = Box<dyn FnOnce() -> LocalBoxFuture<'static, Result<(), Error>>>;
+
+/// Several handlers compiled in one binary, one of which is selected when the
+/// function starts.
+///
+/// Each function that ships the binary selects its handler with the `Handler`
+/// setting of the function, or with the [`HANDLER_OVERRIDE_VAR`] environment
+/// variable. A registry with a single handler runs it regardless of the name,
+/// so it can replace [`run`] in any function.
+///
+/// Handlers can have different event and response types. The [`handlers!`](crate::handlers)
+/// macro registers async functions under their own name.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, Error, HandlerRegistry, LambdaEvent};
+/// use serde_json::Value;
+///
+/// async fn orders(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// async fn invoices(event: LambdaEvent<String>) -> Result<String, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     HandlerRegistry::new()
+///         .register("orders", service_fn(orders))
+///         .register("invoices", service_fn(invoices))
+///         .run()
+///         .await
+/// }
+/// ```
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: Vec<(String, Launch)>,
+}
+
+impl HandlerRegistry {
+    /// Create a registry without handlers.
+    pub fn new() -> Self {
+        HandlerRegistry::default()
+    }
+
+    /// Register a handler under `name`, replacing the handler previously
+    /// registered under the same name.
+    pub fn register<A, B, F>(mut self, name: impl Into<String>, handler: F) -> Self
+    where
+        F: Service<LambdaEvent<A>> + 'static,
+        F::Future: Future<Output = Result<B, F::Error>>,
+        F::Error: fmt::Debug + fmt::Display + 'static,
+        A: for<'de> Deserialize<'de> + 'static,
+        B: Serialize + 'static,
+    {
+        let name = name.into();
+        self.handlers.retain(|(registered, _)| *registered != name);
+        self.handlers.push((name, Box::new(move || run(handler).boxed_local())));
+        self
+    }
+
+    /// Return the names of the registered handlers, in registration order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.handlers.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Return whether a handler is registered under `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.names().any(|registered| registered == name)
+    }
+
+    /// Run the handler selected by [`selected_handler`].
+    pub async fn run(self) -> Result<(), Error> {
+        self.run_named(selected_handler()).await
+    }
+
+    /// Run the handler registered under `name`.
+    pub async fn run_named(self, name: Option<String>) -> Result<(), Error> {
+        let launch = self.select(name)?;
+        launch().await
+    }
+
+    pub(crate) fn select(mut self, name: Option<String>) -> Result<Launch, UnknownHandler> {
+        if self.handlers.len() == 1 {
+            return Ok(self.handlers.remove(0).1);
+        }
+        let position = name
+            .as_deref()
+            .and_then(|name| self.handlers.iter().position(|(registered, _)| registered == name));
+        match position {
+            Some(position) => Ok(self.handlers.swap_remove(position).1),
+            None => Err(UnknownHandler {
+                name,
+                available: self.names().map(String::from).collect(),
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+/// Build a [`HandlerRegistry`] from a list of handlers.
+///
+/// Async functions are registered under their own name, wrapped in
+/// [`service_fn`](crate::service_fn). Other services are registered with a
+/// `"name" => service` pair.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{handlers, Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// async fn orders(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// async fn invoices(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     handlers![orders, invoices].run().await
+/// }
+/// ```
+#[macro_export]
+macro_rules! handlers {
+    ($($name:literal => $handler:expr),+ $(,)?) => {
+        $crate::HandlerRegistry::new()
+            $(.register($name, $handler))+
+    };
+    ($($handler:ident),+ $(,)?) => {
+        $crate::HandlerRegistry::new()
+            $(.register(stringify!($handler), $crate::service_fn($handler)))+
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_fn;
+    use serde_json::Value;
+
+    async fn orders(event: LambdaEvent<Value>) -> Result<Value, Error> {
+        Ok(event.payload)
+    }
+
+    async fn invoices(event: LambdaEvent<String>) -> Result<String, Error> {
+        Ok(event.payload)
+    }
+
+    #[test]
+    fn registers_functions_under_their_name() {
+        let registry = handlers![orders, invoices];
+        assert_eq!(vec!["orders", "invoices"], registry.names().collect::<Vec<_>>());
+
+        let registry = handlers!["a" => service_fn(orders), "b" => service_fn(invoices)];
+        assert_eq!(vec!["a", "b"], registry.names().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn replaces_handlers_with_the_same_name() {
+        let registry = HandlerRegistry::new()
+            .register("orders", service_fn(orders))
+            .register("orders", service_fn(invoices));
+        assert_eq!(vec!["orders"], registry.names().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn selects_the_named_handler() {
+        assert!(handlers![orders, invoices].select(Some("invoices".to_string())).is_ok());
+    }
+
+    #[test]
+    fn runs_a_single_handler_regardless_of_the_name() {
+        assert!(handlers![orders].select(Some("bootstrap".to_string())).is_ok());
+        assert!(handlers![orders].select(None).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_handlers() {
+        let err = handlers![orders, invoices]
+            .select(Some("crop".to_string()))
+            .err()
+            .unwrap();
+        assert_eq!(
+            UnknownHandler {
+                name: Some("crop".to_string()),
+                available: vec!["orders".to_string(), "invoices".to_string()],
+            },
+            err
+        );
+        assert_eq!(
+            "no handler named `crop`, expected one of: orders, invoices",
+            err.to_string()
+        );
+
+        let err = handlers![orders, invoices].select(None).err().unwrap();
+        assert_eq!(None, err.name);
+    }
+}
@@ -1,5 +1,12 @@
-use crate::{types::Diagnostic, Error};
-use http::{Method, Request, Response, Uri};
+use crate::{
+    codec::Codec,
+    limits::{truncate_message, InvokeMode, ERROR_MESSAGE_LIMIT, ERROR_TYPE_LIMIT},
+    types::{Diagnostic, DEFAULT_FUNCTION_ERROR_TYPE},
+    Error,
+};
+#[cfg(test)]
+use http::Response;
+use http::{HeaderValue, Method, Request, Uri};
 use hyper::Body;
 use lambda_runtime_api_client::build_request;
 use serde::Serialize;
@@ -9,6 +16,7 @@ pub(crate) trait IntoRequest {
     fn into_req(self) -> Result<Request<Body>, Error>;
 }
 
+#[cfg(test)]
 pub(crate) trait IntoResponse {
     fn into_rsp(self) -> Result<Response<Body>, Error>;
 }
@@ -27,6 +35,7 @@ impl IntoRequest for NextEventRequest {
     }
 }
 
+#[cfg(test)]
 #[derive(Debug, Eq, PartialEq)]
 pub struct NextEventResponse<'a> {
     // lambda-runtime-aws-request-id
@@ -41,6 +50,7 @@ pub struct NextEventResponse<'a> {
     pub body: Vec<u8>,
 }
 
+#[cfg(test)]
 impl<'a> IntoResponse for NextEventResponse<'a> {
     fn into_rsp(self) -> Result<Response<Body>, Error> {
         let rsp = Response::builder()
@@ -65,19 +75,22 @@ fn test_next_event_request() {
 }
 
 // /runtime/invocation/{AwsRequestId}/response
-pub(crate) struct EventCompletionRequest<'a, T> {
+pub(crate) struct EventCompletionRequest<'a, T, C> {
     pub(crate) request_id: &'a str,
     pub(crate) body: T,
+    pub(crate) codec: &'a C,
 }
 
-impl<'a, T> IntoRequest for EventCompletionRequest<'a, T>
+impl<'a, T, C> IntoRequest for EventCompletionRequest<'a, T, C>
 where
     T: for<'serialize> Serialize,
+    C: Codec,
 {
     fn into_req(self) -> Result<Request<Body>, Error> {
         let uri = format!("/2018-06-01/runtime/invocation/{}/response", self.request_id);
         let uri = Uri::from_str(&uri)?;
-        let body = serde_json::to_vec(&self.body)?;
+        let body = self.codec.encode(&self.body)?;
+        InvokeMode::Buffered.check(body.len())?;
         let body = Body::from(body);
 
         let req = build_request().method(Method::POST).uri(uri).body(body)?;
@@ -90,6 +103,7 @@ fn test_event_completion_request() {
     let req = EventCompletionRequest {
         request_id: "id",
         body: "hello, world!",
+        codec: &crate::codec::JsonCodec::new(),
     };
     let req = req.into_req().unwrap();
     let expected = Uri::from_static("/2018-06-01/runtime/invocation/id/response");
@@ -101,20 +115,30 @@ fn test_event_completion_request() {
     });
 }
 
+#[test]
+fn test_event_completion_request_too_large() {
+    let req = EventCompletionRequest {
+        request_id: "id",
+        body: "a".repeat(crate::limits::BUFFERED_PAYLOAD_LIMIT),
+        codec: &crate::codec::JsonCodec::new(),
+    };
+    let err = req.into_req().unwrap_err();
+    let err = err.downcast_ref::<crate::limits::PayloadTooLarge>().unwrap();
+    assert_eq!(InvokeMode::Buffered, err.mode);
+    assert_eq!(crate::limits::BUFFERED_PAYLOAD_LIMIT + 2, err.size);
+}
+
 // /runtime/invocation/{AwsRequestId}/error
 pub(crate) struct EventErrorRequest<'a> {
     pub(crate) request_id: &'a str,
-    pub(crate) diagnostic: Diagnostic<'a>,
+    pub(crate) diagnostic: Diagnostic,
 }
 
 impl<'a> EventErrorRequest<'a> {
-    pub(crate) fn new(request_id: &'a str, error_type: &'a str, error_message: &'a str) -> EventErrorRequest<'a> {
+    pub(crate) fn new(request_id: &'a str, error_type: &str, error_message: &str) -> EventErrorRequest<'a> {
         EventErrorRequest {
             request_id,
-            diagnostic: Diagnostic {
-                error_type,
-                error_message,
-            },
+            diagnostic: Diagnostic::new(error_type, error_message),
         }
     }
 }
@@ -123,13 +147,19 @@ impl<'a> IntoRequest for EventErrorRequest<'a> {
     fn into_req(self) -> Result<Request<Body>, Error> {
         let uri = format!("/2018-06-01/runtime/invocation/{}/error", self.request_id);
         let uri = Uri::from_str(&uri)?;
-        let body = serde_json::to_vec(&self.diagnostic)?;
+        let error_type = truncate_message(self.diagnostic.error_type(), ERROR_TYPE_LIMIT);
+        let error_message = truncate_message(self.diagnostic.error_message(), ERROR_MESSAGE_LIMIT);
+        let diagnostic = Diagnostic::new(error_type, error_message);
+        let body = serde_json::to_vec(&diagnostic)?;
         let body = Body::from(body);
 
+        // Values that aren't valid in a header fall back to the default one.
+        let function_error_type = HeaderValue::from_str(self.diagnostic.function_error_type())
+            .unwrap_or_else(|_| HeaderValue::from_static(DEFAULT_FUNCTION_ERROR_TYPE));
         let req = build_request()
             .method(Method::POST)
             .uri(uri)
-            .header("lambda-runtime-function-error-type", "unhandled")
+            .header("lambda-runtime-function-error-type", function_error_type)
             .body(body)?;
         Ok(req)
     }
@@ -139,10 +169,7 @@ impl<'a> IntoRequest for EventErrorRequest<'a> {
 fn test_event_error_request() {
     let req = EventErrorRequest {
         request_id: "id",
-        diagnostic: Diagnostic {
-            error_type: "InvalidEventDataError",
-            error_message: "Error parsing event data",
-        },
+        diagnostic: Diagnostic::new("InvalidEventDataError", "Error parsing event data"),
     };
     let req = req.into_req().unwrap();
     let expected = Uri::from_static("/2018-06-01/runtime/invocation/id/error");
@@ -154,26 +181,64 @@ fn test_event_error_request() {
     });
 }
 
+#[test]
+fn test_event_error_request_with_long_message() {
+    let message = format!("{}ü", "a".repeat(ERROR_MESSAGE_LIMIT));
+    let req = EventErrorRequest::new("id", "InvalidEventDataError", &message);
+    let req = req.into_req().unwrap();
+    let body = futures::executor::block_on(hyper::body::to_bytes(req.into_body())).unwrap();
+    let diagnostic: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let error_message = diagnostic["errorMessage"].as_str().unwrap();
+    assert!(error_message.len() <= ERROR_MESSAGE_LIMIT);
+    assert!(error_message.ends_with(" bytes truncated]"));
+    assert_eq!("InvalidEventDataError", diagnostic["errorType"]);
+}
+
+#[test]
+fn test_event_error_request_with_function_error_type() {
+    let req = EventErrorRequest::new("id", "Order.Missing", "the event has no order");
+    let req = req.into_req().unwrap();
+    assert_eq!("unhandled", req.headers()["lambda-runtime-function-error-type"]);
+
+    let req = EventErrorRequest {
+        request_id: "id",
+        diagnostic: Diagnostic::new("Order.Missing", "the event has no order")
+            .with_function_error_type("Function.InvalidOrder"),
+    };
+    let req = req.into_req().unwrap();
+    assert_eq!(
+        "Function.InvalidOrder",
+        req.headers()["lambda-runtime-function-error-type"]
+    );
+}
+
 // /runtime/init/error
-struct InitErrorRequest;
+pub(crate) struct InitErrorRequest {
+    pub(crate) diagnostic: Diagnostic,
+}
 
 impl IntoRequest for InitErrorRequest {
     fn into_req(self) -> Result<Request<Body>, Error> {
         let uri = "/2018-06-01/runtime/init/error".to_string();
         let uri = Uri::from_str(&uri)?;
+        let error_type = truncate_message(self.diagnostic.error_type(), ERROR_TYPE_LIMIT);
+        let error_message = truncate_message(self.diagnostic.error_message(), ERROR_MESSAGE_LIMIT);
+        let body = serde_json::to_vec(&Diagnostic::new(error_type, error_message))?;
 
         let req = build_request()
             .method(Method::POST)
             .uri(uri)
             .header("lambda-runtime-function-error-type", "unhandled")
-            .body(Body::empty())?;
+            .body(Body::from(body))?;
         Ok(req)
     }
 }
 
 #[test]
 fn test_init_error_request() {
-    let req = InitErrorRequest;
+    let req = InitErrorRequest {
+        diagnostic: Diagnostic::new("Runtime.InitError", "the configuration is missing"),
+    };
     let req = req.into_req().unwrap();
     let expected = Uri::from_static("/2018-06-01/runtime/init/error");
     assert_eq!(req.method(), Method::POST);
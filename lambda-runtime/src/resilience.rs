@@ -0,0 +1,464 @@
+//! Circuit breakers and bulkheads for the services that a function calls.
+//!
+//! Execution environments are reused across invocations, so the state of a
+//! [`CircuitBreakerLayer`] survives from one invocation to the next: once a
+//! downstream service has failed enough times in a row, the following invocations
+//! fail fast with a [`CircuitOpen`] error instead of waiting for timeouts. After a
+//! cool down, the next call is let through as a probe. If the probe succeeds, the
+//! circuit closes again. If it fails, the circuit stays open for another cool down.
+//!
+//! Execution environments are frozen between invocations, so the cool down is
+//! measured with the wall clock, which keeps counting while the environment is
+//! frozen, and a circuit that opened during an invocation can be probed at the
+//! beginning of the next one.
+//!
+//! A [`BulkheadLayer`] caps the number of calls in flight to a service, and rejects
+//! any other call with a [`BulkheadFull`] error, so a slow dependency can't use up
+//! all the concurrency of a function that handles several events at once.
+//!
+//! Both layers wrap any [`Service`], like a client of another AWS service modeled
+//! as a tower service.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{
+//!     resilience::{BulkheadLayer, CircuitBreakerLayer},
+//!     service_fn,
+//!     tower::{Layer, ServiceBuilder, ServiceExt},
+//!     Error, LambdaEvent,
+//! };
+//! use serde_json::Value;
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let inventory = ServiceBuilder::new()
+//!         .layer(CircuitBreakerLayer::new(5, Duration::from_secs(30)))
+//!         .layer(BulkheadLayer::new(10))
+//!         .service(service_fn(|sku: String| async move { Ok::<_, Error>(sku.len()) }));
+//!
+//!     lambda_runtime::run(service_fn(move |event: LambdaEvent<Value>| {
+//!         let inventory = inventory.clone();
+//!         async move {
+//!             let sku = event.payload["sku"].as_str().unwrap_or_default().to_string();
+//!             inventory.oneshot(sku).await
+//!         }
+//!     }))
+//!     .await
+//! }
+//! ```
+use crate::Error;
+use futures::future::{self, BoxFuture};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::Semaphore;
+use tower::{Layer, Service};
+
+/// State of a circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through to the inner service.
+    Closed,
+    /// Calls fail fast, until the cool down ends.
+    Open,
+    /// A probe call is in flight, and any other call fails fast.
+    HalfOpen,
+}
+
+/// Error returned when a call is rejected because the circuit is open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitOpen {
+    retry_after: Duration,
+}
+
+impl CircuitOpen {
+    /// Time until the circuit lets a probe call through.
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "circuit breaker is open, calls are rejected for the next {}ms",
+            self.retry_after.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+#[derive(Debug)]
+struct Breaker {
+    state: CircuitState,
+    failures: u32,
+    opened_at: SystemTime,
+}
+
+impl Breaker {
+    // Decide whether a call can go through, and switch to half-open if it's a probe.
+    fn admit(&mut self, open_for: Duration) -> Result<(), CircuitOpen> {
+        match self.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::HalfOpen => Err(CircuitOpen { retry_after: open_for }),
+            CircuitState::Open => {
+                let elapsed = self.opened_at.elapsed().unwrap_or_default();
+                if elapsed >= open_for {
+                    self.state = CircuitState::HalfOpen;
+                    Ok(())
+                } else {
+                    Err(CircuitOpen {
+                        retry_after: open_for - elapsed,
+                    })
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, success: bool, failure_threshold: u32) {
+        if success {
+            self.state = CircuitState::Closed;
+            self.failures = 0;
+            return;
+        }
+        self.failures = self.failures.saturating_add(1);
+        if self.state == CircuitState::HalfOpen || self.failures >= failure_threshold {
+            self.state = CircuitState::Open;
+            self.opened_at = SystemTime::now();
+        }
+    }
+}
+
+/// A [`Layer`] that stops calling a service after consecutive failures.
+///
+/// Every service built by the same layer, and every clone of those services,
+/// shares the state of the circuit. Calls that are dropped before they complete,
+/// like calls cancelled by a timeout, count as failures.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone)]
+pub struct CircuitBreakerLayer {
+    breaker: Arc<Mutex<Breaker>>,
+    failure_threshold: u32,
+    open_for: Duration,
+}
+
+impl CircuitBreakerLayer {
+    /// Create a new layer that opens the circuit after `failure_threshold` consecutive
+    /// failures, and keeps it open for `open_for` before probing the service again.
+    pub fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        CircuitBreakerLayer {
+            breaker: Arc::new(Mutex::new(Breaker {
+                state: CircuitState::Closed,
+                failures: 0,
+                opened_at: SystemTime::UNIX_EPOCH,
+            })),
+            failure_threshold: failure_threshold.max(1),
+            open_for,
+        }
+    }
+
+    /// Return the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        self.breaker.lock().expect("circuit breaker poisoned").state
+    }
+}
+
+impl fmt::Debug for CircuitBreakerLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreakerLayer")
+            .field("state", &self.state())
+            .field("failure_threshold", &self.failure_threshold)
+            .field("open_for", &self.open_for)
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreaker<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker {
+            inner,
+            config: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that fails fast while the circuit of its inner service is open.
+///
+/// See [`CircuitBreakerLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker<S> {
+    inner: S,
+    config: CircuitBreakerLayer,
+}
+
+impl<S> CircuitBreaker<S> {
+    /// Return the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        self.config.state()
+    }
+}
+
+impl<S, R> Service<R> for CircuitBreaker<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+    S::Response: Send + 'static,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<S::Response, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let admitted = self
+            .config
+            .breaker
+            .lock()
+            .expect("circuit breaker poisoned")
+            .admit(self.config.open_for);
+        if let Err(err) = admitted {
+            return Box::pin(future::ready(Err(err.into())));
+        }
+
+        let fut = self.inner.call(req);
+        let outcome = Outcome {
+            breaker: self.config.breaker.clone(),
+            failure_threshold: self.config.failure_threshold,
+            recorded: false,
+        };
+        Box::pin(async move {
+            let result = fut.await.map_err(Into::into);
+            outcome.record(result.is_ok());
+            result
+        })
+    }
+}
+
+// Records the result of a call, or a failure if the call is dropped before it completes.
+struct Outcome {
+    breaker: Arc<Mutex<Breaker>>,
+    failure_threshold: u32,
+    recorded: bool,
+}
+
+impl Outcome {
+    fn record(mut self, success: bool) {
+        self.recorded = true;
+        self.update(success);
+    }
+
+    fn update(&self, success: bool) {
+        if let Ok(mut breaker) = self.breaker.lock() {
+            breaker.record(success, self.failure_threshold);
+        }
+    }
+}
+
+impl Drop for Outcome {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.update(false);
+        }
+    }
+}
+
+/// Error returned when a call is rejected because the bulkhead is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BulkheadFull {
+    max_concurrency: usize,
+}
+
+impl fmt::Display for BulkheadFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bulkhead is full, {} calls are already in flight",
+            self.max_concurrency
+        )
+    }
+}
+
+impl std::error::Error for BulkheadFull {}
+
+/// A [`Layer`] that caps the number of calls in flight to a service.
+///
+/// Every service built by the same layer, and every clone of those services,
+/// shares the same cap.
+#[derive(Clone, Debug)]
+pub struct BulkheadLayer {
+    permits: Arc<Semaphore>,
+    max_concurrency: usize,
+}
+
+impl BulkheadLayer {
+    /// Create a new layer that lets at most `max_concurrency` calls in flight.
+    pub fn new(max_concurrency: usize) -> Self {
+        BulkheadLayer {
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+            max_concurrency,
+        }
+    }
+}
+
+impl<S> Layer<S> for BulkheadLayer {
+    type Service = Bulkhead<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Bulkhead {
+            inner,
+            config: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that rejects calls when too many are already in flight.
+///
+/// See [`BulkheadLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct Bulkhead<S> {
+    inner: S,
+    config: BulkheadLayer,
+}
+
+impl<S, R> Service<R> for Bulkhead<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+    S::Response: Send + 'static,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<S::Response, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let permit = match self.config.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                let err = BulkheadFull {
+                    max_concurrency: self.config.max_concurrency,
+                };
+                return Box::pin(future::ready(Err(err.into())));
+            }
+        };
+
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let result = fut.await.map_err(Into::into);
+            drop(permit);
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_fn;
+    use tokio::sync::oneshot;
+    use tower::{util::BoxCloneService, ServiceExt};
+
+    fn flaky(fail: Arc<Mutex<bool>>) -> BoxCloneService<u32, u32, Error> {
+        BoxCloneService::new(service_fn(move |n: u32| {
+            let fail = *fail.lock().unwrap();
+            async move {
+                if fail {
+                    Err(Error::from("downstream failure"))
+                } else {
+                    Ok(n)
+                }
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn circuit_opens_and_probes_after_cool_down() {
+        let fail = Arc::new(Mutex::new(true));
+        let layer = CircuitBreakerLayer::new(2, Duration::from_millis(50));
+        let svc = layer.layer(flaky(fail.clone()));
+
+        for _ in 0..2 {
+            let err = svc.clone().oneshot(1).await.unwrap_err();
+            assert!(err.downcast_ref::<CircuitOpen>().is_none(), "{err}");
+        }
+        assert_eq!(CircuitState::Open, layer.state());
+        let err = svc.clone().oneshot(1).await.unwrap_err();
+        assert!(err.downcast_ref::<CircuitOpen>().is_some(), "{err}");
+
+        // A failed probe opens the circuit again.
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let err = svc.clone().oneshot(1).await.unwrap_err();
+        assert!(err.downcast_ref::<CircuitOpen>().is_none(), "{err}");
+        assert_eq!(CircuitState::Open, layer.state());
+
+        *fail.lock().unwrap() = false;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(2, svc.clone().oneshot(2).await.unwrap());
+        assert_eq!(CircuitState::Closed, layer.state());
+    }
+
+    #[tokio::test]
+    async fn successes_reset_the_failure_count() {
+        let fail = Arc::new(Mutex::new(true));
+        let layer = CircuitBreakerLayer::new(2, Duration::from_secs(60));
+        let svc = layer.layer(flaky(fail.clone()));
+
+        svc.clone().oneshot(1).await.unwrap_err();
+        *fail.lock().unwrap() = false;
+        svc.clone().oneshot(1).await.unwrap();
+        *fail.lock().unwrap() = true;
+        svc.clone().oneshot(1).await.unwrap_err();
+        assert_eq!(CircuitState::Closed, layer.state());
+    }
+
+    #[tokio::test]
+    async fn cancelled_calls_count_as_failures() {
+        let layer = CircuitBreakerLayer::new(1, Duration::from_secs(60));
+        let svc = layer.layer(service_fn(|_: ()| future::pending::<Result<(), Error>>()));
+
+        let call = svc.oneshot(());
+        assert!(tokio::time::timeout(Duration::from_millis(1), call).await.is_err());
+        assert_eq!(CircuitState::Open, layer.state());
+    }
+
+    #[tokio::test]
+    async fn bulkhead_rejects_calls_over_the_cap() {
+        let (tx, rx) = oneshot::channel::<()>();
+        let rx = Arc::new(Mutex::new(Some(rx)));
+        let svc = BulkheadLayer::new(1).layer(service_fn(move |_: ()| {
+            let rx = rx.lock().unwrap().take();
+            async move {
+                if let Some(rx) = rx {
+                    let _ = rx.await;
+                }
+                Ok::<_, Error>(())
+            }
+        }));
+
+        let in_flight = tokio::spawn(svc.clone().oneshot(()));
+        tokio::task::yield_now().await;
+        let err = svc.clone().oneshot(()).await.unwrap_err();
+        assert!(err.downcast_ref::<BulkheadFull>().is_some(), "{err}");
+
+        tx.send(()).unwrap();
+        in_flight.await.unwrap().unwrap();
+        svc.oneshot(()).await.unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    executor::{SharedExecutor, TokioExecutor},
+    Error,
+};
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use std::{
+    fmt,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{error, info, warn};
+
+/// Time that Lambda gives the runtime to shut down after sending `SIGTERM`.
+pub const SHUTDOWN_BUDGET: Duration = Duration::from_millis(500);
+
+struct Resource {
+    name: String,
+    priority: i32,
+    close: Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>,
+}
+
+/// Outcome of closing the resources of a [`Resources`] registry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClosedResources {
+    /// The resources that were closed, in the order they were closed.
+    pub closed: Vec<String>,
+    /// The resources that panicked, didn't close in time, or were not
+    /// closed because the budget ran out.
+    pub abandoned: Vec<String>,
+}
+
+/// Resources that must be closed when the execution environment shuts down,
+/// like database pools or buffered writers.
+///
+/// The runtime closes the resources of the registry set with
+/// [`RuntimeBuilder::with_resources`](crate::RuntimeBuilder::with_resources) when
+/// the process receives `SIGTERM`, or when the runtime stops with an error.
+/// Resources with a higher priority are closed first, and resources with the same
+/// priority are closed in the reverse order of registration. All of them share
+/// the [`SHUTDOWN_BUDGET`], and the ones that don't close in time are abandoned.
+///
+/// Lambda only sends `SIGTERM` to functions that have at least one extension
+/// registered, so resources are not closed on shutdown otherwise.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, Error, LambdaEvent, Resources, RuntimeBuilder};
+/// use serde_json::Value;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let resources = Resources::new();
+///     resources.register("metrics", 10, || async { /* flush the metrics buffer */ });
+///     resources.register("database", 0, || async { /* close the connection pool */ });
+///
+///     RuntimeBuilder::new()
+///         .with_resources(resources)
+///         .run(service_fn(func))
+///         .await
+/// }
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Resources {
+    executor: SharedExecutor,
+    inner: Arc<Mutex<Vec<Resource>>>,
+}
+
+impl Default for Resources {
+    fn default() -> Self {
+        Resources::new()
+    }
+}
+
+impl Resources {
+    /// Create a registry without resources.
+    pub fn new() -> Self {
+        Resources {
+            executor: Arc::new(TokioExecutor),
+            inner: Arc::default(),
+        }
+    }
+
+    /// Register a resource under `name`, with the function that closes it.
+    pub fn register<F, Fut>(&self, name: impl Into<String>, priority: i32, close: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.inner.lock().expect("resources lock poisoned").push(Resource {
+            name: name.into(),
+            priority,
+            close: Box::new(move || close().boxed()),
+        });
+    }
+
+    /// Return the names of the registered resources, in the order they are closed.
+    pub fn names(&self) -> Vec<String> {
+        let resources = self.inner.lock().expect("resources lock poisoned");
+        let mut order: Vec<_> = resources.iter().rev().collect();
+        order.sort_by_key(|resource| std::cmp::Reverse(resource.priority));
+        order.into_iter().map(|resource| resource.name.clone()).collect()
+    }
+
+    /// Return the number of resources that haven't been closed yet.
+    pub fn len(&self) -> usize {
+        self.inner.lock().expect("resources lock poisoned").len()
+    }
+
+    /// Return `true` if there are no resources left to close.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Close all the resources in priority order, within `budget`.
+    ///
+    /// Resources are removed from the registry, so closing it again does nothing.
+    pub async fn close(&self, budget: Duration) -> ClosedResources {
+        let mut resources = std::mem::take(&mut *self.inner.lock().expect("resources lock poisoned"));
+        resources.reverse();
+        resources.sort_by_key(|resource| std::cmp::Reverse(resource.priority));
+
+        let deadline = Instant::now() + budget;
+        let mut report = ClosedResources::default();
+        for resource in resources {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                warn!(resource = %resource.name, "not closing resource, the shutdown budget ran out");
+                report.abandoned.push(resource.name);
+                continue;
+            }
+
+            let close = AssertUnwindSafe((resource.close)()).catch_unwind();
+            match future::select(close, self.executor.sleep(remaining)).await {
+                Either::Left((Ok(()), _)) => report.closed.push(resource.name),
+                Either::Left((Err(_), _)) => {
+                    error!(resource = %resource.name, "resource panicked while closing");
+                    report.abandoned.push(resource.name);
+                }
+                Either::Right(_) => {
+                    warn!(resource = %resource.name, "resource didn't close within the shutdown budget");
+                    report.abandoned.push(resource.name);
+                }
+            }
+        }
+        report
+    }
+
+    /// Run the runtime loop, and close the resources when the process receives
+    /// `SIGTERM` or when the loop stops.
+    pub(crate) async fn supervise<F>(&self, run: F) -> Result<(), Error>
+    where
+        F: Future<Output = Result<(), Error>>,
+    {
+        futures::pin_mut!(run);
+        let result = match future::select(run, Box::pin(sigterm())).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => {
+                info!("received SIGTERM, closing resources");
+                Ok(())
+            }
+        };
+        if let Err(err) = &result {
+            error!("runtime stopped with an error, closing resources: {err}");
+        }
+        self.close(SHUTDOWN_BUDGET).await;
+        result
+    }
+}
+
+impl fmt::Debug for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+#[cfg(unix)]
+async fn sigterm() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut signal) => {
+            signal.recv().await;
+        }
+        Err(err) => {
+            warn!("unable to listen for SIGTERM, resources won't be closed on shutdown: {err}");
+            future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn sigterm() {
+    future::pending::<()>().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn record(order: &Arc<Mutex<Vec<&'static str>>>, name: &'static str) -> impl Future<Output = ()> {
+        let order = order.clone();
+        async move { order.lock().unwrap().push(name) }
+    }
+
+    #[tokio::test]
+    async fn closes_resources_in_priority_order() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let resources = Resources::new();
+        for (name, priority) in [("database", 0), ("metrics", 10), ("cache", 0), ("logs", 20)] {
+            let closing = record(&order, name);
+            resources.register(name, priority, move || closing);
+        }
+        assert_eq!(vec!["logs", "metrics", "cache", "database"], resources.names());
+
+        let report = resources.close(SHUTDOWN_BUDGET).await;
+        assert_eq!(vec!["logs", "metrics", "cache", "database"], *order.lock().unwrap());
+        assert_eq!(report.closed, *order.lock().unwrap());
+        assert!(report.abandoned.is_empty());
+        assert!(resources.is_empty());
+        assert_eq!(ClosedResources::default(), resources.close(SHUTDOWN_BUDGET).await);
+    }
+
+    #[tokio::test]
+    async fn abandons_resources_after_the_budget() {
+        let resources = Resources::new();
+        resources.register("slow", 10, || tokio::time::sleep(Duration::from_secs(10)));
+        resources.register("panics", 5, || async { panic!("boom") });
+        resources.register("fast", 0, || async {});
+
+        let report = resources.close(Duration::from_millis(20)).await;
+        assert!(report.closed.is_empty());
+        assert_eq!(vec!["slow", "panics", "fast"], report.abandoned);
+    }
+
+    #[tokio::test]
+    async fn closes_resources_when_the_runtime_fails() {
+        let closed = Arc::new(AtomicBool::new(false));
+        let resources = Resources::new();
+        let flag = closed.clone();
+        resources.register("pool", 0, move || async move { flag.store(true, Ordering::SeqCst) });
+
+        let result = resources.supervise(async { Err::<(), Error>("fatal".into()) }).await;
+        assert_eq!("fatal", result.unwrap_err().to_string());
+        assert!(closed.load(Ordering::SeqCst));
+    }
+}
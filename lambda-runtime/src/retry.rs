@@ -0,0 +1,347 @@
+//! Retries for the HTTP requests that a function sends to other services.
+//!
+//! [`IdempotentRetryLayer`] wraps any HTTP client modeled as a tower [`Service`],
+//! like a hyper or reqwest client behind a small adapter. Every request gets an
+//! `Idempotency-Key` header derived from the id of the invocation, so the server
+//! can recognize a request that it already handled, and requests that fail with a
+//! transient error are sent again with the same key.
+//!
+//! Retries are bound by the deadline of the invocation: a request is only retried
+//! when the backoff ends before the deadline, minus a safety margin that leaves
+//! time for the last attempt and for the function to report its result.
+//!
+//! Keys are made of the request id and the position of the request in the
+//! invocation, like `8476a536-e9f4-11e8-9739-2dfe598c3fcd-1`. Lambda keeps the
+//! request id when it retries an asynchronous invocation, so the requests sent
+//! by a retried invocation reuse the keys of the first one, as long as the
+//! function sends them in the same order.
+//!
+//! # Example
+//! ```no_run
+//! use bytes::Bytes;
+//! use http::{Request, Response};
+//! use lambda_runtime::{retry::IdempotentRetryLayer, service_fn, tower::{Layer, ServiceExt}, Error, LambdaEvent};
+//! use serde_json::Value;
+//!
+//! async fn send(req: Request<Bytes>) -> Result<Response<Bytes>, Error> {
+//!     // Send the request with your HTTP client.
+//!     # unimplemented!()
+//! }
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<u16, Error> {
+//!     let client = IdempotentRetryLayer::new(&event.context).layer(service_fn(send));
+//!     let req = Request::post("https://payments.example.com/charges").body(Bytes::from(event.payload.to_string()))?;
+//!     let response = client.oneshot(req).await?;
+//!     Ok(response.status().as_u16())
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_runtime::run(service_fn(func)).await
+//! }
+//! ```
+use crate::{Context, Error};
+use futures::future::BoxFuture;
+use http::{header::RETRY_AFTER, HeaderName, HeaderValue, Request, Response, StatusCode};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{self, Poll},
+    time::{Duration, SystemTime},
+};
+use tower::{Layer, Service, ServiceExt};
+
+/// Default name of the header that carries the idempotency key.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// How many times, and how often, requests are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_backoff: Duration,
+    max_backoff: Duration,
+    safety_margin: Duration,
+}
+
+impl RetryPolicy {
+    /// Create a policy that sends every request up to `max_attempts` times.
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// Set the backoff before the first retry. The backoff doubles after every attempt.
+    pub fn base_backoff(mut self, backoff: Duration) -> Self {
+        self.base_backoff = backoff;
+        self
+    }
+
+    /// Set the maximum backoff between two attempts.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Set the time kept before the deadline of the invocation, during which requests are not retried.
+    pub fn safety_margin(mut self, margin: Duration) -> Self {
+        self.safety_margin = margin;
+        self
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.base_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, with a backoff from 100 milliseconds up to 2 seconds, and a safety margin of 500 milliseconds.
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            safety_margin: Duration::from_millis(500),
+        }
+    }
+}
+
+/// A [`Layer`] that sets idempotency keys on HTTP requests and retries them until the deadline of an invocation.
+///
+/// Create a new layer in every invocation, with the invocation's [`Context`].
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct IdempotentRetryLayer {
+    request_id: Arc<str>,
+    deadline: SystemTime,
+    sequence: Arc<AtomicU64>,
+    policy: RetryPolicy,
+    header: HeaderName,
+}
+
+impl IdempotentRetryLayer {
+    /// Create a new layer for the invocation described by `context`, with the default [`RetryPolicy`].
+    pub fn new(context: &Context) -> Self {
+        IdempotentRetryLayer {
+            request_id: context.request_id.as_str().into(),
+            deadline: context.deadline(),
+            sequence: Arc::new(AtomicU64::new(0)),
+            policy: RetryPolicy::default(),
+            header: HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
+        }
+    }
+
+    /// Retry requests according to `policy`.
+    pub fn policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Send the idempotency key in the header `header`, instead of `Idempotency-Key`.
+    pub fn header(mut self, header: HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    fn next_key(&self) -> HeaderValue {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        HeaderValue::from_str(&format!("{}-{sequence}", self.request_id))
+            .unwrap_or_else(|_| HeaderValue::from(sequence))
+    }
+
+    // Time left to retry before the safety margin, if any.
+    fn remaining(&self) -> Duration {
+        self.deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .saturating_sub(self.policy.safety_margin)
+    }
+}
+
+impl<S> Layer<S> for IdempotentRetryLayer {
+    type Service = IdempotentRetry<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IdempotentRetry {
+            inner,
+            config: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that sets idempotency keys on HTTP requests and retries them until the deadline of an invocation.
+///
+/// See [`IdempotentRetryLayer`] for details.
+#[derive(Debug, Clone)]
+pub struct IdempotentRetry<S> {
+    inner: S,
+    config: IdempotentRetryLayer,
+}
+
+impl<S, B, RB> Service<Request<B>> for IdempotentRetry<S>
+where
+    S: Service<Request<B>, Response = Response<RB>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<Error>,
+    B: Clone + Send + 'static,
+    RB: Send + 'static,
+{
+    type Response = Response<RB>;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<Response<RB>, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        // The inner service was driven to readiness, so it's the one that must send the first attempt.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+        if !req.headers().contains_key(&config.header) {
+            req.headers_mut().insert(config.header.clone(), config.next_key());
+        }
+
+        Box::pin(async move {
+            let mut attempt = 1;
+            loop {
+                let result = inner.call(copy_request(&req)).await.map_err(Into::into);
+                let delay = match &result {
+                    Ok(response) if !is_transient(response.status()) => return result,
+                    Ok(response) => retry_after(response).unwrap_or_else(|| config.policy.backoff(attempt)),
+                    Err(_) => config.policy.backoff(attempt),
+                };
+                if attempt >= config.policy.max_attempts || delay >= config.remaining() {
+                    return result;
+                }
+
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                inner.ready().await.map_err(Into::into)?;
+            }
+        })
+    }
+}
+
+// `http::Request` doesn't implement `Clone` because extensions can't be cloned.
+fn copy_request<B: Clone>(req: &Request<B>) -> Request<B> {
+    let mut copy = Request::new(req.body().clone());
+    *copy.method_mut() = req.method().clone();
+    *copy.uri_mut() = req.uri().clone();
+    *copy.version_mut() = req.version();
+    *copy.headers_mut() = req.headers().clone();
+    copy
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT
+}
+
+fn retry_after<B>(response: &Response<B>) -> Option<Duration> {
+    let seconds = response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_fn;
+    use std::sync::Mutex;
+    use tower::util::BoxCloneService;
+
+    fn context(budget: Duration) -> Context {
+        let deadline = SystemTime::now() + budget;
+        Context {
+            request_id: "8476a536-e9f4-11e8-9739-2dfe598c3fcd".to_string(),
+            deadline: deadline.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64,
+            ..Default::default()
+        }
+    }
+
+    type Server = BoxCloneService<Request<&'static str>, Response<()>, Error>;
+
+    // Answers with the given statuses, in order, and records the keys it receives.
+    fn server(statuses: Vec<u16>) -> (Server, Arc<Mutex<Vec<String>>>) {
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+        let k = keys.clone();
+        let svc = BoxCloneService::new(service_fn(move |req: Request<&'static str>| {
+            k.lock()
+                .unwrap()
+                .push(req.headers()[IDEMPOTENCY_KEY_HEADER].to_str().unwrap().to_string());
+            let status = statuses.lock().unwrap().next().unwrap_or(200);
+            async move { Ok::<_, Error>(Response::builder().status(status).body(()).unwrap()) }
+        }));
+        (svc, keys)
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(3)
+            .base_backoff(Duration::from_millis(1))
+            .safety_margin(Duration::from_millis(10))
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_with_the_same_key() {
+        let (svc, keys) = server(vec![503, 429, 200]);
+        let layer = IdempotentRetryLayer::new(&context(Duration::from_secs(10))).policy(policy());
+        let response = layer.layer(svc.clone()).oneshot(Request::new("charge")).await.unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        let response = layer.layer(svc).oneshot(Request::new("refund")).await.unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        let key = "8476a536-e9f4-11e8-9739-2dfe598c3fcd";
+        let expected = vec![
+            format!("{key}-1"),
+            format!("{key}-1"),
+            format!("{key}-1"),
+            format!("{key}-2"),
+        ];
+        assert_eq!(expected, *keys.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_deadline() {
+        let (svc, keys) = server(vec![503, 503, 503]);
+        let layer = IdempotentRetryLayer::new(&context(Duration::from_millis(5))).policy(policy());
+        let response = layer.layer(svc).oneshot(Request::new("charge")).await.unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+        assert_eq!(1, keys.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn keeps_existing_keys_and_client_errors() {
+        let (svc, keys) = server(vec![400]);
+        let layer = IdempotentRetryLayer::new(&context(Duration::from_secs(10))).policy(policy());
+        let req = Request::builder()
+            .header(IDEMPOTENCY_KEY_HEADER, "custom")
+            .body("charge")
+            .unwrap();
+        let response = layer.layer(svc).oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(vec!["custom".to_string()], *keys.lock().unwrap());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy::default();
+        assert_eq!(Duration::from_millis(100), policy.backoff(1));
+        assert_eq!(Duration::from_millis(400), policy.backoff(3));
+        assert_eq!(Duration::from_secs(2), policy.backoff(10));
+        assert_eq!(Duration::from_secs(2), policy.backoff(64));
+    }
+}
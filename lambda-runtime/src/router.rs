@@ -0,0 +1,178 @@
+use crate::{Error, LambdaEvent};
+use futures::future::{self, Either, ErrInto, Ready, TryFutureExt};
+use std::{
+    collections::HashMap,
+    fmt,
+    task::{Context, Poll},
+};
+use tower::Service;
+
+/// Error returned when an invocation doesn't match any route and there is no fallback.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnknownQualifier(pub Option<String>);
+
+impl fmt::Display for UnknownQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(qualifier) => write!(f, "no handler registered for the qualifier `{qualifier}`"),
+            None => write!(f, "no handler registered for unqualified invocations"),
+        }
+    }
+}
+
+impl std::error::Error for UnknownQualifier {}
+
+/// A [`Service`] that dispatches each invocation to a different handler,
+/// based on the alias or version used to invoke the function.
+///
+/// The qualifier is parsed from the invoked function ARN with [`crate::Context::qualifier`].
+/// Invocations that don't match any route are sent to the fallback handler, or
+/// fail with [`UnknownQualifier`] when there is no fallback.
+///
+/// All the handlers must have the same type. Use [`tower::util::BoxService`] to
+/// route to handlers of different types.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, tower::util::BoxService, AliasRouter, Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// async fn blue(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// async fn green(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let router = AliasRouter::new()
+///         .route("blue", BoxService::new(service_fn(blue)))
+///         .route("green", BoxService::new(service_fn(green)))
+///         .fallback(BoxService::new(service_fn(blue)));
+///     lambda_runtime::run(router).await
+/// }
+/// ```
+pub struct AliasRouter<S> {
+    routes: HashMap<String, S>,
+    fallback: Option<S>,
+}
+
+impl<S> AliasRouter<S> {
+    /// Create a new router without routes.
+    pub fn new() -> Self {
+        AliasRouter {
+            routes: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Send invocations made through `qualifier`, an alias or a version, to `service`.
+    pub fn route(mut self, qualifier: impl Into<String>, service: S) -> Self {
+        self.routes.insert(qualifier.into(), service);
+        self
+    }
+
+    /// Send invocations that don't match any route to `service`.
+    pub fn fallback(mut self, service: S) -> Self {
+        self.fallback = Some(service);
+        self
+    }
+}
+
+impl<S> Default for AliasRouter<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> fmt::Debug for AliasRouter<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AliasRouter")
+            .field("routes", &self.routes.keys().collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl<S, A> Service<LambdaEvent<A>> for AliasRouter<S>
+where
+    S: Service<LambdaEvent<A>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = Either<ErrInto<S::Future, Error>, Ready<Result<S::Response, Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Any of the handlers can be selected for the next invocation
+        let mut pending = false;
+        for service in self.routes.values_mut().chain(self.fallback.iter_mut()) {
+            match service.poll_ready(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err.into())),
+                Poll::Pending => pending = true,
+            }
+        }
+
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn call(&mut self, req: LambdaEvent<A>) -> Self::Future {
+        let qualifier = req.context.qualifier();
+        let service = match qualifier.and_then(|q| self.routes.get_mut(q)) {
+            Some(service) => Some(service),
+            None => self.fallback.as_mut(),
+        };
+
+        match service {
+            Some(service) => Either::Left(service.call(req).err_into()),
+            None => {
+                let err = UnknownQualifier(qualifier.map(String::from));
+                Either::Right(future::ready(Err(err.into())))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, Context};
+    use tower::ServiceExt;
+
+    fn event(arn: &str) -> LambdaEvent<()> {
+        let context = Context {
+            invoked_function_arn: arn.into(),
+            ..Default::default()
+        };
+        LambdaEvent::new((), context)
+    }
+
+    #[tokio::test]
+    async fn routes_by_qualifier() {
+        let handler = |name: &'static str| service_fn(move |_: LambdaEvent<()>| async move { Ok::<_, Error>(name) });
+        let mut router = AliasRouter::new()
+            .route("blue", handler("blue"))
+            .route("green", handler("green"));
+
+        let arn = "arn:aws:lambda:us-east-1:123456789012:function:my-function";
+        let res = router.ready().await.unwrap().call(event(&format!("{arn}:green"))).await;
+        assert_eq!("green", res.unwrap());
+
+        let res = router.ready().await.unwrap().call(event(&format!("{arn}:blue"))).await;
+        assert_eq!("blue", res.unwrap());
+
+        let err = router.ready().await.unwrap().call(event(arn)).await.unwrap_err();
+        assert_eq!("no handler registered for unqualified invocations", err.to_string());
+
+        let mut router = router.fallback(handler("fallback"));
+        let res = router.ready().await.unwrap().call(event(&format!("{arn}:7"))).await;
+        assert_eq!("fallback", res.unwrap());
+    }
+}
@@ -0,0 +1,377 @@
+//! Validation of events against a [JSON Schema](https://json-schema.org/).
+//!
+//! Function URLs, and API Gateway APIs without request validators, forward any
+//! payload to the function. [`ValidateLayer`] checks every event against a schema
+//! before it's deserialized into the handler's payload type, and reports all the
+//! values that don't match the schema at once, with the JSON pointer to each value,
+//! instead of the first error that serde finds.
+//!
+//! Schemas are compiled once, when the function starts. References to other
+//! documents (`$ref`) must point inside the schema itself, because the runtime
+//! doesn't resolve files or URLs.
+//!
+//! This module requires the `schema` feature.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{schema::{JsonSchema, ValidateLayer}, service_fn, tower::Layer, Error, LambdaEvent};
+//! use serde::Deserialize;
+//! use serde_json::json;
+//!
+//! #[derive(Deserialize)]
+//! struct Order {
+//!     id: u32,
+//! }
+//!
+//! async fn func(event: LambdaEvent<Order>) -> Result<u32, Error> {
+//!     Ok(event.payload.id)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let schema = JsonSchema::compile(&json!({
+//!         "type": "object",
+//!         "properties": { "id": { "type": "integer", "minimum": 1 } },
+//!         "required": ["id"]
+//!     }))?;
+//!     lambda_runtime::run(ValidateLayer::new(schema).layer(service_fn(func))).await
+//! }
+//! ```
+use crate::{deserializer, Error, LambdaEvent};
+use futures::future::{self, BoxFuture};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// A compiled JSON Schema.
+///
+/// Cloning a schema is cheap, the compiled validators are shared.
+#[derive(Clone)]
+pub struct JsonSchema {
+    compiled: Arc<jsonschema::JSONSchema>,
+}
+
+impl JsonSchema {
+    /// Compile a schema document.
+    pub fn compile(schema: &Value) -> Result<Self, InvalidSchema> {
+        let compiled = jsonschema::JSONSchema::compile(schema).map_err(|err| InvalidSchema {
+            pointer: err.instance_path.to_string(),
+            message: err.to_string(),
+        })?;
+        Ok(JsonSchema {
+            compiled: Arc::new(compiled),
+        })
+    }
+
+    /// Return every value in `instance` that doesn't match the schema.
+    pub fn violations(&self, instance: &Value) -> Vec<Violation> {
+        match self.compiled.validate(instance) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .map(|err| Violation {
+                    pointer: err.instance_path.to_string(),
+                    message: err.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Debug for JsonSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonSchema").finish_non_exhaustive()
+    }
+}
+
+/// Error returned when a schema document is not a valid JSON Schema.
+#[derive(Debug, Clone)]
+pub struct InvalidSchema {
+    pointer: String,
+    message: String,
+}
+
+impl fmt::Display for InvalidSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid JSON Schema at `{}`: {}", self.pointer, self.message)
+    }
+}
+
+impl std::error::Error for InvalidSchema {}
+
+/// A value that doesn't match a schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Violation {
+    /// JSON pointer to the value, like `/items/0/price`. The pointer to the whole document is empty.
+    pub pointer: String,
+    /// Description of the rule that the value breaks.
+    pub message: String,
+}
+
+/// Error returned when an event, or a response, doesn't match its schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "target", content = "violations", rename_all = "lowercase")]
+pub enum ValidationError {
+    /// The event sent to the function doesn't match the event schema.
+    Event(Vec<Violation>),
+    /// The response of the function doesn't match the response schema.
+    Response(Vec<Violation>),
+}
+
+impl ValidationError {
+    /// Every value that doesn't match the schema.
+    pub fn violations(&self) -> &[Violation] {
+        match self {
+            ValidationError::Event(violations) | ValidationError::Response(violations) => violations,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = match self {
+            ValidationError::Event(_) => "event",
+            ValidationError::Response(_) => "response",
+        };
+        write!(f, "the {target} doesn't match its schema")?;
+        for (i, violation) in self.violations().iter().enumerate() {
+            let separator = if i == 0 { ": " } else { "; " };
+            write!(f, "{separator}[{}] {}", violation.pointer, violation.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// A [`Layer`] that validates events against a [`JsonSchema`] before they reach the handler.
+///
+/// Events that match the schema are deserialized into the handler's payload type.
+/// Events that don't are rejected with a [`ValidationError`], and the handler is
+/// not called.
+///
+/// A schema for responses can be set with [`ValidateLayer::response_schema`]. Responses
+/// are only validated in debug builds, to catch handlers that break their contract
+/// during development without paying for it in production.
+pub struct ValidateLayer<A> {
+    events: JsonSchema,
+    responses: Option<JsonSchema>,
+    _payload: PhantomData<fn(A)>,
+}
+
+impl<A> ValidateLayer<A> {
+    /// Create a new layer that validates events against `schema`.
+    pub fn new(schema: JsonSchema) -> Self {
+        ValidateLayer {
+            events: schema,
+            responses: None,
+            _payload: PhantomData,
+        }
+    }
+
+    /// Validate the responses of the handler against `schema` in debug builds.
+    pub fn response_schema(mut self, schema: JsonSchema) -> Self {
+        self.responses = Some(schema);
+        self
+    }
+}
+
+impl<A> Clone for ValidateLayer<A> {
+    fn clone(&self) -> Self {
+        ValidateLayer {
+            events: self.events.clone(),
+            responses: self.responses.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl<A> fmt::Debug for ValidateLayer<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValidateLayer")
+            .field("responses", &self.responses.is_some())
+            .finish()
+    }
+}
+
+impl<S, A> Layer<S> for ValidateLayer<A> {
+    type Service = Validate<S, A>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Validate {
+            inner,
+            events: self.events.clone(),
+            responses: self.responses.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+
+/// A [`Service`] that validates events against a [`JsonSchema`] before calling the inner handler.
+///
+/// See [`ValidateLayer`] for details.
+pub struct Validate<S, A> {
+    inner: S,
+    events: JsonSchema,
+    responses: Option<JsonSchema>,
+    _payload: PhantomData<fn(A)>,
+}
+
+impl<S: Clone, A> Clone for Validate<S, A> {
+    fn clone(&self) -> Self {
+        Validate {
+            inner: self.inner.clone(),
+            events: self.events.clone(),
+            responses: self.responses.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl<S: fmt::Debug, A> fmt::Debug for Validate<S, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Validate")
+            .field("inner", &self.inner)
+            .field("responses", &self.responses.is_some())
+            .finish()
+    }
+}
+
+impl<S, A> Service<LambdaEvent<Value>> for Validate<S, A>
+where
+    S: Service<LambdaEvent<A>>,
+    S::Future: Send + 'static,
+    S::Response: Serialize + Send + 'static,
+    S::Error: Into<Error>,
+    A: for<'de> Deserialize<'de>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<S::Response, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: LambdaEvent<Value>) -> Self::Future {
+        let violations = self.events.violations(&req.payload);
+        if !violations.is_empty() {
+            return Box::pin(future::ready(Err(ValidationError::Event(violations).into())));
+        }
+
+        let event = match deserializer::deserialize_value(req.payload, req.context) {
+            Ok(event) => event,
+            Err(err) => return Box::pin(future::ready(Err(err.into()))),
+        };
+        let fut = self.inner.call(event);
+        let responses = self.responses.clone().filter(|_| cfg!(debug_assertions));
+        Box::pin(async move {
+            let response = fut.await.map_err(Into::into)?;
+            if let Some(schema) = responses {
+                let violations = schema.violations(&serde_json::to_value(&response)?);
+                if !violations.is_empty() {
+                    return Err(ValidationError::Response(violations).into());
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_fn;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    #[derive(Deserialize)]
+    struct Order {
+        id: u32,
+    }
+
+    fn order_schema() -> JsonSchema {
+        JsonSchema::compile(&json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer", "minimum": 1 },
+                "items": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["id"]
+        }))
+        .unwrap()
+    }
+
+    fn event(payload: Value) -> LambdaEvent<Value> {
+        LambdaEvent::new(payload, crate::Context::default())
+    }
+
+    #[tokio::test]
+    async fn invalid_events_skip_the_handler() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let handler = service_fn(move |event: LambdaEvent<Order>| {
+            c.fetch_add(1, Ordering::SeqCst);
+            async move { Ok::<_, Error>(event.payload.id) }
+        });
+        let mut svc = ValidateLayer::new(order_schema()).layer(handler);
+
+        let err = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(event(json!({"id": 0, "items": ["a", 2]})))
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ValidationError>().unwrap();
+        let pointers = err.violations().iter().map(|v| v.pointer.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["/id", "/items/1"], pointers);
+        assert_eq!(0, calls.load(Ordering::SeqCst));
+
+        let id = svc.ready().await.unwrap().call(event(json!({"id": 7}))).await.unwrap();
+        assert_eq!(7, id);
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn responses_are_validated_in_debug_builds() {
+        let handler = service_fn(|event: LambdaEvent<Order>| async move { Ok::<_, Error>(event.payload.id) });
+        let responses = JsonSchema::compile(&json!({ "type": "integer", "maximum": 10 })).unwrap();
+        let mut svc = ValidateLayer::new(order_schema())
+            .response_schema(responses)
+            .layer(handler);
+
+        let result = svc.ready().await.unwrap().call(event(json!({"id": 11}))).await;
+        if cfg!(debug_assertions) {
+            let err = result.unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(ValidationError::Response(_))),
+                "{err}"
+            );
+        } else {
+            assert_eq!(11, result.unwrap());
+        }
+    }
+
+    #[test]
+    fn invalid_schemas_are_rejected() {
+        assert!(JsonSchema::compile(&json!({ "type": 12 })).is_err());
+    }
+
+    #[test]
+    fn errors_list_every_violation() {
+        let err = ValidationError::Event(order_schema().violations(&json!({"items": [1]})));
+        assert_eq!(2, err.violations().len());
+        assert!(
+            err.to_string().starts_with("the event doesn't match its schema: "),
+            "{err}"
+        );
+        assert_eq!(json!("event"), serde_json::to_value(&err).unwrap()["target"]);
+    }
+}
@@ -0,0 +1,483 @@
+//! Lambda functions get a fixed amount of ephemeral storage in `/tmp`, 512MB by
+//! default and up to 10GB. Its content survives across warm invocations of the
+//! same execution environment, which makes it a good place to cache model files
+//! and other large downloads, as long as the cache doesn't grow until writes fail
+//! with `no space left on device`.
+//!
+//! A [`Scratch`] directory keeps track of the files that it caches, and evicts the
+//! least recently used ones to stay within a size budget. The budget defaults to
+//! the ephemeral storage size configured with the [`EPHEMERAL_STORAGE_VAR`]
+//! environment variable. Files are written atomically, so an invocation that times
+//! out in the middle of a write never leaves a truncated file in the cache.
+//!
+//! The file operations in this module are blocking. Call them from
+//! [`tokio::task::spawn_blocking`] when they write large files.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{scratch::Scratch, Error};
+//!
+//! # fn download(_url: &str) -> Result<Vec<u8>, Error> { unimplemented!() }
+//! fn model(scratch: &Scratch) -> Result<Vec<u8>, Error> {
+//!     let path = match scratch.get("model.bin") {
+//!         Some(path) => path,
+//!         None => scratch.insert("model.bin", &download("https://example.com/model.bin")?)?,
+//!     };
+//!     Ok(std::fs::read(path)?)
+//! }
+//! ```
+use std::{
+    collections::HashMap,
+    env, fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+use tracing::debug;
+
+/// Environment variable with the ephemeral storage size of the function, in MB.
+///
+/// The Lambda service doesn't expose this setting to the function, set it to
+/// the same value as the `EphemeralStorage` property of the function.
+pub const EPHEMERAL_STORAGE_VAR: &str = "LAMBDA_EPHEMERAL_STORAGE_SIZE";
+
+/// Ephemeral storage size of a function that doesn't configure it, in MB.
+pub const DEFAULT_EPHEMERAL_STORAGE: u64 = 512;
+
+/// Directory used by [`Scratch::from_env`].
+pub const DEFAULT_SCRATCH_DIR: &str = "/tmp/lambda-scratch";
+
+const TEMP_PREFIX: &str = ".tmp-";
+
+/// Return the ephemeral storage size of the function in bytes, read from
+/// [`EPHEMERAL_STORAGE_VAR`].
+pub fn ephemeral_storage() -> u64 {
+    let mb = env::var(EPHEMERAL_STORAGE_VAR)
+        .ok()
+        .and_then(|size| size.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_EPHEMERAL_STORAGE);
+    mb * 1024 * 1024
+}
+
+/// Write `contents` to `path` atomically.
+///
+/// The contents are written to a temporary file in the same directory, which is
+/// then renamed to `path`. Readers see either the previous file or the complete
+/// new one.
+pub fn write_atomic(path: impl AsRef<Path>, contents: &[u8]) -> io::Result<()> {
+    write_atomic_with(path.as_ref(), |file| file.write_all(contents))
+}
+
+/// Write a file atomically with a function that writes its content, for
+/// instance by copying a download stream to it.
+pub fn write_atomic_with<F>(path: impl AsRef<Path>, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let temp = dir.join(format!(
+        "{TEMP_PREFIX}{}.{}.{}",
+        name.to_string_lossy(),
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = File::create(&temp).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    match result.and_then(|_| fs::rename(&temp, path)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = fs::remove_file(&temp);
+            Err(err)
+        }
+    }
+}
+
+/// Error returned by [`Scratch`] operations.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ScratchError {
+    /// The key isn't a plain file name.
+    InvalidKey(String),
+    /// The file is larger than the whole budget of the directory.
+    TooLarge {
+        /// The size of the file in bytes.
+        size: u64,
+        /// The budget of the directory in bytes.
+        budget: u64,
+    },
+    /// The file system returned an error.
+    Io(io::Error),
+}
+
+impl fmt::Display for ScratchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScratchError::InvalidKey(key) => write!(f, "invalid scratch key {key:?}, keys must be file names"),
+            ScratchError::TooLarge { size, budget } => {
+                write!(f, "{size} bytes don't fit in the scratch budget of {budget} bytes")
+            }
+            ScratchError::Io(err) => write!(f, "scratch file system error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ScratchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScratchError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ScratchError {
+    fn from(err: io::Error) -> Self {
+        ScratchError::Io(err)
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    files: HashMap<String, Entry>,
+    usage: u64,
+    clock: u64,
+}
+
+impl Entries {
+    fn touch(&mut self, key: &str) -> bool {
+        self.clock += 1;
+        match self.files.get_mut(key) {
+            Some(entry) => {
+                entry.last_used = self.clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, key: String, size: u64) {
+        self.clock += 1;
+        let entry = Entry {
+            size,
+            last_used: self.clock,
+        };
+        if let Some(previous) = self.files.insert(key, entry) {
+            self.usage -= previous.size;
+        }
+        self.usage += size;
+    }
+
+    fn remove(&mut self, key: &str) -> Option<u64> {
+        let entry = self.files.remove(key)?;
+        self.usage -= entry.size;
+        Some(entry.size)
+    }
+
+    fn least_recently_used(&self) -> Option<String> {
+        self.files
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone())
+    }
+}
+
+/// Cache directory with a size budget and least recently used eviction.
+///
+/// Create it once when the function starts and share it between invocations.
+/// Clones share the same directory and bookkeeping.
+#[derive(Debug, Clone)]
+pub struct Scratch {
+    dir: Arc<PathBuf>,
+    budget: u64,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl Scratch {
+    /// Open [`DEFAULT_SCRATCH_DIR`] with the ephemeral storage size of the
+    /// function as budget.
+    ///
+    /// Other files in `/tmp` use the same storage, lower the budget with
+    /// [`with_budget`](Scratch::with_budget) if the function writes them.
+    pub fn from_env() -> io::Result<Self> {
+        Scratch::new(DEFAULT_SCRATCH_DIR, ephemeral_storage())
+    }
+
+    /// Open a cache directory with a budget in bytes, creating it if needed.
+    ///
+    /// Files already in the directory are part of the cache, least recently
+    /// modified first. Leftovers of interrupted writes are removed.
+    pub fn new(dir: impl Into<PathBuf>, budget: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut existing = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(TEMP_PREFIX) {
+                let _ = fs::remove_file(entry.path());
+                continue;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            existing.push((modified, name, metadata.len()));
+        }
+        existing.sort();
+
+        let mut entries = Entries::default();
+        for (_, name, size) in existing {
+            entries.insert(name, size);
+        }
+
+        let scratch = Scratch {
+            dir: Arc::new(dir),
+            budget,
+            entries: Arc::new(Mutex::new(entries)),
+        };
+        scratch.evict(0)?;
+        Ok(scratch)
+    }
+
+    /// Change the budget of the directory, in bytes.
+    pub fn with_budget(self, budget: u64) -> Self {
+        Scratch { budget, ..self }
+    }
+
+    /// Return the directory of the cache.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Return the budget of the directory, in bytes.
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// Return the size of the cached files, in bytes.
+    pub fn usage(&self) -> u64 {
+        self.lock().usage
+    }
+
+    /// Return the path of a cached file, and mark it as recently used.
+    ///
+    /// Returns `None` if the file isn't in the cache, or has been removed
+    /// outside of it.
+    pub fn get(&self, key: &str) -> Option<PathBuf> {
+        let path = self.path(key).ok()?;
+        let mut entries = self.lock();
+        if !entries.touch(key) {
+            return None;
+        }
+        if !path.is_file() {
+            entries.remove(key);
+            return None;
+        }
+        Some(path)
+    }
+
+    /// Cache `contents` under `key`, evicting other files to make room for it.
+    pub fn insert(&self, key: &str, contents: &[u8]) -> Result<PathBuf, ScratchError> {
+        self.insert_with(key, contents.len() as u64, |file| file.write_all(contents))
+    }
+
+    /// Cache a file of `size` bytes written by `write`, evicting other files
+    /// to make room for it.
+    ///
+    /// When `write` writes more than `size` bytes, the file is kept and the
+    /// next insert evicts files to return within the budget.
+    pub fn insert_with<F>(&self, key: &str, size: u64, write: F) -> Result<PathBuf, ScratchError>
+    where
+        F: FnOnce(&mut File) -> io::Result<()>,
+    {
+        let path = self.path(key)?;
+        self.remove(key)?;
+        self.reserve(size)?;
+
+        write_atomic_with(&path, write)?;
+        let size = fs::metadata(&path)?.len();
+        self.lock().insert(key.to_string(), size);
+        Ok(path)
+    }
+
+    /// Return the cached file under `key`, or create it with `write`.
+    pub fn get_or_insert_with<F>(&self, key: &str, size: u64, write: F) -> Result<PathBuf, ScratchError>
+    where
+        F: FnOnce(&mut File) -> io::Result<()>,
+    {
+        match self.get(key) {
+            Some(path) => Ok(path),
+            None => self.insert_with(key, size, write),
+        }
+    }
+
+    /// Remove a file from the cache. Returns whether the file was cached.
+    pub fn remove(&self, key: &str) -> Result<bool, ScratchError> {
+        let path = self.path(key)?;
+        let removed = self.lock().remove(key).is_some();
+        match fs::remove_file(path) {
+            Ok(()) => Ok(removed),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(removed),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Evict the least recently used files until `size` more bytes fit in the
+    /// budget.
+    ///
+    /// Call it before writing large temporary files outside of the cache,
+    /// with a budget that leaves room for them.
+    pub fn reserve(&self, size: u64) -> Result<(), ScratchError> {
+        if size > self.budget {
+            return Err(ScratchError::TooLarge {
+                size,
+                budget: self.budget,
+            });
+        }
+        Ok(self.evict(size)?)
+    }
+
+    fn evict(&self, size: u64) -> io::Result<()> {
+        let mut entries = self.lock();
+        while entries.usage + size > self.budget {
+            let key = match entries.least_recently_used() {
+                Some(key) => key,
+                None => break,
+            };
+            let freed = entries.remove(&key).unwrap_or_default();
+            debug!(key, freed, "evicting scratch file");
+            match fs::remove_file(self.dir.join(&key)) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, ScratchError> {
+        let valid = !key.is_empty()
+            && key != "."
+            && key != ".."
+            && !key.starts_with(TEMP_PREFIX)
+            && !key.contains(['/', '\\', '\0']);
+        if !valid {
+            return Err(ScratchError::InvalidKey(key.to_string()));
+        }
+        Ok(self.dir.join(key))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("lambda-scratch-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_files() {
+        let dir = dir("evict");
+        let scratch = Scratch::new(&dir, 10).unwrap();
+        scratch.insert("a", b"aaaa").unwrap();
+        scratch.insert("b", b"bbbb").unwrap();
+        assert!(scratch.get("a").is_some());
+
+        scratch.insert("c", b"cccc").unwrap();
+        assert_eq!(8, scratch.usage());
+        assert!(scratch.get("a").is_some());
+        assert!(scratch.get("b").is_none());
+        assert!(!dir.join("b").exists());
+        assert_eq!(b"cccc", fs::read(dir.join("c")).unwrap().as_slice());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_files_larger_than_the_budget() {
+        let dir = dir("large");
+        let scratch = Scratch::new(&dir, 4).unwrap();
+        scratch.insert("a", b"aaaa").unwrap();
+
+        let err = scratch.insert("b", b"bbbbb").unwrap_err();
+        assert!(matches!(err, ScratchError::TooLarge { size: 5, budget: 4 }), "{err}");
+        assert!(scratch.get("a").is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_keys_outside_of_the_directory() {
+        let dir = dir("keys");
+        let scratch = Scratch::new(&dir, 4).unwrap();
+        for key in ["", "..", "../a", "a/b", ".tmp-a"] {
+            let err = scratch.insert(key, b"a").unwrap_err();
+            assert!(matches!(err, ScratchError::InvalidKey(_)), "{key}: {err}");
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reopens_existing_files() {
+        let dir = dir("reopen");
+        Scratch::new(&dir, 10).unwrap().insert("a", b"aaaa").unwrap();
+        fs::write(dir.join(".tmp-b.1.0"), b"partial").unwrap();
+
+        let scratch = Scratch::new(&dir, 10).unwrap();
+        assert_eq!(4, scratch.usage());
+        assert!(scratch.get("a").is_some());
+        assert!(!dir.join(".tmp-b.1.0").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn atomic_writes_replace_files() {
+        let dir = dir("atomic");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file");
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(b"second", fs::read(&path).unwrap().as_slice());
+
+        let err = write_atomic_with(&path, |_| Err(io::Error::new(io::ErrorKind::WriteZero, "failed"))).unwrap_err();
+        assert_eq!("failed", err.to_string());
+        assert_eq!(b"second", fs::read(&path).unwrap().as_slice());
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use std::{error::Error, fmt};
+
+use serde::{Serialize, Serializer};
+
+use crate::json::JsonPolicy;
+
+const ERROR_CONTEXT: &str = "failed to serialize the function's response";
+
+/// Response serialization error.
+/// Returned when the value returned by the function cannot be
+/// serialized into JSON, for example a map with non-string keys.
+#[derive(Debug)]
+pub(crate) struct SerializeError {
+    type_name: &'static str,
+    inner: serde_path_to_error::Error<serde_json::Error>,
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.inner.path().to_string();
+        let err = self.inner.inner();
+        if path == "." {
+            write!(f, "{ERROR_CONTEXT} of type `{}`: {err}", self.type_name)
+        } else {
+            write!(f, "{ERROR_CONTEXT} of type `{}`: [{path}] {err}", self.type_name)
+        }
+    }
+}
+
+impl Error for SerializeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.inner)
+    }
+}
+
+// Applies the policy underneath the path tracking serializer,
+// so errors raised by the policy report the path of the value.
+struct Policy<'a, T>(&'a T, JsonPolicy);
+
+impl<'a, T: Serialize> Serialize for Policy<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.1.serialize(self.0, serializer)
+    }
+}
+
+/// Serialize the value returned by the function into JSON, with the given policy.
+pub(crate) fn serialize<T>(value: &T, json: JsonPolicy) -> Result<Vec<u8>, SerializeError>
+where
+    T: Serialize,
+{
+    let mut body = Vec::new();
+    let ser = &mut serde_json::Serializer::new(&mut body);
+    serde_path_to_error::serialize(&Policy(value, json), ser).map_err(|inner| SerializeError {
+        type_name: std::any::type_name::<T>(),
+        inner,
+    })?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::NonFiniteFloats;
+    use std::collections::HashMap;
+
+    #[derive(Default, Serialize)]
+    struct Response {
+        counts: HashMap<(u8, u8), u32>,
+        ratios: Vec<f64>,
+    }
+
+    #[test]
+    fn report_the_path_of_invalid_values() {
+        let response = Response {
+            counts: HashMap::from([((1, 2), 3)]),
+            ..Default::default()
+        };
+        let err = serialize(&response, JsonPolicy::default()).unwrap_err();
+        assert_eq!(
+            "failed to serialize the function's response of type `lambda_runtime::serializer::tests::Response`: [counts] key must be a string",
+            err.to_string()
+        );
+
+        let response = Response {
+            ratios: vec![0.5, f64::NAN],
+            ..Default::default()
+        };
+        let err = serialize(&response, JsonPolicy::new().non_finite_floats(NonFiniteFloats::Reject)).unwrap_err();
+        assert_eq!(
+            "failed to serialize the function's response of type `lambda_runtime::serializer::tests::Response`: [ratios[1]] non-finite float NaN",
+            err.to_string()
+        );
+
+        assert_eq!(
+            b"{\"counts\":{},\"ratios\":[]}".to_vec(),
+            serialize(&Response::default(), JsonPolicy::default()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! Functions read their settings from environment variables, often with
+//! `env::var(..).unwrap()` where they're used. When a deployment changes the
+//! code and the configuration separately, like a blue/green deployment of an
+//! alias, a missing variable panics in the middle of an invocation, and only the
+//! first missing variable is reported.
+//!
+//! A type that implements [`FromEnv`] declares all the variables that the
+//! function needs, and reads them all at once in the init phase. Every missing or
+//! invalid variable is listed in the [`EnvError`], and [`Init::env`](crate::Init::env)
+//! reports it to the Runtime API, so the function refuses to start with an
+//! incompatible environment instead of failing invocations.
+//!
+//! With the `macros` feature, `#[derive(FromEnv)]` implements the trait for
+//! structs with named fields. Every field is read from the variable with the name
+//! of the field in uppercase, and parsed with [`FromStr`]. Fields accept these
+//! attributes:
+//! - `#[env(name = "VAR")]`: read the field from `VAR`.
+//! - `#[env(default = "value")]`: use `value` when the variable is not set.
+//!
+//! Fields of type `Option<T>` are optional. The struct accepts
+//! `#[env(prefix = "APP_")]` to prefix the names of all its variables. Variables
+//! set to an empty string are considered missing.
+//!
+//! # Example
+//! ```ignore
+//! use lambda_runtime::{settings::FromEnv, Error, Init};
+//!
+//! #[derive(FromEnv)]
+//! struct Settings {
+//!     table_name: String,
+//!     #[env(default = "3")]
+//!     max_retries: u32,
+//!     #[env(name = "API_ENDPOINT")]
+//!     endpoint: Option<String>,
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let settings: Settings = Init::new().env().await?;
+//!     // ...
+//!     Ok(())
+//! }
+//! ```
+use std::{ffi::OsString, fmt, str::FromStr};
+
+#[cfg(feature = "macros")]
+pub use lambda_runtime_macros::FromEnv;
+
+/// A type that reads its value from environment variables.
+///
+/// See the [module documentation](self) for details.
+pub trait FromEnv: Sized {
+    /// Read the variables of the value from `reader`.
+    ///
+    /// Read every variable before returning, so all the problems are reported.
+    /// The reader records the problems, and the function returns `None` when
+    /// there is one.
+    fn read(reader: &mut EnvReader<'_>) -> Option<Self>;
+
+    /// Read the value from the environment of the process.
+    fn from_env() -> Result<Self, EnvError> {
+        Self::from_lookup(&|name| std::env::var_os(name))
+    }
+
+    /// Read the value with `lookup`, which returns the value of a variable.
+    fn from_lookup(lookup: &dyn Fn(&str) -> Option<OsString>) -> Result<Self, EnvError> {
+        let mut reader = EnvReader {
+            lookup,
+            problems: Vec::new(),
+        };
+        let value = Self::read(&mut reader);
+        match value {
+            Some(value) if reader.problems.is_empty() => Ok(value),
+            _ => Err(EnvError {
+                problems: reader.problems,
+            }),
+        }
+    }
+}
+
+/// Reader of environment variables that records every problem it finds.
+pub struct EnvReader<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<OsString>,
+    problems: Vec<EnvProblem>,
+}
+
+impl EnvReader<'_> {
+    /// Read and parse a variable that must be set.
+    pub fn required<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match self.optional(name)? {
+            Some(value) => Some(value),
+            None => {
+                self.problem(name, ProblemKind::Missing);
+                None
+            }
+        }
+    }
+
+    /// Read and parse a variable that may be unset.
+    ///
+    /// Returns `None` when the variable is invalid, and `Some(None)` when it's not set.
+    pub fn optional<T>(&mut self, name: &str) -> Option<Option<T>>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let value = match (self.lookup)(name) {
+            None => return Some(None),
+            Some(value) if value.is_empty() => return Some(None),
+            Some(value) => value,
+        };
+        let value = match value.to_str() {
+            Some(value) => value,
+            None => {
+                self.problem(name, ProblemKind::NotUnicode);
+                return None;
+            }
+        };
+        match value.parse() {
+            Ok(value) => Some(Some(value)),
+            Err(err) => {
+                self.problem(name, ProblemKind::Invalid(err.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Read and parse a variable, or parse `default` when it's not set.
+    pub fn or_default<T>(&mut self, name: &str, default: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match self.optional(name)? {
+            Some(value) => Some(value),
+            None => match default.parse() {
+                Ok(value) => Some(value),
+                Err(err) => {
+                    self.problem(name, ProblemKind::InvalidDefault(err.to_string()));
+                    None
+                }
+            },
+        }
+    }
+
+    /// Record a problem with a variable, for values that are validated after
+    /// they're parsed.
+    pub fn problem(&mut self, name: &str, kind: ProblemKind) {
+        self.problems.push(EnvProblem {
+            name: name.to_string(),
+            kind,
+        });
+    }
+}
+
+impl fmt::Debug for EnvReader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvReader").field("problems", &self.problems).finish()
+    }
+}
+
+/// A problem with an environment variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvProblem {
+    /// The name of the variable.
+    pub name: String,
+    /// What is wrong with the variable.
+    pub kind: ProblemKind,
+}
+
+/// What is wrong with an environment variable.
+///
+/// The values of the variables are not included, because they may be secrets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProblemKind {
+    /// The variable is not set, or is empty.
+    Missing,
+    /// The variable is not valid Unicode.
+    NotUnicode,
+    /// The variable can't be parsed, with the reason.
+    Invalid(String),
+    /// The variable is not set, and its default value can't be parsed, with the reason.
+    InvalidDefault(String),
+}
+
+impl fmt::Display for EnvProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = &self.name;
+        match &self.kind {
+            ProblemKind::Missing => write!(f, "{name} is missing"),
+            ProblemKind::NotUnicode => write!(f, "{name} is not valid Unicode"),
+            ProblemKind::Invalid(reason) => write!(f, "{name} is invalid: {reason}"),
+            ProblemKind::InvalidDefault(reason) => write!(f, "the default value of {name} is invalid: {reason}"),
+        }
+    }
+}
+
+/// Error returned when environment variables are missing or invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvError {
+    problems: Vec<EnvProblem>,
+}
+
+impl EnvError {
+    /// All the problems found in the environment.
+    pub fn problems(&self) -> &[EnvProblem] {
+        &self.problems
+    }
+}
+
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid environment: ")?;
+        for (i, problem) in self.problems.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for EnvError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug)]
+    struct Settings {
+        table_name: String,
+        max_retries: u32,
+        endpoint: Option<String>,
+    }
+
+    impl FromEnv for Settings {
+        fn read(reader: &mut EnvReader<'_>) -> Option<Self> {
+            let table_name = reader.required("TABLE_NAME");
+            let max_retries = reader.or_default("MAX_RETRIES", "3");
+            let endpoint = reader.optional("API_ENDPOINT");
+            Some(Settings {
+                table_name: table_name?,
+                max_retries: max_retries?,
+                endpoint: endpoint?,
+            })
+        }
+    }
+
+    fn read(vars: &[(&str, &str)]) -> Result<Settings, EnvError> {
+        let vars: HashMap<String, OsString> = vars.iter().map(|(k, v)| (k.to_string(), v.into())).collect();
+        Settings::from_lookup(&|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn reads_the_variables() {
+        let settings = read(&[("TABLE_NAME", "orders"), ("API_ENDPOINT", "https://example.com")]).unwrap();
+        assert_eq!("orders", settings.table_name);
+        assert_eq!(3, settings.max_retries);
+        assert_eq!(Some("https://example.com"), settings.endpoint.as_deref());
+
+        let settings = read(&[("TABLE_NAME", "orders"), ("MAX_RETRIES", "5"), ("API_ENDPOINT", "")]).unwrap();
+        assert_eq!(5, settings.max_retries);
+        assert_eq!(None, settings.endpoint);
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let err = read(&[("TABLE_NAME", ""), ("MAX_RETRIES", "many")]).unwrap_err();
+        assert_eq!(2, err.problems().len());
+        assert_eq!(ProblemKind::Missing, err.problems()[0].kind);
+        assert_eq!("MAX_RETRIES", err.problems()[1].name);
+        assert_eq!(
+            "invalid environment: TABLE_NAME is missing, MAX_RETRIES is invalid: invalid digit found in string",
+            err.to_string()
+        );
+    }
+}
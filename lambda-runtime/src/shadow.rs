@@ -0,0 +1,333 @@
+//! Mirrored and canary invocations of a second handler, for handler migrations.
+//!
+//! [`ShadowLayer`] sends a percentage of the invocations to a secondary handler,
+//! usually the new implementation of a handler that is being rewritten:
+//!
+//! * In [mirror](ShadowLayer::mirror) mode, the selected invocations are sent
+//!   to both handlers concurrently. The response of the primary handler is
+//!   always returned, and the response of the secondary handler is compared
+//!   with it. Divergences are logged, or reported to a custom callback.
+//! * In [canary](ShadowLayer::canary) mode, the selected invocations are only
+//!   sent to the secondary handler, which answers them.
+//!
+//! Invocations are selected from a hash of their request id, so retries of
+//! the same invocation go to the same handler.
+//!
+//! Mirrored invocations run the secondary handler too, with the same side
+//! effects. Only mirror handlers whose side effects are idempotent, or
+//! disabled in the secondary handler.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{service_fn, shadow::ShadowLayer, tower::Layer, Error, LambdaEvent};
+//! use serde_json::Value;
+//!
+//! async fn legacy(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! async fn rewrite(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     // Compare the rewrite with the legacy handler on 10% of the invocations.
+//!     let layer = ShadowLayer::mirror(service_fn(rewrite), 10);
+//!     lambda_runtime::run(layer.layer(service_fn(legacy))).await
+//! }
+//! ```
+use crate::{Error, LambdaEvent};
+use futures::future::BoxFuture;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service, ServiceExt};
+use tracing::{debug, warn};
+
+/// How the selected invocations are sent to the secondary handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowMode {
+    /// Send them to both handlers, and answer with the primary handler.
+    Mirror,
+    /// Only send them to the secondary handler, and answer with it.
+    Canary,
+}
+
+/// A mirrored invocation where the handlers didn't return the same result.
+#[derive(Debug)]
+pub struct Divergence<'a, B> {
+    /// Id of the invocation.
+    pub request_id: &'a str,
+    /// Result of the primary handler.
+    pub primary: Result<&'a B, &'a Error>,
+    /// Result of the secondary handler.
+    pub secondary: Result<&'a B, &'a Error>,
+}
+
+type Reporter<B> = Arc<dyn Fn(Divergence<'_, B>) + Send + Sync>;
+type Comparator<B> = Arc<dyn Fn(&B, &B) -> bool + Send + Sync>;
+
+/// A [`Layer`] that sends a percentage of the invocations to a secondary handler.
+///
+/// See the [module documentation](self) for details.
+pub struct ShadowLayer<T, B> {
+    secondary: T,
+    mode: ShadowMode,
+    percent: u8,
+    compare: Comparator<B>,
+    report: Reporter<B>,
+}
+
+impl<T, B> ShadowLayer<T, B>
+where
+    B: PartialEq + fmt::Debug + 'static,
+{
+    /// Mirror `percent` of the invocations to `secondary`, and compare its results with the primary handler.
+    pub fn mirror(secondary: T, percent: u8) -> Self {
+        Self::new(secondary, ShadowMode::Mirror, percent)
+    }
+
+    /// Send `percent` of the invocations to `secondary` instead of the primary handler.
+    pub fn canary(secondary: T, percent: u8) -> Self {
+        Self::new(secondary, ShadowMode::Canary, percent)
+    }
+
+    fn new(secondary: T, mode: ShadowMode, percent: u8) -> Self {
+        ShadowLayer {
+            secondary,
+            mode,
+            percent: percent.min(100),
+            compare: Arc::new(|primary: &B, secondary: &B| primary == secondary),
+            report: Arc::new(log_divergence),
+        }
+    }
+}
+
+impl<T, B> ShadowLayer<T, B> {
+    /// Compare the responses of the handlers with `compare` instead of `PartialEq`.
+    ///
+    /// Useful when responses contain values that are expected to differ, like timestamps.
+    pub fn compare(self, compare: impl Fn(&B, &B) -> bool + Send + Sync + 'static) -> Self {
+        ShadowLayer {
+            compare: Arc::new(compare),
+            ..self
+        }
+    }
+
+    /// Report divergences to `report` instead of logging them, for example to emit metrics.
+    pub fn on_divergence(self, report: impl Fn(Divergence<'_, B>) + Send + Sync + 'static) -> Self {
+        ShadowLayer {
+            report: Arc::new(report),
+            ..self
+        }
+    }
+}
+
+fn log_divergence<B: fmt::Debug>(divergence: Divergence<'_, B>) {
+    warn!(
+        request_id = divergence.request_id,
+        primary = ?divergence.primary,
+        secondary = ?divergence.secondary,
+        "the secondary handler diverged from the primary handler"
+    );
+}
+
+impl<T: Clone, B> Clone for ShadowLayer<T, B> {
+    fn clone(&self) -> Self {
+        ShadowLayer {
+            secondary: self.secondary.clone(),
+            mode: self.mode,
+            percent: self.percent,
+            compare: self.compare.clone(),
+            report: self.report.clone(),
+        }
+    }
+}
+
+impl<T, B> fmt::Debug for ShadowLayer<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShadowLayer")
+            .field("mode", &self.mode)
+            .field("percent", &self.percent)
+            .finish()
+    }
+}
+
+impl<S, T: Clone, B> Layer<S> for ShadowLayer<T, B> {
+    type Service = Shadow<S, T, B>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Shadow {
+            primary: inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that sends a percentage of the invocations to a secondary handler.
+///
+/// See [`ShadowLayer`] for details.
+pub struct Shadow<S, T, B> {
+    primary: S,
+    layer: ShadowLayer<T, B>,
+}
+
+impl<S, T, B> Shadow<S, T, B> {
+    fn is_selected(&self, request_id: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        request_id.hash(&mut hasher);
+        hasher.finish() % 100 < u64::from(self.layer.percent)
+    }
+}
+
+impl<S: Clone, T: Clone, B> Clone for Shadow<S, T, B> {
+    fn clone(&self) -> Self {
+        Shadow {
+            primary: self.primary.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, T, B> fmt::Debug for Shadow<S, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shadow")
+            .field("primary", &self.primary)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<S, T, A, B> Service<LambdaEvent<A>> for Shadow<S, T, B>
+where
+    S: Service<LambdaEvent<A>, Response = B>,
+    S::Future: Send + 'static,
+    S::Error: Into<Error>,
+    T: Service<LambdaEvent<A>, Response = B> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+    T::Error: Into<Error>,
+    A: Clone + Send + 'static,
+    B: Send + 'static,
+{
+    type Response = B;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<B, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.primary.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, event: LambdaEvent<A>) -> Self::Future {
+        if !self.is_selected(&event.context.request_id) {
+            let fut = self.primary.call(event);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+
+        let secondary = self.layer.secondary.clone();
+        if self.layer.mode == ShadowMode::Canary {
+            debug!(request_id = %event.context.request_id, "sending the invocation to the secondary handler");
+            return Box::pin(async move { secondary.oneshot(event).await.map_err(Into::into) });
+        }
+
+        let request_id = event.context.request_id.clone();
+        let primary = self.primary.call(event.clone());
+        let compare = self.layer.compare.clone();
+        let report = self.layer.report.clone();
+        Box::pin(async move {
+            let secondary = async move { secondary.oneshot(event).await.map_err(Into::into) };
+            let (primary, secondary): (Result<B, Error>, Result<B, Error>) =
+                futures::join!(async move { primary.await.map_err(Into::into) }, secondary);
+            let diverged = match (&primary, &secondary) {
+                (Ok(primary), Ok(secondary)) => !compare(primary, secondary),
+                (Err(primary), Err(secondary)) => primary.to_string() != secondary.to_string(),
+                _ => true,
+            };
+            if diverged {
+                report(Divergence {
+                    request_id: &request_id,
+                    primary: primary.as_ref(),
+                    secondary: secondary.as_ref(),
+                });
+            }
+            primary
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, Context};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    fn event(request_id: &str, payload: u32) -> LambdaEvent<u32> {
+        let context = Context {
+            request_id: request_id.to_string(),
+            ..Default::default()
+        };
+        LambdaEvent::new(payload, context)
+    }
+
+    async fn double(event: LambdaEvent<u32>) -> Result<u32, Error> {
+        Ok(event.payload * 2)
+    }
+
+    async fn square(event: LambdaEvent<u32>) -> Result<u32, Error> {
+        Ok(event.payload * event.payload)
+    }
+
+    #[tokio::test]
+    async fn mirror_reports_divergences_and_returns_the_primary_response() {
+        let divergences = Arc::new(Mutex::new(Vec::new()));
+        let d = divergences.clone();
+        let layer = ShadowLayer::mirror(service_fn(square), 100).on_divergence(move |divergence| {
+            let secondary = *divergence.secondary.unwrap();
+            d.lock().unwrap().push((divergence.request_id.to_string(), secondary));
+        });
+        let mut service = layer.layer(service_fn(double));
+
+        assert_eq!(4, service.call(event("same", 2)).await.unwrap());
+        assert_eq!(6, service.call(event("different", 3)).await.unwrap());
+        assert_eq!(vec![("different".to_string(), 9)], *divergences.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn canary_answers_with_the_secondary_handler() {
+        let mut service = ShadowLayer::canary(service_fn(square), 100).layer(service_fn(double));
+        assert_eq!(9, service.call(event("1", 3)).await.unwrap());
+
+        let mut service = ShadowLayer::canary(service_fn(square), 0).layer(service_fn(double));
+        assert_eq!(6, service.call(event("1", 3)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn selects_a_stable_percentage_of_invocations() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let secondary = service_fn(move |event: LambdaEvent<u32>| {
+            c.fetch_add(1, Ordering::SeqCst);
+            async move { Ok::<_, Error>(event.payload) }
+        });
+        let mut service = ShadowLayer::mirror(secondary, 25).layer(service_fn(|event: LambdaEvent<u32>| async move {
+            Ok::<_, Error>(event.payload)
+        }));
+
+        for i in 0..1000 {
+            service.call(event(&format!("request-{i}"), i)).await.unwrap();
+        }
+        let selected = calls.load(Ordering::SeqCst);
+        assert!((150..350).contains(&selected), "{selected} invocations selected");
+
+        let layer = ShadowLayer::<_, u32>::mirror(service_fn(square), 50);
+        let service = layer.layer(service_fn(double));
+        assert_eq!(service.is_selected("request-1"), service.is_selected("request-1"));
+    }
+}
@@ -0,0 +1,469 @@
+//! Functions used as tasks of Step Functions state machines can wait for a
+//! callback with a [`TaskToken`], instead of returning their output. The
+//! [`TaskCallbacks`] client sends the task's heartbeats, output, or
+//! [`StatesError`] for a token. Sending the request to the Step Functions API
+//! is delegated to a [`CallbackTransport`], usually backed by the AWS SDK.
+use crate::{Context, Error};
+use futures::future::{self, BoxFuture, Either};
+use serde::{Deserialize, Serialize};
+use std::{fmt, future::Future, time::Duration};
+use tracing::warn;
+
+/// Maximum length of the error name accepted by `SendTaskFailure`.
+pub const MAX_ERROR_LENGTH: usize = 256;
+/// Maximum length of the error cause accepted by `SendTaskFailure`.
+pub const MAX_CAUSE_LENGTH: usize = 32768;
+/// Error name reported when the function runs out of time before the task completes.
+pub const TIMEOUT_ERROR: &str = "Lambda.TaskTimedOut";
+
+/// Token that identifies a task waiting for a callback.
+///
+/// State machines pass the token to the function in the payload, usually
+/// with `"TaskToken.$": "$$.Task.Token"`.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct TaskToken(String);
+
+impl TaskToken {
+    /// Create a token from its value.
+    pub fn new(token: impl Into<String>) -> Self {
+        TaskToken(token.into())
+    }
+
+    /// The value of the token.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// Tokens grant access to the task, keep them out of the logs.
+impl fmt::Debug for TaskToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TaskToken(<{} bytes>)", self.0.len())
+    }
+}
+
+/// Error reported to a state machine, that `Retry` and `Catch` rules match by name.
+///
+/// Names that start with `States.` are reserved by Step Functions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatesError {
+    error: String,
+    cause: String,
+}
+
+impl StatesError {
+    /// Create an error with the name matched by `ErrorEquals`, and a cause
+    /// for humans. Values longer than Step Functions accepts are truncated.
+    pub fn new(error: impl Into<String>, cause: impl Into<String>) -> Self {
+        StatesError {
+            error: truncate(error.into(), MAX_ERROR_LENGTH),
+            cause: truncate(cause.into(), MAX_CAUSE_LENGTH),
+        }
+    }
+
+    /// Create an error named `error`, with the messages of `err` and its sources as the cause.
+    pub fn from_error(error: impl Into<String>, err: &(dyn std::error::Error + 'static)) -> Self {
+        let mut cause = err.to_string();
+        let mut source = err.source();
+        while let Some(err) = source {
+            cause.push_str(": ");
+            cause.push_str(&err.to_string());
+            source = err.source();
+        }
+        StatesError::new(error, cause)
+    }
+
+    /// The name of the error.
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+
+    /// The cause of the error.
+    pub fn cause(&self) -> &str {
+        &self.cause
+    }
+}
+
+impl fmt::Display for StatesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.error, self.cause)
+    }
+}
+
+impl std::error::Error for StatesError {}
+
+fn truncate(mut value: String, max: usize) -> String {
+    if value.len() > max {
+        let mut end = max;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        value.truncate(end);
+    }
+    value
+}
+
+/// The error type that the runtime reports when a handler fails with an `E`.
+///
+/// Use it in the `ErrorEquals` of the `Retry` and `Catch` rules of tasks that
+/// invoke the function directly, instead of waiting for a callback.
+///
+/// # Example
+/// ```
+/// use lambda_runtime::step_functions::error_type;
+///
+/// #[derive(Debug)]
+/// struct PaymentDeclined;
+///
+/// assert!(error_type::<PaymentDeclined>().ends_with("::PaymentDeclined"));
+/// ```
+pub fn error_type<E: ?Sized>() -> &'static str {
+    std::any::type_name::<E>()
+}
+
+/// Outcome of a task, sent to Step Functions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Callback {
+    /// The task is still running, sent with `SendTaskHeartbeat`.
+    Heartbeat,
+    /// The task completed, sent with `SendTaskSuccess`.
+    Success {
+        /// The serialized output of the task.
+        output: String,
+    },
+    /// The task failed, sent with `SendTaskFailure`.
+    Failure(StatesError),
+}
+
+/// Request sent to the Step Functions API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallbackRequest {
+    /// The token of the task.
+    pub task_token: TaskToken,
+    /// The outcome of the task.
+    pub callback: Callback,
+}
+
+/// Sends task callbacks to the Step Functions API.
+///
+/// # Example
+/// ```ignore
+/// use futures::future::BoxFuture;
+/// use lambda_runtime::{step_functions::{Callback, CallbackRequest, CallbackTransport}, Error};
+///
+/// struct SdkTransport(aws_sdk_sfn::Client);
+///
+/// impl CallbackTransport for SdkTransport {
+///     fn send(&self, req: CallbackRequest) -> BoxFuture<'_, Result<(), Error>> {
+///         Box::pin(async move {
+///             let token = req.task_token.as_str();
+///             match req.callback {
+///                 Callback::Heartbeat => {
+///                     self.0.send_task_heartbeat().task_token(token).send().await?;
+///                 }
+///                 Callback::Success { output } => {
+///                     self.0.send_task_success().task_token(token).output(output).send().await?;
+///                 }
+///                 Callback::Failure(err) => {
+///                     self.0
+///                         .send_task_failure()
+///                         .task_token(token)
+///                         .error(err.error())
+///                         .cause(err.cause())
+///                         .send()
+///                         .await?;
+///                 }
+///             }
+///             Ok(())
+///         })
+///     }
+/// }
+/// ```
+pub trait CallbackTransport {
+    /// Send the callback to the Step Functions API.
+    fn send(&self, req: CallbackRequest) -> BoxFuture<'_, Result<(), Error>>;
+}
+
+/// Error returned when a callback can't be sent.
+#[derive(Debug)]
+pub enum CallbackError {
+    /// The output couldn't be serialized.
+    Serialize(serde_json::Error),
+    /// The transport failed to send the callback.
+    Transport(Error),
+}
+
+impl fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallbackError::Serialize(err) => write!(f, "failed to serialize the task output: {err}"),
+            CallbackError::Transport(err) => write!(f, "failed to send the task callback: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CallbackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CallbackError::Serialize(err) => Some(err),
+            CallbackError::Transport(err) => Some(err.as_ref()),
+        }
+    }
+}
+
+/// Client to send the callbacks of tasks.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{
+///     step_functions::{CallbackTransport, StatesError, TaskCallbacks, TaskToken},
+///     Error, LambdaEvent,
+/// };
+/// use serde::Deserialize;
+/// use std::time::Duration;
+///
+/// #[derive(Deserialize)]
+/// #[serde(rename_all = "camelCase")]
+/// struct Request {
+///     task_token: TaskToken,
+///     order_id: String,
+/// }
+///
+/// async fn fulfill(_order_id: &str) -> Result<String, StatesError> {
+///     # unimplemented!()
+/// }
+///
+/// async fn handler<T: CallbackTransport>(callbacks: &TaskCallbacks<T>, event: LambdaEvent<Request>) -> Result<(), Error> {
+///     let Request { task_token, order_id } = event.payload;
+///     callbacks
+///         .complete(&event.context, &task_token, Duration::from_secs(30), fulfill(&order_id))
+///         .await?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TaskCallbacks<T> {
+    transport: T,
+    reserve: Duration,
+}
+
+impl<T> TaskCallbacks<T>
+where
+    T: CallbackTransport,
+{
+    /// Create a new client with the given transport.
+    pub fn new(transport: T) -> Self {
+        TaskCallbacks {
+            transport,
+            reserve: Duration::from_millis(500),
+        }
+    }
+
+    /// Set the time kept before the end of the invocation to report a timeout
+    /// in [`complete`](Self::complete). The default is 500ms.
+    pub fn reserve(self, reserve: Duration) -> Self {
+        TaskCallbacks { reserve, ..self }
+    }
+
+    /// Tell Step Functions that the task is still running.
+    pub async fn heartbeat(&self, token: &TaskToken) -> Result<(), CallbackError> {
+        self.send(token, Callback::Heartbeat).await
+    }
+
+    /// Complete the task with `output`.
+    pub async fn succeed<O: Serialize>(&self, token: &TaskToken, output: &O) -> Result<(), CallbackError> {
+        let output = serde_json::to_string(output).map_err(CallbackError::Serialize)?;
+        self.send(token, Callback::Success { output }).await
+    }
+
+    /// Fail the task with `error`.
+    pub async fn fail(&self, token: &TaskToken, error: StatesError) -> Result<(), CallbackError> {
+        self.send(token, Callback::Failure(error)).await
+    }
+
+    /// Run `task`, sending a heartbeat every `interval` while it runs, and
+    /// complete the task with its result.
+    ///
+    /// The task must finish before the invocation's deadline, minus the
+    /// [reserve](Self::reserve). Otherwise, it's cancelled and fails with a
+    /// [`TIMEOUT_ERROR`], so the state machine doesn't wait for the task's own
+    /// timeout.
+    pub async fn complete<F, O>(
+        &self,
+        ctx: &Context,
+        token: &TaskToken,
+        interval: Duration,
+        task: F,
+    ) -> Result<(), CallbackError>
+    where
+        F: Future<Output = Result<O, StatesError>>,
+        O: Serialize,
+    {
+        let budget = ctx.time_budget().reserve(self.reserve);
+        let heartbeats = async {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(err) = self.heartbeat(token).await {
+                    warn!(error = %err, "failed to send the task heartbeat");
+                }
+            }
+        };
+
+        let task = budget.timeout(task);
+        futures::pin_mut!(task, heartbeats);
+        let result = match future::select(task, heartbeats).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => unreachable!("heartbeats never end"),
+        };
+        match result {
+            Ok(Ok(output)) => self.succeed(token, &output).await,
+            Ok(Err(err)) => self.fail(token, err).await,
+            Err(exceeded) => {
+                let cause = format!("the function ran out of time: {exceeded}");
+                self.fail(token, StatesError::new(TIMEOUT_ERROR, cause)).await
+            }
+        }
+    }
+
+    async fn send(&self, token: &TaskToken, callback: Callback) -> Result<(), CallbackError> {
+        let req = CallbackRequest {
+            task_token: token.clone(),
+            callback,
+        };
+        self.transport.send(req).await.map_err(CallbackError::Transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::{
+        sync::{Arc, Mutex},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    #[derive(Default, Clone)]
+    struct MockTransport {
+        requests: Arc<Mutex<Vec<CallbackRequest>>>,
+    }
+
+    impl CallbackTransport for MockTransport {
+        fn send(&self, req: CallbackRequest) -> BoxFuture<'_, Result<(), Error>> {
+            self.requests.lock().unwrap().push(req);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn context(timeout: Duration) -> Context {
+        let deadline = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + timeout;
+        Context {
+            deadline: deadline.as_millis() as u64,
+            ..Default::default()
+        }
+    }
+
+    fn callbacks(transport: &MockTransport) -> Vec<Callback> {
+        let requests = transport.requests.lock().unwrap();
+        requests.iter().map(|req| req.callback.clone()).collect()
+    }
+
+    #[test]
+    fn states_errors_are_truncated() {
+        let err = StatesError::new("e".repeat(300), "c".repeat(40000));
+        assert_eq!(MAX_ERROR_LENGTH, err.error().len());
+        assert_eq!(MAX_CAUSE_LENGTH, err.cause().len());
+    }
+
+    #[test]
+    fn states_errors_include_the_sources() {
+        let source = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "connection reset");
+        let err = crate::invoke::InvokeError::Transport(Box::new(source));
+        let err = StatesError::from_error("Downstream.Unavailable", &err);
+        assert_eq!(
+            "failed to invoke the function: connection reset: connection reset",
+            err.cause()
+        );
+    }
+
+    #[test]
+    fn tokens_are_not_logged() {
+        let token = TaskToken::new("secret");
+        assert_eq!("TaskToken(<6 bytes>)", format!("{token:?}"));
+        assert_eq!(token, serde_json::from_value(json!("secret")).unwrap());
+    }
+
+    #[tokio::test]
+    async fn complete_sends_the_output() {
+        let transport = MockTransport::default();
+        let client = TaskCallbacks::new(transport.clone());
+        let token = TaskToken::new("token");
+
+        client
+            .complete(
+                &context(Duration::from_secs(5)),
+                &token,
+                Duration::from_secs(1),
+                async { Ok(json!({"ok": true})) },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![Callback::Success {
+                output: r#"{"ok":true}"#.into()
+            }],
+            callbacks(&transport)
+        );
+    }
+
+    #[tokio::test]
+    async fn complete_sends_heartbeats_and_fails_before_the_deadline() {
+        let transport = MockTransport::default();
+        let client = TaskCallbacks::new(transport.clone()).reserve(Duration::from_millis(100));
+        let token = TaskToken::new("token");
+
+        let task = async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        };
+        client
+            .complete(
+                &context(Duration::from_millis(400)),
+                &token,
+                Duration::from_millis(50),
+                task,
+            )
+            .await
+            .unwrap();
+
+        let callbacks = callbacks(&transport);
+        assert!(callbacks.len() > 2, "{callbacks:?}");
+        assert_eq!(Callback::Heartbeat, callbacks[0]);
+        match callbacks.last().unwrap() {
+            Callback::Failure(err) => assert_eq!(TIMEOUT_ERROR, err.error()),
+            other => panic!("unexpected callback {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn task_errors_fail_the_task() {
+        let transport = MockTransport::default();
+        let client = TaskCallbacks::new(transport.clone());
+        let token = TaskToken::new("token");
+
+        client
+            .complete::<_, ()>(
+                &context(Duration::from_secs(5)),
+                &token,
+                Duration::from_secs(1),
+                async { Err(StatesError::new("Payment.Declined", "card expired")) },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![Callback::Failure(StatesError::new("Payment.Declined", "card expired"))],
+            callbacks(&transport)
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Buffered writer for the bodies of streaming responses.
+//!
+//! A [`StreamWriter`] feeds the [`StreamBody`] of a response returned to
+//! [`run_with_streaming_response`](crate::run_with_streaming_response). Writes
+//! are buffered into chunks of [`StreamConfig::buffer_size`] bytes, and
+//! [`StreamWriter::flush`] sends the buffered bytes right away, so handlers
+//! choose the chunk boundaries, like one chunk per server-sent event.
+//!
+//! The writer applies backpressure: when [`StreamConfig::max_pending_chunks`]
+//! chunks are waiting to be sent, writes wait until the runtime catches up
+//! instead of buffering the whole response in memory. Lambda sends the first
+//! [`STREAMING_BURST`] bytes of a response uncapped, and the rest at about
+//! [`STREAMING_BANDWIDTH`] bytes per second. [`StreamConfig::pace`] slows the
+//! writer down to that rate, so producers that can't block for long, like a
+//! database cursor, don't time out waiting on a full channel.
+//!
+//! # Example
+//! ```no_run
+//! use hyper::Response;
+//! use lambda_runtime::{
+//!     service_fn,
+//!     stream_writer::{StreamBody, StreamWriter},
+//!     Error, LambdaEvent,
+//! };
+//! use serde_json::Value;
+//!
+//! async fn handler(_event: LambdaEvent<Value>) -> Result<Response<StreamBody>, Error> {
+//!     let (mut writer, body) = StreamWriter::new();
+//!     tokio::spawn(async move {
+//!         for i in 0..10 {
+//!             writer.write(format!("data: {i}\n\n")).await?;
+//!             // Send every event as soon as it's written.
+//!             writer.flush().await?;
+//!         }
+//!         writer.finish().await
+//!     });
+//!     Ok(Response::builder().header("content-type", "text/event-stream").body(body)?)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     lambda_runtime::run_with_streaming_response(service_fn(handler)).await
+//! }
+//! ```
+use crate::Error;
+use bytes::{Bytes, BytesMut};
+use hyper::body::HttpBody;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::warn;
+
+/// Bytes of a streaming response that Lambda sends without bandwidth cap.
+pub const STREAMING_BURST: usize = 6 * 1024 * 1024;
+
+/// Bandwidth of a streaming response past [`STREAMING_BURST`], in bytes per second.
+pub const STREAMING_BANDWIDTH: usize = 2 * 1024 * 1024;
+
+/// Buffering of a [`StreamWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamConfig {
+    buffer_size: usize,
+    max_pending_chunks: usize,
+    pace: Option<(usize, usize)>,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        StreamConfig {
+            buffer_size: 16 * 1024,
+            max_pending_chunks: 4,
+            pace: None,
+        }
+    }
+}
+
+impl StreamConfig {
+    /// Send the buffered bytes once there are `buffer_size` of them, 16KB by default.
+    pub fn buffer_size(self, buffer_size: usize) -> Self {
+        StreamConfig {
+            buffer_size: buffer_size.max(1),
+            ..self
+        }
+    }
+
+    /// Make writes wait when `max_pending_chunks` chunks are waiting to be sent, 4 by default.
+    pub fn max_pending_chunks(self, max_pending_chunks: usize) -> Self {
+        StreamConfig {
+            max_pending_chunks: max_pending_chunks.max(1),
+            ..self
+        }
+    }
+
+    /// Send at most `bytes_per_second` once `burst` bytes have been sent.
+    ///
+    /// Use [`STREAMING_BANDWIDTH`] and [`STREAMING_BURST`] to follow the bandwidth of Lambda.
+    pub fn pace(self, bytes_per_second: usize, burst: usize) -> Self {
+        StreamConfig {
+            pace: Some((bytes_per_second.max(1), burst)),
+            ..self
+        }
+    }
+
+    /// Create a writer with this configuration, and the body that it feeds.
+    pub fn writer(self) -> (StreamWriter, StreamBody) {
+        let (tx, rx) = mpsc::channel(self.max_pending_chunks);
+        let writer = StreamWriter {
+            tx,
+            buffer: BytesMut::with_capacity(self.buffer_size),
+            config: self,
+            sent: 0,
+            started: Instant::now(),
+        };
+        (writer, StreamBody { rx })
+    }
+}
+
+/// Error returned when the body of the response was dropped, usually because the client went away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamClosed;
+
+impl fmt::Display for StreamClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the response stream was closed")
+    }
+}
+
+impl std::error::Error for StreamClosed {}
+
+/// Handle that writes the body of a streaming response.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug)]
+pub struct StreamWriter {
+    tx: mpsc::Sender<Result<Bytes, Error>>,
+    buffer: BytesMut,
+    config: StreamConfig,
+    sent: usize,
+    started: Instant,
+}
+
+impl StreamWriter {
+    /// Create a writer with the default configuration, and the body that it feeds.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> (StreamWriter, StreamBody) {
+        StreamConfig::default().writer()
+    }
+
+    /// Buffer `data`, and send the full chunks of the buffer.
+    ///
+    /// Waits when too many chunks are waiting to be sent.
+    pub async fn write(&mut self, data: impl AsRef<[u8]>) -> Result<(), StreamClosed> {
+        self.buffer.extend_from_slice(data.as_ref());
+        while self.buffer.len() >= self.config.buffer_size {
+            let chunk = self.buffer.split_to(self.config.buffer_size).freeze();
+            self.send(chunk).await?;
+        }
+        Ok(())
+    }
+
+    /// Send the buffered bytes as a single chunk, even if the buffer isn't full.
+    pub async fn flush(&mut self) -> Result<(), StreamClosed> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = self.buffer.split().freeze();
+        self.send(chunk).await
+    }
+
+    /// Number of bytes that can be written before the buffer is sent.
+    pub fn remaining_capacity(&self) -> usize {
+        self.config.buffer_size - self.buffer.len()
+    }
+
+    /// Number of chunks waiting to be sent.
+    pub fn pending_chunks(&self) -> usize {
+        self.config.max_pending_chunks - self.tx.capacity()
+    }
+
+    /// Number of bytes sent so far, without the buffered ones.
+    pub fn bytes_sent(&self) -> usize {
+        self.sent
+    }
+
+    /// Send the buffered bytes and end the response.
+    pub async fn finish(mut self) -> Result<(), StreamClosed> {
+        self.flush().await
+    }
+
+    /// Send the buffered bytes, and end the response with `err`.
+    ///
+    /// The runtime reports the error to the client in the trailers of the response.
+    pub async fn fail(mut self, err: impl Into<Error>) -> Result<(), StreamClosed> {
+        self.flush().await?;
+        self.tx.send(Err(err.into())).await.map_err(|_| StreamClosed)
+    }
+
+    async fn send(&mut self, chunk: Bytes) -> Result<(), StreamClosed> {
+        if let Some((bytes_per_second, burst)) = self.config.pace {
+            let paced = (self.sent + chunk.len()).saturating_sub(burst);
+            let at = self.started + Duration::from_secs_f64(paced as f64 / bytes_per_second as f64);
+            tokio::time::sleep_until(at).await;
+        }
+        self.sent += chunk.len();
+        self.tx.send(Ok(chunk)).await.map_err(|_| StreamClosed)
+    }
+}
+
+impl Drop for StreamWriter {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            let chunk = self.buffer.split().freeze();
+            if self.tx.try_send(Ok(chunk)).is_err() {
+                warn!("a stream writer was dropped with unsent data, call `finish` to send it");
+            }
+        }
+    }
+}
+
+/// Body of a streaming response, fed by a [`StreamWriter`].
+#[derive(Debug)]
+pub struct StreamBody {
+    rx: mpsc::Receiver<Result<Bytes, Error>>,
+}
+
+impl HttpBody for StreamBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_data(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        self.rx.poll_recv(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn chunks(mut body: StreamBody) -> Vec<Result<Bytes, String>> {
+        let mut chunks = Vec::new();
+        while let Some(chunk) = body.data().await {
+            chunks.push(chunk.map_err(|err| err.to_string()));
+        }
+        chunks
+    }
+
+    #[tokio::test]
+    async fn writes_are_buffered_until_full_or_flushed() {
+        let (mut writer, body) = StreamConfig::default().buffer_size(4).writer();
+        writer.write("abcdef").await.unwrap();
+        assert_eq!(2, writer.remaining_capacity());
+        writer.write("g").await.unwrap();
+        writer.flush().await.unwrap();
+        writer.write("h").await.unwrap();
+        writer.finish().await.unwrap();
+
+        assert_eq!(
+            vec![Ok(Bytes::from("abcd")), Ok(Bytes::from("efg")), Ok(Bytes::from("h"))],
+            chunks(body).await
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_wait_for_pending_chunks() {
+        let (mut writer, mut body) = StreamConfig::default().buffer_size(1).max_pending_chunks(2).writer();
+        writer.write("ab").await.unwrap();
+        assert_eq!(2, writer.pending_chunks());
+
+        let write = tokio::time::timeout(Duration::from_millis(10), writer.write("c")).await;
+        assert!(write.is_err(), "the write should wait for the body");
+
+        assert_eq!(Bytes::from("a"), body.data().await.unwrap().unwrap());
+        writer.write("c").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failures_end_the_body_with_the_error() {
+        let (mut writer, body) = StreamWriter::new();
+        writer.write("partial").await.unwrap();
+        writer.fail("boom").await.unwrap();
+        assert_eq!(
+            vec![Ok(Bytes::from("partial")), Err("boom".to_string())],
+            chunks(body).await
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_fail_once_the_body_is_dropped() {
+        let (mut writer, body) = StreamConfig::default().buffer_size(1).writer();
+        drop(body);
+        assert_eq!(Err(StreamClosed), writer.write("a").await);
+    }
+
+    #[tokio::test]
+    async fn pacing_starts_after_the_burst() {
+        let (mut writer, body) = StreamConfig::default().buffer_size(10).pace(100, 20).writer();
+        let reader = tokio::spawn(chunks(body));
+        let started = Instant::now();
+        writer.write([0; 20]).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(200));
+        writer.write([0; 20]).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        writer.finish().await.unwrap();
+        assert_eq!(4, reader.await.unwrap().len());
+    }
+}
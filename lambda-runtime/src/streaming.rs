@@ -1,14 +1,23 @@
 use crate::{
-    build_event_error_request, deserializer, incoming, type_name_of_val, Config, Context, Error, EventErrorRequest,
-    IntoRequest, LambdaEvent, Runtime,
+    alarms::Alarms,
+    bench, build_codec_error_request, build_event_error_request,
+    codec::{Codec, JsonCodec},
+    diagnostic_for,
+    executor::{Executor, TokioExecutor},
+    incoming, init,
+    limits::InvokeMode,
+    runtime_client, type_name_of_val,
+    types::DEFAULT_FUNCTION_ERROR_TYPE,
+    Config, Context, Diagnostic, Error, EventErrorRequest, IntoRequest, LambdaEvent, Runtime, TaskSet,
 };
+use base64::Engine;
 use bytes::Bytes;
 use futures::FutureExt;
-use http::header::{CONTENT_TYPE, SET_COOKIE};
-use http::{Method, Request, Response, Uri};
+use http::header::{CONTENT_TYPE, SET_COOKIE, TRAILER};
+use http::{HeaderMap, HeaderValue, Method, Request, Response, Uri};
 use hyper::body::HttpBody;
-use hyper::{client::connect::Connection, Body};
-use lambda_runtime_api_client::{build_request, Client};
+use hyper::Body;
+use lambda_runtime_api_client::{build_request, Transport};
 use serde::Deserialize;
 use serde_json::json;
 use std::collections::HashMap;
@@ -18,11 +27,12 @@ use std::{
     fmt::{self, Debug, Display},
     future::Future,
     panic,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
-use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_stream::{Stream, StreamExt};
 use tower::{Service, ServiceExt};
-use tracing::{error, trace, Instrument};
+use tracing::{error, trace, warn, Instrument};
 
 /// Starts the Lambda Rust runtime and stream response back [Configure Lambda
 /// Streaming Response](https://docs.aws.amazon.com/lambda/latest/dg/configuration-response-streaming.html).
@@ -63,7 +73,7 @@ pub async fn run_with_streaming_response<A, B, F>(handler: F) -> Result<(), Erro
 where
     F: Service<LambdaEvent<A>>,
     F::Future: Future<Output = Result<http::Response<B>, F::Error>>,
-    F::Error: Debug + Display,
+    F::Error: Debug + Display + 'static,
     A: for<'de> Deserialize<'de>,
     B: HttpBody + Unpin + Send + 'static,
     B::Data: Into<Bytes> + Send,
@@ -71,22 +81,23 @@ where
 {
     trace!("Loading config from env");
     let config = Config::from_env()?;
-    let client = Client::builder().build().expect("Unable to create a runtime client");
-    let runtime = Runtime { client, config };
+    let client = runtime_client().expect("Unable to create a runtime client");
+    let runtime = Runtime {
+        client,
+        config,
+        executor: Arc::new(TokioExecutor),
+        codec: JsonCodec::new(),
+        recorder: None,
+        alarms: Alarms::default(),
+    };
 
     let client = &runtime.client;
     let incoming = incoming(client);
     runtime.run_with_streaming_response(incoming, handler).await
 }
 
-impl<C> Runtime<C>
-where
-    C: Service<http::Uri> + Clone + Send + Sync + Unpin + 'static,
-    C::Future: Unpin + Send,
-    C::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-    C::Response: AsyncRead + AsyncWrite + Connection + Unpin + Send + 'static,
-{
-    async fn run_with_streaming_response<F, A, B>(
+impl<T: Transport, C: Codec> Runtime<T, C> {
+    pub(crate) async fn run_with_streaming_response<F, A, B>(
         &self,
         incoming: impl Stream<Item = Result<Response<Body>, Error>> + Send,
         mut handler: F,
@@ -94,16 +105,21 @@ where
     where
         F: Service<LambdaEvent<A>>,
         F::Future: Future<Output = Result<Response<B>, F::Error>>,
-        F::Error: fmt::Debug + fmt::Display,
+        F::Error: fmt::Debug + fmt::Display + 'static,
         A: for<'de> Deserialize<'de>,
         B: HttpBody + Unpin + Send + 'static,
         B::Data: Into<Bytes> + Send,
         B::Error: Into<Error> + Send + Debug,
     {
         let client = &self.client;
+        let mut first_invocation = true;
+        // The init phase ends when the runtime asks for the first event.
+        let init_duration = init::process_age();
+        let mut waiting = Instant::now();
         tokio::pin!(incoming);
         while let Some(next_event_response) = incoming.next().await {
             trace!("New event arrived (run loop)");
+            let next_event = waiting.elapsed();
             let event = next_event_response?;
             let (parts, body) = event.into_parts();
 
@@ -115,18 +131,29 @@ where
                 continue;
             }
 
-            let ctx: Context = Context::try_from(parts.headers)?;
+            let cold_start = std::mem::replace(&mut first_invocation, false);
+            let mut ctx: Context = Context::try_from(parts.headers)?;
+            ctx.cold_start = cold_start;
+            ctx.init_duration = init_duration.filter(|_| cold_start);
             let ctx: Context = ctx.with_config(&self.config);
             let request_id = &ctx.request_id.clone();
+            let init_duration = ctx.init_duration;
+            let deadline = ctx.deadline();
+            let available = deadline.duration_since(SystemTime::now()).unwrap_or_default();
 
             let request_span = match &ctx.xray_trace_id {
                 Some(trace_id) => {
                     env::set_var("_X_AMZN_TRACE_ID", trace_id);
-                    tracing::info_span!("Lambda runtime invoke", requestId = request_id, xrayTraceId = trace_id)
+                    tracing::info_span!(
+                        "Lambda runtime invoke",
+                        requestId = request_id,
+                        xrayTraceId = trace_id,
+                        coldStart = cold_start
+                    )
                 }
                 None => {
                     env::remove_var("_X_AMZN_TRACE_ID");
-                    tracing::info_span!("Lambda runtime invoke", requestId = request_id)
+                    tracing::info_span!("Lambda runtime invoke", requestId = request_id, coldStart = cold_start)
                 }
             };
 
@@ -134,6 +161,7 @@ where
             async {
                 let body = hyper::body::to_bytes(body).await?;
                 trace!("incoming request payload - {}", std::str::from_utf8(&body)?);
+                self.alarms.check_request(request_id, body.len());
 
                 #[cfg(debug_assertions)]
                 if parts.status.is_server_error() {
@@ -141,23 +169,30 @@ where
                     return Err(parts.status.to_string().into());
                 }
 
-                let lambda_event = match deserializer::deserialize(&body, ctx) {
-                    Ok(lambda_event) => lambda_event,
+                let decoding = Instant::now();
+                let lambda_event = match self.codec.decode(&body) {
+                    Ok(payload) => LambdaEvent::new(payload, ctx),
                     Err(err) => {
-                        let req = build_event_error_request(request_id, err)?;
+                        let req = build_codec_error_request(request_id, err)?;
                         client.call(req).await.expect("Unable to send response to Runtime APIs");
                         return Ok(());
                     }
                 };
 
+                let decode = decoding.elapsed();
+
+                let tasks = TaskSet::new(self.executor.clone());
+                let started = Instant::now();
                 let req = match handler.ready().await {
                     Ok(handler) => {
                         // Catches panics outside of a `Future`
-                        let task = panic::catch_unwind(panic::AssertUnwindSafe(|| handler.call(lambda_event)));
+                        let task = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                            tasks.sync_scope(|| handler.call(lambda_event))
+                        }));
 
                         let task = match task {
                             // Catches panics inside of the `Future`
-                            Ok(task) => panic::AssertUnwindSafe(task).catch_unwind().await,
+                            Ok(task) => panic::AssertUnwindSafe(tasks.scope(task)).catch_unwind().await,
                             Err(err) => Err(err),
                         };
 
@@ -168,6 +203,9 @@ where
                                     EventCompletionStreamingRequest {
                                         request_id,
                                         body: response,
+                                        tasks: tasks.clone(),
+                                        deadline,
+                                        alarms: self.alarms,
                                     }
                                     .into_req()
                                 }
@@ -188,19 +226,117 @@ where
                     Err(err) => build_event_error_request(request_id, err),
                 }?;
 
+                let handler_time = started.elapsed();
+                self.alarms.check_duration(request_id, handler_time, available);
+
+                let responding = Instant::now();
                 client.call(req).await.expect("Unable to send response to Runtime APIs");
+                if let Some(recorder) = &self.recorder {
+                    recorder.record(&bench::InvocationTimings {
+                        request_id: request_id.clone(),
+                        cold_start,
+                        next_event,
+                        decode,
+                        handler: handler_time,
+                        encode: Duration::ZERO,
+                        respond: responding.elapsed(),
+                        init_duration,
+                    });
+                }
+                // Background tasks can feed the response stream,
+                // so they are awaited once the stream is complete.
+                // Deferred tasks have already been started if the
+                // response prelude was sent.
+                tasks.start_deferred();
+                tasks.finish(deadline).await;
                 Ok::<(), Error>(())
             }
             .instrument(request_span)
             .await?;
+            waiting = Instant::now();
         }
         Ok(())
     }
 }
 
+/// Format of a streamed response, set in the extensions of the handler's response.
+///
+/// # Example
+/// ```
+/// use hyper::{Body, Response};
+/// use lambda_runtime::StreamingFormat;
+///
+/// // Stream a CSV file to an `InvokeWithResponseStream` caller, without the HTTP metadata.
+/// let response = Response::builder()
+///     .header("content-type", "text/csv")
+///     .extension(StreamingFormat::Raw)
+///     .body(Body::from("id,name\n"))
+///     .unwrap();
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StreamingFormat {
+    /// The status code, headers and cookies of the response are sent before the body,
+    /// for function URLs and API Gateway. This is the default.
+    #[default]
+    HttpIntegration,
+    /// Only the body is sent, with the `Content-Type` of the response,
+    /// for callers of `InvokeWithResponseStream`.
+    Raw,
+}
+
+/// A streamed response that failed after its status and headers were sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamFailure {
+    /// Number of bytes of the body sent before the failure.
+    pub bytes_sent: usize,
+    /// Error reported to the client in the trailers of the response.
+    pub diagnostic: Diagnostic,
+}
+
+/// Callback told about streamed responses that fail partway, set in the extensions of the handler's response.
+///
+/// The runtime ends these responses with the `Lambda-Runtime-Function-Error-Type`
+/// and `Lambda-Runtime-Function-Error-Body` trailers, so clients can tell them
+/// apart from complete responses. The hook lets the function record what the
+/// client received, for example to resume the download later.
+///
+/// # Example
+/// ```
+/// use hyper::{Body, Response};
+/// use lambda_runtime::StreamErrorHook;
+///
+/// let response = Response::builder()
+///     .extension(StreamErrorHook::new(|failure| {
+///         tracing::warn!(bytes_sent = failure.bytes_sent, "export interrupted");
+///     }))
+///     .body(Body::empty())
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct StreamErrorHook(Arc<dyn Fn(&StreamFailure) + Send + Sync>);
+
+impl StreamErrorHook {
+    /// Create a hook that calls `hook` with the failures of the stream.
+    pub fn new(hook: impl Fn(&StreamFailure) + Send + Sync + 'static) -> Self {
+        StreamErrorHook(Arc::new(hook))
+    }
+}
+
+impl Debug for StreamErrorHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("StreamErrorHook").finish()
+    }
+}
+
+const HTTP_INTEGRATION_CONTENT_TYPE: &str = "application/vnd.awslambda.http-integration-response";
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
 pub(crate) struct EventCompletionStreamingRequest<'a, B> {
     pub(crate) request_id: &'a str,
     pub(crate) body: Response<B>,
+    pub(crate) tasks: TaskSet,
+    pub(crate) deadline: SystemTime,
+    pub(crate) alarms: Alarms,
 }
 
 impl<'a, B> IntoRequest for EventCompletionStreamingRequest<'a, B>
@@ -218,53 +354,255 @@ where
         let mut builder = build_request().method(Method::POST).uri(uri);
         let headers = builder.headers_mut().unwrap();
 
+        let format = parts.extensions.get::<StreamingFormat>().copied().unwrap_or_default();
         headers.insert("Transfer-Encoding", "chunked".parse()?);
         headers.insert("Lambda-Runtime-Function-Response-Mode", "streaming".parse()?);
+        let content_type = match format {
+            StreamingFormat::HttpIntegration => HeaderValue::from_static(HTTP_INTEGRATION_CONTENT_TYPE),
+            StreamingFormat::Raw => parts
+                .headers
+                .get(CONTENT_TYPE)
+                .cloned()
+                .unwrap_or_else(|| HeaderValue::from_static(DEFAULT_CONTENT_TYPE)),
+        };
+        headers.insert(CONTENT_TYPE, content_type);
         headers.insert(
-            "Content-Type",
-            "application/vnd.awslambda.http-integration-response".parse()?,
+            TRAILER,
+            HeaderValue::from_static("Lambda-Runtime-Function-Error-Type, Lambda-Runtime-Function-Error-Body"),
         );
+        let hook = parts.extensions.get::<StreamErrorHook>().cloned();
 
         let (mut tx, rx) = Body::channel();
+        let tasks = self.tasks;
+        let deadline = self.deadline;
+        let alarms = self.alarms;
+        let request_id = self.request_id.to_string();
 
-        tokio::spawn(async move {
-            let mut header_map = parts.headers;
-            // default Content-Type
-            header_map
-                .entry(CONTENT_TYPE)
-                .or_insert("application/octet-stream".parse().unwrap());
-
-            let cookies = header_map.get_all(SET_COOKIE);
-            let cookies = cookies
-                .iter()
-                .map(|c| String::from_utf8_lossy(c.as_bytes()).to_string())
-                .collect::<Vec<String>>();
-
-            let headers = header_map
-                .iter()
-                .filter(|(k, _)| *k != SET_COOKIE)
-                .map(|(k, v)| (k.as_str(), String::from_utf8_lossy(v.as_bytes()).to_string()))
-                .collect::<HashMap<&str, String>>();
-
-            let metadata_prelude = json!({
-                "statusCode": parts.status.as_u16(),
-                "headers": headers,
-                "cookies": cookies,
-            })
-            .to_string();
-
-            trace!("metadata_prelude: {}", metadata_prelude);
+        let executor = tasks.executor().clone();
+        executor.spawn(Box::pin(async move {
+            if format == StreamingFormat::HttpIntegration {
+                send_prelude(&mut tx, parts).await;
+            }
+            tasks.start_deferred();
 
-            tx.send_data(metadata_prelude.into()).await.unwrap();
-            tx.send_data("\u{0}".repeat(8).into()).await.unwrap();
+            let mut sent = 0;
+            let failure = loop {
+                let chunk: Bytes = match body.data().await {
+                    Some(Ok(chunk)) => chunk.into(),
+                    Some(Err(err)) => {
+                        let err: Error = err.into();
+                        error!("{err:?}, the response stream failed after {sent} bytes");
+                        break Some(diagnostic_for(&err));
+                    }
+                    None => break None,
+                };
+                // The Runtime API truncates the stream past the limit,
+                // stop sending data and report the measured size instead.
+                if let Err(err) = InvokeMode::Streaming.check(sent + chunk.len()) {
+                    error!("{err}, the response stream has been truncated");
+                    break Some(diagnostic_for(&err));
+                }
+                let len = chunk.len();
+                if tx.send_data(chunk).await.is_err() {
+                    // Dropping the body lets the function know that nobody is listening anymore
+                    warn!("the response stream was closed before the response body was complete");
+                    break None;
+                }
+                sent += len;
+            };
+            alarms.check_response(&request_id, sent);
 
-            while let Some(chunk) = body.data().await {
-                let chunk = chunk.unwrap();
-                tx.send_data(chunk.into()).await.unwrap();
+            // Tell the client that the response is incomplete, instead of ending it like a successful one.
+            if let Some(diagnostic) = failure {
+                let failure = StreamFailure {
+                    bytes_sent: sent,
+                    diagnostic,
+                };
+                if let Some(hook) = hook {
+                    (hook.0)(&failure);
+                }
+                if tx.send_trailers(error_trailers(&failure.diagnostic)).await.is_err() {
+                    warn!("the response stream was closed before the error trailers were sent");
+                }
             }
-        });
+
+            // Keep the response stream open until the background work is done
+            tasks.finish(deadline).await;
+        }));
 
         let req = builder.body(rx)?;
         Ok(req)
     }
 }
+
+async fn send_prelude(tx: &mut hyper::body::Sender, parts: http::response::Parts) {
+    let mut header_map = parts.headers;
+    // default Content-Type
+    header_map
+        .entry(CONTENT_TYPE)
+        .or_insert(HeaderValue::from_static(DEFAULT_CONTENT_TYPE));
+
+    let cookies = header_map.get_all(SET_COOKIE);
+    let cookies = cookies
+        .iter()
+        .map(|c| String::from_utf8_lossy(c.as_bytes()).to_string())
+        .collect::<Vec<String>>();
+
+    let headers = header_map
+        .iter()
+        .filter(|(k, _)| *k != SET_COOKIE)
+        .map(|(k, v)| (k.as_str(), String::from_utf8_lossy(v.as_bytes()).to_string()))
+        .collect::<HashMap<&str, String>>();
+
+    let metadata_prelude = json!({
+        "statusCode": parts.status.as_u16(),
+        "headers": headers,
+        "cookies": cookies,
+    })
+    .to_string();
+
+    trace!("metadata_prelude: {}", metadata_prelude);
+
+    tx.send_data(metadata_prelude.into()).await.unwrap();
+    tx.send_data("\u{0}".repeat(8).into()).await.unwrap();
+}
+
+fn error_trailers(diagnostic: &Diagnostic) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    let error_type = HeaderValue::from_str(diagnostic.function_error_type())
+        .unwrap_or_else(|_| HeaderValue::from_static(DEFAULT_FUNCTION_ERROR_TYPE));
+    trailers.insert("Lambda-Runtime-Function-Error-Type", error_type);
+    let body = serde_json::to_vec(diagnostic).expect("diagnostics are always serializable");
+    let body = base64::engine::general_purpose::STANDARD.encode(body);
+    trailers.insert(
+        "Lambda-Runtime-Function-Error-Body",
+        HeaderValue::from_str(&body).expect("base64 is a valid header value"),
+    );
+    trailers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::requests::{IntoResponse, NextEventResponse};
+    use std::{sync::Mutex, time::Duration};
+
+    // Answers the runtime's requests in memory, with the same event every time.
+    struct InMemoryTransport;
+
+    impl Transport for InMemoryTransport {
+        fn call(&self, req: Request<Body>) -> lambda_runtime_api_client::TransportFuture<'_> {
+            Box::pin(async move {
+                if req.uri().path() == "/2018-06-01/runtime/invocation/next" {
+                    return NextEventResponse {
+                        request_id: "8476a536-e9f4-11e8-9739-2dfe598c3fcd",
+                        deadline: 1_542_409_706_888,
+                        arn: "arn:aws:lambda:us-east-2:123456789012:function:custom-runtime",
+                        trace_id: "Root=1-5bef4de7-ad49b0e87f6ef6c87fc2e700;Parent=9a9197af755a6419",
+                        body: serde_json::to_vec(&json!({"message": "hello"}))?,
+                    }
+                    .into_rsp();
+                }
+                hyper::body::to_bytes(req.into_body()).await?;
+                Ok(Response::builder().status(202).body(Body::empty())?)
+            })
+        }
+    }
+
+    fn runtime() -> Runtime<InMemoryTransport> {
+        Runtime {
+            client: InMemoryTransport,
+            config: Config::default(),
+            executor: Arc::new(TokioExecutor),
+            codec: JsonCodec::new(),
+            recorder: None,
+            alarms: Alarms::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn streaming_reports_the_cold_start() -> Result<(), Error> {
+        let contexts = Arc::new(Mutex::new(Vec::new()));
+        let seen = contexts.clone();
+        let runtime = runtime();
+        let incoming = incoming(&runtime.client).take(2);
+        let f = crate::service_fn(move |event: LambdaEvent<serde_json::Value>| {
+            seen.lock().unwrap().push(event.context);
+            async move { Ok::<_, Error>(Response::new(Body::from("hello"))) }
+        });
+        runtime.run_with_streaming_response(incoming, f).await?;
+
+        let contexts = contexts.lock().unwrap();
+        assert_eq!(2, contexts.len());
+        assert!(contexts[0].cold_start);
+        assert!(contexts[0].init_duration.is_some());
+        assert!(!contexts[1].cold_start);
+        assert_eq!(None, contexts[1].init_duration);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streaming_records_invocation_timings() -> Result<(), Error> {
+        let timings = Arc::new(Mutex::new(Vec::new()));
+        let recorded = timings.clone();
+        let runtime = Runtime {
+            recorder: Some(Arc::new(move |t: &bench::InvocationTimings| {
+                recorded.lock().unwrap().push(t.clone())
+            })),
+            ..runtime()
+        };
+        let incoming = incoming(&runtime.client).take(2);
+        let f = crate::service_fn(|_: LambdaEvent<serde_json::Value>| async move {
+            Ok::<_, Error>(Response::new(Body::from("hello")))
+        });
+        runtime.run_with_streaming_response(incoming, f).await?;
+
+        let timings = timings.lock().unwrap();
+        assert_eq!(2, timings.len());
+        assert_eq!("8476a536-e9f4-11e8-9739-2dfe598c3fcd", timings[0].request_id);
+        assert!(timings[0].cold_start);
+        assert!(timings[0].init_duration.is_some());
+        assert!(!timings[1].cold_start);
+        assert_eq!(Duration::ZERO, timings[0].encode);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failures_midstream_end_with_error_trailers() {
+        let chunks: Vec<Result<&str, Error>> = vec![Ok("abc"), Err("boom".into())];
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let f = failures.clone();
+        let response = Response::builder()
+            .extension(StreamingFormat::Raw)
+            .extension(StreamErrorHook::new(move |failure| {
+                f.lock().unwrap().push(failure.clone())
+            }))
+            .body(Body::wrap_stream(futures::stream::iter(chunks)))
+            .unwrap();
+        let req = EventCompletionStreamingRequest {
+            request_id: "id",
+            body: response,
+            tasks: TaskSet::new(Arc::new(TokioExecutor)),
+            deadline: SystemTime::now() + Duration::from_secs(10),
+            alarms: Alarms::default(),
+        }
+        .into_req()
+        .unwrap();
+        assert_eq!(DEFAULT_CONTENT_TYPE, req.headers()[CONTENT_TYPE]);
+
+        let mut body = req.into_body();
+        assert_eq!(Bytes::from("abc"), body.data().await.unwrap().unwrap());
+        assert!(body.data().await.is_none());
+        let trailers = body.trailers().await.unwrap().unwrap();
+        assert_eq!("unhandled", trailers["Lambda-Runtime-Function-Error-Type"]);
+        let diagnostic = base64::engine::general_purpose::STANDARD
+            .decode(trailers["Lambda-Runtime-Function-Error-Body"].as_bytes())
+            .unwrap();
+        let diagnostic: serde_json::Value = serde_json::from_slice(&diagnostic).unwrap();
+        assert!(diagnostic["errorMessage"].as_str().unwrap().contains("boom"));
+
+        let failures = failures.lock().unwrap();
+        assert_eq!(1, failures.len());
+        assert_eq!(3, failures[0].bytes_sent);
+    }
+}
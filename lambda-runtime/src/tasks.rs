@@ -0,0 +1,405 @@
+use crate::executor::{SharedExecutor, TokioExecutor};
+use futures::future::{self, BoxFuture, Either, FutureExt, RemoteHandle};
+use std::{
+    any::Any,
+    fmt,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tracing::{error, warn, Instrument, Span};
+
+type TaskHandle = RemoteHandle<Result<(), Box<dyn Any + Send>>>;
+
+// Time left before the invocation deadline at which pending
+// background tasks are aborted instead of awaited.
+const ABORT_RESERVE: Duration = Duration::from_millis(100);
+
+tokio::task_local! {
+    static CURRENT_TASKS: TaskSet;
+}
+
+/// A set of background tasks bound to a single invocation.
+///
+/// The runtime creates a new `TaskSet` for every invocation. Tasks spawned on it
+/// run inside the invocation's tracing span, and the runtime waits for all of them
+/// to finish before it reports the invocation as complete. Tasks that are still
+/// running when the invocation deadline is about to expire are aborted, so no
+/// background work is left behind when the execution environment is frozen.
+///
+/// Work can also be deferred until the response has been sent with [`TaskSet::defer`].
+#[derive(Clone)]
+pub struct TaskSet {
+    executor: SharedExecutor,
+    inner: Arc<Mutex<Vec<TaskHandle>>>,
+    deferred: Arc<Mutex<Vec<BoxFuture<'static, ()>>>>,
+}
+
+impl Default for TaskSet {
+    fn default() -> Self {
+        TaskSet::new(Arc::new(TokioExecutor))
+    }
+}
+
+impl TaskSet {
+    /// Create a new set whose tasks are spawned on `executor`.
+    pub(crate) fn new(executor: SharedExecutor) -> Self {
+        TaskSet {
+            executor,
+            inner: Arc::default(),
+            deferred: Arc::default(),
+        }
+    }
+
+    /// Return the executor that runs the tasks in the set.
+    pub(crate) fn executor(&self) -> &SharedExecutor {
+        &self.executor
+    }
+
+    /// Return the task set of the invocation being processed, if any.
+    pub fn current() -> Option<TaskSet> {
+        CURRENT_TASKS.try_with(Clone::clone).ok()
+    }
+
+    /// Spawn a new task bound to this invocation.
+    ///
+    /// The task is instrumented with the caller's current tracing span.
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let span = Span::current();
+        let task = async move {
+            future.await;
+        };
+        self.spawn_boxed(Box::pin(task.instrument(span)));
+    }
+
+    fn spawn_boxed(&self, task: BoxFuture<'static, ()>) {
+        // Tasks spawned by the task are bound to the same invocation
+        let task = CURRENT_TASKS.scope(self.clone(), task);
+        let (task, handle) = AssertUnwindSafe(task).catch_unwind().remote_handle();
+        self.executor.spawn(Box::pin(task));
+        self.inner.lock().expect("task set lock poisoned").push(handle);
+    }
+
+    /// Defer a task until the invocation's response has been sent.
+    ///
+    /// For buffered responses, the task starts after the response is posted to
+    /// the Runtime API. For streaming responses, the task starts after the response
+    /// prelude is sent, and the response stream is kept open until it finishes.
+    /// In both cases, the invocation doesn't complete until the task is done
+    /// or the deadline is about to expire.
+    pub fn defer<F>(&self, future: F)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let span = Span::current();
+        let task = async move {
+            future.await;
+        };
+        self.deferred
+            .lock()
+            .expect("task set lock poisoned")
+            .push(Box::pin(task.instrument(span)));
+    }
+
+    /// Return the number of tasks that haven't been awaited yet.
+    ///
+    /// Deferred tasks are not counted until they have been started.
+    pub fn len(&self) -> usize {
+        self.inner.lock().expect("task set lock poisoned").len()
+    }
+
+    /// Return `true` if there are no tasks left in the set.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Abort all the tasks in the set.
+    pub fn abort_all(&self) {
+        // Dropping the handles stops the tasks the next time they are polled
+        self.inner.lock().expect("task set lock poisoned").clear();
+    }
+
+    /// Wait until all the tasks in the set have finished,
+    /// including tasks spawned while waiting.
+    pub async fn join_all(&self) {
+        loop {
+            let handles = std::mem::take(&mut *self.inner.lock().expect("task set lock poisoned"));
+            if handles.is_empty() {
+                return;
+            }
+            for handle in handles {
+                if let Err(err) = handle.await {
+                    let msg = err
+                        .downcast_ref::<&str>()
+                        .map(|msg| msg.to_string())
+                        .or_else(|| err.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    error!("background task panicked: {msg}");
+                }
+            }
+        }
+    }
+
+    /// Start all the deferred tasks.
+    pub(crate) fn start_deferred(&self) {
+        let deferred = std::mem::take(&mut *self.deferred.lock().expect("task set lock poisoned"));
+        if deferred.is_empty() {
+            return;
+        }
+
+        for task in deferred {
+            self.spawn_boxed(task);
+        }
+    }
+
+    /// Wait for all the tasks in the set to finish, aborting
+    /// them if the invocation deadline is about to expire.
+    pub(crate) async fn finish(&self, deadline: SystemTime) {
+        if self.is_empty() {
+            return;
+        }
+
+        let budget = deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+            .saturating_sub(ABORT_RESERVE);
+
+        let join = Box::pin(self.join_all());
+        let timeout = self.executor.sleep(budget);
+        if let Either::Right(_) = future::select(join, timeout).await {
+            warn!(
+                pending = self.len(),
+                "aborting background tasks, the invocation deadline is about to expire"
+            );
+            self.abort_all();
+            self.join_all().await;
+        }
+    }
+
+    /// Run `f` with this set as the current invocation's task set.
+    pub(crate) fn sync_scope<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        CURRENT_TASKS.sync_scope(self.clone(), f)
+    }
+
+    /// Run `future` with this set as the current invocation's task set.
+    pub(crate) async fn scope<F>(&self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        CURRENT_TASKS.scope(self.clone(), future).await
+    }
+}
+
+impl fmt::Debug for TaskSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let deferred = self.deferred.lock().expect("task set lock poisoned").len();
+        f.debug_struct("TaskSet")
+            .field("len", &self.len())
+            .field("deferred", &deferred)
+            .finish()
+    }
+}
+
+/// Spawn a background task bound to the current invocation.
+///
+/// The task inherits the invocation's tracing span, so its logs are correlated
+/// with the request id, and the runtime waits for it to finish before reporting
+/// the invocation as complete.
+///
+/// When it's called outside of an invocation, the task isn't tracked by the
+/// runtime. It's spawned on the current tokio runtime if there is one. Otherwise,
+/// like with the executors of [`run_with_executor`](crate::run_with_executor), it
+/// runs on a new thread, so it must not depend on the timers or I/O of an async
+/// runtime.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, spawn_traced, Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     let payload = event.payload.clone();
+///     spawn_traced(async move {
+///         tracing::info!("auditing {payload}");
+///     });
+///     Ok(event.payload)
+/// }
+/// ```
+pub fn spawn_traced<F>(future: F)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match TaskSet::current() {
+        Some(tasks) => tasks.spawn(future),
+        None => {
+            warn!("spawn_traced called outside of an invocation, the task won't be awaited by the runtime");
+            spawn_detached(future);
+        }
+    }
+}
+
+/// Defer a task until the response of the current invocation has been sent.
+///
+/// See [`TaskSet::defer`] for details. When it's called outside of an invocation,
+/// the task starts immediately, like with [`spawn_traced`].
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{defer, service_fn, Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     let payload = event.payload.clone();
+///     defer(async move {
+///         // runs after the response has been sent to the caller
+///         tracing::info!("flushing metrics for {payload}");
+///     });
+///     Ok(event.payload)
+/// }
+/// ```
+pub fn defer<F>(future: F)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match TaskSet::current() {
+        Some(tasks) => tasks.defer(future),
+        None => {
+            warn!("defer called outside of an invocation, the task will start immediately");
+            spawn_detached(future);
+        }
+    }
+}
+
+// Spawns a task outside of an invocation, where the executor of the runtime isn't known.
+fn spawn_detached<F>(future: F)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let future = future.instrument(Span::current());
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn(future);
+        }
+        Err(_) => {
+            let spawned = std::thread::Builder::new()
+                .name("lambda-detached-task".to_string())
+                .spawn(move || {
+                    futures::executor::block_on(future);
+                });
+            if let Err(err) = spawned {
+                error!("unable to start a thread for a task spawned outside of an invocation: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn finish_waits_for_spawned_tasks() {
+        let tasks = TaskSet::default();
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let c = counter.clone();
+        tasks
+            .scope(async move {
+                spawn_traced(async move {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    c.fetch_add(1, Ordering::SeqCst);
+                });
+            })
+            .await;
+
+        assert_eq!(1, tasks.len());
+        tasks.finish(SystemTime::now() + Duration::from_secs(5)).await;
+        assert_eq!(1, counter.load(Ordering::SeqCst));
+        assert!(tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn finish_waits_for_tasks_spawned_by_tasks() {
+        let tasks = TaskSet::default();
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let c = counter.clone();
+        tasks.spawn(async move {
+            spawn_traced(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                c.fetch_add(1, Ordering::SeqCst);
+            });
+        });
+
+        tasks.finish(SystemTime::now() + Duration::from_secs(5)).await;
+        assert_eq!(1, counter.load(Ordering::SeqCst));
+        assert!(tasks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn finish_aborts_tasks_past_the_deadline() {
+        let tasks = TaskSet::default();
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let c = counter.clone();
+        tasks.spawn(async move {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+
+        tasks.finish(SystemTime::now() + Duration::from_millis(150)).await;
+        assert_eq!(0, counter.load(Ordering::SeqCst));
+        assert!(tasks.is_empty());
+    }
+
+    #[test]
+    fn current_is_none_outside_of_an_invocation() {
+        assert!(TaskSet::current().is_none());
+        let tasks = TaskSet::default();
+        assert!(tasks.sync_scope(TaskSet::current).is_some());
+    }
+
+    #[test]
+    fn spawns_on_a_thread_without_a_tokio_runtime() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        spawn_traced(async move {
+            tx.send(()).unwrap();
+        });
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn deferred_tasks_start_on_demand() {
+        let tasks = TaskSet::default();
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let c = counter.clone();
+        tasks.sync_scope(|| {
+            defer(async move {
+                c.fetch_add(1, Ordering::SeqCst);
+            })
+        });
+
+        assert!(tasks.is_empty());
+        tasks.finish(SystemTime::now() + Duration::from_secs(5)).await;
+        assert_eq!(0, counter.load(Ordering::SeqCst));
+
+        tasks.start_deferred();
+        assert_eq!(1, tasks.len());
+        tasks.finish(SystemTime::now() + Duration::from_secs(5)).await;
+        assert_eq!(1, counter.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,463 @@
+//! Export of sanitized copies of the invocations, for analytics and regression corpora.
+//!
+//! [`TrafficExportLayer`] records the raw payload of a percentage of the events,
+//! with the response or the error of the handler, in a [`TrafficRecord`]. The
+//! record is sent to a [`TrafficExporter`] after the response has been sent, with
+//! [`defer`](crate::defer), so exporting never delays the callers of the function
+//! and never changes the result of an invocation: exports that fail, or that
+//! don't complete within the [budget](TrafficExportLayer::budget), are logged and
+//! dropped.
+//!
+//! Records are masked with a [`Redactor`] before they're exported. The sensitive
+//! headers of the redactor are masked in the `headers` and `multiValueHeaders`
+//! objects of the payloads, like in the events of API Gateway and Application
+//! Load Balancers, and its sensitive fields anywhere in the payloads.
+//!
+//! Exporters usually put the records on an EventBridge bus, where rules route
+//! them to analytics or to an archive that can be replayed, or on a Kinesis
+//! stream. Records are plain JSON documents, and EventBridge rejects events
+//! larger than 256 KiB, so records larger than [`DEFAULT_MAX_BYTES`] are
+//! dropped by default.
+//!
+//! # Example
+//!
+//! An exporter to EventBridge with the AWS SDK:
+//! ```ignore
+//! use aws_sdk_eventbridge::{types::PutEventsRequestEntry, Client};
+//! use futures::future::BoxFuture;
+//! use lambda_runtime::{
+//!     service_fn,
+//!     tower::Layer,
+//!     traffic::{TrafficExportLayer, TrafficExporter, TrafficRecord},
+//!     Error, LambdaEvent,
+//! };
+//! use serde_json::Value;
+//!
+//! struct EventBridge {
+//!     client: Client,
+//!     bus: String,
+//! }
+//!
+//! impl TrafficExporter for EventBridge {
+//!     fn export(&self, record: TrafficRecord) -> BoxFuture<'_, Result<(), Error>> {
+//!         Box::pin(async move {
+//!             let entry = PutEventsRequestEntry::builder()
+//!                 .event_bus_name(&self.bus)
+//!                 .source("orders.traffic")
+//!                 .detail_type("Invocation")
+//!                 .detail(serde_json::to_string(&record)?)
+//!                 .build();
+//!             self.client.put_events().entries(entry).send().await?;
+//!             Ok(())
+//!         })
+//!     }
+//! }
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let config = aws_config::load_from_env().await;
+//!     let exporter = EventBridge {
+//!         client: Client::new(&config),
+//!         bus: "traffic".to_string(),
+//!     };
+//!     let func = TrafficExportLayer::new(exporter, 10).layer(service_fn(func));
+//!     lambda_runtime::run(func).await
+//! }
+//! ```
+//!
+//! A Kinesis exporter is written the same way, with `put_record`, and the request
+//! id of the record as partition key.
+use crate::{
+    defer, deserializer,
+    redact::{FieldRedactor, Redactor, REDACTED},
+    Error, LambdaEvent,
+};
+use futures::future::{self, BoxFuture, TryFutureExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tower::{Layer, Service};
+use tracing::warn;
+
+/// Time that an export can take by default.
+pub const DEFAULT_BUDGET: Duration = Duration::from_millis(500);
+
+/// Size of the largest record exported by default, the limit of an EventBridge event.
+pub const DEFAULT_MAX_BYTES: usize = 256 * 1024;
+
+/// A sanitized copy of an invocation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficRecord {
+    /// Id of the invocation.
+    pub request_id: String,
+    /// ARN used to invoke the function.
+    pub invoked_function_arn: String,
+    /// Time of the invocation, in milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    /// Time that the handler took, in milliseconds.
+    pub duration_ms: u64,
+    /// Payload of the event.
+    pub request: Value,
+    /// Response of the handler, if it succeeded.
+    pub response: Option<Value>,
+    /// Error returned by the handler, if it failed.
+    pub error: Option<String>,
+}
+
+impl TrafficRecord {
+    fn redact(&mut self, redactor: &dyn Redactor) {
+        redact_payload(&mut self.request, redactor);
+        if let Some(response) = &mut self.response {
+            redact_payload(response, redactor);
+        }
+    }
+}
+
+// Masks the sensitive fields of a payload, and its sensitive HTTP headers.
+fn redact_payload(value: &mut Value, redactor: &dyn Redactor) {
+    redactor.redact_json(value);
+    redact_headers(value, redactor);
+}
+
+fn redact_headers(value: &mut Value, redactor: &dyn Redactor) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                match (key.as_str(), value) {
+                    ("headers" | "multiValueHeaders", Value::Object(headers)) => {
+                        for (name, value) in headers.iter_mut() {
+                            if redactor.is_sensitive_header(&name.to_ascii_lowercase()) {
+                                *value = match value {
+                                    Value::Array(values) => Value::Array(vec![REDACTED.into(); values.len()]),
+                                    _ => REDACTED.into(),
+                                };
+                            }
+                        }
+                    }
+                    (_, value) => redact_headers(value, redactor),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_headers(item, redactor)),
+        _ => {}
+    }
+}
+
+/// Destination of the records of a [`TrafficExportLayer`].
+pub trait TrafficExporter: Send + Sync {
+    /// Send a record, usually to EventBridge or Kinesis.
+    fn export(&self, record: TrafficRecord) -> BoxFuture<'_, Result<(), Error>>;
+}
+
+/// A [`Layer`] that exports sanitized copies of a percentage of the invocations.
+///
+/// See the [module documentation](self) for details.
+pub struct TrafficExportLayer<A> {
+    target: Target,
+    percent: u8,
+    _payload: PhantomData<fn(A)>,
+}
+
+#[derive(Clone)]
+struct Target {
+    exporter: Arc<dyn TrafficExporter>,
+    redactor: Arc<dyn Redactor>,
+    budget: Duration,
+    max_bytes: usize,
+}
+
+impl Target {
+    async fn export(self, mut record: TrafficRecord) {
+        record.redact(self.redactor.as_ref());
+        let size = serde_json::to_vec(&record).map(|json| json.len()).unwrap_or_default();
+        if size > self.max_bytes {
+            warn!(
+                size,
+                max_bytes = self.max_bytes,
+                "traffic record too large to be exported"
+            );
+            return;
+        }
+        match tokio::time::timeout(self.budget, self.exporter.export(record)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => warn!(error = %err, "unable to export the traffic record"),
+            Err(_) => warn!(
+                budget_ms = self.budget.as_millis() as u64,
+                "traffic record not exported within its budget"
+            ),
+        }
+    }
+}
+
+impl<A> TrafficExportLayer<A> {
+    /// Export `percent` percent of the invocations to `exporter`, masked with
+    /// [`FieldRedactor::default`].
+    ///
+    /// Invocations are selected from a hash of their request id, so retries of
+    /// an exported invocation are exported too.
+    pub fn new(exporter: impl TrafficExporter + 'static, percent: u8) -> Self {
+        TrafficExportLayer {
+            target: Target {
+                exporter: Arc::new(exporter),
+                redactor: Arc::new(FieldRedactor::default()),
+                budget: DEFAULT_BUDGET,
+                max_bytes: DEFAULT_MAX_BYTES,
+            },
+            percent: percent.min(100),
+            _payload: PhantomData,
+        }
+    }
+
+    /// Mask the records with `redactor`.
+    pub fn redactor(self, redactor: impl Redactor + 'static) -> Self {
+        TrafficExportLayer {
+            target: Target {
+                redactor: Arc::new(redactor),
+                ..self.target
+            },
+            ..self
+        }
+    }
+
+    /// Give up on exports that take longer than `budget` instead of [`DEFAULT_BUDGET`].
+    pub fn budget(self, budget: Duration) -> Self {
+        TrafficExportLayer {
+            target: Target { budget, ..self.target },
+            ..self
+        }
+    }
+
+    /// Drop the records larger than `max_bytes` once serialized, instead of [`DEFAULT_MAX_BYTES`].
+    pub fn max_bytes(self, max_bytes: usize) -> Self {
+        TrafficExportLayer {
+            target: Target {
+                max_bytes,
+                ..self.target
+            },
+            ..self
+        }
+    }
+}
+
+impl<A> Clone for TrafficExportLayer<A> {
+    fn clone(&self) -> Self {
+        TrafficExportLayer {
+            target: self.target.clone(),
+            percent: self.percent,
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl<A> fmt::Debug for TrafficExportLayer<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrafficExportLayer")
+            .field("percent", &self.percent)
+            .field("budget", &self.target.budget)
+            .field("max_bytes", &self.target.max_bytes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, A> Layer<S> for TrafficExportLayer<A> {
+    type Service = TrafficExport<S, A>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TrafficExport {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A [`Service`] that exports sanitized copies of a percentage of the invocations.
+///
+/// See [`TrafficExportLayer`] for details.
+pub struct TrafficExport<S, A> {
+    inner: S,
+    layer: TrafficExportLayer<A>,
+}
+
+impl<S, A> TrafficExport<S, A> {
+    fn is_selected(&self, request_id: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        request_id.hash(&mut hasher);
+        hasher.finish() % 100 < u64::from(self.layer.percent)
+    }
+}
+
+impl<S: Clone, A> Clone for TrafficExport<S, A> {
+    fn clone(&self) -> Self {
+        TrafficExport {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, A> fmt::Debug for TrafficExport<S, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrafficExport")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<S, A> Service<LambdaEvent<Value>> for TrafficExport<S, A>
+where
+    S: Service<LambdaEvent<A>>,
+    S::Future: Send + 'static,
+    S::Response: Serialize + Send + 'static,
+    S::Error: Into<Error>,
+    A: for<'de> Deserialize<'de>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = BoxFuture<'static, Result<S::Response, Error>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: LambdaEvent<Value>) -> Self::Future {
+        if !self.is_selected(&req.context.request_id) {
+            return match deserializer::deserialize_value(req.payload, req.context) {
+                Ok(event) => Box::pin(self.inner.call(event).err_into()),
+                Err(err) => Box::pin(future::ready(Err(err.into()))),
+            };
+        }
+
+        let mut record = TrafficRecord {
+            request_id: req.context.request_id.clone(),
+            invoked_function_arn: req.context.invoked_function_arn.clone(),
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            duration_ms: 0,
+            request: req.payload.clone(),
+            response: None,
+            error: None,
+        };
+        let fut = deserializer::deserialize_value(req.payload, req.context).map(|event| self.inner.call(event));
+        let target = self.layer.target.clone();
+
+        Box::pin(async move {
+            let start = Instant::now();
+            let result = match fut {
+                Ok(fut) => fut.await.map_err(Into::into),
+                Err(err) => Err(err.into()),
+            };
+            record.duration_ms = start.elapsed().as_millis() as u64;
+            match &result {
+                Ok(response) => match serde_json::to_value(response) {
+                    Ok(response) => record.response = Some(response),
+                    Err(err) => warn!(error = %err, "unable to serialize the response for the traffic record"),
+                },
+                Err(err) => record.error = Some(err.to_string()),
+            }
+            defer(target.export(record));
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, Context};
+    use serde_json::json;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+    struct Channel(UnboundedSender<TrafficRecord>);
+
+    impl TrafficExporter for Channel {
+        fn export(&self, record: TrafficRecord) -> BoxFuture<'_, Result<(), Error>> {
+            let _ = self.0.send(record);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn channel() -> (Channel, UnboundedReceiver<TrafficRecord>) {
+        let (tx, rx) = unbounded_channel();
+        (Channel(tx), rx)
+    }
+
+    fn event(payload: Value) -> LambdaEvent<Value> {
+        let context = Context {
+            request_id: "8476a536-e9f4-11e8-9739-2dfe598c3fcd".to_string(),
+            ..Default::default()
+        };
+        LambdaEvent::new(payload, context)
+    }
+
+    async fn greet(event: LambdaEvent<Value>) -> Result<Value, Error> {
+        match event.payload["body"].as_str() {
+            Some("fail") => Err("unable to greet".into()),
+            _ => Ok(json!({"statusCode": 200, "headers": {"Set-Cookie": "session=1"}, "body": "hello"})),
+        }
+    }
+
+    #[tokio::test]
+    async fn exports_sanitized_records() {
+        let (exporter, mut records) = channel();
+        let layer = TrafficExportLayer::new(exporter, 100).redactor(FieldRedactor::default().field("password"));
+        let mut service = layer.layer(service_fn(greet));
+
+        let payload = json!({
+            "headers": {"authorization": "Bearer token", "host": "example.com"},
+            "multiValueHeaders": {"Cookie": ["a=1", "b=2"]},
+            "body": "hi",
+            "password": "hunter2",
+        });
+        service.call(event(payload)).await.unwrap();
+        let record = records.recv().await.unwrap();
+        assert_eq!("8476a536-e9f4-11e8-9739-2dfe598c3fcd", record.request_id);
+        assert_eq!(
+            json!({
+                "headers": {"authorization": REDACTED, "host": "example.com"},
+                "multiValueHeaders": {"Cookie": [REDACTED, REDACTED]},
+                "body": "hi",
+                "password": REDACTED,
+            }),
+            record.request
+        );
+        assert_eq!(json!(REDACTED), record.response.unwrap()["headers"]["Set-Cookie"]);
+
+        let err = service.call(event(json!({"body": "fail"}))).await.unwrap_err();
+        let record = records.recv().await.unwrap();
+        assert_eq!(Some(err.to_string()), record.error);
+        assert_eq!(None, record.response);
+    }
+
+    #[tokio::test]
+    async fn skips_unselected_and_large_records() {
+        let (exporter, mut records) = channel();
+        let mut service = TrafficExportLayer::new(exporter, 0).layer(service_fn(greet));
+        service.call(event(json!({"body": "hi"}))).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(records.try_recv().is_err());
+
+        let (exporter, mut records) = channel();
+        let mut service = TrafficExportLayer::new(exporter, 100)
+            .max_bytes(64)
+            .layer(service_fn(greet));
+        service.call(event(json!({"body": "hi"}))).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(records.try_recv().is_err());
+    }
+}
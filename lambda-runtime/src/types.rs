@@ -1,48 +1,127 @@
-use crate::{Config, Error};
+use crate::{Config, Error, TimeBudget};
 use http::{HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     convert::TryFrom,
+    fmt,
     time::{Duration, SystemTime},
 };
 
+/// Error reported to the Runtime API when an invocation fails.
+///
+/// The runtime reports the errors of a handler with their type name as the
+/// `errorType`. Handlers that fail with a `Diagnostic`, directly or boxed in an
+/// [`Error`], choose the `errorType` themselves, which callers like Step
+/// Functions match on, and the value of the `Lambda-Runtime-Function-Error-Type`
+/// header of the error.
+///
+/// # Example
+/// ```
+/// use lambda_runtime::{Diagnostic, Error, LambdaEvent};
+/// use serde_json::Value;
+///
+/// async fn handler(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     if event.payload.get("order").is_none() {
+///         return Err(Diagnostic::new("Order.Missing", "the event has no order").into());
+///     }
+///     Ok(event.payload)
+/// }
+/// ```
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct Diagnostic<'a> {
-    pub(crate) error_type: &'a str,
-    pub(crate) error_message: &'a str,
+pub struct Diagnostic {
+    error_type: String,
+    error_message: String,
+    #[serde(skip)]
+    function_error_type: Option<String>,
 }
 
+impl Diagnostic {
+    /// Create a diagnostic with the `errorType` and `errorMessage` of the invocation error.
+    pub fn new(error_type: impl Into<String>, error_message: impl Into<String>) -> Self {
+        Diagnostic {
+            error_type: error_type.into(),
+            error_message: error_message.into(),
+            function_error_type: None,
+        }
+    }
+
+    /// Set the `Lambda-Runtime-Function-Error-Type` header of the error, `unhandled` by default.
+    ///
+    /// The Runtime API accepts any value, `Category.Reason` values like
+    /// `Runtime.InvalidEvent` are recommended.
+    pub fn with_function_error_type(mut self, function_error_type: impl Into<String>) -> Self {
+        self.function_error_type = Some(function_error_type.into());
+        self
+    }
+
+    /// The `errorType` of the invocation error.
+    pub fn error_type(&self) -> &str {
+        &self.error_type
+    }
+
+    /// The `errorMessage` of the invocation error.
+    pub fn error_message(&self) -> &str {
+        &self.error_message
+    }
+
+    /// The `Lambda-Runtime-Function-Error-Type` header of the error.
+    pub fn function_error_type(&self) -> &str {
+        self.function_error_type
+            .as_deref()
+            .unwrap_or(DEFAULT_FUNCTION_ERROR_TYPE)
+    }
+}
+
+pub(crate) const DEFAULT_FUNCTION_ERROR_TYPE: &str = "unhandled";
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.error_type, self.error_message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
 /// The request ID, which identifies the request that triggered the function invocation. This header
 /// tracks the invocation within the Lambda control plane. The request ID is used to specify completion
 /// of a given invocation.
+#[allow(dead_code)]
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct RequestId(pub String);
 
 /// The date that the function times out in Unix time milliseconds. For example, `1542409706888`.
+#[allow(dead_code)]
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct InvocationDeadline(pub u64);
 
 /// The ARN of the Lambda function, version, or alias that is specified in the invocation.
 /// For instance, `arn:aws:lambda:us-east-2:123456789012:function:custom-runtime`.
+#[allow(dead_code)]
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct FunctionArn(pub String);
 
 /// The AWS X-Ray Tracing header. For more information,
 /// please see [AWS' documentation](https://docs.aws.amazon.com/xray/latest/devguide/xray-concepts.html#xray-concepts-tracingheader).
+#[allow(dead_code)]
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct XRayTraceId(pub String);
 
 /// For invocations from the AWS Mobile SDK contains data about client application and device.
+#[allow(dead_code)]
 #[derive(Debug, Clone, Eq, PartialEq)]
 struct MobileClientContext(String);
 
 /// For invocations from the AWS Mobile SDK, data about the Amazon Cognito identity provider.
+#[allow(dead_code)]
 #[derive(Debug, Clone, Eq, PartialEq)]
 struct MobileClientIdentity(String);
 
 /// Client context sent by the AWS Mobile SDK.
+///
+/// The context is deserialized from the format that the mobile SDKs use to send
+/// it to the Lambda API, like the `env` key for the environment settings.
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct ClientContext {
     /// Information about the mobile application invoking the function.
@@ -52,13 +131,50 @@ pub struct ClientContext {
     #[serde(default)]
     pub custom: HashMap<String, String>,
     /// Environment settings from the mobile client.
-    #[serde(default)]
+    #[serde(default, alias = "env")]
     pub environment: HashMap<String, String>,
 }
 
+impl ClientContext {
+    /// Return the value of a custom property set by the mobile application.
+    pub fn custom(&self, key: &str) -> Option<&str> {
+        self.custom.get(key).map(String::as_str)
+    }
+
+    /// Return the value of an environment setting from the mobile client.
+    pub fn env(&self, key: &str) -> Option<&str> {
+        self.environment.get(key).map(String::as_str)
+    }
+
+    /// The platform of the mobile device, for example `Android` or `iPhoneOS`.
+    pub fn platform(&self) -> Option<&str> {
+        self.env("platform")
+    }
+
+    /// The version of the platform of the mobile device.
+    pub fn platform_version(&self) -> Option<&str> {
+        self.env("platform_version")
+    }
+
+    /// The manufacturer of the mobile device.
+    pub fn make(&self) -> Option<&str> {
+        self.env("make")
+    }
+
+    /// The model of the mobile device.
+    pub fn model(&self) -> Option<&str> {
+        self.env("model")
+    }
+
+    /// The locale of the mobile device, for example `en_US`.
+    pub fn locale(&self) -> Option<&str> {
+        self.env("locale")
+    }
+}
+
 /// AWS Mobile SDK client fields.
 #[derive(Serialize, Deserialize, Default, Clone, Debug, Eq, PartialEq)]
-#[serde(rename_all = "camelCase")]
+#[serde(default, rename_all = "camelCase")]
 pub struct ClientApplication {
     /// The mobile app installation id
     #[serde(alias = "installation_id")]
@@ -114,6 +230,15 @@ pub struct Context {
     /// Includes information such as the function name, memory allocation,
     /// version, and log streams.
     pub env_config: Config,
+    /// Whether this is the first invocation handled by the execution environment.
+    #[serde(default)]
+    pub cold_start: bool,
+    /// The time between the start of the process and the first request of the
+    /// runtime for an event, set on the first invocation only. It's measured from
+    /// `/proc`, with a resolution of 10ms, and includes the initialization done in
+    /// `main` before the runtime starts.
+    #[serde(default)]
+    pub init_duration: Option<Duration>,
 }
 
 impl TryFrom<HeaderMap> for Context {
@@ -161,6 +286,185 @@ impl TryFrom<HeaderMap> for Context {
     }
 }
 
+impl Context {
+    /// Create a builder of contexts for tests, with realistic values in every field.
+    ///
+    /// Unlike a struct expression, it keeps compiling when fields are added to the context.
+    ///
+    /// # Example
+    /// ```
+    /// use lambda_runtime::{Context, LambdaEvent};
+    /// use std::time::Duration;
+    ///
+    /// let context = Context::builder()
+    ///     .request_id("my-request")
+    ///     .timeout(Duration::from_secs(30))
+    ///     .build();
+    /// let event = LambdaEvent::new("payload", Context::default()).with_context(context);
+    /// assert_eq!("my-request", event.context.request_id);
+    /// ```
+    pub fn builder() -> ContextBuilder {
+        ContextBuilder::default()
+    }
+
+    /// Add environment details to the context by setting `env_config`.
+    pub fn with_config(self, config: &Config) -> Self {
+        Self {
+            env_config: config.clone(),
+            ..self
+        }
+    }
+
+    /// The execution deadline for the current invocation.
+    pub fn deadline(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(self.deadline)
+    }
+
+    /// The time left for the current invocation, which can be split into phases with their own timeouts.
+    pub fn time_budget(&self) -> TimeBudget {
+        TimeBudget::new(self.deadline())
+    }
+
+    /// The version or alias used to invoke the function, parsed from `invoked_function_arn`.
+    ///
+    /// Returns `None` when the function was invoked with an unqualified ARN.
+    pub fn qualifier(&self) -> Option<&str> {
+        // arn:aws:lambda:region:account-id:function:function-name[:qualifier]
+        self.invoked_function_arn
+            .splitn(8, ':')
+            .nth(7)
+            .filter(|q| !q.is_empty())
+    }
+
+    /// The alias used to invoke the function, if the function was invoked through an alias.
+    ///
+    /// Returns `None` when the function was invoked with an unqualified ARN,
+    /// with a numeric version, or with `$LATEST`.
+    pub fn alias(&self) -> Option<&str> {
+        self.qualifier()
+            .filter(|q| *q != "$LATEST" && !q.bytes().all(|b| b.is_ascii_digit()))
+    }
+}
+
+/// Builder of [`Context`] values for tests, created with [`Context::builder`].
+#[derive(Clone, Debug)]
+pub struct ContextBuilder {
+    context: Context,
+}
+
+impl Default for ContextBuilder {
+    fn default() -> Self {
+        let deadline = SystemTime::now() + Duration::from_secs(3);
+        ContextBuilder {
+            context: Context {
+                request_id: "8476a536-e9f4-11e8-9739-2dfe598c3fcd".into(),
+                deadline: deadline
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+                invoked_function_arn: "arn:aws:lambda:us-east-1:123456789012:function:test-function".into(),
+                xray_trace_id: Some(
+                    "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1".into(),
+                ),
+                client_context: None,
+                identity: None,
+                env_config: Config {
+                    function_name: "test-function".into(),
+                    memory: 128,
+                    version: "$LATEST".into(),
+                    log_stream: "2023/01/01/[$LATEST]8476a536e9f411e897392dfe598c3fcd".into(),
+                    log_group: "/aws/lambda/test-function".into(),
+                },
+                cold_start: false,
+                init_duration: None,
+            },
+        }
+    }
+}
+
+impl ContextBuilder {
+    /// Set the id of the invocation.
+    pub fn request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.context.request_id = request_id.into();
+        self
+    }
+
+    /// Set the deadline of the invocation, 3 seconds from the creation of the builder by default.
+    pub fn deadline(mut self, deadline: SystemTime) -> Self {
+        self.context.deadline = deadline
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self
+    }
+
+    /// Set the deadline of the invocation to `timeout` from now.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.deadline(SystemTime::now() + timeout)
+    }
+
+    /// Set the ARN used to invoke the function.
+    pub fn invoked_function_arn(mut self, arn: impl Into<String>) -> Self {
+        self.context.invoked_function_arn = arn.into();
+        self
+    }
+
+    /// Set the qualifier of the invoked function ARN, an alias or a version.
+    pub fn qualifier(mut self, qualifier: &str) -> Self {
+        let arn = self
+            .context
+            .invoked_function_arn
+            .splitn(8, ':')
+            .take(7)
+            .collect::<Vec<_>>();
+        self.context.invoked_function_arn = format!("{}:{qualifier}", arn.join(":"));
+        self
+    }
+
+    /// Set the X-Ray trace id, or remove it with `None`.
+    pub fn xray_trace_id(mut self, trace_id: Option<String>) -> Self {
+        self.context.xray_trace_id = trace_id;
+        self
+    }
+
+    /// Set the client context sent by the AWS Mobile SDK.
+    pub fn client_context(mut self, client_context: ClientContext) -> Self {
+        self.context.client_context = Some(client_context);
+        self
+    }
+
+    /// Set the Cognito identity of the caller.
+    pub fn identity(mut self, identity: CognitoIdentity) -> Self {
+        self.context.identity = Some(identity);
+        self
+    }
+
+    /// Set the configuration of the function.
+    pub fn env_config(mut self, config: Config) -> Self {
+        self.context.env_config = config;
+        self
+    }
+
+    /// Mark the invocation as the first one of the execution environment, with
+    /// the duration of its init phase.
+    pub fn cold_start(mut self, init_duration: Duration) -> Self {
+        self.context.cold_start = true;
+        self.context.init_duration = Some(init_duration);
+        self
+    }
+
+    /// Build the context.
+    pub fn build(self) -> Context {
+        self.context
+    }
+}
+
+impl From<ContextBuilder> for Context {
+    fn from(builder: ContextBuilder) -> Self {
+        builder.build()
+    }
+}
+
 /// Incoming Lambda request containing the event payload and context.
 #[derive(Clone, Debug)]
 pub struct LambdaEvent<T> {
@@ -180,12 +484,42 @@ impl<T> LambdaEvent<T> {
     pub fn into_parts(self) -> (T, Context) {
         (self.payload, self.context)
     }
+
+    /// Replace the context of the event, with a [`Context`] or a [`ContextBuilder`].
+    pub fn with_context(self, context: impl Into<Context>) -> Self {
+        Self {
+            context: context.into(),
+            ..self
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn context_builder() {
+        let context = Context::builder().build();
+        assert!(!context.request_id.is_empty());
+        assert!(context.deadline() > SystemTime::now());
+        assert_eq!("test-function", context.env_config.function_name);
+        assert_eq!(None, context.qualifier());
+
+        let context = Context::builder()
+            .request_id("req-1")
+            .qualifier("live")
+            .qualifier("7")
+            .xray_trace_id(None)
+            .build();
+        assert_eq!("req-1", context.request_id);
+        assert_eq!(Some("7"), context.qualifier());
+        assert_eq!(None, context.xray_trace_id);
+
+        let event = LambdaEvent::new((), Context::default()).with_context(Context::builder().request_id("req-2"));
+        assert_eq!("req-2", event.context.request_id);
+    }
+
     #[test]
     fn round_trip_lambda_error() {
         use serde_json::{json, Value};
@@ -194,10 +528,7 @@ mod test {
             "errorMessage": "Error parsing event data.",
         });
 
-        let actual = Diagnostic {
-            error_type: "InvalidEventDataError",
-            error_message: "Error parsing event data.",
-        };
+        let actual = Diagnostic::new("InvalidEventDataError", "Error parsing event data.");
         let actual: Value = serde_json::to_value(actual).expect("failed to serialize diagnostic");
         assert_eq!(expected, actual);
     }
@@ -257,6 +588,89 @@ mod test {
         assert_eq!(tried.client_context.unwrap(), client_context);
     }
 
+    #[test]
+    fn context_with_android_sdk_client_context_resolves() {
+        let client_context = r#"{
+            "client": {
+                "installation_id": "f1c1a5b0-0b1e-4a8b-9c3c-6c7a4b9e5d21",
+                "app_title": "Demo",
+                "app_version_name": "1.2.0",
+                "app_version_code": "12",
+                "app_package_name": "com.example.demo"
+            },
+            "env": {
+                "platform": "Android",
+                "model": "Pixel 7",
+                "make": "Google",
+                "platform_version": "13",
+                "locale": "en_US"
+            },
+            "services": {
+                "mobile_analytics": {"app_id": "c0ffee"}
+            },
+            "custom": {"tenant": "acme"}
+        }"#;
+        let mut headers = HeaderMap::new();
+        headers.insert("lambda-runtime-aws-request-id", HeaderValue::from_static("my-id"));
+        headers.insert("lambda-runtime-deadline-ms", HeaderValue::from_static("123"));
+        headers.insert(
+            "lambda-runtime-client-context",
+            HeaderValue::from_str(&client_context.replace('\n', "")).unwrap(),
+        );
+        let ctx = Context::try_from(headers).unwrap();
+        let client_context = ctx.client_context.unwrap();
+        assert_eq!(
+            "f1c1a5b0-0b1e-4a8b-9c3c-6c7a4b9e5d21",
+            client_context.client.installation_id
+        );
+        assert_eq!("Demo", client_context.client.app_title);
+        assert_eq!("1.2.0", client_context.client.app_version_name);
+        assert_eq!("12", client_context.client.app_version_code);
+        assert_eq!("com.example.demo", client_context.client.app_package_name);
+        assert_eq!(Some("Android"), client_context.platform());
+        assert_eq!(Some("13"), client_context.platform_version());
+        assert_eq!(Some("Google"), client_context.make());
+        assert_eq!(Some("Pixel 7"), client_context.model());
+        assert_eq!(Some("en_US"), client_context.locale());
+        assert_eq!(Some("acme"), client_context.custom("tenant"));
+        assert_eq!(None, client_context.custom("missing"));
+    }
+
+    #[test]
+    fn client_context_keeps_its_serialized_names() {
+        let client_context: ClientContext = serde_json::from_str(
+            r#"{"client":{"installation_id":"id","app_title":"Demo"},"env":{"platform":"iPhoneOS"}}"#,
+        )
+        .unwrap();
+        assert_eq!("id", client_context.client.installation_id);
+        assert_eq!(Some("iPhoneOS"), client_context.platform());
+
+        let value = serde_json::to_value(&client_context).unwrap();
+        assert_eq!("id", value["client"]["installationId"]);
+        assert_eq!("Demo", value["client"]["appTitle"]);
+        assert_eq!("iPhoneOS", value["environment"]["platform"]);
+        assert_eq!(client_context, serde_json::from_value(value).unwrap());
+
+        let client: ClientApplication = serde_json::from_str(r#"{"client_id":"id"}"#).unwrap();
+        assert_eq!("", client.installation_id);
+    }
+
+    #[test]
+    fn context_with_cognito_identity_header_resolves() {
+        let mut headers = HeaderMap::new();
+        headers.insert("lambda-runtime-aws-request-id", HeaderValue::from_static("my-id"));
+        headers.insert("lambda-runtime-deadline-ms", HeaderValue::from_static("123"));
+        headers.insert(
+            "lambda-runtime-cognito-identity",
+            HeaderValue::from_static(
+                r#"{"cognitoIdentityId":"us-east-1:1234","cognitoIdentityPoolId":"us-east-1:pool"}"#,
+            ),
+        );
+        let identity = Context::try_from(headers).unwrap().identity.unwrap();
+        assert_eq!("us-east-1:1234", identity.identity_id);
+        assert_eq!("us-east-1:pool", identity.identity_pool_id);
+    }
+
     #[test]
     fn context_with_empty_client_context_resolves() {
         let mut headers = HeaderMap::new();
@@ -369,19 +783,26 @@ mod test {
         headers.insert("lambda-runtime-trace-id", HeaderValue::from_static("arn::myarn"));
         Context::try_from(headers);
     }
-}
 
-impl Context {
-    /// Add environment details to the context by setting `env_config`.
-    pub fn with_config(self, config: &Config) -> Self {
-        Self {
-            env_config: config.clone(),
-            ..self
-        }
-    }
+    #[test]
+    fn context_qualifier_from_invoked_function_arn() {
+        let mut ctx = Context {
+            invoked_function_arn: "arn:aws:lambda:us-east-1:123456789012:function:my-function".into(),
+            ..Default::default()
+        };
+        assert_eq!(None, ctx.qualifier());
+        assert_eq!(None, ctx.alias());
 
-    /// The execution deadline for the current invocation.
-    pub fn deadline(&self) -> SystemTime {
-        SystemTime::UNIX_EPOCH + Duration::from_millis(self.deadline)
+        ctx.invoked_function_arn = "arn:aws:lambda:us-east-1:123456789012:function:my-function:42".into();
+        assert_eq!(Some("42"), ctx.qualifier());
+        assert_eq!(None, ctx.alias());
+
+        ctx.invoked_function_arn = "arn:aws:lambda:us-east-1:123456789012:function:my-function:$LATEST".into();
+        assert_eq!(Some("$LATEST"), ctx.qualifier());
+        assert_eq!(None, ctx.alias());
+
+        ctx.invoked_function_arn = "arn:aws:lambda:us-east-1:123456789012:function:my-function:blue".into();
+        assert_eq!(Some("blue"), ctx.qualifier());
+        assert_eq!(Some("blue"), ctx.alias());
     }
 }
@@ -0,0 +1,426 @@
+//! Functions invoked directly by other functions receive events whose schema
+//! is owned by the producers. Deploying a new schema in both functions at the
+//! same time is not possible, so consumers have to accept the payloads of
+//! producers that still send an older version, and events that were queued
+//! before the upgrade.
+//!
+//! [`VersionedPayload`] reads a version discriminator in the event, `"version"`
+//! by default, and deserializes the payload with the type of that version. The
+//! [`Migrations`] of the type then upgrade it step by step to the latest
+//! version, so each upgrade function only knows about two consecutive versions.
+//! Payloads without a discriminator are tried as every version, from the latest
+//! to the oldest. Producers send a `VersionedPayload` too, which adds the
+//! discriminator of the latest version to the payload.
+//!
+//! # Example
+//! ```
+//! use lambda_runtime::versioned::{Migrations, Versioned, VersionedPayload};
+//! use serde::{Deserialize, Serialize};
+//! use serde_json::json;
+//!
+//! #[derive(Deserialize)]
+//! struct OrderV1 {
+//!     amount: u64,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct OrderV2 {
+//!     cents: u64,
+//! }
+//!
+//! #[derive(Debug, Serialize, Deserialize)]
+//! struct Order {
+//!     cents: u64,
+//!     currency: String,
+//! }
+//!
+//! impl Versioned for Order {
+//!     const VERSION: u32 = 3;
+//!
+//!     fn migrations() -> Migrations<Self> {
+//!         Migrations::<OrderV1>::starting_at(1)
+//!             .then(2, |v1: OrderV1| OrderV2 { cents: v1.amount * 100 })
+//!             .then(3, |v2: OrderV2| Order { cents: v2.cents, currency: "USD".into() })
+//!     }
+//! }
+//!
+//! let payload: VersionedPayload<Order> = serde_json::from_value(json!({ "version": 1, "amount": 2 })).unwrap();
+//! assert_eq!(1, payload.version());
+//! assert_eq!(200, payload.cents);
+//!
+//! let event = serde_json::to_value(VersionedPayload::new(payload.into_inner())).unwrap();
+//! assert_eq!(json!({ "version": 3, "cents": 200, "currency": "USD" }), event);
+//! ```
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, ops::Deref, sync::Arc};
+
+/// Name of the field of the payload that holds its version, by default.
+pub const DEFAULT_VERSION_FIELD: &str = "version";
+
+type Decoder<T> = Box<dyn Fn(Value) -> Result<T, serde_json::Error> + Send + Sync>;
+
+/// A payload type with a schema that evolves over time.
+pub trait Versioned: Serialize + DeserializeOwned + 'static {
+    /// The latest version of the schema, which is the version of `Self`.
+    const VERSION: u32;
+
+    /// The name of the field of the payload that holds its version.
+    const FIELD: &'static str = DEFAULT_VERSION_FIELD;
+
+    /// The previous versions of the schema and how to upgrade them to `Self`.
+    ///
+    /// By default, only the latest version is accepted.
+    fn migrations() -> Migrations<Self> {
+        Migrations::starting_at(Self::VERSION)
+    }
+}
+
+/// The chain of versions of a payload, from the oldest one to `T`.
+///
+/// See the [module documentation](self) for details.
+pub struct Migrations<T> {
+    latest: u32,
+    decoders: BTreeMap<u32, Decoder<T>>,
+}
+
+impl<T: DeserializeOwned + 'static> Migrations<T> {
+    /// Start the chain with the oldest version that is still accepted.
+    pub fn starting_at(version: u32) -> Self {
+        let mut decoders: BTreeMap<u32, Decoder<T>> = BTreeMap::new();
+        decoders.insert(version, Box::new(serde_json::from_value::<T>));
+        Migrations {
+            latest: version,
+            decoders,
+        }
+    }
+}
+
+impl<T: 'static> Migrations<T> {
+    /// Add the next version of the chain, with the function that upgrades the
+    /// previous version to it.
+    ///
+    /// # Panics
+    /// Panics if `version` is not greater than the previous version.
+    pub fn then<U, F>(self, version: u32, upgrade: F) -> Migrations<U>
+    where
+        U: DeserializeOwned + 'static,
+        F: Fn(T) -> U + Send + Sync + 'static,
+    {
+        assert!(
+            version > self.latest,
+            "version {version} must be greater than the previous version {}",
+            self.latest
+        );
+        let upgrade = Arc::new(upgrade);
+        let mut decoders: BTreeMap<u32, Decoder<U>> = self
+            .decoders
+            .into_iter()
+            .map(|(from, decode)| {
+                let upgrade = upgrade.clone();
+                let decoder: Decoder<U> = Box::new(move |value| decode(value).map(|previous| upgrade(previous)));
+                (from, decoder)
+            })
+            .collect();
+        decoders.insert(version, Box::new(serde_json::from_value::<U>));
+        Migrations {
+            latest: version,
+            decoders,
+        }
+    }
+
+    /// The latest version of the chain.
+    pub fn latest(&self) -> u32 {
+        self.latest
+    }
+
+    /// The versions accepted by the chain, from the oldest one.
+    pub fn versions(&self) -> impl Iterator<Item = u32> + '_ {
+        self.decoders.keys().copied()
+    }
+
+    /// Deserialize a payload of the given version, or of any version, from the
+    /// latest, when it's `None`, and upgrade it to the latest version.
+    ///
+    /// Returns the value and the version that it was deserialized as.
+    pub fn decode(&self, version: Option<u32>, value: Value) -> Result<(T, u32), MigrationError> {
+        match version {
+            Some(version) => {
+                let decode = self.decoders.get(&version).ok_or(MigrationError::UnknownVersion {
+                    version,
+                    latest: self.latest,
+                })?;
+                let decoded = decode(value).map_err(|source| MigrationError::Invalid { version, source })?;
+                Ok((decoded, version))
+            }
+            None => {
+                let mut last = None;
+                for (version, decode) in self.decoders.iter().rev() {
+                    match decode(value.clone()) {
+                        Ok(decoded) => return Ok((decoded, *version)),
+                        Err(err) => last = Some(err),
+                    }
+                }
+                Err(MigrationError::Unversioned { source: last })
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for Migrations<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migrations")
+            .field("versions", &self.decoders.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Error returned when a payload can't be deserialized as any known version.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MigrationError {
+    /// The version of the payload is not in the chain of migrations.
+    UnknownVersion {
+        /// The version of the payload.
+        version: u32,
+        /// The latest version known by the function.
+        latest: u32,
+    },
+    /// The payload doesn't match the schema of its version.
+    Invalid {
+        /// The version of the payload.
+        version: u32,
+        /// The deserialization error.
+        source: serde_json::Error,
+    },
+    /// The payload has no version, and doesn't match the schema of any version.
+    Unversioned {
+        /// The error of the oldest version.
+        source: Option<serde_json::Error>,
+    },
+    /// The version field is not an unsigned integer.
+    InvalidVersionField {
+        /// The name of the field.
+        field: &'static str,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::UnknownVersion { version, latest } => {
+                write!(
+                    f,
+                    "unknown payload version {version}, the latest known version is {latest}"
+                )
+            }
+            MigrationError::Invalid { version, source } => write!(f, "invalid payload of version {version}: {source}"),
+            MigrationError::Unversioned { source: Some(source) } => {
+                write!(f, "the payload has no version and matches no known version: {source}")
+            }
+            MigrationError::Unversioned { source: None } => write!(f, "the payload has no version"),
+            MigrationError::InvalidVersionField { field } => {
+                write!(f, "the `{field}` field of the payload is not an unsigned integer")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrationError::Invalid { source, .. } => Some(source),
+            MigrationError::Unversioned { source: Some(source) } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// A payload with a version discriminator, upgraded to the latest version of
+/// `T` when it's deserialized.
+///
+/// It dereferences to `T`. See the [module documentation](self) for details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionedPayload<T> {
+    inner: T,
+    version: u32,
+}
+
+impl<T: Versioned> VersionedPayload<T> {
+    /// Wrap a payload of the latest version, to send it to a function.
+    pub fn new(inner: T) -> Self {
+        VersionedPayload {
+            inner,
+            version: T::VERSION,
+        }
+    }
+
+    /// Deserialize a JSON value and upgrade it to the latest version.
+    pub fn from_value(value: Value) -> Result<Self, MigrationError> {
+        let version = match value.get(T::FIELD) {
+            None | Some(Value::Null) => None,
+            Some(version) => Some(
+                version
+                    .as_u64()
+                    .and_then(|version| u32::try_from(version).ok())
+                    .ok_or(MigrationError::InvalidVersionField { field: T::FIELD })?,
+            ),
+        };
+        let (inner, version) = T::migrations().decode(version, value)?;
+        Ok(VersionedPayload { inner, version })
+    }
+}
+
+impl<T> VersionedPayload<T> {
+    /// The version of the payload before it was upgraded.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Return the upgraded payload.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Deref for VersionedPayload<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'de, T: Versioned> Deserialize<'de> for VersionedPayload<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        VersionedPayload::from_value(value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<T: Versioned> Serialize for VersionedPayload<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut value = serde_json::to_value(&self.inner).map_err(serde::ser::Error::custom)?;
+        let object = value
+            .as_object_mut()
+            .ok_or_else(|| serde::ser::Error::custom("versioned payloads must be JSON objects"))?;
+        object.insert(T::FIELD.into(), T::VERSION.into());
+        value.serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    struct UserV1 {
+        name: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct UserV2 {
+        first_name: String,
+        last_name: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        first_name: String,
+        last_name: String,
+        email: Option<String>,
+    }
+
+    impl Versioned for User {
+        const VERSION: u32 = 3;
+        const FIELD: &'static str = "schemaVersion";
+
+        fn migrations() -> Migrations<Self> {
+            Migrations::<UserV1>::starting_at(1)
+                .then(2, |v1: UserV1| {
+                    let (first_name, last_name) = v1.name.split_once(' ').unwrap_or((&v1.name, ""));
+                    UserV2 {
+                        first_name: first_name.into(),
+                        last_name: last_name.into(),
+                    }
+                })
+                .then(3, |v2: UserV2| User {
+                    first_name: v2.first_name,
+                    last_name: v2.last_name,
+                    email: None,
+                })
+        }
+    }
+
+    fn decode(value: Value) -> Result<VersionedPayload<User>, MigrationError> {
+        VersionedPayload::from_value(value)
+    }
+
+    #[test]
+    fn upgrades_previous_versions() {
+        let v1 = decode(json!({ "schemaVersion": 1, "name": "Ada Lovelace" })).unwrap();
+        let v2 = decode(json!({ "schemaVersion": 2, "first_name": "Ada", "last_name": "Lovelace" })).unwrap();
+        assert_eq!(1, v1.version());
+        assert_eq!(2, v2.version());
+        assert_eq!(v1.into_inner(), v2.into_inner());
+
+        let v3 = decode(json!({
+            "schemaVersion": 3,
+            "first_name": "Ada",
+            "last_name": "Lovelace",
+            "email": "ada@example.com"
+        }))
+        .unwrap();
+        assert_eq!(Some("ada@example.com"), v3.email.as_deref());
+    }
+
+    #[test]
+    fn tries_every_version_without_discriminator() {
+        let payload = decode(json!({ "name": "Grace Hopper" })).unwrap();
+        assert_eq!(1, payload.version());
+        assert_eq!("Hopper", payload.last_name);
+
+        let payload = decode(json!({ "first_name": "Grace", "last_name": "Hopper" })).unwrap();
+        assert_eq!(3, payload.version());
+
+        let err = decode(json!({ "nickname": "Amazing Grace" })).unwrap_err();
+        assert!(matches!(err, MigrationError::Unversioned { source: Some(_) }));
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let err = decode(json!({ "schemaVersion": 4, "name": "Alan Turing" })).unwrap_err();
+        assert!(matches!(err, MigrationError::UnknownVersion { version: 4, latest: 3 }));
+
+        let err = decode(json!({ "schemaVersion": 2, "name": "Alan Turing" })).unwrap_err();
+        assert!(matches!(err, MigrationError::Invalid { version: 2, .. }));
+
+        let err = decode(json!({ "schemaVersion": "2", "name": "Alan Turing" })).unwrap_err();
+        assert!(matches!(
+            err,
+            MigrationError::InvalidVersionField { field: "schemaVersion" }
+        ));
+    }
+
+    #[test]
+    fn sends_the_latest_version() {
+        let user = User {
+            first_name: "Alan".into(),
+            last_name: "Turing".into(),
+            email: None,
+        };
+        let value = serde_json::to_value(VersionedPayload::new(user)).unwrap();
+        assert_eq!(3, value["schemaVersion"]);
+
+        let payload: VersionedPayload<User> = serde_json::from_value(value).unwrap();
+        assert_eq!(3, payload.version());
+        assert_eq!("Turing", payload.last_name);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be greater")]
+    fn versions_increase() {
+        let _ = Migrations::<UserV1>::starting_at(2).then(1, |v1: UserV1| v1.name);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{deserializer, Error, LambdaEvent};
+use futures::future::{self, Either, MapOk, Ready, TryFutureExt};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+use tracing::debug;
+
+type Matcher = Arc<dyn Fn(&Value) -> bool + Send + Sync>;
+
+/// Source set by the `serverless-plugin-warmup` plugin in its warmup payloads.
+const SERVERLESS_WARMUP_SOURCE: &str = "serverless-plugin-warmup";
+
+/// A [`Layer`] that answers warmup pings before they reach the handler.
+///
+/// By default, it recognizes the payloads sent by `serverless-plugin-warmup`.
+/// Scheduled events from specific EventBridge rules, and any other payload shape,
+/// can be recognized with [`WarmupLayer::scheduled_rule`] and [`WarmupLayer::matcher`].
+///
+/// Warmup events are answered with a `null` response. Any other event is
+/// deserialized into the handler's payload type and passed to the handler.
+///
+/// # Example
+/// ```no_run
+/// use lambda_runtime::{service_fn, tower::Layer, Error, LambdaEvent, WarmupLayer};
+/// use serde_json::Value;
+///
+/// async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+///     Ok(event.payload)
+/// }
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Error> {
+///     let func = WarmupLayer::new().scheduled_rule("keep-warm").layer(service_fn(func));
+///     lambda_runtime::run(func).await
+/// }
+/// ```
+#[derive(Clone)]
+pub struct WarmupLayer<A> {
+    matchers: Vec<Matcher>,
+    _payload: PhantomData<fn(A)>,
+}
+
+impl<A> WarmupLayer<A> {
+    /// Create a new layer that recognizes `serverless-plugin-warmup` payloads.
+    pub fn new() -> Self {
+        WarmupLayer {
+            matchers: vec![Arc::new(is_serverless_warmup)],
+            _payload: PhantomData,
+        }
+    }
+
+    /// Recognize scheduled events triggered by the EventBridge rule named `rule` as warmup events.
+    pub fn scheduled_rule(self, rule: impl Into<String>) -> Self {
+        let suffix = format!("rule/{}", rule.into());
+        self.matcher(move |payload| {
+            payload.get("detail-type").and_then(Value::as_str) == Some("Scheduled Event")
+                && payload
+                    .get("resources")
+                    .and_then(Value::as_array)
+                    .map(|resources| {
+                        resources
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .any(|arn| arn.ends_with(&suffix))
+                    })
+                    .unwrap_or_default()
+        })
+    }
+
+    /// Recognize payloads for which `matcher` returns `true` as warmup events.
+    pub fn matcher<F>(mut self, matcher: F) -> Self
+    where
+        F: Fn(&Value) -> bool + Send + Sync + 'static,
+    {
+        self.matchers.push(Arc::new(matcher));
+        self
+    }
+}
+
+impl<A> Default for WarmupLayer<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A> fmt::Debug for WarmupLayer<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WarmupLayer")
+            .field("matchers", &self.matchers.len())
+            .finish()
+    }
+}
+
+impl<S, A> Layer<S> for WarmupLayer<A> {
+    type Service = Warmup<S, A>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Warmup {
+            inner,
+            matchers: self.matchers.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+
+/// A [`Service`] that answers warmup pings without calling the inner handler.
+///
+/// See [`WarmupLayer`] for details.
+pub struct Warmup<S, A> {
+    inner: S,
+    matchers: Vec<Matcher>,
+    _payload: PhantomData<fn(A)>,
+}
+
+impl<S, A> Warmup<S, A> {
+    fn is_warmup(&self, payload: &Value) -> bool {
+        self.matchers.iter().any(|matcher| matcher(payload))
+    }
+}
+
+impl<S: Clone, A> Clone for Warmup<S, A> {
+    fn clone(&self) -> Self {
+        Warmup {
+            inner: self.inner.clone(),
+            matchers: self.matchers.clone(),
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl<S: fmt::Debug, A> fmt::Debug for Warmup<S, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Warmup")
+            .field("inner", &self.inner)
+            .field("matchers", &self.matchers.len())
+            .finish()
+    }
+}
+
+impl<S, A> Service<LambdaEvent<Value>> for Warmup<S, A>
+where
+    S: Service<LambdaEvent<A>>,
+    S::Error: Into<Error>,
+    A: for<'de> Deserialize<'de>,
+{
+    type Response = Option<S::Response>;
+    type Error = Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Either<
+        MapOk<future::ErrInto<S::Future, Error>, fn(S::Response) -> Option<S::Response>>,
+        Ready<Result<Option<S::Response>, Error>>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: LambdaEvent<Value>) -> Self::Future {
+        if self.is_warmup(&req.payload) {
+            debug!("warmup event received, skipping the handler");
+            return Either::Right(future::ready(Ok(None)));
+        }
+
+        match deserializer::deserialize_value(req.payload, req.context) {
+            Ok(event) => Either::Left(self.inner.call(event).err_into().map_ok(Some as fn(_) -> _)),
+            Err(err) => Either::Right(future::ready(Err(err.into()))),
+        }
+    }
+}
+
+fn is_serverless_warmup(payload: &Value) -> bool {
+    payload.get("source").and_then(Value::as_str) == Some(SERVERLESS_WARMUP_SOURCE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_fn;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    #[derive(Deserialize)]
+    struct Order {
+        id: u32,
+    }
+
+    #[tokio::test]
+    async fn warmup_events_skip_the_handler() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let handler = service_fn(move |event: LambdaEvent<Order>| {
+            c.fetch_add(1, Ordering::SeqCst);
+            async move { Ok::<_, Error>(event.payload.id) }
+        });
+        let mut service = WarmupLayer::new().scheduled_rule("keep-warm").layer(handler);
+
+        let events = [
+            json!({"source": "serverless-plugin-warmup"}),
+            json!({
+                "source": "aws.events",
+                "detail-type": "Scheduled Event",
+                "resources": ["arn:aws:events:us-east-1:123456789012:rule/keep-warm"],
+                "detail": {}
+            }),
+        ];
+        for payload in events {
+            let event = LambdaEvent::new(payload, Default::default());
+            let res = service.ready().await.unwrap().call(event).await.unwrap();
+            assert_eq!(None, res);
+        }
+        assert_eq!(0, calls.load(Ordering::SeqCst));
+
+        let event = LambdaEvent::new(json!({"id": 7}), Default::default());
+        let res = service.ready().await.unwrap().call(event).await.unwrap();
+        assert_eq!(Some(7), res);
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        let event = LambdaEvent::new(json!({"source": "aws.events"}), Default::default());
+        let err = service.ready().await.unwrap().call(event).await.unwrap_err();
+        assert!(err.to_string().contains("missing field `id`"));
+    }
+}
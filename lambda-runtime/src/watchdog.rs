@@ -0,0 +1,363 @@
+//! A handler that runs synchronous code, like `std::thread::sleep`, a blocking
+//! HTTP client or a long computation, holds the executor thread while it's
+//! polled. Other tasks stop making progress, timers fire late, and the
+//! invocation looks like a deadlock or a slow dependency.
+//!
+//! [`WatchdogLayer`] wraps the futures of the handler and records when they are
+//! polled. A background thread checks them periodically, and when a single poll
+//! lasts longer than the threshold, it logs a warning with the request ID, the
+//! thread and how long the poll has been blocked. When the blocked poll returns,
+//! a second warning reports its total duration with a backtrace of the thread,
+//! captured when `RUST_BACKTRACE` is set. Rust can't capture the stack of
+//! another thread safely, so the backtrace shows where the handler was polled
+//! from, and the request ID tells which event to replay to find the blocking call.
+//!
+//! # Example
+//! ```no_run
+//! use lambda_runtime::{service_fn, tower::Layer, watchdog::WatchdogLayer, Error, LambdaEvent};
+//! use serde_json::Value;
+//! use std::time::Duration;
+//!
+//! async fn func(event: LambdaEvent<Value>) -> Result<Value, Error> {
+//!     Ok(event.payload)
+//! }
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Error> {
+//!     let func = WatchdogLayer::new()
+//!         .threshold(Duration::from_millis(50))
+//!         .layer(service_fn(func));
+//!     lambda_runtime::run(func).await
+//! }
+//! ```
+use crate::LambdaEvent;
+use backtrace::Backtrace;
+use std::{
+    env, fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, Once, Weak,
+    },
+    task::{Context, Poll},
+    thread,
+    time::{Duration, Instant},
+};
+use tower::{Layer, Service};
+use tracing::warn;
+
+/// Duration of a poll after which the executor is considered blocked, by default.
+pub const DEFAULT_THRESHOLD: Duration = Duration::from_millis(100);
+
+type OnBlocked = Arc<dyn Fn(&BlockedPoll) + Send + Sync>;
+
+/// A poll of the handler that blocked the executor thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BlockedPoll {
+    /// The AWS request ID of the invocation.
+    pub request_id: String,
+    /// The name of the thread that polled the handler.
+    pub thread: String,
+    /// How long the poll was blocked when it was detected.
+    pub blocked_for: Duration,
+    /// The number of times the handler future was polled, including this poll.
+    pub polls: u64,
+}
+
+/// A [`Layer`] that detects handlers that block the executor thread.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone)]
+pub struct WatchdogLayer {
+    threshold: Duration,
+    on_blocked: Option<OnBlocked>,
+}
+
+impl WatchdogLayer {
+    /// Create a layer with the [`DEFAULT_THRESHOLD`].
+    pub fn new() -> Self {
+        WatchdogLayer {
+            threshold: DEFAULT_THRESHOLD,
+            on_blocked: None,
+        }
+    }
+
+    /// Warn about polls that last longer than `threshold`.
+    ///
+    /// The watchdog checks the polls four times per threshold, so blocked polls
+    /// are detected between one and one and a quarter thresholds after they start.
+    pub fn threshold(self, threshold: Duration) -> Self {
+        WatchdogLayer { threshold, ..self }
+    }
+
+    /// Call `hook` from the watchdog thread when it detects a blocked poll, for
+    /// example to emit a metric. The hook must not block.
+    pub fn on_blocked<H>(self, hook: H) -> Self
+    where
+        H: Fn(&BlockedPoll) + Send + Sync + 'static,
+    {
+        WatchdogLayer {
+            on_blocked: Some(Arc::new(hook)),
+            ..self
+        }
+    }
+}
+
+impl Default for WatchdogLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for WatchdogLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchdogLayer")
+            .field("threshold", &self.threshold)
+            .field("on_blocked", &self.on_blocked.is_some())
+            .finish()
+    }
+}
+
+impl<S> Layer<S> for WatchdogLayer {
+    type Service = Watchdog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Watchdog {
+            inner,
+            shared: Arc::new(Shared {
+                threshold: self.threshold,
+                on_blocked: self.on_blocked.clone(),
+                epoch: Instant::now(),
+                watched: Mutex::new(Vec::new()),
+                started: Once::new(),
+            }),
+        }
+    }
+}
+
+/// A [`Service`] that watches the polls of the futures of its inner handler.
+///
+/// See [`WatchdogLayer`] for details.
+#[derive(Clone)]
+pub struct Watchdog<S> {
+    inner: S,
+    shared: Arc<Shared>,
+}
+
+impl<S: fmt::Debug> fmt::Debug for Watchdog<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("inner", &self.inner)
+            .field("threshold", &self.shared.threshold)
+            .finish()
+    }
+}
+
+impl<S, A> Service<LambdaEvent<A>> for Watchdog<S>
+where
+    S: Service<LambdaEvent<A>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Watched<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: LambdaEvent<A>) -> Self::Future {
+        let state = Arc::new(PollState {
+            request_id: req.context.request_id.clone(),
+            thread: Mutex::new(String::new()),
+            polls: AtomicU64::new(0),
+            polling_since: AtomicU64::new(0),
+            detected: AtomicBool::new(false),
+        });
+        self.shared.watch(&state);
+        Watched {
+            inner: Box::pin(self.inner.call(req)),
+            state,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// The future of a [`Watchdog`].
+pub struct Watched<F> {
+    inner: Pin<Box<F>>,
+    state: Arc<PollState>,
+    shared: Arc<Shared>,
+}
+
+impl<F> fmt::Debug for Watched<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watched")
+            .field("request_id", &self.state.request_id)
+            .field("polls", &self.state.polls.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl<F: Future> Future for Watched<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let start = self.shared.now();
+        self.state.polls.fetch_add(1, Ordering::Relaxed);
+        if let Some(name) = thread::current().name() {
+            let mut thread = self.state.thread.lock().unwrap();
+            if thread.as_str() != name {
+                *thread = name.to_string();
+            }
+        }
+        self.state.polling_since.store(start, Ordering::Release);
+
+        let poll = self.inner.as_mut().poll(cx);
+
+        self.state.polling_since.store(0, Ordering::Release);
+        if self.state.detected.swap(false, Ordering::AcqRel) {
+            let blocked_for = Duration::from_micros(self.shared.now() - start);
+            warn!(
+                request_id = %self.state.request_id,
+                blocked_for_ms = blocked_for.as_millis() as u64,
+                backtrace = %capture_backtrace(),
+                "the handler blocked the executor thread"
+            );
+        }
+        poll
+    }
+}
+
+// The backtrace of the current thread, when `RUST_BACKTRACE` enables them.
+fn capture_backtrace() -> String {
+    match env::var_os("RUST_BACKTRACE") {
+        Some(value) if value != "0" => format!("{:?}", Backtrace::new()),
+        _ => "disabled backtrace".to_string(),
+    }
+}
+
+struct PollState {
+    request_id: String,
+    thread: Mutex<String>,
+    polls: AtomicU64,
+    // Microseconds since the epoch of the watchdog, plus one, or zero between polls.
+    polling_since: AtomicU64,
+    detected: AtomicBool,
+}
+
+struct Shared {
+    threshold: Duration,
+    on_blocked: Option<OnBlocked>,
+    epoch: Instant,
+    watched: Mutex<Vec<Weak<PollState>>>,
+    started: Once,
+}
+
+impl Shared {
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_micros() as u64 + 1
+    }
+
+    fn watch(self: &Arc<Self>, state: &Arc<PollState>) {
+        self.watched.lock().unwrap().push(Arc::downgrade(state));
+        self.started.call_once(|| {
+            let shared = Arc::downgrade(self);
+            let interval = (self.threshold / 4).max(Duration::from_millis(1));
+            let spawned = thread::Builder::new()
+                .name("lambda-watchdog".into())
+                .spawn(move || loop {
+                    thread::sleep(interval);
+                    match shared.upgrade() {
+                        Some(shared) => shared.check(),
+                        None => return,
+                    }
+                });
+            if let Err(err) = spawned {
+                warn!(error = %err, "unable to start the watchdog thread");
+            }
+        });
+    }
+
+    fn check(&self) {
+        let now = self.now();
+        let mut watched = self.watched.lock().unwrap();
+        watched.retain(|state| state.strong_count() > 0);
+        for state in watched.iter().filter_map(Weak::upgrade) {
+            let since = state.polling_since.load(Ordering::Acquire);
+            if since == 0 || now - since < self.threshold.as_micros() as u64 {
+                continue;
+            }
+            if state.detected.swap(true, Ordering::AcqRel) {
+                continue;
+            }
+            // The poll may have returned since it was read.
+            if state.polling_since.load(Ordering::Acquire) != since {
+                state.detected.store(false, Ordering::Release);
+                continue;
+            }
+            let blocked = BlockedPoll {
+                request_id: state.request_id.clone(),
+                thread: state.thread.lock().unwrap().clone(),
+                blocked_for: Duration::from_micros(now - since),
+                polls: state.polls.load(Ordering::Relaxed),
+            };
+            warn!(
+                request_id = %blocked.request_id,
+                thread = %blocked.thread,
+                blocked_for_ms = blocked.blocked_for.as_millis() as u64,
+                polls = blocked.polls,
+                "the handler is blocking the executor thread, it may run synchronous code"
+            );
+            if let Some(hook) = &self.on_blocked {
+                hook(&blocked);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{service_fn, Context as LambdaContext, Error};
+    use tower::ServiceExt;
+
+    fn event(request_id: &str) -> LambdaEvent<u64> {
+        LambdaEvent::new(0, LambdaContext::builder().request_id(request_id).build())
+    }
+
+    fn layer(detected: Arc<Mutex<Vec<BlockedPoll>>>) -> WatchdogLayer {
+        WatchdogLayer::new()
+            .threshold(Duration::from_millis(40))
+            .on_blocked(move |blocked| detected.lock().unwrap().push(blocked.clone()))
+    }
+
+    #[tokio::test]
+    async fn detects_blocking_handlers() {
+        let detected = Arc::new(Mutex::new(Vec::new()));
+        let handler = layer(detected.clone()).layer(service_fn(|_: LambdaEvent<u64>| async {
+            tokio::task::yield_now().await;
+            std::thread::sleep(Duration::from_millis(200));
+            Ok::<_, Error>(())
+        }));
+        handler.oneshot(event("blocking")).await.unwrap();
+
+        let detected = detected.lock().unwrap();
+        assert_eq!(1, detected.len());
+        assert_eq!("blocking", detected[0].request_id);
+        assert_eq!(2, detected[0].polls);
+        assert!(detected[0].blocked_for >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn ignores_handlers_that_wait() {
+        let detected = Arc::new(Mutex::new(Vec::new()));
+        let handler = layer(detected.clone()).layer(service_fn(|_: LambdaEvent<u64>| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok::<_, Error>(())
+        }));
+        handler.oneshot(event("waiting")).await.unwrap();
+        assert!(detected.lock().unwrap().is_empty());
+    }
+}